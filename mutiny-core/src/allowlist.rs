@@ -0,0 +1,268 @@
+//! A withdrawal allowlist for wallets used as a treasury.
+//!
+//! Once turned on, on-chain sends, sweeps, raw transaction broadcasts and
+//! cooperative channel closes can only pay our own wallet or a destination on the
+//! allowlist, an address or an xpub whose first addresses are matched. Channel
+//! opens can't push funds to the peer. Changes that loosen it, adding a
+//! destination, turning it off or shortening the delay, only apply once the
+//! delay has passed, so someone who got into the wallet can't just add their own
+//! address and send. Changes that tighten it apply right away.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::bip32::{ChildNumber, Xpub};
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::{Address, CompressedPublicKey, Network, ScriptBuf};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const WITHDRAWAL_ALLOWLIST_KEY: &str = "withdrawal_allowlist";
+/// How many receive and change addresses of an xpub are matched
+const XPUB_LOOKAHEAD: u32 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AllowlistEntry {
+    Address(String),
+    /// An account xpub, its segwit and taproot receive and change addresses are allowed
+    Xpub(String),
+}
+
+impl AllowlistEntry {
+    pub(crate) fn validate(&self, network: Network) -> Result<(), MutinyError> {
+        match self {
+            Self::Address(address) => {
+                Address::from_str(address)?.require_network(network)?;
+            }
+            Self::Xpub(xpub) => {
+                Xpub::from_str(xpub).map_err(|_| MutinyError::InvalidArgumentsError)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn matches<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        script: &ScriptBuf,
+        network: Network,
+    ) -> bool {
+        match self {
+            Self::Address(address) => Address::from_str(address)
+                .ok()
+                .and_then(|a| a.require_network(network).ok())
+                .is_some_and(|a| &a.script_pubkey() == script),
+            Self::Xpub(xpub) => {
+                let Ok(xpub) = Xpub::from_str(xpub) else {
+                    return false;
+                };
+                [0, 1].into_iter().any(|chain| {
+                    let Ok(chain) = xpub.ckd_pub(secp, ChildNumber::Normal { index: chain }) else {
+                        return false;
+                    };
+                    (0..XPUB_LOOKAHEAD).any(|index| {
+                        let Ok(key) = chain.ckd_pub(secp, ChildNumber::Normal { index }) else {
+                            return false;
+                        };
+                        let wpkh = CompressedPublicKey(key.public_key).wpubkey_hash();
+                        &ScriptBuf::new_p2wpkh(&wpkh) == script
+                            || &ScriptBuf::new_p2tr(secp, key.to_x_only_pub(), None) == script
+                    })
+                })
+            }
+        }
+    }
+}
+
+/// A change that loosens the allowlist, waiting out the delay
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AllowlistChange {
+    Add(AllowlistEntry),
+    Disable,
+    SetDelay(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingAllowlistChange {
+    pub change: AllowlistChange,
+    /// When it applies
+    pub effective_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WithdrawalAllowlist {
+    pub enabled: bool,
+    /// How long loosening changes wait
+    pub delay_secs: u64,
+    pub entries: Vec<AllowlistEntry>,
+    pub pending: Vec<PendingAllowlistChange>,
+}
+
+impl WithdrawalAllowlist {
+    /// Turning it on applies right away, with no delay to wait out yet
+    pub(crate) fn enable(&mut self, delay_secs: u64, now: u64) {
+        if self.enabled {
+            self.request(AllowlistChange::SetDelay(delay_secs), now);
+        } else {
+            self.enabled = true;
+            self.delay_secs = delay_secs;
+        }
+    }
+
+    /// Applies the change now if it tightens the allowlist or the allowlist is
+    /// off, otherwise queues it until the delay has passed
+    pub(crate) fn request(&mut self, change: AllowlistChange, now: u64) {
+        let tightens = matches!(change, AllowlistChange::SetDelay(d) if d >= self.delay_secs);
+        if !self.enabled || tightens {
+            self.apply(change);
+        } else {
+            self.pending.push(PendingAllowlistChange {
+                change,
+                effective_at: now + self.delay_secs,
+            });
+        }
+    }
+
+    /// Removing a destination tightens the allowlist, so it's right away,
+    /// along with any pending addition of it
+    pub(crate) fn remove(&mut self, entry: &AllowlistEntry) {
+        self.entries.retain(|e| e != entry);
+        self.pending
+            .retain(|p| !matches!(&p.change, AllowlistChange::Add(e) if e == entry));
+    }
+
+    /// Drops every pending change
+    pub(crate) fn cancel_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Applies the pending changes whose delay passed, returns whether any did
+    pub(crate) fn apply_pending(&mut self, now: u64) -> bool {
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| p.effective_at <= now);
+        self.pending = pending;
+        let applied = !due.is_empty();
+        for p in due {
+            self.apply(p.change);
+        }
+        applied
+    }
+
+    fn apply(&mut self, change: AllowlistChange) {
+        match change {
+            AllowlistChange::Add(entry) => {
+                if !self.entries.contains(&entry) {
+                    self.entries.push(entry);
+                }
+            }
+            AllowlistChange::Disable => {
+                self.enabled = false;
+                self.pending.clear();
+            }
+            AllowlistChange::SetDelay(delay_secs) => self.delay_secs = delay_secs,
+        }
+    }
+
+    /// Whether we may send to the script, our own scripts are checked separately
+    pub(crate) fn allows<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        script: &ScriptBuf,
+        network: Network,
+    ) -> bool {
+        !self.enabled
+            || self
+                .entries
+                .iter()
+                .any(|e| e.matches(secp, script, network))
+    }
+}
+
+/// Gets the allowlist, applying the changes whose delay passed
+pub(crate) fn get_withdrawal_allowlist<S: MutinyStorage>(
+    storage: &S,
+    now: u64,
+) -> Result<WithdrawalAllowlist, MutinyError> {
+    let mut allowlist: WithdrawalAllowlist = storage
+        .get_data(WITHDRAWAL_ALLOWLIST_KEY)?
+        .unwrap_or_default();
+    if allowlist.apply_pending(now) {
+        persist_withdrawal_allowlist(storage, &allowlist)?;
+    }
+    Ok(allowlist)
+}
+
+pub(crate) fn persist_withdrawal_allowlist<S: MutinyStorage>(
+    storage: &S,
+    allowlist: &WithdrawalAllowlist,
+) -> Result<(), MutinyError> {
+    storage.write_data(WITHDRAWAL_ALLOWLIST_KEY.to_string(), allowlist, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const ADDRESS: &str = "bcrt1qxj6y3kl6vqsq0tf7rk9yl7jqxa4fzcayyvnl8u";
+    const OTHER: &str = "bcrt1pllm0dukhxm0jm4xd8ckms3tz9pmeckhr06sjk7ev2p4s3mk2yc5qkczfx2";
+
+    #[test]
+    fn test_allowlist_delays_loosening() {
+        let test_name = "test_allowlist_delays_loosening";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::verification_only();
+        let script = Address::from_str(ADDRESS)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let other = Address::from_str(OTHER)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let entry = AllowlistEntry::Address(ADDRESS.to_string());
+        entry.validate(Network::Regtest).unwrap();
+        assert!(entry.validate(Network::Bitcoin).is_err());
+
+        // everything goes while it's off, additions apply right away
+        let mut allowlist = WithdrawalAllowlist::default();
+        assert!(allowlist.allows(&secp, &other, Network::Regtest));
+        allowlist.request(AllowlistChange::Add(entry.clone()), 0);
+        allowlist.enable(86_400, 0);
+        assert!(allowlist.allows(&secp, &script, Network::Regtest));
+        assert!(!allowlist.allows(&secp, &other, Network::Regtest));
+
+        // loosening waits out the delay
+        let other_entry = AllowlistEntry::Address(OTHER.to_string());
+        allowlist.request(AllowlistChange::Add(other_entry.clone()), 1_000);
+        allowlist.request(AllowlistChange::SetDelay(60), 1_000);
+        assert!(!allowlist.allows(&secp, &other, Network::Regtest));
+        assert!(!allowlist.apply_pending(87_399));
+        assert!(allowlist.apply_pending(87_400));
+        assert!(allowlist.allows(&secp, &other, Network::Regtest));
+        assert_eq!(allowlist.delay_secs, 60);
+
+        // tightening is right away
+        allowlist.request(AllowlistChange::SetDelay(3_600), 90_000);
+        assert_eq!(allowlist.delay_secs, 3_600);
+        allowlist.remove(&other_entry);
+        assert!(!allowlist.allows(&secp, &other, Network::Regtest));
+
+        allowlist.request(AllowlistChange::Disable, 90_000);
+        assert!(allowlist.enabled);
+        allowlist.cancel_pending();
+        assert!(!allowlist.apply_pending(100_000));
+        assert!(allowlist.enabled);
+
+        let storage = MemoryStorage::default();
+        allowlist.request(AllowlistChange::Disable, 100_000);
+        persist_withdrawal_allowlist(&storage, &allowlist).unwrap();
+        assert!(get_withdrawal_allowlist(&storage, 100_000).unwrap().enabled);
+        assert!(!get_withdrawal_allowlist(&storage, 103_600).unwrap().enabled);
+    }
+}
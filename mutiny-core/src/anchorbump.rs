@@ -0,0 +1,203 @@
+//! Fee bumping force closes of anchor channels.
+//!
+//! A force close commitment pays the feerate from when the channel was last
+//! updated, which can leave it stuck for days when fees go up. Anchor channels
+//! let us bump it by spending our anchor output in a child transaction (CPFP).
+//! LDK does this on its own at the feerate it thinks is needed, the user can
+//! pick a higher one here. LDK asks again every block until the commitment
+//! confirms, so a feerate the user picked keeps being used.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::{OutPoint, Transaction, Txid};
+use futures_util::lock::Mutex;
+use lightning::events::bump_transaction::BumpTransactionEvent;
+use lightning::ln::types::ChannelId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub(crate) const ANCHOR_BUMP_PREFIX: &str = "anchor_bump/";
+/// Label put on the child transactions that bump a force close
+pub const ANCHOR_BUMP_LABEL: &str = "Anchor bump";
+
+/// The latest request from LDK to bump each force closed channel, only
+/// kept in memory since LDK asks again every block
+pub(crate) type AnchorCloseEvents = Arc<Mutex<HashMap<ChannelId, BumpTransactionEvent>>>;
+
+/// A force close commitment that can be bumped through its anchor
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnchorBump {
+    pub channel_id: String,
+    pub commitment_txid: Txid,
+    pub anchor_outpoint: OutPoint,
+    /// Feerate the user asked for, in sats per 1000 weight units
+    pub user_feerate_sat_per_1000_weight: Option<u32>,
+    /// Child transactions that spent the anchor, newest last
+    pub bump_txids: Vec<Txid>,
+    pub created_at: u64,
+    pub last_updated: u64,
+}
+
+/// Takes the feerate the user asked for when it's higher than LDK's
+pub(crate) fn with_feerate(event: &BumpTransactionEvent, feerate: u32) -> BumpTransactionEvent {
+    let mut event = event.clone();
+    if let BumpTransactionEvent::ChannelClose {
+        package_target_feerate_sat_per_1000_weight,
+        ..
+    } = &mut event
+    {
+        *package_target_feerate_sat_per_1000_weight =
+            (*package_target_feerate_sat_per_1000_weight).max(feerate);
+    }
+    event
+}
+
+fn anchor_bump_key(channel_id: &str) -> String {
+    format!("{ANCHOR_BUMP_PREFIX}{channel_id}")
+}
+
+pub(crate) fn get_anchor_bump<S: MutinyStorage>(
+    storage: &S,
+    channel_id: &ChannelId,
+) -> Result<Option<AnchorBump>, MutinyError> {
+    storage.get_data(anchor_bump_key(&channel_id.to_string()))
+}
+
+fn persist_anchor_bump<S: MutinyStorage>(
+    storage: &S,
+    bump: &AnchorBump,
+) -> Result<(), MutinyError> {
+    storage.write_data(anchor_bump_key(&bump.channel_id), bump, None)
+}
+
+/// Records a force close commitment the first time LDK asks to bump it
+pub(crate) fn record_anchor_close<S: MutinyStorage>(
+    storage: &S,
+    channel_id: &ChannelId,
+    commitment_txid: Txid,
+    anchor_outpoint: OutPoint,
+) -> Result<AnchorBump, MutinyError> {
+    if let Some(bump) = get_anchor_bump(storage, channel_id)? {
+        if bump.commitment_txid == commitment_txid {
+            return Ok(bump);
+        }
+    }
+
+    let now = utils::now().as_secs();
+    let bump = AnchorBump {
+        channel_id: channel_id.to_string(),
+        commitment_txid,
+        anchor_outpoint,
+        user_feerate_sat_per_1000_weight: None,
+        bump_txids: vec![],
+        created_at: now,
+        last_updated: now,
+    };
+    persist_anchor_bump(storage, &bump)?;
+    Ok(bump)
+}
+
+pub(crate) fn set_anchor_bump_feerate<S: MutinyStorage>(
+    storage: &S,
+    channel_id: &ChannelId,
+    feerate: u32,
+) -> Result<AnchorBump, MutinyError> {
+    let mut bump = get_anchor_bump(storage, channel_id)?.ok_or(MutinyError::NotFound)?;
+    bump.user_feerate_sat_per_1000_weight = Some(feerate);
+    bump.last_updated = utils::now().as_secs();
+    persist_anchor_bump(storage, &bump)?;
+    Ok(bump)
+}
+
+/// Remembers a signed transaction if it spends the anchor of a force close,
+/// returns the force close it bumps
+pub(crate) fn record_anchor_bump_tx<S: MutinyStorage>(
+    storage: &S,
+    tx: &Transaction,
+) -> Result<Option<AnchorBump>, MutinyError> {
+    let txid = tx.compute_txid();
+    let Some(mut bump) = list_anchor_bumps(storage)?.into_iter().find(|b| {
+        tx.input
+            .iter()
+            .any(|i| i.previous_output == b.anchor_outpoint)
+    }) else {
+        return Ok(None);
+    };
+
+    if !bump.bump_txids.contains(&txid) {
+        bump.bump_txids.push(txid);
+        bump.last_updated = utils::now().as_secs();
+        persist_anchor_bump(storage, &bump)?;
+    }
+    Ok(Some(bump))
+}
+
+/// Lists the force closes that have been up for bumping, newest first
+pub(crate) fn list_anchor_bumps<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<AnchorBump>, MutinyError> {
+    let mut bumps: Vec<AnchorBump> = storage
+        .scan::<AnchorBump>(ANCHOR_BUMP_PREFIX, None)?
+        .into_values()
+        .collect();
+    bumps.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+
+    Ok(bumps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction::Version, TxIn};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_anchor_bump_records() {
+        let test_name = "test_anchor_bump_records";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let channel_id = ChannelId([1; 32]);
+        let commitment_txid = Txid::from_byte_array([2; 32]);
+        let anchor_outpoint = OutPoint::new(commitment_txid, 1);
+
+        let bump =
+            record_anchor_close(&storage, &channel_id, commitment_txid, anchor_outpoint).unwrap();
+        // asked again next block, nothing changes
+        let again =
+            record_anchor_close(&storage, &channel_id, commitment_txid, anchor_outpoint).unwrap();
+        assert_eq!(bump, again);
+
+        let bump = set_anchor_bump_feerate(&storage, &channel_id, 5_000).unwrap();
+        assert_eq!(bump.user_feerate_sat_per_1000_weight, Some(5_000));
+        assert!(set_anchor_bump_feerate(&storage, &ChannelId([9; 32]), 5_000).is_err());
+
+        let child = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: anchor_outpoint,
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        let unrelated = Transaction {
+            input: vec![TxIn::default()],
+            ..child.clone()
+        };
+        assert!(record_anchor_bump_tx(&storage, &unrelated)
+            .unwrap()
+            .is_none());
+        let bump = record_anchor_bump_tx(&storage, &child).unwrap().unwrap();
+        assert_eq!(bump.bump_txids, vec![child.compute_txid()]);
+
+        assert_eq!(list_anchor_bumps(&storage).unwrap(), vec![bump]);
+    }
+}
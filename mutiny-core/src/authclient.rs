@@ -1,10 +1,11 @@
+use crate::servicestatus::ServiceHealth;
 use crate::{authmanager::AuthManager, error::MutinyError, logging::MutinyLogger, utils};
 use async_lock::RwLock;
 use bitcoin::hashes::{hex::prelude::*, sha256, Hash};
 use bitcoin::key::rand::Rng;
 use bitcoin::secp256k1::rand::thread_rng;
 use jwt_compact::UntrustedToken;
-use lightning::{log_debug, log_error, log_info};
+use lightning::{log_debug, log_error, log_info, log_warn};
 use lightning::{log_trace, util::logger::*};
 use reqwest::Client;
 use reqwest::{Method, StatusCode, Url};
@@ -17,6 +18,8 @@ pub struct MutinyAuthClient {
     url: String,
     http_client: Client,
     jwt: RwLock<Option<String>>,
+    /// Whether the auth server answered the last time we asked for a token
+    pub health: ServiceHealth,
     logger: Arc<MutinyLogger>,
 }
 
@@ -28,6 +31,7 @@ impl MutinyAuthClient {
             url,
             http_client,
             jwt: RwLock::new(None),
+            health: ServiceHealth::default(),
             logger,
         }
     }
@@ -98,6 +102,23 @@ impl MutinyAuthClient {
     // TODO: Multiple concurrent `retrieve_new_jwt` calls could trigger multiple token refreshes.
     // In a future PR, maybe we can add JWT parsing and validation before initiating a new token request.
     async fn retrieve_new_jwt(&self) -> Result<String, MutinyError> {
+        let result = self.fetch_jwt().await;
+        match result.as_ref() {
+            Ok(_) => {
+                if self.health.record_success() {
+                    log_info!(self.logger, "Auth server is reachable again");
+                }
+            }
+            Err(e) => {
+                if self.health.record_failure(e) {
+                    log_warn!(self.logger, "Auth server is unreachable: {e}");
+                }
+            }
+        }
+        result
+    }
+
+    async fn fetch_jwt(&self) -> Result<String, MutinyError> {
         let mut lock = self.jwt.write().await;
         log_debug!(self.logger, "Retrieving new JWT token");
 
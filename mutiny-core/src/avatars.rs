@@ -0,0 +1,299 @@
+//! A bounded local cache of contact and profile images.
+//!
+//! Images are fetched through Primal's media CDN, which serves small resized
+//! copies, and kept in storage so contact lists render their avatars right
+//! away and while offline. How long an image stays fresh follows the CDN's
+//! `Cache-Control` header, stale images are still served when they can't be
+//! fetched again. Once the cache is over its size limit the least recently
+//! used images are dropped.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::{sha256, Hash};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use url::Url;
+
+pub(crate) const AVATAR_PREFIX: &str = "avatar/";
+/// Size and last use of every cached image, for evicting the least recently used
+pub(crate) const AVATAR_INDEX_KEY: &str = "avatar_index";
+/// Primal's media cache, serving resized copies of profile images
+pub const IMAGE_CDN_URL: &str = "https://primal.b-cdn.net/media-cache";
+/// Most the cache may hold, in bytes of image data
+const MAX_AVATAR_CACHE_BYTES: u64 = 5 * 1024 * 1024;
+/// Bigger images aren't cached, an avatar from the CDN is far smaller
+const MAX_AVATAR_BYTES: u64 = 256 * 1024;
+/// How long an image is fresh when the CDN doesn't say
+const DEFAULT_AVATAR_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CachedAvatar {
+    /// The image's own url, not the CDN's
+    pub url: String,
+    pub content_type: String,
+    /// Base64 encoded image data
+    pub data: String,
+    pub fetched_at: u64,
+    /// After this it's fetched again, but still served when that fails
+    pub expires_at: u64,
+}
+
+impl CachedAvatar {
+    /// A `data:` url the UI can show directly
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.content_type, self.data)
+    }
+
+    pub fn is_fresh(&self, now: u64) -> bool {
+        now < self.expires_at
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+struct AvatarIndexEntry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+struct AvatarIndex {
+    /// Keyed by the image's storage key
+    entries: HashMap<String, AvatarIndexEntry>,
+}
+
+impl AvatarIndex {
+    fn total_size(&self) -> u64 {
+        self.entries.values().map(|e| e.size).sum()
+    }
+
+    /// Drops the least recently used images until the cache fits, returning their keys
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.total_size() > max_bytes {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+}
+
+/// How long the response may be cached for from its `Cache-Control` header,
+/// None if it mustn't be stored at all
+pub(crate) fn cache_ttl(cache_control: Option<&str>) -> Option<u64> {
+    let Some(cache_control) = cache_control else {
+        return Some(DEFAULT_AVATAR_TTL_SECS);
+    };
+
+    let mut ttl = DEFAULT_AVATAR_TTL_SECS;
+    for directive in cache_control.split(',').map(|d| d.trim().to_lowercase()) {
+        if directive == "no-store" {
+            return None;
+        } else if directive == "no-cache" {
+            // kept for offline use, but always fetched again when we can
+            return Some(0);
+        } else if let Some(max_age) = directive.strip_prefix("max-age=") {
+            if let Ok(max_age) = max_age.trim_matches('"').parse() {
+                ttl = max_age;
+            }
+        }
+    }
+    Some(ttl)
+}
+
+/// The CDN url serving the image
+pub(crate) fn cdn_url(image_url: &str) -> Result<Url, MutinyError> {
+    Url::parse_with_params(IMAGE_CDN_URL, &[("s", "S"), ("a", "1"), ("u", image_url)])
+        .map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
+fn avatar_key(url: &str) -> String {
+    format!("{AVATAR_PREFIX}{}", sha256::Hash::hash(url.as_bytes()))
+}
+
+fn get_avatar_index<S: MutinyStorage>(storage: &S) -> Result<AvatarIndex, MutinyError> {
+    Ok(storage.get_data(AVATAR_INDEX_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn get_cached_avatar<S: MutinyStorage>(
+    storage: &S,
+    url: &str,
+) -> Result<Option<CachedAvatar>, MutinyError> {
+    storage.get_data(avatar_key(url))
+}
+
+/// Records that the image was just shown, so it's evicted last
+pub(crate) fn touch_avatar<S: MutinyStorage>(storage: &S, url: &str) -> Result<(), MutinyError> {
+    let mut index = get_avatar_index(storage)?;
+    if let Some(entry) = index.entries.get_mut(&avatar_key(url)) {
+        entry.last_used = utils::now().as_secs();
+        storage.write_data(AVATAR_INDEX_KEY.to_string(), index, None)?;
+    }
+    Ok(())
+}
+
+/// Saves the image, dropping the least recently used ones when the cache is full
+pub(crate) fn cache_avatar<S: MutinyStorage>(
+    storage: &S,
+    avatar: &CachedAvatar,
+) -> Result<(), MutinyError> {
+    let size = avatar.data.len() as u64;
+    if size > MAX_AVATAR_BYTES {
+        return Ok(());
+    }
+
+    let key = avatar_key(&avatar.url);
+    let mut index = get_avatar_index(storage)?;
+    index.entries.insert(
+        key.clone(),
+        AvatarIndexEntry {
+            size,
+            last_used: utils::now().as_secs(),
+        },
+    );
+    let evicted = index.evict(MAX_AVATAR_CACHE_BYTES);
+
+    storage.write_data(key, avatar, None)?;
+    storage.write_data(AVATAR_INDEX_KEY.to_string(), index, None)?;
+    if !evicted.is_empty() {
+        storage.delete(&evicted)?;
+    }
+    Ok(())
+}
+
+/// Removes every cached image
+pub(crate) fn clear_avatar_cache<S: MutinyStorage>(storage: &S) -> Result<(), MutinyError> {
+    let mut keys = storage.scan_keys(AVATAR_PREFIX, None)?;
+    keys.push(AVATAR_INDEX_KEY.to_string());
+    storage.delete(&keys)
+}
+
+/// Fetches the image through the CDN, along with how long it may be cached for
+pub(crate) async fn fetch_avatar(
+    client: &Client,
+    url: &str,
+) -> Result<(CachedAvatar, Option<u64>), MutinyError> {
+    let request = client
+        .get(cdn_url(url)?)
+        .build()
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+    let resp = utils::fetch_with_timeout(client, request)
+        .await?
+        .error_for_status()
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let ttl = cache_ttl(header(reqwest::header::CACHE_CONTROL).as_deref());
+    let content_type =
+        header(reqwest::header::CONTENT_TYPE).unwrap_or_else(|| "image/jpeg".to_string());
+    if !content_type.starts_with("image/") {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    let bytes = resp
+        .bytes()
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    let now = utils::now().as_secs();
+    let avatar = CachedAvatar {
+        url: url.to_string(),
+        content_type,
+        data: base64::encode(bytes),
+        fetched_at: now,
+        expires_at: now + ttl.unwrap_or_default(),
+    };
+    Ok((avatar, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn avatar(url: &str, size: usize) -> CachedAvatar {
+        CachedAvatar {
+            url: url.to_string(),
+            content_type: "image/png".to_string(),
+            data: "a".repeat(size),
+            fetched_at: 0,
+            expires_at: 100,
+        }
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let test_name = "test_cache_ttl";
+        log!("{}", test_name);
+
+        assert_eq!(cache_ttl(None), Some(DEFAULT_AVATAR_TTL_SECS));
+        assert_eq!(cache_ttl(Some("public, max-age=3600")), Some(3_600));
+        assert_eq!(cache_ttl(Some("no-cache")), Some(0));
+        assert_eq!(cache_ttl(Some("max-age=60, no-store")), None);
+        assert_eq!(cache_ttl(Some("public")), Some(DEFAULT_AVATAR_TTL_SECS));
+
+        let url = cdn_url("https://example.com/me.png?size=big").unwrap();
+        assert!(url.as_str().starts_with(IMAGE_CDN_URL));
+        assert_eq!(
+            url.query_pairs().find(|(k, _)| k == "u").unwrap().1,
+            "https://example.com/me.png?size=big"
+        );
+    }
+
+    #[test]
+    fn test_avatar_cache_eviction() {
+        let test_name = "test_avatar_cache_eviction";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let first = avatar("https://example.com/1.png", 200_000);
+        cache_avatar(&storage, &first).unwrap();
+        assert_eq!(
+            get_cached_avatar(&storage, &first.url).unwrap(),
+            Some(first.clone())
+        );
+        assert!(first.is_fresh(99));
+        assert!(!first.is_fresh(100));
+        assert!(first.data_url().starts_with("data:image/png;base64,"));
+
+        // too big to cache
+        let huge = avatar("https://example.com/huge.png", 300_000);
+        cache_avatar(&storage, &huge).unwrap();
+        assert_eq!(get_cached_avatar(&storage, &huge.url).unwrap(), None);
+
+        // filling the cache drops the least recently used
+        let mut index = AvatarIndex::default();
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            index.entries.insert(
+                key.to_string(),
+                AvatarIndexEntry {
+                    size: 100,
+                    last_used: 10 - i as u64,
+                },
+            );
+        }
+        assert_eq!(index.evict(200), vec!["c".to_string()]);
+        assert_eq!(index.total_size(), 200);
+
+        clear_avatar_cache(&storage).unwrap();
+        assert_eq!(get_cached_avatar(&storage, &first.url).unwrap(), None);
+        assert_eq!(get_avatar_index(&storage).unwrap(), AvatarIndex::default());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::MutinyError;
+use crate::ldkstorage::MONITORS_PREFIX_KEY;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub(crate) const BACKUP_STATUS_KEY: &str = "backup_status";
+/// Don't remind the user more than once a day
+pub(crate) const BACKUP_REMINDER_INTERVAL_SECS: u64 = 60 * 60 * 24;
+
+/// When the channel state last changed compared to when the user last backed it up.
+/// All times are epoch seconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupStatus {
+    /// First time a channel monitor was written since the last verified backup
+    pub last_state_change: Option<u64>,
+    /// Last time the wallet state was exported
+    pub last_export: Option<u64>,
+    /// Last time an exported backup was checked to contain the latest channel state
+    pub last_verified: Option<u64>,
+    /// Last time we reminded the user to back up
+    pub last_reminder: Option<u64>,
+}
+
+impl BackupStatus {
+    /// Whether the channel state is newer than any verified backup
+    pub fn needs_backup(&self) -> bool {
+        match (self.last_state_change, self.last_verified) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(changed), Some(verified)) => changed > verified,
+        }
+    }
+
+    /// Whether we should remind the user to back up at the given time
+    pub(crate) fn should_remind(&self, now: u64) -> bool {
+        self.needs_backup()
+            && self.last_reminder.map_or(true, |t| {
+                now.saturating_sub(t) >= BACKUP_REMINDER_INTERVAL_SECS
+            })
+    }
+}
+
+pub(crate) fn get_backup_status<S: MutinyStorage>(
+    storage: &S,
+) -> Result<BackupStatus, MutinyError> {
+    Ok(storage.get_data(BACKUP_STATUS_KEY)?.unwrap_or_default())
+}
+
+/// Applies the change to the backup status, only writing it if anything changed
+pub(crate) fn update_backup_status<S: MutinyStorage>(
+    storage: &S,
+    f: impl FnOnce(&mut BackupStatus),
+) -> Result<BackupStatus, MutinyError> {
+    let old = get_backup_status(storage)?;
+    let mut status = old.clone();
+    f(&mut status);
+    if status != old {
+        storage.write_data(BACKUP_STATUS_KEY.to_string(), &status, None)?;
+    }
+
+    Ok(status)
+}
+
+/// Called on every channel monitor write. Only the first write since the last
+/// verified backup is recorded, the ones after it don't change whether a
+/// backup is needed.
+pub(crate) fn record_channel_state_change<S: MutinyStorage>(
+    storage: &S,
+) -> Result<(), MutinyError> {
+    update_backup_status(storage, |s| {
+        if !s.needs_backup() {
+            s.last_state_change = Some(utils::now().as_secs())
+        }
+    })?;
+    Ok(())
+}
+
+pub(crate) fn record_backup_exported<S: MutinyStorage>(storage: &S) -> Result<(), MutinyError> {
+    update_backup_status(storage, |s| s.last_export = Some(utils::now().as_secs()))?;
+    Ok(())
+}
+
+/// Checks that an exported backup contains the latest version of every channel monitor.
+/// Restoring from a backup with outdated monitors can lose funds.
+pub(crate) fn backup_has_latest_state<S: MutinyStorage>(
+    storage: &S,
+    backup: &Value,
+) -> Result<bool, MutinyError> {
+    let Some(backup) = backup.as_object() else {
+        return Ok(false);
+    };
+    let monitors = storage.scan::<Value>(MONITORS_PREFIX_KEY, None)?;
+
+    Ok(monitors
+        .iter()
+        .all(|(key, value)| backup.get(key) == Some(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_backup_status() {
+        let test_name = "test_backup_status";
+        log!("{}", test_name);
+
+        let mut status = BackupStatus::default();
+        assert!(!status.needs_backup());
+
+        status.last_state_change = Some(100);
+        assert!(status.needs_backup());
+        assert!(status.should_remind(100));
+
+        status.last_reminder = Some(100);
+        assert!(!status.should_remind(200));
+        assert!(status.should_remind(100 + BACKUP_REMINDER_INTERVAL_SECS));
+
+        status.last_verified = Some(150);
+        assert!(!status.needs_backup());
+        assert!(!status.should_remind(100 + BACKUP_REMINDER_INTERVAL_SECS));
+    }
+
+    #[test]
+    fn test_record_channel_state_change() {
+        let test_name = "test_record_channel_state_change";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        record_channel_state_change(&storage).unwrap();
+        let status = get_backup_status(&storage).unwrap();
+        assert!(status.needs_backup());
+
+        // later writes keep the first change that isn't backed up
+        let first = status.last_state_change.unwrap();
+        update_backup_status(&storage, |s| s.last_state_change = Some(first - 10)).unwrap();
+        record_channel_state_change(&storage).unwrap();
+        assert_eq!(
+            get_backup_status(&storage).unwrap().last_state_change,
+            Some(first - 10)
+        );
+
+        // once verified the next write is recorded again
+        update_backup_status(&storage, |s| s.last_verified = Some(first - 5)).unwrap();
+        record_channel_state_change(&storage).unwrap();
+        let status = get_backup_status(&storage).unwrap();
+        assert!(status.last_state_change.unwrap() >= first);
+        assert!(status.needs_backup());
+    }
+
+    #[test]
+    fn test_backup_has_latest_state() {
+        let test_name = "test_backup_has_latest_state";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let key = format!("{MONITORS_PREFIX_KEY}txid_0_node");
+        storage
+            .write_data(key.clone(), json!("monitor_v1"), None)
+            .unwrap();
+
+        assert!(!backup_has_latest_state(&storage, &json!({})).unwrap());
+        assert!(!backup_has_latest_state(&storage, &json!({ &key: "monitor_v0" })).unwrap());
+        assert!(backup_has_latest_state(&storage, &json!({ &key: "monitor_v1" })).unwrap());
+    }
+}
@@ -0,0 +1,308 @@
+//! Milestone badges, like the first zap sent or the 100th payment.
+//!
+//! Milestones are always recorded locally. When the user opts in, each one is
+//! also published as a NIP-58 badge: a badge definition and an award to
+//! ourselves, both signed by the wallet's nostr key and sent through the outbox.
+
+use crate::error::MutinyError;
+use crate::event::HTLCStatus;
+use crate::nostr::sign_event;
+use crate::outbox::{persist_outbox_event, OutboxEvent};
+use crate::storage::MutinyStorage;
+use crate::MutinyInvoice;
+use bitcoin::bech32::{self, Bech32, Hrp};
+use bitcoin::secp256k1::{Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub(crate) const BADGE_SETTINGS_KEY: &str = "badge_settings";
+pub(crate) const MILESTONE_PREFIX: &str = "milestone/";
+/// NIP-58 badge definition, a parameterized replaceable event
+const BADGE_DEFINITION_KIND: u64 = 30009;
+/// NIP-58 badge award
+const BADGE_AWARD_KIND: u64 = 8;
+/// Sent payments with one of these labels are counted as zaps, zap invoices
+/// only commit to the zap request by hash so we can't tell from the invoice
+const ZAP_LABELS: [&str; 2] = ["zap", "zaps"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Milestone {
+    FirstPaymentReceived,
+    FirstPaymentSent,
+    FirstZapSent,
+    HundredthPayment,
+}
+
+impl Milestone {
+    pub const ALL: [Milestone; 4] = [
+        Milestone::FirstPaymentReceived,
+        Milestone::FirstPaymentSent,
+        Milestone::FirstZapSent,
+        Milestone::HundredthPayment,
+    ];
+
+    /// The `d` tag of the milestone's badge definition
+    pub fn badge_id(&self) -> &'static str {
+        match self {
+            Milestone::FirstPaymentReceived => "mutiny-first-payment-received",
+            Milestone::FirstPaymentSent => "mutiny-first-payment-sent",
+            Milestone::FirstZapSent => "mutiny-first-zap-sent",
+            Milestone::HundredthPayment => "mutiny-hundredth-payment",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Milestone::FirstPaymentReceived => "First Sats",
+            Milestone::FirstPaymentSent => "First Spend",
+            Milestone::FirstZapSent => "First Zap",
+            Milestone::HundredthPayment => "Centurion",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Milestone::FirstPaymentReceived => "Received a first lightning payment",
+            Milestone::FirstPaymentSent => "Sent a first lightning payment",
+            Milestone::FirstZapSent => "Sent a first zap",
+            Milestone::HundredthPayment => "Made 100 lightning payments",
+        }
+    }
+}
+
+/// A milestone the wallet has reached
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EarnedMilestone {
+    pub milestone: Milestone,
+    /// When the payment that earned it was made, epoch seconds
+    pub earned_at: u64,
+    /// Whether the badge was queued for publishing
+    pub published: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BadgeSettings {
+    /// Publish badges for new milestones to nostr, off unless the user opts in
+    pub publish: bool,
+}
+
+pub(crate) fn npub(pubkey: &XOnlyPublicKey) -> Result<String, MutinyError> {
+    bech32::encode::<Bech32>(Hrp::parse_unchecked("npub"), &pubkey.serialize())
+        .map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
+fn is_zap(invoice: &MutinyInvoice) -> bool {
+    invoice
+        .labels
+        .iter()
+        .any(|l| ZAP_LABELS.contains(&l.trim().to_lowercase().as_str()))
+}
+
+/// The milestones the payments have reached, with when each was reached
+pub(crate) fn reached_milestones(invoices: &[MutinyInvoice]) -> Vec<(Milestone, u64)> {
+    let mut paid: Vec<&MutinyInvoice> = invoices
+        .iter()
+        .filter(|i| i.status == HTLCStatus::Succeeded)
+        .collect();
+    paid.sort_by_key(|i| i.last_updated);
+
+    let first =
+        |f: &dyn Fn(&MutinyInvoice) -> bool| paid.iter().find(|i| f(i)).map(|i| i.last_updated);
+    let mut reached = vec![];
+    if let Some(at) = first(&|i| i.inbound) {
+        reached.push((Milestone::FirstPaymentReceived, at));
+    }
+    if let Some(at) = first(&|i| !i.inbound) {
+        reached.push((Milestone::FirstPaymentSent, at));
+    }
+    if let Some(at) = first(&|i| !i.inbound && is_zap(i)) {
+        reached.push((Milestone::FirstZapSent, at));
+    }
+    if let Some(invoice) = paid.get(99) {
+        reached.push((Milestone::HundredthPayment, invoice.last_updated));
+    }
+    reached
+}
+
+/// The badge definition and our award of it to ourselves
+pub(crate) fn create_badge_events(key: &SecretKey, milestone: Milestone) -> (Value, Value) {
+    let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+    let definition_tags = json!([
+        ["d", milestone.badge_id()],
+        ["name", milestone.name()],
+        ["description", milestone.description()],
+    ]);
+    let definition = sign_event(key, BADGE_DEFINITION_KIND, definition_tags, String::new());
+
+    let award_tags = json!([
+        [
+            "a",
+            format!("{BADGE_DEFINITION_KIND}:{pubkey}:{}", milestone.badge_id())
+        ],
+        ["p", pubkey.to_string()],
+    ]);
+    let award = sign_event(key, BADGE_AWARD_KIND, award_tags, String::new());
+
+    (definition, award)
+}
+
+/// Records any newly reached milestones and, when publishing is on, queues
+/// the badges of every milestone that hasn't been published yet.
+/// Returns the newly reached ones.
+pub(crate) fn update_milestones<S: MutinyStorage>(
+    storage: &S,
+    key: &SecretKey,
+    invoices: &[MutinyInvoice],
+    relays: Vec<String>,
+) -> Result<Vec<EarnedMilestone>, MutinyError> {
+    let earned = list_earned_milestones(storage)?;
+    let mut new = vec![];
+    for (milestone, earned_at) in reached_milestones(invoices) {
+        if earned.iter().all(|e| e.milestone != milestone) {
+            let milestone = EarnedMilestone {
+                milestone,
+                earned_at,
+                published: false,
+            };
+            persist_earned_milestone(storage, &milestone)?;
+            new.push(milestone);
+        }
+    }
+
+    if get_badge_settings(storage)?.publish {
+        let unpublished = earned
+            .into_iter()
+            .chain(new.clone())
+            .filter(|e| !e.published);
+        for mut milestone in unpublished {
+            let (definition, award) = create_badge_events(key, milestone.milestone);
+            persist_outbox_event(storage, &OutboxEvent::new(definition, relays.clone())?)?;
+            persist_outbox_event(storage, &OutboxEvent::new(award, relays.clone())?)?;
+            milestone.published = true;
+            persist_earned_milestone(storage, &milestone)?;
+        }
+    }
+
+    Ok(new)
+}
+
+pub(crate) fn get_badge_settings<S: MutinyStorage>(
+    storage: &S,
+) -> Result<BadgeSettings, MutinyError> {
+    Ok(storage.get_data(BADGE_SETTINGS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_badge_settings<S: MutinyStorage>(
+    storage: &S,
+    settings: &BadgeSettings,
+) -> Result<(), MutinyError> {
+    storage.write_data(BADGE_SETTINGS_KEY.to_string(), settings, None)
+}
+
+fn milestone_key(milestone: Milestone) -> String {
+    format!("{MILESTONE_PREFIX}{}", milestone.badge_id())
+}
+
+pub(crate) fn persist_earned_milestone<S: MutinyStorage>(
+    storage: &S,
+    milestone: &EarnedMilestone,
+) -> Result<(), MutinyError> {
+    storage.write_data(milestone_key(milestone.milestone), milestone, None)
+}
+
+/// Lists the milestones reached so far, newest first
+pub(crate) fn list_earned_milestones<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<EarnedMilestone>, MutinyError> {
+    let mut milestones: Vec<EarnedMilestone> = storage
+        .scan::<EarnedMilestone>(MILESTONE_PREFIX, None)?
+        .into_values()
+        .collect();
+    milestones.sort_by_key(|m| std::cmp::Reverse(m.earned_at));
+
+    Ok(milestones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr::verify_event;
+    use crate::outbox::list_outbox_events;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn payment(inbound: bool, last_updated: u64, labels: Vec<String>) -> MutinyInvoice {
+        MutinyInvoice {
+            inbound,
+            status: HTLCStatus::Succeeded,
+            last_updated,
+            labels,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reached_milestones() {
+        let test_name = "test_reached_milestones";
+        log!("{}", test_name);
+
+        let mut invoices = vec![
+            MutinyInvoice {
+                status: HTLCStatus::Failed,
+                ..payment(false, 1, vec![])
+            },
+            payment(true, 2, vec![]),
+            payment(false, 3, vec![]),
+            payment(false, 4, vec!["Zaps".to_string()]),
+        ];
+        assert_eq!(
+            reached_milestones(&invoices),
+            vec![
+                (Milestone::FirstPaymentReceived, 2),
+                (Milestone::FirstPaymentSent, 3),
+                (Milestone::FirstZapSent, 4),
+            ]
+        );
+
+        invoices.extend((0..97).map(|i| payment(true, 10 + i, vec![])));
+        assert!(reached_milestones(&invoices).contains(&(Milestone::HundredthPayment, 106)));
+    }
+
+    #[test]
+    fn test_update_milestones() {
+        let test_name = "test_update_milestones";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let invoices = vec![payment(true, 2, vec![])];
+
+        // recorded locally, nothing published until opted in
+        let new = update_milestones(&storage, &key, &invoices, relays.clone()).unwrap();
+        assert_eq!(new.len(), 1);
+        assert!(list_outbox_events(&storage).unwrap().is_empty());
+        assert!(update_milestones(&storage, &key, &invoices, relays.clone())
+            .unwrap()
+            .is_empty());
+
+        persist_badge_settings(&storage, &BadgeSettings { publish: true }).unwrap();
+        update_milestones(&storage, &key, &invoices, relays.clone()).unwrap();
+        let events = list_outbox_events(&storage).unwrap();
+        assert_eq!(events.len(), 2);
+        let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+        for event in events {
+            assert_eq!(verify_event(&event.event).unwrap(), pubkey);
+        }
+        assert!(list_earned_milestones(&storage).unwrap()[0].published);
+
+        // published only once
+        update_milestones(&storage, &key, &invoices, relays).unwrap();
+        assert_eq!(list_outbox_events(&storage).unwrap().len(), 2);
+
+        assert!(npub(&pubkey).unwrap().starts_with("npub1"));
+    }
+}
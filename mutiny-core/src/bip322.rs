@@ -0,0 +1,179 @@
+//! BIP-322 "simple" message signatures, used to prove ownership of an address.
+//!
+//! The signature is the witness of a virtual transaction spending a virtual
+//! output locked to the address, committing to the message. Only single key
+//! addresses are supported: P2WPKH and P2TR key path spends.
+
+use crate::error::MutinyError;
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::blockdata::opcodes::OP_0;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::CompressedPublicKey;
+use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, script, Address, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+    Txid, Witness,
+};
+
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// The tagged hash of the message, as committed to by the to_spend transaction
+pub(crate) fn message_hash(message: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The virtual transaction creating the output that gets "spent" by the signature
+pub(crate) fn to_spend_tx(script_pubkey: &ScriptBuf, message: &[u8]) -> Transaction {
+    let script_sig = script::Builder::new()
+        .push_opcode(OP_0)
+        .push_slice(message_hash(message))
+        .into_script();
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFFFFFF),
+            script_sig,
+            sequence: Sequence(0),
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    }
+}
+
+/// The virtual transaction whose witness is the signature, unsigned
+pub(crate) fn to_sign_tx(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence(0),
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script::Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+/// Encodes a signed to_sign witness as a BIP-322 simple signature
+pub(crate) fn encode_signature(witness: &Witness) -> String {
+    base64::encode(serialize(witness))
+}
+
+/// Checks a BIP-322 simple signature for the message was made by the address's key
+pub fn verify_message_bip322(
+    address: &Address,
+    message: &str,
+    signature: &str,
+) -> Result<bool, MutinyError> {
+    let witness: Witness =
+        deserialize(&base64::decode(signature)?).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let script_pubkey = address.script_pubkey();
+    let to_spend = to_spend_tx(&script_pubkey, message.as_bytes());
+    let to_sign = to_sign_tx(&to_spend);
+    let secp = Secp256k1::verification_only();
+
+    if script_pubkey.is_p2wpkh() {
+        let (Some(sig), Some(pubkey), 2) = (witness.nth(0), witness.nth(1), witness.len()) else {
+            return Ok(false);
+        };
+        let (Ok(sig), Ok(pubkey)) = (
+            bitcoin::ecdsa::Signature::from_slice(sig),
+            CompressedPublicKey::from_slice(pubkey),
+        ) else {
+            return Ok(false);
+        };
+        if ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()) != script_pubkey {
+            return Ok(false);
+        }
+        let sighash = SighashCache::new(&to_sign)
+            .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, sig.sighash_type)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        Ok(secp.verify_ecdsa(&msg, &sig.signature, &pubkey.0).is_ok())
+    } else if script_pubkey.is_p2tr() {
+        let (Some(sig), 1) = (witness.nth(0), witness.len()) else {
+            return Ok(false);
+        };
+        let Ok(sig) = bitcoin::taproot::Signature::from_slice(sig) else {
+            return Ok(false);
+        };
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+            .map_err(|_| MutinyError::PubkeyInvalid)?;
+        let sighash = SighashCache::new(&to_sign)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[to_spend.output[0].clone()]),
+                sig.sighash_type,
+            )
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        Ok(secp
+            .verify_schnorr(&sig.signature, &msg, &output_key)
+            .is_ok())
+    } else {
+        // multisig and legacy addresses need the full format, which we don't support
+        Err(MutinyError::InvalidArgumentsError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::hex::DisplayHex;
+    use bitcoin::Network;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_bip322_vectors() {
+        let test_name = "test_bip322_vectors";
+        log!("{}", test_name);
+
+        // test vectors from BIP-322
+        assert_eq!(
+            message_hash(b"").to_lower_hex_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash(b"Hello World").to_lower_hex_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+
+        let address = Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l")
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap();
+        let to_spend = to_spend_tx(&address.script_pubkey(), b"Hello World");
+        assert_eq!(
+            to_spend.compute_txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            to_sign_tx(&to_spend).compute_txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+
+        let signature = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(verify_message_bip322(&address, "Hello World", signature).unwrap());
+        assert!(!verify_message_bip322(&address, "Hello World!", signature).unwrap());
+    }
+}
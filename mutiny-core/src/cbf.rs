@@ -0,0 +1,392 @@
+use crate::error::MutinyError;
+use crate::utils;
+use bitcoin::bip158::BlockFilter;
+use bitcoin::block::Header;
+use bitcoin::consensus::deserialize;
+use bitcoin::params::Params;
+use bitcoin::{Block, BlockHash, CompactTarget, ScriptBuf, Target};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Most headers bitcoind's REST interface returns in one request
+pub(crate) const HEADERS_PER_REQUEST: u32 = 2_000;
+/// Most filters we ask for at once. The REST interface serves one filter per
+/// request, so a batch is sent in parallel instead of one after the other.
+pub(crate) const FILTERS_PER_BATCH: usize = 50;
+/// Size of a serialized block header
+const HEADER_SIZE: usize = 80;
+
+#[derive(Deserialize)]
+struct ChainInfo {
+    blocks: u32,
+}
+
+#[derive(Deserialize)]
+struct BlockHashResponse {
+    blockhash: String,
+}
+
+/// Fetches BIP158 compact block filters, and the blocks they match, from a
+/// bitcoind REST endpoint (`-rest -blockfilterindex`). Filters are matched
+/// locally so the server never learns our addresses, only which blocks we
+/// download, and those also contain everyone else's transactions.
+#[derive(Debug, Clone)]
+pub struct CompactFilterClient {
+    url: String,
+    client: Client,
+}
+
+impl CompactFilterClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response, MutinyError> {
+        let request = self
+            .client
+            .get(format!("{}/rest/{path}", self.url))
+            .build()
+            .map_err(|_| MutinyError::ChainAccessFailed)?;
+
+        utils::fetch_with_timeout(&self.client, request)
+            .await?
+            .error_for_status()
+            .map_err(|_| MutinyError::ChainAccessFailed)
+    }
+
+    async fn get_bytes(&self, path: &str) -> Result<Vec<u8>, MutinyError> {
+        Ok(self
+            .get(path)
+            .await?
+            .bytes()
+            .await
+            .map_err(|_| MutinyError::ChainAccessFailed)?
+            .to_vec())
+    }
+
+    /// Height of the server's best block
+    pub(crate) async fn get_tip_height(&self) -> Result<u32, MutinyError> {
+        let info: ChainInfo = self
+            .get("chaininfo.json")
+            .await?
+            .json()
+            .await
+            .map_err(|_| MutinyError::ChainAccessFailed)?;
+        Ok(info.blocks)
+    }
+
+    pub(crate) async fn get_block_hash(&self, height: u32) -> Result<BlockHash, MutinyError> {
+        let response: BlockHashResponse = self
+            .get(&format!("blockhashbyheight/{height}.json"))
+            .await?
+            .json()
+            .await
+            .map_err(|_| MutinyError::ChainAccessFailed)?;
+        BlockHash::from_str(&response.blockhash).map_err(|_| MutinyError::ChainAccessFailed)
+    }
+
+    /// Headers starting with the given block, checked to connect to each other
+    pub(crate) async fn get_headers(
+        &self,
+        from: BlockHash,
+        count: u32,
+    ) -> Result<Vec<Header>, MutinyError> {
+        let bytes = self
+            .get_bytes(&format!("headers/{from}.bin?count={count}"))
+            .await?;
+        if bytes.len() % HEADER_SIZE != 0 {
+            return Err(MutinyError::ChainAccessFailed);
+        }
+        let headers = bytes
+            .chunks(HEADER_SIZE)
+            .map(|chunk| deserialize::<Header>(chunk).map_err(|_| MutinyError::ChainAccessFailed))
+            .collect::<Result<Vec<_>, _>>()?;
+        verify_header_chain(from, &headers)?;
+
+        Ok(headers)
+    }
+
+    /// The filters of the blocks, in the same order
+    pub(crate) async fn get_filters(
+        &self,
+        hashes: &[BlockHash],
+    ) -> Result<Vec<BlockFilter>, MutinyError> {
+        let mut filters = Vec::with_capacity(hashes.len());
+        for batch in hashes.chunks(FILTERS_PER_BATCH) {
+            let futs = batch.iter().map(|hash| self.get_filter(*hash));
+            filters.extend(futures::future::try_join_all(futs).await?);
+        }
+        Ok(filters)
+    }
+
+    pub(crate) async fn get_filter(&self, hash: BlockHash) -> Result<BlockFilter, MutinyError> {
+        let bytes = self
+            .get_bytes(&format!("blockfilter/basic/{hash}.bin"))
+            .await?;
+        // the filter is served as a length prefixed byte vector
+        let content: Vec<u8> = deserialize(&bytes).map_err(|_| MutinyError::ChainAccessFailed)?;
+        Ok(BlockFilter::new(&content))
+    }
+
+    /// The full block, checked to be the one we asked for with all of its transactions
+    pub(crate) async fn get_block(&self, hash: BlockHash) -> Result<Block, MutinyError> {
+        let bytes = self.get_bytes(&format!("block/{hash}.bin")).await?;
+        let block: Block = deserialize(&bytes).map_err(|_| MutinyError::ChainAccessFailed)?;
+        if block.block_hash() != hash || !block.check_merkle_root() {
+            return Err(MutinyError::ChainAccessFailed);
+        }
+        Ok(block)
+    }
+}
+
+/// Checks the headers start at `from`, connect to each other and have valid proof of work
+pub(crate) fn verify_header_chain(from: BlockHash, headers: &[Header]) -> Result<(), MutinyError> {
+    let first = headers.first().ok_or(MutinyError::ChainAccessFailed)?;
+    if first.block_hash() != from {
+        return Err(MutinyError::ChainAccessFailed);
+    }
+
+    for pair in headers.windows(2) {
+        if pair[1].prev_blockhash != pair[0].block_hash()
+            || pair[1].validate_pow(pair[1].target()).is_err()
+        {
+            return Err(MutinyError::ChainAccessFailed);
+        }
+    }
+
+    Ok(())
+}
+
+/// Follows the difficulty along a chain of headers, so each header can be checked
+/// to carry the proof of work the consensus rules require of it, retargets included.
+/// Otherwise a server could make up headers at a difficulty of its choosing.
+#[derive(Debug, Clone)]
+pub(crate) struct DifficultyContext {
+    params: Params,
+    /// Height of the last header checked
+    height: u32,
+    last: Header,
+    /// Time of the first block of the current retarget period
+    period_start_time: u32,
+}
+
+impl DifficultyContext {
+    /// Starts from the first header of a retarget period, its own difficulty is trusted
+    pub(crate) fn new(
+        params: Params,
+        height: u32,
+        period_start: Header,
+    ) -> Result<Self, MutinyError> {
+        if height as u64 % params.difficulty_adjustment_interval() != 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(Self {
+            params,
+            height,
+            last: period_start,
+            period_start_time: period_start.time,
+        })
+    }
+
+    /// Height of the first block of the retarget period the height is in
+    pub(crate) fn period_start(params: &Params, height: u32) -> u32 {
+        height - height % params.difficulty_adjustment_interval() as u32
+    }
+
+    /// Checks the header builds on the last one with the required difficulty
+    /// and moves on to it
+    pub(crate) fn connect(&mut self, header: &Header) -> Result<(), MutinyError> {
+        if header.prev_blockhash != self.last.block_hash() {
+            return Err(MutinyError::ChainAccessFailed);
+        }
+
+        let height = self.height + 1;
+        let retarget = height as u64 % self.params.difficulty_adjustment_interval() == 0;
+        let valid = if self.params.allow_min_difficulty_blocks {
+            // test networks can drop to the minimum difficulty at any time,
+            // all we can check is that the work is there
+            header.target() <= self.params.max_attainable_target
+                && header.validate_pow(header.target()).is_ok()
+        } else {
+            let required = if retarget && !self.params.no_pow_retargeting {
+                // bitcoin core measures the period from its first block to its last
+                let timespan = self.last.time.saturating_sub(self.period_start_time);
+                CompactTarget::from_next_work_required(
+                    self.last.bits,
+                    timespan as u64,
+                    &self.params,
+                )
+            } else {
+                self.last.bits
+            };
+            header.validate_pow(Target::from_compact(required)).is_ok()
+        };
+        if !valid {
+            return Err(MutinyError::ChainAccessFailed);
+        }
+
+        if retarget {
+            self.period_start_time = header.time;
+        }
+        self.height = height;
+        self.last = *header;
+        Ok(())
+    }
+}
+
+/// Whether the block might pay to or spend from any of the scripts
+pub(crate) fn filter_matches(
+    filter: &BlockFilter,
+    hash: BlockHash,
+    scripts: &HashSet<ScriptBuf>,
+) -> Result<bool, MutinyError> {
+    if scripts.is_empty() {
+        return Ok(false);
+    }
+    filter
+        .match_any(hash, scripts.iter().map(|s| s.as_bytes()))
+        .map_err(|_| MutinyError::ChainAccessFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::block::Version;
+    use bitcoin::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction;
+    use bitcoin::{
+        absolute, Amount, Network, OutPoint, Transaction, TxIn, TxMerkleNode, TxOut, WPubkeyHash,
+    };
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn mine_header(prev: &Header, merkle_root: TxMerkleNode) -> Header {
+        let mut header = Header {
+            version: Version::ONE,
+            prev_blockhash: prev.block_hash(),
+            merkle_root,
+            time: prev.time + 600,
+            bits: prev.bits,
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn test_verify_header_chain() {
+        let test_name = "test_verify_header_chain";
+        log!("{}", test_name);
+
+        let genesis = genesis_block(Network::Regtest).header;
+        let one = mine_header(&genesis, TxMerkleNode::all_zeros());
+        let two = mine_header(&one, TxMerkleNode::all_zeros());
+
+        assert!(verify_header_chain(genesis.block_hash(), &[genesis, one, two]).is_ok());
+        // has to start where we asked
+        assert!(verify_header_chain(one.block_hash(), &[genesis, one, two]).is_err());
+        // has to connect
+        assert!(verify_header_chain(genesis.block_hash(), &[genesis, two]).is_err());
+        assert!(verify_header_chain(genesis.block_hash(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_difficulty_context() {
+        let test_name = "test_difficulty_context";
+        log!("{}", test_name);
+
+        // regtest's minimum difficulty, but with mainnet's rules
+        let mut params = Params::new(Network::Regtest);
+        params.allow_min_difficulty_blocks = false;
+        params.no_pow_retargeting = false;
+        let interval = params.difficulty_adjustment_interval() as u32;
+
+        let genesis = genesis_block(Network::Regtest).header;
+        assert!(DifficultyContext::new(params.clone(), 1, genesis).is_err());
+        let mut context = DifficultyContext::new(params.clone(), 0, genesis).unwrap();
+
+        // blocks twice as fast as they should be
+        let mut last = genesis;
+        for _ in 1..interval {
+            let mut header = mine_header(&last, TxMerkleNode::all_zeros());
+            header.time = last.time + 300;
+            while header.validate_pow(header.target()).is_err() {
+                header.nonce += 1;
+            }
+            context.connect(&header).unwrap();
+            last = header;
+        }
+
+        // a header that doesn't follow the last one
+        assert!(context.connect(&genesis).is_err());
+
+        // the difficulty has to go up at the retarget
+        let same = mine_header(&last, TxMerkleNode::all_zeros());
+        assert!(context.clone().connect(&same).is_err());
+
+        let timespan = (last.time - genesis.time) as u64;
+        let mut next = same;
+        next.bits = CompactTarget::from_next_work_required(last.bits, timespan, &params);
+        assert_ne!(next.bits, last.bits);
+        while next.validate_pow(next.target()).is_err() {
+            next.nonce += 1;
+        }
+        context.connect(&next).unwrap();
+
+        // and stays there until the next one
+        let mut easier = mine_header(&next, TxMerkleNode::all_zeros());
+        easier.bits = last.bits;
+        while easier.validate_pow(easier.target()).is_err() {
+            easier.nonce += 1;
+        }
+        assert!(context.connect(&easier).is_err());
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let test_name = "test_filter_matches";
+        log!("{}", test_name);
+
+        let ours = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([1; 20]));
+        let theirs = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([2; 20]));
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ours.clone(),
+            }],
+        };
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut block = Block {
+            header: mine_header(&genesis, TxMerkleNode::all_zeros()),
+            txdata: vec![tx],
+        };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        let hash = block.block_hash();
+
+        let filter = BlockFilter::new_script_filter(&block, |_| {
+            Err(bitcoin::bip158::Error::UtxoMissing(OutPoint::null()))
+        })
+        .unwrap();
+
+        assert!(filter_matches(&filter, hash, &HashSet::from([ours.clone()])).unwrap());
+        assert!(!filter_matches(&filter, hash, &HashSet::from([theirs.clone()])).unwrap());
+        assert!(filter_matches(&filter, hash, &HashSet::from([ours, theirs])).unwrap());
+        assert!(!filter_matches(&filter, hash, &HashSet::new()).unwrap());
+    }
+}
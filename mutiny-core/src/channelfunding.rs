@@ -0,0 +1,119 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const CHANNEL_FUNDING_PREFIX: &str = "channel_funding/";
+
+/// A channel open funded from outside the wallet, e.g. by withdrawing a
+/// federation's ecash to the deposit address. Once the deposit confirms the
+/// channel is opened with exactly those coins, so the user goes from ecash to
+/// self-custodial lightning without handling the on-chain funds themselves.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelFundingRequest {
+    pub id: String,
+    /// Where the federation should send the funds
+    pub address: String,
+    /// How much the channel is expected to be, whatever arrives is used
+    pub amount_sats: u64,
+    /// The peer to open the channel with, the LSP if not set
+    #[serde(default)]
+    pub peer: Option<PublicKey>,
+    pub status: ChannelFundingStatus,
+    #[serde(default)]
+    pub channel_outpoint: Option<OutPoint>,
+    /// The last error we got trying to open the channel
+    #[serde(default)]
+    pub error: Option<String>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChannelFundingStatus {
+    /// Waiting for the deposit to arrive and confirm
+    WaitingForDeposit,
+    /// The channel open was broadcast
+    Opened,
+    /// Cancelled by the user, anything deposited stays in the on-chain wallet
+    Cancelled,
+}
+
+impl ChannelFundingRequest {
+    /// Label used for the deposit address so the deposit can be
+    /// tied back to the request in the activity
+    pub fn label(&self) -> String {
+        format!("{CHANNEL_FUNDING_PREFIX}{}", self.id)
+    }
+}
+
+fn channel_funding_key(id: &str) -> String {
+    format!("{CHANNEL_FUNDING_PREFIX}{id}")
+}
+
+pub(crate) fn get_channel_funding_request<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<ChannelFundingRequest>, MutinyError> {
+    storage.get_data(channel_funding_key(id))
+}
+
+pub(crate) fn persist_channel_funding_request<S: MutinyStorage>(
+    storage: &S,
+    request: &ChannelFundingRequest,
+) -> Result<(), MutinyError> {
+    storage.write_data(channel_funding_key(&request.id), request, None)
+}
+
+/// All channel funding requests, newest first
+pub(crate) fn list_channel_funding_requests<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<ChannelFundingRequest>, MutinyError> {
+    let mut requests: Vec<ChannelFundingRequest> = storage
+        .scan::<ChannelFundingRequest>(CHANNEL_FUNDING_PREFIX, None)?
+        .into_values()
+        .collect();
+    requests.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_channel_funding_storage() {
+        let test_name = "test_channel_funding_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let request = |id: &str, created_at: u64| ChannelFundingRequest {
+            id: id.to_string(),
+            address: "bcrt1p8wpt9v4frpf3tkn0srd97pksgsxc5hs52lafxwru9kgeephvs7rqjeprhg".to_string(),
+            amount_sats: 100_000,
+            peer: None,
+            status: ChannelFundingStatus::WaitingForDeposit,
+            channel_outpoint: None,
+            error: None,
+            created_at,
+        };
+
+        persist_channel_funding_request(&storage, &request("a", 1)).unwrap();
+        persist_channel_funding_request(&storage, &request("b", 2)).unwrap();
+
+        let requests = list_channel_funding_requests(&storage).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].id, "b");
+        assert_eq!(
+            get_channel_funding_request(&storage, "a").unwrap(),
+            Some(request("a", 1))
+        );
+        assert_eq!(request("a", 1).label(), "channel_funding/a");
+    }
+}
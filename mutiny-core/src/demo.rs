@@ -0,0 +1,310 @@
+//! A wallet that makes up its activity, invoices and balances instead of
+//! talking to the network. Everything is derived from a seed string, so the
+//! same seed always gives the same wallet, which is what frontend development
+//! and screenshots need.
+
+use crate::error::MutinyError;
+use crate::event::HTLCStatus;
+use crate::nodemanager::MutinyBip21RawMaterials;
+use crate::{ActivityItem, MutinyBalance, MutinyInvoice, PrivacyLevel, TransactionDetails};
+use bdk_chain::ConfirmationTime;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::{Keypair, Secp256k1};
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Address, Network, Txid};
+use hex_conservative::DisplayHex;
+use lightning::ln::PaymentSecret;
+use lightning_invoice::{Bolt11Invoice, InvoiceBuilder};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// All demo activity happens after this time, so it never depends on the clock
+const DEMO_START_TIME: u64 = 1_700_000_000;
+const DEMO_START_HEIGHT: u32 = 820_000;
+/// Time between demo activity items
+const DEMO_ACTIVITY_INTERVAL_SECS: u64 = 3 * 60 * 60;
+const DEMO_ACTIVITY_COUNT: u64 = 12;
+
+const DEMO_LABELS: [&str; 6] = ["Coffee", "Rent", "Alice", "Bob", "Savings", "Zaps"];
+
+pub struct DemoWallet {
+    seed: String,
+    network: Network,
+    next_address_index: AtomicU32,
+    next_invoice_index: AtomicU32,
+}
+
+impl DemoWallet {
+    pub fn new(seed: String, network: Network) -> Self {
+        Self {
+            seed,
+            network,
+            next_address_index: AtomicU32::new(0),
+            next_invoice_index: AtomicU32::new(0),
+        }
+    }
+
+    /// Deterministic bytes for the given purpose
+    fn hash(&self, tag: &str, index: u64) -> sha256::Hash {
+        sha256::Hash::hash(format!("{}/{tag}/{index}", self.seed).as_bytes())
+    }
+
+    /// A deterministic number in `[min, max)`
+    fn number(&self, tag: &str, index: u64, min: u64, max: u64) -> u64 {
+        let bytes = self.hash(tag, index).to_byte_array();
+        let n = u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes"));
+        min + n % (max - min)
+    }
+
+    fn secret_key(&self, tag: &str, index: u64) -> SecretKey {
+        // a hash is a valid key with overwhelming probability, skip the rare one that isn't
+        (0..)
+            .find_map(|i| SecretKey::from_slice(&self.hash(tag, index + i).to_byte_array()).ok())
+            .expect("a valid key")
+    }
+
+    fn label(&self, index: u64) -> String {
+        DEMO_LABELS[self.number("label", index, 0, DEMO_LABELS.len() as u64) as usize].to_string()
+    }
+
+    /// The made up activity, newest first. Every third item is an on-chain
+    /// receive, the rest are lightning payments in and out.
+    pub fn get_activity(&self) -> Vec<ActivityItem> {
+        let mut activity = (0..DEMO_ACTIVITY_COUNT)
+            .map(|i| {
+                let time = DEMO_START_TIME + i * DEMO_ACTIVITY_INTERVAL_SECS;
+                match i % 3 {
+                    0 => ActivityItem::OnChain(self.demo_transaction(i, time)),
+                    1 => ActivityItem::Lightning(Box::new(self.demo_payment(i, time, true))),
+                    _ => ActivityItem::Lightning(Box::new(self.demo_payment(i, time, false))),
+                }
+            })
+            .collect::<Vec<_>>();
+        activity.sort_by(|a, b| b.cmp(a));
+        activity
+    }
+
+    fn demo_transaction(&self, index: u64, time: u64) -> TransactionDetails {
+        let txid = Txid::from_raw_hash(self.hash("txid", index));
+        // the newest on-chain receive is left unconfirmed
+        let confirmation_time = if index + 3 >= DEMO_ACTIVITY_COUNT {
+            ConfirmationTime::Unconfirmed { last_seen: time }
+        } else {
+            ConfirmationTime::Confirmed {
+                height: DEMO_START_HEIGHT + (index * DEMO_ACTIVITY_INTERVAL_SECS / 600) as u32,
+                time,
+            }
+        };
+
+        TransactionDetails {
+            transaction: None,
+            txid: Some(txid),
+            internal_id: txid,
+            received: self.number("onchain", index, 100_000, 1_000_000),
+            sent: 0,
+            fee: Some(self.number("fee", index, 200, 2_000)),
+            confirmation_time,
+            labels: vec![self.label(index)],
+            replaces: vec![],
+            address_reused: false,
+        }
+    }
+
+    fn demo_payment(&self, index: u64, time: u64, inbound: bool) -> MutinyInvoice {
+        // outbound payments are always smaller than the inbound one before them
+        let amount_sats = if inbound {
+            self.number("lightning", index, 10_000, 60_000)
+        } else {
+            self.number("lightning", index, 1_000, 10_000)
+        };
+        let preimage = self.hash("preimage", index).to_byte_array();
+        let mut invoice: MutinyInvoice = self
+            .build_invoice(Some(amount_sats), &self.label(index), time, &preimage)
+            .expect("demo labels fit in an invoice")
+            .into();
+        invoice.preimage = Some(preimage.to_lower_hex_string());
+        invoice.status = HTLCStatus::Succeeded;
+        invoice.inbound = inbound;
+        invoice.labels = vec![self.label(index)];
+        invoice.last_updated = time;
+        if !inbound {
+            let fee = self.number("routing_fee", index, 1, 20);
+            invoice.fees_paid = Some(fee);
+            invoice.fee_paid_msat = Some(fee * 1_000);
+            invoice.privacy_level = PrivacyLevel::Private;
+        }
+        invoice
+    }
+
+    fn build_invoice(
+        &self,
+        amount_sats: Option<u64>,
+        description: &str,
+        time: u64,
+        preimage: &[u8; 32],
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        let secp = Secp256k1::new();
+        let node_key = self.secret_key("node", 0);
+        let payment_hash = sha256::Hash::hash(preimage);
+        let payment_secret = self.hash("payment_secret", time).to_byte_array();
+
+        let builder = InvoiceBuilder::new(self.network.into())
+            .description(description.to_string())
+            .duration_since_epoch(Duration::from_secs(time))
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(payment_secret))
+            .min_final_cltv_expiry_delta(144);
+        let builder = match amount_sats {
+            Some(sats) => {
+                let msats = sats.checked_mul(1_000).ok_or(MutinyError::BadAmountError)?;
+                builder.amount_milli_satoshis(msats)
+            }
+            None => builder,
+        };
+
+        // signing is deterministic so the same inputs always give the same invoice,
+        // it fails if the description is too long for an invoice
+        builder
+            .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &node_key))
+            .map_err(|_| MutinyError::InvoiceCreationFailed)
+    }
+
+    /// Balances that add up to the demo activity
+    pub fn get_balance(&self) -> MutinyBalance {
+        let mut balance = MutinyBalance {
+            confirmed: 0,
+            unconfirmed: 0,
+            lightning: 0,
+            closing: 0,
+        };
+        // oldest first so each payment out comes after the one in that pays for it
+        for item in self.get_activity().into_iter().rev() {
+            match item {
+                ActivityItem::OnChain(tx) => match tx.confirmation_time {
+                    ConfirmationTime::Confirmed { .. } => balance.confirmed += tx.received,
+                    ConfirmationTime::Unconfirmed { .. } => balance.unconfirmed += tx.received,
+                },
+                ActivityItem::Lightning(invoice) => {
+                    let amount = invoice.amount_sats.unwrap_or_default();
+                    if invoice.inbound {
+                        balance.lightning += amount;
+                    } else {
+                        balance.lightning -= amount + invoice.fees_paid.unwrap_or_default();
+                    }
+                }
+                ActivityItem::ChannelClosed(_)
+                | ActivityItem::Rebalance(_)
+                | ActivityItem::ChannelPurchase(_) => {}
+            }
+        }
+        balance
+    }
+
+    /// The next demo receive address
+    pub fn get_new_address(&self) -> Address {
+        let index = self.next_address_index.fetch_add(1, Ordering::Relaxed);
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &self.secret_key("address", index as u64));
+        Address::p2tr(&secp, keypair.x_only_public_key().0, None, self.network)
+    }
+
+    /// A real, but unpayable, invoice for the given amount
+    pub fn create_invoice(
+        &self,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        if amount_sats == Some(0) {
+            return Err(MutinyError::BadAmountError);
+        }
+        let index = self.next_invoice_index.fetch_add(1, Ordering::Relaxed) as u64;
+        let time = DEMO_START_TIME + DEMO_ACTIVITY_COUNT * DEMO_ACTIVITY_INTERVAL_SECS + index;
+        let preimage = self.hash("invoice", index).to_byte_array();
+        let description = labels.first().cloned().unwrap_or_default();
+
+        let mut invoice: MutinyInvoice = self
+            .build_invoice(amount_sats, &description, time, &preimage)?
+            .into();
+        invoice.inbound = true;
+        invoice.labels = labels;
+        invoice.last_updated = time;
+        Ok(invoice)
+    }
+
+    pub fn create_bip21(
+        &self,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyError> {
+        let invoice = self.create_invoice(amount_sats, labels.clone())?;
+        Ok(MutinyBip21RawMaterials {
+            address: self.get_new_address(),
+            invoice: invoice.bolt11,
+            btc_amount: amount_sats
+                .map(|amount| bitcoin::Amount::from_sat(amount).to_btc().to_string()),
+            labels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_demo_wallet_is_deterministic() {
+        let test_name = "test_demo_wallet_is_deterministic";
+        log!("{}", test_name);
+
+        let one = DemoWallet::new("screenshots".to_string(), Network::Signet);
+        let two = DemoWallet::new("screenshots".to_string(), Network::Signet);
+        let other = DemoWallet::new("something else".to_string(), Network::Signet);
+
+        assert_eq!(one.get_activity(), two.get_activity());
+        assert_ne!(one.get_activity(), other.get_activity());
+        assert_eq!(one.get_activity().len(), DEMO_ACTIVITY_COUNT as usize);
+        let address = one.get_new_address();
+        assert_eq!(address, two.get_new_address());
+        assert_ne!(address, one.get_new_address());
+
+        let bip21 = one
+            .create_bip21(Some(5_000), vec!["Test".to_string()])
+            .unwrap();
+        let same = two
+            .create_bip21(Some(5_000), vec!["Test".to_string()])
+            .unwrap();
+        assert_eq!(bip21.invoice, same.invoice);
+        assert_eq!(bip21.btc_amount, Some("0.00005".to_string()));
+        assert_eq!(
+            bip21.invoice.unwrap().amount_milli_satoshis(),
+            Some(5_000_000)
+        );
+        assert!(one.create_invoice(Some(0), vec![]).is_err());
+        assert_eq!(
+            one.create_invoice(Some(u64::MAX), vec![]),
+            Err(MutinyError::BadAmountError)
+        );
+        assert_eq!(
+            one.create_invoice(Some(5_000), vec!["a".repeat(1_000)]),
+            Err(MutinyError::InvoiceCreationFailed)
+        );
+    }
+
+    #[test]
+    fn test_demo_balance_matches_activity() {
+        let test_name = "test_demo_balance_matches_activity";
+        log!("{}", test_name);
+
+        let wallet = DemoWallet::new("screenshots".to_string(), Network::Signet);
+        let balance = wallet.get_balance();
+
+        assert!(balance.confirmed > 0);
+        assert!(balance.unconfirmed > 0);
+        assert!(balance.lightning > 0);
+        assert_eq!(balance.lightning, wallet.get_balance().lightning);
+    }
+}
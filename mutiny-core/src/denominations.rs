@@ -0,0 +1,285 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+pub(crate) const DENOMINATION_PREFERENCES_KEY: &str = "denomination_preferences";
+const PRICE_HISTORY_PREFIX: &str = "price_history/";
+/// Prices are kept at a daily resolution to keep the history small
+const PRICE_HISTORY_BUCKET_SECS: u64 = 86_400;
+/// Only the last two years of daily prices are kept
+const PRICE_HISTORY_MAX_BUCKETS: usize = 730;
+
+const SATS_PER_BTC: f64 = 100_000_000.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Denomination {
+    #[default]
+    Sats,
+    Btc,
+    Fiat,
+}
+
+impl FromStr for Denomination {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sats" | "sat" => Ok(Self::Sats),
+            "btc" => Ok(Self::Btc),
+            "fiat" => Ok(Self::Fiat),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DenominationPreferences {
+    pub denomination: Denomination,
+    /// Fiat currency code used for display, e.g. "usd"
+    pub fiat_currency: String,
+    /// BCP 47 locale used for number formatting, e.g. "en-US"
+    pub locale: String,
+}
+
+impl Default for DenominationPreferences {
+    fn default() -> Self {
+        Self {
+            denomination: Denomination::default(),
+            fiat_currency: "usd".to_string(),
+            locale: "en-US".to_string(),
+        }
+    }
+}
+
+/// Returns the (thousands, decimal) separators for the given locale
+fn separators(locale: &str) -> (&'static str, &'static str) {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match language.as_str() {
+        "de" | "es" | "it" | "pt" | "nl" | "id" | "tr" | "da" => (".", ","),
+        "fr" | "ru" | "pl" | "cs" | "sv" | "nb" | "fi" | "uk" => ("\u{a0}", ","),
+        _ => (",", "."),
+    }
+}
+
+/// Formats a number with the given number of decimals using the locale's separators
+fn format_number(value: f64, decimals: usize, locale: &str) -> String {
+    let (thousands, decimal) = separators(locale);
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (int_part, frac_part) = match formatted.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut grouped = String::with_capacity(int_part.len() + int_part.len() / 3);
+    for (i, c) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push_str(thousands);
+        }
+        grouped.push(c);
+    }
+
+    let sign = if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        "-"
+    } else {
+        ""
+    };
+
+    match frac_part {
+        Some(frac) => format!("{sign}{grouped}{decimal}{frac}"),
+        None => format!("{sign}{grouped}"),
+    }
+}
+
+/// Number of decimals a fiat currency is displayed with
+fn fiat_decimals(currency: &str) -> usize {
+    match currency.to_lowercase().as_str() {
+        "jpy" | "krw" | "vnd" | "clp" | "isk" | "huf" => 0,
+        _ => 2,
+    }
+}
+
+pub fn sats_to_fiat(sats: u64, price: f32) -> f64 {
+    sats as f64 / SATS_PER_BTC * price as f64
+}
+
+pub fn fiat_to_sats(fiat: f64, price: f32) -> Result<u64, MutinyError> {
+    if price <= 0.0 || fiat < 0.0 || !fiat.is_finite() {
+        return Err(MutinyError::BadAmountError);
+    }
+    Ok((fiat / price as f64 * SATS_PER_BTC).round() as u64)
+}
+
+pub fn format_sats(sats: u64, locale: &str) -> String {
+    format!("{} sats", format_number(sats as f64, 0, locale))
+}
+
+pub fn format_btc(sats: u64, locale: &str) -> String {
+    format!(
+        "{} BTC",
+        format_number(sats as f64 / SATS_PER_BTC, 8, locale)
+    )
+}
+
+pub fn format_fiat(sats: u64, price: f32, currency: &str, locale: &str) -> String {
+    let value = sats_to_fiat(sats, price);
+    format!(
+        "{} {}",
+        format_number(value, fiat_decimals(currency), locale),
+        currency.to_uppercase()
+    )
+}
+
+/// Formats the amount using the user's preferences. A price is required
+/// when the preferred denomination is fiat.
+pub fn format_amount(
+    sats: u64,
+    prefs: &DenominationPreferences,
+    price: Option<f32>,
+) -> Result<String, MutinyError> {
+    match prefs.denomination {
+        Denomination::Sats => Ok(format_sats(sats, &prefs.locale)),
+        Denomination::Btc => Ok(format_btc(sats, &prefs.locale)),
+        Denomination::Fiat => {
+            let price = price.ok_or(MutinyError::BitcoinPriceError)?;
+            Ok(format_fiat(
+                sats,
+                price,
+                &prefs.fiat_currency,
+                &prefs.locale,
+            ))
+        }
+    }
+}
+
+fn price_history_key(currency: &str) -> String {
+    format!("{PRICE_HISTORY_PREFIX}{}", currency.to_lowercase())
+}
+
+/// Records the price of bitcoin in the given currency at the given time,
+/// dropping the oldest prices once the history is full
+pub(crate) fn record_price<S: MutinyStorage>(
+    storage: &S,
+    currency: &str,
+    timestamp: u64,
+    price: f32,
+) -> Result<(), MutinyError> {
+    let key = price_history_key(currency);
+    let mut history: BTreeMap<u64, f32> = storage.get_data(&key)?.unwrap_or_default();
+    history.insert(timestamp / PRICE_HISTORY_BUCKET_SECS, price);
+    while history.len() > PRICE_HISTORY_MAX_BUCKETS {
+        history.pop_first();
+    }
+    storage.write_data(key, history, None)
+}
+
+/// Gets the latest price of bitcoin in the given currency from at or before
+/// the given time, none if there is no price that old.
+pub(crate) fn get_price_at<S: MutinyStorage>(
+    storage: &S,
+    currency: &str,
+    timestamp: u64,
+) -> Result<Option<f32>, MutinyError> {
+    let history: BTreeMap<u64, f32> = storage
+        .get_data(price_history_key(currency))?
+        .unwrap_or_default();
+    let bucket = timestamp / PRICE_HISTORY_BUCKET_SECS;
+
+    let price = history
+        .range(..=bucket)
+        .next_back()
+        .map(|(_, price)| *price);
+
+    Ok(price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_format_amounts() {
+        let test_name = "test_format_amounts";
+        log!("{}", test_name);
+
+        assert_eq!(format_sats(1_234_567, "en-US"), "1,234,567 sats");
+        assert_eq!(format_sats(123, "en-US"), "123 sats");
+        assert_eq!(format_sats(1_234_567, "de-DE"), "1.234.567 sats");
+        assert_eq!(format_btc(1_234_567, "en-US"), "0.01234567 BTC");
+        assert_eq!(format_btc(1_234_567, "de"), "0,01234567 BTC");
+        assert_eq!(format_fiat(100_000, 50_000.0, "usd", "en-US"), "50.00 USD");
+        assert_eq!(format_fiat(100_000, 5_000_000.0, "jpy", "en"), "5,000 JPY");
+
+        let prefs = DenominationPreferences {
+            denomination: Denomination::Fiat,
+            ..Default::default()
+        };
+        assert!(format_amount(1, &prefs, None).is_err());
+    }
+
+    #[test]
+    fn test_fiat_conversion() {
+        let test_name = "test_fiat_conversion";
+        log!("{}", test_name);
+
+        assert_eq!(fiat_to_sats(50.0, 50_000.0).unwrap(), 100_000);
+        assert_eq!(sats_to_fiat(100_000, 50_000.0), 50.0);
+        assert!(fiat_to_sats(1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_price_history() {
+        let test_name = "test_price_history";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert_eq!(get_price_at(&storage, "usd", 0).unwrap(), None);
+
+        record_price(&storage, "USD", PRICE_HISTORY_BUCKET_SECS * 10, 100.0).unwrap();
+        record_price(&storage, "usd", PRICE_HISTORY_BUCKET_SECS * 20, 200.0).unwrap();
+
+        // there is no price from before the history
+        assert_eq!(get_price_at(&storage, "usd", 0).unwrap(), None);
+        assert_eq!(
+            get_price_at(&storage, "usd", PRICE_HISTORY_BUCKET_SECS * 15).unwrap(),
+            Some(100.0)
+        );
+        assert_eq!(
+            get_price_at(&storage, "usd", PRICE_HISTORY_BUCKET_SECS * 25).unwrap(),
+            Some(200.0)
+        );
+        assert_eq!(get_price_at(&storage, "eur", 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_price_history_pruned() {
+        let test_name = "test_price_history_pruned";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let buckets = PRICE_HISTORY_MAX_BUCKETS as u64 + 10;
+        for day in 0..buckets {
+            record_price(&storage, "usd", day * PRICE_HISTORY_BUCKET_SECS, day as f32).unwrap();
+        }
+
+        let history: BTreeMap<u64, f32> =
+            storage.get_data(price_history_key("usd")).unwrap().unwrap();
+        assert_eq!(history.len(), PRICE_HISTORY_MAX_BUCKETS);
+        assert_eq!(get_price_at(&storage, "usd", 0).unwrap(), None);
+        assert_eq!(
+            get_price_at(&storage, "usd", 10 * PRICE_HISTORY_BUCKET_SECS).unwrap(),
+            Some(10.0)
+        );
+    }
+}
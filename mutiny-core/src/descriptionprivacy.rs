@@ -0,0 +1,125 @@
+//! What goes into the description of the invoices we create.
+//!
+//! An invoice string is seen by everyone it passes through on the way to the
+//! payer, and its description is readable by all of them. By default it is the
+//! invoice's first label, often a contact's name or a memo, which says a lot
+//! about who is getting paid for what. The description can instead be left
+//! generic, or only committed to by its hash while the text stays local.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const DESCRIPTION_PRIVACY_KEY: &str = "description_privacy";
+/// The description when the real one is withheld, the same as an invoice without labels
+pub const GENERIC_INVOICE_DESCRIPTION: &str = "";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DescriptionPrivacy {
+    /// The invoice's first label, as is
+    #[default]
+    Full,
+    /// A placeholder that says nothing about the payment
+    Generic,
+    /// Only the hash of the first label, the text itself stays in the wallet
+    Hashed,
+}
+
+impl FromStr for DescriptionPrivacy {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "generic" => Ok(Self::Generic),
+            "hashed" => Ok(Self::Hashed),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// What the invoice commits to as its description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum InvoiceDescription {
+    Direct(String),
+    Hash(sha256::Hash),
+}
+
+impl InvoiceDescription {
+    pub(crate) fn new(privacy: DescriptionPrivacy, labels: &[String]) -> Self {
+        let description = labels.first().cloned().unwrap_or_default();
+        match privacy {
+            DescriptionPrivacy::Full => Self::Direct(description),
+            DescriptionPrivacy::Generic => Self::Direct(GENERIC_INVOICE_DESCRIPTION.to_string()),
+            DescriptionPrivacy::Hashed => Self::Hash(sha256::Hash::hash(description.as_bytes())),
+        }
+    }
+}
+
+pub(crate) fn get_description_privacy<S: MutinyStorage>(
+    storage: &S,
+) -> Result<DescriptionPrivacy, MutinyError> {
+    Ok(storage
+        .get_data(DESCRIPTION_PRIVACY_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn set_description_privacy<S: MutinyStorage>(
+    storage: &S,
+    privacy: DescriptionPrivacy,
+) -> Result<(), MutinyError> {
+    storage.write_data(DESCRIPTION_PRIVACY_KEY.to_string(), privacy, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_invoice_description() {
+        let test_name = "test_invoice_description";
+        log!("{}", test_name);
+
+        let labels = vec!["Alice".to_string(), "rent".to_string()];
+        assert_eq!(
+            InvoiceDescription::new(DescriptionPrivacy::Full, &labels),
+            InvoiceDescription::Direct("Alice".to_string())
+        );
+        assert_eq!(
+            InvoiceDescription::new(DescriptionPrivacy::Generic, &labels),
+            InvoiceDescription::Direct(GENERIC_INVOICE_DESCRIPTION.to_string())
+        );
+        assert_eq!(
+            InvoiceDescription::new(DescriptionPrivacy::Hashed, &labels),
+            InvoiceDescription::Hash(sha256::Hash::hash(b"Alice"))
+        );
+        assert_eq!(
+            InvoiceDescription::new(DescriptionPrivacy::Full, &[]),
+            InvoiceDescription::Direct(String::new())
+        );
+
+        assert_eq!(
+            DescriptionPrivacy::from_str("Hashed").unwrap(),
+            DescriptionPrivacy::Hashed
+        );
+        assert!(DescriptionPrivacy::from_str("secret").is_err());
+
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_description_privacy(&storage).unwrap(),
+            DescriptionPrivacy::Full
+        );
+        set_description_privacy(&storage, DescriptionPrivacy::Generic).unwrap();
+        assert_eq!(
+            get_description_privacy(&storage).unwrap(),
+            DescriptionPrivacy::Generic
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! An anonymized summary of the wallet's channels and payments for bug
+//! reports. It is built on the device and holds no keys, pubkeys, hashes,
+//! txids, labels or exact amounts, only buckets and counts. What the user
+//! previews is exactly what gets attached.
+
+use crate::event::HTLCStatus;
+use crate::nodemanager::{ChannelClosure, MutinyChannel};
+use crate::paymentfailure::PaymentFailureDetails;
+use crate::MutinyInvoice;
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bumped whenever what's in the bundle changes
+pub const DIAGNOSTICS_VERSION: u32 = 1;
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiagnosticsBundle {
+    pub version: u32,
+    /// Day the bundle was made, as epoch seconds rounded down to midnight UTC
+    pub generated_on: u64,
+    pub network: Network,
+    pub channels: Vec<ChannelDiagnostics>,
+    /// Closed channels by the kind of closure
+    pub closures: BTreeMap<String, usize>,
+    pub payments: PaymentDiagnostics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChannelDiagnostics {
+    pub size: String,
+    /// Our share of the channel, rounded to the nearest 10%
+    pub local_balance_percent: u8,
+    pub usable: bool,
+    pub opened_by_us: bool,
+    pub anchor: bool,
+    pub awaiting_confirmations: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentDiagnostics {
+    pub inbound_succeeded: usize,
+    pub inbound_pending: usize,
+    pub outbound_succeeded: usize,
+    pub outbound_failed: usize,
+    pub outbound_in_flight: usize,
+    /// Payments by amount bucket
+    pub amounts: BTreeMap<String, usize>,
+    /// Failure messages with anything identifying stripped, by count
+    pub failure_codes: BTreeMap<String, usize>,
+    pub failed_attempts: usize,
+    /// Median time from the first to the last failed attempt of a payment
+    pub median_retry_secs: Option<u64>,
+}
+
+/// A coarse size so a channel can't be matched to the public graph
+pub(crate) fn amount_bucket(sats: u64) -> String {
+    match sats {
+        0..=9_999 => "<10k",
+        10_000..=99_999 => "10k-100k",
+        100_000..=499_999 => "100k-500k",
+        500_000..=999_999 => "500k-1M",
+        1_000_000..=4_999_999 => "1M-5M",
+        _ => ">5M",
+    }
+    .to_string()
+}
+
+/// The kind of failure without its details, which can contain channel ids
+/// or messages from peers, e.g. "Failed on path: ChannelFailure"
+pub(crate) fn failure_code(failure: &str) -> String {
+    let end = failure.find(['{', '(', '[', '\n']).unwrap_or(failure.len());
+    failure[..end]
+        .trim()
+        .trim_end_matches(':')
+        .trim()
+        .to_string()
+}
+
+impl DiagnosticsBundle {
+    pub(crate) fn new(
+        network: Network,
+        now: u64,
+        channels: &[MutinyChannel],
+        closures: &[ChannelClosure],
+        invoices: &[MutinyInvoice],
+        failures: &[PaymentFailureDetails],
+    ) -> Self {
+        let mut channels: Vec<ChannelDiagnostics> = channels
+            .iter()
+            .map(|c| ChannelDiagnostics {
+                size: amount_bucket(c.size),
+                local_balance_percent: match c.size {
+                    0 => 0,
+                    size => ((c.balance * 10 + size / 2) / size * 10).min(100) as u8,
+                },
+                usable: c.is_usable,
+                opened_by_us: c.is_outbound,
+                anchor: c.is_anchor,
+                awaiting_confirmations: c
+                    .confirmations_required
+                    .is_some_and(|required| c.confirmations < required),
+            })
+            .collect();
+        // sorted so the order doesn't say which channel is which
+        channels.sort();
+
+        let mut closure_counts = BTreeMap::new();
+        for closure in closures {
+            // the reason can end with a message from the peer, e.g. for force closes
+            let kind = closure.reason.split(':').next().unwrap_or_default();
+            *closure_counts.entry(failure_code(kind)).or_default() += 1;
+        }
+
+        let mut payments = PaymentDiagnostics::default();
+        for invoice in invoices {
+            match (invoice.inbound, &invoice.status) {
+                (true, HTLCStatus::Succeeded) => payments.inbound_succeeded += 1,
+                (true, _) => payments.inbound_pending += 1,
+                (false, HTLCStatus::Succeeded) => payments.outbound_succeeded += 1,
+                (false, HTLCStatus::Failed) => payments.outbound_failed += 1,
+                (false, _) => payments.outbound_in_flight += 1,
+            }
+            if let Some(amount) = invoice.amount_sats {
+                *payments.amounts.entry(amount_bucket(amount)).or_default() += 1;
+            }
+        }
+
+        let mut retry_secs = vec![];
+        for details in failures {
+            payments.failed_attempts += details.attempts.len();
+            for attempt in details.attempts.iter() {
+                *payments
+                    .failure_codes
+                    .entry(failure_code(&attempt.failure))
+                    .or_default() += 1;
+            }
+            if let Some(reason) = details.reason.as_ref() {
+                *payments
+                    .failure_codes
+                    .entry(failure_code(reason))
+                    .or_default() += 1;
+            }
+            let first = details.attempts.iter().map(|a| a.timestamp).min();
+            let last = details.attempts.iter().map(|a| a.timestamp).max();
+            if let (Some(first), Some(last)) = (first, last) {
+                retry_secs.push(last - first);
+            }
+        }
+        retry_secs.sort();
+        payments.median_retry_secs = retry_secs.get(retry_secs.len() / 2).copied();
+
+        Self {
+            version: DIAGNOSTICS_VERSION,
+            generated_on: now - now % DAY_SECS,
+            network,
+            channels,
+            closures: closure_counts,
+            payments,
+        }
+    }
+
+    /// The bundle as it's attached to a bug report
+    pub fn to_json(&self) -> Result<String, crate::error::MutinyError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::PublicKey;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const PEER: &str = "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54";
+
+    #[test]
+    fn test_diagnostics_are_anonymized() {
+        let test_name = "test_diagnostics_are_anonymized";
+        log!("{}", test_name);
+
+        let channel = MutinyChannel {
+            user_chan_id: "secret-channel-id".to_string(),
+            balance: 123_456,
+            size: 250_000,
+            reserve: 2_500,
+            inbound: 124_044,
+            outpoint: None,
+            peer: PublicKey::from_str(PEER).unwrap(),
+            confirmations_required: Some(3),
+            confirmations: 1,
+            is_outbound: true,
+            is_usable: false,
+            is_anchor: true,
+            zero_reserve: false,
+            punishment_reserve: 2_500,
+            pending_outbound_htlcs: 0,
+            dust_exposure: 0,
+            commitment_fee: 0,
+            peer_alias: Some("secret alias".to_string()),
+        };
+        let invoice = MutinyInvoice {
+            amount_sats: Some(21_000),
+            status: HTLCStatus::Failed,
+            labels: vec!["rent".to_string()],
+            ..Default::default()
+        };
+        let failures = PaymentFailureDetails {
+            payment_hash: "secret-hash".to_string(),
+            attempts: vec![],
+            reason: Some("RetriesExhausted".to_string()),
+        };
+
+        let bundle = DiagnosticsBundle::new(
+            Network::Signet,
+            1_700_000_123,
+            &[channel],
+            &[],
+            &[invoice],
+            &[failures],
+        );
+        assert_eq!(bundle.generated_on, 1_699_920_000);
+        assert_eq!(bundle.channels[0].size, "100k-500k");
+        assert_eq!(bundle.channels[0].local_balance_percent, 50);
+        assert!(bundle.channels[0].awaiting_confirmations);
+        assert_eq!(bundle.payments.outbound_failed, 1);
+        assert_eq!(bundle.payments.amounts.get("10k-100k"), Some(&1));
+        assert_eq!(
+            bundle.payments.failure_codes.get("RetriesExhausted"),
+            Some(&1)
+        );
+
+        let json = bundle.to_json().unwrap();
+        for secret in [PEER, "secret-channel-id", "secret-hash", "rent", "123456"] {
+            assert!(!json.contains(secret));
+        }
+    }
+
+    #[test]
+    fn test_failure_code() {
+        let test_name = "test_failure_code";
+        log!("{}", test_name);
+
+        assert_eq!(
+            failure_code(
+                "Failed on path: ChannelFailure { short_channel_id: 123, is_permanent: false }"
+            ),
+            "Failed on path: ChannelFailure"
+        );
+        assert_eq!(failure_code("Failed on path"), "Failed on path");
+        assert_eq!(
+            failure_code("CounterpartyForceClosed { peer_msg: \"bye\" }"),
+            "CounterpartyForceClosed"
+        );
+    }
+}
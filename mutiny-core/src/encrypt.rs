@@ -4,9 +4,10 @@ use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use aes::Aes256;
 use aes_gcm::Aes256Gcm;
 use aes_gcm::{
-    aead::{generic_array::GenericArray, Aead},
+    aead::{generic_array::GenericArray, Aead, Payload},
     KeyInit,
 };
+use anyhow::anyhow;
 use argon2::Argon2;
 use base64;
 use bitcoin::secp256k1;
@@ -26,7 +27,7 @@ pub struct Cipher {
 
 pub fn encryption_key_from_pass(password: &str) -> Result<Cipher, MutinyError> {
     let mut salt = [0u8; 16];
-    getrandom(&mut salt).unwrap();
+    fill_random(&mut salt)?;
 
     let key = get_encryption_key(password, &salt)?;
 
@@ -38,10 +39,14 @@ pub fn encryption_key_from_pass(password: &str) -> Result<Cipher, MutinyError> {
     })
 }
 
+fn fill_random(bytes: &mut [u8]) -> Result<(), MutinyError> {
+    getrandom(bytes).map_err(|e| MutinyError::Other(anyhow!("Failed to generate randomness: {e}")))
+}
+
 pub fn encrypt(content: &str, c: Cipher) -> Result<String, MutinyError> {
     // convert key and nonce to proper format for aes_gcm
     let mut nonce = [0u8; 12];
-    getrandom(&mut nonce).unwrap();
+    fill_random(&mut nonce)?;
 
     // convert nonce to proper format for aes_gcm
     let nonce = GenericArray::from_slice(&nonce);
@@ -124,11 +129,58 @@ pub fn decrypt_with_key(
     Ok(decrypted)
 }
 
+/// Encrypts the bytes with AES-256-GCM, binding the given associated data
+/// into the authentication tag. The output is `nonce || ciphertext || tag`.
+pub fn encrypt_with_key_and_aad(
+    encryption_key: &SecretKey,
+    bytes: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, MutinyError> {
+    let mut nonce = [0u8; 12];
+    fill_random(&mut nonce)?;
+    let nonce = GenericArray::from_slice(&nonce);
+
+    let key = GenericArray::clone_from_slice(&encryption_key.secret_bytes());
+    let cipher = Aes256Gcm::new(&key);
+    let encrypted = cipher.encrypt(nonce, Payload { msg: bytes, aad })?;
+
+    let mut result: Vec<u8> = Vec::with_capacity(12 + encrypted.len());
+    result.extend(nonce);
+    result.extend(encrypted);
+
+    Ok(result)
+}
+
+/// Decrypts bytes created by [`encrypt_with_key_and_aad`], failing if either
+/// the ciphertext or the associated data have been tampered with.
+pub fn decrypt_with_key_and_aad(
+    encryption_key: &SecretKey,
+    bytes: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, MutinyError> {
+    if bytes.len() < 12 + 16 {
+        return Err(MutinyError::IncorrectPassword);
+    }
+    let (nonce, encrypted) = bytes.split_at(12);
+
+    let key = GenericArray::clone_from_slice(&encryption_key.secret_bytes());
+    let cipher = Aes256Gcm::new(&key);
+    let decrypted = cipher.decrypt(
+        GenericArray::from_slice(nonce),
+        Payload {
+            msg: encrypted,
+            aad,
+        },
+    )?;
+
+    Ok(decrypted)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::encrypt::{
-        decrypt_with_key, decrypt_with_password, encrypt, encrypt_with_key,
-        encryption_key_from_pass,
+        decrypt_with_key, decrypt_with_key_and_aad, decrypt_with_password, encrypt,
+        encrypt_with_key, encrypt_with_key_and_aad, encryption_key_from_pass,
     };
     use bitcoin::secp256k1::SecretKey;
 
@@ -156,4 +208,24 @@ mod tests {
         let decrypted = decrypt_with_key(&key, encrypted).unwrap();
         assert_eq!(content, decrypted);
     }
+
+    #[test]
+    fn test_encryption_with_key_and_aad() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let content = [6u8; 32].to_vec();
+        let aad = b"key:1";
+
+        let encrypted = encrypt_with_key_and_aad(&key, &content, aad).unwrap();
+
+        let decrypted = decrypt_with_key_and_aad(&key, &encrypted, aad).unwrap();
+        assert_eq!(content, decrypted);
+
+        // different associated data must fail
+        assert!(decrypt_with_key_and_aad(&key, &encrypted, b"key:2").is_err());
+
+        // tampered ciphertext must fail
+        let mut tampered = encrypted.clone();
+        tampered[20] ^= 1;
+        assert!(decrypt_with_key_and_aad(&key, &tampered, aad).is_err());
+    }
 }
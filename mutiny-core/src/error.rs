@@ -1,7 +1,7 @@
 use aes::cipher::block_padding::UnpadError;
 use bdk_wallet::error::BuildFeeBumpError;
 use bdk_wallet::signer::SignerError;
-use bdk_wallet::tx_builder::AddUtxoError;
+use bdk_wallet::tx_builder::{AddForeignUtxoError, AddUtxoError};
 use bitcoin::psbt::ExtractTxError;
 use hex_conservative::HexToArrayError;
 use lightning::ln::channelmanager::RetryableSendFailure;
@@ -178,6 +178,30 @@ pub enum MutinyError {
     JwtAuthFailure,
     #[error("Failed to parse VSS value from getObject response.")]
     FailedParsingVssValue,
+    /// A VSS value did not match the key or version it was stored under
+    #[error("VSS value failed integrity check.")]
+    VssIntegrityError,
+    /// A lightning address server returned an invalid response
+    #[error("Failed to get an invoice from the lightning address.")]
+    LnUrlFailure,
+    /// An embedding app tried to do something it has not been granted
+    #[error("The app does not have permission to do this.")]
+    PermissionDenied,
+    /// A chain snapshot was not signed by the expected key or did not connect to a checkpoint
+    #[error("The chain snapshot failed verification.")]
+    InvalidChainSnapshot,
+    /// A hook registered by the embedder vetoed the operation.
+    #[error("Vetoed by a hook: {0}")]
+    HookVetoed(String),
+    /// A remote config was not signed by the expected key or was for another network
+    #[error("The remote config failed verification.")]
+    InvalidRemoteConfig,
+    /// Coins in the vault were spent without a withdrawal request
+    #[error("Funds in the vault can only be spent by requesting a withdrawal.")]
+    VaultLocked,
+    /// The withdrawal allowlist is on and the destination isn't on it
+    #[error("Funds can only be sent to an address on the withdrawal allowlist.")]
+    DestinationNotAllowed,
     #[error(transparent)]
     Other(anyhow::Error),
 }
@@ -262,6 +286,14 @@ impl PartialEq for MutinyError {
             (Self::CashuMintError, Self::CashuMintError) => true,
             (Self::EmptyMintURLError, Self::EmptyMintURLError) => true,
             (Self::TokenAlreadySpent, Self::TokenAlreadySpent) => true,
+            (Self::VssIntegrityError, Self::VssIntegrityError) => true,
+            (Self::LnUrlFailure, Self::LnUrlFailure) => true,
+            (Self::PermissionDenied, Self::PermissionDenied) => true,
+            (Self::InvalidChainSnapshot, Self::InvalidChainSnapshot) => true,
+            (Self::HookVetoed(x), Self::HookVetoed(y)) => x == y,
+            (Self::InvalidRemoteConfig, Self::InvalidRemoteConfig) => true,
+            (Self::VaultLocked, Self::VaultLocked) => true,
+            (Self::DestinationNotAllowed, Self::DestinationNotAllowed) => true,
             (Self::Other(e), Self::Other(e2)) => e.to_string() == e2.to_string(),
             _ => false,
         }
@@ -354,6 +386,12 @@ impl From<AddUtxoError> for MutinyError {
     }
 }
 
+impl From<AddForeignUtxoError> for MutinyError {
+    fn from(_: AddForeignUtxoError) -> Self {
+        Self::WalletOperationFailed
+    }
+}
+
 impl From<bip39::Error> for MutinyError {
     fn from(_e: bip39::Error) -> Self {
         Self::InvalidMnemonic
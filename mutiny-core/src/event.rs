@@ -1,20 +1,41 @@
+use crate::anchorbump::{record_anchor_close, with_feerate, AnchorCloseEvents};
+use crate::hodl::{get_hodl_invoice, persist_hodl_invoice, HodlInvoiceStatus};
+use crate::hooks::{
+    ChannelAcceptance, ChannelHookState, ChannelOpenRequest, HookEvent, HookRegistry,
+};
+use crate::inboundchannels::{
+    get_inbound_channel_policy, persist_inbound_channel_request, ChannelRequestDecision,
+    InboundChannelRequest,
+};
 use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
 use crate::logging::MutinyLogger;
 use crate::lsp::{AnyLsp, Lsp};
 use crate::messagehandler::{BumpChannelClosureTransaction, CommonLnEvent, CommonLnEventCallback};
 use crate::node::BumpTxEventHandler;
 use crate::nodemanager::ChannelClosure;
+use crate::offers::{get_offer, update_refund_status, RefundStatus};
 use crate::onchain::OnChainWallet;
+use crate::paymentfailure::{
+    record_failed_attempt, record_payment_failure_reason, FailedPaymentAttempt,
+};
+use crate::probe::{ProbeOutcome, ProbeResults};
+use crate::split::mark_split_share_paid;
 use crate::storage::MutinyStorage;
+use crate::swap::mark_swap_deposit_completed;
 use crate::utils::{self, sleep};
+use crate::zeroreserve::is_zero_reserve_peer;
 use crate::{fees::MutinyFeeEstimator, storage::read_payment_info, PrivacyLevel};
 use crate::{keymanager::PhantomKeysManager, storage::persist_payment_info};
 use anyhow::anyhow;
 use bitcoin::absolute::LockTime;
+use bitcoin::hex::DisplayHex;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::Secp256k1;
 use core::fmt;
-use lightning::events::{BumpTransactionEvent, ClosureReason, Event, PaymentPurpose, ReplayEvent};
+use lightning::events::{
+    BumpTransactionEvent, ClosureReason, Event, HTLCDestination, PaymentPurpose, ReplayEvent,
+};
+use lightning::ln::types::ChannelId;
 use lightning::sign::SpendableOutputDescriptor;
 use lightning::{
     log_debug, log_error, log_info, log_warn, util::errors::APIError, util::logger::Logger,
@@ -37,6 +58,12 @@ pub struct PaymentInfo {
     pub fee_paid_msat: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bolt11: Option<Bolt11Invoice>,
+    /// The BOLT12 offer or refund, for payments without a bolt11
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offer: Option<String>,
+    /// Custom TLV records sent along with a keysend payment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_tlvs: Vec<CustomTlv>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payee_pubkey: Option<PublicKey>,
     #[serde(default)]
@@ -44,6 +71,16 @@ pub struct PaymentInfo {
     pub last_update: u64,
 }
 
+/// A custom record from a payment's onion, like the podcasting 2.0 boost
+/// records sent with keysend payments
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomTlv {
+    /// The TLV type, custom records use types of 65536 and above
+    pub tlv_type: u64,
+    /// Hex encoded value
+    pub value: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MillisatAmount(pub Option<u64>);
 
@@ -98,10 +135,13 @@ pub struct EventHandler<S: MutinyStorage> {
     keys_manager: Arc<PhantomKeysManager<S>>,
     persister: Arc<MutinyNodePersister<S>>,
     bump_tx_event_handler: Arc<BumpTxEventHandler<S>>,
+    anchor_close_events: AnchorCloseEvents,
+    probe_results: ProbeResults,
     lsp_client: Option<AnyLsp<S>>,
     logger: Arc<MutinyLogger>,
     do_not_bump_channel_closed_tx: bool,
     ln_event_callback: Option<CommonLnEventCallback>,
+    hooks: Option<Arc<HookRegistry>>,
 }
 
 impl<S: MutinyStorage> EventHandler<S> {
@@ -113,10 +153,13 @@ impl<S: MutinyStorage> EventHandler<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         persister: Arc<MutinyNodePersister<S>>,
         bump_tx_event_handler: Arc<BumpTxEventHandler<S>>,
+        anchor_close_events: AnchorCloseEvents,
+        probe_results: ProbeResults,
         lsp_client: Option<AnyLsp<S>>,
         logger: Arc<MutinyLogger>,
         do_not_bump_channel_closed_tx: bool,
         ln_event_callback: Option<CommonLnEventCallback>,
+        hooks: Option<Arc<HookRegistry>>,
     ) -> Self {
         Self {
             channel_manager,
@@ -126,9 +169,18 @@ impl<S: MutinyStorage> EventHandler<S> {
             lsp_client,
             persister,
             bump_tx_event_handler,
+            anchor_close_events,
+            probe_results,
             logger,
             do_not_bump_channel_closed_tx,
             ln_event_callback,
+            hooks,
+        }
+    }
+
+    fn notify_hooks(&self, event: HookEvent) {
+        if let Some(hooks) = self.hooks.as_ref() {
+            hooks.notify(event);
         }
     }
 
@@ -173,6 +225,13 @@ impl<S: MutinyStorage> EventHandler<S> {
                                 channel_value_satoshis,
                                 params.absolute_fee.expect("Absolute fee should be set"),
                             )
+                        } else if let Some(utxos) = &params.funding_utxos {
+                            self.wallet.create_signed_psbt_with_utxos(
+                                output_script,
+                                channel_value_satoshis,
+                                Some(params.sats_per_vbyte),
+                                utxos,
+                            )
                         } else {
                             self.wallet.create_signed_psbt_to_spk(
                                 output_script,
@@ -281,17 +340,44 @@ impl<S: MutinyStorage> EventHandler<S> {
                     return Ok(());
                 }
 
+                // hold invoices wait for the preimage to be given, or to be cancelled
+                match get_hodl_invoice(&self.persister.storage, &payment_hash.0) {
+                    Ok(Some(mut hodl))
+                        if matches!(
+                            hodl.status,
+                            HodlInvoiceStatus::Open | HodlInvoiceStatus::Held
+                        ) =>
+                    {
+                        log_info!(
+                            self.logger,
+                            "Holding payment for hold invoice {payment_hash}"
+                        );
+                        hodl.status = HodlInvoiceStatus::Held;
+                        hodl.held_msat = Some(amount_msat);
+                        if let Err(e) = persist_hodl_invoice(&self.persister.storage, &mut hodl) {
+                            log_error!(self.logger, "ERROR: could not persist hold invoice: {e}");
+                        }
+                        return Ok(());
+                    }
+                    Ok(Some(_)) => {
+                        self.channel_manager.fail_htlc_backwards(&payment_hash);
+                        return Ok(());
+                    }
+                    Ok(None) => (),
+                    Err(e) => log_error!(self.logger, "ERROR: could not read hold invoice: {e}"),
+                }
+
                 if let Some(payment_preimage) = match purpose {
                     PaymentPurpose::Bolt11InvoicePayment {
                         payment_preimage, ..
                     } => payment_preimage,
                     PaymentPurpose::SpontaneousPayment(preimage) => Some(preimage),
-                    PaymentPurpose::Bolt12OfferPayment { .. }
-                    | PaymentPurpose::Bolt12RefundPayment { .. } => {
-                        log_error!(self.logger, "Not support Bolt12");
-                        self.channel_manager.fail_htlc_backwards(&payment_hash);
-                        return Ok(());
+                    PaymentPurpose::Bolt12OfferPayment {
+                        payment_preimage, ..
                     }
+                    | PaymentPurpose::Bolt12RefundPayment {
+                        payment_preimage, ..
+                    } => payment_preimage,
                 } {
                     self.channel_manager.claim_funds(payment_preimage);
                 } else {
@@ -306,23 +392,47 @@ impl<S: MutinyStorage> EventHandler<S> {
                 amount_msat,
                 htlcs,
                 sender_intended_total_msat,
-                onion_fields: _,
+                onion_fields,
             } => {
                 log_debug!(self.logger, "EVENT: PaymentClaimed claimed payment from payment hash {} of {} millisatoshis ({sender_intended_total_msat:?} intended)  from {} htlcs", payment_hash, amount_msat, htlcs.len());
 
-                let (payment_preimage, payment_secret) = match purpose {
+                let (payment_preimage, payment_secret, offer) = match purpose {
                     PaymentPurpose::Bolt11InvoicePayment {
                         payment_preimage,
                         payment_secret,
                         ..
-                    } => (payment_preimage, Some(payment_secret)),
-                    PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None),
-                    PaymentPurpose::Bolt12OfferPayment { .. }
-                    | PaymentPurpose::Bolt12RefundPayment { .. } => {
-                        log_error!(self.logger, "Not support Bolt12");
-                        return Ok(());
+                    } => (payment_preimage, Some(payment_secret), None),
+                    PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None, None),
+                    PaymentPurpose::Bolt12OfferPayment {
+                        payment_preimage,
+                        payment_secret,
+                        payment_context,
+                    } => {
+                        let offer = get_offer(&self.persister.storage, &payment_context.offer_id)
+                            .ok()
+                            .flatten()
+                            .map(|o| o.offer);
+                        (payment_preimage, Some(payment_secret), offer)
                     }
+                    // the refund was recorded when we claimed it
+                    PaymentPurpose::Bolt12RefundPayment {
+                        payment_preimage,
+                        payment_secret,
+                        ..
+                    } => (payment_preimage, Some(payment_secret), None),
                 };
+                let custom_tlvs: Vec<CustomTlv> = onion_fields
+                    .map(|fields| {
+                        fields
+                            .custom_tlvs()
+                            .iter()
+                            .map(|(tlv_type, value)| CustomTlv {
+                                tlv_type: *tlv_type,
+                                value: value.to_lower_hex_string(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
                 match read_payment_info(
                     &self.persister.storage,
                     &payment_hash.0,
@@ -333,9 +443,15 @@ impl<S: MutinyStorage> EventHandler<S> {
                         let payment_preimage = payment_preimage.map(|p| p.0);
                         let payment_secret = payment_secret.map(|p| p.0);
                         saved_payment_info.status = HTLCStatus::Succeeded;
-                        saved_payment_info.preimage = payment_preimage;
+                        // hold invoices get their preimage when they're settled
+                        saved_payment_info.preimage =
+                            payment_preimage.or(saved_payment_info.preimage);
                         saved_payment_info.secret = payment_secret;
                         saved_payment_info.amt_msat = MillisatAmount(Some(amount_msat));
+                        if offer.is_some() {
+                            saved_payment_info.offer = offer;
+                        }
+                        saved_payment_info.custom_tlvs = custom_tlvs;
                         saved_payment_info.last_update = crate::utils::now().as_secs();
                         match persist_payment_info(
                             &self.persister.storage,
@@ -363,6 +479,8 @@ impl<S: MutinyStorage> EventHandler<S> {
                             fee_paid_msat: None,
                             payee_pubkey: receiver_node_id,
                             bolt11: None,
+                            offer,
+                            custom_tlvs,
                             last_update,
                             privacy_level: PrivacyLevel::NotAvailable,
                         };
@@ -381,6 +499,11 @@ impl<S: MutinyStorage> EventHandler<S> {
                     }
                 }
 
+                self.notify_hooks(HookEvent::AfterReceive {
+                    payment_hash: format!("{payment_hash:x}"),
+                    amount_sats: amount_msat / 1_000,
+                });
+
                 if let Some(cb) = self.ln_event_callback.as_ref() {
                     let event = CommonLnEvent::PaymentClaimed {
                         receiver_node_id: receiver_node_id.map(|node_id| format!("{node_id}")),
@@ -389,8 +512,31 @@ impl<S: MutinyStorage> EventHandler<S> {
                     };
                     cb.trigger(event);
                 }
+
+                match mark_split_share_paid(&self.persister.storage, &payment_hash.0) {
+                    Ok(Some(split)) => {
+                        log_info!(self.logger, "Split request {} completed", split.id);
+                        if let Some(cb) = self.ln_event_callback.as_ref() {
+                            cb.trigger(CommonLnEvent::SplitRequestCompleted {
+                                id: split.id,
+                                total_sats: split.total_sats,
+                            });
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(e) => log_error!(self.logger, "ERROR: could not update split request: {e}"),
+                }
+
+                match mark_swap_deposit_completed(&self.persister.storage, &payment_hash.0) {
+                    Ok(Some(deposit)) => {
+                        log_info!(self.logger, "Swap deposit {} completed", deposit.id)
+                    }
+                    Ok(None) => (),
+                    Err(e) => log_error!(self.logger, "ERROR: could not update swap deposit: {e}"),
+                }
             }
             Event::PaymentSent {
+                payment_id,
                 payment_preimage,
                 payment_hash,
                 fee_paid_msat,
@@ -423,11 +569,47 @@ impl<S: MutinyStorage> EventHandler<S> {
                         }
                     }
                     None => {
-                        // we succeeded in a payment that we didn't have saved? ...
-                        log_warn!(
-                            self.logger,
-                            "WARN: payment succeeded but we did not have it stored"
-                        );
+                        // LDK pays our refunds on its own, so they're first seen here
+                        let refund = payment_id.and_then(|id| {
+                            update_refund_status(&self.persister.storage, &id, RefundStatus::Paid)
+                                .ok()
+                                .flatten()
+                        });
+                        match refund {
+                            Some(refund) => {
+                                let payment_info = PaymentInfo {
+                                    preimage: Some(payment_preimage.0),
+                                    secret: None,
+                                    status: HTLCStatus::Succeeded,
+                                    amt_msat: MillisatAmount(Some(refund.amount_sats * 1_000)),
+                                    fee_paid_msat,
+                                    bolt11: None,
+                                    offer: Some(refund.refund),
+                                    custom_tlvs: vec![],
+                                    payee_pubkey: None,
+                                    privacy_level: PrivacyLevel::NotAvailable,
+                                    last_update: crate::utils::now().as_secs(),
+                                };
+                                if let Err(e) = persist_payment_info(
+                                    &self.persister.storage,
+                                    &payment_hash.0,
+                                    &payment_info,
+                                    false,
+                                ) {
+                                    log_error!(
+                                        self.logger,
+                                        "ERROR: could not persist payment info: {e}"
+                                    );
+                                }
+                            }
+                            None => {
+                                // we succeeded in a payment that we didn't have saved? ...
+                                log_warn!(
+                                    self.logger,
+                                    "WARN: payment succeeded but we did not have it stored"
+                                );
+                            }
+                        }
                     }
                 }
                 if let Some(cb) = self.ln_event_callback.as_ref() {
@@ -440,6 +622,8 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::OpenChannelRequest {
                 temporary_channel_id,
                 counterparty_node_id,
+                funding_satoshis,
+                push_msat,
                 channel_type,
                 ..
             } => {
@@ -472,10 +656,58 @@ impl<S: MutinyStorage> EventHandler<S> {
                     "EVENT: OpenChannelRequest zero-conf channel: {is_zero_conf_channel}"
                 );
 
-                if lsp_pubkey.as_ref() != Some(&counterparty_node_id) {
-                    log_error!(
+                let from_lsp = lsp_pubkey.as_ref() == Some(&counterparty_node_id);
+                // the embedder's channel acceptor takes over from the policy below
+                let acceptance = match self.hooks.as_ref() {
+                    Some(hooks) => {
+                        hooks
+                            .accept_channel(ChannelOpenRequest {
+                                temporary_channel_id: temporary_channel_id.to_string(),
+                                counterparty_node_id,
+                                funding_sats: funding_satoshis,
+                                push_msat,
+                                zero_conf: is_zero_conf_channel,
+                                from_lsp,
+                            })
+                            .await
+                    }
+                    None => None,
+                };
+
+                if let Some(acceptance) = acceptance {
+                    self.handle_channel_acceptance(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        funding_satoshis,
+                        internal_channel_id,
+                        is_zero_conf_channel,
+                        from_lsp,
+                        acceptance,
+                    );
+                } else if !from_lsp
+                    && is_zero_reserve_peer(&self.persister.storage, &counterparty_node_id)
+                {
+                    // a trusted peer, accepted like our LSP without the policy
+                    log_debug!(
+                        self.logger,
+                        "EVENT: OpenChannelRequest from a zero reserve peer, accepting"
+                    );
+                    let result = self.channel_manager.accept_inbound_channel(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        internal_channel_id,
+                    );
+                    log_result(result);
+                } else if !from_lsp {
+                    log_debug!(
                         self.logger,
-                        "EVENT: OpenChannelRequest error: The counterparty node id doesn't match the LSP pubkey"
+                        "EVENT: OpenChannelRequest not from our LSP, checking inbound channel policy"
+                    );
+                    self.handle_peer_channel_request(
+                        &temporary_channel_id,
+                        &counterparty_node_id,
+                        funding_satoshis,
+                        internal_channel_id,
                     );
                 } else if is_zero_conf_channel {
                     // if the event request channel type is 0-conf, accept 0 conf channel
@@ -504,20 +736,73 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::PaymentPathSuccessful { .. } => {
                 log_debug!(self.logger, "EVENT: PaymentPathSuccessful, ignored");
             }
-            Event::PaymentPathFailed { .. } => {
-                log_debug!(self.logger, "EVENT: PaymentPathFailed, ignored");
+            Event::PaymentPathFailed {
+                payment_hash,
+                payment_failed_permanently,
+                failure,
+                path,
+                short_channel_id,
+                ..
+            } => {
+                log_debug!(
+                    self.logger,
+                    "EVENT: PaymentPathFailed: {payment_hash} on channel {short_channel_id:?}: {failure:?}"
+                );
+
+                let attempt = FailedPaymentAttempt::new(
+                    &path,
+                    &failure,
+                    short_channel_id,
+                    payment_failed_permanently,
+                );
+                if let Err(e) =
+                    record_failed_attempt(&self.persister.storage, &payment_hash.0, attempt)
+                {
+                    log_error!(self.logger, "ERROR: could not persist failed attempt: {e}");
+                }
             }
-            Event::ProbeSuccessful { .. } => {
-                log_debug!(self.logger, "EVENT: ProbeSuccessful, ignored");
+            Event::ProbeSuccessful {
+                payment_id, path, ..
+            } => {
+                log_debug!(self.logger, "EVENT: ProbeSuccessful: {payment_id}");
+                self.probe_results
+                    .lock()
+                    .await
+                    .insert(payment_id, ProbeOutcome::new(&path, true));
             }
-            Event::ProbeFailed { .. } => {
-                log_debug!(self.logger, "EVENT: ProbeFailed, ignored");
+            Event::ProbeFailed {
+                payment_id,
+                path,
+                short_channel_id,
+                ..
+            } => {
+                log_debug!(
+                    self.logger,
+                    "EVENT: ProbeFailed: {payment_id} at channel {short_channel_id:?}"
+                );
+                self.probe_results
+                    .lock()
+                    .await
+                    .insert(payment_id, ProbeOutcome::new(&path, false));
             }
             Event::PaymentFailed {
+                payment_id,
                 payment_hash,
                 reason,
                 ..
             } => {
+                match update_refund_status(
+                    &self.persister.storage,
+                    &payment_id,
+                    RefundStatus::Failed,
+                ) {
+                    Ok(Some(refund)) => {
+                        log_warn!(self.logger, "EVENT: refund {} failed", refund.id)
+                    }
+                    Ok(None) => (),
+                    Err(e) => log_error!(self.logger, "ERROR: could not update refund: {e}"),
+                }
+
                 if let Some(payment_hash) = payment_hash {
                     log_error!(
                         self.logger,
@@ -525,6 +810,16 @@ impl<S: MutinyStorage> EventHandler<S> {
                         payment_hash
                     );
 
+                    if let Some(reason) = reason.as_ref() {
+                        if let Err(e) = record_payment_failure_reason(
+                            &self.persister.storage,
+                            &payment_hash.0,
+                            format!("{reason:?}"),
+                        ) {
+                            log_error!(self.logger, "ERROR: could not persist failure reason: {e}");
+                        }
+                    }
+
                     match read_payment_info(
                         &self.persister.storage,
                         &payment_hash.0,
@@ -567,6 +862,28 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::PaymentForwarded { .. } => {
                 log_info!(self.logger, "EVENT: PaymentForwarded somehow...");
             }
+            Event::HTLCHandlingFailed {
+                failed_next_destination: HTLCDestination::FailedPayment { payment_hash },
+                ..
+            } => {
+                log_debug!(
+                    self.logger,
+                    "EVENT: HTLCHandlingFailed for payment {payment_hash}"
+                );
+
+                // a held payment that timed out can be paid again
+                if let Ok(Some(mut hodl)) =
+                    get_hodl_invoice(&self.persister.storage, &payment_hash.0)
+                {
+                    if hodl.status == HodlInvoiceStatus::Held {
+                        hodl.status = HodlInvoiceStatus::Open;
+                        hodl.held_msat = None;
+                        if let Err(e) = persist_hodl_invoice(&self.persister.storage, &mut hodl) {
+                            log_error!(self.logger, "ERROR: could not persist hold invoice: {e}");
+                        }
+                    }
+                }
+            }
             Event::HTLCHandlingFailed { .. } => {
                 log_debug!(self.logger, "EVENT: HTLCHandlingFailed, ignored");
             }
@@ -581,7 +898,15 @@ impl<S: MutinyStorage> EventHandler<S> {
                 sleep(min).await;
                 forwarding_channel_manager.process_pending_htlc_forwards();
             }
-            Event::SpendableOutputs { outputs, .. } => {
+            Event::SpendableOutputs {
+                outputs,
+                channel_id,
+            } => {
+                if let Some(channel_id) = channel_id.as_ref() {
+                    if let Err(e) = self.persister.record_channel_sweep(channel_id, &outputs) {
+                        log_error!(self.logger, "Failed to record channel sweep: {e}");
+                    }
+                }
                 if let Err(e) = self.handle_spendable_outputs(&outputs).await {
                     log_error!(self.logger, "Failed to handle spendable outputs: {e}");
                     // if we have an error we should persist the outputs so we can try again later
@@ -647,6 +972,14 @@ impl<S: MutinyStorage> EventHandler<S> {
                     maybe_force_closed,
                 };
 
+                self.notify_hooks(HookEvent::ChannelEvent {
+                    channel_id: channel_id.to_string(),
+                    counterparty_node_id: node_id,
+                    state: ChannelHookState::Closed {
+                        reason: reason.to_string(),
+                    },
+                });
+
                 let closure = ChannelClosure::new(
                     user_channel_id,
                     channel_id,
@@ -683,6 +1016,12 @@ impl<S: MutinyStorage> EventHandler<S> {
                     user_channel_id,
                     counterparty_node_id,
                     channel_type);
+
+                self.notify_hooks(HookEvent::ChannelEvent {
+                    channel_id: channel_id.to_string(),
+                    counterparty_node_id: Some(counterparty_node_id),
+                    state: ChannelHookState::Ready,
+                });
             }
             Event::ChannelPending {
                 channel_id,
@@ -697,6 +1036,12 @@ impl<S: MutinyStorage> EventHandler<S> {
                     user_channel_id,
                     counterparty_node_id);
 
+                self.notify_hooks(HookEvent::ChannelEvent {
+                    channel_id: channel_id.to_string(),
+                    counterparty_node_id: Some(counterparty_node_id),
+                    state: ChannelHookState::Pending,
+                });
+
                 if let Err(e) = self.persister.delete_channel_open_params(user_channel_id) {
                     log_warn!(
                         self.logger,
@@ -709,6 +1054,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                 BumpTransactionEvent::ChannelClose {
                     channel_id,
                     commitment_tx,
+                    anchor_descriptor,
                     ..
                 } => {
                     let txid = format!("{:x}", commitment_tx.compute_txid());
@@ -739,11 +1085,40 @@ impl<S: MutinyStorage> EventHandler<S> {
                         log_error!(self.logger, "Failed to rebroadcast commitment tx: {e}");
                     }
 
-                    if self.do_not_bump_channel_closed_tx {
-                        log_debug!(self.logger, "Skip channel close transaction");
-                    } else {
-                        log_debug!(self.logger, "Bump channel close transaction");
-                        self.bump_tx_event_handler.handle_event(&event);
+                    // keep it around so the user can bump it at their own feerate
+                    let user_feerate = match record_anchor_close(
+                        &self.persister.storage,
+                        channel_id,
+                        commitment_tx.compute_txid(),
+                        anchor_descriptor.outpoint,
+                    ) {
+                        Ok(bump) => bump.user_feerate_sat_per_1000_weight,
+                        Err(e) => {
+                            log_error!(self.logger, "Failed to record anchor close: {e}");
+                            None
+                        }
+                    };
+                    self.anchor_close_events
+                        .lock()
+                        .await
+                        .insert(*channel_id, event.clone());
+
+                    match user_feerate {
+                        Some(feerate) => {
+                            log_debug!(
+                                self.logger,
+                                "Bump channel close transaction at user feerate {feerate}"
+                            );
+                            self.bump_tx_event_handler
+                                .handle_event(&with_feerate(&event, feerate));
+                        }
+                        None if self.do_not_bump_channel_closed_tx => {
+                            log_debug!(self.logger, "Skip channel close transaction");
+                        }
+                        None => {
+                            log_debug!(self.logger, "Bump channel close transaction");
+                            self.bump_tx_event_handler.handle_event(&event);
+                        }
                     }
                     if let Some(cb) = self.ln_event_callback.as_ref() {
                         let closure_bumping_event = BumpChannelClosureTransaction {
@@ -776,7 +1151,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                 }
             },
             Event::ConnectionNeeded { node_id, addresses } => {
-                // we don't support bolt 12 yet, and we won't have the connection info anyways
+                // offer messages go through our LSP, and we won't have the connection info anyways
                 log_debug!(
                     self.logger,
                     "EVENT: ConnectionNeeded: {node_id} @ {addresses:?}"
@@ -811,6 +1186,137 @@ impl<S: MutinyStorage> EventHandler<S> {
         Ok(())
     }
 
+    /// Accepts or rejects a channel request as the channel acceptor decided. Requests
+    /// from anyone but our LSP are recorded like the ones the policy decides on.
+    #[allow(clippy::too_many_arguments)]
+    fn handle_channel_acceptance(
+        &self,
+        temporary_channel_id: &ChannelId,
+        counterparty_node_id: &PublicKey,
+        funding_satoshis: u64,
+        internal_channel_id: u128,
+        zero_conf: bool,
+        from_lsp: bool,
+        acceptance: ChannelAcceptance,
+    ) {
+        let decision = match acceptance {
+            ChannelAcceptance::Accept => {
+                log_info!(
+                    self.logger,
+                    "EVENT: OpenChannelRequest accepted by channel acceptor"
+                );
+                // a zero-conf channel can only be accepted as one
+                let result = if zero_conf {
+                    self.channel_manager
+                        .accept_inbound_channel_from_trusted_peer_0conf(
+                            temporary_channel_id,
+                            counterparty_node_id,
+                            internal_channel_id,
+                        )
+                } else {
+                    self.channel_manager.accept_inbound_channel(
+                        temporary_channel_id,
+                        counterparty_node_id,
+                        internal_channel_id,
+                    )
+                };
+                if let Err(e) = result {
+                    log_error!(self.logger, "EVENT: OpenChannelRequest error: {e:?}");
+                }
+                ChannelRequestDecision::Accepted
+            }
+            ChannelAcceptance::Reject { reason } => {
+                log_debug!(
+                    self.logger,
+                    "EVENT: OpenChannelRequest rejected by channel acceptor: {reason}"
+                );
+                if let Err(e) = self.channel_manager.force_close_without_broadcasting_txn(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                    reason.clone(),
+                ) {
+                    log_error!(
+                        self.logger,
+                        "EVENT: could not reject channel request: {e:?}"
+                    );
+                }
+                ChannelRequestDecision::Rejected { reason }
+            }
+        };
+
+        if from_lsp {
+            return;
+        }
+        let request = InboundChannelRequest::new(
+            temporary_channel_id.to_string(),
+            *counterparty_node_id,
+            funding_satoshis,
+            decision,
+        );
+        if let Err(e) = persist_inbound_channel_request(&self.persister.storage, &request) {
+            log_error!(self.logger, "ERROR: could not persist channel request: {e}");
+        }
+    }
+
+    /// Channels from anyone but our LSP are accepted or rejected by the inbound
+    /// channel policy, when the user turned it on
+    fn handle_peer_channel_request(
+        &self,
+        temporary_channel_id: &ChannelId,
+        counterparty_node_id: &PublicKey,
+        funding_satoshis: u64,
+        internal_channel_id: u128,
+    ) {
+        let policy = get_inbound_channel_policy(&self.persister.storage).unwrap_or_default();
+        if !policy.enabled {
+            log_error!(
+                self.logger,
+                "EVENT: OpenChannelRequest error: The counterparty node id doesn't match the LSP pubkey"
+            );
+            return;
+        }
+        let decision = policy.evaluate(counterparty_node_id, funding_satoshis);
+
+        match &decision {
+            ChannelRequestDecision::Accepted => {
+                log_info!(self.logger, "EVENT: OpenChannelRequest accepted by policy");
+                if let Err(e) = self.channel_manager.accept_inbound_channel(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                    internal_channel_id,
+                ) {
+                    log_error!(self.logger, "EVENT: OpenChannelRequest error: {e:?}");
+                }
+            }
+            ChannelRequestDecision::Rejected { reason } => {
+                log_debug!(
+                    self.logger,
+                    "EVENT: OpenChannelRequest rejected by policy: {reason}"
+                );
+                if let Err(e) = self.channel_manager.force_close_without_broadcasting_txn(
+                    temporary_channel_id,
+                    counterparty_node_id,
+                    reason.clone(),
+                ) {
+                    log_error!(
+                        self.logger,
+                        "EVENT: could not reject channel request: {e:?}"
+                    );
+                }
+            }
+        }
+
+        let request = InboundChannelRequest::new(
+            temporary_channel_id.to_string(),
+            *counterparty_node_id,
+            funding_satoshis,
+            decision,
+        );
+        if let Err(e) = persist_inbound_channel_request(&self.persister.storage, &request) {
+            log_error!(self.logger, "ERROR: could not persist channel request: {e}");
+        }
+    }
+
     // Separate function to handle spendable outputs
     // This is so we can return a result and handle errors
     // without having to use a lot of nested if statements
@@ -904,6 +1410,8 @@ mod test {
             amt_msat: MillisatAmount(Some(420)),
             fee_paid_msat: None,
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
@@ -1,6 +1,7 @@
 use crate::logging::MutinyLogger;
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, utils};
+use async_trait::async_trait;
 use bitcoin::{FeeRate, Weight};
 use esplora_client::AsyncClient;
 use futures::lock::Mutex;
@@ -9,7 +10,7 @@ use lightning::chain::chaininterface::{
 };
 use lightning::log_trace;
 use lightning::util::logger::Logger;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -18,13 +19,55 @@ pub(crate) const TX_OVERHEAD: usize = 10;
 pub(crate) const TAPROOT_INPUT_NON_WITNESS_SIZE: usize = 41;
 pub(crate) const TAPROOT_INPUT_WITNESS_SIZE: usize = 67;
 pub(crate) const P2WSH_OUTPUT_SIZE: usize = 43;
-#[allow(dead_code)]
 pub(crate) const TAPROOT_OUTPUT_SIZE: usize = 43;
 
+pub(crate) const FEE_HISTORY_KEY: &str = "fee_history";
+/// Fees are checked every 10 minutes but we only keep a sample every half hour
+const FEE_HISTORY_INTERVAL_SECS: u64 = 30 * 60;
+/// How long fee samples are kept
+const FEE_HISTORY_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+/// Need at least a day of history before suggesting to wait
+const MIN_FEE_HISTORY_SAMPLES: usize = 48;
+/// Suggest waiting when fees are higher than this much of the past week
+const WAIT_PERCENTILE: u8 = 75;
+
+/// Fee rates at a point in time, in sat/vbyte
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FeeRateSample {
+    pub timestamp: u64,
+    /// Next block
+    pub high: f64,
+    /// Within 6 blocks
+    pub normal: f64,
+    /// Within a week
+    pub low: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SendTiming {
+    /// Fees are normal or low right now
+    Now,
+    /// Fees are high compared to the past week
+    Wait,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SendTimingSuggestion {
+    pub timing: SendTiming,
+    /// Fee for the send at the current normal fee rate
+    pub fee_now_sats: u64,
+    /// Fee for the send at the median rate of the past week
+    pub typical_fee_sats: Option<u64>,
+    /// How much of the past week fees were lower than now, from 0 to 100
+    pub percentile: Option<u8>,
+}
+
 #[derive(Clone)]
 pub struct MutinyFeeEstimator<S: MutinyStorage> {
     storage: S,
     esplora: Arc<AsyncClient>,
+    /// Fee providers, tried in order
+    providers: Vec<Arc<dyn FeeProvider>>,
     logger: Arc<MutinyLogger>,
     last_fee_update_time_secs: Arc<Mutex<Option<u64>>>,
 }
@@ -35,14 +78,46 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
         esplora: Arc<AsyncClient>,
         logger: Arc<MutinyLogger>,
     ) -> MutinyFeeEstimator<S> {
+        let providers = Self::providers_for(&FeeSource::default(), &esplora);
         MutinyFeeEstimator {
             storage,
             esplora,
+            providers,
             logger,
             last_fee_update_time_secs: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Use a different source for fee estimates
+    pub fn with_fee_source(mut self, source: FeeSource) -> Self {
+        self.providers = Self::providers_for(&source, &self.esplora);
+        self
+    }
+
+    fn providers_for(source: &FeeSource, esplora: &Arc<AsyncClient>) -> Vec<Arc<dyn FeeProvider>> {
+        let esplora_provider: Arc<dyn FeeProvider> =
+            Arc::new(EsploraFeeProvider::new(esplora.clone()));
+        match source {
+            FeeSource::Esplora => vec![
+                Arc::new(MempoolSpaceFeeProvider::new(
+                    esplora.url().to_string(),
+                    esplora.client().clone(),
+                )),
+                esplora_provider,
+            ],
+            FeeSource::MempoolSpace(url) => vec![
+                Arc::new(MempoolSpaceFeeProvider::new(
+                    url.clone(),
+                    esplora.client().clone(),
+                )),
+                esplora_provider,
+            ],
+            FeeSource::Static(sats_per_vbyte) => {
+                vec![Arc::new(StaticFeeProvider::new(*sats_per_vbyte))]
+            }
+        }
+    }
+
     /// Calculate the estimated fee in satoshis for a transaction.
     /// It is assumed that the inputs will be Taproot key spends.
     pub fn calculate_expected_fee(
@@ -88,14 +163,90 @@ struct MempoolFees {
     minimum_fee: f64,
 }
 
-impl<S: MutinyStorage> MutinyFeeEstimator<S> {
-    async fn get_mempool_recommended_fees(&self) -> anyhow::Result<HashMap<String, f64>> {
-        let client = self.esplora.client();
-        let request = client
-            .get(format!("{}/v1/fees/recommended", self.esplora.url()))
+/// Where fee estimates come from, selected in the wallet config
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum FeeSource {
+    /// mempool.space's recommended fees from our esplora server, falling
+    /// back to esplora's own estimates
+    #[default]
+    Esplora,
+    /// A mempool.space compatible API at the given url, falling back to
+    /// esplora's estimates
+    MempoolSpace(String),
+    /// Always use this fee rate, in sat/vbyte
+    Static(f64),
+}
+
+/// Something that can give us fee rate estimates
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait FeeProvider: Send + Sync {
+    /// Name used when logging
+    fn name(&self) -> &'static str;
+
+    /// Fee rates in sat/vbyte, keyed by the number of blocks to confirm within
+    async fn get_fee_estimates(&self) -> anyhow::Result<HashMap<String, f64>>;
+}
+
+/// Esplora's `fee-estimates` endpoint
+pub struct EsploraFeeProvider {
+    esplora: Arc<AsyncClient>,
+}
+
+impl EsploraFeeProvider {
+    pub fn new(esplora: Arc<AsyncClient>) -> Self {
+        Self { esplora }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for EsploraFeeProvider {
+    fn name(&self) -> &'static str {
+        "esplora"
+    }
+
+    async fn get_fee_estimates(&self) -> anyhow::Result<HashMap<String, f64>> {
+        Ok(self
+            .esplora
+            .get_fee_estimates()
+            .await?
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect())
+    }
+}
+
+/// mempool.space's recommended fees, these track the mempool more closely
+/// than esplora's estimates which are often stale
+pub struct MempoolSpaceFeeProvider {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl MempoolSpaceFeeProvider {
+    pub fn new(url: String, client: reqwest::Client) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for MempoolSpaceFeeProvider {
+    fn name(&self) -> &'static str {
+        "mempool"
+    }
+
+    async fn get_fee_estimates(&self) -> anyhow::Result<HashMap<String, f64>> {
+        let request = self
+            .client
+            .get(format!("{}/v1/fees/recommended", self.url))
             .build()?;
 
-        let fees_response = utils::fetch_with_timeout(client, request)
+        let fees_response = utils::fetch_with_timeout(&self.client, request)
             .await?
             .error_for_status()?;
         let fees = fees_response.json::<MempoolFees>().await?;
@@ -110,7 +261,39 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
 
         Ok(fee_estimates)
     }
+}
+
+/// A fee rate set by the user, used for every confirmation target
+pub struct StaticFeeProvider {
+    sats_per_vbyte: f64,
+}
+
+impl StaticFeeProvider {
+    pub fn new(sats_per_vbyte: f64) -> Self {
+        Self { sats_per_vbyte }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FeeProvider for StaticFeeProvider {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn get_fee_estimates(&self) -> anyhow::Result<HashMap<String, f64>> {
+        if !self.sats_per_vbyte.is_finite() || self.sats_per_vbyte < 1.0 {
+            anyhow::bail!("Invalid static fee rate: {}", self.sats_per_vbyte);
+        }
+
+        Ok(["1", "3", "6", "12", "1008"]
+            .into_iter()
+            .map(|blocks| (blocks.to_string(), self.sats_per_vbyte))
+            .collect())
+    }
+}
 
+impl<S: MutinyStorage> MutinyFeeEstimator<S> {
     pub async fn update_fee_estimates_if_necessary(&self) -> Result<(), MutinyError> {
         let last_sync = self.get_last_sync_time().await;
         if last_sync.is_none() || utils::now().as_secs() > last_sync.unwrap() + 60 * 10 {
@@ -120,33 +303,27 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
     }
 
     async fn update_fee_estimates(&self) -> Result<(), MutinyError> {
-        // first try mempool.space's API
-        let mempool_fees = self.get_mempool_recommended_fees().await;
-
-        // if that fails, fall back to esplora's API
-        let fee_estimates = match mempool_fees {
-            Ok(mempool_fees) => {
-                log_trace!(self.logger, "Retrieved fees from mempool");
-                mempool_fees
-            }
-            Err(e) => {
-                log_trace!(
-                    self.logger,
-                    "Failed to retrieve fees from mempool, falling back to esplora: {e}"
-                );
-                self.esplora
-                    .get_fee_estimates()
-                    .await
-                    .map_err(|e| {
-                        log_trace!(self.logger, "Failed to get esplora fee: {e}");
-                        e
-                    })?
-                    .into_iter()
-                    .map(|(k, v)| (k.to_string(), v))
-                    .collect()
+        // try each provider in order until one works
+        let mut fee_estimates = None;
+        for provider in self.providers.iter() {
+            match provider.get_fee_estimates().await {
+                Ok(estimates) => {
+                    log_trace!(self.logger, "Retrieved fees from {}", provider.name());
+                    fee_estimates = Some(estimates);
+                    break;
+                }
+                Err(e) => {
+                    log_trace!(
+                        self.logger,
+                        "Failed to retrieve fees from {}: {e}",
+                        provider.name()
+                    );
+                }
             }
-        };
+        }
+        let fee_estimates = fee_estimates.ok_or(MutinyError::ChainAccessFailed)?;
 
+        self.record_fee_sample(&fee_estimates)?;
         self.storage.insert_fee_estimates(fee_estimates)?;
         let mut update_time_lock = self.last_fee_update_time_secs.lock().await;
         *update_time_lock = Some(utils::now().as_secs());
@@ -168,6 +345,98 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
         // OnChainSweep is the highest fee rate we have, so use that
         self.get_est_sat_per_1000_weight(ConfirmationTarget::UrgentOnChainSweep)
     }
+
+    /// Fee rates seen over the past week, oldest first
+    pub fn get_fee_history(&self) -> Result<Vec<FeeRateSample>, MutinyError> {
+        Ok(self.storage.get_data(FEE_HISTORY_KEY)?.unwrap_or_default())
+    }
+
+    fn record_fee_sample(&self, estimates: &HashMap<String, f64>) -> Result<(), MutinyError> {
+        let (Some(high), Some(normal), Some(low)) = (
+            estimates.get("1"),
+            estimates.get("6"),
+            estimates.get("1008"),
+        ) else {
+            return Ok(());
+        };
+
+        let now = utils::now().as_secs();
+        let mut history = self.get_fee_history()?;
+        if history
+            .last()
+            .is_some_and(|s| now < s.timestamp + FEE_HISTORY_INTERVAL_SECS)
+        {
+            return Ok(());
+        }
+        history.retain(|s| s.timestamp + FEE_HISTORY_MAX_AGE_SECS > now);
+        history.push(FeeRateSample {
+            timestamp: now,
+            high: *high,
+            normal: *normal,
+            low: *low,
+        });
+
+        self.storage
+            .write_data(FEE_HISTORY_KEY.to_string(), history, None)
+    }
+
+    /// Suggests whether to send now or wait for lower fees, based on how the
+    /// current normal fee rate compares to the past week.
+    pub fn suggest_send_timing(
+        &self,
+        num_utxos: usize,
+    ) -> Result<SendTimingSuggestion, MutinyError> {
+        let sats_per_kw = self.get_normal_fee_rate();
+        let fee_for = |sats_per_kw: u32| {
+            self.calculate_expected_fee(
+                num_utxos,
+                TAPROOT_OUTPUT_SIZE,
+                Some(TAPROOT_OUTPUT_SIZE),
+                Some(sats_per_kw),
+            )
+        };
+        let fee_now_sats = fee_for(sats_per_kw);
+
+        let history = self.get_fee_history()?;
+        let Some((percentile, median)) = fee_rate_percentile(&history, sats_per_kw as f64 / 250.0)
+        else {
+            return Ok(SendTimingSuggestion {
+                timing: SendTiming::Now,
+                fee_now_sats,
+                typical_fee_sats: None,
+                percentile: None,
+            });
+        };
+
+        let typical_fee_sats = fee_for(((median * 250.0) as u32).max(FEERATE_FLOOR_SATS_PER_KW));
+        let timing = if percentile >= WAIT_PERCENTILE && typical_fee_sats < fee_now_sats {
+            SendTiming::Wait
+        } else {
+            SendTiming::Now
+        };
+
+        Ok(SendTimingSuggestion {
+            timing,
+            fee_now_sats,
+            typical_fee_sats: Some(typical_fee_sats),
+            percentile: Some(percentile),
+        })
+    }
+}
+
+/// How much of the history had a lower normal fee rate than the given one,
+/// along with the median rate. None if there isn't enough history.
+fn fee_rate_percentile(history: &[FeeRateSample], sats_per_vbyte: f64) -> Option<(u8, f64)> {
+    if history.len() < MIN_FEE_HISTORY_SAMPLES {
+        return None;
+    }
+
+    let mut rates = history.iter().map(|s| s.normal).collect::<Vec<_>>();
+    rates.sort_by(|a, b| a.total_cmp(b));
+    let lower = rates.iter().filter(|r| **r < sats_per_vbyte).count();
+    let percentile = (lower * 100 / rates.len()) as u8;
+
+    Some((percentile, rates[rates.len() / 2]))
 }
 
 impl<S: MutinyStorage> FeeEstimator for MutinyFeeEstimator<S> {
@@ -381,4 +650,91 @@ mod test {
             2154
         );
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_static_fee_source() {
+        let test_name = "test_static_fee_source";
+        log!("{}", test_name);
+
+        let fee_estimator = create_fee_estimator()
+            .await
+            .with_fee_source(FeeSource::Static(5.0));
+        fee_estimator.update_fee_estimates().await.unwrap();
+
+        assert_eq!(fee_estimator.get_normal_fee_rate(), 1_250);
+        assert_eq!(fee_estimator.get_high_fee_rate(), 1_250);
+
+        // rates below the relay minimum are rejected
+        let fee_estimator = create_fee_estimator()
+            .await
+            .with_fee_source(FeeSource::Static(0.5));
+        assert!(fee_estimator.update_fee_estimates().await.is_err());
+    }
+
+    #[test]
+    fn test_fee_rate_percentile() {
+        let history = (0..MIN_FEE_HISTORY_SAMPLES as u64)
+            .map(|i| FeeRateSample {
+                timestamp: i * FEE_HISTORY_INTERVAL_SECS,
+                high: 20.0,
+                normal: (i + 1) as f64,
+                low: 1.0,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(fee_rate_percentile(&history, 100.0), Some((100, 25.0)));
+        assert_eq!(fee_rate_percentile(&history, 1.0), Some((0, 25.0)));
+        assert_eq!(fee_rate_percentile(&history, 13.0).unwrap().0, 25);
+
+        // not enough history
+        assert_eq!(fee_rate_percentile(&history[1..], 100.0), None);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_suggest_send_timing() {
+        let test_name = "test_suggest_send_timing";
+        log!("{}", test_name);
+
+        let fee_estimator = create_fee_estimator().await;
+
+        // no history yet, just send
+        let suggestion = fee_estimator.suggest_send_timing(1).unwrap();
+        assert_eq!(suggestion.timing, SendTiming::Now);
+        assert_eq!(suggestion.percentile, None);
+
+        let history = (0..MIN_FEE_HISTORY_SAMPLES as u64)
+            .map(|i| FeeRateSample {
+                timestamp: i * FEE_HISTORY_INTERVAL_SECS,
+                high: 10.0,
+                normal: 5.0,
+                low: 1.0,
+            })
+            .collect::<Vec<_>>();
+        fee_estimator
+            .storage
+            .write_data(FEE_HISTORY_KEY.to_string(), history, None)
+            .unwrap();
+
+        let mut fee_estimates = HashMap::new();
+        fee_estimates.insert("6".to_string(), 20_f64);
+        fee_estimator
+            .storage
+            .insert_fee_estimates(fee_estimates.clone())
+            .unwrap();
+        let suggestion = fee_estimator.suggest_send_timing(1).unwrap();
+        assert_eq!(suggestion.timing, SendTiming::Wait);
+        assert_eq!(suggestion.percentile, Some(100));
+        assert!(suggestion.typical_fee_sats.unwrap() < suggestion.fee_now_sats);
+
+        fee_estimates.insert("6".to_string(), 5_f64);
+        fee_estimator
+            .storage
+            .insert_fee_estimates(fee_estimates)
+            .unwrap();
+        let suggestion = fee_estimator.suggest_send_timing(1).unwrap();
+        assert_eq!(suggestion.timing, SendTiming::Now);
+        assert_eq!(suggestion.percentile, Some(0));
+    }
 }
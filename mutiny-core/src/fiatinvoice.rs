@@ -0,0 +1,209 @@
+//! Invoices priced in fiat, for merchants.
+//!
+//! The sat amount is locked at the price when the invoice is created and the
+//! fiat amount goes in the description. A bolt11 invoice only holds that
+//! quote for a short while: the expiry watcher replaces it with a new one at
+//! the current price once it expires or the price moves too far, until the
+//! request is paid or runs out of validity. Every invoice issued stays
+//! payable until it expires, so a payment to any of them completes the request.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::sha256;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub(crate) const FIAT_INVOICE_PREFIX: &str = "fiat_invoice/";
+/// How far the price can move, as a fraction, before the invoice is requoted
+pub const FIAT_RATE_DRIFT_THRESHOLD: f64 = 0.01;
+/// Longest a quote is held, requoted after this even if the price hasn't moved
+pub const MAX_QUOTE_SECS: u64 = 600;
+/// How often the expiry watcher looks at open fiat invoices
+pub(crate) const FIAT_INVOICE_WATCH_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FiatInvoiceStatus {
+    Open,
+    Paid,
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FiatInvoice {
+    pub id: String,
+    pub fiat_amount: f64,
+    /// Lowercase currency code, like the price api uses
+    pub currency: String,
+    /// Price of a bitcoin in the currency the current quote is at
+    pub price: f32,
+    pub amount_sats: u64,
+    /// The invoice to show, replaced on every requote
+    pub bolt11: Bolt11Invoice,
+    /// Every invoice issued for this request, oldest first
+    pub payment_hashes: Vec<sha256::Hash>,
+    pub status: FiatInvoiceStatus,
+    pub created_at: u64,
+    /// When the request stops being requoted
+    pub expires_at: u64,
+}
+
+impl FiatInvoice {
+    pub(crate) fn new(
+        fiat_amount: f64,
+        currency: String,
+        price: f32,
+        bolt11: Bolt11Invoice,
+        validity_secs: u64,
+    ) -> Result<Self, MutinyError> {
+        let now = utils::now().as_secs();
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            fiat_amount,
+            currency,
+            price,
+            amount_sats: sats_for_fiat(fiat_amount, price)?,
+            payment_hashes: vec![*bolt11.payment_hash()],
+            bolt11,
+            status: FiatInvoiceStatus::Open,
+            created_at: now,
+            expires_at: now + validity_secs,
+        })
+    }
+
+    /// Whether the current quote has to be replaced, because it expired or
+    /// the price moved past the threshold
+    pub(crate) fn needs_requote(&self, now: u64, current_price: Option<f32>) -> bool {
+        let quote_expired = self
+            .bolt11
+            .expires_at()
+            .map_or(true, |at| now >= at.as_secs());
+        quote_expired || current_price.is_some_and(|p| rate_drifted(self.price, p))
+    }
+
+    /// Replaces the quote with one at the new price
+    pub(crate) fn requote(&mut self, price: f32, bolt11: Bolt11Invoice) -> Result<(), MutinyError> {
+        self.amount_sats = sats_for_fiat(self.fiat_amount, price)?;
+        self.price = price;
+        self.payment_hashes.push(*bolt11.payment_hash());
+        self.bolt11 = bolt11;
+        Ok(())
+    }
+}
+
+/// The sats the fiat amount buys at the price of a bitcoin
+pub(crate) fn sats_for_fiat(fiat_amount: f64, price: f32) -> Result<u64, MutinyError> {
+    if !fiat_amount.is_finite() || fiat_amount <= 0.0 || !price.is_finite() || price <= 0.0 {
+        return Err(MutinyError::BadAmountError);
+    }
+    let sats = (fiat_amount / price as f64 * 100_000_000.0).round() as u64;
+    if sats == 0 {
+        return Err(MutinyError::BadAmountError);
+    }
+    Ok(sats)
+}
+
+pub(crate) fn rate_drifted(quoted: f32, current: f32) -> bool {
+    ((current - quoted) / quoted).abs() as f64 > FIAT_RATE_DRIFT_THRESHOLD
+}
+
+/// The invoice description, carrying the fiat amount so the payer sees what they're paying
+pub(crate) fn fiat_description(fiat_amount: f64, currency: &str) -> String {
+    format!("{fiat_amount:.2} {}", currency.to_uppercase())
+}
+
+/// How long the next quote is held, never past the end of the request
+pub(crate) fn quote_secs(now: u64, expires_at: u64) -> u64 {
+    expires_at.saturating_sub(now).min(MAX_QUOTE_SECS)
+}
+
+fn fiat_invoice_key(id: &str) -> String {
+    format!("{FIAT_INVOICE_PREFIX}{id}")
+}
+
+pub(crate) fn persist_fiat_invoice<S: MutinyStorage>(
+    storage: &S,
+    invoice: &FiatInvoice,
+) -> Result<(), MutinyError> {
+    storage.write_data(fiat_invoice_key(&invoice.id), invoice, None)
+}
+
+pub(crate) fn get_fiat_invoice<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<FiatInvoice>, MutinyError> {
+    storage.get_data(fiat_invoice_key(id))
+}
+
+/// Lists the fiat invoices, newest first
+pub(crate) fn list_fiat_invoices<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<FiatInvoice>, MutinyError> {
+    let mut invoices: Vec<FiatInvoice> = storage
+        .scan::<FiatInvoice>(FIAT_INVOICE_PREFIX, None)?
+        .into_values()
+        .collect();
+    invoices.sort_by_key(|i| std::cmp::Reverse(i.created_at));
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nrefpp5pczykgk37af5388n8dzynljpkzs7sje4melqgazlwv9y3apay8jqhp5rd8saxz3juve3eejq7z5fjttxmpaq88d7l92xv34n4h3mq6kwq2qcqzzsxqzfvsp5z0jwpehkuz9f2kv96h62p8x30nku76aj8yddpcust7g8ad0tr52q9qyyssqfy622q25helv8cj8hyxqltws4rdwz0xx2hw0uh575mn7a76cp3q4jcptmtjkjs4a34dqqxn8uy70d0qlxqleezv4zp84uk30pp5q3nqq4c9gkz";
+
+    #[test]
+    fn test_fiat_amounts() {
+        let test_name = "test_fiat_amounts";
+        log!("{}", test_name);
+
+        assert_eq!(sats_for_fiat(50.0, 50_000.0).unwrap(), 100_000);
+        assert!(sats_for_fiat(0.0, 50_000.0).is_err());
+        assert!(sats_for_fiat(10.0, 0.0).is_err());
+        assert!(sats_for_fiat(0.000_001, 50_000.0).is_err());
+
+        assert!(!rate_drifted(50_000.0, 50_400.0));
+        assert!(rate_drifted(50_000.0, 50_600.0));
+        assert!(rate_drifted(50_000.0, 49_400.0));
+
+        assert_eq!(fiat_description(12.5, "usd"), "12.50 USD");
+        assert_eq!(quote_secs(100, 10_000), MAX_QUOTE_SECS);
+        assert_eq!(quote_secs(100, 400), 300);
+        assert_eq!(quote_secs(500, 400), 0);
+    }
+
+    #[test]
+    fn test_fiat_invoice_requote() {
+        let test_name = "test_fiat_invoice_requote";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let bolt11 = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let mut invoice =
+            FiatInvoice::new(50.0, "usd".to_string(), 50_000.0, bolt11.clone(), 3_600).unwrap();
+        assert_eq!(invoice.amount_sats, 100_000);
+        persist_fiat_invoice(&storage, &invoice).unwrap();
+        assert_eq!(
+            get_fiat_invoice(&storage, &invoice.id).unwrap(),
+            Some(invoice.clone())
+        );
+
+        // the test invoice expired long ago
+        assert!(invoice.needs_requote(utils::now().as_secs(), None));
+
+        invoice.requote(40_000.0, bolt11).unwrap();
+        assert_eq!(invoice.amount_sats, 125_000);
+        assert_eq!(invoice.payment_hashes.len(), 2);
+        persist_fiat_invoice(&storage, &invoice).unwrap();
+        assert_eq!(list_fiat_invoices(&storage).unwrap(), vec![invoice]);
+    }
+}
@@ -6,9 +6,9 @@ use bitcoin::hashes::hex::FromHex;
 use bitcoin::Network;
 use hex_conservative::DisplayHex;
 use lightning::ln::msgs::NodeAnnouncement;
-use lightning::routing::gossip::NodeId;
+use lightning::routing::gossip::{NodeAnnouncementInfo, NodeId};
 use lightning::util::logger::Logger;
-use lightning::util::ser::ReadableArgs;
+use lightning::util::ser::{ReadableArgs, Writeable};
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -27,6 +27,7 @@ use crate::storage::MutinyStorage;
 use crate::utils;
 
 pub(crate) const LN_PEER_METADATA_KEY_PREFIX: &str = "ln_peer/";
+pub(crate) const NODE_INFO_CACHE_PREFIX: &str = "node_info/";
 pub const GOSSIP_SYNC_TIME_KEY: &str = "last_sync_timestamp";
 pub const NETWORK_GRAPH_KEY: &str = "network_graph";
 pub const PROB_SCORER_KEY: &str = "prob_scorer";
@@ -54,17 +55,38 @@ async fn get_scorer(
     logger: Arc<MutinyLogger>,
 ) -> Result<Option<HubPreferentialScorer>, MutinyError> {
     if let Some(prob_scorer_str) = storage.get_data::<String>(PROB_SCORER_KEY)? {
-        let prob_scorer_bytes: Vec<u8> = Vec::from_hex(&prob_scorer_str)?;
-        let mut readable_bytes = lightning::io::Cursor::new(prob_scorer_bytes);
-        let params = decay_params();
-        let args = (params, Arc::clone(&network_graph), Arc::clone(&logger));
-        let scorer = ProbScorer::read(&mut readable_bytes, args)?;
-        Ok(Some(HubPreferentialScorer::new(scorer)))
+        let scorer = decode_scorer(&prob_scorer_str, network_graph, logger)?;
+        Ok(Some(scorer))
     } else {
         Ok(None)
     }
 }
 
+/// Decodes a hex encoded scorer, as it is stored and exported
+pub(crate) fn decode_scorer(
+    hex: &str,
+    network_graph: Arc<NetworkGraph>,
+    logger: Arc<MutinyLogger>,
+) -> Result<HubPreferentialScorer, MutinyError> {
+    let scorer_bytes: Vec<u8> = Vec::from_hex(hex)?;
+    let mut readable_bytes = lightning::io::Cursor::new(scorer_bytes);
+    let args = (decay_params(), network_graph, logger);
+    let scorer = ProbScorer::read(&mut readable_bytes, args)?;
+    Ok(HubPreferentialScorer::new(scorer))
+}
+
+/// Hex encodes the scorer, the inverse of [`decode_scorer`]
+pub(crate) fn encode_scorer(scorer: &HubPreferentialScorer) -> String {
+    scorer.encode().to_lower_hex_string()
+}
+
+pub(crate) fn persist_scorer(
+    storage: &impl MutinyStorage,
+    scorer: &HubPreferentialScorer,
+) -> Result<(), MutinyError> {
+    storage.write_data(PROB_SCORER_KEY.to_string(), encode_scorer(scorer), None)
+}
+
 #[allow(dead_code)]
 async fn get_gossip_data(
     storage: &impl MutinyStorage,
@@ -191,17 +213,9 @@ pub async fn get_gossip_sync(
     let scorer_hex: Option<String> = storage.get_data(PROB_SCORER_KEY)?;
 
     if let Some(hex) = scorer_hex {
-        let scorer_bytes: Vec<u8> = Vec::from_hex(&hex)?;
-        let mut readable_bytes = lightning::io::Cursor::new(scorer_bytes);
-        let params = decay_params();
-        let args = (
-            params,
-            Arc::clone(&gossip_data.network_graph),
-            Arc::clone(&logger),
-        );
-        if let Ok(scorer) = ProbScorer::read(&mut readable_bytes, args) {
+        let network_graph = Arc::clone(&gossip_data.network_graph);
+        if let Ok(scorer) = decode_scorer(&hex, network_graph, Arc::clone(&logger)) {
             log_debug!(logger, "retrieved local scorer");
-            let scorer = HubPreferentialScorer::new(scorer);
             gossip_data.scorer = Some(scorer);
         } else {
             log_error!(logger, "failed to parse local scorer");
@@ -481,6 +495,88 @@ pub(crate) fn save_ln_peer_info(
     Ok(())
 }
 
+/// What a node announced about itself, kept so peers can be shown by name
+/// before the network graph has synced
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct CachedNodeInfo {
+    pub alias: Option<String>,
+    /// Hex encoded color
+    pub color: Option<String>,
+    pub addresses: Vec<String>,
+    /// Timestamp of the node announcement this came from
+    pub timestamp: u32,
+}
+
+impl From<&NodeAnnouncementInfo> for CachedNodeInfo {
+    fn from(value: &NodeAnnouncementInfo) -> Self {
+        let alias = value.alias().to_string();
+        Self {
+            alias: Some(alias).filter(|a| !a.is_empty()),
+            color: Some(value.rgb().to_lower_hex_string()),
+            addresses: value.addresses().iter().map(|a| a.to_string()).collect(),
+            timestamp: value.last_update(),
+        }
+    }
+}
+
+fn node_info_key(node_id: &NodeId) -> String {
+    format!("{NODE_INFO_CACHE_PREFIX}{node_id}")
+}
+
+pub(crate) fn get_cached_node_info(
+    storage: &impl MutinyStorage,
+    node_id: &NodeId,
+) -> Result<Option<CachedNodeInfo>, MutinyError> {
+    storage.get_data(node_info_key(node_id))
+}
+
+/// Copies what the network graph knows about the nodes into storage,
+/// skipping nodes whose cached info is already as new. Returns how many
+/// were updated.
+pub(crate) fn cache_node_info(
+    storage: &impl MutinyStorage,
+    network_graph: &NetworkGraph,
+    node_ids: &[NodeId],
+) -> Result<usize, MutinyError> {
+    let graph = network_graph.read_only();
+    let mut updated = 0;
+    for node_id in node_ids {
+        let Some(info) = graph
+            .node(node_id)
+            .and_then(|n| n.announcement_info.as_ref())
+            .map(CachedNodeInfo::from)
+        else {
+            continue;
+        };
+        let current = get_cached_node_info(storage, node_id)?;
+        if current.map_or(true, |c| c.timestamp < info.timestamp) {
+            storage.write_data(node_info_key(node_id), info, None)?;
+            updated += 1;
+        }
+    }
+
+    Ok(updated)
+}
+
+/// The node's alias from the network graph, or from the cache when the
+/// graph doesn't have it yet
+pub(crate) fn lookup_node_alias(
+    storage: &impl MutinyStorage,
+    network_graph: &NetworkGraph,
+    node_id: &NodeId,
+) -> Result<Option<String>, MutinyError> {
+    let alias = network_graph
+        .read_only()
+        .node(node_id)
+        .and_then(|n| n.announcement_info.as_ref())
+        .map(|a| a.alias().to_string())
+        .filter(|a| !a.is_empty());
+    match alias {
+        Some(alias) => Ok(Some(alias)),
+        None => Ok(get_cached_node_info(storage, node_id)?.and_then(|i| i.alias)),
+    }
+}
+
 pub(crate) fn get_rgs_url(
     network: Network,
     user_provided_url: Option<&str>,
@@ -626,4 +722,61 @@ mod test {
         assert!(read.is_some());
         assert_eq!(read.unwrap(), expected);
     }
+
+    #[test]
+    fn test_node_info_cache() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = NetworkGraph::new(Network::Regtest, logger);
+        let node_id = dummy_node_id();
+
+        // nothing in the graph or the cache yet
+        assert_eq!(
+            cache_node_info(&storage, &network_graph, &[node_id]).unwrap(),
+            0
+        );
+        assert_eq!(
+            lookup_node_alias(&storage, &network_graph, &node_id).unwrap(),
+            None
+        );
+
+        let info = CachedNodeInfo {
+            alias: Some("cached alias".to_string()),
+            color: Some("123456".to_string()),
+            addresses: vec!["example.com:9735".to_string()],
+            timestamp: 1,
+        };
+        storage
+            .write_data(node_info_key(&node_id), &info, None)
+            .unwrap();
+        assert_eq!(
+            get_cached_node_info(&storage, &node_id).unwrap(),
+            Some(info)
+        );
+        assert_eq!(
+            lookup_node_alias(&storage, &network_graph, &node_id).unwrap(),
+            Some("cached alias".to_string())
+        );
+    }
+
+    #[test]
+    fn test_scorer_export_import() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let network_graph = Arc::new(NetworkGraph::new(Network::Regtest, logger.clone()));
+
+        let scorer = ProbScorer::new(decay_params(), network_graph.clone(), logger.clone());
+        let exported = encode_scorer(&HubPreferentialScorer::new(scorer));
+
+        let imported = decode_scorer(&exported, network_graph.clone(), logger.clone()).unwrap();
+        assert_eq!(encode_scorer(&imported), exported);
+
+        persist_scorer(&storage, &imported).unwrap();
+        assert_eq!(
+            storage.get_data::<String>(PROB_SCORER_KEY).unwrap(),
+            Some(exported.clone())
+        );
+
+        assert!(decode_scorer("not hex", network_graph, logger).is_err());
+    }
 }
@@ -0,0 +1,125 @@
+//! Hold invoices are made for a payment hash whose preimage we don't know yet.
+//! Payments to them are held instead of claimed until they're settled with the
+//! preimage or cancelled, which fails the payment back to the payer. This is
+//! what escrow needs: the preimage is only released once both sides are happy.
+//!
+//! A held payment can't wait forever. If it's neither settled nor cancelled
+//! before its HTLCs get close to expiring, LDK fails it back and the invoice
+//! can be paid again.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::Hash;
+use hex_conservative::DisplayHex;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const HODL_INVOICE_PREFIX: &str = "hodl_invoice/";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HodlInvoiceStatus {
+    /// Waiting for a payment
+    Open,
+    /// A payment arrived and is waiting to be settled or cancelled
+    Held,
+    Settled,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HodlInvoice {
+    /// Hex encoded payment hash
+    pub payment_hash: String,
+    pub bolt11: Bolt11Invoice,
+    pub amount_sats: u64,
+    /// What the held payment pays us, in msats
+    pub held_msat: Option<u64>,
+    pub status: HodlInvoiceStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl HodlInvoice {
+    pub(crate) fn new(bolt11: Bolt11Invoice, amount_sats: u64) -> Self {
+        let now = utils::now().as_secs();
+        Self {
+            payment_hash: bolt11.payment_hash().to_byte_array().to_lower_hex_string(),
+            bolt11,
+            amount_sats,
+            held_msat: None,
+            status: HodlInvoiceStatus::Open,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+fn hodl_invoice_key(payment_hash: &[u8; 32]) -> String {
+    format!(
+        "{HODL_INVOICE_PREFIX}{}",
+        payment_hash.to_lower_hex_string()
+    )
+}
+
+pub(crate) fn persist_hodl_invoice<S: MutinyStorage>(
+    storage: &S,
+    invoice: &mut HodlInvoice,
+) -> Result<(), MutinyError> {
+    invoice.updated_at = utils::now().as_secs();
+    let key = hodl_invoice_key(&invoice.bolt11.payment_hash().to_byte_array());
+    storage.write_data(key, &*invoice, None)
+}
+
+pub(crate) fn get_hodl_invoice<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Result<Option<HodlInvoice>, MutinyError> {
+    storage.get_data(hodl_invoice_key(payment_hash))
+}
+
+/// Lists our hold invoices, newest first
+pub(crate) fn list_hodl_invoices<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<HodlInvoice>, MutinyError> {
+    let mut invoices: Vec<HodlInvoice> = storage
+        .scan::<HodlInvoice>(HODL_INVOICE_PREFIX, None)?
+        .into_values()
+        .collect();
+    invoices.sort_by_key(|i| std::cmp::Reverse(i.created_at));
+
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nrefpp5pczykgk37af5388n8dzynljpkzs7sje4melqgazlwv9y3apay8jqhp5rd8saxz3juve3eejq7z5fjttxmpaq88d7l92xv34n4h3mq6kwq2qcqzzsxqzfvsp5z0jwpehkuz9f2kv96h62p8x30nku76aj8yddpcust7g8ad0tr52q9qyyssqfy622q25helv8cj8hyxqltws4rdwz0xx2hw0uh575mn7a76cp3q4jcptmtjkjs4a34dqqxn8uy70d0qlxqleezv4zp84uk30pp5q3nqq4c9gkz";
+
+    #[test]
+    fn test_hodl_invoice_records() {
+        let test_name = "test_hodl_invoice_records";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let bolt11 = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let payment_hash = bolt11.payment_hash().to_byte_array();
+
+        let mut invoice = HodlInvoice::new(bolt11, 92_372);
+        assert_eq!(invoice.status, HodlInvoiceStatus::Open);
+        persist_hodl_invoice(&storage, &mut invoice).unwrap();
+        assert_eq!(
+            get_hodl_invoice(&storage, &payment_hash).unwrap(),
+            Some(invoice.clone())
+        );
+        assert_eq!(list_hodl_invoices(&storage).unwrap(), vec![invoice]);
+        assert!(get_hodl_invoice(&storage, &[0; 32]).unwrap().is_none());
+    }
+}
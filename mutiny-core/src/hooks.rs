@@ -0,0 +1,475 @@
+//! Hooks for embedders, to run their own code on wallet events without forking
+//! the crate, e.g. compliance checks, budgeting rules or analytics.
+//!
+//! A hook subscribes to some kinds of events and is registered with the
+//! capabilities it's allowed to use. Every hook can observe. Before a payment
+//! is sent, hooks with [`HookCapability::Annotate`] can add labels to it and
+//! hooks with [`HookCapability::Veto`] can stop it. Anything a hook returns
+//! beyond its capabilities is ignored. The other events are only observed,
+//! their hooks run in the background so they never hold up the wallet.
+//!
+//! A channel acceptor can also be set, which decides on the channels peers ask
+//! to open with us in place of the built in policy.
+
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::utils;
+use bitcoin::secp256k1::PublicKey;
+use futures::future::{self, Either};
+use futures::pin_mut;
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_info, log_warn};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum HookKind {
+    BeforeSend,
+    AfterReceive,
+    ChannelEvent,
+    SyncComplete,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum HookCapability {
+    /// Add labels to the payment
+    Annotate,
+    /// Stop the payment from being sent
+    Veto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChannelHookState {
+    Pending,
+    Ready,
+    Closed { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum HookEvent {
+    /// A payment is about to be sent, the destination is an invoice, offer or
+    /// address. It's empty for refunds, their recipient isn't known yet.
+    BeforeSend {
+        destination: String,
+        /// Not known for sweeps, which send everything
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    },
+    /// A lightning payment was received
+    AfterReceive {
+        payment_hash: String,
+        amount_sats: u64,
+    },
+    ChannelEvent {
+        channel_id: String,
+        counterparty_node_id: Option<PublicKey>,
+        state: ChannelHookState,
+    },
+    /// The wallet finished syncing with the chain
+    SyncComplete { duration_ms: u64 },
+}
+
+impl HookEvent {
+    pub fn kind(&self) -> HookKind {
+        match self {
+            HookEvent::BeforeSend { .. } => HookKind::BeforeSend,
+            HookEvent::AfterReceive { .. } => HookKind::AfterReceive,
+            HookEvent::ChannelEvent { .. } => HookKind::ChannelEvent,
+            HookEvent::SyncComplete { .. } => HookKind::SyncComplete,
+        }
+    }
+}
+
+/// What a hook wants done with the event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookResponse {
+    pub veto: Option<String>,
+    pub labels: Vec<String>,
+}
+
+impl HookResponse {
+    pub fn allow() -> Self {
+        Self::default()
+    }
+
+    pub fn veto(reason: impl Into<String>) -> Self {
+        Self {
+            veto: Some(reason.into()),
+            labels: vec![],
+        }
+    }
+
+    pub fn annotate(labels: Vec<String>) -> Self {
+        Self { veto: None, labels }
+    }
+}
+
+pub type HookFuture = Pin<Box<dyn Future<Output = HookResponse> + Send>>;
+pub type HookHandler = Arc<dyn Fn(HookEvent) -> HookFuture + Send + Sync>;
+
+/// How long the channel acceptor has to decide, the peer gives up on us soon after
+const CHANNEL_ACCEPTOR_TIMEOUT_MS: i32 = 30_000;
+
+/// A channel a peer asked to open with us
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelOpenRequest {
+    pub temporary_channel_id: String,
+    pub counterparty_node_id: PublicKey,
+    pub funding_sats: u64,
+    /// What the opener gives us when the channel opens
+    pub push_msat: u64,
+    /// Whether the peer wants to use the channel before it confirms. Accepting
+    /// it trusts them not to double spend the funding.
+    pub zero_conf: bool,
+    /// Whether the peer is our LSP
+    pub from_lsp: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum ChannelAcceptance {
+    Accept,
+    Reject { reason: String },
+}
+
+pub type ChannelAcceptorFuture = Pin<Box<dyn Future<Output = ChannelAcceptance> + Send>>;
+pub type ChannelAcceptor = Arc<dyn Fn(ChannelOpenRequest) -> ChannelAcceptorFuture + Send + Sync>;
+
+/// A registered hook, without its handler
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HookInfo {
+    pub id: String,
+    pub name: String,
+    pub kinds: Vec<HookKind>,
+    pub capabilities: Vec<HookCapability>,
+}
+
+#[derive(Clone)]
+struct RegisteredHook {
+    info: HookInfo,
+    handler: HookHandler,
+}
+
+pub struct HookRegistry {
+    hooks: RwLock<Vec<RegisteredHook>>,
+    channel_acceptor: RwLock<Option<ChannelAcceptor>>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl HookRegistry {
+    pub(crate) fn new(logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            hooks: RwLock::new(vec![]),
+            channel_acceptor: RwLock::new(None),
+            logger,
+        }
+    }
+
+    /// Sets the channel acceptor, or with None goes back to the built in policy
+    pub fn set_channel_acceptor(
+        &self,
+        acceptor: Option<ChannelAcceptor>,
+    ) -> Result<(), MutinyError> {
+        log_info!(
+            self.logger,
+            "{} channel acceptor",
+            if acceptor.is_some() {
+                "Setting"
+            } else {
+                "Removing"
+            }
+        );
+        *self
+            .channel_acceptor
+            .write()
+            .map_err(|_| MutinyError::WalletOperationFailed)? = acceptor;
+        Ok(())
+    }
+
+    pub fn has_channel_acceptor(&self) -> bool {
+        self.channel_acceptor.read().is_ok_and(|a| a.is_some())
+    }
+
+    /// Asks the channel acceptor about the request, None if there is no acceptor.
+    /// Requests it doesn't answer in time are rejected.
+    pub(crate) async fn accept_channel(
+        &self,
+        request: ChannelOpenRequest,
+    ) -> Option<ChannelAcceptance> {
+        let acceptor = self.channel_acceptor.read().ok()?.clone()?;
+
+        let decision = acceptor(request);
+        let timeout = utils::sleep(CHANNEL_ACCEPTOR_TIMEOUT_MS);
+        pin_mut!(timeout);
+        match future::select(decision, timeout).await {
+            Either::Left((acceptance, _)) => Some(acceptance),
+            Either::Right(_) => {
+                log_warn!(self.logger, "Channel acceptor timed out, rejecting");
+                Some(ChannelAcceptance::Reject {
+                    reason: "channel acceptor timed out".to_string(),
+                })
+            }
+        }
+    }
+
+    /// Registers a hook for the kinds of events, returns its id
+    pub fn register(
+        &self,
+        name: String,
+        kinds: Vec<HookKind>,
+        capabilities: Vec<HookCapability>,
+        handler: HookHandler,
+    ) -> Result<String, MutinyError> {
+        if kinds.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let info = HookInfo {
+            id: Uuid::new_v4().to_string(),
+            name,
+            kinds,
+            capabilities,
+        };
+        let id = info.id.clone();
+        log_info!(self.logger, "Registering hook {} ({id})", info.name);
+        self.hooks
+            .write()
+            .map_err(|_| MutinyError::WalletOperationFailed)?
+            .push(RegisteredHook { info, handler });
+
+        Ok(id)
+    }
+
+    /// Removes a hook, returns whether it was registered
+    pub fn unregister(&self, id: &str) -> Result<bool, MutinyError> {
+        let mut hooks = self
+            .hooks
+            .write()
+            .map_err(|_| MutinyError::WalletOperationFailed)?;
+        let len = hooks.len();
+        hooks.retain(|h| h.info.id != id);
+        Ok(hooks.len() != len)
+    }
+
+    pub fn list(&self) -> Result<Vec<HookInfo>, MutinyError> {
+        Ok(self
+            .hooks
+            .read()
+            .map_err(|_| MutinyError::WalletOperationFailed)?
+            .iter()
+            .map(|h| h.info.clone())
+            .collect())
+    }
+
+    fn subscribed(&self, kind: HookKind) -> Vec<RegisteredHook> {
+        match self.hooks.read() {
+            Ok(hooks) => hooks
+                .iter()
+                .filter(|h| h.info.kinds.contains(&kind))
+                .cloned()
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Runs the hooks before a payment is sent, in the order they were registered.
+    /// Returns the labels the hooks added, or an error if one vetoed it.
+    pub(crate) async fn before_send(&self, event: HookEvent) -> Result<Vec<String>, MutinyError> {
+        let mut labels = vec![];
+        for hook in self.subscribed(event.kind()) {
+            let response = (hook.handler)(event.clone()).await;
+            let (veto, annotations) = apply_policy(&hook.info, response);
+
+            if let Some(reason) = veto {
+                log_info!(self.logger, "Hook {} vetoed: {reason}", hook.info.name);
+                return Err(MutinyError::HookVetoed(reason));
+            }
+            labels = merge_labels(labels, annotations);
+        }
+
+        Ok(labels)
+    }
+
+    /// Lets the hooks observe the event in the background
+    pub(crate) fn notify(self: &Arc<Self>, event: HookEvent) {
+        if self.subscribed(event.kind()).is_empty() {
+            return;
+        }
+        let registry = self.clone();
+        utils::spawn(async move {
+            for hook in registry.subscribed(event.kind()) {
+                let response = (hook.handler)(event.clone()).await;
+                if response != HookResponse::allow() {
+                    log_debug!(
+                        registry.logger,
+                        "Hook {} responded to an event it can only observe",
+                        hook.info.name
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// The veto and labels of a response the hook has the capabilities for
+fn apply_policy(hook: &HookInfo, response: HookResponse) -> (Option<String>, Vec<String>) {
+    let veto = response
+        .veto
+        .filter(|_| hook.capabilities.contains(&HookCapability::Veto));
+    let labels = if hook.capabilities.contains(&HookCapability::Annotate) {
+        response.labels
+    } else {
+        vec![]
+    };
+    (veto, labels)
+}
+
+/// Adds the hooks' labels to the ones given for the payment
+pub(crate) fn merge_labels(mut labels: Vec<String>, added: Vec<String>) -> Vec<String> {
+    for label in added {
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn handler(response: HookResponse) -> HookHandler {
+        Arc::new(move |_| {
+            let response = response.clone();
+            Box::pin(async move { response })
+        })
+    }
+
+    fn send_event() -> HookEvent {
+        HookEvent::BeforeSend {
+            destination: "bc1q".to_string(),
+            amount_sats: Some(1_000),
+            labels: vec![],
+        }
+    }
+
+    #[test]
+    async fn test_hook_capabilities() {
+        let test_name = "test_hook_capabilities";
+        log!("{}", test_name);
+
+        let registry = HookRegistry::new(Arc::new(MutinyLogger::default()));
+        let budget = registry
+            .register(
+                "budget".to_string(),
+                vec![HookKind::BeforeSend],
+                vec![HookCapability::Annotate],
+                handler(HookResponse {
+                    // can't veto without the capability
+                    veto: Some("over budget".to_string()),
+                    labels: vec!["groceries".to_string()],
+                }),
+            )
+            .unwrap();
+        registry
+            .register(
+                "analytics".to_string(),
+                vec![HookKind::BeforeSend, HookKind::SyncComplete],
+                vec![],
+                handler(HookResponse::annotate(vec!["tracked".to_string()])),
+            )
+            .unwrap();
+
+        let labels = registry.before_send(send_event()).await.unwrap();
+        assert_eq!(labels, vec!["groceries".to_string()]);
+        assert_eq!(
+            merge_labels(vec!["rent".to_string()], labels),
+            vec!["rent".to_string(), "groceries".to_string()]
+        );
+
+        registry
+            .register(
+                "compliance".to_string(),
+                vec![HookKind::BeforeSend],
+                vec![HookCapability::Veto],
+                handler(HookResponse::veto("blocked address")),
+            )
+            .unwrap();
+        assert_eq!(
+            registry.before_send(send_event()).await,
+            Err(MutinyError::HookVetoed("blocked address".to_string()))
+        );
+
+        assert_eq!(registry.list().unwrap().len(), 3);
+        assert!(registry.unregister(&budget).unwrap());
+        assert!(!registry.unregister(&budget).unwrap());
+        assert!(registry
+            .register(
+                "empty".to_string(),
+                vec![],
+                vec![],
+                handler(HookResponse::allow())
+            )
+            .is_err());
+    }
+
+    #[test]
+    async fn test_channel_acceptor() {
+        let test_name = "test_channel_acceptor";
+        log!("{}", test_name);
+
+        let registry = HookRegistry::new(Arc::new(MutinyLogger::default()));
+        let request = ChannelOpenRequest {
+            temporary_channel_id: "00".repeat(32),
+            counterparty_node_id: PublicKey::from_str(
+                "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+            )
+            .unwrap(),
+            funding_sats: 100_000,
+            push_msat: 0,
+            zero_conf: false,
+            from_lsp: false,
+        };
+        assert!(!registry.has_channel_acceptor());
+        assert_eq!(registry.accept_channel(request.clone()).await, None);
+
+        let acceptor: ChannelAcceptor = Arc::new(|request| {
+            Box::pin(async move {
+                if request.funding_sats >= 1_000_000 {
+                    ChannelAcceptance::Accept
+                } else {
+                    ChannelAcceptance::Reject {
+                        reason: "too small".to_string(),
+                    }
+                }
+            })
+        });
+        registry.set_channel_acceptor(Some(acceptor)).unwrap();
+        assert!(registry.has_channel_acceptor());
+        assert_eq!(
+            registry.accept_channel(request.clone()).await,
+            Some(ChannelAcceptance::Reject {
+                reason: "too small".to_string()
+            })
+        );
+        let big = ChannelOpenRequest {
+            funding_sats: 2_000_000,
+            ..request.clone()
+        };
+        assert_eq!(
+            registry.accept_channel(big).await,
+            Some(ChannelAcceptance::Accept)
+        );
+
+        registry.set_channel_acceptor(None).unwrap();
+        assert_eq!(registry.accept_channel(request).await, None);
+    }
+}
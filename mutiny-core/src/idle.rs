@@ -0,0 +1,214 @@
+//! Idle detection, to save battery while the app sits in the background.
+//!
+//! After the configured minutes without user interaction the wallet goes
+//! idle: peers we don't have a channel with are disconnected, gossip and
+//! scorer syncs are skipped and the chain is polled less often. The next
+//! API call wakes it right away.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+pub(crate) const IDLE_SETTINGS_KEY: &str = "idle_settings";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdleSettings {
+    pub enabled: bool,
+    /// Minutes without interaction before going idle
+    pub idle_after_mins: u32,
+    /// How often the chain is polled while idle
+    pub idle_sync_interval_secs: u64,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_after_mins: 5,
+            idle_sync_interval_secs: 600,
+        }
+    }
+}
+
+impl IdleSettings {
+    fn validate(&self) -> Result<(), MutinyError> {
+        if self.idle_after_mins == 0 || self.idle_sync_interval_secs == 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdleStatus {
+    pub idle: bool,
+    /// Epoch seconds of the last user interaction
+    pub last_interaction: u64,
+    /// Epoch seconds since when the wallet has been idle
+    pub idle_since: Option<u64>,
+    pub settings: IdleSettings,
+}
+
+pub(crate) struct IdleTracker {
+    settings: RwLock<IdleSettings>,
+    last_interaction: AtomicU64,
+    idle_since: AtomicU64,
+    /// Shared with the nodes, so their background tasks can back off too
+    idle: Arc<AtomicBool>,
+}
+
+impl IdleTracker {
+    pub(crate) fn new(settings: IdleSettings, now: u64) -> Self {
+        Self {
+            settings: RwLock::new(settings),
+            last_interaction: AtomicU64::new(now),
+            idle_since: AtomicU64::new(0),
+            idle: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn idle_flag(&self) -> Arc<AtomicBool> {
+        self.idle.clone()
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn settings(&self) -> IdleSettings {
+        *self.settings.read().expect("idle settings lock poisoned")
+    }
+
+    pub(crate) fn set_settings(&self, settings: IdleSettings, now: u64) {
+        *self.settings.write().expect("idle settings lock poisoned") = settings;
+        // new thresholds count from now
+        self.touch(now);
+    }
+
+    /// Records a user interaction, returns true if it woke the wallet up
+    pub(crate) fn touch(&self, now: u64) -> bool {
+        self.last_interaction.store(now, Ordering::Relaxed);
+        let woke = self.idle.swap(false, Ordering::Relaxed);
+        if woke {
+            self.idle_since.store(0, Ordering::Relaxed);
+        }
+        woke
+    }
+
+    /// Goes idle if it's been long enough since the last interaction,
+    /// returns true only when it just went idle
+    pub(crate) fn check(&self, now: u64) -> bool {
+        let settings = self.settings();
+        if !settings.enabled || self.is_idle() {
+            return false;
+        }
+        let last = self.last_interaction.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < settings.idle_after_mins as u64 * 60 {
+            return false;
+        }
+        self.idle_since.store(now, Ordering::Relaxed);
+        !self.idle.swap(true, Ordering::Relaxed)
+    }
+
+    /// How long to wait between syncs
+    pub(crate) fn sync_interval_secs(&self, active_secs: u64) -> u64 {
+        if self.is_idle() {
+            self.settings().idle_sync_interval_secs.max(active_secs)
+        } else {
+            active_secs
+        }
+    }
+
+    pub(crate) fn status(&self) -> IdleStatus {
+        let idle_since = self.idle_since.load(Ordering::Relaxed);
+        IdleStatus {
+            idle: self.is_idle(),
+            last_interaction: self.last_interaction.load(Ordering::Relaxed),
+            idle_since: (idle_since > 0).then_some(idle_since),
+            settings: self.settings(),
+        }
+    }
+}
+
+pub(crate) fn get_idle_settings<S: MutinyStorage>(
+    storage: &S,
+) -> Result<IdleSettings, MutinyError> {
+    Ok(storage.get_data(IDLE_SETTINGS_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_idle_settings<S: MutinyStorage>(
+    storage: &S,
+    settings: &IdleSettings,
+) -> Result<(), MutinyError> {
+    settings.validate()?;
+    storage.write_data(IDLE_SETTINGS_KEY.to_string(), settings, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_idle_tracker() {
+        let test_name = "test_idle_tracker";
+        log!("{}", test_name);
+
+        let tracker = IdleTracker::new(IdleSettings::default(), 1_000);
+        assert!(!tracker.check(1_000 + 299));
+        assert!(tracker.check(1_000 + 300));
+        assert!(tracker.is_idle());
+        // only reported once
+        assert!(!tracker.check(1_000 + 400));
+        assert_eq!(tracker.sync_interval_secs(60), 600);
+        assert_eq!(tracker.status().idle_since, Some(1_300));
+
+        assert!(tracker.touch(2_000));
+        assert!(!tracker.is_idle());
+        assert!(!tracker.touch(2_001));
+        assert_eq!(tracker.sync_interval_secs(60), 60);
+        assert_eq!(tracker.status().idle_since, None);
+        assert_eq!(tracker.status().last_interaction, 2_001);
+
+        tracker.set_settings(
+            IdleSettings {
+                enabled: false,
+                ..Default::default()
+            },
+            2_001,
+        );
+        assert!(!tracker.check(10_000));
+    }
+
+    #[test]
+    fn test_idle_settings() {
+        let test_name = "test_idle_settings";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_idle_settings(&storage).unwrap(),
+            IdleSettings::default()
+        );
+
+        let settings = IdleSettings {
+            enabled: true,
+            idle_after_mins: 1,
+            idle_sync_interval_secs: 300,
+        };
+        persist_idle_settings(&storage, &settings).unwrap();
+        assert_eq!(get_idle_settings(&storage).unwrap(), settings);
+
+        let invalid = IdleSettings {
+            idle_after_mins: 0,
+            ..settings
+        };
+        assert!(persist_idle_settings(&storage, &invalid).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Accepting channels opened to us by peers other than our LSP.
+//!
+//! Without a policy these requests are left alone, like before. Once the user
+//! turns it on the policy accepts or rejects them and each one is recorded.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const INBOUND_CHANNEL_POLICY_KEY: &str = "inbound_channel_policy";
+pub(crate) const INBOUND_CHANNEL_REQUEST_PREFIX: &str = "inbound_channel_request/";
+
+/// Which inbound channel requests we accept.
+/// Requests from our LSP are always handled separately.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InboundChannelPolicy {
+    /// Accept channels from peers other than our LSP
+    pub enabled: bool,
+    /// Only accept channels from these peers, anyone when empty
+    #[serde(default)]
+    pub allowed_peers: Vec<PublicKey>,
+    /// The least the opener has to put into the channel
+    #[serde(default)]
+    pub min_funding_sats: u64,
+}
+
+/// What we did with an inbound channel request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChannelRequestDecision {
+    Accepted,
+    Rejected { reason: String },
+}
+
+/// A channel a peer asked to open with us, kept so the user can see who tried
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InboundChannelRequest {
+    /// The temporary channel id of the request
+    pub id: String,
+    pub counterparty: PublicKey,
+    pub funding_sats: u64,
+    pub decision: ChannelRequestDecision,
+    /// Epoch time in seconds
+    pub timestamp: u64,
+}
+
+impl InboundChannelRequest {
+    pub(crate) fn new(
+        id: String,
+        counterparty: PublicKey,
+        funding_sats: u64,
+        decision: ChannelRequestDecision,
+    ) -> Self {
+        Self {
+            id,
+            counterparty,
+            funding_sats,
+            decision,
+            timestamp: utils::now().as_secs(),
+        }
+    }
+}
+
+impl InboundChannelPolicy {
+    /// Decides on a request to open a channel funded with `funding_sats` by the counterparty
+    pub fn evaluate(&self, counterparty: &PublicKey, funding_sats: u64) -> ChannelRequestDecision {
+        let reject = |reason: &str| ChannelRequestDecision::Rejected {
+            reason: reason.to_string(),
+        };
+        if !self.enabled {
+            return reject("not accepting channels");
+        }
+        if !self.allowed_peers.is_empty() && !self.allowed_peers.contains(counterparty) {
+            return reject("peer not allowed");
+        }
+        if funding_sats < self.min_funding_sats {
+            return reject("funding too small");
+        }
+
+        ChannelRequestDecision::Accepted
+    }
+}
+
+pub(crate) fn get_inbound_channel_policy<S: MutinyStorage>(
+    storage: &S,
+) -> Result<InboundChannelPolicy, MutinyError> {
+    Ok(storage
+        .get_data(INBOUND_CHANNEL_POLICY_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn persist_inbound_channel_policy<S: MutinyStorage>(
+    storage: &S,
+    policy: &InboundChannelPolicy,
+) -> Result<(), MutinyError> {
+    storage.write_data(INBOUND_CHANNEL_POLICY_KEY.to_string(), policy, None)
+}
+
+fn inbound_channel_request_key(id: &str) -> String {
+    format!("{INBOUND_CHANNEL_REQUEST_PREFIX}{id}")
+}
+
+pub(crate) fn persist_inbound_channel_request<S: MutinyStorage>(
+    storage: &S,
+    request: &InboundChannelRequest,
+) -> Result<(), MutinyError> {
+    storage.write_data(inbound_channel_request_key(&request.id), request, None)
+}
+
+/// Lists the inbound channel requests we've seen, newest first
+pub(crate) fn list_inbound_channel_requests<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<InboundChannelRequest>, MutinyError> {
+    let mut requests: Vec<InboundChannelRequest> = storage
+        .scan::<InboundChannelRequest>(INBOUND_CHANNEL_REQUEST_PREFIX, None)?
+        .into_values()
+        .collect();
+    requests.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const PEER: &str = "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54";
+    const OTHER_PEER: &str = "0218845781f631c48f1c9709e23092067d06837f30aa0cd0544ac887fe91ddd166";
+
+    #[test]
+    fn test_inbound_channel_policy() {
+        let test_name = "test_inbound_channel_policy";
+        log!("{}", test_name);
+
+        let peer = PublicKey::from_str(PEER).unwrap();
+        let other = PublicKey::from_str(OTHER_PEER).unwrap();
+
+        // off by default
+        assert!(matches!(
+            InboundChannelPolicy::default().evaluate(&peer, 1_000_000),
+            ChannelRequestDecision::Rejected { .. }
+        ));
+
+        let policy = InboundChannelPolicy {
+            enabled: true,
+            allowed_peers: vec![peer],
+            min_funding_sats: 100_000,
+        };
+        assert!(matches!(
+            policy.evaluate(&other, 1_000_000),
+            ChannelRequestDecision::Rejected { .. }
+        ));
+        assert!(matches!(
+            policy.evaluate(&peer, 10_000),
+            ChannelRequestDecision::Rejected { .. }
+        ));
+        assert_eq!(
+            policy.evaluate(&peer, 400_000),
+            ChannelRequestDecision::Accepted
+        );
+
+        // anyone when no peers are listed
+        let open = InboundChannelPolicy {
+            allowed_peers: vec![],
+            ..policy
+        };
+        assert_eq!(
+            open.evaluate(&other, 400_000),
+            ChannelRequestDecision::Accepted
+        );
+    }
+
+    #[test]
+    fn test_inbound_channel_request_storage() {
+        let test_name = "test_inbound_channel_request_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_inbound_channel_policy(&storage).unwrap(),
+            InboundChannelPolicy::default()
+        );
+
+        let request = InboundChannelRequest::new(
+            "temp".to_string(),
+            PublicKey::from_str(PEER).unwrap(),
+            500_000,
+            ChannelRequestDecision::Accepted,
+        );
+        persist_inbound_channel_request(&storage, &request).unwrap();
+        assert_eq!(
+            list_inbound_channel_requests(&storage).unwrap(),
+            vec![request]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::error::MutinyError;
+use crate::nodemanager::NodeManager;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::Address;
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_info};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use uuid::Uuid;
+
+pub(crate) const JOB_PREFIX: &str = "job/";
+
+/// How far a sweep is once its destination has been checked
+const SWEEP_VALIDATED_PROGRESS: u8 = 25;
+
+/// Held while a job is claimed, so it can't be claimed twice
+static CLAIM_LOCK: Mutex<()> = Mutex::new(());
+
+/// Identifies this run of the wallet, jobs are claimed with it so the ones
+/// running here aren't mistaken for ones interrupted by a restart
+fn runner_id() -> &'static str {
+    static RUNNER_ID: OnceLock<String> = OnceLock::new();
+    RUNNER_ID.get_or_init(|| Uuid::new_v4().to_string())
+}
+
+/// A long running operation that is persisted so it survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    /// Progress of the job from 0 to 100
+    pub progress: u8,
+    /// Result of the job once completed, e.g. a txid
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Set when the user asked to cancel the job, it will stop at the next step
+    #[serde(default)]
+    pub cancel_requested: bool,
+    /// The run of the wallet that claimed the job, set once it's running
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JobKind {
+    /// Sweep the whole on-chain wallet to the destination address
+    Sweep {
+        destination: String,
+        fee_rate: Option<u64>,
+    },
+    /// Rescan the on-chain wallet, the result is the on-chain balance afterwards
+    Rescan {
+        start_height: u32,
+        gap_limit: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+impl Job {
+    pub(crate) fn new(kind: JobKind) -> Self {
+        let now = utils::now().as_secs();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            status: JobStatus::Pending,
+            progress: 0,
+            result: None,
+            error: None,
+            cancel_requested: false,
+            owner: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+fn job_key(id: &str) -> String {
+    format!("{JOB_PREFIX}{id}")
+}
+
+pub(crate) fn persist_job<S: MutinyStorage>(storage: &S, job: &Job) -> Result<(), MutinyError> {
+    storage.write_data(job_key(&job.id), job, None)
+}
+
+pub(crate) fn get_job<S: MutinyStorage>(storage: &S, id: &str) -> Result<Option<Job>, MutinyError> {
+    storage.get_data(job_key(id))
+}
+
+/// Lists all jobs, newest first
+pub(crate) fn list_jobs<S: MutinyStorage>(storage: &S) -> Result<Vec<Job>, MutinyError> {
+    let mut jobs: Vec<Job> = storage
+        .scan::<Job>(JOB_PREFIX, None)?
+        .into_values()
+        .collect();
+    jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+
+    Ok(jobs)
+}
+
+/// Updates the stored job, returns the latest version of it
+fn update_job<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+    f: impl FnOnce(&mut Job),
+) -> Result<Job, MutinyError> {
+    let mut job = get_job(storage, id)?.ok_or(MutinyError::NotFound)?;
+    f(&mut job);
+    job.updated_at = utils::now().as_secs();
+    persist_job(storage, &job)?;
+
+    Ok(job)
+}
+
+/// Requests a job to be cancelled. Pending jobs are cancelled immediately,
+/// running jobs are cancelled before their next step.
+pub(crate) fn cancel_job<S: MutinyStorage>(storage: &S, id: &str) -> Result<Job, MutinyError> {
+    update_job(storage, id, |job| {
+        if job.status.is_finished() {
+            return;
+        }
+        job.cancel_requested = true;
+        if job.status == JobStatus::Pending {
+            job.status = JobStatus::Cancelled;
+        }
+    })
+}
+
+/// Marks a pending job as running here. Returns None if it was already
+/// claimed, has finished or was cancelled.
+pub(crate) fn claim_job<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<Job>, MutinyError> {
+    let _lock = CLAIM_LOCK.lock().unwrap();
+    let job = get_job(storage, id)?.ok_or(MutinyError::NotFound)?;
+    if job.status != JobStatus::Pending {
+        return Ok(None);
+    }
+
+    let job = update_job(storage, id, |job| {
+        job.status = JobStatus::Running;
+        job.owner = Some(runner_id().to_string());
+    })?;
+    Ok(Some(job))
+}
+
+/// Claims the job and runs it in the background, returns the claimed job or
+/// None if it was already claimed
+pub(crate) fn start_job<S: MutinyStorage>(
+    node_manager: Arc<NodeManager<S>>,
+    id: &str,
+) -> Result<Option<Job>, MutinyError> {
+    let Some(job) = claim_job(&node_manager.storage, id)? else {
+        return Ok(None);
+    };
+
+    let claimed = job.clone();
+    utils::spawn(async move {
+        let id = job.id.clone();
+        if let Err(e) = run_job(node_manager.clone(), job).await {
+            log_error!(node_manager.logger, "Failed to run job {id}: {e}");
+        }
+    });
+
+    Ok(Some(claimed))
+}
+
+/// Stops the job if the user asked to cancel it, returns whether it did
+fn cancel_if_requested<S: MutinyStorage>(storage: &S, id: &str) -> Result<bool, MutinyError> {
+    let job = get_job(storage, id)?.ok_or(MutinyError::NotFound)?;
+    if !job.cancel_requested {
+        return Ok(false);
+    }
+    update_job(storage, id, |job| job.status = JobStatus::Cancelled)?;
+    Ok(true)
+}
+
+/// Runs a claimed job to completion, recording its progress in storage.
+async fn run_job<S: MutinyStorage>(
+    node_manager: Arc<NodeManager<S>>,
+    job: Job,
+) -> Result<(), MutinyError> {
+    let storage = &node_manager.storage;
+    let logger = &node_manager.logger;
+    let id = job.id;
+
+    log_info!(logger, "Running job {id}");
+
+    let res = match job.kind {
+        JobKind::Sweep {
+            destination,
+            fee_rate,
+        } => {
+            match Address::from_str(&destination)
+                .map_err(|_| MutinyError::InvalidArgumentsError)
+                .and_then(|a| {
+                    a.require_network(node_manager.wallet.network)
+                        .map_err(|_| MutinyError::IncorrectNetwork)
+                }) {
+                Ok(address) => {
+                    update_job(storage, &id, |job| job.progress = SWEEP_VALIDATED_PROGRESS)?;
+                    if cancel_if_requested(storage, &id)? {
+                        return Ok(());
+                    }
+                    node_manager
+                        .sweep_wallet(address, vec![], fee_rate, None)
+                        .await
+                        .map(|txid| txid.to_string())
+                }
+                Err(e) => Err(e),
+            }
+        }
+        JobKind::Rescan {
+            start_height,
+            gap_limit,
+        } => {
+            let storage = storage.clone();
+            let job_id = id.clone();
+            let progress: Arc<dyn Fn(u8) + Send + Sync> = Arc::new(move |progress| {
+                let _ = update_job(&storage, &job_id, |job| job.progress = progress);
+            });
+            match node_manager
+                .rescan_onchain(start_height, gap_limit, Some(progress))
+                .await
+            {
+                Ok(()) => node_manager.get_wallet_balance().map(|b| b.to_string()),
+                Err(e) => Err(e),
+            }
+        }
+    };
+
+    match res {
+        Ok(result) => {
+            log_info!(logger, "Job {id} completed");
+            update_job(storage, &id, |job| {
+                job.status = JobStatus::Completed;
+                job.progress = 100;
+                job.result = Some(result);
+            })?;
+        }
+        Err(e) => {
+            log_error!(logger, "Job {id} failed: {e}");
+            update_job(storage, &id, |job| {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Restarts any jobs that had not started yet, e.g. because the page was reloaded.
+/// Jobs that were interrupted while running are marked as failed since we cannot
+/// know how far they got, the user can check their activity and submit them again.
+/// Jobs already running here, e.g. when the wallet is resumed, are left alone.
+pub(crate) fn resume_jobs<S: MutinyStorage>(node_manager: Arc<NodeManager<S>>) {
+    let jobs = match list_jobs(&node_manager.storage) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log_error!(node_manager.logger, "Failed to list jobs: {e}");
+            return;
+        }
+    };
+
+    for job in jobs.into_iter().filter(|j| !j.status.is_finished()) {
+        if job.status == JobStatus::Running {
+            if job.owner.as_deref() == Some(runner_id()) {
+                continue;
+            }
+            let res = update_job(&node_manager.storage, &job.id, |job| {
+                job.status = JobStatus::Failed;
+                job.error = Some("Interrupted before finishing".to_string());
+            });
+            if let Err(e) = res {
+                log_error!(node_manager.logger, "Failed to update job {}: {e}", job.id);
+            }
+            continue;
+        }
+
+        if let Err(e) = start_job(node_manager.clone(), &job.id) {
+            log_error!(node_manager.logger, "Failed to resume job {}: {e}", job.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_job_storage_and_cancel() {
+        let test_name = "test_job_storage_and_cancel";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let job = Job::new(JobKind::Sweep {
+            destination: "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh".to_string(),
+            fee_rate: None,
+        });
+        persist_job(&storage, &job).unwrap();
+
+        assert_eq!(list_jobs(&storage).unwrap(), vec![job.clone()]);
+
+        let cancelled = cancel_job(&storage, &job.id).unwrap();
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+        assert!(cancelled.cancel_requested);
+
+        // cancelling a finished job does nothing
+        let again = cancel_job(&storage, &job.id).unwrap();
+        assert_eq!(again.status, JobStatus::Cancelled);
+
+        assert!(cancel_job(&storage, "unknown").is_err());
+    }
+
+    #[test]
+    fn test_claim_job_once() {
+        let test_name = "test_claim_job_once";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let job = Job::new(JobKind::Rescan {
+            start_height: 0,
+            gap_limit: None,
+        });
+        persist_job(&storage, &job).unwrap();
+
+        let claimed = claim_job(&storage, &job.id).unwrap().unwrap();
+        assert_eq!(claimed.status, JobStatus::Running);
+        assert_eq!(claimed.owner.as_deref(), Some(runner_id()));
+
+        // a job that's already running isn't claimed again
+        assert!(claim_job(&storage, &job.id).unwrap().is_none());
+
+        let cancelled = Job::new(JobKind::Rescan {
+            start_height: 0,
+            gap_limit: None,
+        });
+        persist_job(&storage, &cancelled).unwrap();
+        cancel_job(&storage, &cancelled.id).unwrap();
+        assert!(claim_job(&storage, &cancelled.id).unwrap().is_none());
+    }
+}
@@ -9,6 +9,12 @@ pub(crate) enum ChildKey {
     Node,
     // Federation,
     // BlindAuth,
+    Nostr,
+    SwapRefund,
+    SettingsSync,
+    SocialRecovery,
+    ChannelBackup,
+    Watchtower,
 }
 
 impl ChildKey {
@@ -17,6 +23,12 @@ impl ChildKey {
             ChildKey::Node => 0,
             // ChildKey::Federation => 1,
             // ChildKey::BlindAuth => 2,
+            ChildKey::Nostr => 3,
+            ChildKey::SwapRefund => 4,
+            ChildKey::SettingsSync => 5,
+            ChildKey::SocialRecovery => 6,
+            ChildKey::ChannelBackup => 7,
+            ChildKey::Watchtower => 8,
         }
     }
 }
@@ -1,5 +1,6 @@
 use crate::logging::MutinyLogger;
 use crate::onchain::OnChainWallet;
+use crate::scb::record_channel_keys_id;
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, key::create_root_child_key};
 use crate::{key::ChildKey, labels::LabelStorage};
@@ -165,8 +166,14 @@ impl<S: MutinyStorage> SignerProvider for PhantomKeysManager<S> {
         channel_value_satoshis: u64,
         user_channel_id: u128,
     ) -> [u8; 32] {
-        self.inner
-            .generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id)
+        let keys_id =
+            self.inner
+                .generate_channel_keys_id(inbound, channel_value_satoshis, user_channel_id);
+        // static channel backups need it to sweep our funds after a force close
+        if let Err(e) = record_channel_keys_id(&self.wallet.storage, user_channel_id, &keys_id) {
+            log_warn!(self.logger, "Failed to record channel keys id: {e}");
+        }
+        keys_id
     }
 
     fn derive_channel_signer(
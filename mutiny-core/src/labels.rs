@@ -1,7 +1,8 @@
 use crate::error::MutinyError;
 use crate::nodemanager::NodeManager;
-use crate::storage::MutinyStorage;
-use bitcoin::Address;
+use crate::storage::{MutinyStorage, StorageTransaction};
+use crate::vss::VssKeyValueItem;
+use bitcoin::{Address, Txid};
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -9,10 +10,15 @@ use std::collections::{HashMap, HashSet};
 
 use uuid::Uuid;
 
-const ADDRESS_LABELS_MAP_KEY: &str = "address_labels";
-const INVOICE_LABELS_MAP_KEY: &str = "invoice_labels";
-const LABEL_PREFIX: &str = "label/";
-const CONTACT_PREFIX: &str = "contact/";
+pub(crate) const ADDRESS_LABELS_MAP_KEY: &str = "address_labels";
+pub(crate) const INVOICE_LABELS_MAP_KEY: &str = "invoice_labels";
+pub(crate) const LABEL_PREFIX: &str = "label/";
+pub(crate) const CONTACT_PREFIX: &str = "contact/";
+pub(crate) const LABEL_LINEAGE_PREFIX: &str = "label_lineage/";
+pub(crate) const LABEL_HISTORY_PREFIX: &str = "label_history/";
+/// Version of the last bulk label change, the label keys it wrote are synced
+/// to VSS with it
+pub const LABELS_VERSION_KEY: &str = "labels_version";
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default)]
 pub struct LabelItem {
@@ -46,6 +52,28 @@ pub(crate) fn get_contact_key(label: impl AsRef<str>) -> String {
     format!("{}{}", CONTACT_PREFIX, label.as_ref())
 }
 
+fn get_label_history_key(label: impl AsRef<str>) -> String {
+    format!("{}{}", LABEL_HISTORY_PREFIX, label.as_ref())
+}
+
+/// Whether the key holds labels that bulk label changes sync through VSS,
+/// restores compare their version against [`LABELS_VERSION_KEY`]
+pub fn is_synced_label_key(key: &str) -> bool {
+    key == ADDRESS_LABELS_MAP_KEY
+        || key == INVOICE_LABELS_MAP_KEY
+        || key == LABELS_VERSION_KEY
+        || key.starts_with(LABEL_PREFIX)
+        || key.starts_with(LABEL_HISTORY_PREFIX)
+}
+
+/// A label that was renamed or merged into another
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LabelRename {
+    pub from: String,
+    pub to: String,
+    pub time: u64,
+}
+
 pub trait LabelStorage {
     /// Get a map of addresses to labels. This can be used to get all the labels for an address
     fn get_address_labels(&self) -> Result<HashMap<String, Vec<String>>, MutinyError>;
@@ -85,6 +113,31 @@ pub trait LabelStorage {
     fn edit_contact(&self, id: impl AsRef<str>, contact: Contact) -> Result<(), MutinyError>;
     /// Gets all the existing tags (labels and contacts)
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError>;
+    /// Moves everything labeled `from` over to `into` and removes `from`.
+    /// Contacts can be merged into, but not away.
+    fn merge_labels(&self, from: &str, into: &str) -> Result<(), MutinyError>;
+    /// Renames a label that isn't a contact, the old name is kept in its history.
+    /// Use `merge_labels` if the new name is already in use.
+    fn rename_label(&self, label: &str, new_label: &str) -> Result<(), MutinyError>;
+    /// The renames and merges that led to the label, oldest first
+    fn get_label_history(&self, label: &str) -> Result<Vec<LabelRename>, MutinyError>;
+    /// Adds the label to each of the addresses and invoices, keeping their other labels
+    fn apply_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError>;
+    /// Removes the label from each of the addresses and invoices
+    fn remove_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError>;
+    /// Deletes a label that isn't a contact from everything it is on,
+    /// or moves it all to `reassign_to` instead
+    fn delete_label(&self, label: &str, reassign_to: Option<String>) -> Result<(), MutinyError>;
 }
 
 impl<S: MutinyStorage> LabelStorage for S {
@@ -117,48 +170,9 @@ impl<S: MutinyStorage> LabelStorage for S {
     }
 
     fn set_address_labels(&self, address: Address, labels: Vec<String>) -> Result<(), MutinyError> {
-        // update the labels map
-        let mut address_labels = self.get_address_labels()?;
-        address_labels.insert(address.to_string(), labels.clone());
-        self.write_data(ADDRESS_LABELS_MAP_KEY.to_string(), address_labels, None)?;
-
-        // update the label items
-        let now = crate::utils::now().as_secs();
-        for label in labels {
-            let key = get_label_item_key(&label);
-            match self.get_label(&label)? {
-                Some(mut label_item) => {
-                    // Add the address to the label item
-                    // and sort so we can dedup the addresses
-                    label_item.addresses.insert(address.to_string());
-
-                    // Update the last used timestamp
-                    label_item.last_used_time = now;
-
-                    // if it is a contact, update last used
-                    if let Some(contact) = self.get_contact(&label)? {
-                        let mut contact = contact;
-                        contact.last_used = now;
-                        self.edit_contact(&label, contact)?;
-                    }
-
-                    self.write_data(key, label_item, None)?;
-                }
-                None => {
-                    let mut addresses = HashSet::with_capacity(1);
-                    addresses.insert(address.to_string());
-                    // Create a new label item
-                    let label_item = LabelItem {
-                        addresses,
-                        invoices: HashSet::new(),
-                        last_used_time: now,
-                    };
-                    self.write_data(key, label_item, None)?;
-                }
-            }
-        }
-
-        Ok(())
+        let mut tx = StorageTransaction::new();
+        stage_address_labels(self, &mut tx, address, labels)?;
+        self.commit_transaction(tx)
     }
 
     fn set_invoice_labels(
@@ -166,47 +180,9 @@ impl<S: MutinyStorage> LabelStorage for S {
         invoice: Bolt11Invoice,
         labels: Vec<String>,
     ) -> Result<(), MutinyError> {
-        // update the labels map
-        let mut invoice_labels = self.get_invoice_labels()?;
-        invoice_labels.insert(invoice.clone(), labels.clone());
-        self.write_data(INVOICE_LABELS_MAP_KEY.to_string(), invoice_labels, None)?;
-
-        // update the label items
-        let now = crate::utils::now().as_secs();
-        for label in labels {
-            let key = get_label_item_key(&label);
-            match self.get_label(&label)? {
-                Some(mut label_item) => {
-                    // Add the invoice to the label item
-                    // and sort so we can dedup the invoices
-                    label_item.invoices.insert(invoice.clone());
-
-                    // Update the last used timestamp
-                    label_item.last_used_time = now;
-
-                    // if it is a contact, update last used
-                    if let Some(contact) = self.get_contact(&label)? {
-                        let mut contact = contact;
-                        contact.last_used = now;
-                        self.edit_contact(&label, contact)?;
-                    }
-
-                    self.write_data(key, label_item, None)?;
-                }
-                None => {
-                    // Create a new label item
-                    let invoices = HashSet::from_iter(vec![invoice.clone()]);
-                    let label_item = LabelItem {
-                        addresses: HashSet::new(),
-                        invoices,
-                        last_used_time: now,
-                    };
-                    self.write_data(key, label_item, None)?;
-                }
-            }
-        }
-
-        Ok(())
+        let mut tx = StorageTransaction::new();
+        stage_invoice_labels(self, &mut tx, invoice, labels)?;
+        self.commit_transaction(tx)
     }
 
     fn get_contacts(&self) -> Result<HashMap<String, Contact>, MutinyError> {
@@ -309,23 +285,14 @@ impl<S: MutinyStorage> LabelStorage for S {
         for value in addr_labels.values_mut() {
             value.retain(|s| *s != id.as_ref());
         }
-        let to_set: Vec<(String, Value)> = vec![
-            (
-                ADDRESS_LABELS_MAP_KEY.to_string(),
-                serde_json::to_value(addr_labels)?,
-            ),
-            (
-                INVOICE_LABELS_MAP_KEY.to_string(),
-                serde_json::to_value(inv_labels)?,
-            ),
-        ];
-        self.write_raw(to_set)?;
+        let mut tx = StorageTransaction::new();
+        tx.write(ADDRESS_LABELS_MAP_KEY, addr_labels, None)?;
+        tx.write(INVOICE_LABELS_MAP_KEY, inv_labels, None)?;
 
         // then delete actual label
-        let contact_key = get_contact_key(&id);
-        let label_item_key = get_label_item_key(&id);
-        self.delete(&[contact_key, label_item_key])?;
-        Ok(())
+        tx.delete(get_contact_key(&id));
+        tx.delete(get_label_item_key(&id));
+        self.commit_transaction(tx)
     }
 
     fn edit_contact(&self, id: impl AsRef<str>, contact: Contact) -> Result<(), MutinyError> {
@@ -359,6 +326,510 @@ impl<S: MutinyStorage> LabelStorage for S {
 
         Ok(tag_items)
     }
+
+    fn merge_labels(&self, from: &str, into: &str) -> Result<(), MutinyError> {
+        if from == into {
+            return Ok(());
+        }
+        if self.get_contact(from)?.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if self.get_label(from)?.is_none() {
+            return Err(MutinyError::NotFound);
+        }
+
+        let mut tx = StorageTransaction::new();
+        let version = next_labels_version(self)?;
+        let deleted = stage_merge_label(self, &mut tx, version, from, into)?;
+        commit_label_changes(self, tx, version, deleted)
+    }
+
+    fn rename_label(&self, label: &str, new_label: &str) -> Result<(), MutinyError> {
+        if new_label.trim().is_empty() || self.get_label(new_label)?.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        self.merge_labels(label, new_label)
+    }
+
+    fn get_label_history(&self, label: &str) -> Result<Vec<LabelRename>, MutinyError> {
+        Ok(self
+            .get_data(get_label_history_key(label))?
+            .unwrap_or_default())
+    }
+
+    fn apply_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError> {
+        if label.trim().is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let selection = LabelSelection::Items {
+            addresses: addresses.into_iter().collect(),
+            invoices: invoices.into_iter().collect(),
+        };
+
+        let mut tx = StorageTransaction::new();
+        let version = next_labels_version(self)?;
+        stage_relabel(self, &mut tx, version, &selection, |labels| {
+            labels.push(label.to_string())
+        })?;
+        commit_label_changes(self, tx, version, vec![])
+    }
+
+    fn remove_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError> {
+        let selection = LabelSelection::Items {
+            addresses: addresses.into_iter().collect(),
+            invoices: invoices.into_iter().collect(),
+        };
+
+        let mut tx = StorageTransaction::new();
+        let version = next_labels_version(self)?;
+        stage_relabel(self, &mut tx, version, &selection, |labels| {
+            labels.retain(|l| l != label)
+        })?;
+        commit_label_changes(self, tx, version, vec![])
+    }
+
+    fn delete_label(&self, label: &str, reassign_to: Option<String>) -> Result<(), MutinyError> {
+        if let Some(into) = reassign_to {
+            return self.merge_labels(label, &into);
+        }
+        if self.get_contact(label)?.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let mut tx = StorageTransaction::new();
+        let version = next_labels_version(self)?;
+        stage_relabel(self, &mut tx, version, &LabelSelection::All, |labels| {
+            labels.retain(|l| l != label)
+        })?;
+        let deleted = vec![get_label_item_key(label), get_label_history_key(label)];
+        commit_label_changes(self, tx, version, deleted)
+    }
+}
+
+/// Reads a value from the transaction if it was already changed there, otherwise from storage
+fn get_staged<S: MutinyStorage, T>(
+    storage: &S,
+    tx: &StorageTransaction,
+    key: &str,
+) -> Result<Option<T>, MutinyError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    match tx.get(key)? {
+        Some(value) => Ok(Some(value)),
+        None => storage.get_data(key),
+    }
+}
+
+/// Stages the changes for labeling an address, so they can be committed
+/// together with the rest of a payment
+pub(crate) fn stage_address_labels<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    address: Address,
+    labels: Vec<String>,
+) -> Result<(), MutinyError> {
+    // update the labels map
+    let mut address_labels: HashMap<String, Vec<String>> =
+        get_staged(storage, tx, ADDRESS_LABELS_MAP_KEY)?.unwrap_or_default();
+    address_labels.insert(address.to_string(), labels.clone());
+    tx.write(ADDRESS_LABELS_MAP_KEY, address_labels, None)?;
+
+    // update the label items
+    let now = crate::utils::now().as_secs();
+    for label in labels {
+        let key = get_label_item_key(&label);
+        match get_staged::<S, LabelItem>(storage, tx, &key)? {
+            Some(mut label_item) => {
+                // Add the address to the label item
+                // and sort so we can dedup the addresses
+                label_item.addresses.insert(address.to_string());
+
+                // Update the last used timestamp
+                label_item.last_used_time = now;
+
+                // if it is a contact, update last used
+                stage_contact_last_used(storage, tx, &label, now)?;
+
+                tx.write(key, label_item, None)?;
+            }
+            None => {
+                let mut addresses = HashSet::with_capacity(1);
+                addresses.insert(address.to_string());
+                // Create a new label item
+                let label_item = LabelItem {
+                    addresses,
+                    invoices: HashSet::new(),
+                    last_used_time: now,
+                };
+                tx.write(key, label_item, None)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Stages the changes for labeling an invoice, so they can be committed
+/// together with the rest of a payment
+pub(crate) fn stage_invoice_labels<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    invoice: Bolt11Invoice,
+    labels: Vec<String>,
+) -> Result<(), MutinyError> {
+    // update the labels map
+    let mut invoice_labels: HashMap<Bolt11Invoice, Vec<String>> =
+        get_staged(storage, tx, INVOICE_LABELS_MAP_KEY)?.unwrap_or_default();
+    invoice_labels.insert(invoice.clone(), labels.clone());
+    tx.write(INVOICE_LABELS_MAP_KEY, invoice_labels, None)?;
+
+    // update the label items
+    let now = crate::utils::now().as_secs();
+    for label in labels {
+        let key = get_label_item_key(&label);
+        match get_staged::<S, LabelItem>(storage, tx, &key)? {
+            Some(mut label_item) => {
+                // Add the invoice to the label item
+                // and sort so we can dedup the invoices
+                label_item.invoices.insert(invoice.clone());
+
+                // Update the last used timestamp
+                label_item.last_used_time = now;
+
+                // if it is a contact, update last used
+                stage_contact_last_used(storage, tx, &label, now)?;
+
+                tx.write(key, label_item, None)?;
+            }
+            None => {
+                // Create a new label item
+                let invoices = HashSet::from_iter(vec![invoice.clone()]);
+                let label_item = LabelItem {
+                    addresses: HashSet::new(),
+                    invoices,
+                    last_used_time: now,
+                };
+                tx.write(key, label_item, None)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn stage_contact_last_used<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    label: &str,
+    now: u64,
+) -> Result<(), MutinyError> {
+    let key = get_contact_key(label);
+    if let Some(mut contact) = get_staged::<S, Contact>(storage, tx, &key)? {
+        contact.last_used = now;
+        tx.write(key, contact, None)?;
+    }
+    Ok(())
+}
+
+/// The items a bulk label change rewrites
+enum LabelSelection {
+    All,
+    Items {
+        addresses: HashSet<String>,
+        invoices: HashSet<Bolt11Invoice>,
+    },
+}
+
+/// A version for a bulk label change, newer than the last one even if
+/// two happen within the same second
+fn next_labels_version<S: MutinyStorage>(storage: &S) -> Result<u32, MutinyError> {
+    let last: u32 = storage.get_data(LABELS_VERSION_KEY)?.unwrap_or_default();
+    let now = crate::utils::now().as_secs() as u32;
+    Ok(now.max(last + 1))
+}
+
+/// Commits a bulk label change along with its version and deletes. VSS can't
+/// delete keys, so deleted ones are overwritten there with null which restores skip.
+fn commit_label_changes<S: MutinyStorage>(
+    storage: &S,
+    mut tx: StorageTransaction,
+    version: u32,
+    deleted: Vec<String>,
+) -> Result<(), MutinyError> {
+    tx.write(LABELS_VERSION_KEY, version, Some(version))?;
+    for key in deleted.iter() {
+        tx.delete(key.as_str());
+    }
+    storage.commit_transaction(tx)?;
+
+    if let Some(vss) = storage.vss_client() {
+        if !deleted.is_empty() {
+            let items = deleted
+                .into_iter()
+                .map(|key| VssKeyValueItem {
+                    key,
+                    value: Value::Null,
+                    version,
+                })
+                .collect();
+            storage.spawn_vss_write(vss, items);
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the label changes of one item to the label items, returns the
+/// item's labels in order without duplicates
+fn stage_label_item_changes<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    version: u32,
+    before: &[String],
+    mut after: Vec<String>,
+    mut update: impl FnMut(&mut LabelItem, bool),
+) -> Result<Vec<String>, MutinyError> {
+    let mut seen = HashSet::new();
+    after.retain(|l| seen.insert(l.clone()));
+
+    let now = crate::utils::now().as_secs();
+    let added = after.iter().filter(|l| !before.contains(l));
+    let removed = before.iter().filter(|l| !after.contains(l));
+    for (label, add) in added.map(|l| (l, true)).chain(removed.map(|l| (l, false))) {
+        let key = get_label_item_key(label);
+        let mut label_item = get_staged::<S, LabelItem>(storage, tx, &key)?.unwrap_or_default();
+        update(&mut label_item, add);
+        if add {
+            label_item.last_used_time = now;
+        }
+        tx.write(key, label_item, Some(version))?;
+    }
+
+    Ok(after)
+}
+
+/// Rewrites the labels of the selected addresses and invoices with `change`,
+/// keeping the label items in sync. Everything is written with the version
+/// so it is synced through VSS.
+fn stage_relabel<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    version: u32,
+    selection: &LabelSelection,
+    change: impl Fn(&mut Vec<String>),
+) -> Result<(), MutinyError> {
+    let mut address_labels: HashMap<String, Vec<String>> =
+        get_staged(storage, tx, ADDRESS_LABELS_MAP_KEY)?.unwrap_or_default();
+    let addresses: Vec<String> = match selection {
+        LabelSelection::All => address_labels.keys().cloned().collect(),
+        LabelSelection::Items { addresses, .. } => addresses.iter().cloned().collect(),
+    };
+    for address in addresses {
+        let before = address_labels.get(&address).cloned().unwrap_or_default();
+        let mut after = before.clone();
+        change(&mut after);
+        let after = stage_label_item_changes(storage, tx, version, &before, after, |item, add| {
+            if add {
+                item.addresses.insert(address.clone());
+            } else {
+                item.addresses.remove(&address);
+            }
+        })?;
+        if after != before {
+            address_labels.insert(address, after);
+        }
+    }
+    tx.write(ADDRESS_LABELS_MAP_KEY, address_labels, Some(version))?;
+
+    let mut invoice_labels: HashMap<Bolt11Invoice, Vec<String>> =
+        get_staged(storage, tx, INVOICE_LABELS_MAP_KEY)?.unwrap_or_default();
+    let invoices: Vec<Bolt11Invoice> = match selection {
+        LabelSelection::All => invoice_labels.keys().cloned().collect(),
+        LabelSelection::Items { invoices, .. } => invoices.iter().cloned().collect(),
+    };
+    for invoice in invoices {
+        let before = invoice_labels.get(&invoice).cloned().unwrap_or_default();
+        let mut after = before.clone();
+        change(&mut after);
+        let after = stage_label_item_changes(storage, tx, version, &before, after, |item, add| {
+            if add {
+                item.invoices.insert(invoice.clone());
+            } else {
+                item.invoices.remove(&invoice);
+            }
+        })?;
+        if after != before {
+            invoice_labels.insert(invoice, after);
+        }
+    }
+    tx.write(INVOICE_LABELS_MAP_KEY, invoice_labels, Some(version))?;
+
+    Ok(())
+}
+
+/// Moves everything labeled `from` to `into` and records the rename in the
+/// history of `into`, along with the history `from` had. Returns the keys of
+/// `from` to delete.
+fn stage_merge_label<S: MutinyStorage>(
+    storage: &S,
+    tx: &mut StorageTransaction,
+    version: u32,
+    from: &str,
+    into: &str,
+) -> Result<Vec<String>, MutinyError> {
+    stage_relabel(storage, tx, version, &LabelSelection::All, |labels| {
+        for label in labels.iter_mut() {
+            if label == from {
+                *label = into.to_string();
+            }
+        }
+    })?;
+
+    // keep the label around even when nothing had it, like creating a contact does
+    let into_key = get_label_item_key(into);
+    let from_item = storage.get_label(from)?.unwrap_or_default();
+    let mut into_item = get_staged::<S, LabelItem>(storage, tx, &into_key)?.unwrap_or_default();
+    into_item.last_used_time = into_item.last_used_time.max(from_item.last_used_time);
+    tx.write(into_key, into_item, Some(version))?;
+
+    let mut history: Vec<LabelRename> =
+        get_staged(storage, tx, &get_label_history_key(into))?.unwrap_or_default();
+    history.extend(storage.get_label_history(from)?);
+    history.push(LabelRename {
+        from: from.to_string(),
+        to: into.to_string(),
+        time: crate::utils::now().as_secs(),
+    });
+    history.sort_by_key(|r| r.time);
+    tx.write(get_label_history_key(into), history, Some(version))?;
+
+    Ok(vec![get_label_item_key(from), get_label_history_key(from)])
+}
+
+/// Records that a change output was given the labels of the coins it was made from,
+/// so the user can review where a label on their change came from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LabelLineage {
+    /// The change address that inherited the labels
+    pub address: String,
+    pub txid: Txid,
+    /// The addresses of the coins that were spent
+    pub input_addresses: Vec<String>,
+    /// Labels the change got from the spent coins
+    pub inherited_labels: Vec<String>,
+    /// Set once the user has corrected the labels by hand
+    #[serde(default)]
+    pub corrected: bool,
+    pub created_at: u64,
+}
+
+fn label_lineage_key(address: &str) -> String {
+    format!("{LABEL_LINEAGE_PREFIX}{address}")
+}
+
+pub(crate) fn get_label_lineage<S: MutinyStorage>(
+    storage: &S,
+    address: &str,
+) -> Result<Option<LabelLineage>, MutinyError> {
+    storage.get_data(label_lineage_key(address))
+}
+
+pub(crate) fn persist_label_lineage<S: MutinyStorage>(
+    storage: &S,
+    lineage: &LabelLineage,
+) -> Result<(), MutinyError> {
+    storage.write_data(label_lineage_key(&lineage.address), lineage, None)
+}
+
+/// All recorded label lineage, newest first
+pub(crate) fn list_label_lineage<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<LabelLineage>, MutinyError> {
+    let mut lineage: Vec<LabelLineage> = storage
+        .scan::<LabelLineage>(LABEL_LINEAGE_PREFIX, None)?
+        .into_values()
+        .collect();
+    lineage.sort_by_key(|l| std::cmp::Reverse(l.created_at));
+
+    Ok(lineage)
+}
+
+/// A single BIP-329 label record, one line of an export
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bip329Label {
+    /// One of "tx", "addr", "pubkey", "input", "output" or "xpub"
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Only for outputs, false if the coin is frozen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
+impl Bip329Label {
+    pub(crate) fn new(kind: &str, reference: String, labels: &[String]) -> Self {
+        Self {
+            kind: kind.to_string(),
+            reference,
+            label: (!labels.is_empty()).then(|| labels.join(", ")),
+            origin: None,
+            spendable: None,
+        }
+    }
+
+    /// BIP-329 has a single label per record, ours are joined with commas
+    pub fn labels(&self) -> Vec<String> {
+        self.label
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect()
+    }
+}
+
+/// How many records of a BIP-329 import were applied
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bip329ImportSummary {
+    pub imported: usize,
+    /// Records of types we don't keep labels for, or that aren't in this wallet
+    pub skipped: usize,
+}
+
+/// Writes the records as JSON lines
+pub(crate) fn to_bip329_jsonl(records: &[Bip329Label]) -> Result<String, MutinyError> {
+    let lines = records
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Reads JSON lines of records, blank lines are ignored
+pub(crate) fn parse_bip329_jsonl(jsonl: &str) -> Result<Vec<Bip329Label>, MutinyError> {
+    jsonl
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|_| MutinyError::InvalidArgumentsError))
+        .collect()
 }
 
 impl<S: MutinyStorage> LabelStorage for NodeManager<S> {
@@ -421,6 +892,40 @@ impl<S: MutinyStorage> LabelStorage for NodeManager<S> {
     fn get_tag_items(&self) -> Result<Vec<TagItem>, MutinyError> {
         self.storage.get_tag_items()
     }
+
+    fn merge_labels(&self, from: &str, into: &str) -> Result<(), MutinyError> {
+        self.storage.merge_labels(from, into)
+    }
+
+    fn rename_label(&self, label: &str, new_label: &str) -> Result<(), MutinyError> {
+        self.storage.rename_label(label, new_label)
+    }
+
+    fn get_label_history(&self, label: &str) -> Result<Vec<LabelRename>, MutinyError> {
+        self.storage.get_label_history(label)
+    }
+
+    fn apply_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError> {
+        self.storage.apply_label(label, addresses, invoices)
+    }
+
+    fn remove_label(
+        &self,
+        label: &str,
+        addresses: Vec<String>,
+        invoices: Vec<Bolt11Invoice>,
+    ) -> Result<(), MutinyError> {
+        self.storage.remove_label(label, addresses, invoices)
+    }
+
+    fn delete_label(&self, label: &str, reassign_to: Option<String>) -> Result<(), MutinyError> {
+        self.storage.delete_label(label, reassign_to)
+    }
 }
 
 #[cfg(test)]
@@ -836,6 +1341,141 @@ mod tests {
         assert_eq!(stored_contact, Some(contact));
     }
 
+    #[test]
+    fn test_bulk_label_changes() {
+        let test_name = "test_bulk_label_changes";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let address = Address::from_str(ADDRESS).unwrap().assume_checked();
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+        storage
+            .set_address_labels(address.clone(), vec!["coffee".to_string()])
+            .unwrap();
+        storage
+            .set_invoice_labels(
+                invoice.clone(),
+                vec!["Coffee".to_string(), "food".to_string()],
+            )
+            .unwrap();
+
+        // merging moves the invoice over without duplicating labels
+        storage.merge_labels("Coffee", "coffee").unwrap();
+        assert!(storage.get_label("Coffee").unwrap().is_none());
+        let coffee = storage.get_label("coffee").unwrap().unwrap();
+        assert!(coffee.addresses.contains(ADDRESS));
+        assert!(coffee.invoices.contains(&invoice));
+        assert_eq!(
+            storage.get_invoice_labels().unwrap().get(&invoice),
+            Some(&vec!["coffee".to_string(), "food".to_string()])
+        );
+        let first_version: u32 = storage.get_data(LABELS_VERSION_KEY).unwrap().unwrap();
+
+        // renaming keeps the history, including the merge
+        assert!(storage.rename_label("coffee", "food").is_err());
+        storage.rename_label("coffee", "caffeine").unwrap();
+        let history = storage.get_label_history("caffeine").unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|r| (r.from.as_str(), r.to.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("Coffee", "coffee"), ("coffee", "caffeine")]
+        );
+        assert!(storage.get_label_history("coffee").unwrap().is_empty());
+        let version: u32 = storage.get_data(LABELS_VERSION_KEY).unwrap().unwrap();
+        assert!(version > first_version);
+
+        // applying and removing only touch the given items
+        storage
+            .apply_label("work", vec![ADDRESS.to_string()], vec![invoice.clone()])
+            .unwrap();
+        storage
+            .remove_label("food", vec![], vec![invoice.clone()])
+            .unwrap();
+        assert_eq!(
+            storage.get_address_labels().unwrap().get(ADDRESS),
+            Some(&vec!["caffeine".to_string(), "work".to_string()])
+        );
+        assert_eq!(
+            storage.get_invoice_labels().unwrap().get(&invoice),
+            Some(&vec!["caffeine".to_string(), "work".to_string()])
+        );
+        assert!(storage
+            .get_label("food")
+            .unwrap()
+            .unwrap()
+            .invoices
+            .is_empty());
+
+        // deleting with reassignment merges, without it the label is removed everywhere
+        storage
+            .delete_label("caffeine", Some("work".to_string()))
+            .unwrap();
+        assert!(storage.get_label("caffeine").unwrap().is_none());
+        assert_eq!(storage.get_label_history("work").unwrap().len(), 3);
+        storage.delete_label("work", None).unwrap();
+        assert!(storage.get_label("work").unwrap().is_none());
+        assert!(storage.get_label_history("work").unwrap().is_empty());
+        assert_eq!(
+            storage.get_address_labels().unwrap().get(ADDRESS),
+            Some(&vec![])
+        );
+
+        // contacts can't be merged away or deleted
+        let id = storage
+            .create_new_contact(create_test_contacts().into_values().next().unwrap())
+            .unwrap();
+        assert!(storage.merge_labels(&id, "food").is_err());
+        assert!(storage.delete_label(&id, None).is_err());
+        assert_eq!(
+            storage.merge_labels("missing", "food"),
+            Err(MutinyError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_bip329_jsonl() {
+        let test_name = "test_bip329_jsonl";
+        log!("{}", test_name);
+
+        let records = vec![
+            Bip329Label::new(
+                "addr",
+                "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c".to_string(),
+                &["Rent".to_string(), "Alice".to_string()],
+            ),
+            Bip329Label {
+                spendable: Some(false),
+                ..Bip329Label::new(
+                    "output",
+                    "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1"
+                        .to_string(),
+                    &[],
+                )
+            },
+        ];
+        let jsonl = to_bip329_jsonl(&records).unwrap();
+        assert_eq!(
+            jsonl,
+            r#"{"type":"addr","ref":"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c","label":"Rent, Alice"}
+{"type":"output","ref":"f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1","spendable":false}"#
+        );
+
+        let parsed = parse_bip329_jsonl(&format!("{jsonl}\n\n")).unwrap();
+        assert_eq!(parsed, records);
+        assert_eq!(
+            parsed[0].labels(),
+            vec!["Rent".to_string(), "Alice".to_string()]
+        );
+        assert!(parsed[1].labels().is_empty());
+
+        // other wallets' fields and types are fine
+        let sparrow = r#"{"type":"xpub","ref":"xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8","label":"Wallet","origin":"wpkh([d34db33f/84'/0'/0'])"}"#;
+        assert_eq!(parse_bip329_jsonl(sparrow).unwrap()[0].kind, "xpub");
+        assert!(parse_bip329_jsonl("not json").is_err());
+    }
+
     #[test]
     fn test_get_tag_items() {
         let test_name = "test_get_tag_items";
@@ -1,3 +1,4 @@
+use crate::backup::record_channel_state_change;
 use crate::error::{MutinyError, MutinyStorageError};
 use crate::fees::MutinyFeeEstimator;
 use crate::gossip::PROB_SCORER_KEY;
@@ -10,9 +11,11 @@ use crate::nodemanager::ChannelClosure;
 use crate::storage::{IndexItem, MutinyStorage, VersionedValue};
 use crate::utils;
 use crate::utils::{sleep, spawn};
+use crate::watchtower::{deliver_justice_blobs, watch_commitment_update};
 use crate::{chain::MutinyChain, scorer::HubPreferentialScorer};
 use anyhow::anyhow;
 use bitcoin::hashes::hex::FromHex;
+use bitcoin::secp256k1::SecretKey;
 use bitcoin::Network;
 use bitcoin::{BlockHash, Transaction};
 use esplora_client::AsyncClient;
@@ -25,6 +28,7 @@ use lightning::io::Cursor;
 use lightning::ln::channelmanager::{
     self, ChainParameters, ChannelManager as LdkChannelManager, ChannelManagerReadArgs,
 };
+use lightning::ln::types::ChannelId;
 use lightning::sign::{InMemorySigner, SpendableOutputDescriptor};
 use lightning::util::logger::Logger;
 use lightning::util::persist::Persister;
@@ -47,6 +51,7 @@ const CHANNEL_OPENING_PARAMS_PREFIX: &str = "chan_open_params/";
 pub const CHANNEL_CLOSURE_PREFIX: &str = "channel_closure/";
 pub const CHANNEL_CLOSURE_BUMP_PREFIX: &str = "channel_closure_bump/";
 const FAILED_SPENDABLE_OUTPUT_DESCRIPTOR_KEY: &str = "failed_spendable_outputs";
+const CHANNEL_SWEEP_PREFIX: &str = "channel_sweep/";
 
 pub(crate) type PhantomChannelManager<S: MutinyStorage> = LdkChannelManager<
     Arc<ChainMonitor<S>>,
@@ -65,6 +70,10 @@ pub struct MutinyNodePersister<S: MutinyStorage> {
     pub(crate) storage: S,
     manager_version: Arc<AtomicU32>,
     pub(crate) chain_monitor: Arc<Mutex<Option<Arc<ChainMonitor<S>>>>>,
+    /// Only set once the node has one, justice transactions need a fee rate
+    fee_estimator: Option<Arc<MutinyFeeEstimator<S>>>,
+    /// The key justice transactions are registered with towers by
+    watchtower_key: Option<SecretKey>,
     logger: Arc<MutinyLogger>,
 }
 
@@ -81,10 +90,58 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
             storage,
             manager_version: Arc::new(AtomicU32::new(0)),
             chain_monitor: Arc::new(Mutex::new(None)),
+            fee_estimator: None,
+            watchtower_key: None,
             logger,
         }
     }
 
+    pub(crate) fn with_fee_estimator(mut self, fee_estimator: Arc<MutinyFeeEstimator<S>>) -> Self {
+        self.fee_estimator = Some(fee_estimator);
+        self
+    }
+
+    pub(crate) fn with_watchtower_key(mut self, watchtower_key: SecretKey) -> Self {
+        self.watchtower_key = Some(watchtower_key);
+        self
+    }
+
+    /// Hands the update to the watchtower client, the peer's commitments in it
+    /// can only be turned into justice transactions now.
+    fn watch_commitment_update(
+        &self,
+        update: Option<&ChannelMonitorUpdate>,
+        monitor: &ChannelMonitor<InMemorySigner>,
+    ) {
+        let (Some(fee_estimator), Some(watchtower_key)) =
+            (self.fee_estimator.as_ref(), self.watchtower_key)
+        else {
+            return;
+        };
+        match watch_commitment_update(
+            &self.storage,
+            fee_estimator.get_high_fee_rate(),
+            monitor,
+            update,
+        ) {
+            Ok(0) => {}
+            Ok(queued) => {
+                log_debug!(
+                    self.logger,
+                    "Queued {queued} justice transactions for watchtowers"
+                );
+                let storage = self.storage.clone();
+                let logger = self.logger.clone();
+                spawn(async move {
+                    if let Err(e) = deliver_justice_blobs(&storage, &watchtower_key).await {
+                        log_warn!(logger, "Failed to deliver justice transactions: {e}");
+                    }
+                });
+            }
+            Err(e) => log_error!(self.logger, "Failed to build justice transactions: {e}"),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn manager_version(&self) -> u32 {
         self.manager_version.load(Ordering::Relaxed)
@@ -455,6 +512,48 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         Ok(())
     }
 
+    /// Records the outputs LDK handed us from a closed channel, so we can show
+    /// how much of the channel's funds have been claimed. Outputs we've already
+    /// recorded are skipped in case the event is replayed.
+    pub(crate) fn record_channel_sweep(
+        &self,
+        channel_id: &ChannelId,
+        outputs: &[SpendableOutputDescriptor],
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(&format!(
+            "{CHANNEL_SWEEP_PREFIX}{}",
+            channel_id.0.to_lower_hex_string()
+        ));
+        let mut swept: Vec<(bitcoin::OutPoint, u64)> =
+            self.storage.get_data(&key)?.unwrap_or_default();
+
+        for output in outputs {
+            let (outpoint, value) = match output {
+                SpendableOutputDescriptor::StaticOutput {
+                    outpoint, output, ..
+                } => (outpoint, output.value),
+                SpendableOutputDescriptor::DelayedPaymentOutput(d) => (&d.outpoint, d.output.value),
+                SpendableOutputDescriptor::StaticPaymentOutput(d) => (&d.outpoint, d.output.value),
+            };
+            let outpoint = outpoint.into_bitcoin_outpoint();
+            if !swept.iter().any(|(o, _)| *o == outpoint) {
+                swept.push((outpoint, value.to_sat()));
+            }
+        }
+
+        self.storage.write_data(key, swept, None)
+    }
+
+    /// How much of a closed channel's funds have been claimed on-chain
+    pub(crate) fn get_channel_swept_sats(&self, channel_id: &[u8; 32]) -> Result<u64, MutinyError> {
+        let key = self.get_key(&format!(
+            "{CHANNEL_SWEEP_PREFIX}{}",
+            channel_id.to_lower_hex_string()
+        ));
+        let swept: Vec<(bitcoin::OutPoint, u64)> = self.storage.get_data(key)?.unwrap_or_default();
+        Ok(swept.iter().map(|(_, value)| value).sum())
+    }
+
     /// Persists the failed spendable outputs to storage.
     /// Previously failed spendable outputs are not overwritten.
     ///
@@ -568,6 +667,10 @@ pub(crate) struct ChannelOpenParams {
     pub(crate) absolute_fee: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) utxos: Option<Vec<bitcoin::OutPoint>>,
+    /// Coins picked by the user to fund the channel, any change goes back to
+    /// the wallet. Unlike `utxos` these aren't swept in full.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) funding_utxos: Option<Vec<bitcoin::OutPoint>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) labels: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -582,6 +685,7 @@ impl ChannelOpenParams {
             sats_per_vbyte,
             absolute_fee: None,
             utxos: None,
+            funding_utxos: None,
             labels: None,
             opening_tx: None,
             failure_reason: None,
@@ -597,6 +701,7 @@ impl ChannelOpenParams {
             sats_per_vbyte,
             absolute_fee: Some(absolute_fee),
             utxos: Some(utxos),
+            funding_utxos: None,
             labels: None,
             opening_tx: None,
             failure_reason: None,
@@ -654,6 +759,12 @@ impl<S: MutinyStorage> Persist<InMemorySigner> for MutinyNodePersister<S> {
         funding_txo: OutPoint,
         monitor: &ChannelMonitor<InMemorySigner>,
     ) -> ChannelMonitorUpdateStatus {
+        // existing channels are persisted again on every start, only a channel
+        // that was just opened has an initial commitment to watch
+        if monitor.get_latest_update_id() == 0 {
+            self.watch_commitment_update(None, monitor);
+        }
+
         let key = self.get_monitor_key(&funding_txo);
 
         let update_id = monitor.get_latest_update_id();
@@ -677,9 +788,11 @@ impl<S: MutinyStorage> Persist<InMemorySigner> for MutinyNodePersister<S> {
     fn update_persisted_channel(
         &self,
         funding_txo: OutPoint,
-        _update: Option<&ChannelMonitorUpdate>,
+        update: Option<&ChannelMonitorUpdate>,
         monitor: &ChannelMonitor<InMemorySigner>,
     ) -> ChannelMonitorUpdateStatus {
+        self.watch_commitment_update(update, monitor);
+
         let key = self.get_monitor_key(&funding_txo);
         let update_id = monitor.get_latest_update_id();
         debug_assert!(update_id == utils::get_monitor_version(&monitor.encode()));
@@ -719,6 +832,13 @@ pub(crate) fn persist_monitor(
 ) -> Result<(), lightning::io::Error> {
     let res = storage.write_data(key.clone(), object, version);
 
+    // any backup made before this is now outdated
+    if res.is_ok() {
+        if let Err(e) = record_channel_state_change(&storage) {
+            log_warn!(logger, "Failed to record channel state change: {e}");
+        }
+    }
+
     res.map_err(|e| {
         match e {
             MutinyError::PersistenceFailed { source } => {
@@ -818,6 +938,8 @@ mod test {
             amt_msat: MillisatAmount(Some(420)),
             fee_paid_msat: None,
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
@@ -873,6 +995,11 @@ mod test {
             reason: "This is a test.".to_string(),
             timestamp: utils::now().as_secs(),
             channel_funding_txo: None,
+            closing_txid: None,
+            swept_amount_sats: 0,
+            pending_sweep_sats: 0,
+            fully_swept: false,
+            estimated_secs_remaining: None,
         };
         let result = persister.persist_channel_closure(user_channel_id, closure.clone());
         assert!(result.is_ok());
@@ -884,6 +1011,73 @@ mod test {
         assert_eq!(result, Some(closure));
     }
 
+    #[test]
+    fn test_persist_channel_open_params() {
+        let test_name = "test_persist_channel_open_params";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+
+        let user_channel_id: u128 = 123456789;
+        let outpoint = bitcoin::OutPoint::from_str(
+            "2d0f9a6e5a3ea1a5a2fd32b96b0b1b8fe6ab4c0b8e1c31f2ef1a4b4b3e8e6c6f:1",
+        )
+        .unwrap();
+        let mut params = ChannelOpenParams::new(5);
+        params.funding_utxos = Some(vec![outpoint]);
+        persister
+            .persist_channel_open_params(user_channel_id, params)
+            .unwrap();
+
+        let stored = persister
+            .get_channel_open_params(user_channel_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.sats_per_vbyte, 5);
+        assert_eq!(stored.funding_utxos, Some(vec![outpoint]));
+        assert!(stored.utxos.is_none());
+
+        // params saved before coin control still read
+        let old = serde_json::json!({ "sats_per_vbyte": 1 });
+        let old: ChannelOpenParams = serde_json::from_value(old).unwrap();
+        assert!(old.funding_utxos.is_none());
+    }
+
+    #[test]
+    fn test_record_channel_sweep() {
+        let test_name = "test_record_channel_sweep";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+        let channel_id = ChannelId([1; 32]);
+        assert_eq!(persister.get_channel_swept_sats(&channel_id.0).unwrap(), 0);
+
+        let output = |index: u16, value: u64| SpendableOutputDescriptor::StaticOutput {
+            outpoint: OutPoint {
+                txid: Txid::all_zeros(),
+                index,
+            },
+            output: TxOut {
+                value: bitcoin::Amount::from_sat(value),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            },
+            channel_keys_id: None,
+        };
+        persister
+            .record_channel_sweep(&channel_id, &[output(0, 10_000)])
+            .unwrap();
+        // replayed events shouldn't be counted twice
+        persister
+            .record_channel_sweep(&channel_id, &[output(0, 10_000), output(1, 5_000)])
+            .unwrap();
+
+        assert_eq!(
+            persister.get_channel_swept_sats(&channel_id.0).unwrap(),
+            15_000
+        );
+        assert_eq!(persister.get_channel_swept_sats(&[2; 32]).unwrap(), 0);
+    }
+
     #[test]
     fn test_persist_spendable_output_descriptor() {
         let test_name = "test_persist_spendable_output_descriptor";
@@ -9,60 +9,225 @@
 )]
 extern crate core;
 
+pub mod allowlist;
+pub mod anchorbump;
 pub mod authclient;
 pub mod authmanager;
+pub mod avatars;
+pub mod backup;
+pub mod badges;
+pub mod bip322;
+pub mod cbf;
 mod chain;
+pub mod channelfunding;
+#[cfg(feature = "demo")]
+pub mod demo;
+pub mod denominations;
+pub mod descriptionprivacy;
+pub mod diagnostics;
 pub mod encrypt;
 pub mod error;
 pub mod event;
 mod fees;
+pub mod fiatinvoice;
 mod gossip;
+pub mod hodl;
+pub mod hooks;
+pub mod idle;
+pub mod inboundchannels;
+pub mod jobs;
 mod key;
 mod keymanager;
 pub mod labels;
 mod ldkstorage;
+pub mod lnaddress;
 pub mod logging;
 pub mod lsp;
+pub mod memory;
 pub mod messagehandler;
+pub mod messages;
+pub mod moderation;
+pub mod mpp;
 mod networking;
 mod node;
 pub mod nodemanager;
+mod nostr;
+pub mod offers;
 mod onchain;
+pub mod operations;
+pub mod outbox;
+pub mod paymentcard;
+pub mod paymentfailure;
+pub mod paymentlink;
+pub mod paymentpolicy;
+pub mod paymentrequest;
 mod peermanager;
+pub mod peeroverrides;
+pub mod permissions;
+pub mod probe;
+pub mod psbtsession;
+pub mod qr;
+pub mod rebalance;
+pub mod receipts;
+pub mod remoteconfig;
+pub mod routeexplain;
+pub mod routehints;
+pub mod routingnode;
+pub mod scb;
 pub mod scorer;
+pub mod servicestatus;
+pub mod settingssync;
+pub mod snapshot;
+pub mod socialrecovery;
+pub mod split;
+pub mod statement;
 pub mod storage;
 mod subscription;
+pub mod swap;
+mod sweepkey;
+pub mod transfer;
 pub mod utils;
+pub mod validation;
+pub mod vault;
 pub mod vss;
+pub mod watchonly;
+pub mod watchtower;
+pub mod zeroreserve;
 
 #[cfg(test)]
 mod test_utils;
 
 use crate::authmanager::AuthManager;
+use crate::avatars::{
+    cache_avatar, clear_avatar_cache, fetch_avatar, get_cached_avatar, touch_avatar, CachedAvatar,
+};
+use crate::badges::{
+    get_badge_settings, list_earned_milestones, npub, persist_badge_settings, BadgeSettings,
+    EarnedMilestone,
+};
+use crate::denominations::{
+    get_price_at, record_price, Denomination, DenominationPreferences, DENOMINATION_PREFERENCES_KEY,
+};
+use crate::descriptionprivacy::DescriptionPrivacy;
+use crate::diagnostics::DiagnosticsBundle;
 use crate::error::MutinyError;
+use crate::event::{CustomTlv, HTLCStatus, MillisatAmount, PaymentInfo};
+pub use crate::fees::FeeSource;
+use crate::fiatinvoice::{
+    fiat_description, get_fiat_invoice, list_fiat_invoices, persist_fiat_invoice, quote_secs,
+    FiatInvoice, FiatInvoiceStatus, FIAT_INVOICE_WATCH_INTERVAL_SECS, MAX_QUOTE_SECS,
+};
 pub use crate::gossip::{GOSSIP_SYNC_TIME_KEY, NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
+use crate::hooks::{ChannelAcceptor, HookCapability, HookHandler, HookInfo, HookKind};
+use crate::inboundchannels::{
+    get_inbound_channel_policy, list_inbound_channel_requests, persist_inbound_channel_policy,
+    InboundChannelPolicy, InboundChannelRequest,
+};
+use crate::jobs::{
+    cancel_job, get_job, list_jobs, persist_job, resume_jobs, start_job, Job, JobKind,
+};
 pub use crate::keymanager::generate_seed;
 pub use crate::ldkstorage::{
     CHANNEL_CLOSURE_BUMP_PREFIX, CHANNEL_CLOSURE_PREFIX, CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY,
 };
+use crate::lnaddress::{
+    fetch_invoice, get_ln_address_aliases, persist_ln_address_aliases, AliasRotationPolicy,
+    LightningAddress, LnAddressAliases,
+};
+use crate::lsp::lsps1::{ChannelPurchase, CHANNEL_PURCHASE_PREFIX};
+use crate::moderation::{
+    get_moderation_settings, normalize_pubkey, persist_moderation_settings, DirectMessage,
+    ModerationSettings,
+};
+use crate::mpp::{MppConfig, MppStrategy};
 use crate::nodemanager::NodeManager;
-use crate::nodemanager::{ChannelClosure, MutinyBip21RawMaterials};
+use crate::nodemanager::{
+    AddressType, ChannelClosure, CoinSelectionStrategy, ConsolidationConfig,
+    MutinyBip21RawMaterials,
+};
+use crate::nostr::nostr_key;
+use crate::offers::{MutinyOffer, MutinyRefund};
+use crate::operations::{operation_id, Operation, OperationKind};
+use crate::paymentcard::{get_payment_card, persist_payment_card, PaymentCard, PaymentCardUpdate};
+use crate::paymentfailure::{get_payment_failure_details, PaymentFailureDetails};
+use crate::paymentlink::{
+    get_payment_link, list_payment_links, parse_payment_link, persist_payment_link, PaymentLink,
+    PaymentLinkEvent, PaymentLinkTarget,
+};
+use crate::paymentpolicy::PaymentPolicy;
+use crate::paymentrequest::{
+    delete_payment_request, fetch_payment_request_dms, get_payment_request, list_payment_requests,
+    persist_payment_request, PaymentRequest, PAYMENT_REQUEST_MESSAGES_SINCE_KEY,
+    PAYMENT_REQUEST_POLL_INTERVAL_SECS,
+};
+use crate::peeroverrides::{
+    get_peer_overrides, list_peer_overrides, set_peer_overrides, PeerOverrides,
+};
+use crate::permissions::{
+    get_app_grant, list_app_grants, persist_app_grant, AppGrant, AppPermission, AppSession,
+};
+use crate::probe::{ProbeResult, ProbeTarget};
+use crate::rebalance::{get_rebalances, Rebalance};
+use crate::receipts::{
+    create_receipt_dm, fetch_receipt_dms, get_receipt, list_receipts, persist_receipt,
+    PaymentReceipt, RECEIPT_MESSAGES_SINCE_KEY,
+};
+use crate::remoteconfig::{
+    get_remote_config, get_remote_config_overrides, set_remote_config_overrides, RemoteConfig,
+    RemoteConfigOverrides,
+};
+use crate::routehints::RouteHintSelection;
+use crate::scb::{
+    channel_backup_key, create_scb, decrypt_scb, encrypt_scb, list_scb_recoveries, ScbRecovery,
+};
+use crate::servicestatus::{service_status, ServiceStatus};
+use crate::settingssync::{
+    fetch_remote_settings, get_wallet_settings, persist_wallet_settings, queue_settings_event,
+    settings_key, WalletSettings,
+};
+use crate::socialrecovery::{
+    create_shards, fetch_recovery_dms, get_held_shard, get_social_recovery_config,
+    list_held_shards, persist_held_shard, persist_social_recovery_config, queue_recovery_dm,
+    social_recovery_key, Guardian, HeldShard, RecoveryMessage, SocialRecoveryConfig,
+    LIVENESS_CHECK_INTERVAL_SECS, RECOVERY_MESSAGES_SINCE_KEY, SOCIAL_RECOVERY_KEY,
+};
+use crate::split::{
+    get_split_request, get_split_send, list_split_requests, persist_new_split_request,
+    persist_split_send, split_amount, split_weighted, SplitRequest, SplitSend, SplitSendRecipient,
+    SplitShare,
+};
+use crate::statement::Statement;
 use crate::storage::get_invoice_by_hash;
+use crate::swap::{
+    deposit_address, deposit_script, derive_refund_key, get_swap_deposit, list_swap_deposits,
+    next_refund_key_index, persist_swap_deposit, SwapDeposit, SwapDepositStatus,
+    SWAP_DEPOSIT_PREFIX,
+};
+use crate::transfer::{
+    create_transfer_dm, fetch_transfer_dms, find_answer, get_transfer, lightning_transfer_amount,
+    list_transfers, persist_transfer, Transfer, TransferMessage, TransferStatus,
+    TRANSFER_ANSWER_TIMEOUT_SECS, TRANSFER_MESSAGES_SINCE_KEY, TRANSFER_POLL_INTERVAL_SECS,
+};
 use crate::utils::sleep;
 use crate::utils::spawn;
+use crate::watchtower::{
+    get_watchtower_config, list_justice_blobs, persist_watchtower_config, JusticeBlob,
+    WatchtowerConfig, WATCHTOWER_LABEL,
+};
+use crate::zeroreserve::{get_zero_reserve_peers, set_zero_reserve_peer};
 use crate::{authclient::MutinyAuthClient, logging::MutinyLogger};
 use crate::{
-    event::{HTLCStatus, MillisatAmount, PaymentInfo},
-    onchain::FULL_SYNC_STOP_GAP,
+    labels::LabelStorage,
+    nodemanager::{NodeBalance, NodeLightningBalance},
 };
-use crate::{labels::LabelStorage, nodemanager::NodeBalance};
 use crate::{logging::LOGGING_KEY, nodemanager::NodeManagerBuilder};
 use crate::{
-    onchain::get_esplora_url,
+    onchain::{get_esplora_urls, select_esplora_url},
     storage::{
-        get_payment_hash_from_key, get_transaction_details, list_payment_info, IndexItem,
-        MutinyStorage, DEVICE_ID_KEY, EXPECTED_NETWORK_KEY, NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX,
-        PAYMENT_INBOUND_PREFIX_KEY, PAYMENT_OUTBOUND_PREFIX_KEY, TRANSACTION_DETAILS_PREFIX_KEY,
+        get_payment_hash_from_key, get_transaction_details, get_tx_replacement, list_payment_info,
+        IndexItem, MutinyStorage, StorageBreakdown, DEVICE_ID_KEY, EXPECTED_NETWORK_KEY,
+        NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX, PAYMENT_INBOUND_PREFIX_KEY,
+        PAYMENT_OUTBOUND_PREFIX_KEY, TRANSACTION_DETAILS_PREFIX_KEY,
     },
 };
 use anyhow::Context;
@@ -70,10 +235,11 @@ use bdk_chain::ConfirmationTime;
 use bip39::Mnemonic;
 pub use bitcoin;
 use bitcoin::consensus::encode::serialize_hex;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::constants::ChainHash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, XOnlyPublicKey};
 use bitcoin::{bip32::Xpriv, Transaction};
 use bitcoin::{hashes::sha256, Network, Txid};
-use bitcoin::{hashes::Hash, Address};
+use bitcoin::{hashes::Hash, Address, OutPoint};
 
 use futures_util::lock::Mutex;
 use hex_conservative::{DisplayHex, FromHex};
@@ -81,14 +247,17 @@ use itertools::Itertools;
 pub use lightning;
 use lightning::chain::BestBlock;
 use lightning::ln::PaymentHash;
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
 pub use lightning_invoice;
 use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
 
-use messagehandler::CommonLnEventCallback;
+use messagehandler::{CommonLnEvent, CommonLnEventCallback};
 use serde::{Deserialize, Serialize};
 use utils::{spawn_with_handle, StopHandle};
+use uuid::Uuid;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -107,7 +276,8 @@ use mockall::{automock, predicate::*};
 pub const DEVICE_LOCK_INTERVAL_SECS: u64 = 5;
 const BITCOIN_PRICE_CACHE_SEC: u64 = 300;
 const DEFAULT_PAYMENT_TIMEOUT: u64 = 30;
-const DUST_LIMIT: u64 = 546;
+/// Outputs below this are not relayed
+pub(crate) const DUST_LIMIT: u64 = 546;
 
 #[cfg_attr(test, automock)]
 pub trait InvoiceHandler {
@@ -155,11 +325,27 @@ impl MutinyBalance {
     }
 }
 
+/// A breakdown of the wallet balance by where the funds are held
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MutinyDetailedBalance {
+    pub confirmed: u64,
+    pub unconfirmed: u64,
+    /// Lightning balance of each node
+    pub nodes: Vec<NodeLightningBalance>,
+    /// Total channel reserves across all nodes that cannot be spent
+    pub reserved: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum ActivityItem {
     OnChain(TransactionDetails),
     Lightning(Box<MutinyInvoice>),
     ChannelClosed(ChannelClosure),
+    /// A payment to ourselves between two of our channels, shown once
+    /// instead of as a send and a receive
+    Rebalance(Rebalance),
+    /// An inbound channel bought from an LSP, shown once the channel confirms
+    ChannelPurchase(Box<ChannelPurchase>),
 }
 
 /// A wallet transaction
@@ -184,6 +370,12 @@ pub struct TransactionDetails {
     pub confirmation_time: ConfirmationTime,
     /// Labels associated with this transaction
     pub labels: Vec<String>,
+    /// Earlier versions of this transaction that it replaced with RBF, oldest first
+    #[serde(default)]
+    pub replaces: Vec<Txid>,
+    /// Whether this paid to one of our addresses that received in another transaction
+    #[serde(default)]
+    pub address_reused: bool,
 }
 
 impl PartialOrd for TransactionDetails {
@@ -226,6 +418,12 @@ impl ActivityItem {
                 HTLCStatus::Pending | HTLCStatus::InFlight => None,
             },
             ActivityItem::ChannelClosed(c) => Some(c.timestamp),
+            ActivityItem::Rebalance(r) => match r.status {
+                HTLCStatus::Succeeded => Some(r.last_updated),
+                HTLCStatus::Failed => Some(r.last_updated),
+                HTLCStatus::Pending | HTLCStatus::InFlight => None,
+            },
+            ActivityItem::ChannelPurchase(p) => p.confirmed_at,
         }
     }
 
@@ -234,9 +432,16 @@ impl ActivityItem {
             ActivityItem::OnChain(t) => t.labels.clone(),
             ActivityItem::Lightning(i) => i.labels.clone(),
             ActivityItem::ChannelClosed(_) => vec![],
+            ActivityItem::Rebalance(_) => vec![],
+            ActivityItem::ChannelPurchase(p) => vec![p.label()],
         }
     }
 
+    /// The id of the operation this is part of, items of the same user action share it
+    pub fn operation_id(&self) -> Option<String> {
+        operation_id(&self.labels())
+    }
+
     pub fn is_channel_open(&self) -> bool {
         match self {
             ActivityItem::OnChain(onchain) => {
@@ -244,6 +449,8 @@ impl ActivityItem {
             }
             ActivityItem::Lightning(_) => false,
             ActivityItem::ChannelClosed(_) => false,
+            ActivityItem::Rebalance(_) => false,
+            ActivityItem::ChannelPurchase(_) => true,
         }
     }
 }
@@ -335,6 +542,12 @@ impl FromStr for PrivacyLevel {
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct MutinyInvoice {
     pub bolt11: Option<Bolt11Invoice>,
+    /// The BOLT12 offer or refund this payment was made to or received for
+    #[serde(default)]
+    pub offer: Option<String>,
+    /// Custom TLV records that came with a keysend payment
+    #[serde(default)]
+    pub custom_tlvs: Vec<CustomTlv>,
     pub description: Option<String>,
     pub payment_hash: sha256::Hash,
     pub preimage: Option<String>,
@@ -356,6 +569,8 @@ impl Default for MutinyInvoice {
     fn default() -> Self {
         MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash: sha256::Hash::all_zeros(),
             preimage: None,
@@ -402,6 +617,8 @@ impl From<Bolt11Invoice> for MutinyInvoice {
 
         MutinyInvoice {
             bolt11: Some(value),
+            offer: None,
+            custom_tlvs: vec![],
             description,
             payment_hash,
             preimage: None,
@@ -442,6 +659,8 @@ impl From<MutinyInvoice> for PaymentInfo {
             amt_msat,
             fee_paid_msat,
             bolt11,
+            offer: invoice.offer,
+            custom_tlvs: invoice.custom_tlvs,
             payee_pubkey,
             privacy_level: invoice.privacy_level,
             last_update,
@@ -479,6 +698,8 @@ impl MutinyInvoice {
                     fees_paid: i.fee_paid_msat.map(|f| f / 1_000),
                     fee_paid_msat: i.fee_paid_msat,
                     privacy_level: i.privacy_level,
+                    offer: i.offer,
+                    custom_tlvs: i.custom_tlvs,
                     ..invoice.into()
                 })
             }
@@ -489,6 +710,8 @@ impl MutinyInvoice {
                 let payment_hash = sha256::Hash::from_byte_array(payment_hash.0);
                 let invoice = MutinyInvoice {
                     bolt11: None,
+                    offer: i.offer,
+                    custom_tlvs: i.custom_tlvs,
                     description: None,
                     payment_hash,
                     preimage,
@@ -526,6 +749,7 @@ pub struct MutinyWalletConfigBuilder {
     websocket_proxy_addr: Option<String>,
     network: Option<Network>,
     user_esplora_url: Option<String>,
+    fallback_esplora_urls: Vec<String>,
     user_rgs_url: Option<String>,
     lsp_url: Option<String>,
     lsp_connection_string: Option<String>,
@@ -539,6 +763,15 @@ pub struct MutinyWalletConfigBuilder {
     skip_device_lock: bool,
     pub safe_mode: bool,
     skip_hodl_invoices: bool,
+    memory_budget_bytes: Option<u64>,
+    chain_snapshot: Option<(String, XOnlyPublicKey)>,
+    coin_selection: CoinSelectionStrategy,
+    gap_limit: Option<usize>,
+    compact_filter_url: Option<String>,
+    fee_source: FeeSource,
+    consolidation: Option<ConsolidationConfig>,
+    mpp_strategy: MppStrategy,
+    remote_config: Option<(String, XOnlyPublicKey)>,
 }
 
 impl MutinyWalletConfigBuilder {
@@ -549,6 +782,7 @@ impl MutinyWalletConfigBuilder {
             websocket_proxy_addr: None,
             network: None,
             user_esplora_url: None,
+            fallback_esplora_urls: vec![],
             user_rgs_url: None,
             lsp_url: None,
             lsp_connection_string: None,
@@ -562,6 +796,15 @@ impl MutinyWalletConfigBuilder {
             skip_device_lock: false,
             safe_mode: false,
             skip_hodl_invoices: true,
+            memory_budget_bytes: None,
+            chain_snapshot: None,
+            coin_selection: CoinSelectionStrategy::default(),
+            gap_limit: None,
+            compact_filter_url: None,
+            fee_source: FeeSource::default(),
+            consolidation: None,
+            mpp_strategy: MppStrategy::default(),
+            remote_config: None,
         }
     }
 
@@ -580,6 +823,11 @@ impl MutinyWalletConfigBuilder {
         self.user_esplora_url = Some(user_esplora_url);
     }
 
+    /// Esplora servers to fail over to, in order, when the main one keeps failing
+    pub fn with_fallback_esplora_urls(&mut self, fallback_esplora_urls: Vec<String>) {
+        self.fallback_esplora_urls = fallback_esplora_urls;
+    }
+
     pub fn with_user_rgs_url(&mut self, user_rgs_url: String) {
         self.user_rgs_url = Some(user_rgs_url);
     }
@@ -633,6 +881,59 @@ impl MutinyWalletConfigBuilder {
         self.skip_hodl_invoices = false;
     }
 
+    /// Trim caches automatically when the estimated memory usage goes over this budget
+    pub fn with_memory_budget_bytes(&mut self, memory_budget_bytes: u64) {
+        self.memory_budget_bytes = Some(memory_budget_bytes);
+    }
+
+    /// Bootstrap wallets that haven't synced yet from the chain snapshot at this url,
+    /// it must be signed by the given key. Only speeds up syncing with compact filters,
+    /// esplora still looks up every address.
+    pub fn with_chain_snapshot(&mut self, url: String, signing_key: XOnlyPublicKey) {
+        self.chain_snapshot = Some((url, signing_key));
+    }
+
+    /// How coins are picked for on-chain sends and channel opens
+    pub fn with_coin_selection(&mut self, coin_selection: CoinSelectionStrategy) {
+        self.coin_selection = coin_selection;
+    }
+
+    /// How many unused addresses in a row a full scan looks at before stopping,
+    /// restored wallets with deep address usage need a bigger one
+    pub fn with_gap_limit(&mut self, gap_limit: usize) {
+        self.gap_limit = Some(gap_limit);
+    }
+
+    /// Sync the on-chain wallet with compact block filters from this bitcoind REST
+    /// endpoint instead of sending our addresses to esplora. Lightning chain data
+    /// and broadcasting still go through esplora.
+    pub fn with_compact_filter_url(&mut self, compact_filter_url: String) {
+        self.compact_filter_url = Some(compact_filter_url);
+    }
+
+    /// Where fee estimates come from, defaults to mempool.space's recommended
+    /// fees from the esplora server with esplora's estimates as a fallback
+    pub fn with_fee_source(&mut self, fee_source: FeeSource) {
+        self.fee_source = fee_source;
+    }
+
+    /// Opt in to merging small coins together in the background whenever
+    /// the fee rate drops low enough
+    pub fn with_consolidation(&mut self, consolidation: ConsolidationConfig) {
+        self.consolidation = Some(consolidation);
+    }
+
+    /// How lightning payments are split into parts
+    pub fn with_mpp_strategy(&mut self, mpp_strategy: MppStrategy) {
+        self.mpp_strategy = mpp_strategy;
+    }
+
+    /// Fetch the operator's config from this url at startup,
+    /// it must be signed by the given key
+    pub fn with_remote_config(&mut self, url: String, signing_key: XOnlyPublicKey) {
+        self.remote_config = Some((url, signing_key));
+    }
+
     pub fn build(self) -> MutinyWalletConfig {
         let network = self.network.expect("network is required");
 
@@ -642,6 +943,7 @@ impl MutinyWalletConfigBuilder {
             websocket_proxy_addr: self.websocket_proxy_addr,
             network,
             user_esplora_url: self.user_esplora_url,
+            fallback_esplora_urls: self.fallback_esplora_urls,
             user_rgs_url: self.user_rgs_url,
             lsp_url: self.lsp_url,
             lsp_connection_string: self.lsp_connection_string,
@@ -655,6 +957,15 @@ impl MutinyWalletConfigBuilder {
             skip_device_lock: self.skip_device_lock,
             safe_mode: self.safe_mode,
             skip_hodl_invoices: self.skip_hodl_invoices,
+            memory_budget_bytes: self.memory_budget_bytes,
+            chain_snapshot: self.chain_snapshot,
+            coin_selection: self.coin_selection,
+            gap_limit: self.gap_limit,
+            compact_filter_url: self.compact_filter_url,
+            fee_source: self.fee_source,
+            consolidation: self.consolidation,
+            mpp_strategy: self.mpp_strategy,
+            remote_config: self.remote_config,
         }
     }
 }
@@ -666,6 +977,7 @@ pub struct MutinyWalletConfig {
     websocket_proxy_addr: Option<String>,
     network: Network,
     user_esplora_url: Option<String>,
+    fallback_esplora_urls: Vec<String>,
     user_rgs_url: Option<String>,
     lsp_url: Option<String>,
     lsp_connection_string: Option<String>,
@@ -679,6 +991,15 @@ pub struct MutinyWalletConfig {
     skip_device_lock: bool,
     pub safe_mode: bool,
     skip_hodl_invoices: bool,
+    memory_budget_bytes: Option<u64>,
+    chain_snapshot: Option<(String, XOnlyPublicKey)>,
+    coin_selection: CoinSelectionStrategy,
+    gap_limit: Option<usize>,
+    compact_filter_url: Option<String>,
+    fee_source: FeeSource,
+    consolidation: Option<ConsolidationConfig>,
+    mpp_strategy: MppStrategy,
+    remote_config: Option<(String, XOnlyPublicKey)>,
 }
 
 pub struct MutinyWalletBuilder<S: MutinyStorage> {
@@ -794,7 +1115,7 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         let network = self
             .network
             .map_or_else(|| Err(MutinyError::InvalidArgumentsError), Ok)?;
-        let config = self.config.unwrap_or(
+        let mut config = self.config.unwrap_or(
             MutinyWalletConfigBuilder::new(self.xprivkey)
                 .with_network(network)
                 .build(),
@@ -819,13 +1140,44 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             self.logs,
         ));
 
+        // Need to prevent the wallet from running twice on this device, e.g. in two tabs.
+        // If another instance is alive we still start, but read-only so we don't
+        // race it on the lightning state.
+        log_debug!(logger, "checking instance lock");
+        let instance_id = Uuid::new_v4().to_string();
+        let locked_by = if self.storage.try_claim_instance_lock(&instance_id).await? {
+            None
+        } else {
+            let lock = self.storage.fetch_instance_lock().await?;
+            Some(lock.map(|lock| lock.instance).unwrap_or_default())
+        };
+        let read_only = locked_by.is_some();
+        if let Some(locked_by) = locked_by {
+            log_warn!(
+                logger,
+                "Wallet is already running in instance {locked_by}, starting read-only"
+            );
+            config.safe_mode = true;
+            config.skip_device_lock = true;
+            if let Some(cb) = self.ln_event_callback.as_ref() {
+                cb.trigger(CommonLnEvent::ConcurrentInstanceDetected { locked_by });
+            }
+        }
+        log_debug!(logger, "finished checking instance lock");
+
         // Need to prevent other devices from running at the same time
         log_debug!(logger, "checking device lock");
         if !config.skip_device_lock {
             let start = Instant::now();
-            if let Some(lock) = self.storage.get_device_lock()? {
+            let device_id = self.storage.get_device_id()?;
+            let previous_lock = self.storage.get_device_lock()?;
+            if let Some(lock) = previous_lock.as_ref() {
                 log_info!(logger, "Current device lock: {lock:?}");
             }
+            // without VSS we can only trust our local state if this device was the last to run
+            let can_start_degraded = previous_lock
+                .as_ref()
+                .map_or(true, |lock| lock.is_last_locker(&device_id));
             self.storage.set_device_lock(&logger)?;
             log_debug!(
                 logger,
@@ -837,7 +1189,6 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             }
 
             // Wait device lock syncing
-            let device_id = self.storage.get_device_id()?;
             let max_retries = 10;
             let mut retries = 0;
             while retries <= max_retries {
@@ -854,6 +1205,16 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
                     Err(MutinyError::FailedParsingVssValue) => {
                         log_info!(logger, "Failed to parse VSS value, retrying... {retries}");
                     }
+                    Err(e)
+                        if can_start_degraded
+                            && self
+                                .storage
+                                .vss_client()
+                                .is_some_and(|v| v.health.unreachable_since().is_some()) =>
+                    {
+                        log_warn!(logger, "VSS is unreachable, starting with local state: {e}");
+                        break;
+                    }
                     Err(e) => {
                         log_error!(logger, "Error fetching device lock: {:?}", e);
                         return Err(e);
@@ -878,17 +1239,55 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         log_trace!(logger, "spawning claim device lock");
         let storage_clone = self.storage.clone();
         let logger_clone = logger.clone();
+        let instance_id_clone = instance_id.clone();
+        let ln_event_callback = self.ln_event_callback.clone();
         let device_lock_stop_handle = spawn_with_handle(|stop_signal| async move {
+            let mut lock_released = false;
             loop {
                 if stop_signal.stopping() {
                     log_debug!(logger_clone, "stopping claim device lock");
-                    if let Err(e) = storage_clone.release_device_lock(&logger_clone) {
-                        log_error!(logger_clone, "Error releasing device lock: {e}");
+                    // a read-only instance never held the locks
+                    if !read_only {
+                        if let Err(e) = storage_clone.release_device_lock(&logger_clone) {
+                            log_error!(logger_clone, "Error releasing device lock: {e}");
+                        }
+                        if let Err(e) = storage_clone
+                            .release_instance_lock(&instance_id_clone)
+                            .await
+                        {
+                            log_error!(logger_clone, "Error releasing instance lock: {e}");
+                        }
                     }
                     break;
                 }
-                if let Err(e) = storage_clone.set_device_lock(&logger_clone) {
-                    log_error!(logger_clone, "Error setting device lock: {e}");
+                if !read_only {
+                    if let Err(e) = storage_clone.set_device_lock(&logger_clone) {
+                        log_error!(logger_clone, "Error setting device lock: {e}");
+                    }
+                    match storage_clone
+                        .try_claim_instance_lock(&instance_id_clone)
+                        .await
+                    {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            log_warn!(logger_clone, "Instance lock was taken by another instance");
+                        }
+                        Err(e) => log_error!(logger_clone, "Error setting instance lock: {e}"),
+                    }
+                } else if !lock_released {
+                    // a read-only instance never takes the lock, its nodes aren't running.
+                    // Once the other instance stops the app is told so it can restart read-write.
+                    match storage_clone.fetch_instance_lock().await {
+                        Ok(lock) if !lock.is_some_and(|l| l.is_locked(&instance_id_clone)) => {
+                            log_info!(logger_clone, "The instance holding the lock stopped");
+                            lock_released = true;
+                            if let Some(cb) = ln_event_callback.as_ref() {
+                                cb.trigger(CommonLnEvent::InstanceLockReleased);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => log_error!(logger_clone, "Error fetching instance lock: {e}"),
+                    }
                 }
 
                 let mut remained_sleep_ms = (DEVICE_LOCK_INTERVAL_SECS * 1000) as i32;
@@ -902,7 +1301,12 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         log_trace!(logger, "finished spawning claim device lock");
 
         log_trace!(logger, "setting up esplora");
-        let esplora_server_url = get_esplora_url(network, config.user_esplora_url.clone());
+        let esplora_urls = get_esplora_urls(
+            network,
+            config.user_esplora_url.clone(),
+            &config.fallback_esplora_urls,
+        );
+        let esplora_server_url = select_esplora_url(&self.storage, &esplora_urls, &logger).await;
         let esplora = esplora_client::Builder::new(&esplora_server_url).build_async()?;
         let esplora = Arc::new(esplora);
         log_trace!(logger, "finished setting up esplora");
@@ -994,6 +1398,20 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             .collect::<Vec<_>>();
         activity_index.extend(closures);
 
+        // add the confirmed channel purchases to the activity index
+        let purchases = self
+            .storage
+            .scan::<ChannelPurchase>(CHANNEL_PURCHASE_PREFIX, None)?
+            .into_iter()
+            .filter_map(|(k, v)| {
+                Some(IndexItem {
+                    timestamp: Some(v.confirmed_at?),
+                    key: k,
+                })
+            })
+            .collect::<Vec<_>>();
+        activity_index.extend(purchases);
+
         // add inbound invoices to the activity index
         let inbound = self
             .storage
@@ -1047,14 +1465,16 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             logger: logger.clone(),
             network,
             skip_hodl_invoices: self.skip_hodl_invoices,
-            safe_mode: self.safe_mode,
+            safe_mode: self.safe_mode || read_only,
+            read_only,
             bitcoin_price_cache: Arc::new(Mutex::new(price_cache)),
+            app_spend_lock: Arc::new(std::sync::Mutex::new(())),
             device_lock_stop_handle,
         };
         log_trace!(logger, "finished creating mutiny wallet");
         // if we are in safe mode, don't create any nodes or
         // start any nostr services
-        if self.safe_mode {
+        if mw.safe_mode {
             return Ok(mw);
         }
 
@@ -1083,6 +1503,43 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         };
         log_trace!(logger, "finished listing nodes");
 
+        // queue the payment requests other wallets send to our npub
+        let requests_mw = mw.clone();
+        utils::spawn(async move { requests_mw.watch_payment_requests().await });
+
+        // pick up any jobs that didn't finish last time
+        resume_jobs(mw.node_manager.clone().ok_or(MutinyError::NotRunning)?);
+
+        // pull in settings changed on other devices
+        let sync_mw = mw.clone();
+        utils::spawn(async move {
+            if let Err(e) = sync_mw.sync_wallet_settings().await {
+                log_warn!(sync_mw.logger, "Failed to sync wallet settings: {e}");
+            }
+            if let Err(e) = sync_mw.check_social_recovery().await {
+                log_warn!(sync_mw.logger, "Failed to check social recovery: {e}");
+            }
+        });
+
+        // bind the VSS values written before authenticated encryption to their keys
+        if !mw.read_only {
+            let storage = mw.storage.clone();
+            let logger = mw.logger.clone();
+            utils::spawn(async move {
+                if let Err(e) = storage.migrate_legacy_vss().await {
+                    log_warn!(logger, "Failed to upgrade unauthenticated VSS values: {e}");
+                }
+            });
+        }
+
+        // keep fiat priced invoices at the current rate
+        let fiat_mw = mw.clone();
+        utils::spawn(async move { fiat_mw.watch_fiat_invoices().await });
+
+        // answer other wallets moving their balance to us and pick up payment receipts
+        let dm_mw = mw.clone();
+        utils::spawn(async move { dm_mw.watch_direct_messages().await });
+
         log_info!(
             mw.logger,
             "Final setup took {}ms",
@@ -1107,7 +1564,11 @@ pub struct MutinyWallet<S: MutinyStorage> {
     network: Network,
     skip_hodl_invoices: bool,
     safe_mode: bool,
+    /// Whether another instance of this wallet was already running on this device
+    read_only: bool,
     bitcoin_price_cache: Arc<Mutex<HashMap<String, (f32, Duration)>>>,
+    /// Held while an app's spending is read and updated, see [`AppSession`]
+    pub(crate) app_spend_lock: Arc<std::sync::Mutex<()>>,
     device_lock_stop_handle: StopHandle,
 }
 
@@ -1130,11 +1591,66 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let node_manager = Arc::new(nm_builder.build().await?);
         self.node_manager.replace(node_manager.clone());
         NodeManager::start_sync(node_manager.clone());
+        if !self.safe_mode {
+            resume_jobs(node_manager.clone());
+        }
 
         log_trace!(self.logger, "finished calling start");
         Ok(())
     }
 
+    /// Pauses the wallet before the app is backgrounded or frozen.
+    /// This stops the nodes so peers are disconnected cleanly and all
+    /// channel state is persisted, then waits for pending storage writes.
+    /// Storage stays open so [`MutinyWallet::resume`] is quick.
+    pub async fn prepare_for_sleep(&mut self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling prepare_for_sleep");
+
+        let node_manager = self.node_manager.take().ok_or(MutinyError::NotRunning)?;
+        node_manager.stop().await?;
+
+        // persist any wallet changes that haven't been written yet
+        {
+            let mut wallet = node_manager
+                .wallet
+                .wallet
+                .try_write()
+                .map_err(|_| MutinyError::WalletOperationFailed)?;
+            if let Some(changeset) = wallet.take_staged() {
+                self.storage.write_changes(&changeset)?;
+            }
+        }
+
+        self.storage.flush().await;
+        log_trace!(self.logger, "finished calling prepare_for_sleep");
+
+        Ok(())
+    }
+
+    /// Resumes the wallet after [`MutinyWallet::prepare_for_sleep`].
+    /// Does nothing if the wallet is already running.
+    pub async fn resume(&mut self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling resume");
+
+        if self.node_manager.is_some() {
+            return Ok(());
+        }
+
+        let mut nm_builder = NodeManagerBuilder::new(self.xprivkey, self.storage.clone())
+            .with_config(self.config.clone());
+        nm_builder.with_logger(self.logger.clone());
+
+        let node_manager = Arc::new(nm_builder.build().await?);
+        self.node_manager.replace(node_manager.clone());
+        NodeManager::start_sync(node_manager.clone());
+        if !self.safe_mode {
+            resume_jobs(node_manager);
+        }
+        log_trace!(self.logger, "finished calling resume");
+
+        Ok(())
+    }
+
     /// Pays a lightning invoice from a federation (preferred) or node.
     /// An amount should only be provided if the invoice does not have an amount.
     /// Amountless invoices cannot be paid by a federation.
@@ -1144,6 +1660,27 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         inv: &Bolt11Invoice,
         amt_sats: Option<u64>,
         labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.pay_invoice_with_options(
+            inv,
+            amt_sats,
+            MppConfig::default(),
+            PaymentPolicy::default(),
+            labels,
+        )
+        .await
+    }
+
+    /// Pays a lightning invoice like `pay_invoice`, with limits on how it's split
+    /// into parts and how long it's retried for. MPP limits that aren't set come
+    /// from the wallet's default.
+    pub async fn pay_invoice_with_options(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        mpp: MppConfig,
+        policy: PaymentPolicy,
+        labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling pay_invoice");
 
@@ -1158,10 +1695,6 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             return Err(MutinyError::InvoiceExpired);
         }
 
-        // set labels now, need to set it before in case the payment times out
-        self.storage
-            .set_invoice_labels(inv.clone(), labels.clone())?;
-
         // If any balance at all, then fallback to node manager for payment.
         // Take the error from the node manager as the priority.
         let res = if node_manager
@@ -1179,7 +1712,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             > 0
         {
             let res = node_manager
-                .pay_invoice(None, inv, amt_sats, labels)
+                .pay_invoice(None, inv, amt_sats, mpp, policy, labels)
                 .await?;
 
             Ok(res)
@@ -1191,6 +1724,176 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Sends probes toward a lightning invoice or node pubkey to estimate the
+    /// fee and how likely a payment is to succeed, without moving any funds.
+    /// The amount is in satoshis and required unless the invoice has one.
+    pub async fn probe_payment(
+        &self,
+        destination: &str,
+        amt_sats: Option<u64>,
+    ) -> Result<ProbeResult, MutinyError> {
+        log_trace!(self.logger, "calling probe_payment");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        let target = match Bolt11Invoice::from_str(destination.trim()) {
+            Ok(inv) => {
+                if inv.network() != self.network {
+                    return Err(MutinyError::IncorrectNetwork);
+                }
+                if inv.would_expire(utils::now()) {
+                    return Err(MutinyError::InvoiceExpired);
+                }
+                ProbeTarget::Invoice(inv)
+            }
+            Err(_) => ProbeTarget::Node(
+                PublicKey::from_str(destination.trim())
+                    .map_err(|_| MutinyError::InvalidArgumentsError)?,
+            ),
+        };
+
+        let res = node_manager.probe_payment(&target, amt_sats).await;
+        log_trace!(self.logger, "finished calling probe_payment");
+
+        res
+    }
+
+    /// Creates a BOLT12 offer, a static payment code that can be paid any number of times.
+    /// Without an amount the payer picks how much to send. The amount should be in satoshis.
+    pub async fn create_offer(
+        &self,
+        amount_sats: Option<u64>,
+        description: Option<String>,
+    ) -> Result<MutinyOffer, MutinyError> {
+        log_trace!(self.logger, "calling create_offer");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let res = node_manager.create_offer(amount_sats, description).await;
+        log_trace!(self.logger, "finished calling create_offer");
+
+        res
+    }
+
+    /// Lists the BOLT12 offers we created, newest first
+    pub fn list_offers(&self) -> Result<Vec<MutinyOffer>, MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager.list_offers()
+    }
+
+    /// Pays a BOLT12 offer. An amount should only be provided if the offer does
+    /// not have an amount, or to pay more than it asks.
+    /// The amount should be in satoshis.
+    pub async fn pay_offer(
+        &self,
+        offer: &Offer,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling pay_offer");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        if !offer.supports_chain(ChainHash::using_genesis_block(self.network)) {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+
+        if offer.is_expired() {
+            return Err(MutinyError::InvoiceExpired);
+        }
+
+        let res = node_manager
+            .pay_offer(None, offer, amount_sats, labels)
+            .await;
+        log_trace!(self.logger, "finished calling pay_offer");
+
+        res
+    }
+
+    /// Creates a BOLT12 refund, for merchants to pay back a customer. The customer
+    /// claims it with their wallet, which sends us an invoice we pay right away.
+    /// Whoever claims it first gets it, so it should only be shared with them.
+    /// The amount should be in satoshis.
+    pub async fn create_refund(
+        &self,
+        amount_sats: u64,
+        description: Option<String>,
+        expiry_secs: Option<u64>,
+    ) -> Result<MutinyRefund, MutinyError> {
+        log_trace!(self.logger, "calling create_refund");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        let res = node_manager
+            .create_refund(amount_sats, description, expiry_secs)
+            .await;
+        log_trace!(self.logger, "finished calling create_refund");
+
+        res
+    }
+
+    /// Claims a BOLT12 refund someone created for us
+    pub async fn claim_refund(&self, refund: &Refund) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling claim_refund");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        if refund.chain() != ChainHash::using_genesis_block(self.network) {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+
+        if refund.is_expired() {
+            return Err(MutinyError::InvoiceExpired);
+        }
+
+        let res = node_manager.claim_refund(refund).await;
+        log_trace!(self.logger, "finished calling claim_refund");
+
+        res
+    }
+
+    /// Lists the BOLT12 refunds we created, newest first
+    pub fn list_refunds(&self) -> Result<Vec<MutinyRefund>, MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager.list_refunds()
+    }
+
+    /// Registers a hook run on wallet events, see [`hooks`] for what hooks can do.
+    /// Returns the id to unregister it with.
+    pub fn register_hook(
+        &self,
+        name: String,
+        kinds: Vec<HookKind>,
+        capabilities: Vec<HookCapability>,
+        handler: HookHandler,
+    ) -> Result<String, MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager
+            .hooks
+            .register(name, kinds, capabilities, handler)
+    }
+
+    /// Removes a hook, returns whether it was registered
+    pub fn unregister_hook(&self, id: &str) -> Result<bool, MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager.hooks.unregister(id)
+    }
+
+    pub fn list_hooks(&self) -> Result<Vec<HookInfo>, MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager.hooks.list()
+    }
+
+    /// Sets a channel acceptor that decides on the channels peers, our LSP
+    /// included, ask to open with us in place of the built in policy.
+    /// None goes back to the policy.
+    pub fn set_channel_acceptor(
+        &self,
+        acceptor: Option<ChannelAcceptor>,
+    ) -> Result<(), MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        node_manager.hooks.set_channel_acceptor(acceptor)
+    }
+
     /// Estimates the lightning fee for a transaction. Amount is either from the invoice
     /// if one is available or a passed in amount (priority). It will try to predict either
     /// sending the payment through a federation or through lightning, depending on balances.
@@ -1246,10 +1949,13 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     ///
     /// If the server returns a status of 500 with a different error message,
     /// a [`MutinyError::LspGenericError`] is returned.
+    ///
+    /// The address is of the given type, or the wallet's default address type if not set.
     pub async fn create_bip21(
         &self,
         amount: Option<u64>,
         labels: Vec<String>,
+        address_type: Option<AddressType>,
     ) -> Result<MutinyBip21RawMaterials, MutinyError> {
         log_trace!(self.logger, "calling create_bip21");
 
@@ -1264,7 +1970,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             )
         };
 
-        let Ok(address) = self.create_address(labels.clone()).await else {
+        let Ok(address) = self.create_address(labels.clone(), address_type).await else {
             return Err(MutinyError::WalletOperationFailed);
         };
         log_trace!(self.logger, "finished calling create_bip21");
@@ -1283,6 +1989,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         amount: u64,
         labels: Vec<String>,
         fee_rate: Option<u64>,
+        utxos: Vec<OutPoint>,
     ) -> Result<Txid, MutinyError> {
         log_trace!(self.logger, "calling send_to_address");
 
@@ -1293,7 +2000,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let b = node_manager.get_balance().await?;
         let res = if b.confirmed + b.unconfirmed > 0 {
             let res = node_manager
-                .send_to_address(send_to, amount, labels, fee_rate)
+                .send_to_address(send_to, amount, labels, fee_rate, utxos)
                 .await?;
             Ok(res)
         } else {
@@ -1304,6 +2011,29 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Sends to several addresses in one transaction, saving fees compared to
+    /// paying each of them separately. Each recipient is an address, an amount
+    /// in sats and the labels for that payment.
+    pub async fn send_batch(
+        &self,
+        recipients: Vec<(Address, u64, Vec<String>)>,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling send_batch");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        let b = node_manager.get_balance().await?;
+        let total: u64 = recipients.iter().map(|(_, amount, _)| amount).sum();
+        if b.confirmed + b.unconfirmed < total {
+            return Err(MutinyError::InsufficientBalance);
+        }
+        let res = node_manager.send_batch(recipients, fee_rate).await;
+        log_trace!(self.logger, "finished calling send_batch");
+
+        res
+    }
+
     /// Estimates the onchain fee for a transaction sending to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     pub async fn estimate_tx_fee(
@@ -1394,16 +2124,19 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(())
     }
 
+    /// Creates a new on-chain address of the given type,
+    /// or the wallet's default address type if not set.
     pub async fn create_address(
         &self,
         labels: Vec<String>,
+        address_type: Option<AddressType>,
     ) -> Result<bitcoin::Address, MutinyError> {
         log_trace!(self.logger, "calling create_address");
 
         let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
 
         // Fallback to node_manager address creation
-        let Ok(addr) = node_manager.get_new_address(labels.clone()) else {
+        let Ok(addr) = node_manager.get_new_address_of_type(labels.clone(), address_type) else {
             return Err(MutinyError::WalletOperationFailed);
         };
 
@@ -1416,25 +2149,68 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         amount: u64,
         labels: Vec<String>,
         expiry_delta_secs: Option<u32>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.create_invoice_with_privacy(amount, labels, expiry_delta_secs, None)
+            .await
+    }
+
+    /// Creates a lightning invoice, choosing what its description reveals instead
+    /// of using the wallet's default. The description comes from the first label.
+    pub async fn create_invoice_with_privacy(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling create_lightning_invoice");
 
         let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
 
         let (inv, _fee) = node_manager
-            .create_invoice(amount, labels, expiry_delta_secs)
+            .create_invoice(amount, labels, expiry_delta_secs, privacy)
             .await?;
 
         log_trace!(self.logger, "finished calling create_lightning_invoice");
         Ok(inv)
     }
 
-    /// Gets the current balance of the wallet.
-    /// This includes both on-chain, lightning funds, and federations.
-    ///
-    /// This will not include any funds in an unconfirmed lightning channel.
-    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyError> {
-        log_trace!(self.logger, "calling get_balance");
+    /// Creates a lightning invoice whose route hints are chosen by the caller, to
+    /// leave out private channels with unreliable peers or give the hints directly.
+    pub async fn create_invoice_with_route_hints(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
+        route_hints: RouteHintSelection,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling create_invoice_with_route_hints");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let res = node_manager
+            .create_invoice_with_route_hints(
+                amount,
+                labels,
+                expiry_delta_secs,
+                privacy,
+                route_hints,
+            )
+            .await;
+        log_trace!(
+            self.logger,
+            "finished calling create_invoice_with_route_hints"
+        );
+
+        res
+    }
+
+    /// Gets the current balance of the wallet.
+    /// This includes both on-chain, lightning funds, and federations.
+    ///
+    /// This will not include any funds in an unconfirmed lightning channel.
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyError> {
+        log_trace!(self.logger, "calling get_balance");
 
         let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
 
@@ -1443,6 +2219,38 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(MutinyBalance::new(ln_balance))
     }
 
+    /// Gets the balance broken down by node, including channel reserves
+    /// that are part of the lightning balance but cannot be spent.
+    pub async fn get_detailed_balance(&self) -> Result<MutinyDetailedBalance, MutinyError> {
+        log_trace!(self.logger, "calling get_detailed_balance");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        let balance = node_manager.get_balance().await?;
+        let nodes = node_manager.get_node_balances().await;
+        let reserved = nodes.iter().map(|n| n.reserved).sum();
+        log_trace!(self.logger, "finished calling get_detailed_balance");
+
+        Ok(MutinyDetailedBalance {
+            confirmed: balance.confirmed,
+            unconfirmed: balance.unconfirmed,
+            nodes,
+            reserved,
+        })
+    }
+
+    /// Gets how many bytes each kind of data is using in storage,
+    /// so users running into browser storage quotas know what to prune.
+    pub async fn get_storage_breakdown(&self) -> Result<StorageBreakdown, MutinyError> {
+        log_trace!(self.logger, "calling get_storage_breakdown");
+
+        let sizes = self.storage.value_sizes().await?;
+        let breakdown = StorageBreakdown::from_sizes(&sizes);
+        log_trace!(self.logger, "finished calling get_storage_breakdown");
+
+        Ok(breakdown)
+    }
+
     fn get_invoice_internal(
         &self,
         key: &str,
@@ -1502,20 +2310,32 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         };
 
         let labels_map = self.storage.get_invoice_labels()?;
+        let moderation = get_moderation_settings(&self.storage)?;
+        let rebalances = get_rebalances(&self.storage)?;
 
         let mut activities = Vec::with_capacity(index.len());
         for item in index {
             if item.key.starts_with(PAYMENT_INBOUND_PREFIX_KEY) {
-                if let Some(mutiny_invoice) =
+                if let Some(mut mutiny_invoice) =
                     self.get_invoice_internal(&item.key, true, &labels_map)?
                 {
+                    // the rebalance is shown with its outgoing side
+                    if rebalances.contains_key(&mutiny_invoice.payment_hash) {
+                        continue;
+                    }
+                    moderation.moderate_invoice(&mut mutiny_invoice);
                     activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
                 }
             } else if item.key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY) {
                 if let Some(mutiny_invoice) =
                     self.get_invoice_internal(&item.key, false, &labels_map)?
                 {
-                    activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
+                    match rebalances.get(&mutiny_invoice.payment_hash) {
+                        Some(rebalance) => activities.push(ActivityItem::Rebalance(
+                            rebalance.clone().with_payment(&mutiny_invoice),
+                        )),
+                        None => activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice))),
+                    }
                 }
             } else if item.key.starts_with(CHANNEL_CLOSURE_PREFIX) {
                 if let Some(mut closure) = self.storage.get_data::<ChannelClosure>(&item.key)? {
@@ -1531,6 +2351,10 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     }
                     activities.push(ActivityItem::ChannelClosed(closure));
                 }
+            } else if item.key.starts_with(CHANNEL_PURCHASE_PREFIX) {
+                if let Some(purchase) = self.storage.get_data::<ChannelPurchase>(&item.key)? {
+                    activities.push(ActivityItem::ChannelPurchase(Box::new(purchase)));
+                }
             } else if item.key.starts_with(ONCHAIN_PREFIX) {
                 // convert keys to txid
                 let txid_str = item.key.trim_start_matches(ONCHAIN_PREFIX);
@@ -1539,6 +2363,15 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     continue;
                 };
                 if let Some(tx_details) = node_manager.get_transaction(txid)? {
+                    // the replacement is shown instead of a transaction that was bumped
+                    // with RBF, unless the original ended up confirming
+                    if matches!(
+                        tx_details.confirmation_time,
+                        ConfirmationTime::Unconfirmed { .. }
+                    ) && get_tx_replacement(&self.storage, txid)?.is_some()
+                    {
+                        continue;
+                    }
                     // make sure it is a relevant transaction
                     if tx_details.sent != 0 || tx_details.received != 0 {
                         activities.push(ActivityItem::OnChain(tx_details));
@@ -1563,6 +2396,112 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(activities)
     }
 
+    /// Gets the filters applied to zap comments and direct messages
+    pub fn get_moderation_settings(&self) -> Result<ModerationSettings, MutinyError> {
+        get_moderation_settings(&self.storage)
+    }
+
+    /// Sets the filters applied to zap comments and direct messages.
+    /// Followed pubkeys can be given as npubs or hex.
+    pub fn set_moderation_settings(
+        &self,
+        settings: ModerationSettings,
+    ) -> Result<ModerationSettings, MutinyError> {
+        let settings = settings.normalize()?;
+        persist_moderation_settings(&self.storage, &settings)?;
+        Ok(settings)
+    }
+
+    /// The wallet's nostr identity as an npub, milestone badges are published by it
+    pub fn get_npub(&self) -> Result<String, MutinyError> {
+        let key = nostr_key(self.xprivkey)?;
+        npub(&key.x_only_public_key(&Secp256k1::new()).0)
+    }
+
+    /// Gets whether milestone badges are published to nostr
+    pub fn get_badge_settings(&self) -> Result<BadgeSettings, MutinyError> {
+        get_badge_settings(&self.storage)
+    }
+
+    /// Sets whether milestone badges are published to nostr. Turning it on also
+    /// publishes the milestones reached before, on the next background sync.
+    pub fn set_badge_settings(&self, settings: BadgeSettings) -> Result<(), MutinyError> {
+        persist_badge_settings(&self.storage, &settings)
+    }
+
+    /// Lists the milestones reached so far, newest first. They are recorded
+    /// whether or not badges are published.
+    pub fn list_milestones(&self) -> Result<Vec<EarnedMilestone>, MutinyError> {
+        list_earned_milestones(&self.storage)
+    }
+
+    /// Gets the policy for channels opened to us by peers other than our LSP
+    pub fn get_inbound_channel_policy(&self) -> Result<InboundChannelPolicy, MutinyError> {
+        get_inbound_channel_policy(&self.storage)
+    }
+
+    /// Sets which inbound channel requests are accepted
+    pub fn set_inbound_channel_policy(
+        &self,
+        policy: InboundChannelPolicy,
+    ) -> Result<(), MutinyError> {
+        persist_inbound_channel_policy(&self.storage, &policy)
+    }
+
+    /// The channels peers other than our LSP asked to open, and what we did with them
+    pub fn list_inbound_channel_requests(&self) -> Result<Vec<InboundChannelRequest>, MutinyError> {
+        list_inbound_channel_requests(&self.storage)
+    }
+
+    /// Peers we trust to open channels without a reserve, besides our LSP
+    pub fn list_zero_reserve_peers(&self) -> Result<Vec<PublicKey>, MutinyError> {
+        get_zero_reserve_peers(&self.storage)
+    }
+
+    /// Marks a peer as trusted for zero reserve channels. Their channel requests
+    /// are accepted like our LSP's and channels we open to them don't ask for a reserve.
+    pub fn set_zero_reserve_peer(&self, peer: PublicKey, enabled: bool) -> Result<(), MutinyError> {
+        set_zero_reserve_peer(&self.storage, peer, enabled)
+    }
+
+    /// The overrides set for a peer, the defaults if there are none
+    pub fn get_peer_overrides(&self, peer: &PublicKey) -> Result<PeerOverrides, MutinyError> {
+        get_peer_overrides(&self.storage, peer)
+    }
+
+    /// Every peer with overrides set
+    pub fn list_peer_overrides(&self) -> Result<HashMap<PublicKey, PeerOverrides>, MutinyError> {
+        list_peer_overrides(&self.storage)
+    }
+
+    /// Sets how we connect to and open channels with a peer, setting the defaults
+    /// clears them. Takes effect from the next connection or channel open.
+    pub fn set_peer_overrides(
+        &self,
+        peer: PublicKey,
+        overrides: PeerOverrides,
+    ) -> Result<(), MutinyError> {
+        set_peer_overrides(&self.storage, &peer, &overrides)
+    }
+
+    /// Whether VSS and the auth server are reachable. When they aren't the
+    /// wallet runs on its local state and queues its backups until they are.
+    pub fn get_service_status(&self) -> Result<ServiceStatus, MutinyError> {
+        service_status(&self.storage, self.config.auth_client.as_deref())
+    }
+
+    /// Drops the direct messages that don't pass the moderation settings
+    pub fn filter_direct_messages(
+        &self,
+        messages: Vec<DirectMessage>,
+    ) -> Result<Vec<DirectMessage>, MutinyError> {
+        let moderation = get_moderation_settings(&self.storage)?;
+        Ok(messages
+            .into_iter()
+            .filter(|m| moderation.allows(Some(&m.from), &m.content))
+            .collect())
+    }
+
     pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
         log_trace!(self.logger, "calling get_transaction");
 
@@ -1606,6 +2545,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let index = index.try_read()?.clone().into_iter().collect_vec();
 
         let labels_map = self.storage.get_invoice_labels()?;
+        let moderation = get_moderation_settings(&self.storage)?;
 
         let mut activities = Vec::with_capacity(index.len());
         for item in index {
@@ -1615,9 +2555,10 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                 let hash = sha256::Hash::from_str(payment_hash_str)?;
 
                 if payment_hashes.contains(&hash) {
-                    if let Some(mutiny_invoice) =
+                    if let Some(mut mutiny_invoice) =
                         self.get_invoice_internal(&item.key, true, &labels_map)?
                     {
+                        moderation.moderate_invoice(&mut mutiny_invoice);
                         activities.push(ActivityItem::Lightning(Box::new(mutiny_invoice)));
                     }
                 }
@@ -1640,6 +2581,32 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(activities)
     }
 
+    /// Gets all the activity of one user action, e.g. the on-chain deposit,
+    /// lightning payment and refund of a swap. The id is the
+    /// [`ActivityItem::operation_id`] shared by the items.
+    pub fn get_operation(&self, id: &str) -> Result<Option<Operation>, MutinyError> {
+        log_trace!(self.logger, "calling get_operation");
+
+        let Some(kind) = OperationKind::from_id(id) else {
+            return Ok(None);
+        };
+        let items: Vec<ActivityItem> = self
+            .get_activity(None, None)?
+            .into_iter()
+            .filter(|item| item.operation_id().as_deref() == Some(id))
+            .collect();
+
+        log_trace!(self.logger, "finished calling get_operation");
+        if items.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Operation {
+            id: id.to_string(),
+            kind,
+            items,
+        }))
+    }
+
     pub fn list_invoices(&self) -> Result<Vec<MutinyInvoice>, MutinyError> {
         log_trace!(self.logger, "calling list_invoices");
 
@@ -1695,10 +2662,1006 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling get_invoice_by_hash");
 
-        let res = get_invoice_by_hash(hash, &self.storage, &self.logger);
-        log_trace!(self.logger, "finished calling get_invoice_by_hash");
+        let res = get_invoice_by_hash(hash, &self.storage, &self.logger);
+        log_trace!(self.logger, "finished calling get_invoice_by_hash");
+
+        res
+    }
+
+    /// Gets the details of every failed attempt at paying the given payment hash,
+    /// e.g. which channel failed and the fees that were attempted.
+    pub fn get_payment_failure_details(
+        &self,
+        hash: &sha256::Hash,
+    ) -> Result<Option<PaymentFailureDetails>, MutinyError> {
+        log_trace!(self.logger, "calling get_payment_failure_details");
+
+        let res = get_payment_failure_details(&self.storage, &hash.to_byte_array());
+        log_trace!(self.logger, "finished calling get_payment_failure_details");
+
+        res
+    }
+
+    /// Builds an anonymized summary of our channels and payments to attach to
+    /// bug reports. Nothing leaves the device, show the user
+    /// [`DiagnosticsBundle::to_json`] before they send it anywhere.
+    pub async fn export_diagnostics(&self) -> Result<DiagnosticsBundle, MutinyError> {
+        log_trace!(self.logger, "calling export_diagnostics");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let channels = node_manager.list_channels().await?;
+        let closures = node_manager.list_channel_closures().await?;
+        let invoices = self.list_invoices()?;
+        let failures = invoices
+            .iter()
+            .filter(|i| !i.inbound && i.status == HTLCStatus::Failed)
+            .filter_map(|i| {
+                get_payment_failure_details(&self.storage, &i.payment_hash.to_byte_array())
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let bundle = DiagnosticsBundle::new(
+            self.network,
+            utils::now().as_secs(),
+            &channels,
+            &closures,
+            &invoices,
+            &failures,
+        );
+
+        log_trace!(self.logger, "finished calling export_diagnostics");
+        Ok(bundle)
+    }
+
+    /// Builds the account statement for a calendar month (UTC): opening and
+    /// closing balances for on-chain and lightning, all activity in the month
+    /// and the fees paid. With a `fiat` currency, amounts are also valued at
+    /// the current price, there is no price history to value them at the time.
+    pub async fn generate_statement(
+        &self,
+        year: i32,
+        month: u32,
+        fiat: Option<String>,
+    ) -> Result<Statement, MutinyError> {
+        log_trace!(self.logger, "calling generate_statement");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let balance = self.get_balance().await?;
+        let activity = self.get_activity(None, None)?;
+        let closures = node_manager.list_channel_closures().await?;
+
+        let mut funding_txids: HashSet<Txid> = node_manager
+            .list_channels()
+            .await?
+            .into_iter()
+            .filter_map(|c| c.outpoint.map(|o| o.txid))
+            .collect();
+        funding_txids.extend(
+            closures
+                .iter()
+                .filter_map(|c| c.channel_funding_txo.map(|o| o.txid)),
+        );
+        let closing_txids: HashSet<Txid> = closures.iter().filter_map(|c| c.closing_txid).collect();
+
+        let now = utils::now().as_secs();
+        let fiat = match fiat {
+            Some(currency) => {
+                let current = self.get_bitcoin_price(Some(currency.clone())).await?;
+                let storage = self.storage.clone();
+                let history_currency = currency.clone();
+                // past times use the price recorded then, anything from now on the current one
+                let price_at = move |time: u64| {
+                    if time >= now {
+                        return Some(current);
+                    }
+                    get_price_at(&storage, &history_currency, time)
+                        .ok()
+                        .flatten()
+                };
+                Some((currency, price_at))
+            }
+            None => None,
+        };
+
+        let statement = Statement::new(
+            year,
+            month,
+            now,
+            balance.confirmed + balance.unconfirmed,
+            balance.lightning + balance.closing,
+            &activity,
+            &funding_txids,
+            &closing_txids,
+            fiat,
+        );
+
+        log_trace!(self.logger, "finished calling generate_statement");
+        statement
+    }
+
+    /// Queues a payment request so the user can approve or decline it later.
+    /// Requests for other networks or with expired invoices are rejected.
+    pub fn queue_payment_request(&self, request: PaymentRequest) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling queue_payment_request");
+
+        if request.invoice.network() != self.network {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+
+        if request.is_expired() {
+            return Err(MutinyError::InvoiceExpired);
+        }
+
+        if request.amount_sats().is_none() {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        // don't overwrite a request we already have queued
+        if get_payment_request(&self.storage, &request.id)?.is_none() {
+            log_info!(
+                self.logger,
+                "Queued payment request {} from {}",
+                request.id,
+                request.sender
+            );
+            persist_payment_request(&self.storage, &request)?;
+        }
+        log_trace!(self.logger, "finished calling queue_payment_request");
+
+        Ok(())
+    }
+
+    /// Lists the payment requests waiting for approval, oldest first.
+    /// Expired requests are removed.
+    pub fn list_payment_requests(&self) -> Result<Vec<PaymentRequest>, MutinyError> {
+        log_trace!(self.logger, "calling list_payment_requests");
+
+        let (expired, pending): (Vec<_>, Vec<_>) = list_payment_requests(&self.storage)?
+            .into_iter()
+            .partition(|r| r.is_expired());
+
+        for request in expired {
+            delete_payment_request(&self.storage, &request.id)?;
+        }
+        log_trace!(self.logger, "finished calling list_payment_requests");
+
+        Ok(pending)
+    }
+
+    /// Approves a queued payment request and pays its invoice.
+    /// The request is removed from the queue once the payment is attempted.
+    pub async fn approve_payment_request(
+        &self,
+        id: &str,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling approve_payment_request");
+
+        let request = get_payment_request(&self.storage, id)?.ok_or(MutinyError::NotFound)?;
+        let amt_sats = if request.invoice.amount_milli_satoshis().is_none() {
+            request.amount_sats
+        } else {
+            None
+        };
+
+        let res = self.pay_invoice(&request.invoice, amt_sats, labels).await;
+
+        // keep the request around if we never got to attempt the payment
+        if !matches!(
+            res,
+            Err(MutinyError::NotRunning | MutinyError::InsufficientBalance)
+        ) {
+            delete_payment_request(&self.storage, id)?;
+        }
+        log_trace!(self.logger, "finished calling approve_payment_request");
+
+        res
+    }
+
+    /// Declines a queued payment request, removing it from the queue.
+    pub fn decline_payment_request(&self, id: &str) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling decline_payment_request");
+
+        if get_payment_request(&self.storage, id)?.is_none() {
+            return Err(MutinyError::NotFound);
+        }
+        delete_payment_request(&self.storage, id)?;
+        log_trace!(self.logger, "finished calling decline_payment_request");
+
+        Ok(())
+    }
+
+    async fn watch_payment_requests(&self) {
+        loop {
+            if let Err(e) = self.check_payment_requests().await {
+                log_debug!(self.logger, "Failed to check payment requests: {e}");
+            }
+
+            for _ in 0..PAYMENT_REQUEST_POLL_INTERVAL_SECS {
+                match self.node_manager.as_ref() {
+                    Some(nm) if !nm.stop.load(std::sync::atomic::Ordering::Relaxed) => {}
+                    _ => return,
+                }
+                sleep(1_000).await;
+            }
+        }
+    }
+
+    /// Queues the payment requests other wallets sent to our npub, so the user
+    /// can approve or decline them. Ones that can't be paid are dropped.
+    async fn check_payment_requests(&self) -> Result<(), MutinyError> {
+        let key = nostr_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let since: u64 = self
+            .storage
+            .get_data(PAYMENT_REQUEST_MESSAGES_SINCE_KEY)?
+            .unwrap_or_default();
+        let requests = fetch_payment_request_dms(&key, &relays, since).await?;
+
+        // approved and declined requests are deleted, so start after the newest
+        // one we've seen for them not to be queued again
+        let mut newest = since;
+        for (request, created_at) in requests {
+            newest = newest.max(created_at + 1);
+            let id = request.id.clone();
+            if let Err(e) = self.queue_payment_request(request) {
+                log_debug!(self.logger, "Dropped payment request {id}: {e}");
+            }
+        }
+        self.storage
+            .write_data(PAYMENT_REQUEST_MESSAGES_SINCE_KEY.to_string(), newest, None)?;
+
+        Ok(())
+    }
+
+    /// Creates a shareable payment link for an invoice of the given amount.
+    /// The link falls back to our lightning address once the invoice expires.
+    pub async fn create_payment_link(
+        &self,
+        amount_sats: u64,
+        memo: Option<String>,
+        expiry_secs: Option<u32>,
+    ) -> Result<PaymentLink, MutinyError> {
+        log_trace!(self.logger, "calling create_payment_link");
+
+        let labels = memo.clone().into_iter().collect();
+        let invoice = self
+            .create_lightning_invoice(amount_sats, labels, expiry_secs)
+            .await?;
+        let bolt11 = invoice.bolt11.ok_or(MutinyError::InvoiceCreationFailed)?;
+        let fallback_address = self.get_lightning_address(None).await?;
+        let link = PaymentLink::new(bolt11, amount_sats, memo, fallback_address)?;
+        persist_payment_link(&self.storage, &link)?;
+
+        log_trace!(self.logger, "finished calling create_payment_link");
+        Ok(link)
+    }
+
+    /// Opens a payment link, returning what to pay. If it's one of our own
+    /// links the click is recorded on it.
+    pub fn resolve_payment_link(&self, url: &str) -> Result<PaymentLinkTarget, MutinyError> {
+        log_trace!(self.logger, "calling resolve_payment_link");
+
+        let target = parse_payment_link(url)?;
+        if let Some(mut link) = get_payment_link(&self.storage, &target.id)? {
+            link.events.push(PaymentLinkEvent::Clicked {
+                at: utils::now().as_secs(),
+            });
+            self.update_payment_link_status(&mut link);
+            persist_payment_link(&self.storage, &link)?;
+        }
+
+        log_trace!(self.logger, "finished calling resolve_payment_link");
+        Ok(target)
+    }
+
+    /// Lists our payment links, newest first, with their click and paid events
+    pub fn list_payment_links(&self) -> Result<Vec<PaymentLink>, MutinyError> {
+        log_trace!(self.logger, "calling list_payment_links");
+
+        let mut links = list_payment_links(&self.storage)?;
+        for link in links.iter_mut() {
+            if self.update_payment_link_status(link) {
+                persist_payment_link(&self.storage, link)?;
+            }
+        }
+
+        log_trace!(self.logger, "finished calling list_payment_links");
+        Ok(links)
+    }
+
+    /// Records the paid event once the link's invoice is paid, returns true if it was added
+    fn update_payment_link_status(&self, link: &mut PaymentLink) -> bool {
+        if link.is_paid() {
+            return false;
+        }
+        match get_invoice_by_hash(link.invoice.payment_hash(), &self.storage, &self.logger) {
+            Ok(invoice) if invoice.status == HTLCStatus::Succeeded => {
+                link.events.push(PaymentLinkEvent::Paid {
+                    at: invoice.last_updated,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Splits a bill between the given contacts, creating an invoice for each
+    /// contact's share. The invoices are labeled with the contact so the user
+    /// can send them however they like, and the memo is their description.
+    /// Once every share is paid a `SplitRequestCompleted` event is emitted.
+    pub async fn request_split(
+        &self,
+        total_sats: u64,
+        participants: Vec<String>,
+        memo: Option<String>,
+    ) -> Result<SplitRequest, MutinyError> {
+        log_trace!(self.logger, "calling request_split");
+
+        let amounts = split_amount(total_sats, participants.len());
+        if amounts.is_empty() || amounts.iter().any(|a| *a == 0) {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        // checked before any invoice is made, so a bad contact leaves nothing behind
+        for contact_id in participants.iter() {
+            if self.storage.get_contact(contact_id)?.is_none() {
+                return Err(MutinyError::NotFound);
+            }
+        }
+
+        let mut shares = Vec::with_capacity(participants.len());
+        for (contact_id, amount_sats) in participants.into_iter().zip(amounts) {
+            // the first label is the invoice's description
+            let labels = match memo.as_ref() {
+                Some(memo) => vec![memo.clone(), contact_id.clone()],
+                None => vec![contact_id.clone()],
+            };
+            let invoice = self
+                .create_lightning_invoice(amount_sats, labels, None)
+                .await?
+                .bolt11
+                .ok_or(MutinyError::InvoiceCreationFailed)?;
+
+            shares.push(SplitShare {
+                contact_id,
+                amount_sats,
+                invoice,
+                paid: false,
+            });
+        }
+
+        let split = SplitRequest {
+            id: Uuid::new_v4().to_string(),
+            memo,
+            total_sats,
+            shares,
+            created_at: utils::now().as_secs(),
+            completed: false,
+        };
+        persist_new_split_request(&self.storage, &split)?;
+        log_trace!(self.logger, "finished calling request_split");
+
+        Ok(split)
+    }
+
+    /// Gets a split request, including which shares have been paid.
+    pub fn get_split_request(&self, id: &str) -> Result<Option<SplitRequest>, MutinyError> {
+        get_split_request(&self.storage, id)
+    }
+
+    /// Lists all split requests, newest first.
+    pub fn list_split_requests(&self) -> Result<Vec<SplitRequest>, MutinyError> {
+        list_split_requests(&self.storage)
+    }
+
+    /// Pays the total amount split between the given lightning addresses,
+    /// proportionally to each recipient's weight. The payments are made concurrently
+    /// and are all labeled with the split's label so they can be grouped in the activity.
+    ///
+    /// Returns an error only if none of the payments succeeded, otherwise the result
+    /// of each payment is recorded on its recipient.
+    pub async fn send_split(
+        &self,
+        recipients: Vec<(String, u32)>,
+        total_sats: u64,
+    ) -> Result<SplitSend, MutinyError> {
+        log_trace!(self.logger, "calling send_split");
+
+        if recipients.is_empty() || total_sats == 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let addresses = recipients
+            .iter()
+            .map(|(address, _)| LightningAddress::from_str(address))
+            .collect::<Result<Vec<_>, _>>()?;
+        let weights: Vec<u32> = recipients.iter().map(|(_, weight)| *weight).collect();
+        let amounts = split_weighted(total_sats, &weights)?;
+
+        let mut split = SplitSend {
+            id: Uuid::new_v4().to_string(),
+            total_sats,
+            recipients: vec![],
+            created_at: utils::now().as_secs(),
+        };
+        let label = split.label();
+
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|_| MutinyError::LnUrlFailure)?;
+        let payments = addresses
+            .iter()
+            .zip(amounts.iter())
+            .map(|(address, amount)| {
+                let client = &client;
+                let label = label.clone();
+                async move {
+                    // recipients with no weight don't get anything
+                    if *amount == 0 {
+                        return Ok(None);
+                    }
+                    let invoice = fetch_invoice(client, address, *amount).await?;
+                    self.pay_invoice(&invoice, None, vec![label])
+                        .await
+                        .map(Some)
+                }
+            });
+        let results = futures_util::future::join_all(payments).await;
+
+        let mut first_error = None;
+        for ((address, weight), (amount_sats, result)) in addresses
+            .into_iter()
+            .zip(weights)
+            .zip(amounts.into_iter().zip(results))
+        {
+            let (payment_hash, error) = match result {
+                Ok(invoice) => (invoice.map(|i| i.payment_hash.to_string()), None),
+                Err(e) => {
+                    log_warn!(self.logger, "Failed to pay {address} in split: {e}");
+                    let error = e.to_string();
+                    first_error.get_or_insert(e);
+                    (None, Some(error))
+                }
+            };
+            split.recipients.push(SplitSendRecipient {
+                address: address.to_string(),
+                weight,
+                amount_sats,
+                payment_hash,
+                error,
+            });
+        }
+        persist_split_send(&self.storage, &split)?;
+        log_trace!(self.logger, "finished calling send_split");
+
+        match first_error {
+            Some(e) if split.paid_sats() == 0 => Err(e),
+            _ => Ok(split),
+        }
+    }
+
+    /// Gets a split payment made with [`MutinyWallet::send_split`].
+    pub fn get_split_send(&self, id: &str) -> Result<Option<SplitSend>, MutinyError> {
+        get_split_send(&self.storage, id)
+    }
+
+    /// Deposits on-chain funds with a swap provider to receive them over lightning.
+    ///
+    /// The deposit can only be claimed by the provider with the preimage of the returned
+    /// deposit's invoice, which they get by paying it. If they haven't paid by the timeout
+    /// height, the deposit is refunded to our wallet automatically.
+    pub async fn create_swap_deposit(
+        &self,
+        provider_pubkey: PublicKey,
+        amount_sats: u64,
+        timeout_height: u32,
+        fee_rate: Option<u64>,
+    ) -> Result<SwapDeposit, MutinyError> {
+        log_trace!(self.logger, "calling create_swap_deposit");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        if amount_sats < DUST_LIMIT {
+            return Err(MutinyError::BadAmountError);
+        }
+        // a timeout in the past would let us refund before the provider could claim
+        if timeout_height <= self.get_best_block().await?.height {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let label = format!("{SWAP_DEPOSIT_PREFIX}{id}");
+        let invoice = self
+            .create_lightning_invoice(amount_sats, vec![label.clone()], None)
+            .await?;
+        let bolt11 = invoice.bolt11.ok_or(MutinyError::InvoiceCreationFailed)?;
+
+        let secp = Secp256k1::new();
+        let refund_key_index = next_refund_key_index(&self.storage)?;
+        let refund_key = derive_refund_key(&secp, self.xprivkey, refund_key_index)?;
+        let refund_pubkey = PublicKey::from_secret_key(&secp, &refund_key);
+
+        let script = deposit_script(
+            &invoice.payment_hash,
+            &provider_pubkey,
+            &refund_pubkey,
+            timeout_height,
+        );
+        let address = deposit_address(&script, self.network);
+
+        let mut deposit = SwapDeposit {
+            id,
+            invoice: bolt11.to_string(),
+            payment_hash: invoice.payment_hash,
+            provider_pubkey,
+            refund_pubkey,
+            refund_key_index,
+            timeout_height,
+            address: address.to_string(),
+            amount_sats,
+            funding_txid: None,
+            funding_vout: None,
+            refund_txid: None,
+            status: SwapDepositStatus::Pending,
+            error: None,
+            created_at: utils::now().as_secs(),
+        };
+        // persist before funding so the refund key is never lost
+        persist_swap_deposit(&self.storage, &deposit)?;
+
+        let txid = self
+            .send_to_address(address.clone(), amount_sats, vec![label], fee_rate, vec![])
+            .await?;
+        let vout = node_manager
+            .wallet
+            .get_transaction(txid)?
+            .and_then(|details| details.transaction)
+            .and_then(|tx| {
+                tx.output
+                    .iter()
+                    .position(|o| o.script_pubkey == address.script_pubkey())
+            })
+            .ok_or(MutinyError::WalletOperationFailed)?;
+
+        deposit.funding_txid = Some(txid);
+        deposit.funding_vout = Some(vout as u32);
+        persist_swap_deposit(&self.storage, &deposit)?;
+        log_trace!(self.logger, "finished calling create_swap_deposit");
+
+        Ok(deposit)
+    }
+
+    /// Gets a swap deposit made with [`MutinyWallet::create_swap_deposit`].
+    pub fn get_swap_deposit(&self, id: &str) -> Result<Option<SwapDeposit>, MutinyError> {
+        get_swap_deposit(&self.storage, id)
+    }
+
+    /// Lists all swap deposits and their refund status, newest first.
+    pub fn list_swap_deposits(&self) -> Result<Vec<SwapDeposit>, MutinyError> {
+        list_swap_deposits(&self.storage)
+    }
+
+    /// Gets the payment card, a single blob of all the static identifiers
+    /// that can be used to pay this wallet. The node pubkey and npub are
+    /// filled in from the running node and the wallet's nostr key.
+    pub async fn get_payment_card(&self) -> Result<PaymentCard, MutinyError> {
+        log_trace!(self.logger, "calling get_payment_card");
+
+        let card = get_payment_card(&self.storage)?.unwrap_or_default();
+        let card = self.with_own_identifiers(card).await?;
+        log_trace!(self.logger, "finished calling get_payment_card");
+
+        Ok(card)
+    }
+
+    /// Sets identifiers on the payment card, bumping its version if anything changed.
+    pub async fn update_payment_card(
+        &self,
+        update: PaymentCardUpdate,
+    ) -> Result<PaymentCard, MutinyError> {
+        log_trace!(self.logger, "calling update_payment_card");
+
+        update.validate(self.network)?;
+        let mut card = get_payment_card(&self.storage)?.unwrap_or_default();
+        if card.update(update) {
+            card.version += 1;
+            card.updated_at = utils::now().as_secs();
+            persist_payment_card(&self.storage, &card)?;
+        }
+        let card = self.with_own_identifiers(card).await?;
+        log_trace!(self.logger, "finished calling update_payment_card");
+
+        Ok(card)
+    }
+
+    /// Fills in the identifiers that come from the wallet's own keys
+    async fn with_own_identifiers(
+        &self,
+        mut card: PaymentCard,
+    ) -> Result<PaymentCard, MutinyError> {
+        if let Some(node_manager) = self.node_manager.as_ref() {
+            card.node_pubkey = node_manager.list_nodes().await?.first().copied();
+        }
+        card.npub = Some(self.get_npub()?);
+        Ok(card)
+    }
+
+    /// Registers another lightning address that pays this wallet. Aliases are
+    /// handed out according to the rotation policy so long-term payers can't
+    /// link all of the wallet's receives to one identifier.
+    pub async fn add_lightning_address_alias(
+        &self,
+        address: String,
+    ) -> Result<LnAddressAliases, MutinyError> {
+        log_trace!(self.logger, "calling add_lightning_address_alias");
+
+        let address = LightningAddress::from_str(&address)?;
+        let mut aliases = get_ln_address_aliases(&self.storage)?;
+        aliases.add(&address, utils::now().as_secs())?;
+        persist_ln_address_aliases(&self.storage, &aliases)?;
+        self.sync_shared_lightning_address(&aliases).await?;
+        log_trace!(self.logger, "finished calling add_lightning_address_alias");
+
+        Ok(aliases)
+    }
+
+    /// Removes a lightning address alias, it will no longer be handed out.
+    pub async fn remove_lightning_address_alias(
+        &self,
+        address: String,
+    ) -> Result<LnAddressAliases, MutinyError> {
+        log_trace!(self.logger, "calling remove_lightning_address_alias");
+
+        let address = LightningAddress::from_str(&address)?.to_string();
+        let mut aliases = get_ln_address_aliases(&self.storage)?;
+        if !aliases.remove(&address) {
+            return Err(MutinyError::NotFound);
+        }
+        persist_ln_address_aliases(&self.storage, &aliases)?;
+        self.sync_shared_lightning_address(&aliases).await?;
+        log_trace!(
+            self.logger,
+            "finished calling remove_lightning_address_alias"
+        );
+
+        Ok(aliases)
+    }
+
+    /// Lists the lightning address aliases along with the rotation policy.
+    pub fn list_lightning_address_aliases(&self) -> Result<LnAddressAliases, MutinyError> {
+        get_ln_address_aliases(&self.storage)
+    }
+
+    /// Sets how lightning address aliases are handed out.
+    pub fn set_lightning_address_rotation(
+        &self,
+        policy: AliasRotationPolicy,
+    ) -> Result<(), MutinyError> {
+        if policy == (AliasRotationPolicy::TimeBased { interval_secs: 0 }) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let mut aliases = get_ln_address_aliases(&self.storage)?;
+        aliases.policy = policy;
+        persist_ln_address_aliases(&self.storage, &aliases)
+    }
+
+    /// Gets the lightning address to give out, optionally to a specific contact.
+    /// Rotates the shared alias when due and keeps the payment card in sync with it.
+    pub async fn get_lightning_address(
+        &self,
+        contact_id: Option<String>,
+    ) -> Result<Option<String>, MutinyError> {
+        log_trace!(self.logger, "calling get_lightning_address");
+
+        let mut aliases = get_ln_address_aliases(&self.storage)?;
+        let before = aliases.clone();
+        let address = aliases.select(contact_id.as_deref(), utils::now().as_secs());
+        if aliases != before {
+            persist_ln_address_aliases(&self.storage, &aliases)?;
+            self.sync_shared_lightning_address(&aliases).await?;
+        }
+        log_trace!(self.logger, "finished calling get_lightning_address");
+
+        Ok(address)
+    }
+
+    async fn sync_shared_lightning_address(
+        &self,
+        aliases: &LnAddressAliases,
+    ) -> Result<(), MutinyError> {
+        let shared = aliases.shared().map(|a| a.address.clone());
+        let card = self.get_payment_card().await?;
+        if card.lightning_address != shared {
+            let update = PaymentCardUpdate {
+                lightning_address: Some(shared.unwrap_or_default()),
+                ..Default::default()
+            };
+            self.update_payment_card(update).await?;
+        }
+        Ok(())
+    }
+
+    /// Grants an embedding app access to the wallet. Returns the grant and the
+    /// api key the app uses to open an [`AppSession`], the key is not stored
+    /// and cannot be retrieved again.
+    pub fn create_app_grant(
+        &self,
+        name: String,
+        permissions: Vec<AppPermission>,
+        spend_limit_sats: Option<u64>,
+    ) -> Result<(AppGrant, String), MutinyError> {
+        log_trace!(self.logger, "calling create_app_grant");
+
+        if permissions.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let (grant, api_key) = AppGrant::new(name, permissions, spend_limit_sats)?;
+        persist_app_grant(&self.storage, &grant)?;
+        log_trace!(self.logger, "finished calling create_app_grant");
+
+        Ok((grant, api_key))
+    }
+
+    /// Lists all app grants, newest first.
+    pub fn list_app_grants(&self) -> Result<Vec<AppGrant>, MutinyError> {
+        list_app_grants(&self.storage)
+    }
+
+    /// Revokes an app's access, any open sessions stop working immediately.
+    pub fn revoke_app_grant(&self, id: &str) -> Result<AppGrant, MutinyError> {
+        log_trace!(self.logger, "calling revoke_app_grant");
+
+        let mut grant = get_app_grant(&self.storage, id)?.ok_or(MutinyError::NotFound)?;
+        grant.revoked = true;
+        persist_app_grant(&self.storage, &grant)?;
+        log_trace!(self.logger, "finished calling revoke_app_grant");
+
+        Ok(grant)
+    }
+
+    /// Opens a view of the wallet limited to what the api key's grant allows.
+    pub fn open_app_session(&self, api_key: String) -> Result<AppSession<S>, MutinyError> {
+        AppSession::new(self.clone(), api_key)
+    }
+
+    /// Queues a job to run in the background. Jobs are persisted and will
+    /// be resumed if the wallet is restarted before they finish.
+    pub fn submit_job(&self, kind: JobKind) -> Result<Job, MutinyError> {
+        log_trace!(self.logger, "calling submit_job");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+
+        let job = Job::new(kind);
+        persist_job(&self.storage, &job)?;
+        // claimed before it's spawned so resuming the wallet doesn't start it again
+        let job = start_job(node_manager.clone(), &job.id)?.unwrap_or(job);
+        log_trace!(self.logger, "finished calling submit_job");
+
+        Ok(job)
+    }
+
+    /// Rescans the on-chain wallet in the background, skipping transactions
+    /// confirmed before the start height. The gap limit defaults to the configured one.
+    /// Returns the job so its progress can be followed with [`MutinyWallet::get_job`].
+    pub fn rescan_onchain(
+        &self,
+        start_height: u32,
+        gap_limit: Option<usize>,
+    ) -> Result<Job, MutinyError> {
+        if gap_limit == Some(0) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        self.submit_job(JobKind::Rescan {
+            start_height,
+            gap_limit,
+        })
+    }
+
+    /// Gets a job by its id.
+    pub fn get_job(&self, id: &str) -> Result<Option<Job>, MutinyError> {
+        get_job(&self.storage, id)
+    }
+
+    /// Lists all jobs, newest first.
+    pub fn list_jobs(&self) -> Result<Vec<Job>, MutinyError> {
+        list_jobs(&self.storage)
+    }
+
+    /// Requests a job to be cancelled. Jobs that have not started yet
+    /// are cancelled immediately, running jobs stop before their next step.
+    pub fn cancel_job(&self, id: &str) -> Result<Job, MutinyError> {
+        cancel_job(&self.storage, id)
+    }
+
+    /// Exports a static channel backup: which channels each node has and with
+    /// whom, encrypted to a key from the seed. It's the last resort when the
+    /// wallet state is lost and VSS can't restore it. It holds no channel
+    /// state, so it only needs exporting again after opening channels.
+    pub async fn export_scb(&self) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling export_scb");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let backup = create_scb(self.network, node_manager.channel_backups().await);
+        let scb = encrypt_scb(&channel_backup_key(self.xprivkey)?, &backup)?;
+
+        log_trace!(self.logger, "finished calling export_scb");
+        Ok(scb)
+    }
+
+    /// Recovers the funds in the channels of a static channel backup made by
+    /// this seed. We reconnect to each peer, which force closes the channel
+    /// with its latest commitment when it sees we lost it, and our output is
+    /// swept to the wallet once that confirms. Nothing of ours is broadcast
+    /// from the backup. Channels we still have the state for are left alone.
+    /// Returns how many channels are being recovered.
+    pub async fn recover_from_scb(&self, scb: &str) -> Result<usize, MutinyError> {
+        log_trace!(self.logger, "calling recover_from_scb");
+
+        let backup = decrypt_scb(&channel_backup_key(self.xprivkey)?, scb)?;
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let restored = node_manager.restore_scb(&backup).await?;
+        log_info!(
+            self.logger,
+            "Recovering {restored} of {} channels from static channel backup",
+            backup.channel_count()
+        );
+
+        log_trace!(self.logger, "finished calling recover_from_scb");
+        Ok(restored)
+    }
+
+    /// The channels being recovered from a static channel backup and how far along they are
+    pub fn list_scb_recoveries(&self) -> Result<Vec<ScbRecovery>, MutinyError> {
+        list_scb_recoveries(&self.storage)
+    }
+
+    pub fn get_watchtower_config(&self) -> Result<WatchtowerConfig, MutinyError> {
+        get_watchtower_config(&self.storage)
+    }
+
+    /// Sets the watchtowers justice transactions are registered with, an empty
+    /// list turns the watchtower client off. Only commitments made from now on
+    /// are covered.
+    pub fn set_watchtowers(&self, towers: Vec<String>) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_watchtowers");
+
+        let towers = towers
+            .into_iter()
+            .map(|t| {
+                url::Url::parse(t.trim())
+                    .map(|u| u.as_str().trim_end_matches('/').to_string())
+                    .map_err(|_| MutinyError::InvalidArgumentsError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut config = get_watchtower_config(&self.storage)?;
+        // justice transactions all pay to the same address, so it's revealed once
+        if !towers.is_empty() && config.sweep_script.is_none() {
+            let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+            let address = node_manager.get_new_address(vec![WATCHTOWER_LABEL.to_string()])?;
+            config.sweep_script = Some(address.script_pubkey());
+        }
+        config.towers = towers;
+        persist_watchtower_config(&self.storage, &config)?;
+
+        log_trace!(self.logger, "finished calling set_watchtowers");
+        Ok(())
+    }
+
+    /// Justice transactions some watchtower hasn't accepted yet
+    pub fn list_pending_justice_blobs(&self) -> Result<Vec<JusticeBlob>, MutinyError> {
+        list_justice_blobs(&self.storage)
+    }
+
+    /// Creates an invoice for an amount in fiat, like a merchant pricing in dollars.
+    /// The sat amount is locked at the current price and the fiat amount is put in
+    /// the description. Until it is paid or `validity_secs` runs out, the invoice
+    /// is replaced whenever its quote expires or the price moves too far.
+    pub async fn create_invoice_fiat(
+        &self,
+        fiat_amount: f64,
+        currency: String,
+        validity_secs: u64,
+    ) -> Result<FiatInvoice, MutinyError> {
+        log_trace!(self.logger, "calling create_invoice_fiat");
+
+        if validity_secs == 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let currency = currency.to_lowercase();
+        let price = self.get_bitcoin_price(Some(currency.clone())).await?;
+        let amount_sats = fiatinvoice::sats_for_fiat(fiat_amount, price)?;
+        let invoice = self
+            .create_lightning_invoice(
+                amount_sats,
+                vec![fiat_description(fiat_amount, &currency)],
+                Some(validity_secs.min(MAX_QUOTE_SECS) as u32),
+            )
+            .await?;
+        let bolt11 = invoice.bolt11.ok_or(MutinyError::InvoiceCreationFailed)?;
+
+        let fiat_invoice = FiatInvoice::new(fiat_amount, currency, price, bolt11, validity_secs)?;
+        persist_fiat_invoice(&self.storage, &fiat_invoice)?;
+
+        log_trace!(self.logger, "finished calling create_invoice_fiat");
+        Ok(fiat_invoice)
+    }
+
+    /// Gets a fiat priced invoice, with its current quote
+    pub fn get_fiat_invoice(&self, id: &str) -> Result<Option<FiatInvoice>, MutinyError> {
+        get_fiat_invoice(&self.storage, id)
+    }
+
+    /// Lists the fiat priced invoices, newest first
+    pub fn list_fiat_invoices(&self) -> Result<Vec<FiatInvoice>, MutinyError> {
+        list_fiat_invoices(&self.storage)
+    }
+
+    /// Expiry watcher for fiat priced invoices, runs until the wallet is stopped
+    async fn watch_fiat_invoices(&self) {
+        loop {
+            if let Err(e) = self.check_fiat_invoices().await {
+                log_warn!(self.logger, "Failed to check fiat invoices: {e}");
+            }
+
+            for _ in 0..FIAT_INVOICE_WATCH_INTERVAL_SECS {
+                match self.node_manager.as_ref() {
+                    Some(nm) if !nm.stop.load(std::sync::atomic::Ordering::Relaxed) => {}
+                    _ => return,
+                }
+                sleep(1_000).await;
+            }
+        }
+    }
 
-        res
+    /// Marks open fiat invoices paid or expired and requotes the rest when needed
+    async fn check_fiat_invoices(&self) -> Result<(), MutinyError> {
+        let open = list_fiat_invoices(&self.storage)?
+            .into_iter()
+            .filter(|i| i.status == FiatInvoiceStatus::Open);
+
+        for mut invoice in open {
+            let paid = invoice.payment_hashes.iter().any(|hash| {
+                get_invoice_by_hash(hash, &self.storage, &self.logger)
+                    .is_ok_and(|i| i.status == HTLCStatus::Succeeded)
+            });
+            let now = utils::now().as_secs();
+            if paid {
+                invoice.status = FiatInvoiceStatus::Paid;
+            } else if now >= invoice.expires_at {
+                invoice.status = FiatInvoiceStatus::Expired;
+            } else {
+                // without a price we can still replace an expired quote at the old one
+                let price = self
+                    .get_bitcoin_price(Some(invoice.currency.clone()))
+                    .await
+                    .ok();
+                if !invoice.needs_requote(now, price) {
+                    continue;
+                }
+                let price = price.unwrap_or(invoice.price);
+                let amount_sats = fiatinvoice::sats_for_fiat(invoice.fiat_amount, price)?;
+                let new = self
+                    .create_lightning_invoice(
+                        amount_sats,
+                        vec![fiat_description(invoice.fiat_amount, &invoice.currency)],
+                        Some(quote_secs(now, invoice.expires_at) as u32),
+                    )
+                    .await?;
+                let bolt11 = new.bolt11.ok_or(MutinyError::InvoiceCreationFailed)?;
+                log_debug!(
+                    self.logger,
+                    "Requoting fiat invoice {} at {price} {}",
+                    invoice.id,
+                    invoice.currency
+                );
+                invoice.requote(price, bolt11)?;
+            }
+            persist_fiat_invoice(&self.storage, &invoice)?;
+        }
+
+        Ok(())
     }
 
     /// Stops all of the nodes and background processes.
@@ -1797,7 +3760,10 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         self.start().await?;
 
         let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
-        node_manager.wallet.full_sync(FULL_SYNC_STOP_GAP).await?;
+        node_manager
+            .wallet
+            .full_sync(node_manager.wallet.gap_limit())
+            .await?;
 
         log_trace!(self.logger, "finished calling reset_onchain_tracker");
         Ok(())
@@ -1860,6 +3826,77 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         self.safe_mode
     }
 
+    /// Returns true if the wallet was started read-only because it is
+    /// already running in another instance on this device, e.g. another tab.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Gets a contact or profile image as a `data:` url, from the cache when it's
+    /// fresh. Stale images are fetched again through the CDN but still returned
+    /// when that fails, so avatars show while offline.
+    pub async fn get_avatar(&self, url: String) -> Result<Option<String>, MutinyError> {
+        let now = utils::now().as_secs();
+        let cached = get_cached_avatar(&self.storage, &url)?;
+        if let Some(avatar) = cached.as_ref().filter(|a| a.is_fresh(now)) {
+            touch_avatar(&self.storage, &url)?;
+            return Ok(Some(avatar.data_url()));
+        }
+
+        match self.fetch_and_cache_avatar(&url).await {
+            Ok(avatar) => Ok(Some(avatar.data_url())),
+            Err(e) => {
+                log_debug!(self.logger, "Could not fetch avatar {url}: {e}");
+                Ok(cached.map(|a| a.data_url()))
+            }
+        }
+    }
+
+    /// Fetches the images that aren't cached or have gone stale, all of the
+    /// contacts' images when no urls are given. Returns how many were fetched.
+    pub async fn prewarm_avatars(&self, urls: Option<Vec<String>>) -> Result<usize, MutinyError> {
+        let urls = match urls {
+            Some(urls) => urls,
+            None => self
+                .storage
+                .get_contacts()?
+                .into_values()
+                .filter_map(|c| c.image_url)
+                .collect(),
+        };
+
+        let now = utils::now().as_secs();
+        let mut fetched = 0;
+        for url in urls.into_iter().unique() {
+            if get_cached_avatar(&self.storage, &url)?.is_some_and(|a| a.is_fresh(now)) {
+                continue;
+            }
+            match self.fetch_and_cache_avatar(&url).await {
+                Ok(_) => fetched += 1,
+                Err(e) => log_debug!(self.logger, "Could not fetch avatar {url}: {e}"),
+            }
+        }
+
+        Ok(fetched)
+    }
+
+    /// Removes every cached contact and profile image
+    pub fn clear_avatar_cache(&self) -> Result<(), MutinyError> {
+        clear_avatar_cache(&self.storage)
+    }
+
+    async fn fetch_and_cache_avatar(&self, url: &str) -> Result<CachedAvatar, MutinyError> {
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let (avatar, ttl) = fetch_avatar(&client, url).await?;
+        // the CDN asked for it not to be stored
+        if ttl.is_some() {
+            cache_avatar(&self.storage, &avatar)?;
+        }
+        Ok(avatar)
+    }
+
     /// Gets the current bitcoin price in USD.
     pub async fn get_bitcoin_price(&self, fiat: Option<String>) -> Result<f32, MutinyError> {
         log_trace!(self.logger, "calling get_bitcoin_price");
@@ -1910,6 +3947,664 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         res
     }
 
+    /// Gets the user's denomination preferences
+    pub fn get_denomination_preferences(&self) -> Result<DenominationPreferences, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(DENOMINATION_PREFERENCES_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Sets the user's denomination preferences, they are synced to the
+    /// user's other devices along with the rest of the wallet settings
+    pub fn set_denomination_preferences(
+        &self,
+        prefs: DenominationPreferences,
+    ) -> Result<(), MutinyError> {
+        let mut settings = self.get_wallet_settings()?;
+        settings.denomination = prefs;
+        self.set_wallet_settings(settings)
+    }
+
+    /// The operator's config fetched at startup with the user's overrides applied,
+    /// if the wallet was set up to fetch one
+    pub fn get_remote_config(&self) -> Result<Option<RemoteConfig>, MutinyError> {
+        let overrides = get_remote_config_overrides(&self.storage)?;
+        Ok(get_remote_config(&self.storage)?.map(|c| c.with_overrides(&overrides)))
+    }
+
+    pub fn get_remote_config_overrides(&self) -> Result<RemoteConfigOverrides, MutinyError> {
+        get_remote_config_overrides(&self.storage)
+    }
+
+    /// Sets what the user picked over the remote config, LSP and fee api
+    /// changes are used from the next startup
+    pub fn set_remote_config_overrides(
+        &self,
+        overrides: RemoteConfigOverrides,
+    ) -> Result<(), MutinyError> {
+        set_remote_config_overrides(&self.storage, overrides)
+    }
+
+    /// Gets the preferences that are synced across the user's devices
+    pub fn get_wallet_settings(&self) -> Result<WalletSettings, MutinyError> {
+        let mut settings = get_wallet_settings(&self.storage)?.unwrap_or_default();
+        settings.denomination = self.get_denomination_preferences()?;
+        Ok(settings)
+    }
+
+    /// Saves the preferences and queues them to be published to the user's
+    /// relays as a NIP-78 event encrypted to the wallet key
+    pub fn set_wallet_settings(&self, mut settings: WalletSettings) -> Result<(), MutinyError> {
+        if settings
+            .relays
+            .iter()
+            .any(|r| !r.starts_with("wss://") && !r.starts_with("ws://"))
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        settings.updated_at = utils::now().as_secs();
+        self.save_wallet_settings(&settings)?;
+
+        let key = settings_key(self.xprivkey)?;
+        queue_settings_event(&self.storage, &key, &settings)
+    }
+
+    fn save_wallet_settings(&self, settings: &WalletSettings) -> Result<(), MutinyError> {
+        self.storage.write_data(
+            DENOMINATION_PREFERENCES_KEY.to_string(),
+            &settings.denomination,
+            None,
+        )?;
+        persist_wallet_settings(&self.storage, settings)
+    }
+
+    /// Pulls the wallet settings from the user's relays, whichever of the
+    /// local or remote settings were changed last wins. Works without VSS.
+    pub async fn sync_wallet_settings(&self) -> Result<WalletSettings, MutinyError> {
+        log_trace!(self.logger, "calling sync_wallet_settings");
+
+        let key = settings_key(self.xprivkey)?;
+        let local = get_wallet_settings(&self.storage)?;
+        let relays = local.clone().unwrap_or_default().sync_relays();
+        let remote = fetch_remote_settings(&key, &relays).await?;
+
+        let settings = match (local, remote) {
+            (Some(local), Some(remote)) if remote.updated_at > local.updated_at => {
+                log_info!(self.logger, "Applying wallet settings from another device");
+                self.save_wallet_settings(&remote)?;
+                remote
+            }
+            (None, Some(remote)) => {
+                self.save_wallet_settings(&remote)?;
+                remote
+            }
+            (Some(local), remote) => {
+                if remote.map_or(true, |r| r.updated_at < local.updated_at) {
+                    queue_settings_event(&self.storage, &key, &local)?;
+                }
+                local
+            }
+            (None, None) => WalletSettings::default(),
+        };
+        log_trace!(self.logger, "finished calling sync_wallet_settings");
+
+        Ok(settings)
+    }
+
+    /// The hex pubkey our contacts use to make us one of their guardians
+    pub fn get_social_recovery_pubkey(&self) -> Result<String, MutinyError> {
+        let key = social_recovery_key(self.xprivkey)?;
+        Ok(key.x_only_public_key(&Secp256k1::new()).0.to_string())
+    }
+
+    /// Turns on social recovery. The seed is split so that any `threshold` of
+    /// the guardians can recover it, each guardian is a contact id with the
+    /// npub or hex pubkey their wallet gets shards on.
+    pub fn enable_social_recovery(
+        &self,
+        threshold: u8,
+        guardians: Vec<(String, String)>,
+    ) -> Result<SocialRecoveryConfig, MutinyError> {
+        log_trace!(self.logger, "calling enable_social_recovery");
+
+        if guardians.is_empty() || guardians.len() > u8::MAX as usize {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let mut pubkeys = Vec::with_capacity(guardians.len());
+        for (contact_id, pubkey) in guardians.iter() {
+            if self.storage.get_contact(contact_id)?.is_none() {
+                return Err(MutinyError::NotFound);
+            }
+            let pubkey = XOnlyPublicKey::from_str(&normalize_pubkey(pubkey)?)
+                .map_err(|_| MutinyError::PubkeyInvalid)?;
+            pubkeys.push(pubkey);
+        }
+
+        let mnemonic = self
+            .storage
+            .get_mnemonic()?
+            .ok_or(MutinyError::InvalidMnemonic)?;
+        let key = social_recovery_key(self.xprivkey)?;
+        let owner = key.x_only_public_key(&Secp256k1::new()).0;
+        let shards = create_shards(&mnemonic, &owner, threshold, guardians.len() as u8)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+
+        let mut config = SocialRecoveryConfig {
+            threshold,
+            guardians: vec![],
+            created_at: utils::now().as_secs(),
+            last_check_at: utils::now().as_secs(),
+        };
+        for (((contact_id, _), pubkey), shard) in guardians.into_iter().zip(pubkeys).zip(shards) {
+            config.guardians.push(Guardian {
+                contact_id,
+                pubkey: pubkey.to_string(),
+                index: shard.index,
+                last_seen: None,
+            });
+            let message = RecoveryMessage::Shard { shard };
+            queue_recovery_dm(&self.storage, &key, &pubkey, &message, relays.clone())?;
+        }
+        persist_social_recovery_config(&self.storage, &config)?;
+
+        log_trace!(self.logger, "finished calling enable_social_recovery");
+        Ok(config)
+    }
+
+    pub fn get_social_recovery(&self) -> Result<Option<SocialRecoveryConfig>, MutinyError> {
+        get_social_recovery_config(&self.storage)
+    }
+
+    /// Stops checking on guardians. Shards already sent can't be taken back,
+    /// so move the funds to a new seed if a guardian can no longer be trusted.
+    pub fn disable_social_recovery(&self) -> Result<(), MutinyError> {
+        self.storage.delete(&[SOCIAL_RECOVERY_KEY])
+    }
+
+    /// Handles the recovery messages sent to us: stores shards contacts ask us
+    /// to hold, answers their pings and records our guardians' answers. Pings
+    /// our guardians when they haven't been checked on in a while.
+    pub async fn check_social_recovery(&self) -> Result<Option<SocialRecoveryConfig>, MutinyError> {
+        log_trace!(self.logger, "calling check_social_recovery");
+
+        let key = social_recovery_key(self.xprivkey)?;
+        let our_pubkey = key.x_only_public_key(&Secp256k1::new()).0.to_string();
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let since: u64 = self
+            .storage
+            .get_data(RECOVERY_MESSAGES_SINCE_KEY)?
+            .unwrap_or_default();
+        let messages = fetch_recovery_dms(&key, &relays, since).await?;
+
+        let mut config = get_social_recovery_config(&self.storage)?;
+        let mut newest = since;
+        for (sender, message, created_at) in messages {
+            newest = newest.max(created_at);
+            match message {
+                RecoveryMessage::Shard { shard } if shard.owner == sender.to_string() => {
+                    let existing = get_held_shard(&self.storage, &shard.owner)?;
+                    if existing.map_or(true, |h| h.received_at <= created_at) {
+                        log_info!(self.logger, "Holding a backup shard for {sender}");
+                        let held = HeldShard {
+                            shard,
+                            received_at: created_at,
+                        };
+                        persist_held_shard(&self.storage, &held)?;
+                    }
+                }
+                RecoveryMessage::Ping { owner } if owner == sender.to_string() => {
+                    if get_held_shard(&self.storage, &owner)?.is_some() {
+                        let message = RecoveryMessage::Pong { owner };
+                        queue_recovery_dm(&self.storage, &key, &sender, &message, relays.clone())?;
+                    }
+                }
+                RecoveryMessage::Pong { owner } if owner == our_pubkey => {
+                    let guardian = config.as_mut().and_then(|c| {
+                        c.guardians
+                            .iter_mut()
+                            .find(|g| g.pubkey == sender.to_string())
+                    });
+                    if let Some(guardian) = guardian {
+                        guardian.last_seen = guardian.last_seen.max(Some(created_at));
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.storage
+            .write_data(RECOVERY_MESSAGES_SINCE_KEY.to_string(), newest, None)?;
+
+        if let Some(config) = config.as_mut() {
+            let now = utils::now().as_secs();
+            if now.saturating_sub(config.last_check_at) >= LIVENESS_CHECK_INTERVAL_SECS {
+                for guardian in config.guardians.iter() {
+                    let pubkey = XOnlyPublicKey::from_str(&guardian.pubkey)
+                        .map_err(|_| MutinyError::PubkeyInvalid)?;
+                    let message = RecoveryMessage::Ping {
+                        owner: our_pubkey.clone(),
+                    };
+                    queue_recovery_dm(&self.storage, &key, &pubkey, &message, relays.clone())?;
+                }
+                config.last_check_at = now;
+            }
+            persist_social_recovery_config(&self.storage, config)?;
+        }
+
+        log_trace!(self.logger, "finished calling check_social_recovery");
+        Ok(config)
+    }
+
+    /// Lists the backup shards we hold as a guardian for our contacts
+    pub fn list_held_backup_shards(&self) -> Result<Vec<HeldShard>, MutinyError> {
+        list_held_shards(&self.storage)
+    }
+
+    /// Gives a contact's shard back to the recovery key of their new device.
+    /// Only do this after confirming with them out of band that they asked for it.
+    pub fn release_backup_shard(
+        &self,
+        owner: String,
+        recovery_pubkey: String,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling release_backup_shard");
+
+        let held = get_held_shard(&self.storage, &normalize_pubkey(&owner)?)?
+            .ok_or(MutinyError::NotFound)?;
+        let recovery_pubkey = XOnlyPublicKey::from_str(&normalize_pubkey(&recovery_pubkey)?)
+            .map_err(|_| MutinyError::PubkeyInvalid)?;
+        let key = social_recovery_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let message = RecoveryMessage::Release { shard: held.shard };
+        queue_recovery_dm(&self.storage, &key, &recovery_pubkey, &message, relays)?;
+
+        log_trace!(self.logger, "finished calling release_backup_shard");
+        Ok(())
+    }
+
+    /// Moves the whole balance to another Mutiny user, found by their npub or
+    /// hex nostr pubkey. Asks their wallet over direct messages where to send it,
+    /// then pays the lightning balance to their invoice and sweeps the on-chain
+    /// balance to their address. Progress is saved on the returned transfer,
+    /// which is failed with the reason if the recipient declines, doesn't answer
+    /// in time or a payment fails.
+    pub async fn transfer_balance(
+        &self,
+        recipient: String,
+        labels: Vec<String>,
+    ) -> Result<Transfer, MutinyError> {
+        log_trace!(self.logger, "calling transfer_balance");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let recipient = XOnlyPublicKey::from_str(&normalize_pubkey(&recipient)?)
+            .map_err(|_| MutinyError::PubkeyInvalid)?;
+
+        let spendable = node_manager
+            .get_node_balances()
+            .await
+            .iter()
+            .map(|n| n.spendable)
+            .sum();
+        let lightning_sats = lightning_transfer_amount(spendable);
+        let balance = node_manager.get_balance().await?;
+        let onchain = balance.confirmed + balance.unconfirmed > 0;
+        if lightning_sats == 0 && !onchain {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let key = nostr_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let mut transfer = Transfer::new_outgoing(&recipient, lightning_sats, onchain);
+        let event = create_transfer_dm(&key, &recipient, &transfer.request())?;
+        persist_transfer(&self.storage, &mut transfer)?;
+        node_manager
+            .publish_nostr_event(event, relays.clone())
+            .await?;
+
+        let deadline = transfer.created_at + TRANSFER_ANSWER_TIMEOUT_SECS;
+        let answer = loop {
+            if let Ok(messages) = fetch_transfer_dms(&key, &relays, transfer.created_at).await {
+                if let Some(answer) = find_answer(&transfer, &messages) {
+                    break Some(answer);
+                }
+            }
+            if utils::now().as_secs() >= deadline {
+                break None;
+            }
+            sleep(TRANSFER_POLL_INTERVAL_SECS as i32 * 1_000).await;
+        };
+
+        match answer {
+            Some(TransferMessage::TransferAccept {
+                invoice, address, ..
+            }) => {
+                transfer.invoice = invoice;
+                transfer.address = address;
+                transfer.status = TransferStatus::Paying;
+                persist_transfer(&self.storage, &mut transfer)?;
+                self.pay_transfer(&mut transfer, labels).await;
+            }
+            Some(TransferMessage::TransferDecline { reason, .. }) => {
+                transfer.fail(format!("Recipient declined: {reason}"));
+            }
+            _ => transfer.fail("Recipient didn't answer"),
+        }
+        persist_transfer(&self.storage, &mut transfer)?;
+
+        log_trace!(self.logger, "finished calling transfer_balance");
+        Ok(transfer)
+    }
+
+    /// Sends both parts of an accepted transfer. A failed lightning payment
+    /// doesn't stop the on-chain funds from being swept.
+    async fn pay_transfer(&self, transfer: &mut Transfer, labels: Vec<String>) {
+        let mut failures = vec![];
+
+        if transfer.lightning_sats > 0 {
+            let invoice = transfer
+                .invoice
+                .as_deref()
+                .map(Bolt11Invoice::from_str)
+                .and_then(|i| i.ok());
+            match invoice {
+                // the recipient can't ask for more than we offered
+                Some(invoice)
+                    if invoice
+                        .amount_milli_satoshis()
+                        .is_some_and(|a| a != transfer.lightning_sats * 1_000) =>
+                {
+                    failures.push("Recipient's invoice is for the wrong amount".to_string());
+                }
+                Some(invoice) => {
+                    let amount = invoice
+                        .amount_milli_satoshis()
+                        .is_none()
+                        .then_some(transfer.lightning_sats);
+                    match self.pay_invoice(&invoice, amount, labels.clone()).await {
+                        Ok(_) => transfer.lightning_paid = true,
+                        Err(e) => failures.push(format!("Lightning payment failed: {e}")),
+                    }
+                    let _ = persist_transfer(&self.storage, transfer);
+                }
+                None => failures.push("Recipient can't receive over lightning".to_string()),
+            }
+        }
+
+        if transfer.onchain {
+            let address = transfer
+                .address
+                .as_deref()
+                .and_then(|a| Address::from_str(a).ok())
+                .and_then(|a| a.require_network(self.network).ok());
+            match address {
+                Some(address) => match self.sweep_wallet(address, labels, None, None).await {
+                    Ok(txid) => transfer.txid = Some(txid),
+                    Err(e) => failures.push(format!("On-chain sweep failed: {e}")),
+                },
+                None => failures.push("Recipient gave no valid address".to_string()),
+            }
+        }
+
+        if failures.is_empty() {
+            transfer.status = TransferStatus::Completed;
+        } else {
+            transfer.fail(failures.join(", "));
+        }
+    }
+
+    pub fn get_transfer(&self, id: String) -> Result<Option<Transfer>, MutinyError> {
+        get_transfer(&self.storage, &id)
+    }
+
+    /// Lists the balance transfers we sent and received, newest first
+    pub fn list_transfers(&self) -> Result<Vec<Transfer>, MutinyError> {
+        list_transfers(&self.storage)
+    }
+
+    async fn watch_direct_messages(&self) {
+        loop {
+            if let Err(e) = self.check_transfer_requests().await {
+                log_debug!(self.logger, "Failed to check transfer requests: {e}");
+            }
+            if let Err(e) = self.check_payment_receipts().await {
+                log_debug!(self.logger, "Failed to check payment receipts: {e}");
+            }
+
+            for _ in 0..TRANSFER_POLL_INTERVAL_SECS {
+                match self.node_manager.as_ref() {
+                    Some(nm) if !nm.stop.load(std::sync::atomic::Ordering::Relaxed) => {}
+                    _ => return,
+                }
+                sleep(1_000).await;
+            }
+        }
+    }
+
+    /// Answers the transfer requests other wallets sent us with an invoice and
+    /// an address to send their balance to
+    async fn check_transfer_requests(&self) -> Result<(), MutinyError> {
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let key = nostr_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let since: u64 = self
+            .storage
+            .get_data(TRANSFER_MESSAGES_SINCE_KEY)?
+            .unwrap_or_default();
+        let messages = fetch_transfer_dms(&key, &relays, since).await?;
+
+        let mut newest = since;
+        for (sender, message, created_at) in messages {
+            newest = newest.max(created_at);
+            let TransferMessage::TransferRequest {
+                id,
+                lightning_sats,
+                onchain,
+            } = message
+            else {
+                continue;
+            };
+            if get_transfer(&self.storage, &id)?.is_some() {
+                continue;
+            }
+
+            let invoice = if lightning_sats > 0 {
+                match self
+                    .create_lightning_invoice(lightning_sats, vec![], None)
+                    .await
+                {
+                    Ok(invoice) => invoice.bolt11.map(|i| i.to_string()),
+                    Err(e) => {
+                        log_warn!(self.logger, "Failed to create transfer invoice: {e}");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let address = if onchain {
+                Some(node_manager.get_new_address(vec![])?.to_string())
+            } else {
+                None
+            };
+
+            let now = utils::now().as_secs();
+            let mut transfer = Transfer {
+                id: id.clone(),
+                counterparty: sender.to_string(),
+                outgoing: false,
+                lightning_sats,
+                onchain,
+                status: TransferStatus::Accepted,
+                invoice: invoice.clone(),
+                address: address.clone(),
+                lightning_paid: false,
+                txid: None,
+                failure: None,
+                created_at: now,
+                updated_at: now,
+            };
+            let answer = if invoice.is_none() && address.is_none() {
+                let reason = "Can't receive right now".to_string();
+                transfer.fail(&reason);
+                TransferMessage::TransferDecline { id, reason }
+            } else {
+                log_info!(self.logger, "Accepting a balance transfer from {sender}");
+                TransferMessage::TransferAccept {
+                    id,
+                    invoice,
+                    address,
+                }
+            };
+            let event = create_transfer_dm(&key, &sender, &answer)?;
+            node_manager
+                .publish_nostr_event(event, relays.clone())
+                .await?;
+            persist_transfer(&self.storage, &mut transfer)?;
+        }
+        self.storage
+            .write_data(TRANSFER_MESSAGES_SINCE_KEY.to_string(), newest, None)?;
+
+        Ok(())
+    }
+
+    /// Sends the payee of a lightning payment we made a receipt with the amount,
+    /// the memo and the preimage, as a direct message to their npub or hex nostr
+    /// pubkey. Their wallet uses it to label the payment as coming from us.
+    pub async fn send_payment_receipt(
+        &self,
+        payment_hash: &sha256::Hash,
+        recipient: String,
+        memo: Option<String>,
+    ) -> Result<PaymentReceipt, MutinyError> {
+        log_trace!(self.logger, "calling send_payment_receipt");
+
+        let node_manager = self.node_manager.as_ref().ok_or(MutinyError::NotRunning)?;
+        let recipient = XOnlyPublicKey::from_str(&normalize_pubkey(&recipient)?)
+            .map_err(|_| MutinyError::PubkeyInvalid)?;
+        let invoice = get_invoice_by_hash(payment_hash, &self.storage, &self.logger)?;
+        let (false, HTLCStatus::Succeeded, Some(preimage), Some(amount_sats)) = (
+            invoice.inbound,
+            invoice.status,
+            invoice.preimage,
+            invoice.amount_sats,
+        ) else {
+            return Err(MutinyError::InvalidArgumentsError);
+        };
+
+        let receipt = PaymentReceipt {
+            payment_hash: payment_hash.to_string(),
+            counterparty: recipient.to_string(),
+            outgoing: true,
+            amount_sats,
+            memo,
+            preimage,
+            created_at: utils::now().as_secs(),
+        };
+        let key = nostr_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let event = create_receipt_dm(&key, &recipient, &receipt.message())?;
+        node_manager.publish_nostr_event(event, relays).await?;
+        persist_receipt(&self.storage, &receipt)?;
+
+        log_trace!(self.logger, "finished calling send_payment_receipt");
+        Ok(receipt)
+    }
+
+    /// Gets the receipt for a payment, the one sent to us if there are both
+    pub fn get_payment_receipt(
+        &self,
+        payment_hash: &sha256::Hash,
+    ) -> Result<Option<PaymentReceipt>, MutinyError> {
+        get_receipt(&self.storage, &payment_hash.to_string())
+    }
+
+    /// Lists the payment receipts we sent and received, newest first
+    pub fn list_payment_receipts(&self) -> Result<Vec<PaymentReceipt>, MutinyError> {
+        list_receipts(&self.storage)
+    }
+
+    /// Records the receipts sent to us for payments we received and labels the
+    /// payments with the payer's npub. Receipts for payments we don't have, or
+    /// that don't match them, are ignored.
+    async fn check_payment_receipts(&self) -> Result<(), MutinyError> {
+        let key = nostr_key(self.xprivkey)?;
+        let relays = self.get_wallet_settings()?.sync_relays();
+        let since: u64 = self
+            .storage
+            .get_data(RECEIPT_MESSAGES_SINCE_KEY)?
+            .unwrap_or_default();
+        let messages = fetch_receipt_dms(&key, &relays, since).await?;
+
+        let mut newest = since;
+        for (sender, message, created_at) in messages {
+            newest = newest.max(created_at);
+            let Ok(receipt) = PaymentReceipt::from_message(&sender, message, created_at) else {
+                continue;
+            };
+            let Ok(hash) = sha256::Hash::from_str(&receipt.payment_hash) else {
+                continue;
+            };
+            let Ok(invoice) = get_invoice_by_hash(&hash, &self.storage, &self.logger) else {
+                continue;
+            };
+            if !invoice.inbound
+                || invoice.status != HTLCStatus::Succeeded
+                || invoice.amount_sats != Some(receipt.amount_sats)
+            {
+                continue;
+            }
+
+            if let Some(bolt11) = invoice.bolt11 {
+                let label = npub(&sender)?;
+                let mut labels = invoice.labels;
+                if !labels.contains(&label) {
+                    labels.push(label);
+                    self.storage.set_invoice_labels(bolt11, labels)?;
+                }
+            }
+            log_info!(
+                self.logger,
+                "Got a receipt for payment {}",
+                receipt.payment_hash
+            );
+            persist_receipt(&self.storage, &receipt)?;
+        }
+        self.storage
+            .write_data(RECEIPT_MESSAGES_SINCE_KEY.to_string(), newest, None)?;
+
+        Ok(())
+    }
+
+    /// Formats an amount using the user's denomination preferences.
+    /// If a timestamp is given, fiat amounts use the price from that time
+    /// when we have one, otherwise the current price is used.
+    pub async fn format_amount(
+        &self,
+        sats: u64,
+        timestamp: Option<u64>,
+    ) -> Result<String, MutinyError> {
+        let prefs = self.get_denomination_preferences()?;
+
+        let price = match prefs.denomination {
+            Denomination::Fiat => {
+                let historical = match timestamp {
+                    Some(t) => get_price_at(&self.storage, &prefs.fiat_currency, t)?,
+                    None => None,
+                };
+                match historical {
+                    Some(price) => Some(price),
+                    None => Some(
+                        self.get_bitcoin_price(Some(prefs.fiat_currency.clone()))
+                            .await?,
+                    ),
+                }
+            }
+            Denomination::Sats | Denomination::Btc => None,
+        };
+
+        denominations::format_amount(sats, &prefs, price)
+    }
+
     async fn fetch_and_cache_price(
         fiat: String,
         now: Duration,
@@ -1934,6 +4629,11 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     if let Err(e) = storage.insert_bitcoin_price_cache(cache) {
                         log_error!(logger, "failed to save bitcoin price cache: {e:?}");
                     }
+
+                    // keep a history so past activity can be shown at the rate of the time
+                    if let Err(e) = record_price(&storage, &fiat, now.as_secs(), new_price) {
+                        log_error!(logger, "failed to save bitcoin price history: {e:?}");
+                    }
                 });
 
                 Ok(new_price)
@@ -2280,13 +4980,54 @@ mod tests {
         mw.storage.insert_mnemonic(mnemonic).unwrap();
         assert!(NodeManager::has_node_manager(storage));
 
-        let bip21 = mw.create_bip21(None, vec![]).await.unwrap();
+        let bip21 = mw.create_bip21(None, vec![], None).await.unwrap();
         assert!(bip21.invoice.is_none());
 
         let new_node = mw.node_manager.as_ref().unwrap().new_node().await;
         assert!(new_node.is_err());
     }
 
+    #[test]
+    async fn second_instance_is_read_only() {
+        let test_name = "second_instance_is_read_only";
+        log!("{}", test_name);
+
+        let network = Network::Regtest;
+        let xpriv = Xpriv::new_master(network, &[0; 32]).unwrap();
+
+        let storage = MemoryStorage::new(None, None, None);
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mut mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config.clone())
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+        assert!(!mw.is_read_only());
+
+        let mut mw2 = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config.clone())
+            .build()
+            .await
+            .expect("second instance should initialize");
+        assert!(mw2.is_read_only());
+        assert!(mw2.is_safe_mode());
+
+        // the lock is released once the first instance stops, and the
+        // read-only instance doesn't take it over
+        mw.stop().await.expect("should stop");
+        let lock = storage.fetch_instance_lock().await.unwrap().unwrap();
+        assert_eq!(lock.time, 0);
+        let mw3 = MutinyWalletBuilder::new(xpriv, storage)
+            .with_config(config)
+            .build()
+            .await
+            .expect("third instance should initialize");
+        assert!(!mw3.is_read_only());
+        mw2.stop().await.expect("should stop");
+    }
+
     #[test]
     async fn test_sort_index_item() {
         let test_name = "test_sort_index_item";
@@ -2335,6 +5076,11 @@ mod tests {
             reason: "".to_string(),
             timestamp: 1686258926,
             channel_funding_txo: None,
+            closing_txid: None,
+            swept_amount_sats: 0,
+            pending_sweep_sats: 0,
+            fully_swept: false,
+            estimated_secs_remaining: None,
         };
         let closure_chan_id: u128 = 6969;
         node.persister
@@ -2403,6 +5149,8 @@ mod tests {
                 .unwrap();
         let invoice1 = PaymentInfo {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             preimage: None,
             payee_pubkey: Some(pubkey),
             status: HTLCStatus::Succeeded,
@@ -2419,6 +5167,8 @@ mod tests {
                 .unwrap();
         let invoice2 = PaymentInfo {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             preimage: None,
             secret: None,
             payee_pubkey: Some(pubkey),
@@ -2435,6 +5185,8 @@ mod tests {
                 .unwrap();
         let invoice3 = PaymentInfo {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             preimage: None,
             payee_pubkey: Some(pubkey),
             amt_msat: MillisatAmount(Some(101 * 1_000)),
@@ -2451,6 +5203,8 @@ mod tests {
                 .unwrap();
         let mut invoice4 = PaymentInfo {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             preimage: None,
             payee_pubkey: Some(pubkey),
             amt_msat: MillisatAmount(Some(102 * 1_000)),
@@ -2473,6 +5227,8 @@ mod tests {
                 last_seen: now().as_secs(),
             },
             labels: vec![],
+            replaces: vec![],
+            address_reused: false,
         };
         persist_transaction_details(&storage, &transaction_details1).unwrap();
 
@@ -0,0 +1,304 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use lightning_invoice::Bolt11Invoice;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const LN_ADDRESS_ALIASES_KEY: &str = "ln_address_aliases";
+
+/// A lightning address, e.g. `satoshi@example.com` (LUD-16)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightningAddress {
+    pub username: String,
+    pub domain: String,
+}
+
+impl FromStr for LightningAddress {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (username, domain) = s
+            .split_once('@')
+            .ok_or(MutinyError::InvalidArgumentsError)?;
+        if username.is_empty() || domain.is_empty() || !domain.contains('.') {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        Ok(Self {
+            username: username.to_string(),
+            domain: domain.to_string(),
+        })
+    }
+}
+
+impl core::fmt::Display for LightningAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}@{}", self.username, self.domain)
+    }
+}
+
+impl LightningAddress {
+    pub fn lnurlp_url(&self) -> String {
+        format!(
+            "https://{}/.well-known/lnurlp/{}",
+            self.domain, self.username
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayResponse {
+    callback: String,
+    min_sendable: u64,
+    max_sendable: u64,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    pr: String,
+}
+
+async fn get_json<T: for<'de> Deserialize<'de>>(
+    client: &Client,
+    url: &str,
+) -> Result<T, MutinyError> {
+    let request = client
+        .get(url)
+        .build()
+        .map_err(|_| MutinyError::LnUrlFailure)?;
+
+    utils::fetch_with_timeout(client, request)
+        .await?
+        .error_for_status()
+        .map_err(|_| MutinyError::LnUrlFailure)?
+        .json()
+        .await
+        .map_err(|_| MutinyError::LnUrlFailure)
+}
+
+/// Gets an invoice for the given amount from the lightning address's server
+pub(crate) async fn fetch_invoice(
+    client: &Client,
+    address: &LightningAddress,
+    amount_sats: u64,
+) -> Result<Bolt11Invoice, MutinyError> {
+    let pay: PayResponse = get_json(client, &address.lnurlp_url()).await?;
+    if pay.tag != "payRequest" {
+        return Err(MutinyError::LnUrlFailure);
+    }
+
+    let amount_msats = amount_sats
+        .checked_mul(1_000)
+        .ok_or(MutinyError::BadAmountError)?;
+    if amount_msats < pay.min_sendable || amount_msats > pay.max_sendable {
+        return Err(MutinyError::BadAmountError);
+    }
+
+    let separator = if pay.callback.contains('?') { '&' } else { '?' };
+    let url = format!("{}{separator}amount={amount_msats}", pay.callback);
+    let invoice: InvoiceResponse = get_json(client, &url).await?;
+    let invoice = Bolt11Invoice::from_str(&invoice.pr)?;
+
+    // make sure the server didn't give us an invoice for a different amount
+    if invoice.amount_milli_satoshis() != Some(amount_msats) {
+        return Err(MutinyError::LnUrlFailure);
+    }
+
+    Ok(invoice)
+}
+
+/// How the wallet picks which of its lightning address aliases to hand out
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AliasRotationPolicy {
+    /// Always hand out the same alias
+    #[default]
+    Static,
+    /// Every contact gets their own alias so payers can't be linked together
+    PerContact,
+    /// The shared alias is replaced with an unused one after the interval
+    TimeBased { interval_secs: u64 },
+}
+
+/// A lightning address registered for this wallet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LnAddressAlias {
+    pub address: String,
+    /// The contact this alias was handed out to, if any
+    pub contact_id: Option<String>,
+    pub created_at: u64,
+    /// When the alias stopped being handed out. It keeps receiving
+    /// payments, it is just not given to new payers anymore.
+    pub retired_at: Option<u64>,
+}
+
+impl LnAddressAlias {
+    fn is_free(&self) -> bool {
+        self.contact_id.is_none() && self.retired_at.is_none()
+    }
+}
+
+/// All the aliases of this wallet and the policy for rotating through them.
+/// The shared alias is the oldest free one, it is what goes on the payment card.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LnAddressAliases {
+    pub policy: AliasRotationPolicy,
+    pub aliases: Vec<LnAddressAlias>,
+    /// Epoch time in seconds the shared alias was last rotated
+    pub rotated_at: u64,
+}
+
+impl LnAddressAliases {
+    pub(crate) fn add(&mut self, address: &LightningAddress, now: u64) -> Result<(), MutinyError> {
+        let address = address.to_string();
+        if self.aliases.iter().any(|a| a.address == address) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if self.shared().is_none() {
+            self.rotated_at = now;
+        }
+
+        self.aliases.push(LnAddressAlias {
+            address,
+            contact_id: None,
+            created_at: now,
+            retired_at: None,
+        });
+        Ok(())
+    }
+
+    /// Removes an alias, returns false if it didn't exist
+    pub(crate) fn remove(&mut self, address: &str) -> bool {
+        let len = self.aliases.len();
+        self.aliases.retain(|a| a.address != address);
+        self.aliases.len() != len
+    }
+
+    /// The alias currently handed out to payers that aren't a known contact
+    pub fn shared(&self) -> Option<&LnAddressAlias> {
+        self.aliases.iter().find(|a| a.is_free())
+    }
+
+    /// Picks the alias to give to the payer, rotating or assigning aliases as
+    /// the policy requires. Falls back to the shared alias once there are no
+    /// unused aliases left, so registering aliases ahead of time is on the caller.
+    pub(crate) fn select(&mut self, contact_id: Option<&str>, now: u64) -> Option<String> {
+        if let AliasRotationPolicy::TimeBased { interval_secs } = self.policy {
+            let free = self.aliases.iter().filter(|a| a.is_free()).count();
+            if free > 1 && now.saturating_sub(self.rotated_at) >= interval_secs {
+                if let Some(current) = self.aliases.iter_mut().find(|a| a.is_free()) {
+                    current.retired_at = Some(now);
+                    self.rotated_at = now;
+                }
+            }
+        }
+
+        if let (AliasRotationPolicy::PerContact, Some(contact_id)) = (self.policy, contact_id) {
+            if let Some(alias) = self
+                .aliases
+                .iter()
+                .find(|a| a.contact_id.as_deref() == Some(contact_id))
+            {
+                return Some(alias.address.clone());
+            }
+
+            // keep the first free alias as the shared one
+            if let Some(alias) = self.aliases.iter_mut().filter(|a| a.is_free()).nth(1) {
+                alias.contact_id = Some(contact_id.to_string());
+                return Some(alias.address.clone());
+            }
+        }
+
+        self.shared().map(|a| a.address.clone())
+    }
+}
+
+pub(crate) fn get_ln_address_aliases<S: MutinyStorage>(
+    storage: &S,
+) -> Result<LnAddressAliases, MutinyError> {
+    Ok(storage
+        .get_data(LN_ADDRESS_ALIASES_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn persist_ln_address_aliases<S: MutinyStorage>(
+    storage: &S,
+    aliases: &LnAddressAliases,
+) -> Result<(), MutinyError> {
+    storage.write_data(LN_ADDRESS_ALIASES_KEY.to_string(), aliases, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_parse_lightning_address() {
+        let test_name = "test_parse_lightning_address";
+        log!("{}", test_name);
+
+        let address = LightningAddress::from_str("Satoshi@Example.com").unwrap();
+        assert_eq!(address.to_string(), "satoshi@example.com");
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://example.com/.well-known/lnurlp/satoshi"
+        );
+
+        assert!(LightningAddress::from_str("satoshi").is_err());
+        assert!(LightningAddress::from_str("@example.com").is_err());
+        assert!(LightningAddress::from_str("satoshi@localhost").is_err());
+    }
+
+    #[test]
+    fn test_alias_rotation() {
+        let test_name = "test_alias_rotation";
+        log!("{}", test_name);
+
+        let a = LightningAddress::from_str("a@example.com").unwrap();
+        let b = LightningAddress::from_str("b@example.com").unwrap();
+        let c = LightningAddress::from_str("c@example.com").unwrap();
+
+        let mut aliases = LnAddressAliases::default();
+        assert_eq!(aliases.select(None, 0), None);
+        aliases.add(&a, 0).unwrap();
+        assert!(aliases.add(&a, 0).is_err());
+        aliases.add(&b, 0).unwrap();
+        aliases.add(&c, 0).unwrap();
+
+        // static always gives out the first one
+        assert_eq!(aliases.select(Some("bob"), 1_000).unwrap(), a.to_string());
+
+        // time based rotates once the interval passed
+        aliases.policy = AliasRotationPolicy::TimeBased { interval_secs: 100 };
+        assert_eq!(aliases.select(None, 50).unwrap(), a.to_string());
+        assert_eq!(aliases.select(None, 100).unwrap(), b.to_string());
+        assert_eq!(aliases.select(None, 150).unwrap(), b.to_string());
+        assert_eq!(aliases.select(None, 200).unwrap(), c.to_string());
+        // nothing left to rotate to
+        assert_eq!(aliases.select(None, 300).unwrap(), c.to_string());
+        assert_eq!(aliases.aliases[0].retired_at, Some(100));
+
+        // per contact gives each contact their own alias
+        let mut aliases = LnAddressAliases {
+            policy: AliasRotationPolicy::PerContact,
+            ..Default::default()
+        };
+        aliases.add(&a, 0).unwrap();
+        aliases.add(&b, 0).unwrap();
+        aliases.add(&c, 0).unwrap();
+        assert_eq!(aliases.select(Some("bob"), 0).unwrap(), b.to_string());
+        assert_eq!(aliases.select(Some("carol"), 0).unwrap(), c.to_string());
+        assert_eq!(aliases.select(Some("bob"), 0).unwrap(), b.to_string());
+        assert_eq!(aliases.select(None, 0).unwrap(), a.to_string());
+        // out of aliases, falls back to the shared one
+        assert_eq!(aliases.select(Some("dave"), 0).unwrap(), a.to_string());
+    }
+}
@@ -107,6 +107,28 @@ impl MutinyLogger {
         get_logging_data(storage)
     }
 
+    /// Bytes used by the logs buffered in memory
+    pub(crate) fn memory_logs_size(&self) -> u64 {
+        self.memory_logs
+            .lock()
+            .map(|logs| logs.iter().map(|l| l.len() as u64).sum())
+            .unwrap_or_default()
+    }
+
+    /// Drops all but the most recent buffered logs. Only applies when logs
+    /// are not persisted, otherwise the buffer is already flushed every few seconds.
+    pub(crate) fn trim_memory_logs(&self, keep: usize) {
+        if self.should_persist {
+            return;
+        }
+        if let Ok(mut logs) = self.memory_logs.lock() {
+            if logs.len() > keep {
+                let excess = logs.len() - keep;
+                logs.drain(..excess);
+            }
+        }
+    }
+
     pub(crate) async fn stop(&self) {
         if let Some(stop_handle) = self.stop_handle.as_ref() {
             stop_handle.stop().await
@@ -173,6 +195,30 @@ fn get_logging_data<S: MutinyStorage>(storage: &S) -> Result<Option<Vec<String>>
     storage.get_data(LOGGING_KEY)
 }
 
+/// Bytes used by the persisted logs
+pub(crate) fn logging_data_size<S: MutinyStorage>(storage: &S) -> Result<u64, MutinyError> {
+    Ok(get_logging_data(storage)?
+        .unwrap_or_default()
+        .iter()
+        .map(|l| l.len() as u64)
+        .sum())
+}
+
+/// Drops all but the most recent persisted logs
+pub(crate) fn trim_logging_data<S: MutinyStorage>(
+    storage: &S,
+    keep: usize,
+) -> Result<(), MutinyError> {
+    let mut logs = get_logging_data(storage)?.unwrap_or_default();
+    if logs.len() > keep {
+        let excess = logs.len() - keep;
+        logs.drain(..excess);
+        storage.write_data(LOGGING_KEY.to_string(), &logs, None)?;
+    }
+
+    Ok(())
+}
+
 fn write_logging_data<S: MutinyStorage>(
     storage: &S,
     mut recent_logs: Vec<String>,
@@ -248,6 +294,24 @@ mod tests {
         assert_eq!(logger.get_logs(&()).unwrap(), None);
     }
 
+    #[test]
+    async fn trim_memory_logs() {
+        let test_name = "trim_memory_logs";
+        log!("{}", test_name);
+
+        let logger = MutinyLogger::default();
+        for i in 0..10 {
+            log_debug!(logger, "testing {i}");
+        }
+        let size = logger.memory_logs_size();
+        assert!(size > 0);
+
+        logger.trim_memory_logs(2);
+        assert_eq!(logger.memory_logs.lock().unwrap().len(), 2);
+        assert!(logger.memory_logs_size() < size);
+        assert!(logger.memory_logs.lock().unwrap()[1].contains("testing 9"));
+    }
+
     #[test]
     async fn log_with_storage() {
         let test_name = "log_with_storage";
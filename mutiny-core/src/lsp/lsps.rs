@@ -5,6 +5,8 @@ use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{PublicKey, Secp256k1};
 use bitcoin::Network;
 use futures::channel::oneshot;
+use futures::future::{self, Either};
+use futures::pin_mut;
 use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY_DELTA;
 use lightning::ln::PaymentHash;
 use lightning::routing::gossip::RoutingFees;
@@ -21,6 +23,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::{
     error::MutinyError,
@@ -35,6 +38,12 @@ use crate::{
 
 use super::FeeResponse;
 
+/// How long we wait for the LSP to answer a request before giving up
+const LSP_RESPONSE_TIMEOUT_MS: i32 = 30_000;
+/// The longest a JIT channel invoice is valid for, it is cut short when the
+/// fee parameters expire first since the LSP won't honor them after that
+const MAX_INVOICE_EXPIRY_SECS: u64 = 3600;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct LspsConfig {
     pub connection_string: String,
@@ -60,6 +69,65 @@ pub(crate) struct PendingPaymentInfo {
 type PendingFeeRequestSender = oneshot::Sender<Result<GetInfoResponse, MutinyError>>;
 type PendingBuyRequestSender = oneshot::Sender<Result<Bolt11Invoice, MutinyError>>;
 
+pub(crate) struct PendingBuyRequest {
+    pub sender: PendingBuyRequestSender,
+    pub invoice_expiry_secs: u64,
+}
+
+/// Picks the cheapest of the LSP's opening fee parameters that are still valid
+/// and accept the payment size, as LSPS2 leaves the choice to the client
+pub(crate) fn select_opening_fee_params(
+    menu: &[OpeningFeeParams],
+    payment_size_msat: u64,
+    now_secs: u64,
+) -> Result<OpeningFeeParams, MutinyError> {
+    let valid: Vec<&OpeningFeeParams> = menu
+        .iter()
+        .filter(|p| p.valid_until.timestamp() > now_secs as i64)
+        .collect();
+    if valid.is_empty() {
+        return Err(MutinyError::LspGenericError);
+    }
+    if valid
+        .iter()
+        .all(|p| payment_size_msat < p.min_payment_size_msat)
+    {
+        return Err(MutinyError::BadAmountError);
+    }
+
+    valid
+        .into_iter()
+        .filter(|p| {
+            p.min_payment_size_msat <= payment_size_msat
+                && payment_size_msat <= p.max_payment_size_msat
+        })
+        .filter_map(|p| {
+            compute_opening_fee(payment_size_msat, p.min_fee_msat, p.proportional.into())
+                // the fee has to leave something of the payment for us
+                .filter(|fee| *fee < payment_size_msat)
+                .map(|fee| (fee, p))
+        })
+        .min_by_key(|(fee, _)| *fee)
+        .map(|(_, p)| p.clone())
+        .ok_or(MutinyError::LspAmountTooHighError)
+}
+
+/// Waits for the LSP's answer, failing if it doesn't come in time
+async fn await_lsp_response<T>(
+    receiver: oneshot::Receiver<Result<T, MutinyError>>,
+) -> Result<T, MutinyError> {
+    let timeout_future = async {
+        utils::sleep(LSP_RESPONSE_TIMEOUT_MS).await;
+    };
+    pin_mut!(timeout_future);
+
+    match future::select(receiver, timeout_future).await {
+        Either::Left((Ok(res), _)) => res,
+        Either::Left((Err(_), _)) => Err(MutinyError::LspGenericError),
+        Either::Right(_) => Err(MutinyError::LspConnectionError),
+    }
+}
+
 #[derive(Clone)]
 pub struct LspsClient<S: MutinyStorage> {
     pub pubkey: PublicKey,
@@ -71,7 +139,7 @@ pub struct LspsClient<S: MutinyStorage> {
     network: Network,
     logger: Arc<MutinyLogger>,
     pending_fee_requests: Arc<Mutex<HashMap<RequestId, PendingFeeRequestSender>>>,
-    pending_buy_requests: Arc<Mutex<HashMap<RequestId, PendingBuyRequestSender>>>,
+    pending_buy_requests: Arc<Mutex<HashMap<RequestId, PendingBuyRequest>>>,
     pending_channel_info: Arc<Mutex<HashMap<RequestId, JitChannelInfo>>>,
     pending_payments: Arc<Mutex<HashMap<PaymentHash, PendingPaymentInfo>>>,
     stop: Arc<AtomicBool>,
@@ -151,11 +219,14 @@ impl<S: MutinyStorage> LspsClient<S> {
 
                 let mut pending_buy_requests = self.pending_buy_requests.lock().unwrap();
 
-                if let Some(buy_response_sender) = pending_buy_requests.remove(&request_id) {
-                    let invoice_expiry_delta_secs = 3600;
+                if let Some(PendingBuyRequest {
+                    sender: buy_response_sender,
+                    invoice_expiry_secs,
+                }) = pending_buy_requests.remove(&request_id)
+                {
                     let (payment_hash, payment_secret) = match self
                         .channel_manager
-                        .create_inbound_payment(None, invoice_expiry_delta_secs, None)
+                        .create_inbound_payment(None, invoice_expiry_secs as u32, None)
                     {
                         Ok((payment_hash, payment_secret)) => (payment_hash, payment_secret),
                         Err(_) => {
@@ -234,6 +305,7 @@ impl<S: MutinyStorage> LspsClient<S> {
                         .payment_hash(payment_hash)
                         .payment_secret(payment_secret)
                         .duration_since_epoch(utils::now())
+                        .expiry_time(Duration::from_secs(invoice_expiry_secs))
                         .payee_pub_key(payee_pub_key)
                         .basic_mpp()
                         .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA.into())
@@ -344,12 +416,32 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
             request_id
         };
 
-        let get_info_response = pending_fee_request_receiver.await.map_err(|e| {
-            log_debug!(self.logger, "error receiving get info response: {:?}", e);
-            MutinyError::LspGenericError
-        })??;
+        let get_info_response = match await_lsp_response(pending_fee_request_receiver).await {
+            Ok(response) => response,
+            Err(e) => {
+                log_debug!(self.logger, "error receiving get info response: {e}");
+                self.pending_fee_requests
+                    .lock()
+                    .unwrap()
+                    .remove(&request_id);
+                return Err(e);
+            }
+        };
 
-        let fee_params = get_info_response.opening_fee_params_menu[0].clone();
+        let fee_params = select_opening_fee_params(
+            &get_info_response.opening_fee_params_menu,
+            fee_request.amount_msat,
+            utils::now().as_secs(),
+        )
+        .map_err(|e| {
+            log_error!(
+                self.logger,
+                "no usable opening fee params for {}msats in a menu of {}",
+                fee_request.amount_msat,
+                get_info_response.opening_fee_params_menu.len()
+            );
+            e
+        })?;
 
         let min_fee_msat = fee_params.min_fee_msat;
         let proportional_fee = fee_params.proportional;
@@ -409,25 +501,45 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
             .lsps2_client_handler()
             .expect("to be configured with lsps2 client config");
 
+        // the params can't be bought once expired, and the invoice shouldn't outlive them
+        let valid_for_secs =
+            (fee_params.valid_until.timestamp() as u64).saturating_sub(utils::now().as_secs());
+        if valid_for_secs == 0 {
+            log_error!(self.logger, "opening fee params expired before buying");
+            return Err(MutinyError::LspGenericError);
+        }
+
         let (pending_buy_request_sender, pending_buy_request_receiver) =
             oneshot::channel::<Result<Bolt11Invoice, MutinyError>>();
 
-        {
-            let mut pending_buy_requests: std::sync::MutexGuard<
-                '_,
-                HashMap<RequestId, oneshot::Sender<Result<Bolt11Invoice, MutinyError>>>,
-            > = self.pending_buy_requests.lock().unwrap();
+        let request_id = {
+            let mut pending_buy_requests = self.pending_buy_requests.lock().unwrap();
 
             let request_id = lsps2_client_handler
                 .select_opening_params(self.pubkey, Some(payment_size_msat), fee_params.clone())
                 .map_err(|_| MutinyError::LspGenericError)?;
 
-            pending_buy_requests.insert(request_id.clone(), pending_buy_request_sender);
-        }
+            pending_buy_requests.insert(
+                request_id.clone(),
+                PendingBuyRequest {
+                    sender: pending_buy_request_sender,
+                    invoice_expiry_secs: valid_for_secs.min(MAX_INVOICE_EXPIRY_SECS),
+                },
+            );
+            request_id
+        };
 
-        let invoice = pending_buy_request_receiver
-            .await
-            .map_err(|_| MutinyError::LspGenericError)??;
+        let invoice = match await_lsp_response(pending_buy_request_receiver).await {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                log_debug!(self.logger, "error receiving buy response: {e}");
+                self.pending_buy_requests
+                    .lock()
+                    .unwrap()
+                    .remove(&request_id);
+                return Err(e);
+            }
+        };
 
         let payment_hash = PaymentHash(invoice.payment_hash().to_byte_array());
         let payment_amount = invoice.amount_milli_satoshis();
@@ -487,3 +599,72 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use chrono::{TimeZone, Utc};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn fee_params(
+        min_fee_msat: u64,
+        valid_until: i64,
+        max_payment_size_msat: u64,
+    ) -> OpeningFeeParams {
+        OpeningFeeParams {
+            min_fee_msat,
+            proportional: 1_000,
+            valid_until: Utc.timestamp_opt(valid_until, 0).unwrap(),
+            min_lifetime: 144,
+            max_client_to_self_delay: 2016,
+            min_payment_size_msat: 10_000_000,
+            max_payment_size_msat,
+            promise: "promise".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_select_opening_fee_params() {
+        let test_name = "test_select_opening_fee_params";
+        log!("{}", test_name);
+
+        let now = 1_000;
+        let cheap = fee_params(1_000_000, 2_000, 50_000_000);
+        let pricey = fee_params(5_000_000, 2_000, 1_000_000_000);
+        let expired = fee_params(0, 999, 1_000_000_000);
+        let menu = vec![expired.clone(), pricey.clone(), cheap.clone()];
+
+        // the cheapest valid params that take the amount
+        assert_eq!(
+            select_opening_fee_params(&menu, 20_000_000, now).unwrap(),
+            cheap
+        );
+        assert_eq!(
+            select_opening_fee_params(&menu, 100_000_000, now).unwrap(),
+            pricey
+        );
+        assert_eq!(
+            select_opening_fee_params(&menu, 2_000_000_000, now).unwrap_err(),
+            MutinyError::LspAmountTooHighError
+        );
+        assert_eq!(
+            select_opening_fee_params(&menu, 1_000_000, now).unwrap_err(),
+            MutinyError::BadAmountError
+        );
+        assert_eq!(
+            select_opening_fee_params(&[expired], 20_000_000, now).unwrap_err(),
+            MutinyError::LspGenericError
+        );
+        assert!(select_opening_fee_params(&[], 20_000_000, now).is_err());
+
+        // a fee that eats the whole payment isn't usable
+        let greedy = fee_params(20_000_000, 2_000, 50_000_000);
+        assert_eq!(
+            select_opening_fee_params(&[greedy], 20_000_000, now).unwrap_err(),
+            MutinyError::LspAmountTooHighError
+        );
+    }
+}
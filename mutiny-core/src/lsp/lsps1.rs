@@ -0,0 +1,512 @@
+//! Buying inbound channels from an LSP with LSPS1.
+//!
+//! Unlike the JIT channels of LSPS2, an LSPS1 order is paid up front, over
+//! lightning or on-chain, and the LSP then opens a channel of the size we asked
+//! for. We talk to the LSP's HTTP API, keep every order in storage and follow it
+//! until our channel from it confirms.
+
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::{OutPoint, Txid};
+use lightning::log_error;
+use lightning::util::logger::Logger;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub(crate) const CHANNEL_PURCHASE_PREFIX: &str = "lsps1_order/";
+
+const GET_INFO_PATH: &str = "/api/v1/get_info";
+const CREATE_ORDER_PATH: &str = "/api/v1/create_order";
+const GET_ORDER_PATH: &str = "/api/v1/get_order";
+
+/// Expiry used when the LSP allows longer, about three months
+const DEFAULT_CHANNEL_EXPIRY_BLOCKS: u32 = 13_140;
+
+/// LSPS1 sends amounts as strings so they survive JSON number limits,
+/// some LSPs send numbers anyways
+mod string_sats {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(sats: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&sats.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+            Value::Number(n) => n
+                .as_u64()
+                .ok_or_else(|| serde::de::Error::custom("invalid amount")),
+            v => Err(serde::de::Error::custom(format!("invalid amount: {v}"))),
+        }
+    }
+}
+
+/// The channels the LSP is willing to sell
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Lsps1Options {
+    pub min_required_channel_confirmations: u16,
+    pub min_funding_confirms_within_blocks: u16,
+    #[serde(default)]
+    pub supports_zero_channel_reserve: bool,
+    pub max_channel_expiry_blocks: u32,
+    #[serde(with = "string_sats")]
+    pub min_initial_client_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub max_initial_client_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub min_initial_lsp_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub max_initial_lsp_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub min_channel_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub max_channel_balance_sat: u64,
+}
+
+impl Lsps1Options {
+    /// Checks that the LSP sells a channel with this much inbound liquidity
+    pub(crate) fn check_lsp_balance(&self, lsp_balance_sat: u64) -> Result<(), MutinyError> {
+        if lsp_balance_sat
+            < self
+                .min_initial_lsp_balance_sat
+                .max(self.min_channel_balance_sat)
+        {
+            return Err(MutinyError::BadAmountError);
+        }
+        if lsp_balance_sat
+            > self
+                .max_initial_lsp_balance_sat
+                .min(self.max_channel_balance_sat)
+        {
+            return Err(MutinyError::LspAmountTooHighError);
+        }
+        Ok(())
+    }
+
+    /// The channel expiry to ask for, at most what the LSP allows
+    pub(crate) fn channel_expiry_blocks(&self, requested: Option<u32>) -> u32 {
+        requested
+            .unwrap_or(DEFAULT_CHANNEL_EXPIRY_BLOCKS)
+            .min(self.max_channel_expiry_blocks)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Lsps1Info {
+    /// Where to connect to the LSP's node, so it can open the channel to us
+    pub uris: Vec<String>,
+    pub options: Lsps1Options,
+}
+
+impl Lsps1Info {
+    /// Older LSPs nest the options, newer ones put them next to the uris
+    fn from_response(value: Value) -> Result<Self, serde_json::Error> {
+        let uris = match value.get("uris") {
+            Some(uris) => serde_json::from_value(uris.clone())?,
+            None => vec![],
+        };
+        let options = match value.get("options") {
+            Some(options) => serde_json::from_value(options.clone())?,
+            None => serde_json::from_value(value)?,
+        };
+        Ok(Self { uris, options })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct CreateOrderRequest {
+    #[serde(with = "string_sats")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub refund_onchain_address: String,
+    pub announce_channel: bool,
+    /// Our node, the channel is opened to it
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderState {
+    Created,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderPaymentState {
+    ExpectPayment,
+    /// Paid over lightning, the LSP holds the payment until the channel is open
+    Hold,
+    Paid,
+    Refunded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Bolt11OrderPayment {
+    pub state: OrderPaymentState,
+    pub expires_at: String,
+    #[serde(with = "string_sats")]
+    pub fee_total_sat: u64,
+    #[serde(with = "string_sats")]
+    pub order_total_sat: u64,
+    pub invoice: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OnchainOrderPayment {
+    pub state: OrderPaymentState,
+    pub expires_at: String,
+    #[serde(with = "string_sats")]
+    pub fee_total_sat: u64,
+    #[serde(with = "string_sats")]
+    pub order_total_sat: u64,
+    pub address: String,
+    #[serde(default)]
+    pub min_onchain_payment_confirmations: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrderPayment {
+    #[serde(default)]
+    pub bolt11: Option<Bolt11OrderPayment>,
+    /// Not every LSP takes on-chain payments
+    #[serde(default)]
+    pub onchain: Option<OnchainOrderPayment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrderChannel {
+    pub funded_at: String,
+    /// As `txid:vout`
+    pub funding_outpoint: String,
+    pub expires_at: String,
+}
+
+impl OrderChannel {
+    pub fn outpoint(&self) -> Option<OutPoint> {
+        OutPoint::from_str(&self.funding_outpoint).ok()
+    }
+}
+
+/// An order as the LSP reports it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Lsps1Order {
+    pub order_id: String,
+    #[serde(with = "string_sats")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "string_sats")]
+    pub client_balance_sat: u64,
+    pub channel_expiry_blocks: u32,
+    pub created_at: String,
+    pub order_state: OrderState,
+    pub payment: OrderPayment,
+    #[serde(default)]
+    pub channel: Option<OrderChannel>,
+}
+
+/// How we paid for an order
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum PurchasePayment {
+    Lightning { payment_hash: String },
+    OnChain { txid: Txid },
+}
+
+/// An inbound channel bought from an LSP, tracked until it confirms
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelPurchase {
+    pub order: Lsps1Order,
+    pub lsp_url: String,
+    /// Set once we've paid for the order
+    #[serde(default)]
+    pub paid_with: Option<PurchasePayment>,
+    /// When our channel from the order confirmed
+    #[serde(default)]
+    pub confirmed_at: Option<u64>,
+    pub created_at: u64,
+}
+
+impl ChannelPurchase {
+    /// Label for the payment, so it can be tied back to the purchase in the activity
+    pub fn label(&self) -> String {
+        format!("{CHANNEL_PURCHASE_PREFIX}{}", self.order.order_id)
+    }
+
+    /// Whether the LSP may still open the channel
+    pub fn is_pending(&self) -> bool {
+        self.confirmed_at.is_none() && self.order.order_state != OrderState::Failed
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    #[serde(default)]
+    code: Option<i64>,
+    message: String,
+}
+
+pub(crate) struct Lsps1Client {
+    url: String,
+    http_client: Client,
+    logger: Arc<MutinyLogger>,
+}
+
+impl Lsps1Client {
+    pub(crate) fn new(url: &str, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            url: url.trim().trim_end_matches('/').to_string(),
+            http_client: Client::new(),
+            logger,
+        }
+    }
+
+    pub(crate) async fn get_info(&self) -> Result<Lsps1Info, MutinyError> {
+        let request = self
+            .http_client
+            .get(format!("{}{GET_INFO_PATH}", self.url))
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        let value: Value = self.send(request).await?;
+
+        Lsps1Info::from_response(value).map_err(|e| {
+            log_error!(self.logger, "Could not parse LSPS1 info: {e}");
+            MutinyError::LspGenericError
+        })
+    }
+
+    pub(crate) async fn create_order(
+        &self,
+        request: &CreateOrderRequest,
+    ) -> Result<Lsps1Order, MutinyError> {
+        let request = self
+            .http_client
+            .post(format!("{}{CREATE_ORDER_PATH}", self.url))
+            .json(request)
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        self.send(request).await
+    }
+
+    pub(crate) async fn get_order(&self, order_id: &str) -> Result<Lsps1Order, MutinyError> {
+        let request = self
+            .http_client
+            .get(format!("{}{GET_ORDER_PATH}", self.url))
+            .query(&[("order_id", order_id)])
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        self.send(request).await
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::Request) -> Result<T, MutinyError> {
+        let response = utils::fetch_with_timeout(&self.http_client, request)
+            .await
+            .map_err(|e| {
+                log_error!(self.logger, "Could not reach LSPS1 server: {e}");
+                MutinyError::LspConnectionError
+            })?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(|e| {
+            log_error!(self.logger, "Could not read LSPS1 response: {e}");
+            MutinyError::LspGenericError
+        })?;
+        if !status.is_success() {
+            match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(error) => log_error!(
+                    self.logger,
+                    "LSPS1 request failed with {status}: {} ({:?})",
+                    error.message,
+                    error.code
+                ),
+                Err(_) => log_error!(self.logger, "LSPS1 request failed with {status}: {body}"),
+            }
+            return Err(MutinyError::LspGenericError);
+        }
+
+        serde_json::from_str(&body).map_err(|e| {
+            log_error!(self.logger, "Could not parse LSPS1 response: {e}");
+            MutinyError::LspGenericError
+        })
+    }
+}
+
+fn channel_purchase_key(order_id: &str) -> String {
+    format!("{CHANNEL_PURCHASE_PREFIX}{order_id}")
+}
+
+pub(crate) fn persist_channel_purchase<S: MutinyStorage>(
+    storage: &S,
+    purchase: &ChannelPurchase,
+) -> Result<(), MutinyError> {
+    storage.write_data(
+        channel_purchase_key(&purchase.order.order_id),
+        purchase,
+        None,
+    )
+}
+
+pub(crate) fn get_channel_purchase<S: MutinyStorage>(
+    storage: &S,
+    order_id: &str,
+) -> Result<Option<ChannelPurchase>, MutinyError> {
+    storage.get_data(channel_purchase_key(order_id))
+}
+
+/// Lists the channel purchases, newest first
+pub(crate) fn list_channel_purchases<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<ChannelPurchase>, MutinyError> {
+    let mut purchases: Vec<ChannelPurchase> = storage
+        .scan::<ChannelPurchase>(CHANNEL_PURCHASE_PREFIX, None)?
+        .into_values()
+        .collect();
+    purchases.sort_by_key(|p| std::cmp::Reverse(p.created_at));
+
+    Ok(purchases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn options() -> Value {
+        json!({
+            "min_required_channel_confirmations": 0,
+            "min_funding_confirms_within_blocks": 6,
+            "supports_zero_channel_reserve": true,
+            "max_channel_expiry_blocks": 20160,
+            "min_initial_client_balance_sat": "0",
+            "max_initial_client_balance_sat": "0",
+            "min_initial_lsp_balance_sat": "100000",
+            "max_initial_lsp_balance_sat": "10000000",
+            "min_channel_balance_sat": "50000",
+            "max_channel_balance_sat": 5000000,
+        })
+    }
+
+    #[test]
+    fn test_lsps1_info() {
+        let test_name = "test_lsps1_info";
+        log!("{}", test_name);
+
+        let nested = Lsps1Info::from_response(json!({ "options": options() })).unwrap();
+        let mut flat = options();
+        flat["uris"] = json!(["02aa@127.0.0.1:9735"]);
+        let flat = Lsps1Info::from_response(flat).unwrap();
+        assert_eq!(nested.options, flat.options);
+        assert!(nested.uris.is_empty());
+        assert_eq!(flat.uris, vec!["02aa@127.0.0.1:9735".to_string()]);
+
+        let options = flat.options;
+        assert_eq!(options.max_channel_balance_sat, 5_000_000);
+        options.check_lsp_balance(1_000_000).unwrap();
+        assert_eq!(
+            options.check_lsp_balance(99_999),
+            Err(MutinyError::BadAmountError)
+        );
+        // the smaller of the two maximums applies
+        assert_eq!(
+            options.check_lsp_balance(6_000_000),
+            Err(MutinyError::LspAmountTooHighError)
+        );
+        assert_eq!(
+            options.channel_expiry_blocks(None),
+            DEFAULT_CHANNEL_EXPIRY_BLOCKS
+        );
+        assert_eq!(options.channel_expiry_blocks(Some(50_000)), 20_160);
+    }
+
+    #[test]
+    fn test_channel_purchases() {
+        let test_name = "test_channel_purchases";
+        log!("{}", test_name);
+
+        let order: Lsps1Order = serde_json::from_value(json!({
+            "order_id": "bb4b5d0a-8334-49d8-9463-90a6d413af7c",
+            "lsp_balance_sat": "1000000",
+            "client_balance_sat": "0",
+            "required_channel_confirmations": 0,
+            "funding_confirms_within_blocks": 6,
+            "channel_expiry_blocks": 13140,
+            "token": "",
+            "created_at": "2024-01-01T00:00:00Z",
+            "announce_channel": false,
+            "order_state": "CREATED",
+            "payment": {
+                "bolt11": {
+                    "state": "EXPECT_PAYMENT",
+                    "expires_at": "2024-01-01T01:00:00Z",
+                    "fee_total_sat": "8888",
+                    "order_total_sat": "8888",
+                    "invoice": "lnbc888880n1p..."
+                }
+            },
+            "channel": null
+        }))
+        .unwrap();
+        assert_eq!(order.lsp_balance_sat, 1_000_000);
+        assert_eq!(order.payment.onchain, None);
+        assert_eq!(
+            order.payment.bolt11.as_ref().unwrap().state,
+            OrderPaymentState::ExpectPayment
+        );
+
+        let storage = MemoryStorage::default();
+        let older = ChannelPurchase {
+            order: order.clone(),
+            lsp_url: "https://lsp.example.com".to_string(),
+            paid_with: None,
+            confirmed_at: None,
+            created_at: 1,
+        };
+        let mut newer = older.clone();
+        newer.order.order_id = "second".to_string();
+        newer.created_at = 2;
+        newer.order.order_state = OrderState::Failed;
+        persist_channel_purchase(&storage, &older).unwrap();
+        persist_channel_purchase(&storage, &newer).unwrap();
+
+        assert!(older.is_pending());
+        assert!(!newer.is_pending());
+        assert_eq!(
+            older.label(),
+            "lsps1_order/bb4b5d0a-8334-49d8-9463-90a6d413af7c"
+        );
+        assert_eq!(
+            get_channel_purchase(&storage, &order.order_id).unwrap(),
+            Some(older.clone())
+        );
+        assert_eq!(
+            list_channel_purchases(&storage).unwrap(),
+            vec![newer, older]
+        );
+
+        let channel = OrderChannel {
+            funded_at: "2024-01-01T00:10:00Z".to_string(),
+            funding_outpoint: "0101010101010101010101010101010101010101010101010101010101010101:1"
+                .to_string(),
+            expires_at: "2024-04-01T00:10:00Z".to_string(),
+        };
+        assert_eq!(channel.outpoint().unwrap().vout, 1);
+    }
+}
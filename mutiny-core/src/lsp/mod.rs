@@ -20,6 +20,7 @@ use std::sync::{atomic::AtomicBool, Arc};
 use voltage::LspClient;
 
 pub mod lsps;
+pub mod lsps1;
 pub mod voltage;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -84,6 +85,101 @@ where
     }
 }
 
+pub(crate) const LSP_LIST_KEY: &str = "lsp_list";
+
+/// Fees under this are never too high, JIT channels have a minimum fee that is
+/// large compared to small payments
+pub const DEFAULT_MAX_LSP_FEE_BASE_SATS: u64 = 5_000;
+/// 5% of the amount received
+pub const DEFAULT_MAX_LSP_FEE_PPM: u64 = 50_000;
+
+/// The LSPs to use in order of preference. When the one in use can't be reached
+/// or quotes more than the fee limit the wallet moves on to the next.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LspList {
+    pub lsps: Vec<LspConfig>,
+    pub max_fee_base_sats: u64,
+    pub max_fee_ppm: u64,
+}
+
+impl Default for LspList {
+    fn default() -> Self {
+        Self {
+            lsps: vec![],
+            max_fee_base_sats: DEFAULT_MAX_LSP_FEE_BASE_SATS,
+            max_fee_ppm: DEFAULT_MAX_LSP_FEE_PPM,
+        }
+    }
+}
+
+impl LspList {
+    /// Whether the fee quoted for receiving the amount is over our limit
+    pub fn is_fee_too_high(&self, amount_sat: u64, fee_sat: u64) -> bool {
+        let limit = self
+            .max_fee_base_sats
+            .max(amount_sat.saturating_mul(self.max_fee_ppm) / 1_000_000);
+        fee_sat > limit
+    }
+
+    /// The LSP to move to from the current one, wrapping around to the first.
+    /// None if there is no other LSP to move to.
+    pub fn next_after(&self, current: &LspConfig) -> Option<&LspConfig> {
+        let next = match self.lsps.iter().position(|l| l.matches(current)) {
+            Some(i) => (i + 1) % self.lsps.len(),
+            None => 0,
+        };
+        self.lsps.get(next).filter(|l| !l.matches(current))
+    }
+}
+
+/// Why the wallet moved on from an LSP
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum LspFailoverReason {
+    Unreachable { error: String },
+    FeeTooHigh { amount_sat: u64, fee_sat: u64 },
+}
+
+impl LspFailoverReason {
+    /// The reason to fail over for the result of a fee request, if any
+    pub(crate) fn from_fee_result(
+        list: &LspList,
+        amount_sat: u64,
+        res: &Result<u64, MutinyError>,
+    ) -> Option<Self> {
+        match res {
+            Ok(fee_sat) if list.is_fee_too_high(amount_sat, *fee_sat) => Some(Self::FeeTooHigh {
+                amount_sat,
+                fee_sat: *fee_sat,
+            }),
+            Ok(_) => None,
+            Err(e) => Self::from_error(e),
+        }
+    }
+
+    pub(crate) fn from_error(e: &MutinyError) -> Option<Self> {
+        match e {
+            MutinyError::ConnectionFailed
+            | MutinyError::LspConnectionError
+            | MutinyError::LspGenericError => Some(Self::Unreachable {
+                error: e.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn get_lsp_list<S: MutinyStorage>(storage: &S) -> Result<LspList, MutinyError> {
+    Ok(storage.get_data(LSP_LIST_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_lsp_list<S: MutinyStorage>(
+    storage: &S,
+    list: &LspList,
+) -> Result<(), MutinyError> {
+    storage.write_data(LSP_LIST_KEY.to_string(), list, None)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct InvoiceRequest {
     // Used only for VoltageFlow
@@ -236,3 +332,73 @@ impl<S: MutinyStorage> Lsp for AnyLsp<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_lsp_list_failover() {
+        let test_name = "test_lsp_list_failover";
+        log!("{}", test_name);
+
+        let primary = LspConfig::new_voltage_flow("https://lsp.one".to_string());
+        let backup = LspConfig::new_lsps("pubkey@lsp.two:9735".to_string(), None);
+        let list = LspList {
+            lsps: vec![primary.clone(), backup.clone()],
+            ..Default::default()
+        };
+
+        assert_eq!(list.next_after(&primary), Some(&backup));
+        assert_eq!(list.next_after(&backup), Some(&primary));
+        // an LSP that isn't listed moves to the first one
+        let other = LspConfig::new_voltage_flow("https://lsp.three".to_string());
+        assert_eq!(list.next_after(&other), Some(&primary));
+        let single = LspList {
+            lsps: vec![primary.clone()],
+            ..Default::default()
+        };
+        assert_eq!(single.next_after(&primary), None);
+        assert_eq!(LspList::default().next_after(&primary), None);
+
+        // the base limit covers small amounts, the ppm limit large ones
+        assert!(!list.is_fee_too_high(10_000, 5_000));
+        assert!(list.is_fee_too_high(10_000, 5_001));
+        assert!(!list.is_fee_too_high(1_000_000, 50_000));
+        assert!(list.is_fee_too_high(1_000_000, 50_001));
+
+        assert_eq!(
+            LspFailoverReason::from_fee_result(&list, 10_000, &Ok(6_000)),
+            Some(LspFailoverReason::FeeTooHigh {
+                amount_sat: 10_000,
+                fee_sat: 6_000
+            })
+        );
+        assert_eq!(
+            LspFailoverReason::from_fee_result(&list, 10_000, &Ok(2_500)),
+            None
+        );
+        assert!(matches!(
+            LspFailoverReason::from_fee_result(
+                &list,
+                10_000,
+                &Err(MutinyError::LspConnectionError)
+            ),
+            Some(LspFailoverReason::Unreachable { .. })
+        ));
+        assert_eq!(
+            LspFailoverReason::from_fee_result(&list, 10, &Err(MutinyError::BadAmountError)),
+            None
+        );
+
+        let storage = MemoryStorage::default();
+        assert_eq!(get_lsp_list(&storage).unwrap(), LspList::default());
+        persist_lsp_list(&storage, &list).unwrap();
+        assert_eq!(get_lsp_list(&storage).unwrap(), list);
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of buffered logs kept in memory when trimming
+pub(crate) const TRIMMED_MEMORY_LOG_ITEMS: usize = 100;
+/// Number of persisted logs kept when trimming
+pub(crate) const TRIMMED_LOG_ITEMS: usize = 1_000;
+
+/// Approximate size of the largest structures the wallet keeps in memory.
+/// Sizes are estimates based on the serialized data, not exact heap usage.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub network_graph_bytes: u64,
+    pub network_graph_nodes: u64,
+    pub network_graph_channels: u64,
+    pub activity_index_items: u64,
+    pub activity_index_bytes: u64,
+    /// Logs buffered in memory and persisted logs
+    pub log_bytes: u64,
+}
+
+impl MemoryUsage {
+    pub fn total_bytes(&self) -> u64 {
+        self.network_graph_bytes + self.activity_index_bytes + self.log_bytes
+    }
+
+    /// Whether usage is over the given budget, always false without a budget
+    pub fn over_budget(&self, budget_bytes: Option<u64>) -> bool {
+        budget_bytes.is_some_and(|budget| self.total_bytes() > budget)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_memory_budget() {
+        let test_name = "test_memory_budget";
+        log!("{}", test_name);
+
+        let usage = MemoryUsage {
+            network_graph_bytes: 1_000,
+            activity_index_bytes: 200,
+            log_bytes: 300,
+            ..Default::default()
+        };
+        assert_eq!(usage.total_bytes(), 1_500);
+        assert!(!usage.over_budget(None));
+        assert!(!usage.over_budget(Some(1_500)));
+        assert!(usage.over_budget(Some(1_499)));
+    }
+}
@@ -9,6 +9,7 @@ use lightning::ln::wire::{CustomMessageReader, Type};
 use lightning::util::ser::{Writeable, Writer};
 use serde::{Deserialize, Serialize};
 
+use crate::lsp::{LspConfig, LspFailoverReason};
 use crate::node::LiquidityManager;
 use crate::storage::MutinyStorage;
 
@@ -65,6 +66,60 @@ pub enum CommonLnEvent {
         payment_hash: String,
         amount_msat: u64,
     },
+    // Every share of a split request has been paid
+    SplitRequestCompleted {
+        id: String,
+        total_sats: u64,
+    },
+    // A swap deposit timed out and its refund transaction confirmed
+    SwapDepositRefunded {
+        id: String,
+        txid: String,
+    },
+    // A channel was opened with the deposit of a channel funding request
+    ChannelFundingOpened {
+        id: String,
+        outpoint: String,
+    },
+    // The esplora server kept failing and the wallet switched to the next one,
+    // it's used once the wallet is restarted
+    EsploraFailover {
+        previous_url: String,
+        url: String,
+    },
+    // The LSP couldn't be reached or quoted too high a fee and the wallet switched
+    // to the next configured one, it's used once the wallet is restarted
+    LspFailover {
+        previous: LspConfig,
+        next: LspConfig,
+        reason: LspFailoverReason,
+    },
+    // The channel state is newer than any verified backup
+    BackupReminder {
+        /// When the channel state last changed, in epoch seconds
+        state_changed_at: u64,
+        /// When a backup was last verified, in epoch seconds
+        last_verified_at: Option<u64>,
+    },
+    // An exported backup was verified to contain the latest channel state
+    BackupVerified {
+        verified_at: u64,
+    },
+    // VSS stopped answering or is reachable again. While it is down the wallet
+    // runs on local state and keeps the writes to send once it is back.
+    RemoteStorageStatusChanged {
+        reachable: bool,
+        pending_remote_writes: usize,
+    },
+    // The wallet is already open in another instance on this device,
+    // this instance has been started read-only
+    ConcurrentInstanceDetected {
+        /// The instance that is holding the lock
+        locked_by: String,
+    },
+    // The instance that held the lock stopped, restarting this read-only
+    // instance brings it up read-write
+    InstanceLockReleased,
 }
 
 #[derive(Clone)]
@@ -0,0 +1,117 @@
+//! Stable codes for error and status messages, and the translations for them.
+//!
+//! Frontends should match on codes rather than on the English text, which can
+//! change. A catalog for the user's language can be installed at runtime,
+//! anything it doesn't translate falls back to English.
+
+use crate::event::HTLCStatus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+static MESSAGE_CATALOG: RwLock<Option<MessageCatalog>> = RwLock::new(None);
+
+/// Anything that can be shown to the user and has a stable code for it
+pub trait MessageCode {
+    /// A stable, snake_case code, this never changes once released
+    fn code(&self) -> &'static str;
+
+    /// The English message, used when there is no translation
+    fn default_message(&self) -> String;
+
+    /// The message in the installed catalog's language
+    fn localized_message(&self) -> String {
+        localize(self.code(), &self.default_message())
+    }
+}
+
+/// Translations for one language, keyed by message code
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageCatalog {
+    /// BCP 47 language tag, like "es" or "pt-BR"
+    pub locale: String,
+    pub messages: HashMap<String, String>,
+}
+
+impl MessageCatalog {
+    pub fn new(locale: String, messages: HashMap<String, String>) -> Self {
+        Self { locale, messages }
+    }
+
+    pub fn get(&self, code: &str) -> Option<&str> {
+        self.messages.get(code).map(|m| m.as_str())
+    }
+}
+
+/// Installs the catalog used for localized messages, `None` goes back to English
+pub fn set_message_catalog(catalog: Option<MessageCatalog>) {
+    if let Ok(mut current) = MESSAGE_CATALOG.write() {
+        *current = catalog;
+    }
+}
+
+/// The locale of the installed catalog, if there is one
+pub fn message_catalog_locale() -> Option<String> {
+    MESSAGE_CATALOG
+        .read()
+        .ok()
+        .and_then(|c| c.as_ref().map(|c| c.locale.clone()))
+}
+
+/// Translates the code with the installed catalog, or returns the default
+pub fn localize(code: &str, default: &str) -> String {
+    MESSAGE_CATALOG
+        .read()
+        .ok()
+        .and_then(|c| c.as_ref().and_then(|c| c.get(code).map(|m| m.to_string())))
+        .unwrap_or_else(|| default.to_string())
+}
+
+impl MessageCode for HTLCStatus {
+    fn code(&self) -> &'static str {
+        match self {
+            HTLCStatus::Pending => "status_pending",
+            HTLCStatus::InFlight => "status_in_flight",
+            HTLCStatus::Succeeded => "status_succeeded",
+            HTLCStatus::Failed => "status_failed",
+        }
+    }
+
+    fn default_message(&self) -> String {
+        match self {
+            HTLCStatus::Pending => "Pending",
+            HTLCStatus::InFlight => "In flight",
+            HTLCStatus::Succeeded => "Succeeded",
+            HTLCStatus::Failed => "Failed",
+        }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_message_catalog() {
+        let test_name = "test_message_catalog";
+        log!("{}", test_name);
+
+        assert_eq!(HTLCStatus::InFlight.localized_message(), "In flight");
+
+        let messages = HashMap::from([("status_in_flight".to_string(), "En curso".to_string())]);
+        set_message_catalog(Some(MessageCatalog::new("es".to_string(), messages)));
+        assert_eq!(message_catalog_locale(), Some("es".to_string()));
+        assert_eq!(HTLCStatus::InFlight.localized_message(), "En curso");
+        // untranslated codes fall back to english
+        assert_eq!(HTLCStatus::Failed.localized_message(), "Failed");
+
+        set_message_catalog(None);
+        assert_eq!(message_catalog_locale(), None);
+        assert_eq!(HTLCStatus::InFlight.localized_message(), "In flight");
+    }
+}
@@ -0,0 +1,254 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::MutinyInvoice;
+use bitcoin::bech32;
+use hex_conservative::DisplayHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub(crate) const MODERATION_SETTINGS_KEY: &str = "moderation_settings";
+/// NIP-57 zap request, these end up as the description of zap invoices
+const ZAP_REQUEST_KIND: u64 = 9734;
+
+/// Filters for zap comments and direct messages. They are applied in core
+/// so every client shows the same thing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModerationSettings {
+    /// Comments and messages containing any of these words are hidden, case insensitive
+    #[serde(default)]
+    pub blocked_words: Vec<String>,
+    /// Comments on zaps smaller than this are hidden, the zap itself is still shown
+    #[serde(default)]
+    pub min_zap_comment_sats: Option<u64>,
+    /// Only show comments and messages from pubkeys in `followed_pubkeys`
+    #[serde(default)]
+    pub followed_only: bool,
+    /// Hex encoded pubkeys, npubs are converted when the settings are saved
+    #[serde(default)]
+    pub followed_pubkeys: Vec<String>,
+}
+
+/// A decrypted direct message to be run through the filters
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DirectMessage {
+    pub id: String,
+    /// Hex encoded pubkey of the sender
+    pub from: String,
+    pub content: String,
+    pub created_at: u64,
+}
+
+pub(crate) fn normalize_pubkey(pubkey: &str) -> Result<String, MutinyError> {
+    let pubkey = pubkey.trim().to_lowercase();
+    if pubkey.starts_with("npub") {
+        let (hrp, data) =
+            bech32::decode(&pubkey).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        if hrp.to_string() != "npub" || data.len() != 32 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        return Ok(data.to_lower_hex_string());
+    }
+
+    if pubkey.len() != 64 || !pubkey.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(MutinyError::PubkeyInvalid);
+    }
+    Ok(pubkey)
+}
+
+impl ModerationSettings {
+    /// Lowercases the blocked words and converts npubs to hex so they can be compared
+    pub(crate) fn normalize(mut self) -> Result<Self, MutinyError> {
+        let mut words = self
+            .blocked_words
+            .iter()
+            .map(|w| w.trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        words.sort();
+        words.dedup();
+        self.blocked_words = words;
+
+        let mut pubkeys = self
+            .followed_pubkeys
+            .iter()
+            .map(|p| normalize_pubkey(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        pubkeys.sort();
+        pubkeys.dedup();
+        self.followed_pubkeys = pubkeys;
+
+        Ok(self)
+    }
+
+    /// Whether text from the given author should be shown
+    pub fn allows(&self, author: Option<&str>, content: &str) -> bool {
+        if self.followed_only {
+            let followed = author
+                .map(|a| a.to_lowercase())
+                .is_some_and(|a| self.followed_pubkeys.contains(&a));
+            if !followed {
+                return false;
+            }
+        }
+
+        let content = content.to_lowercase();
+        !self.blocked_words.iter().any(|w| content.contains(w))
+    }
+
+    /// Whether the comment on a zap of the given amount should be shown
+    pub fn allows_zap_comment(
+        &self,
+        author: Option<&str>,
+        content: &str,
+        amount_sats: Option<u64>,
+    ) -> bool {
+        if let Some(min) = self.min_zap_comment_sats {
+            if amount_sats.unwrap_or_default() < min {
+                return false;
+            }
+        }
+        self.allows(author, content)
+    }
+
+    /// Clears the comment from an incoming zap if it doesn't pass the filters.
+    /// Returns true if the comment was hidden.
+    pub(crate) fn moderate_invoice(&self, invoice: &mut MutinyInvoice) -> bool {
+        if !invoice.inbound {
+            return false;
+        }
+        let Some(mut zap_request) = invoice
+            .description
+            .as_deref()
+            .and_then(|d| serde_json::from_str::<Value>(d).ok())
+            .filter(|v| v.get("kind").and_then(|k| k.as_u64()) == Some(ZAP_REQUEST_KIND))
+        else {
+            return false;
+        };
+
+        let author = zap_request.get("pubkey").and_then(|p| p.as_str());
+        let content = zap_request
+            .get("content")
+            .and_then(|c| c.as_str())
+            .unwrap_or_default();
+        if content.is_empty() || self.allows_zap_comment(author, content, invoice.amount_sats) {
+            return false;
+        }
+
+        // keep the zap request so the sender is still shown, just without the comment
+        zap_request["content"] = Value::String(String::new());
+        invoice.description = Some(zap_request.to_string());
+        true
+    }
+}
+
+pub(crate) fn get_moderation_settings<S: MutinyStorage>(
+    storage: &S,
+) -> Result<ModerationSettings, MutinyError> {
+    Ok(storage
+        .get_data(MODERATION_SETTINGS_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn persist_moderation_settings<S: MutinyStorage>(
+    storage: &S,
+    settings: &ModerationSettings,
+) -> Result<(), MutinyError> {
+    storage.write_data(MODERATION_SETTINGS_KEY.to_string(), settings, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const PUBKEY: &str = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+    const NPUB: &str = "npub180cvv07tjdrrgpa0j7j7tmnyl2yr6yr7l8j4s3evf6u64th6gkwsyjh6w6";
+
+    #[test]
+    fn test_moderation_filters() {
+        let test_name = "test_moderation_filters";
+        log!("{}", test_name);
+
+        let settings = ModerationSettings {
+            blocked_words: vec![" Scam ".to_string(), "scam".to_string()],
+            min_zap_comment_sats: Some(21),
+            followed_only: false,
+            followed_pubkeys: vec![NPUB.to_string()],
+        }
+        .normalize()
+        .unwrap();
+        assert_eq!(settings.blocked_words, vec!["scam".to_string()]);
+        assert_eq!(settings.followed_pubkeys, vec![PUBKEY.to_string()]);
+
+        assert!(settings.allows(None, "gm"));
+        assert!(!settings.allows(None, "free SCAM here"));
+        assert!(!settings.allows_zap_comment(None, "gm", Some(1)));
+        assert!(settings.allows_zap_comment(None, "gm", Some(21)));
+
+        let settings = ModerationSettings {
+            followed_only: true,
+            ..settings
+        };
+        assert!(!settings.allows(None, "gm"));
+        assert!(settings.allows(Some(PUBKEY), "gm"));
+
+        assert!(ModerationSettings {
+            followed_pubkeys: vec!["not a pubkey".to_string()],
+            ..Default::default()
+        }
+        .normalize()
+        .is_err());
+    }
+
+    #[test]
+    fn test_moderate_zap_invoice() {
+        let test_name = "test_moderate_zap_invoice";
+        log!("{}", test_name);
+
+        let settings = ModerationSettings {
+            min_zap_comment_sats: Some(100),
+            ..Default::default()
+        };
+        let zap_request = json!({
+            "kind": ZAP_REQUEST_KIND,
+            "pubkey": PUBKEY,
+            "content": "great post",
+            "tags": [],
+        });
+        let mut invoice = MutinyInvoice {
+            description: Some(zap_request.to_string()),
+            amount_sats: Some(10),
+            inbound: true,
+            ..Default::default()
+        };
+
+        assert!(settings.moderate_invoice(&mut invoice));
+        let description: Value =
+            serde_json::from_str(invoice.description.as_ref().unwrap()).unwrap();
+        assert_eq!(description["content"], "");
+        assert_eq!(description["pubkey"], PUBKEY);
+
+        // big enough zaps keep their comment
+        let mut invoice = MutinyInvoice {
+            description: Some(zap_request.to_string()),
+            amount_sats: Some(1_000),
+            inbound: true,
+            ..Default::default()
+        };
+        assert!(!settings.moderate_invoice(&mut invoice));
+
+        // regular descriptions are left alone
+        let mut invoice = MutinyInvoice {
+            description: Some("coffee".to_string()),
+            amount_sats: Some(10),
+            inbound: true,
+            ..Default::default()
+        };
+        assert!(!settings.moderate_invoice(&mut invoice));
+        assert_eq!(invoice.description.as_deref(), Some("coffee"));
+    }
+}
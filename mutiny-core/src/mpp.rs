@@ -0,0 +1,313 @@
+//! Splitting large lightning payments into parts (MPP) that fit the
+//! liquidity we expect along the way.
+//!
+//! LDK's router does the actual splitting. What we control is how many parts
+//! it may use, how much of a channel's capacity one part may take, and how
+//! many times failed parts get retried. A retry routes the failed part again,
+//! splitting it further when it no longer fits. Large sends fail far less
+//! often when these limits come from the channels we actually have.
+//!
+//! On top of that, an [`MppConfig`] can cap the number of parts, ask for
+//! smaller parts, or turn splitting off. It's set as the wallet's default and
+//! can be overridden for a single payment.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const MPP_CONFIG_KEY: &str = "mpp_config";
+
+/// LDK's default for the most parts a payment is split into
+const DEFAULT_MAX_PATH_COUNT: u8 = 10;
+/// Past this, the onion for each part barely has any amount left to carry
+const MAX_PATH_COUNT: u8 = 32;
+/// LDK's default: a part may only use a quarter of a channel's capacity
+const DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF: u8 = 2;
+const DEFAULT_RETRY_ATTEMPTS: u32 = 15;
+/// Extra retries for every part, each failed part can need a few re-splits
+const RETRY_ATTEMPTS_PER_PART: u32 = 3;
+const MAX_RETRY_ATTEMPTS: u32 = 50;
+
+/// How payments are split into parts
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum MppStrategy {
+    /// LDK's defaults, the same limits for every payment
+    #[default]
+    Default,
+    /// Sizes the parts from our channels' outbound balances and the scorer's
+    /// liquidity estimates for the recipient's channels
+    ChannelAware,
+}
+
+impl core::str::FromStr for MppStrategy {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" | "Default" => Ok(Self::Default),
+            "channel_aware" | "ChannelAware" => Ok(Self::ChannelAware),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// Limits on how a payment is split. Anything left unset falls back to the
+/// wallet's default, and from there to the [`MppStrategy`].
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MppConfig {
+    /// Whether the payment may be split at all
+    pub allow_mpp: Option<bool>,
+    /// The most parts a payment is split into
+    pub max_parts: Option<u8>,
+    /// The largest part we'd like to send, in sats. LDK's router can't be given
+    /// a largest part, so this makes sure it may use enough parts to stay under it.
+    pub max_shard_sats: Option<u64>,
+}
+
+impl MppConfig {
+    /// Fills in what isn't set here from the other config
+    pub(crate) fn or(self, other: MppConfig) -> MppConfig {
+        MppConfig {
+            allow_mpp: self.allow_mpp.or(other.allow_mpp),
+            max_parts: self.max_parts.or(other.max_parts),
+            max_shard_sats: self.max_shard_sats.or(other.max_shard_sats),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), MutinyError> {
+        if self.max_parts.is_some_and(|p| p == 0 || p > MAX_PATH_COUNT)
+            || self.max_shard_sats == Some(0)
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn get_mpp_config<S: MutinyStorage>(storage: &S) -> Result<MppConfig, MutinyError> {
+    Ok(storage.get_data(MPP_CONFIG_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn set_mpp_config<S: MutinyStorage>(
+    storage: &S,
+    config: MppConfig,
+) -> Result<(), MutinyError> {
+    config.validate()?;
+    storage.write_data(MPP_CONFIG_KEY.to_string(), config, None)
+}
+
+/// The limits given to the router for one payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MppParams {
+    pub max_path_count: u8,
+    pub max_channel_saturation_power_of_half: u8,
+    pub retry_attempts: u32,
+}
+
+impl Default for MppParams {
+    fn default() -> Self {
+        Self {
+            max_path_count: DEFAULT_MAX_PATH_COUNT,
+            max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF,
+            retry_attempts: DEFAULT_RETRY_ATTEMPTS,
+        }
+    }
+}
+
+/// How many of the biggest channels it takes to carry the amount,
+/// `None` if all of them together can't
+fn parts_needed(amount_msat: u64, liquidity_msat: &[u64]) -> Option<usize> {
+    let mut liquidity = liquidity_msat.to_vec();
+    liquidity.sort_unstable_by(|a, b| b.cmp(a));
+    let mut total = 0u64;
+    for (i, l) in liquidity.into_iter().enumerate() {
+        total = total.saturating_add(l);
+        if total >= amount_msat {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+fn saturated(liquidity_msat: &[u64], power_of_half: u8) -> Vec<u64> {
+    liquidity_msat.iter().map(|l| l >> power_of_half).collect()
+}
+
+/// Picks the router limits for sending `amount_msat`.
+///
+/// `outbound_msat` is what each of our usable channels can send right now,
+/// `inbound_estimates_msat` is the most the scorer thinks each of the
+/// recipient's channels can receive, empty when we know nothing about them.
+pub(crate) fn plan_mpp(
+    strategy: MppStrategy,
+    amount_msat: u64,
+    outbound_msat: &[u64],
+    inbound_estimates_msat: &[u64],
+) -> MppParams {
+    if strategy == MppStrategy::Default {
+        return MppParams::default();
+    }
+
+    // use as little of each channel as still lets the whole amount through,
+    // big parts are less likely to find a route
+    let fits = |power: u8| {
+        parts_needed(amount_msat, &saturated(outbound_msat, power)).is_some()
+            && (inbound_estimates_msat.is_empty()
+                || parts_needed(amount_msat, &saturated(inbound_estimates_msat, power)).is_some())
+    };
+    let max_channel_saturation_power_of_half = (0..=DEFAULT_MAX_CHANNEL_SATURATION_POWER_OF_HALF)
+        .rev()
+        .find(|power| fits(*power))
+        .unwrap_or(0);
+
+    let outbound = saturated(outbound_msat, max_channel_saturation_power_of_half);
+    let inbound = saturated(inbound_estimates_msat, max_channel_saturation_power_of_half);
+    let parts = parts_needed(amount_msat, &outbound)
+        .unwrap_or(outbound.len())
+        .max(parts_needed(amount_msat, &inbound).unwrap_or(inbound.len()))
+        .max(1);
+
+    // leave room for every part to be split again when it fails
+    let max_path_count =
+        (parts * 2).clamp(DEFAULT_MAX_PATH_COUNT as usize, MAX_PATH_COUNT as usize) as u8;
+    let retry_attempts =
+        (DEFAULT_RETRY_ATTEMPTS + RETRY_ATTEMPTS_PER_PART * parts as u32).min(MAX_RETRY_ATTEMPTS);
+
+    MppParams {
+        max_path_count,
+        max_channel_saturation_power_of_half,
+        retry_attempts,
+    }
+}
+
+/// Applies the configured limits on top of the strategy's plan for sending `amount_msat`
+pub(crate) fn apply_mpp_config(
+    mut params: MppParams,
+    config: &MppConfig,
+    amount_msat: u64,
+) -> MppParams {
+    if config.allow_mpp == Some(false) {
+        params.max_path_count = 1;
+        return params;
+    }
+
+    if let Some(max_shard_sats) = config.max_shard_sats {
+        let parts = amount_msat
+            .div_ceil(max_shard_sats.saturating_mul(1_000).max(1))
+            .min(MAX_PATH_COUNT as u64) as u8;
+        if parts > params.max_path_count {
+            params.max_path_count = parts;
+            params.retry_attempts = (params.retry_attempts
+                + RETRY_ATTEMPTS_PER_PART * parts as u32)
+                .min(MAX_RETRY_ATTEMPTS);
+        }
+    }
+    if let Some(max_parts) = config.max_parts {
+        params.max_path_count = params.max_path_count.min(max_parts.max(1));
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_plan_mpp() {
+        let test_name = "test_plan_mpp";
+        log!("{}", test_name);
+
+        let channels = [1_000_000_000, 400_000_000, 100_000_000];
+
+        // the default strategy never looks at the channels
+        assert_eq!(
+            plan_mpp(MppStrategy::Default, 1_200_000_000, &channels, &[]),
+            MppParams::default()
+        );
+
+        // small payments keep the defaults
+        let small = plan_mpp(MppStrategy::ChannelAware, 10_000_000, &channels, &[]);
+        assert_eq!(small.max_channel_saturation_power_of_half, 2);
+        assert_eq!(small.max_path_count, DEFAULT_MAX_PATH_COUNT);
+
+        // a quarter of every channel isn't enough, half of them is
+        let large = plan_mpp(MppStrategy::ChannelAware, 700_000_000, &channels, &[]);
+        assert_eq!(large.max_channel_saturation_power_of_half, 1);
+        assert!(large.retry_attempts > DEFAULT_RETRY_ATTEMPTS);
+
+        // nearly everything we have needs the whole of each channel
+        let all = plan_mpp(MppStrategy::ChannelAware, 1_400_000_000, &channels, &[]);
+        assert_eq!(all.max_channel_saturation_power_of_half, 0);
+
+        // the recipient's side can be the tighter one
+        let inbound = plan_mpp(
+            MppStrategy::ChannelAware,
+            100_000_000,
+            &channels,
+            &[60_000_000, 60_000_000],
+        );
+        assert_eq!(inbound.max_channel_saturation_power_of_half, 0);
+
+        assert_eq!(
+            MppStrategy::from_str("channel_aware").unwrap(),
+            MppStrategy::ChannelAware
+        );
+        assert!(MppStrategy::from_str("random").is_err());
+    }
+
+    #[test]
+    fn test_apply_mpp_config() {
+        let test_name = "test_apply_mpp_config";
+        log!("{}", test_name);
+
+        let plan = MppParams::default();
+        assert_eq!(
+            apply_mpp_config(plan, &MppConfig::default(), 1_000_000),
+            plan
+        );
+
+        let single = MppConfig {
+            allow_mpp: Some(false),
+            max_parts: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(apply_mpp_config(plan, &single, 1_000_000).max_path_count, 1);
+
+        // 1m sats in 50k shards needs 20 parts
+        let shards = MppConfig {
+            max_shard_sats: Some(50_000),
+            ..Default::default()
+        };
+        let sharded = apply_mpp_config(plan, &shards, 1_000_000_000);
+        assert_eq!(sharded.max_path_count, 20);
+        assert!(sharded.retry_attempts > plan.retry_attempts);
+
+        // the cap on parts wins
+        let capped = MppConfig {
+            max_parts: Some(4),
+            ..shards
+        };
+        assert_eq!(
+            apply_mpp_config(plan, &capped, 1_000_000_000).max_path_count,
+            4
+        );
+
+        // a payment's own limits go over the wallet's default
+        assert_eq!(capped.or(single).allow_mpp, Some(false));
+        assert_eq!(capped.or(single).max_parts, Some(4));
+
+        assert!(MppConfig {
+            max_parts: Some(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(capped.validate().is_ok());
+    }
+}
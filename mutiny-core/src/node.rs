@@ -1,15 +1,39 @@
+use crate::anchorbump::{
+    get_anchor_bump, set_anchor_bump_feerate, with_feerate, AnchorBump, AnchorCloseEvents,
+};
+use crate::descriptionprivacy::{
+    get_description_privacy, DescriptionPrivacy, InvoiceDescription, GENERIC_INVOICE_DESCRIPTION,
+};
+use crate::hodl::{get_hodl_invoice, persist_hodl_invoice, HodlInvoice, HodlInvoiceStatus};
+use crate::hooks::HookRegistry;
 use crate::lsp::LspConfig;
 use crate::messagehandler::CommonLnEventCallback;
+use crate::mpp::{apply_mpp_config, get_mpp_config, plan_mpp, MppConfig, MppParams, MppStrategy};
 use crate::nodemanager::ChannelClosure;
+use crate::offers::{
+    offer_payment_msats, persist_offer, persist_refund, MutinyOffer, MutinyRefund,
+    DEFAULT_REFUND_EXPIRY_SECS,
+};
+use crate::paymentfailure::get_payment_failure_details;
+use crate::paymentpolicy::PaymentPolicy;
 use crate::peermanager::{LspMessageRouter, PeerManager};
+use crate::peeroverrides::{get_peer_overrides, is_auto_reconnect_disabled};
+use crate::probe::{ProbeResult, ProbeResults, ProbeTarget, DEFAULT_PROBE_TIMEOUT_SECS};
+use crate::rebalance::{close_circular_path, forwarding_fee_msat, persist_rebalance, Rebalance};
+use crate::routeexplain::RouteExplanation;
+use crate::routehints::channel_route_hint;
+use crate::routingnode::RoutingNodeIdentity;
+use crate::scb::{find_to_remote_output, ScbRecovery, ScbRecoveryStatus};
 use crate::storage::MutinyStorage;
 use crate::utils::get_monitor_version;
+use crate::watchtower::watchtower_key;
+use crate::zeroreserve::{is_zero_reserve_peer, their_reserve_millionths};
 use crate::{
     chain::MutinyChain,
     error::{MutinyError, MutinyStorageError},
-    event::{EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
+    event::{CustomTlv, EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
     fees::MutinyFeeEstimator,
-    gossip::{get_all_peers, read_peer_info, save_peer_connection_info},
+    gossip::{get_all_peers, lookup_node_alias, read_peer_info, save_peer_connection_info},
     keymanager::{
         create_keys_manager, deterministic_uuid_from_keys_manager, pubkey_from_keys_manager,
     },
@@ -24,43 +48,59 @@ use crate::{
 };
 use crate::{fees::P2WSH_OUTPUT_SIZE, peermanager::connect_peer_if_necessary};
 use crate::{keymanager::PhantomKeysManager, scorer::HubPreferentialScorer};
-use crate::{labels::LabelStorage, DEFAULT_PAYMENT_TIMEOUT};
+use crate::{labels::stage_invoice_labels, DEFAULT_PAYMENT_TIMEOUT};
 use crate::{
     ldkstorage::{persist_monitor, ChannelOpenParams},
-    storage::persist_payment_info,
+    storage::{persist_payment_info, persist_payment_info_with, StorageTransaction},
 };
 use crate::{messagehandler::MutinyMessageHandler, storage::read_payment_info};
 use anyhow::{anyhow, Context};
 use bitcoin::bip32::Xpriv;
 use bitcoin::hashes::sha256::Hash as Sha256;
-use bitcoin::{hashes::Hash, secp256k1::PublicKey, FeeRate, Network, OutPoint};
+use bitcoin::{
+    hashes::Hash,
+    secp256k1::{PublicKey, Secp256k1},
+    FeeRate, Network, OutPoint, Txid,
+};
 use core::time::Duration;
 use esplora_client::AsyncClient;
 use futures_util::lock::Mutex;
-use hex_conservative::DisplayHex;
+use hex_conservative::{DisplayHex, FromHex};
+use lightning::chain::transaction::OutPoint as LdkOutPoint;
 use lightning::events::bump_transaction::{BumpTransactionEventHandler, Wallet};
+use lightning::ln::chan_utils::ChannelTransactionParameters;
 use lightning::ln::channel_state::ChannelDetails;
+use lightning::ln::features::ChannelTypeFeatures;
 use lightning::ln::invoice_utils::{
-    create_invoice_from_channelmanager_and_duration_since_epoch, create_phantom_invoice,
+    create_invoice_from_channelmanager_and_duration_since_epoch,
+    create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash,
+    create_invoice_from_channelmanager_with_description_hash_and_duration_since_epoch,
+    create_phantom_invoice, create_phantom_invoice_with_description_hash,
 };
 use lightning::ln::PaymentSecret;
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::onion_message::messenger::OnionMessenger as LdkOnionMessenger;
 use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
-use lightning::sign::{InMemorySigner, NodeSigner, Recipient};
+use lightning::sign::{
+    ChannelSigner, EntropySource, InMemorySigner, NodeSigner, Recipient, SignerProvider,
+    SpendableOutputDescriptor, StaticPaymentOutputDescriptor,
+};
 use lightning::util::config::MaxDustHTLCExposure;
 use lightning::util::ser::Writeable;
 use lightning::{
-    chain::{chainmonitor, Filter, Watch},
+    chain::{chainmonitor, channelmonitor::Balance, Filter, Watch},
     ln::{
-        channelmanager::{PaymentId, PhantomRouteHints, Retry},
+        channelmanager::{PaymentId, PhantomRouteHints, RecentPaymentDetails, Retry},
         peer_handler::{IgnoringMessageHandler, MessageHandler as LdkMessageHandler},
+        types::ChannelId,
         PaymentHash, PaymentPreimage,
     },
     log_debug, log_error, log_info, log_trace, log_warn,
     routing::{
         gossip,
         gossip::NodeId,
-        router::{DefaultRouter, PaymentParameters, RouteParameters},
+        router::{find_route, DefaultRouter, PaymentParameters, Route, RouteHint, RouteParameters},
     },
     util::{
         config::{ChannelHandshakeConfig, ChannelHandshakeLimits, UserConfig},
@@ -68,12 +108,12 @@ use lightning::{
     },
 };
 use lightning::{
-    ln::channelmanager::{RecipientOnionFields, RetryableSendFailure},
+    ln::channelmanager::{ProbeSendFailure, RecipientOnionFields, RetryableSendFailure},
     routing::scoring::ProbabilisticScoringFeeParameters,
     util::config::ChannelConfig,
 };
 use lightning_background_processor::process_events_async;
-use lightning_invoice::Bolt11Invoice;
+use lightning_invoice::{Bolt11Invoice, InvoiceBuilder};
 use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityManager as LDKLSPLiquidityManager};
 
@@ -94,6 +134,10 @@ use web_time::Instant;
 
 const INITIAL_RECONNECTION_DELAY: u64 = 2;
 const MAX_RECONNECTION_DELAY: u64 = 60;
+/// Average time between blocks, used to estimate when timelocks expire
+const AVG_BLOCK_TIME_SECS: u64 = 600;
+/// TLV type keysend messages are sent in
+pub(crate) const KEYSEND_MESSAGE_TLV_TYPE: u64 = 34349334;
 
 pub(crate) type PendingConnections = Arc<Mutex<HashMap<NodeId, u32>>>;
 
@@ -207,10 +251,13 @@ pub struct NodeBuilder<S: MutinyStorage> {
     has_done_initial_sync: Option<Arc<AtomicBool>>,
 
     // optional
+    idle: Option<Arc<AtomicBool>>,
+    hooks: Option<Arc<HookRegistry>>,
     lsp_config: Option<LspConfig>,
     logger: Option<Arc<MutinyLogger>>,
     do_not_connect_peers: bool,
     do_not_bump_channel_close_tx: bool,
+    mpp_strategy: MppStrategy,
 }
 
 impl<S: MutinyStorage> NodeBuilder<S> {
@@ -227,6 +274,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             wallet: None,
             esplora: None,
             has_done_initial_sync: None,
+            idle: None,
+            hooks: None,
             ln_event_callback: None,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr: None,
@@ -235,6 +284,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             network: None,
             do_not_connect_peers: false,
             do_not_bump_channel_close_tx: false,
+            mpp_strategy: MppStrategy::default(),
         }
     }
 
@@ -302,6 +352,18 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         self
     }
 
+    /// Set while the wallet is idle, non-essential peers aren't reconnected then
+    pub fn with_idle_flag(mut self, idle: Arc<AtomicBool>) -> NodeBuilder<S> {
+        self.idle = Some(idle);
+        self
+    }
+
+    /// Embedder hooks notified of the node's channel and payment events
+    pub fn with_hooks(mut self, hooks: Arc<HookRegistry>) -> NodeBuilder<S> {
+        self.hooks = Some(hooks);
+        self
+    }
+
     #[cfg(target_arch = "wasm32")]
     /// Required
     pub fn with_websocket_proxy_addr(&mut self, websocket_proxy_addr: String) {
@@ -328,6 +390,10 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         self.do_not_bump_channel_close_tx = true;
     }
 
+    pub fn with_mpp_strategy(&mut self, mpp_strategy: MppStrategy) {
+        self.mpp_strategy = mpp_strategy;
+    }
+
     pub fn log_params(&self, logger: &Arc<MutinyLogger>) {
         log_debug!(logger, "build parameters:");
         log_debug!(logger, "- uuid: {:?}", self.uuid);
@@ -360,6 +426,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             "- do_not_connect_peers: {}",
             self.do_not_connect_peers
         );
+        log_debug!(logger, "- mpp_strategy: {:?}", self.mpp_strategy);
     }
 
     pub async fn build(self) -> Result<Node<S>, MutinyError> {
@@ -431,11 +498,11 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         };
 
         // init the persister
-        let persister = Arc::new(MutinyNodePersister::new(
-            uuid.clone(),
-            self.storage,
-            logger.clone(),
-        ));
+        let persister = Arc::new(
+            MutinyNodePersister::new(uuid.clone(), self.storage, logger.clone())
+                .with_fee_estimator(fee_estimator.clone())
+                .with_watchtower_key(watchtower_key(self.xprivkey)?),
+        );
 
         // init chain monitor
         let chain_monitor: Arc<ChainMonitor<S>> = Arc::new(ChainMonitor::new(
@@ -463,7 +530,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         let network_graph = gossip_sync.network_graph().clone();
 
         let router: Arc<Router<_>> = Arc::new(DefaultRouter::new(
-            network_graph,
+            network_graph.clone(),
             logger.clone(),
             keys_manager.clone(),
             scorer.clone(),
@@ -555,7 +622,10 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         log_trace!(logger, "finished creating lsp client");
 
         log_trace!(logger, "creating onion routers");
-        let message_router = Arc::new(LspMessageRouter::new(lsp_client_pubkey));
+        let message_router = Arc::new(LspMessageRouter::new(
+            lsp_client_pubkey,
+            keys_manager.get_secure_random_bytes(),
+        ));
         let onion_message_handler = Arc::new(OnionMessenger::new(
             keys_manager.clone(),
             keys_manager.clone(),
@@ -594,6 +664,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             Arc::clone(&keys_manager),
             Arc::clone(&logger),
         ));
+        let anchor_close_events = AnchorCloseEvents::default();
+        let probe_results = ProbeResults::default();
         log_trace!(logger, "finished creating bump tx event handler");
 
         // init event handler
@@ -609,11 +681,14 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             wallet.clone(),
             keys_manager.clone(),
             persister.clone(),
-            bump_tx_event_handler,
+            bump_tx_event_handler.clone(),
+            anchor_close_events.clone(),
+            probe_results.clone(),
             lsp_client.clone(),
             logger.clone(),
             self.do_not_bump_channel_close_tx,
             self.ln_event_callback.clone(),
+            self.hooks.clone(),
         );
         log_trace!(logger, "finished creating event handler");
 
@@ -793,6 +868,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         let background_processor_channel_manager = channel_manager.clone();
         let background_chain_monitor = chain_monitor.clone();
         let background_gossip_sync = gossip_sync.clone();
+        let background_scorer = scorer.clone();
         let background_logger = logger.clone();
         let background_stop = stop.clone();
         stopped_components.try_write()?.push(false);
@@ -812,7 +888,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                     gs,
                     background_processor_peer_manager.clone(),
                     background_processor_logger.clone(),
-                    Some(scorer.clone()),
+                    Some(background_scorer.clone()),
                     |d| {
                         let background_event_stop = background_stop.clone();
                         Box::pin(async move {
@@ -861,6 +937,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             let reconnection_uuid = uuid.clone();
             let reconnection_lsp_client = lsp_client.clone();
             let reconnection_stop = stop.clone();
+            let reconnection_idle = self.idle.clone().unwrap_or_default();
+            let reconnection_channel_manager = channel_manager.clone();
             let reconnection_stopped_comp = stopped_components.clone();
             reconnection_stopped_comp.try_write()?.push(false);
             let pending_connections = pending_connections.clone();
@@ -877,6 +955,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                     reconnection_uuid,
                     reconnection_lsp_client.as_ref(),
                     reconnection_stop,
+                    reconnection_idle,
+                    reconnection_channel_manager,
                     reconnection_stopped_comp,
                     network == Network::Regtest,
                 )
@@ -1021,6 +1101,12 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             sync_lock,
             stop,
             has_done_initial_sync,
+            network_graph,
+            scorer,
+            mpp_strategy: self.mpp_strategy,
+            bump_tx_event_handler,
+            anchor_close_events,
+            probe_results,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
         })
@@ -1046,11 +1132,190 @@ pub(crate) struct Node<S: MutinyStorage> {
     pub(crate) sync_lock: Arc<Mutex<()>>,
     stop: Arc<AtomicBool>,
     has_done_initial_sync: Arc<AtomicBool>,
+    network_graph: Arc<NetworkGraph>,
+    scorer: Arc<utils::Mutex<HubPreferentialScorer>>,
+    mpp_strategy: MppStrategy,
+    bump_tx_event_handler: Arc<BumpTxEventHandler<S>>,
+    anchor_close_events: AnchorCloseEvents,
+    probe_results: ProbeResults,
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: String,
 }
 
 impl<S: MutinyStorage> Node<S> {
+    /// Moves a channel from a static channel backup along. Until the peer has
+    /// force closed, we connect to it so it learns we lost the channel. Once
+    /// its commitment confirms, our output of it is swept to the wallet.
+    pub(crate) async fn recover_scb_channel(
+        &self,
+        mut recovery: ScbRecovery,
+    ) -> Result<ScbRecovery, MutinyError> {
+        if recovery.status != ScbRecoveryStatus::Closing {
+            return Ok(recovery);
+        }
+        let channel = recovery.channel.clone();
+        let (funding_txid, funding_index) = channel
+            .funding_txo
+            .split_once('_')
+            .and_then(|(txid, index)| {
+                Some((Txid::from_str(txid).ok()?, index.parse::<u16>().ok()?))
+            })
+            .ok_or(MutinyError::InvalidArgumentsError)?;
+
+        let closing_txid = self
+            .wallet
+            .blockchain
+            .get_output_status(&funding_txid, funding_index as u64)
+            .await?
+            .filter(|o| o.spent && o.status.as_ref().is_some_and(|s| s.confirmed))
+            .and_then(|o| o.txid);
+        let Some(closing_txid) = closing_txid else {
+            let peer = channel.counterparty_node_id;
+            if self.peer_manager.get_peer_node_ids().contains(&peer) {
+                return Ok(recovery);
+            }
+            // the peer only learns we lost the channel when we reconnect
+            let connection_string = channel.peer_connection_string.clone().or_else(|| {
+                read_peer_info(&self.persister.storage, &NodeId::from_pubkey(&peer))
+                    .ok()
+                    .flatten()
+                    .and_then(|p| p.connection_string)
+            });
+            match connection_string {
+                Some(connection_string) => {
+                    let info = PubkeyConnectionInfo::new(&connection_string)?;
+                    self.connect_peer(info, None).await?;
+                }
+                None => log_warn!(
+                    self.logger,
+                    "No address for peer {peer}, can't ask it to close channel {}",
+                    channel.funding_txo
+                ),
+            }
+            return Ok(recovery);
+        };
+
+        let closing_txid_str = closing_txid.to_string();
+        recovery.updated_at = utils::now().as_secs();
+        let Some(keys_id) = channel.keys_id() else {
+            log_warn!(
+                self.logger,
+                "Channel {} was closed in {closing_txid} but we can't derive its keys",
+                channel.funding_txo
+            );
+            recovery.status = ScbRecoveryStatus::CannotSweep {
+                closing_txid: closing_txid_str,
+            };
+            return Ok(recovery);
+        };
+
+        let closing_tx = self
+            .wallet
+            .blockchain
+            .get_tx(&closing_txid)
+            .await?
+            .ok_or(MutinyError::NotFound)?;
+        let signer = self
+            .keys_manager
+            .derive_channel_signer(channel.channel_value_sats, keys_id);
+        let payment_point = signer.pubkeys().payment_point;
+        let Some((vout, output)) = find_to_remote_output(&closing_tx, &channel, &payment_point)
+        else {
+            recovery.status = ScbRecoveryStatus::NothingToSweep {
+                closing_txid: closing_txid_str,
+            };
+            return Ok(recovery);
+        };
+
+        let mut channel_type_features = ChannelTypeFeatures::only_static_remote_key();
+        if channel.anchors {
+            channel_type_features.set_anchors_zero_fee_htlc_tx_required();
+        }
+        let descriptor =
+            SpendableOutputDescriptor::StaticPaymentOutput(StaticPaymentOutputDescriptor {
+                outpoint: LdkOutPoint {
+                    txid: closing_txid,
+                    index: vout as u16,
+                },
+                output,
+                channel_keys_id: keys_id,
+                channel_value_satoshis: channel.channel_value_sats,
+                channel_transaction_parameters: Some(ChannelTransactionParameters {
+                    holder_pubkeys: signer.pubkeys().clone(),
+                    holder_selected_contest_delay: 0,
+                    is_outbound_from_holder: false,
+                    counterparty_parameters: None,
+                    funding_outpoint: Some(LdkOutPoint {
+                        txid: funding_txid,
+                        index: funding_index,
+                    }),
+                    channel_type_features,
+                }),
+            });
+
+        let sweep_tx = self
+            .keys_manager
+            .spend_spendable_outputs(
+                &[&descriptor],
+                vec![],
+                self.fee_estimator.get_normal_fee_rate(),
+                None,
+                &Secp256k1::new(),
+            )
+            .map_err(|_| MutinyError::Other(anyhow!("Failed to sweep recovered channel")))?;
+        let txid = sweep_tx.compute_txid();
+        self.wallet.broadcast_transaction(sweep_tx).await?;
+        log_info!(
+            self.logger,
+            "Swept channel {} recovered from backup in {txid}",
+            channel.funding_txo
+        );
+
+        recovery.status = ScbRecoveryStatus::Swept {
+            txid: txid.to_string(),
+        };
+        Ok(recovery)
+    }
+
+    /// Whether this node has a force close waiting on its commitment to confirm
+    pub(crate) async fn has_anchor_close(&self, channel_id: &ChannelId) -> bool {
+        self.anchor_close_events
+            .lock()
+            .await
+            .contains_key(channel_id)
+    }
+
+    /// Bumps a force close through its anchor at the feerate, which is kept
+    /// for the bumps LDK does on its own until the commitment confirms
+    pub(crate) async fn bump_force_close(
+        &self,
+        channel_id: &ChannelId,
+        feerate_sat_per_1000_weight: u32,
+    ) -> Result<AnchorBump, MutinyError> {
+        let event = self
+            .anchor_close_events
+            .lock()
+            .await
+            .get(channel_id)
+            .cloned()
+            .ok_or(MutinyError::NotFound)?;
+        set_anchor_bump_feerate(
+            &self.persister.storage,
+            channel_id,
+            feerate_sat_per_1000_weight,
+        )?;
+
+        log_info!(
+            self.logger,
+            "Bumping force close of channel {channel_id} at {feerate_sat_per_1000_weight} sat/kw"
+        );
+        // the child transaction is recorded when the wallet signs it
+        self.bump_tx_event_handler
+            .handle_event(&with_feerate(&event, feerate_sat_per_1000_weight));
+
+        get_anchor_bump(&self.persister.storage, channel_id)?.ok_or(MutinyError::NotFound)
+    }
+
     pub async fn stop(&self) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling stop");
 
@@ -1181,6 +1446,65 @@ impl<S: MutinyStorage> Node<S> {
         log_trace!(self.logger, "finished calling disconnect_peer");
     }
 
+    /// Disconnects the peers we have no channel with, other than the lsp.
+    /// Returns how many were disconnected.
+    pub async fn disconnect_non_essential_peers(&self) -> usize {
+        log_trace!(self.logger, "calling disconnect_non_essential_peers");
+
+        let mut essential: Vec<PublicKey> = self
+            .channel_manager
+            .list_channels()
+            .iter()
+            .map(|c| c.counterparty.node_id)
+            .collect();
+        if let Some(lsp) = self.lsp_client.as_ref() {
+            essential.push(lsp.get_lsp_pubkey().await);
+        }
+
+        let mut disconnected = 0;
+        for peer in self.peer_manager.get_peer_node_ids() {
+            if !essential.contains(&peer) {
+                self.peer_manager.disconnect_by_node_id(peer);
+                disconnected += 1;
+            }
+        }
+        log_trace!(
+            self.logger,
+            "finished calling disconnect_non_essential_peers"
+        );
+
+        disconnected
+    }
+
+    /// Gossips who we are, if we have an announced channel the network would
+    /// take the announcement for. Returns whether it was sent.
+    pub(crate) fn broadcast_node_announcement(
+        &self,
+        identity: &RoutingNodeIdentity,
+    ) -> Result<bool, MutinyError> {
+        let has_announced_channel = self
+            .channel_manager
+            .list_usable_channels()
+            .iter()
+            .any(|c| c.is_announced);
+        if !has_announced_channel {
+            return Ok(false);
+        }
+
+        self.peer_manager.broadcast_node_announcement(
+            identity.rgb()?,
+            identity.alias_bytes()?,
+            identity.socket_addresses()?,
+        );
+        log_debug!(
+            self.logger,
+            "broadcast node announcement as {}",
+            identity.alias
+        );
+
+        Ok(true)
+    }
+
     pub fn get_phantom_route_hint(&self) -> PhantomRouteHints {
         log_trace!(self.logger, "calling get_phantom_route_hint");
         let res = self.channel_manager.get_phantom_route_hints();
@@ -1254,7 +1578,7 @@ impl<S: MutinyStorage> Node<S> {
             * 1000
     }
 
-    fn get_inbound_capacity_msat(&self) -> u64 {
+    pub(crate) fn get_inbound_capacity_msat(&self) -> u64 {
         self.channel_manager
             .list_usable_channels()
             .iter()
@@ -1324,6 +1648,7 @@ impl<S: MutinyStorage> Node<S> {
         route_hints: Option<Vec<PhantomRouteHints>>,
         labels: Vec<String>,
         expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
     ) -> Result<(Bolt11Invoice, u64), MutinyError> {
         log_trace!(self.logger, "calling create_invoice");
 
@@ -1364,8 +1689,10 @@ impl<S: MutinyStorage> Node<S> {
                         Some(amount_sat),
                         None,
                         route_hints,
+                        None,
                         labels,
                         expiry_delta_secs,
+                        privacy,
                     )
                     .await?,
                     0,
@@ -1376,8 +1703,10 @@ impl<S: MutinyStorage> Node<S> {
                     Some(amount_sat),
                     None,
                     route_hints,
+                    None,
                     labels,
                     expiry_delta_secs,
+                    privacy,
                 )
                 .await?,
                 0,
@@ -1389,17 +1718,244 @@ impl<S: MutinyStorage> Node<S> {
         res
     }
 
+    /// The route hints for our channels with these funding outpoints. Every channel
+    /// has to exist and be usable as a hint, so a typo doesn't quietly drop it.
+    pub(crate) fn route_hints_for_channels(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<Vec<RouteHint>, MutinyError> {
+        let channels = self.channel_manager.list_channels();
+        outpoints
+            .iter()
+            .map(|outpoint| {
+                let channel = channels
+                    .iter()
+                    .find(|c| c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(*outpoint))
+                    .ok_or(MutinyError::NotFound)?;
+                channel_route_hint(channel).ok_or_else(|| {
+                    log_error!(
+                        self.logger,
+                        "channel {outpoint} can't be used as a route hint yet"
+                    );
+                    MutinyError::InvalidArgumentsError
+                })
+            })
+            .collect()
+    }
+
+    /// Creates an invoice with exactly the given route hints instead of the ones
+    /// LDK would pick. With no hints it can only be paid over public channels.
+    /// This doesn't open a channel with the LSP, the hints have to lead to us.
+    pub async fn create_invoice_with_route_hints(
+        &self,
+        amount_sat: u64,
+        route_hints: Vec<RouteHint>,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        log_trace!(self.logger, "calling create_invoice_with_route_hints");
+
+        if amount_sat < 1 {
+            return Err(MutinyError::BadAmountError);
+        }
+        let amount_msat = amount_sat * 1_000;
+        let expiry_delta_secs = expiry_delta_secs.unwrap_or(3600);
+
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .create_inbound_payment(Some(amount_msat), expiry_delta_secs, Some(40))
+            .map_err(|_| MutinyError::InvoiceCreationFailed)?;
+
+        let privacy = match privacy {
+            Some(privacy) => privacy,
+            None => get_description_privacy(&self.persister.storage)?,
+        };
+        let builder = InvoiceBuilder::new(self.network.into());
+        let mut builder = match InvoiceDescription::new(privacy, &labels) {
+            InvoiceDescription::Direct(description) => builder.description(description),
+            InvoiceDescription::Hash(hash) => builder.description_hash(hash),
+        }
+        .payment_hash(Sha256::from_byte_array(payment_hash.0))
+        .payment_secret(payment_secret)
+        .duration_since_epoch(utils::now())
+        .expiry_time(Duration::from_secs(expiry_delta_secs.into()))
+        .payee_pub_key(self.pubkey)
+        .basic_mpp()
+        // the same buffer over the payment's cltv delta LDK's invoices use
+        .min_final_cltv_expiry_delta(40 + 3)
+        .amount_milli_satoshis(amount_msat);
+        for hint in route_hints {
+            builder = builder.private_route(hint);
+        }
+
+        let secp = Secp256k1::new();
+        let invoice = builder
+            .try_build_signed(|hash| {
+                let sig =
+                    secp.sign_ecdsa_recoverable(hash, &self.keys_manager.get_node_secret_key());
+
+                // verify that the signature is correct and we produced a valid invoice
+                let pk = secp.recover_ecdsa(hash, &sig)?;
+                if pk != self.pubkey {
+                    return Err(bitcoin::secp256k1::Error::IncorrectSignature);
+                }
+
+                Ok(sig)
+            })
+            .map_err(|e| {
+                log_error!(self.logger, "ERROR: could not generate invoice: {e:?}");
+                MutinyError::InvoiceCreationFailed
+            })?;
+
+        self.save_invoice_payment_info(invoice.clone(), Some(amount_msat), None, labels)
+            .await?;
+
+        log_info!(self.logger, "SUCCESS: generated invoice: {invoice}");
+        log_trace!(
+            self.logger,
+            "finished calling create_invoice_with_route_hints"
+        );
+
+        Ok(invoice)
+    }
+
+    /// Creates a hold invoice for the payment hash. Payments to it are held until
+    /// settled with the preimage or cancelled. The payment has to fit in our
+    /// current inbound liquidity, a held payment can't open a channel with the LSP.
+    pub async fn create_hodl_invoice(
+        &self,
+        payment_hash: PaymentHash,
+        amount_sat: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling create_hodl_invoice");
+
+        if amount_sat < 1 {
+            return Err(MutinyError::BadAmountError);
+        }
+        if get_hodl_invoice(&self.persister.storage, &payment_hash.0)?.is_some()
+            || read_payment_info(&self.persister.storage, &payment_hash.0, true, &self.logger)
+                .is_some()
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let amount_msat = amount_sat
+            .checked_mul(1_000)
+            .ok_or(MutinyError::BadAmountError)?;
+        if self.get_inbound_capacity_msat() < amount_msat {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let bolt11 = self
+            .create_internal_invoice(
+                Some(amount_sat),
+                None,
+                None,
+                Some(payment_hash),
+                labels,
+                expiry_delta_secs,
+                None,
+            )
+            .await?;
+        let mut invoice = HodlInvoice::new(bolt11, amount_sat);
+        persist_hodl_invoice(&self.persister.storage, &mut invoice)?;
+
+        log_trace!(self.logger, "finished calling create_hodl_invoice");
+        Ok(invoice)
+    }
+
+    /// Claims the payment held for a hold invoice with its preimage
+    pub fn settle_hodl_invoice(
+        &self,
+        preimage: PaymentPreimage,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling settle_hodl_invoice");
+
+        let payment_hash = PaymentHash(Sha256::hash(&preimage.0).to_byte_array());
+        let mut invoice = get_hodl_invoice(&self.persister.storage, &payment_hash.0)?
+            .ok_or(MutinyError::NotFound)?;
+        if invoice.status != HodlInvoiceStatus::Held {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        // the payment is recorded as received once LDK tells us it was claimed
+        if let Some(mut payment_info) =
+            read_payment_info(&self.persister.storage, &payment_hash.0, true, &self.logger)
+        {
+            payment_info.preimage = Some(preimage.0);
+            persist_payment_info(
+                &self.persister.storage,
+                &payment_hash.0,
+                &payment_info,
+                true,
+            )?;
+        }
+        self.channel_manager.claim_funds(preimage);
+        invoice.status = HodlInvoiceStatus::Settled;
+        persist_hodl_invoice(&self.persister.storage, &mut invoice)?;
+
+        log_trace!(self.logger, "finished calling settle_hodl_invoice");
+        Ok(invoice)
+    }
+
+    /// Fails back any payment held for the hold invoice, it can't be paid anymore
+    pub fn cancel_hodl_invoice(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling cancel_hodl_invoice");
+
+        let mut invoice = get_hodl_invoice(&self.persister.storage, &payment_hash.0)?
+            .ok_or(MutinyError::NotFound)?;
+        if !matches!(
+            invoice.status,
+            HodlInvoiceStatus::Open | HodlInvoiceStatus::Held
+        ) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        invoice.status = HodlInvoiceStatus::Cancelled;
+        persist_hodl_invoice(&self.persister.storage, &mut invoice)?;
+        // a failed payment info makes us fail any later payment to it too
+        if let Some(mut payment_info) =
+            read_payment_info(&self.persister.storage, &payment_hash.0, true, &self.logger)
+        {
+            payment_info.status = HTLCStatus::Failed;
+            payment_info.last_update = utils::now().as_secs();
+            persist_payment_info(
+                &self.persister.storage,
+                &payment_hash.0,
+                &payment_info,
+                true,
+            )?;
+        }
+        self.channel_manager.fail_htlc_backwards(&payment_hash);
+
+        log_trace!(self.logger, "finished calling cancel_hodl_invoice");
+        Ok(invoice)
+    }
+
+    /// Without a privacy setting for the description the wallet's default is used
+    #[allow(clippy::too_many_arguments)]
     async fn create_internal_invoice(
         &self,
         amount_sat: Option<u64>,
         fee_amount_msat: Option<u64>,
         route_hints: Option<Vec<PhantomRouteHints>>,
+        payment_hash: Option<PaymentHash>,
         labels: Vec<String>,
         expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
     ) -> Result<Bolt11Invoice, MutinyError> {
         let amount_msat = amount_sat.map(|s| s * 1_000);
+        let privacy = match privacy {
+            Some(privacy) => privacy,
+            None => get_description_privacy(&self.persister.storage)?,
+        };
         // Use first element of labels as description
-        let description = labels.first().unwrap_or(&"".to_string()).to_owned();
+        let description = InvoiceDescription::new(privacy, &labels);
 
         // wait for first sync to complete
         for _ in 0..60 {
@@ -1415,8 +1971,22 @@ impl<S: MutinyStorage> Node<S> {
             sleep(1_000).await;
         }
 
-        let invoice_res = match route_hints {
-            None => {
+        let invoice_res = match (route_hints, payment_hash, description) {
+            (None, None, InvoiceDescription::Hash(hash)) => {
+                let now = crate::utils::now();
+                create_invoice_from_channelmanager_with_description_hash_and_duration_since_epoch(
+                    &self.channel_manager.clone(),
+                    self.keys_manager.clone(),
+                    self.logger.clone(),
+                    self.network.into(),
+                    amount_msat,
+                    lightning_invoice::Sha256(hash),
+                    now,
+                    expiry_delta_secs.unwrap_or(3600),
+                    Some(40),
+                )
+            }
+            (None, None, InvoiceDescription::Direct(description)) => {
                 let now = crate::utils::now();
                 create_invoice_from_channelmanager_and_duration_since_epoch(
                     &self.channel_manager.clone(),
@@ -1430,19 +2000,56 @@ impl<S: MutinyStorage> Node<S> {
                     Some(40),
                 )
             }
-            Some(r) => create_phantom_invoice(
-                amount_msat,
-                None,
-                description,
-                expiry_delta_secs.unwrap_or(3600),
-                r,
-                self.keys_manager.clone(),
-                self.keys_manager.clone(),
-                self.logger.clone(),
-                self.network.into(),
-                Some(40),
-                crate::utils::now(),
-            ),
+            (None, Some(payment_hash), description) => {
+                // LDK can't make an invoice for a given payment hash with a description hash
+                let description = match description {
+                    InvoiceDescription::Direct(description) => description,
+                    InvoiceDescription::Hash(_) => GENERIC_INVOICE_DESCRIPTION.to_string(),
+                };
+                let now = crate::utils::now();
+                create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+                    &self.channel_manager.clone(),
+                    self.keys_manager.clone(),
+                    self.logger.clone(),
+                    self.network.into(),
+                    amount_msat,
+                    description,
+                    now,
+                    expiry_delta_secs.unwrap_or(3600),
+                    payment_hash,
+                    Some(40),
+                )
+            }
+            (Some(r), payment_hash, InvoiceDescription::Hash(hash)) => {
+                create_phantom_invoice_with_description_hash(
+                    amount_msat,
+                    payment_hash,
+                    expiry_delta_secs.unwrap_or(3600),
+                    lightning_invoice::Sha256(hash),
+                    r,
+                    self.keys_manager.clone(),
+                    self.keys_manager.clone(),
+                    self.logger.clone(),
+                    self.network.into(),
+                    Some(40),
+                    crate::utils::now(),
+                )
+            }
+            (Some(r), payment_hash, InvoiceDescription::Direct(description)) => {
+                create_phantom_invoice(
+                    amount_msat,
+                    payment_hash,
+                    description,
+                    expiry_delta_secs.unwrap_or(3600),
+                    r,
+                    self.keys_manager.clone(),
+                    self.keys_manager.clone(),
+                    self.logger.clone(),
+                    self.network.into(),
+                    Some(40),
+                    crate::utils::now(),
+                )
+            }
         };
         let invoice = invoice_res.map_err(|e| {
             log_error!(self.logger, "ERROR: could not generate invoice: {e}");
@@ -1473,12 +2080,18 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(amount_msat),
             fee_paid_msat: fee_amount_msat,
             bolt11: Some(invoice.clone()),
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             privacy_level: PrivacyLevel::NotAvailable,
             last_update,
         };
-        persist_payment_info(
+        // the labels are committed together with the payment so we never have one without the other
+        let mut tx = StorageTransaction::new();
+        stage_invoice_labels(&self.persister.storage, &mut tx, invoice, labels)?;
+        persist_payment_info_with(
             &self.persister.storage,
+            tx,
             &payment_hash.0,
             &payment_info,
             true,
@@ -1488,8 +2101,6 @@ impl<S: MutinyStorage> Node<S> {
             MutinyError::InvoiceCreationFailed
         })?;
 
-        self.persister.storage.set_invoice_labels(invoice, labels)?;
-
         Ok(())
     }
 
@@ -1499,7 +2110,10 @@ impl<S: MutinyStorage> Node<S> {
         user_channel_id: u128,
     ) -> Result<Option<ChannelClosure>, MutinyError> {
         log_trace!(self.logger, "calling get_channel_closure");
-        let res = self.persister.get_channel_closure(user_channel_id);
+        let res = self
+            .persister
+            .get_channel_closure(user_channel_id)
+            .map(|c| c.map(|c| self.enrich_channel_closure(c)));
         log_trace!(self.logger, "finished calling get_channel_closure");
 
         res
@@ -1508,25 +2122,130 @@ impl<S: MutinyStorage> Node<S> {
     /// Gets all the closed channels for this node
     pub fn get_channel_closures(&self) -> Result<Vec<ChannelClosure>, MutinyError> {
         log_trace!(self.logger, "calling get_channel_closures");
-        let res = self.persister.list_channel_closures();
+        let res = self.persister.list_channel_closures().map(|closures| {
+            closures
+                .into_iter()
+                .map(|c| self.enrich_channel_closure(c))
+                .collect()
+        });
         log_trace!(self.logger, "finished calling get_channel_closures");
 
         res
     }
 
+    /// Fills in the closing txid and sweep progress of a closed channel from
+    /// its channel monitor. Once the monitor has been archived there is
+    /// nothing left to claim so the channel is reported as fully swept.
+    fn enrich_channel_closure(&self, mut closure: ChannelClosure) -> ChannelClosure {
+        if let Some(channel_id) = closure.channel_id {
+            match self.persister.get_channel_swept_sats(&channel_id) {
+                Ok(swept) => closure.swept_amount_sats = swept,
+                Err(e) => log_warn!(self.logger, "Failed to get swept amount: {e}"),
+            }
+        }
+
+        let monitor = closure.channel_funding_txo.and_then(|txo| {
+            let funding_txo = lightning::chain::transaction::OutPoint {
+                txid: txo.txid,
+                index: txo.vout as u16,
+            };
+            self.chain_monitor.get_monitor(funding_txo).ok()
+        });
+        let Some(monitor) = monitor else {
+            closure.pending_sweep_sats = 0;
+            closure.fully_swept = true;
+            closure.estimated_secs_remaining = None;
+            return closure;
+        };
+
+        let funding_txid = monitor.get_funding_txo().0.txid;
+        closure.closing_txid = monitor
+            .get_relevant_txids()
+            .into_iter()
+            .filter(|(txid, _, _)| *txid != funding_txid)
+            .min_by_key(|(_, height, _)| *height)
+            .map(|(txid, _, _)| txid);
+
+        let balances = monitor.get_claimable_balances();
+        closure.pending_sweep_sats = balances.iter().map(|b| b.claimable_amount_satoshis()).sum();
+
+        let current_height = self.channel_manager.current_best_block().height;
+        let maturity_height = balances
+            .iter()
+            .filter_map(|b| match b {
+                Balance::ClaimableAwaitingConfirmations {
+                    confirmation_height,
+                    ..
+                } => Some(*confirmation_height),
+                Balance::ContentiousClaimable { timeout_height, .. } => Some(*timeout_height),
+                Balance::MaybeTimeoutClaimableHTLC {
+                    claimable_height, ..
+                } => Some(*claimable_height),
+                Balance::MaybePreimageClaimableHTLC { expiry_height, .. } => Some(*expiry_height),
+                _ => None,
+            })
+            .max();
+        closure.estimated_secs_remaining = maturity_height
+            .map(|height| height.saturating_sub(current_height) as u64 * AVG_BLOCK_TIME_SECS);
+        closure.fully_swept = closure.closing_txid.is_some() && balances.is_empty();
+
+        closure
+    }
+
     fn retry_strategy() -> Retry {
         Retry::Attempts(15)
     }
 
-    /// init_invoice_payment sends off the payment but does not wait for results
-    /// use pay_invoice_with_timeout to wait for results
+    /// The router limits for sending the amount, sized from our usable channels
+    /// and what the scorer knows about the payee's channels when given one,
+    /// then capped by the payment's MPP config and the wallet's default
+    fn mpp_params(&self, amount_msats: u64, payee: Option<PublicKey>, mpp: MppConfig) -> MppParams {
+        let outbound: Vec<u64> = self
+            .channel_manager
+            .list_usable_channels()
+            .iter()
+            .map(|c| c.next_outbound_htlc_limit_msat)
+            .collect();
+
+        let graph = self.network_graph.read_only();
+        let payee = payee.map(|p| NodeId::from_pubkey(&p));
+        let inbound: Vec<u64> = match (payee.and_then(|p| graph.node(&p)), self.scorer.lock()) {
+            (Some(node), Ok(scorer)) => node
+                .channels
+                .iter()
+                .filter_map(|scid| {
+                    scorer
+                        .estimated_channel_liquidity_range(*scid, payee.as_ref()?)
+                        .map(|(_, max)| max)
+                        // nothing learned about it yet, so it could be all of it
+                        .or_else(|| {
+                            let capacity = graph.channel(*scid)?.capacity_sats?;
+                            Some(capacity * 1_000)
+                        })
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        let plan = plan_mpp(self.mpp_strategy, amount_msats, &outbound, &inbound);
+        let config = mpp.or(get_mpp_config(&self.persister.storage).unwrap_or_default());
+        apply_mpp_config(plan, &config, amount_msats)
+    }
+
+    /// init_invoice_payment sends off the payment but does not wait for results
+    /// use pay_invoice_with_timeout to wait for results
     pub async fn init_invoice_payment(
         &self,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
+        mpp: MppConfig,
+        policy: PaymentPolicy,
     ) -> Result<(PaymentId, PaymentHash), MutinyError> {
         log_trace!(self.logger, "calling init_invoice_payment");
 
+        mpp.validate()?;
+        policy.validate()?;
+
         let payment_hash = invoice.payment_hash().to_byte_array();
 
         if read_payment_info(&self.persister.storage, &payment_hash, false, &self.logger)
@@ -1592,7 +2311,7 @@ impl<S: MutinyStorage> Node<S> {
             }
             let amount_msats = amt_sats.unwrap() * 1_000;
             (
-                self.pay_invoice_internal(invoice, amount_msats),
+                self.pay_invoice_internal(invoice, amount_msats, mpp, policy),
                 amount_msats,
             )
         } else {
@@ -1601,7 +2320,7 @@ impl<S: MutinyStorage> Node<S> {
             }
             let amount_msats = invoice.amount_milli_satoshis().unwrap();
             (
-                self.pay_invoice_internal(invoice, amount_msats),
+                self.pay_invoice_internal(invoice, amount_msats, mpp, policy),
                 amount_msats,
             )
         };
@@ -1614,6 +2333,8 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(Some(amt_msat)),
             fee_paid_msat: None,
             bolt11: Some(invoice.clone()),
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             privacy_level: PrivacyLevel::NotAvailable,
             last_update,
@@ -1661,6 +2382,8 @@ impl<S: MutinyStorage> Node<S> {
         &self,
         invoice: &Bolt11Invoice,
         amount_msats: u64,
+        mpp: MppConfig,
+        policy: PaymentPolicy,
     ) -> Result<PaymentId, RetryableSendFailure> {
         let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
         let payment_hash = PaymentHash((*invoice.payment_hash()).to_byte_array());
@@ -1678,10 +2401,21 @@ impl<S: MutinyStorage> Node<S> {
                 .with_bolt11_features(features.clone())
                 .unwrap();
         }
+        // route hints lead to private channels the scorer can't know about,
+        // so only size for the payee's channels when it's reached through public ones
+        let payee = invoice
+            .route_hints()
+            .is_empty()
+            .then(|| invoice.recover_payee_pub_key());
+        let mpp = self.mpp_params(amount_msats, payee, mpp);
+        payment_params.max_path_count = mpp.max_path_count;
+        payment_params.max_channel_saturation_power_of_half =
+            mpp.max_channel_saturation_power_of_half;
         let route_params = RouteParameters {
             payment_params,
             final_value_msat: amount_msats,
-            max_total_routing_fee_msat: None, // main change from LDK, we just want payment to succeed
+            // main change from LDK, unless capped we just want payment to succeed
+            max_total_routing_fee_msat: policy.max_fee_sats.map(|sats| sats.saturating_mul(1_000)),
         };
 
         self.channel_manager
@@ -1691,7 +2425,7 @@ impl<S: MutinyStorage> Node<S> {
                 recipient_onion,
                 payment_id,
                 route_params,
-                Self::retry_strategy(),
+                Retry::Attempts(policy.retry_attempts(mpp.retry_attempts)),
             )
             .map(|_| payment_id)
     }
@@ -1701,6 +2435,7 @@ impl<S: MutinyStorage> Node<S> {
         payment_id: PaymentId,
         payment_hash: PaymentHash,
         timeout: u64,
+        abandon_on_permanent_failure: bool,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         let start = utils::now().as_secs();
@@ -1713,6 +2448,19 @@ impl<S: MutinyStorage> Node<S> {
                 return Err(MutinyError::PaymentTimeout);
             }
 
+            // failed attempts from an earlier try at the same invoice don't count
+            if abandon_on_permanent_failure
+                && get_payment_failure_details(&self.persister.storage, &payment_hash.0)?
+                    .is_some_and(|d| {
+                        d.attempts
+                            .iter()
+                            .any(|a| a.permanent && a.timestamp >= start)
+                    })
+            {
+                self.channel_manager.abandon_payment(payment_id);
+                return Err(MutinyError::RoutingFailed);
+            }
+
             let payment_info = read_payment_info(
                 &self.persister.storage,
                 &payment_hash.0,
@@ -1740,17 +2488,26 @@ impl<S: MutinyStorage> Node<S> {
         &self,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
-        timeout_secs: Option<u64>,
+        mpp: MppConfig,
+        policy: PaymentPolicy,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         log_trace!(self.logger, "calling pay_invoice_with_timeout");
 
         // initiate payment
-        let (payment_id, payment_hash) = self.init_invoice_payment(invoice, amt_sats).await?;
-        let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
+        let (payment_id, payment_hash) = self
+            .init_invoice_payment(invoice, amt_sats, mpp, policy)
+            .await?;
+        let timeout: u64 = policy.timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
 
         let res = self
-            .await_payment(payment_id, payment_hash, timeout, labels)
+            .await_payment(
+                payment_id,
+                payment_hash,
+                timeout,
+                policy.abandon_on_permanent_failure,
+                labels,
+            )
             .await;
         log_trace!(self.logger, "finished calling pay_invoice_with_timeout");
 
@@ -1764,6 +2521,7 @@ impl<S: MutinyStorage> Node<S> {
         to_node: PublicKey,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
         labels: Vec<String>,
         payment_id: PaymentId,
     ) -> Result<MutinyInvoice, MutinyError> {
@@ -1771,6 +2529,19 @@ impl<S: MutinyStorage> Node<S> {
 
         let amt_msats = amt_sats * 1_000;
 
+        let mut tlvs = custom_tlvs
+            .iter()
+            .map(|tlv| {
+                let value = Vec::<u8>::from_hex(&tlv.value)
+                    .map_err(|_| MutinyError::InvalidArgumentsError)?;
+                Ok((tlv.tlv_type, value))
+            })
+            .collect::<Result<Vec<_>, MutinyError>>()?;
+        if let Some(msg) = message {
+            // keysend messages are encoded as TLV type 34349334
+            tlvs.push((KEYSEND_MESSAGE_TLV_TYPE, msg.encode()));
+        }
+
         // check if we have enough balance to send
         let channels = self.channel_manager.list_channels();
         if self
@@ -1824,17 +2595,25 @@ impl<S: MutinyStorage> Node<S> {
             max_total_routing_fee_msat: None,
         };
 
-        let recipient_onion = if let Some(msg) = message {
-            // keysend messages are encoded as TLV type 34349334
+        let recipient_onion = if tlvs.is_empty() {
+            RecipientOnionFields::spontaneous_empty()
+        } else {
+            // custom records need a type of at least 2^16 and no duplicates
             RecipientOnionFields::secret_only(payment_secret)
-                .with_custom_tlvs(vec![(34349334, msg.encode())])
+                .with_custom_tlvs(tlvs)
                 .map_err(|_| {
-                    log_error!(self.logger, "could not encode keysend message");
-                    MutinyError::InvoiceCreationFailed
+                    log_error!(self.logger, "could not encode keysend custom records");
+                    MutinyError::InvalidArgumentsError
                 })?
-        } else {
-            RecipientOnionFields::spontaneous_empty()
         };
+        let custom_tlvs = recipient_onion
+            .custom_tlvs()
+            .iter()
+            .map(|(tlv_type, value)| CustomTlv {
+                tlv_type: *tlv_type,
+                value: value.to_lower_hex_string(),
+            })
+            .collect();
 
         let pay_result = self.channel_manager.send_spontaneous_payment_with_retry(
             Some(preimage),
@@ -1854,6 +2633,8 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(Some(amt_msats)),
             fee_paid_msat: None,
             bolt11: None,
+            offer: None,
+            custom_tlvs,
             payee_pubkey: Some(to_node),
             privacy_level: PrivacyLevel::NotAvailable,
             last_update,
@@ -1906,6 +2687,7 @@ impl<S: MutinyStorage> Node<S> {
         to_node: PublicKey,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
         labels: Vec<String>,
         timeout_secs: Option<u64>,
     ) -> Result<MutinyInvoice, MutinyError> {
@@ -1917,20 +2699,611 @@ impl<S: MutinyStorage> Node<S> {
 
         // initiate payment
         let pay = self
-            .init_keysend_payment(to_node, amt_sats, message, labels.clone(), payment_id)
+            .init_keysend_payment(
+                to_node,
+                amt_sats,
+                message,
+                custom_tlvs,
+                labels.clone(),
+                payment_id,
+            )
             .await?;
 
         let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
         let payment_hash = PaymentHash(pay.payment_hash.to_byte_array());
 
         let res = self
-            .await_payment(payment_id, payment_hash, timeout, labels)
+            .await_payment(payment_id, payment_hash, timeout, false, labels)
             .await;
         log_trace!(self.logger, "finished calling keysend_with_timeout");
 
         res
     }
 
+    /// The route parameters a payment of the invoice would use, with the
+    /// same split. The amount is only used when the invoice has none.
+    fn invoice_route_params(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<RouteParameters, MutinyError> {
+        let amount_msats = match (invoice.amount_milli_satoshis(), amt_sats) {
+            (Some(0), _) | (None, None) | (None, Some(0)) => {
+                return Err(MutinyError::BadAmountError)
+            }
+            (Some(amt), _) => amt,
+            (None, Some(amt)) => amt * 1_000,
+        };
+        let mut payment_params = PaymentParameters::from_node_id(
+            invoice.recover_payee_pub_key(),
+            invoice.min_final_cltv_expiry_delta() as u32,
+        )
+        .with_route_hints(invoice.route_hints())
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        if let Some(features) = invoice.features() {
+            payment_params = payment_params
+                .with_bolt11_features(features.clone())
+                .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        }
+        let payee = invoice
+            .route_hints()
+            .is_empty()
+            .then(|| invoice.recover_payee_pub_key());
+        let mpp = self.mpp_params(amount_msats, payee, MppConfig::default());
+        payment_params.max_path_count = mpp.max_path_count;
+        payment_params.max_channel_saturation_power_of_half =
+            mpp.max_channel_saturation_power_of_half;
+
+        Ok(RouteParameters::from_payment_params_and_value(
+            payment_params,
+            amount_msats,
+        ))
+    }
+
+    /// Runs the router for the invoice without paying it and explains the
+    /// route it found hop by hop, or why it couldn't find one.
+    pub fn explain_route(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<RouteExplanation, MutinyError> {
+        log_trace!(self.logger, "calling explain_route");
+
+        let route_params = self.invoice_route_params(invoice, amt_sats)?;
+        let channels = self.channel_manager.list_usable_channels();
+        let first_hops: Vec<&ChannelDetails> = channels.iter().collect();
+        let route = {
+            let scorer = self.scorer.lock().map_err(|_| MutinyError::RoutingFailed)?;
+            find_route(
+                &self.pubkey,
+                &route_params,
+                &self.network_graph.read_only(),
+                Some(&first_hops),
+                self.logger.clone(),
+                &*scorer,
+                &scoring_params(),
+                &self.keys_manager.get_secure_random_bytes(),
+            )
+        };
+
+        let res = match route {
+            Ok(route) => {
+                let our_scids: Vec<u64> = channels
+                    .iter()
+                    .filter_map(|c| c.get_outbound_payment_scid())
+                    .collect();
+                let capacity_sats = |scid: u64| {
+                    channels
+                        .iter()
+                        .find(|c| c.get_outbound_payment_scid() == Some(scid))
+                        .map(|c| c.channel_value_satoshis)
+                        .or_else(|| {
+                            self.network_graph
+                                .read_only()
+                                .channel(scid)
+                                .and_then(|c| c.capacity_sats)
+                        })
+                };
+                let alias = |pubkey: &PublicKey| {
+                    lookup_node_alias(
+                        &self.persister.storage,
+                        &self.network_graph,
+                        &NodeId::from_pubkey(pubkey),
+                    )
+                    .ok()
+                    .flatten()
+                };
+                RouteExplanation::from_route(&route, &our_scids, capacity_sats, alias)
+            }
+            Err(e) => {
+                log_debug!(self.logger, "no route found to explain: {}", e.err);
+                let outbound_msat = channels
+                    .iter()
+                    .map(|c| c.next_outbound_htlc_limit_msat)
+                    .sum();
+                RouteExplanation::no_route(route_params.final_value_msat, e.err, outbound_msat)
+            }
+        };
+        log_trace!(self.logger, "finished calling explain_route");
+
+        Ok(res)
+    }
+
+    /// Probes the routes a payment would take, without sending any funds,
+    /// to see whether it would get through and what it would cost.
+    /// Waits for the probes up to the timeout, late ones count as failed.
+    pub async fn probe_payment(
+        &self,
+        target: &ProbeTarget,
+        amt_sats: Option<u64>,
+        timeout_secs: Option<u64>,
+    ) -> Result<ProbeResult, MutinyError> {
+        log_trace!(self.logger, "calling probe_payment");
+
+        let sent = match target {
+            ProbeTarget::Invoice(invoice) => {
+                let route_params = self.invoice_route_params(invoice, amt_sats)?;
+                self.channel_manager
+                    .send_preflight_probes(route_params, None)
+            }
+            ProbeTarget::Node(node_id) => {
+                let amt_sats = amt_sats
+                    .filter(|a| *a > 0)
+                    .ok_or(MutinyError::BadAmountError)?;
+                self.channel_manager.send_spontaneous_preflight_probes(
+                    *node_id,
+                    amt_sats * 1_000,
+                    40,
+                    None,
+                )
+            }
+        };
+
+        let probe_ids: Vec<PaymentId> = match sent {
+            Ok(probes) => probes.into_iter().map(|(_, id)| id).collect(),
+            Err(ProbeSendFailure::RouteNotFound) => {
+                log_debug!(self.logger, "no route found to probe");
+                return Ok(ProbeResult::unreachable());
+            }
+            Err(e) => {
+                log_error!(self.logger, "could not send probes: {e:?}");
+                return Err(MutinyError::RoutingFailed);
+            }
+        };
+
+        let timeout = timeout_secs.unwrap_or(DEFAULT_PROBE_TIMEOUT_SECS);
+        let start = utils::now().as_secs();
+        let outcomes = loop {
+            let done = {
+                let results = self.probe_results.lock().await;
+                probe_ids.iter().all(|id| results.contains_key(id))
+            };
+            if done || utils::now().as_secs() - start > timeout {
+                let mut results = self.probe_results.lock().await;
+                break probe_ids
+                    .iter()
+                    .filter_map(|id| results.remove(id))
+                    .collect::<Vec<_>>();
+            }
+
+            sleep(250).await;
+        };
+
+        let res = ProbeResult::from_outcomes(probe_ids.len(), &outcomes);
+        log_trace!(self.logger, "finished calling probe_payment");
+
+        Ok(res)
+    }
+
+    /// Creates a BOLT12 offer that can be paid any number of times.
+    /// Without an amount the payer picks how much to send.
+    pub fn create_offer(
+        &self,
+        amount_sats: Option<u64>,
+        description: Option<String>,
+    ) -> Result<MutinyOffer, MutinyError> {
+        log_trace!(self.logger, "calling create_offer");
+
+        if amount_sats == Some(0) {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let mut builder = self
+            .channel_manager
+            .create_offer_builder(None)
+            .map_err(|e| {
+                log_error!(self.logger, "could not create offer builder: {e:?}");
+                MutinyError::InvoiceCreationFailed
+            })?;
+        if let Some(amount_sats) = amount_sats {
+            builder = builder.amount_msats(amount_sats * 1_000);
+        }
+        if let Some(description) = description {
+            builder = builder.description(description);
+        }
+        let offer = builder.build().map_err(|e| {
+            log_error!(self.logger, "could not build offer: {e:?}");
+            MutinyError::InvoiceCreationFailed
+        })?;
+
+        let offer = MutinyOffer::new(&offer)?;
+        persist_offer(&self.persister.storage, &offer)?;
+        log_trace!(self.logger, "finished calling create_offer");
+
+        Ok(offer)
+    }
+
+    /// Creates a BOLT12 refund that pays the amount to whoever claims it first,
+    /// before it expires. LDK pays the invoice the recipient sends for it.
+    pub fn create_refund(
+        &self,
+        amount_sats: u64,
+        description: Option<String>,
+        expiry_secs: Option<u64>,
+    ) -> Result<MutinyRefund, MutinyError> {
+        log_trace!(self.logger, "calling create_refund");
+
+        if amount_sats == 0 {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let mut entropy = [0u8; 32];
+        getrandom::getrandom(&mut entropy).map_err(|_| MutinyError::SeedGenerationFailed)?;
+        let payment_id = PaymentId(entropy);
+
+        let expires_at = utils::now().as_secs() + expiry_secs.unwrap_or(DEFAULT_REFUND_EXPIRY_SECS);
+        let mut builder = self
+            .channel_manager
+            .create_refund_builder(
+                amount_sats * 1_000,
+                Duration::from_secs(expires_at),
+                payment_id,
+                Self::retry_strategy(),
+                None,
+            )
+            .map_err(|e| {
+                log_error!(self.logger, "could not create refund builder: {e:?}");
+                MutinyError::InvoiceCreationFailed
+            })?;
+        if let Some(description) = description {
+            builder = builder.description(description);
+        }
+        let refund = builder.build().map_err(|e| {
+            log_error!(self.logger, "could not build refund: {e:?}");
+            MutinyError::InvoiceCreationFailed
+        })?;
+
+        let refund = MutinyRefund::new(&refund, payment_id, expires_at);
+        persist_refund(&self.persister.storage, &refund)?;
+        log_trace!(self.logger, "finished calling create_refund");
+
+        Ok(refund)
+    }
+
+    /// Claims a BOLT12 refund by sending its payer an invoice for it.
+    /// The payment shows up like any other once the payer pays.
+    pub fn claim_refund(&self, refund: &Refund) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling claim_refund");
+
+        let invoice = self
+            .channel_manager
+            .request_refund_payment(refund)
+            .map_err(|e| {
+                log_error!(self.logger, "could not request refund payment: {e:?}");
+                MutinyError::InvoiceCreationFailed
+            })?;
+
+        let payment_hash = invoice.payment_hash();
+        let mut payment_info =
+            read_payment_info(&self.persister.storage, &payment_hash.0, true, &self.logger)
+                .unwrap_or(PaymentInfo {
+                    preimage: None,
+                    secret: None,
+                    status: HTLCStatus::Pending,
+                    amt_msat: MillisatAmount(Some(invoice.amount_msats())),
+                    fee_paid_msat: None,
+                    bolt11: None,
+                    offer: None,
+                    custom_tlvs: vec![],
+                    payee_pubkey: Some(self.pubkey),
+                    privacy_level: PrivacyLevel::NotAvailable,
+                    last_update: utils::now().as_secs(),
+                });
+        payment_info.offer = Some(refund.to_string());
+        persist_payment_info(
+            &self.persister.storage,
+            &payment_hash.0,
+            &payment_info,
+            true,
+        )?;
+        log_trace!(self.logger, "finished calling claim_refund");
+
+        MutinyInvoice::from(payment_info, payment_hash, true, vec![])
+    }
+
+    /// Pays a BOLT12 offer, the amount is needed when the offer doesn't have one.
+    /// LDK requests an invoice from the offer's node and pays it once it arrives.
+    pub async fn pay_offer_with_timeout(
+        &self,
+        offer: &Offer,
+        amount_sats: Option<u64>,
+        timeout_secs: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling pay_offer_with_timeout");
+
+        let amount_msats = offer_payment_msats(offer, amount_sats)?;
+
+        let mut entropy = [0u8; 32];
+        getrandom::getrandom(&mut entropy).map_err(|_| MutinyError::SeedGenerationFailed)?;
+        let payment_id = PaymentId(entropy);
+
+        self.channel_manager
+            .pay_for_offer(
+                offer,
+                None,
+                amount_msats,
+                None,
+                payment_id,
+                Self::retry_strategy(),
+                None,
+            )
+            .map_err(|e| {
+                log_error!(self.logger, "could not pay offer: {e:?}");
+                MutinyError::InvoiceInvalid
+            })?;
+
+        let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
+        let start = utils::now().as_secs();
+        let payment_hash = self
+            .await_offer_invoice(offer, payment_id, start + timeout)
+            .await?;
+
+        let elapsed = utils::now().as_secs() - start;
+        let res = self
+            .await_payment(
+                payment_id,
+                payment_hash,
+                timeout.saturating_sub(elapsed),
+                false,
+                labels,
+            )
+            .await;
+        log_trace!(self.logger, "finished calling pay_offer_with_timeout");
+
+        res
+    }
+
+    /// Waits for the invoice of an offer we're paying, we only learn the
+    /// payment hash once it arrives. Records the payment under it.
+    async fn await_offer_invoice(
+        &self,
+        offer: &Offer,
+        payment_id: PaymentId,
+        deadline: u64,
+    ) -> Result<PaymentHash, MutinyError> {
+        loop {
+            if utils::now().as_secs() > deadline {
+                self.channel_manager.abandon_payment(payment_id);
+                return Err(MutinyError::PaymentTimeout);
+            }
+
+            let recent = self
+                .channel_manager
+                .list_recent_payments()
+                .into_iter()
+                .find(|p| match p {
+                    RecentPaymentDetails::AwaitingInvoice { payment_id: id }
+                    | RecentPaymentDetails::Pending { payment_id: id, .. }
+                    | RecentPaymentDetails::Fulfilled { payment_id: id, .. }
+                    | RecentPaymentDetails::Abandoned { payment_id: id, .. } => *id == payment_id,
+                });
+
+            let (payment_hash, amount_msats, status) = match recent {
+                Some(RecentPaymentDetails::AwaitingInvoice { .. }) => {
+                    sleep(250).await;
+                    continue;
+                }
+                Some(RecentPaymentDetails::Pending {
+                    payment_hash,
+                    total_msat,
+                    ..
+                }) => (payment_hash, Some(total_msat), HTLCStatus::InFlight),
+                Some(RecentPaymentDetails::Fulfilled {
+                    payment_hash: Some(payment_hash),
+                    ..
+                }) => (payment_hash, None, HTLCStatus::Succeeded),
+                // the invoice never came or couldn't be paid
+                _ => return Err(MutinyError::RoutingFailed),
+            };
+
+            // the payment may have already completed and been recorded
+            let mut payment_info = read_payment_info(
+                &self.persister.storage,
+                &payment_hash.0,
+                false,
+                &self.logger,
+            )
+            .unwrap_or(PaymentInfo {
+                preimage: None,
+                secret: None,
+                status,
+                amt_msat: MillisatAmount(amount_msats),
+                fee_paid_msat: None,
+                bolt11: None,
+                offer: None,
+                custom_tlvs: vec![],
+                payee_pubkey: offer.signing_pubkey(),
+                privacy_level: PrivacyLevel::NotAvailable,
+                last_update: utils::now().as_secs(),
+            });
+            payment_info.offer = Some(offer.to_string());
+            persist_payment_info(
+                &self.persister.storage,
+                &payment_hash.0,
+                &payment_info,
+                false,
+            )?;
+
+            return Ok(payment_hash);
+        }
+    }
+
+    /// Moves `amount_sats` from one of our channels to another by paying
+    /// ourselves out over `from` and back in over `to`
+    pub async fn rebalance(
+        &self,
+        from: OutPoint,
+        to: OutPoint,
+        amount_sats: u64,
+        timeout_secs: Option<u64>,
+    ) -> Result<Rebalance, MutinyError> {
+        log_trace!(self.logger, "calling rebalance");
+
+        if from == to || amount_sats == 0 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let channels = self.channel_manager.list_usable_channels();
+        let find = |outpoint: OutPoint| {
+            channels
+                .iter()
+                .find(|c| c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(outpoint))
+                .ok_or(MutinyError::NotFound)
+        };
+        let from_channel = find(from)?;
+        let to_channel = find(to)?;
+
+        let amount_msat = amount_sats * 1_000;
+        if from_channel.next_outbound_htlc_limit_msat < amount_msat
+            || to_channel.inbound_capacity_msat < amount_msat
+        {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        // the last hop is the peer forwarding to us, so we need their fees for it
+        let peer = to_channel.counterparty.node_id;
+        let forwarding = to_channel
+            .counterparty
+            .forwarding_info
+            .clone()
+            .ok_or(MutinyError::RoutingFailed)?;
+        let to_scid = to_channel
+            .get_inbound_payment_scid()
+            .ok_or(MutinyError::RoutingFailed)?;
+
+        let mut payment_params =
+            PaymentParameters::from_node_id(peer, forwarding.cltv_expiry_delta as u32);
+        payment_params.max_path_count = 1;
+        let route_params = RouteParameters {
+            payment_params,
+            final_value_msat: amount_msat + forwarding_fee_msat(&forwarding, amount_msat),
+            max_total_routing_fee_msat: None,
+        };
+        let route = {
+            let scorer = self.scorer.lock().map_err(|_| MutinyError::RoutingFailed)?;
+            find_route(
+                &self.pubkey,
+                &route_params,
+                &self.network_graph.read_only(),
+                Some(&[from_channel]),
+                self.logger.clone(),
+                &*scorer,
+                &scoring_params(),
+                &self.keys_manager.get_secure_random_bytes(),
+            )
+            .map_err(|e| {
+                log_error!(self.logger, "could not find rebalance route: {}", e.err);
+                MutinyError::RoutingFailed
+            })?
+        };
+        let path = route
+            .paths
+            .into_iter()
+            .next()
+            .ok_or(MutinyError::RoutingFailed)?;
+        let path = close_circular_path(
+            path,
+            self.pubkey,
+            self.channel_manager.node_features(),
+            to_scid,
+            &forwarding,
+            amount_msat,
+        )?;
+
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .create_inbound_payment(Some(amount_msat), 3600, None)
+            .map_err(|_| MutinyError::InvoiceCreationFailed)?;
+        let payment_id = PaymentId(payment_hash.0);
+
+        let now = utils::now().as_secs();
+        let mut payment_info = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(amount_msat)),
+            fee_paid_msat: None,
+            bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
+            payee_pubkey: Some(self.pubkey),
+            privacy_level: PrivacyLevel::NotAvailable,
+            last_update: now,
+        };
+        persist_payment_info(
+            &self.persister.storage,
+            &payment_hash.0,
+            &payment_info,
+            false,
+        )?;
+        let rebalance = Rebalance {
+            payment_hash: Sha256::from_byte_array(payment_hash.0),
+            from_channel: from,
+            to_channel: to,
+            amount_sats,
+            status: HTLCStatus::InFlight,
+            fee_paid_msat: None,
+            created_at: now,
+            last_updated: now,
+        };
+        persist_rebalance(&self.persister.storage, &rebalance)?;
+
+        let route = Route {
+            paths: vec![path],
+            route_params: None,
+        };
+        if let Err(e) = self.channel_manager.send_payment_with_route(
+            route,
+            payment_hash,
+            RecipientOnionFields::secret_only(payment_secret),
+            payment_id,
+        ) {
+            log_error!(self.logger, "failed to send rebalance: {e:?}");
+            payment_info.status = HTLCStatus::Failed;
+            persist_payment_info(
+                &self.persister.storage,
+                &payment_hash.0,
+                &payment_info,
+                false,
+            )?;
+            return Err(MutinyError::RoutingFailed);
+        }
+
+        let timeout = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
+        let payment = self
+            .await_payment(payment_id, payment_hash, timeout, false, vec![])
+            .await?;
+        log_info!(
+            self.logger,
+            "Rebalanced {amount_sats} sats from {from} to {to} for {:?} msats",
+            payment.fee_paid_msat
+        );
+
+        log_trace!(self.logger, "finished calling rebalance");
+        Ok(rebalance.with_payment(&payment))
+    }
+
     async fn await_chan_funding_tx(
         &self,
         user_channel_id: u128,
@@ -1998,20 +3371,74 @@ impl<S: MutinyStorage> Node<S> {
         }
     }
 
+    /// Starts opening a channel. If any utxos are given the funding transaction
+    /// spends only those, otherwise the coins are picked by the wallet.
+    /// `push_msat` is given to the peer when the channel opens.
+    /// Config for a channel we open, trusted peers don't have to keep a reserve
+    async fn channel_config_for_peer(&self, pubkey: &PublicKey) -> UserConfig {
+        let accept_underpaying_htlcs = self
+            .lsp_client
+            .as_ref()
+            .is_some_and(|l| l.accept_underpaying_htlcs());
+        let is_lsp = match self.lsp_client.as_ref() {
+            Some(lsp) => lsp.get_lsp_pubkey().await == *pubkey,
+            None => false,
+        };
+        let zero_reserve = is_lsp || is_zero_reserve_peer(&self.persister.storage, pubkey);
+
+        let mut config = default_user_config(accept_underpaying_htlcs);
+        config
+            .channel_handshake_config
+            .their_channel_reserve_proportional_millionths = their_reserve_millionths(zero_reserve);
+        if get_peer_overrides(&self.persister.storage, pubkey)
+            .is_ok_and(|o| o.force_legacy_channel_type)
+        {
+            config
+                .channel_handshake_config
+                .negotiate_anchors_zero_fee_htlc_tx = false;
+        }
+        config
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn init_open_channel(
         &self,
         pubkey: PublicKey,
         amount_sat: u64,
+        push_msat: u64,
         fee_rate: Option<u64>,
         user_channel_id: Option<u128>,
+        utxos: &[OutPoint],
+        announce: bool,
     ) -> Result<u128, MutinyError> {
         log_trace!(self.logger, "calling init_open_channel");
 
-        let accept_underpaying_htlcs = self
-            .lsp_client
-            .as_ref()
-            .is_some_and(|l| l.accept_underpaying_htlcs());
-        let config = default_user_config(accept_underpaying_htlcs);
+        if push_msat > amount_sat * 1_000 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        // check the coins up front, the funding tx is only built once the peer accepts
+        if !utxos.is_empty() {
+            let wallet_utxos = self.wallet.list_utxos()?;
+            let mut total = 0;
+            for outpoint in utxos {
+                let utxo = wallet_utxos
+                    .iter()
+                    .find(|u| u.outpoint == *outpoint)
+                    .ok_or(MutinyError::InvalidArgumentsError)?;
+                total += utxo.txout.value.to_sat();
+            }
+            if total < amount_sat {
+                return Err(MutinyError::InsufficientBalance);
+            }
+        }
+
+        let mut config = self.channel_config_for_peer(&pubkey).await;
+        if announce {
+            // an announced channel is known by its real scid anyway
+            config.channel_handshake_config.announce_for_forwarding = true;
+            config.channel_handshake_config.negotiate_scid_privacy = false;
+        }
 
         let user_channel_id = user_channel_id.unwrap_or_else(|| {
             // generate random user channel id
@@ -2029,14 +3456,17 @@ impl<S: MutinyStorage> Node<S> {
         };
 
         // save params to db
-        let params = ChannelOpenParams::new(sats_per_vbyte);
+        let mut params = ChannelOpenParams::new(sats_per_vbyte);
+        if !utxos.is_empty() {
+            params.funding_utxos = Some(utxos.to_vec());
+        }
         self.persister
             .persist_channel_open_params(user_channel_id, params)?;
 
         let res = match self.channel_manager.create_channel(
             pubkey,
             amount_sat,
-            0,
+            push_msat,
             user_channel_id,
             None,
             Some(config),
@@ -2062,18 +3492,30 @@ impl<S: MutinyStorage> Node<S> {
         res
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn open_channel_with_timeout(
         &self,
         pubkey: PublicKey,
         amount_sat: u64,
+        push_msat: u64,
         fee_rate: Option<u64>,
         user_channel_id: Option<u128>,
+        utxos: &[OutPoint],
+        announce: bool,
         timeout: u64,
     ) -> Result<OutPoint, MutinyError> {
         log_trace!(self.logger, "calling open_channel_with_timeout");
 
         let init = self
-            .init_open_channel(pubkey, amount_sat, fee_rate, user_channel_id)
+            .init_open_channel(
+                pubkey,
+                amount_sat,
+                push_msat,
+                fee_rate,
+                user_channel_id,
+                utxos,
+                announce,
+            )
             .await?;
 
         let res = self.await_chan_funding_tx(init, &pubkey, timeout).await;
@@ -2092,9 +3534,8 @@ impl<S: MutinyStorage> Node<S> {
 
         // Calculate the total value of the selected utxos
         let utxo_value: u64 = {
-            // find the wallet utxos
-            let wallet = self.wallet.wallet.try_read()?;
-            let all_utxos = wallet.list_unspent();
+            // find the wallet utxos, from the taproot and segwit wallets
+            let all_utxos = self.wallet.list_utxos()?;
 
             // calculate total value of utxos
             let mut total = 0;
@@ -2119,11 +3560,7 @@ impl<S: MutinyStorage> Node<S> {
         // channel size is the total value of the utxos minus the fee
         let channel_value_satoshis = utxo_value - expected_fee;
 
-        let accept_underpaying_htlcs = self
-            .lsp_client
-            .as_ref()
-            .is_some_and(|l| l.accept_underpaying_htlcs());
-        let config = default_user_config(accept_underpaying_htlcs);
+        let config = self.channel_config_for_peer(&pubkey).await;
 
         let user_channel_id = user_chan_id.unwrap_or_else(|| {
             // generate random user channel id
@@ -2264,6 +3701,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     uuid: String,
     lsp_client: Option<&AnyLsp<S>>,
     stop: Arc<AtomicBool>,
+    idle: Arc<AtomicBool>,
+    channel_manager: Arc<PhantomChannelManager<S>>,
     stopped_components: Arc<RwLock<Vec<bool>>>,
     skip_fee_estimates: bool,
 ) {
@@ -2306,60 +3745,71 @@ async fn start_reconnection_handling<S: MutinyStorage>(
             let connection_string = lsp.get_lsp_connection_string().await;
             let mut node_id = NodeId::from_pubkey(&pubkey);
 
-            let connect_res = connect_peer_if_necessary(
-                #[cfg(target_arch = "wasm32")]
-                &websocket_proxy_addr_copy_proxy,
-                &PubkeyConnectionInfo::new(connection_string.as_str()).unwrap(),
-                &storage_copy,
-                proxy_logger.clone(),
-                peer_man_proxy.clone(),
-                pending_connections.clone(),
-                proxy_fee_estimator.clone(),
-                stop_copy.clone(),
-            )
-            .await;
-            match connect_res {
-                Ok(_) => {
-                    log_trace!(proxy_logger, "auto connected lsp: {node_id}");
-                }
-                Err(e) => {
-                    log_trace!(proxy_logger, "could not connect to lsp {node_id}: {e}");
-                    match lsp {
-                        AnyLsp::VoltageFlow(lock) => {
-                            let mut client = lock.write().await;
-                            if let Err(e) = client.set_connection_info().await {
-                                log_error!(
-                                    proxy_logger,
-                                    "could not set connection info from voltage lsp: {e}"
-                                );
-                            } else {
-                                log_trace!(proxy_logger, "set connection info from voltage lsp");
-                                // get new pubkey and connection string
-                                let pubkey = lsp.get_lsp_pubkey().await;
-                                node_id = NodeId::from_pubkey(&pubkey);
-                                let connection_string = lsp.get_lsp_connection_string().await;
-
-                                if let Err(e) = connect_peer_if_necessary(
-                                    #[cfg(target_arch = "wasm32")]
-                                    &websocket_proxy_addr_copy_proxy,
-                                    &PubkeyConnectionInfo::new(connection_string.as_str()).unwrap(),
-                                    &storage_copy,
-                                    proxy_logger.clone(),
-                                    peer_man_proxy.clone(),
-                                    pending_connections.clone(),
-                                    proxy_fee_estimator.clone(),
-                                    stop_copy.clone(),
-                                )
-                                .await
-                                {
+            if is_auto_reconnect_disabled(&storage_copy, &pubkey) {
+                log_debug!(
+                    proxy_logger,
+                    "auto connecting disabled for lsp {node_id}, skipping"
+                );
+            } else {
+                let connect_res = connect_peer_if_necessary(
+                    #[cfg(target_arch = "wasm32")]
+                    &websocket_proxy_addr_copy_proxy,
+                    &PubkeyConnectionInfo::new(connection_string.as_str()).unwrap(),
+                    &storage_copy,
+                    proxy_logger.clone(),
+                    peer_man_proxy.clone(),
+                    pending_connections.clone(),
+                    proxy_fee_estimator.clone(),
+                    stop_copy.clone(),
+                )
+                .await;
+                match connect_res {
+                    Ok(_) => {
+                        log_trace!(proxy_logger, "auto connected lsp: {node_id}");
+                    }
+                    Err(e) => {
+                        log_trace!(proxy_logger, "could not connect to lsp {node_id}: {e}");
+                        match lsp {
+                            AnyLsp::VoltageFlow(lock) => {
+                                let mut client = lock.write().await;
+                                if let Err(e) = client.set_connection_info().await {
                                     log_error!(
                                         proxy_logger,
-                                        "could not connect to lsp after setting connection info: {e}"
+                                        "could not set connection info from voltage lsp: {e}"
                                     );
+                                } else {
+                                    log_trace!(
+                                        proxy_logger,
+                                        "set connection info from voltage lsp"
+                                    );
+                                    // get new pubkey and connection string
+                                    let pubkey = lsp.get_lsp_pubkey().await;
+                                    node_id = NodeId::from_pubkey(&pubkey);
+                                    let connection_string = lsp.get_lsp_connection_string().await;
+
+                                    if let Err(e) = connect_peer_if_necessary(
+                                        #[cfg(target_arch = "wasm32")]
+                                        &websocket_proxy_addr_copy_proxy,
+                                        &PubkeyConnectionInfo::new(connection_string.as_str())
+                                            .unwrap(),
+                                        &storage_copy,
+                                        proxy_logger.clone(),
+                                        peer_man_proxy.clone(),
+                                        pending_connections.clone(),
+                                        proxy_fee_estimator.clone(),
+                                        stop_copy.clone(),
+                                    )
+                                    .await
+                                    {
+                                        log_error!(
+                                            proxy_logger,
+                                            "could not connect to lsp after setting connection info: {e}"
+                                        );
+                                    }
                                 }
                             }
+                            AnyLsp::Lsps(_) => {} // nothing to do here, just retry next loop
                         }
-                        AnyLsp::Lsps(_) => {} // nothing to do here, just retry next loop
                     }
                 }
             }
@@ -2386,6 +3836,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
             })
             .map(|(n, d)| (n, d.connection_string.unwrap()))
             .filter(|(n, _)| lsp_node_id != Some(*n))
+            .filter(|(n, _)| !auto_reconnect_disabled(&storage_copy, n))
             .collect();
         for (pubkey, conn_str) in initial_peers.into_iter() {
             log_trace!(
@@ -2471,6 +3922,15 @@ async fn start_reconnection_handling<S: MutinyStorage>(
 
             let peer_connections = get_all_peers(&storage_copy).unwrap_or_default();
             let current_connections = peer_man_proxy.get_peer_node_ids();
+            // while idle only the peers we have channels with and the lsp are kept
+            let essential: Option<Vec<NodeId>> = idle.load(Ordering::Relaxed).then(|| {
+                channel_manager
+                    .list_channels()
+                    .iter()
+                    .map(|c| NodeId::from_pubkey(&c.counterparty.node_id))
+                    .chain(lsp_node_id)
+                    .collect()
+            });
 
             let not_connected: Vec<(NodeId, String)> = peer_connections
                 .into_iter()
@@ -2484,6 +3944,8 @@ async fn start_reconnection_handling<S: MutinyStorage>(
                         .iter()
                         .any(|c| &NodeId::from_pubkey(c) == n)
                 })
+                .filter(|(n, _)| essential.as_ref().map_or(true, |e| e.contains(n)))
+                .filter(|(n, _)| !auto_reconnect_disabled(&storage_copy, n))
                 .collect();
 
             for (pubkey, conn_str) in not_connected.into_iter() {
@@ -2540,6 +4002,12 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     });
 }
 
+fn auto_reconnect_disabled<S: MutinyStorage>(storage: &S, node_id: &NodeId) -> bool {
+    node_id
+        .as_pubkey()
+        .is_ok_and(|pk| is_auto_reconnect_disabled(storage, &pk))
+}
+
 fn stop_component(stopped_components: &Arc<RwLock<Vec<bool>>>) {
     let mut stopped = stopped_components
         .try_write()
@@ -2610,6 +4078,9 @@ pub(crate) fn default_user_config(accept_underpaying_htlcs: bool) -> UserConfig
             negotiate_anchors_zero_fee_htlc_tx: true, // enable anchor channels
             max_inbound_htlc_value_in_flight_percent_of_channel: 100,
             our_to_self_delay: 6 * 24 * 2, // 2 days
+            // inbound channels can't be configured per peer, they only come
+            // from our LSP or peers the channel acceptor lets through.
+            // Channels we open use `channel_config_for_peer`
             their_channel_reserve_proportional_millionths: 0,
             ..Default::default()
         },
@@ -2815,7 +4286,7 @@ mod tests {
         let amount_sats = 1_000;
 
         let (invoice, _) = node
-            .create_invoice(amount_sats, None, vec![], None)
+            .create_invoice(amount_sats, None, vec![], None, None)
             .await
             .unwrap();
 
@@ -2849,13 +4320,19 @@ mod tests {
         let node = create_node(storage).await;
 
         let invoice = node
-            .create_invoice(10_000, None, vec![], None)
+            .create_invoice(10_000, None, vec![], None, None)
             .await
             .unwrap()
             .0;
 
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(
+                &invoice,
+                None,
+                MppConfig::default(),
+                PaymentPolicy::default(),
+                vec![],
+            )
             .await;
 
         match result {
@@ -2875,7 +4352,7 @@ mod tests {
         // check that we get PaymentTimeout if we don't have the payment info
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::PaymentTimeout);
@@ -2888,6 +4365,8 @@ mod tests {
             amt_msat: MillisatAmount(Some(1000)),
             fee_paid_msat: None,
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
         };
@@ -2903,7 +4382,7 @@ mod tests {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::PaymentTimeout);
@@ -2920,7 +4399,7 @@ mod tests {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::RoutingFailed);
@@ -2937,7 +4416,7 @@ mod tests {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert!(result.is_ok());
@@ -2985,7 +4464,7 @@ mod wasm_test {
         let labels = vec![label.clone()];
 
         let (invoice, _) = node
-            .create_invoice(amount_sats, None, labels.clone(), None)
+            .create_invoice(amount_sats, None, labels.clone(), None, None)
             .await
             .unwrap();
 
@@ -3032,13 +4511,19 @@ mod wasm_test {
         let node = create_node(storage).await;
 
         let invoice = node
-            .create_invoice(10_000, None, vec![], None)
+            .create_invoice(10_000, None, vec![], None, None)
             .await
             .unwrap()
             .0;
 
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(
+                &invoice,
+                None,
+                MppConfig::default(),
+                PaymentPolicy::default(),
+                vec![],
+            )
             .await;
 
         match result {
@@ -3058,7 +4543,7 @@ mod wasm_test {
         // check that we get PaymentTimeout if we don't have the payment info
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::PaymentTimeout);
@@ -3071,6 +4556,8 @@ mod wasm_test {
             amt_msat: MillisatAmount(Some(1000)),
             fee_paid_msat: None,
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
         };
@@ -3085,7 +4572,7 @@ mod wasm_test {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::PaymentTimeout);
@@ -3102,7 +4589,7 @@ mod wasm_test {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert_eq!(result.unwrap_err(), MutinyError::RoutingFailed);
@@ -3119,7 +4606,7 @@ mod wasm_test {
         .unwrap();
 
         let result = node
-            .await_payment(payment_id, payment_hash, 1, vec![])
+            .await_payment(payment_id, payment_hash, 1, false, vec![])
             .await;
 
         assert!(result.is_ok());
@@ -1,30 +1,105 @@
-use crate::labels::LabelStorage;
-use crate::ldkstorage::CHANNEL_CLOSURE_PREFIX;
-use crate::logging::LOGGING_KEY;
+use crate::allowlist::{
+    get_withdrawal_allowlist, persist_withdrawal_allowlist, AllowlistChange, AllowlistEntry,
+    WithdrawalAllowlist,
+};
+use crate::anchorbump::{list_anchor_bumps, AnchorBump};
+use crate::backup::{
+    backup_has_latest_state, get_backup_status, record_backup_exported, update_backup_status,
+    BackupStatus,
+};
+use crate::badges::update_milestones;
+use crate::cbf::CompactFilterClient;
+use crate::channelfunding::{
+    get_channel_funding_request, list_channel_funding_requests, persist_channel_funding_request,
+    ChannelFundingRequest, ChannelFundingStatus, CHANNEL_FUNDING_PREFIX,
+};
+use crate::descriptionprivacy::{
+    get_description_privacy, set_description_privacy, DescriptionPrivacy,
+};
+use crate::event::{CustomTlv, HTLCStatus};
+use crate::hodl::{list_hodl_invoices, HodlInvoice};
+use crate::hooks::{merge_labels, HookEvent, HookRegistry};
+use crate::idle::{
+    get_idle_settings, persist_idle_settings, IdleSettings, IdleStatus, IdleTracker,
+};
+use crate::labels::{
+    get_label_lineage, list_label_lineage, parse_bip329_jsonl, persist_label_lineage,
+    to_bip329_jsonl, Bip329ImportSummary, Bip329Label, LabelLineage, LabelStorage,
+};
+use crate::ldkstorage::{CHANNEL_CLOSURE_PREFIX, MONITORS_PREFIX_KEY};
+use crate::logging::{logging_data_size, trim_logging_data, LOGGING_KEY};
+use crate::lsp::lsps1::{
+    get_channel_purchase, list_channel_purchases, persist_channel_purchase, ChannelPurchase,
+    CreateOrderRequest, Lsps1Client, Lsps1Info, OrderState, PurchasePayment,
+    CHANNEL_PURCHASE_PREFIX,
+};
 use crate::lsp::voltage;
-use crate::messagehandler::CommonLnEventCallback;
+use crate::memory::{MemoryUsage, TRIMMED_LOG_ITEMS, TRIMMED_MEMORY_LOG_ITEMS};
+use crate::messagehandler::{CommonLnEvent, CommonLnEventCallback};
+use crate::mpp::{get_mpp_config, set_mpp_config, MppConfig, MppStrategy};
+use crate::nostr::nostr_key;
+use crate::offers::{list_offers, list_refunds, offer_amount_sats, MutinyOffer, MutinyRefund};
+use crate::outbox::{list_outbox_events, persist_outbox_event, publish_outbox_event, OutboxEvent};
+use crate::paymentpolicy::PaymentPolicy;
 use crate::peermanager::PeerManager;
+use crate::probe::{ProbeResult, ProbeTarget};
+use crate::psbtsession::{
+    get_psbt_session, list_psbt_sessions, persist_psbt_session, PsbtSession, PsbtStatus,
+};
+use crate::rebalance::Rebalance;
+use crate::remoteconfig::{get_remote_config_overrides, load_remote_config};
+use crate::routeexplain::RouteExplanation;
+use crate::routehints::{to_route_hint, RouteHintSelection};
+use crate::routingnode::{
+    get_routing_node_identity, set_routing_node_identity, RoutingNodeIdentity,
+    NODE_ANNOUNCEMENT_INTERVAL_SECS,
+};
+use crate::scb::{
+    list_scb_recoveries, persist_scb_recovery, restore_scb, ChannelBackup, NodeChannelBackup,
+    ScbRecovery, ScbRecoveryStatus, StaticChannelBackup,
+};
+use crate::servicestatus::list_pending_vss_items;
+use crate::settingssync::get_wallet_settings;
+use crate::swap::{
+    build_refund_tx, derive_refund_key, list_swap_deposits, persist_swap_deposit,
+    refundable_swap_deposits, unconfirmed_swap_refunds, SwapDepositStatus,
+};
 use crate::utils::sleep;
+use crate::vault::{
+    get_vault, get_vault_withdrawal, list_vault_withdrawals, persist_vault,
+    persist_vault_withdrawal, vault_outpoints, Vault, VaultWithdrawal, VaultWithdrawalStatus,
+    VAULT_LABEL,
+};
+use crate::watchtower::{deliver_justice_blobs, watchtower_key};
 use crate::MutinyInvoice;
 use crate::MutinyWalletConfig;
 use crate::TransactionDetails;
 use crate::{
     chain::MutinyChain,
     error::MutinyError,
-    fees::MutinyFeeEstimator,
+    fees::{FeeRateSample, FeeSource, MutinyFeeEstimator, SendTimingSuggestion},
     gossip,
     gossip::{fetch_updated_gossip, get_rgs_url},
     logging::MutinyLogger,
-    lsp::{deserialize_lsp_config, Lsp, LspConfig},
+    lsp::{
+        deserialize_lsp_config, get_lsp_list, persist_lsp_list, Lsp, LspConfig, LspFailoverReason,
+        LspList,
+    },
     node::{Node, PubkeyConnectionInfo, RapidGossipSync},
-    onchain::get_esplora_url,
     onchain::OnChainWallet,
+    onchain::{
+        get_esplora_urls, get_watch_only_descriptors, persist_active_esplora_url,
+        select_esplora_url,
+    },
     utils,
 };
 use crate::{gossip::*, scorer::HubPreferentialScorer};
 use crate::{
     node::NodeBuilder,
-    storage::{MutinyStorage, DEVICE_ID_KEY, KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY},
+    storage::{
+        list_payment_info, IndexItem, MutinyStorage, DEFAULT_ADDRESS_TYPE_KEY, DEVICE_ID_KEY,
+        KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY, SEGWIT_KEYCHAIN_STORE_KEY,
+    },
 };
 use anyhow::anyhow;
 use async_lock::RwLock;
@@ -34,20 +109,25 @@ use bitcoin::address::NetworkUnchecked;
 use bitcoin::bip32::Xpriv;
 use bitcoin::blockdata::script;
 use bitcoin::hashes::hex::FromHex;
-use bitcoin::secp256k1::PublicKey;
-use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::{Address, Amount, FeeRate, Network, OutPoint, PrivateKey, Transaction, Txid};
 use esplora_client::{AsyncClient, Builder};
 use futures::future::join_all;
 use hex_conservative::DisplayHex;
 use lightning::chain::Confirm;
 use lightning::events::ClosureReason;
 use lightning::ln::channel_state::ChannelDetails;
-use lightning::ln::channelmanager::PhantomRouteHints;
 use lightning::ln::script::ShutdownScript;
 use lightning::ln::types::ChannelId;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::routing::gossip::NodeId;
 use lightning::sign::{NodeSigner, Recipient};
+use lightning::util::config::ChannelConfigUpdate;
 use lightning::util::logger::*;
+use lightning::util::ser::Writeable;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
 use lightning_invoice::Bolt11Invoice;
 use lightning_transaction_sync::EsploraSyncClient;
@@ -55,6 +135,7 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cmp::max;
+use std::str::FromStr;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 #[cfg(not(target_arch = "wasm32"))]
@@ -64,6 +145,13 @@ use url::Url;
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
+/// Failed syncs in a row before we move to the next esplora server
+const ESPLORA_FAILOVER_THRESHOLD: u32 = 3;
+
+/// How often we save the scorer. LDK only does it hourly and on shutdown,
+/// a browser tab is often closed before either.
+const SCORER_PERSIST_INTERVAL_SECS: u64 = 5 * 60;
+
 // This is the NodeStorage object saved to the DB
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct NodeStorage {
@@ -94,6 +182,101 @@ pub struct NodeIdentity {
     pub pubkey: PublicKey,
 }
 
+/// The kind of on-chain address to receive to
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    /// Pay to taproot (p2tr), `bc1p...`
+    #[default]
+    Taproot,
+    /// Native segwit (p2wpkh), `bc1q...`, for payers that can't send to taproot yet
+    SegwitV0,
+}
+
+impl core::str::FromStr for AddressType {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p2tr" | "Taproot" => Ok(Self::Taproot),
+            "p2wpkh" | "SegwitV0" => Ok(Self::SegwitV0),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// How coins are picked to fund on-chain sends and channel opens
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CoinSelectionStrategy {
+    /// Looks for an exact match that needs no change output
+    #[default]
+    BranchAndBound,
+    /// Spends the oldest coins first
+    OldestFirst,
+    /// Avoids linking addresses together by funding from the coins
+    /// of a single address when possible
+    Privacy,
+}
+
+impl core::str::FromStr for CoinSelectionStrategy {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "branch_and_bound" | "BranchAndBound" => Ok(Self::BranchAndBound),
+            "oldest_first" | "OldestFirst" => Ok(Self::OldestFirst),
+            "privacy" | "Privacy" => Ok(Self::Privacy),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// Merging small coins into one while fees are low, so they don't cost more
+/// than they're worth to spend when fees are high
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConsolidationConfig {
+    /// Only consolidate when the normal fee rate is at or below this, in sat/vbyte
+    pub max_fee_rate: u64,
+    /// Coins worth this much or less get merged
+    pub max_utxo_sats: u64,
+    /// Wait until there are at least this many small coins
+    pub min_utxos: usize,
+}
+
+impl Default for ConsolidationConfig {
+    fn default() -> Self {
+        Self {
+            max_fee_rate: 2,
+            max_utxo_sats: 20_000,
+            min_utxos: 5,
+        }
+    }
+}
+
+/// Rough vbytes of a taproot key spend input, used to estimate
+/// what an on-chain deposit costs to spend later
+const TAPROOT_INPUT_VBYTES: u64 = 58;
+
+/// A way to receive a payment and what it costs in total
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReceiveOption {
+    pub kind: ReceiveOptionKind,
+    /// The node that would receive the payment, none for on-chain
+    pub node_pubkey: Option<PublicKey>,
+    /// Everything the receiver pays, including costs that come later
+    /// like the fee to spend an on-chain deposit
+    pub fee_sats: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ReceiveOptionKind {
+    /// Over an existing channel with enough inbound liquidity
+    Lightning,
+    /// Over a new channel opened by the LSP when the payment arrives
+    JitChannel { lsp_pubkey: PublicKey },
+    /// To an on-chain address
+    OnChain,
+}
+
 #[derive(Serialize, Clone, Eq, PartialEq)]
 pub struct MutinyBip21RawMaterials {
     pub address: Address,
@@ -128,13 +311,49 @@ impl Ord for MutinyPeer {
     }
 }
 
+/// An unspent output of the on-chain wallet
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct MutinyUtxo {
+    pub outpoint: OutPoint,
+    pub amount_sats: u64,
+    /// 0 while the output is unconfirmed
+    pub confirmations: u32,
+    pub address: Option<String>,
+    pub labels: Vec<String>,
+    /// Whether it was received as change from one of our own transactions
+    pub is_change: bool,
+    /// Frozen utxos are only spent when selected manually
+    pub frozen: bool,
+}
+
+/// Weight of a commitment transaction without any HTLC outputs, from BOLT 3
+const COMMITMENT_TX_BASE_WEIGHT: u64 = 724;
+const COMMITMENT_TX_BASE_ANCHOR_WEIGHT: u64 = 1124;
+const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
+/// Value of each of the two anchor outputs, paid by the channel opener
+const ANCHOR_OUTPUT_VALUE_SATS: u64 = 330;
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct MutinyChannel {
     pub user_chan_id: String,
+    /// What we can send right now, after the reserve, fees and pending HTLCs
     pub balance: u64,
     pub size: u64,
+    /// Everything that is neither in balance nor inbound
     pub reserve: u64,
     pub inbound: u64,
+    /// The part of reserve the peer requires us to keep so we can be punished
+    #[serde(default)]
+    pub punishment_reserve: u64,
+    /// Our outgoing payments in flight over this channel
+    #[serde(default)]
+    pub pending_outbound_htlcs: u64,
+    /// HTLCs too small to get their own output, they'd go to fees on a force close
+    #[serde(default)]
+    pub dust_exposure: u64,
+    /// Fee of the current commitment transaction, only ours to pay if we opened the channel
+    #[serde(default)]
+    pub commitment_fee: u64,
     pub outpoint: Option<OutPoint>,
     pub peer: PublicKey,
     pub confirmations_required: Option<u32>,
@@ -142,6 +361,12 @@ pub struct MutinyChannel {
     pub is_outbound: bool,
     pub is_usable: bool,
     pub is_anchor: bool,
+    /// Whether the peer lets us spend the whole balance, without a reserve
+    #[serde(default)]
+    pub zero_reserve: bool,
+    /// The alias the peer announced, if we know it
+    #[serde(default)]
+    pub peer_alias: Option<String>,
 }
 
 impl From<&ChannelDetails> for MutinyChannel {
@@ -160,6 +385,44 @@ impl From<&ChannelDetails> for MutinyChannel {
             .map(|t| t.supports_anchors_zero_fee_htlc_tx())
             .unwrap_or(false);
 
+        let pending_outbound_htlcs = c
+            .pending_outbound_htlcs
+            .iter()
+            .map(|h| h.amount_msat)
+            .sum::<u64>()
+            / 1_000;
+        let dust_exposure = c
+            .pending_inbound_htlcs
+            .iter()
+            .filter(|h| h.is_dust)
+            .map(|h| h.amount_msat)
+            .chain(
+                c.pending_outbound_htlcs
+                    .iter()
+                    .filter(|h| h.is_dust)
+                    .map(|h| h.amount_msat),
+            )
+            .sum::<u64>()
+            / 1_000;
+        let commitment_fee = if c.is_outbound {
+            let non_dust_htlcs = c
+                .pending_inbound_htlcs
+                .iter()
+                .filter(|h| !h.is_dust)
+                .count()
+                + c.pending_outbound_htlcs
+                    .iter()
+                    .filter(|h| !h.is_dust)
+                    .count();
+            commitment_fee_sats(
+                c.feerate_sat_per_1000_weight.unwrap_or_default(),
+                non_dust_htlcs,
+                is_anchor,
+            )
+        } else {
+            0
+        };
+
         MutinyChannel {
             user_chan_id: c.user_channel_id.to_be_bytes().to_lower_hex_string(),
             balance,
@@ -173,10 +436,33 @@ impl From<&ChannelDetails> for MutinyChannel {
             is_outbound: c.is_outbound,
             is_usable: c.is_usable,
             is_anchor,
+            zero_reserve: c.unspendable_punishment_reserve == Some(0),
+            punishment_reserve: c.unspendable_punishment_reserve.unwrap_or_default(),
+            pending_outbound_htlcs,
+            dust_exposure,
+            commitment_fee,
+            peer_alias: None,
         }
     }
 }
 
+/// What the channel opener pays for the commitment transaction at the
+/// feerate, anchor outputs included
+pub(crate) fn commitment_fee_sats(feerate_pkw: u32, non_dust_htlcs: usize, anchors: bool) -> u64 {
+    let base_weight = if anchors {
+        COMMITMENT_TX_BASE_ANCHOR_WEIGHT
+    } else {
+        COMMITMENT_TX_BASE_WEIGHT
+    };
+    let weight = base_weight + COMMITMENT_TX_WEIGHT_PER_HTLC * non_dust_htlcs as u64;
+    let fee = feerate_pkw as u64 * weight / 1000;
+    if anchors {
+        fee + 2 * ANCHOR_OUTPUT_VALUE_SATS
+    } else {
+        fee
+    }
+}
+
 /// Information about a channel that was closed.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ChannelClosure {
@@ -187,6 +473,21 @@ pub struct ChannelClosure {
     pub timestamp: u64,
     #[serde(default)]
     pub channel_funding_txo: Option<OutPoint>,
+    /// The transaction that spent the channel's funding output
+    #[serde(default)]
+    pub closing_txid: Option<Txid>,
+    /// Our funds from the channel that have been claimed on-chain so far
+    #[serde(default)]
+    pub swept_amount_sats: u64,
+    /// Our funds still waiting to be claimed, some may be timelocked
+    #[serde(default)]
+    pub pending_sweep_sats: u64,
+    /// Set once there is nothing left for us to claim from the channel
+    #[serde(default)]
+    pub fully_swept: bool,
+    /// Roughly how long until the last timelocked output can be claimed
+    #[serde(default)]
+    pub estimated_secs_remaining: Option<u64>,
 }
 
 impl ChannelClosure {
@@ -206,6 +507,11 @@ impl ChannelClosure {
             node_id,
             reason: reason.to_string(),
             timestamp: utils::now().as_secs(),
+            closing_txid: None,
+            swept_amount_sats: 0,
+            pending_sweep_sats: 0,
+            fully_swept: false,
+            estimated_secs_remaining: None,
         }
     }
 
@@ -245,6 +551,20 @@ pub struct NodeBalance {
     pub closing: u64,
 }
 
+/// The lightning balance of a single node
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeLightningBalance {
+    pub node_pubkey: PublicKey,
+    /// Balance in open channels, excluding channels that are closing
+    pub lightning: u64,
+    /// Balance in channels that are closing
+    pub closing: u64,
+    /// Channel reserves we cannot spend from
+    pub reserved: u64,
+    /// Amount we can currently send over our channels
+    pub spendable: u64,
+}
+
 pub struct NodeManagerBuilder<S: MutinyStorage> {
     xprivkey: Xpriv,
     storage: S,
@@ -299,10 +619,16 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             .map_or_else(|| Err(MutinyError::InvalidArgumentsError), Ok)?;
         let logger = self.logger.unwrap_or(Arc::new(MutinyLogger::default()));
         let stop = self.stop.unwrap_or(Arc::new(AtomicBool::new(false)));
+        let esplora_urls = get_esplora_urls(
+            c.network,
+            c.user_esplora_url.clone(),
+            &c.fallback_esplora_urls,
+        );
         let esplora = if let Some(e) = self.esplora {
             e
         } else {
-            let esplora_server_url = get_esplora_url(c.network, c.user_esplora_url);
+            let esplora_server_url =
+                select_esplora_url(&self.storage, &esplora_urls, &logger).await;
             let esplora = Builder::new(&esplora_server_url).build_async()?;
             Arc::new(esplora)
         };
@@ -322,26 +648,66 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         ));
         log_trace!(logger, "finished creating tx sync client");
 
+        let remote_config = match c.remote_config.as_ref() {
+            Some((url, signing_key)) => {
+                log_trace!(logger, "loading remote config");
+                let config = load_remote_config(
+                    &self.storage,
+                    &Client::new(),
+                    url,
+                    signing_key,
+                    c.network,
+                    &logger,
+                )
+                .await;
+                log_trace!(logger, "finished loading remote config");
+                config
+            }
+            None => None,
+        }
+        .map(|config| {
+            config.with_overrides(&get_remote_config_overrides(&self.storage).unwrap_or_default())
+        });
+
+        // the operator's fee api replaces the default source, not one picked for the wallet
+        let fee_source = match remote_config.as_ref().and_then(|r| r.fee_endpoints.first()) {
+            Some(url) if c.fee_source == FeeSource::Esplora => FeeSource::MempoolSpace(url.clone()),
+            _ => c.fee_source.clone(),
+        };
+
         log_trace!(logger, "creating fee estimator");
-        let fee_estimator = Arc::new(MutinyFeeEstimator::new(
-            self.storage.clone(),
-            esplora.clone(),
-            logger.clone(),
-        ));
+        let fee_estimator = Arc::new(
+            MutinyFeeEstimator::new(self.storage.clone(), esplora.clone(), logger.clone())
+                .with_fee_source(fee_source),
+        );
         log_trace!(logger, "finished creating fee estimator");
 
         log_trace!(logger, "creating on chain wallet");
-        let wallet = Arc::new(OnChainWallet::new(
-            self.xprivkey,
-            self.storage.clone(),
-            c.network,
-            esplora.clone(),
-            fee_estimator.clone(),
-            stop.clone(),
-            logger.clone(),
-        )?);
+        let wallet = Arc::new(
+            OnChainWallet::new(
+                self.xprivkey,
+                self.storage.clone(),
+                c.network,
+                esplora.clone(),
+                fee_estimator.clone(),
+                stop.clone(),
+                logger.clone(),
+            )?
+            .with_coin_selection(c.coin_selection)
+            .with_gap_limit(c.gap_limit)
+            .with_compact_filters(c.compact_filter_url.clone().map(CompactFilterClient::new)),
+        );
         log_trace!(logger, "finished creating on chain wallet");
 
+        if let Some((url, signing_key)) = c.chain_snapshot.as_ref() {
+            log_trace!(logger, "bootstrapping from chain snapshot");
+            // not fatal, we can always sync from esplora
+            if let Err(e) = wallet.bootstrap_from_snapshot(url, signing_key).await {
+                log_warn!(logger, "Failed to bootstrap from chain snapshot: {e}");
+            }
+            log_trace!(logger, "finished bootstrapping from chain snapshot");
+        }
+
         log_trace!(logger, "creating chain");
         let chain = Arc::new(MutinyChain::new(tx_sync, wallet.clone(), logger.clone()));
         log_trace!(logger, "finished creating chain");
@@ -361,15 +727,30 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         let lsp_config = if c.safe_mode {
             None
         } else {
-            create_lsp_config(c.lsp_url, c.lsp_connection_string, c.lsp_token).unwrap_or_else(
-                |_| {
-                    log_warn!(
-                        logger,
-                        "Failed to create lsp config, falling back to no LSP configured"
-                    );
-                    None
-                },
-            )
+            // without one given use the first of our preferred LSPs
+            let preferred_lsp = get_lsp_list(&self.storage)
+                .ok()
+                .and_then(|l| l.lsps.into_iter().next());
+            let lsp_given = c.lsp_url.is_some() || c.lsp_connection_string.is_some();
+            if !lsp_given && preferred_lsp.is_some() {
+                preferred_lsp
+            } else {
+                // fall back to the operator's recommended LSP when none is given
+                let lsp_url = if !lsp_given {
+                    remote_config.and_then(|r| r.recommended_lsps.into_iter().next())
+                } else {
+                    c.lsp_url
+                };
+                create_lsp_config(lsp_url, c.lsp_connection_string, c.lsp_token).unwrap_or_else(
+                    |_| {
+                        log_warn!(
+                            logger,
+                            "Failed to create lsp config, falling back to no LSP configured"
+                        );
+                        None
+                    },
+                )
+            }
         };
         log_trace!(logger, "finished creating lsp config");
 
@@ -384,6 +765,11 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         );
 
         let has_done_initial_ldk_sync = Arc::new(AtomicBool::new(false));
+        let idle = Arc::new(IdleTracker::new(
+            get_idle_settings(&self.storage)?,
+            utils::now().as_secs(),
+        ));
+        let hooks = Arc::new(HookRegistry::new(logger.clone()));
 
         let nodes = if c.safe_mode {
             // If safe mode is enabled, we don't start any nodes
@@ -415,6 +801,8 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
                     .with_wallet(wallet.clone())
                     .with_esplora(esplora.clone())
                     .with_initial_sync(has_done_initial_ldk_sync.clone())
+                    .with_idle_flag(idle.idle_flag())
+                    .with_hooks(hooks.clone())
                     .with_network(c.network);
                 node_builder.with_logger(logger.clone());
 
@@ -434,6 +822,7 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
                 if c.do_not_bump_channel_close_tx {
                     node_builder.do_not_bump_channel_close_tx();
                 }
+                node_builder.with_mpp_strategy(c.mpp_strategy);
 
                 let node = node_builder.build().await?;
 
@@ -500,13 +889,19 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             websocket_proxy_addr,
             user_rgs_url: c.user_rgs_url,
             esplora,
+            esplora_urls,
             ln_event_callback: self.ln_event_callback,
             lsp_config,
             logger,
             do_not_connect_peers: c.do_not_connect_peers,
             do_not_bump_channel_close_tx: c.do_not_bump_channel_close_tx,
             safe_mode: c.safe_mode,
+            memory_budget_bytes: c.memory_budget_bytes,
+            consolidation: c.consolidation,
+            mpp_strategy: c.mpp_strategy,
             has_done_initial_ldk_sync,
+            idle,
+            hooks,
         };
 
         Ok(nm)
@@ -528,6 +923,8 @@ pub struct NodeManager<S: MutinyStorage> {
     websocket_proxy_addr: String,
     user_rgs_url: Option<String>,
     esplora: Arc<AsyncClient>,
+    /// All the configured esplora servers in order of preference
+    esplora_urls: Vec<String>,
     ln_event_callback: Option<CommonLnEventCallback>,
     pub(crate) wallet: Arc<OnChainWallet<S>>,
     gossip_sync: Arc<RapidGossipSync>,
@@ -542,8 +939,14 @@ pub struct NodeManager<S: MutinyStorage> {
     do_not_connect_peers: bool,
     do_not_bump_channel_close_tx: bool,
     pub safe_mode: bool,
+    memory_budget_bytes: Option<u64>,
+    /// Set when the user opted in to consolidating small coins while fees are low
+    consolidation: Option<ConsolidationConfig>,
+    mpp_strategy: MppStrategy,
     /// If we've completed an initial sync this instance
     pub(crate) has_done_initial_ldk_sync: Arc<AtomicBool>,
+    idle: Arc<IdleTracker>,
+    pub(crate) hooks: Arc<HookRegistry>,
 }
 
 impl<S: MutinyStorage> NodeManager<S> {
@@ -609,13 +1012,26 @@ impl<S: MutinyStorage> NodeManager<S> {
         };
         utils::spawn(async move {
             let mut synced = false;
+            let mut gossip_synced = false;
+            let mut sync_failures = 0;
+            let mut failed_over = false;
+            let mut last_node_announcement = 0;
+            let mut last_scorer_persist = utils::now().as_secs();
+            let mut remote_storage_down = false;
             loop {
                 // If we are stopped, don't sync
                 if nm.stop.load(Ordering::Relaxed) {
                     return;
                 }
 
-                if !synced {
+                if nm.idle.check(utils::now().as_secs()) {
+                    nm.enter_idle().await;
+                }
+
+                // gossip is only needed for paying, it waits until the user is back
+                if (!synced || !gossip_synced) && !nm.idle.is_idle() {
+                    gossip_synced = true;
+
                     if let Err(e) = nm.sync_rgs().await {
                         log_error!(nm.logger, "Failed to sync RGS: {e}");
                     } else {
@@ -637,25 +1053,214 @@ impl<S: MutinyStorage> NodeManager<S> {
                     log_info!(nm.logger, "Updated fee estimates!");
                 }
 
+                let sync_start = Instant::now();
                 if let Err(e) = nm.sync().await {
                     log_error!(nm.logger, "Failed to sync: {e}");
-                } else if !synced {
-                    // if this is the first sync, set the done_first_sync flag
-                    let _ = nm.storage.set_done_first_sync();
-                    synced = true;
+                    sync_failures += 1;
+                    if sync_failures >= ESPLORA_FAILOVER_THRESHOLD && !failed_over {
+                        match nm.fail_over_esplora() {
+                            Ok(switched) => failed_over = switched,
+                            Err(e) => log_error!(nm.logger, "Failed to fail over esplora: {e}"),
+                        }
+                    }
+                } else {
+                    sync_failures = 0;
+                    if !synced {
+                        // if this is the first sync, set the done_first_sync flag
+                        let _ = nm.storage.set_done_first_sync();
+                        synced = true;
+                    }
+                    nm.hooks.notify(HookEvent::SyncComplete {
+                        duration_ms: sync_start.elapsed().as_millis() as u64,
+                    });
+                }
+
+                nm.reconcile_remote_storage(&mut remote_storage_down).await;
+
+                // recovering channels sweeps on-chain, don't touch them in safe mode
+                if !nm.safe_mode {
+                    if let Err(e) = nm.recover_scb_channels().await {
+                        log_error!(nm.logger, "Failed to recover backed up channels: {e}");
+                    }
+                }
+
+                if let Err(e) = nm.check_backup_reminder() {
+                    log_error!(nm.logger, "Failed to check backup status: {e}");
+                }
+
+                // swap deposits are refunded with on-chain funds, don't touch them in safe mode
+                if !nm.safe_mode {
+                    if let Err(e) = nm.refund_expired_swap_deposits().await {
+                        log_error!(nm.logger, "Failed to refund swap deposits: {e}");
+                    }
+                }
+
+                // opening channels spends on-chain funds, don't touch them in safe mode
+                if !nm.safe_mode {
+                    if let Err(e) = nm.open_funded_channels().await {
+                        log_error!(nm.logger, "Failed to open funded channels: {e}");
+                    }
+                }
+
+                // vault withdrawals spend on-chain funds too
+                if !nm.safe_mode {
+                    if let Err(e) = nm.process_vault_withdrawals().await {
+                        log_error!(nm.logger, "Failed to process vault withdrawals: {e}");
+                    }
+                }
+
+                // consolidating spends on-chain funds, don't touch them in safe mode
+                if let (Some(config), false) = (nm.consolidation, nm.safe_mode) {
+                    if let Err(e) = nm.wallet.consolidate_utxos(&config).await {
+                        log_error!(nm.logger, "Failed to consolidate utxos: {e}");
+                    }
+                }
+
+                if utils::now().as_secs() - last_node_announcement
+                    >= NODE_ANNOUNCEMENT_INTERVAL_SECS
+                {
+                    match nm.announce_node().await {
+                        Ok(true) => last_node_announcement = utils::now().as_secs(),
+                        Ok(false) => {}
+                        Err(e) => log_error!(nm.logger, "Failed to announce node: {e}"),
+                    }
+                }
+
+                if utils::now().as_secs() - last_scorer_persist >= SCORER_PERSIST_INTERVAL_SECS {
+                    match nm.persist_scorer() {
+                        Ok(_) => last_scorer_persist = utils::now().as_secs(),
+                        Err(e) => log_error!(nm.logger, "Failed to persist scorer: {e}"),
+                    }
+                }
+
+                if let Err(e) = nm.refresh_channel_purchases().await {
+                    log_error!(nm.logger, "Failed to refresh channel purchases: {e}");
+                }
+
+                if let Err(e) = nm.check_milestones() {
+                    log_error!(nm.logger, "Failed to check milestones: {e}");
+                }
+
+                if let Err(e) = nm.retry_nostr_outbox().await {
+                    log_error!(nm.logger, "Failed to retry nostr outbox: {e}");
+                }
+
+                if let Err(e) = nm.deliver_justice_blobs().await {
+                    log_error!(nm.logger, "Failed to deliver justice transactions: {e}");
+                }
+
+                if let Err(e) = nm.trim_memory_if_over_budget() {
+                    log_error!(nm.logger, "Failed to trim memory: {e}");
                 }
 
                 // wait for next sync round, checking graceful shutdown check each second.
-                for _ in 0..sync_interval_secs {
+                // an idle wallet waits longer, but syncs right away once it's used again
+                let was_idle = nm.idle.is_idle();
+                for _ in 0..nm.idle.sync_interval_secs(sync_interval_secs) {
                     if nm.stop.load(Ordering::Relaxed) {
                         return;
                     }
+                    if was_idle && !nm.idle.is_idle() {
+                        break;
+                    }
                     sleep(1_000).await;
                 }
             }
         });
     }
 
+    /// The alias, color and addresses we gossip as a routing node, if we are one
+    pub fn get_routing_node_identity(&self) -> Result<Option<RoutingNodeIdentity>, MutinyError> {
+        get_routing_node_identity(&self.storage)
+    }
+
+    /// Sets who we are to the network, sent once we have an announced channel.
+    /// None stops announcing the node, its existing announcement ages out.
+    /// Only useful natively, where other nodes can connect to the addresses.
+    pub fn set_routing_node_identity(
+        &self,
+        identity: Option<RoutingNodeIdentity>,
+    ) -> Result<(), MutinyError> {
+        set_routing_node_identity(&self.storage, identity)
+    }
+
+    /// Sends the node announcement from every node with an announced channel.
+    /// Returns whether any did.
+    pub(crate) async fn announce_node(&self) -> Result<bool, MutinyError> {
+        let Some(identity) = get_routing_node_identity(&self.storage)? else {
+            return Ok(false);
+        };
+
+        let mut announced = false;
+        for node in self.nodes.read().await.values() {
+            announced |= node.broadcast_node_announcement(&identity)?;
+        }
+        Ok(announced)
+    }
+
+    /// Cuts down on background work once the wallet has gone idle
+    async fn enter_idle(&self) {
+        let mut disconnected = 0;
+        for node in self.nodes.read().await.values() {
+            disconnected += node.disconnect_non_essential_peers().await;
+        }
+        log_info!(
+            self.logger,
+            "Wallet went idle, disconnected {disconnected} non-essential peers"
+        );
+    }
+
+    /// Records a user interaction, waking the wallet up if it was idle
+    pub fn record_interaction(&self) {
+        if self.idle.touch(utils::now().as_secs()) {
+            log_info!(self.logger, "Wallet is no longer idle");
+        }
+    }
+
+    /// Whether the wallet is idle and the thresholds it goes idle at
+    pub fn get_idle_status(&self) -> IdleStatus {
+        self.idle.status()
+    }
+
+    /// Sets when the wallet goes idle and how often it syncs while idle
+    pub fn set_idle_settings(&self, settings: IdleSettings) -> Result<(), MutinyError> {
+        persist_idle_settings(&self.storage, &settings)?;
+        self.idle.set_settings(settings, utils::now().as_secs());
+        Ok(())
+    }
+
+    /// Moves on to the next configured esplora server after the current one kept
+    /// failing. The chain clients can't be swapped while running, so the new server
+    /// is saved and used from the next start, the app is told so it can restart.
+    /// Returns false if there is no other server to move to.
+    fn fail_over_esplora(&self) -> Result<bool, MutinyError> {
+        if self.esplora_urls.len() < 2 {
+            return Ok(false);
+        }
+
+        let current = self.esplora.url();
+        let next = self
+            .esplora_urls
+            .iter()
+            .position(|u| u == current)
+            .map_or(0, |i| (i + 1) % self.esplora_urls.len());
+        let next = self.esplora_urls[next].clone();
+        persist_active_esplora_url(&self.storage, &next)?;
+        log_warn!(
+            self.logger,
+            "Esplora server {current} keeps failing, switching to {next}"
+        );
+
+        if let Some(cb) = self.ln_event_callback.as_ref() {
+            cb.trigger(CommonLnEvent::EsploraFailover {
+                previous_url: current.to_string(),
+                url: next,
+            });
+        }
+
+        Ok(true)
+    }
+
     /// Broadcast a transaction to the network.
     /// The transaction is broadcast through the configured esplora server.
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
@@ -666,85 +1271,762 @@ impl<S: MutinyStorage> NodeManager<S> {
         res
     }
 
-    /// Returns the network of the wallet.
-    pub fn get_network(&self) -> Network {
-        self.network
+    /// Broadcasts a hex encoded transaction that was built outside the wallet.
+    /// If it touches our addresses it shows up in the wallet right away.
+    /// With the withdrawal allowlist on, it can only pay us or allowed destinations.
+    pub async fn broadcast_raw_tx(&self, hex: &str) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling broadcast_raw_tx");
+
+        let tx: Transaction = bitcoin::consensus::encode::deserialize_hex(hex.trim())
+            .map_err(|_| MutinyError::InvalidTransaction)?;
+        self.wallet.check_allowed_tx(&tx)?;
+        let txid = tx.compute_txid();
+        self.wallet.broadcast_transaction(tx).await?;
+
+        log_trace!(self.logger, "finished calling broadcast_raw_tx");
+        Ok(txid)
     }
 
-    /// Gets a new bitcoin address from the wallet.
-    /// Will generate the last unused address in our bdk wallet.
-    pub fn get_new_address(&self, labels: Vec<String>) -> Result<Address, MutinyError> {
-        log_trace!(self.logger, "calling get_new_address");
+    /// Gets a transaction by its txid, from the wallet if it's one of ours
+    /// and from the chain backend otherwise.
+    pub async fn get_raw_tx(&self, txid: Txid) -> Result<Option<Transaction>, MutinyError> {
+        log_trace!(self.logger, "calling get_raw_tx");
 
-        if let Ok(mut wallet) = self.wallet.wallet.try_write() {
-            let address = wallet.reveal_next_address(KeychainKind::External).address;
-            self.set_address_labels(address.clone(), labels)?;
-            log_trace!(self.logger, "finished calling get_new_address");
+        let local = self
+            .wallet
+            .get_transaction(txid)?
+            .and_then(|details| details.transaction);
+        let res = match local {
+            Some(tx) => Some(tx),
+            None => self.esplora.get_tx(&txid).await?,
+        };
+
+        log_trace!(self.logger, "finished calling get_raw_tx");
+        Ok(res)
+    }
 
-            if let Some(changeset) = wallet.take_staged() {
-                self.storage.write_changes(&changeset)?;
+    /// Broadcasts refunds for any swap deposits that timed out before
+    /// the swap provider paid us. Deposits that fail to refund are retried
+    /// on the next sync with the error recorded on them. Refunds are only
+    /// marked as done once they confirm.
+    pub(crate) async fn refund_expired_swap_deposits(&self) -> Result<(), MutinyError> {
+        let storage = &self.storage;
+        for mut deposit in unconfirmed_swap_refunds(storage)? {
+            let Some(txid) = deposit.refund_txid else {
+                continue;
+            };
+            let confirmed = self
+                .wallet
+                .get_transaction(txid)?
+                .is_some_and(|details| details.confirmation_time.is_confirmed());
+            if !confirmed {
+                continue;
             }
 
-            return Ok(address);
+            log_info!(self.logger, "Swap deposit {} refunded", deposit.id);
+            deposit.status = SwapDepositStatus::Refunded;
+            persist_swap_deposit(storage, &deposit)?;
+            if let Some(cb) = self.ln_event_callback.as_ref() {
+                cb.trigger(CommonLnEvent::SwapDepositRefunded {
+                    id: deposit.id.clone(),
+                    txid: txid.to_string(),
+                });
+            }
         }
 
-        log_error!(self.logger, "Could not get wallet lock to get new address");
-        Err(MutinyError::WalletOperationFailed)
-    }
+        // avoid hitting esplora when there is nothing to refund
+        if !list_swap_deposits(storage)?
+            .iter()
+            .any(|d| d.status == SwapDepositStatus::Pending)
+        {
+            return Ok(());
+        }
 
-    /// Gets the current balance of the on-chain wallet.
-    pub fn get_wallet_balance(&self) -> Result<u64, MutinyError> {
-        log_trace!(self.logger, "calling get_wallet_balance");
+        let height = self
+            .esplora
+            .get_height()
+            .await
+            .map_err(|_| MutinyError::ChainAccessFailed)?;
+
+        let secp = Secp256k1::new();
+        for mut deposit in refundable_swap_deposits(storage, height)? {
+            log_info!(self.logger, "Refunding swap deposit {}", deposit.id);
+
+            let res = async {
+                let refund_key = derive_refund_key(&secp, self.xprivkey, deposit.refund_key_index)?;
+                let address = self.get_new_address(vec![deposit.label()])?;
+                let tx = build_refund_tx(
+                    &secp,
+                    &deposit,
+                    &refund_key,
+                    address.script_pubkey(),
+                    self.estimate_fee_normal() as u64,
+                )?;
+                let txid = tx.compute_txid();
+                self.broadcast_transaction(tx).await?;
+                Ok::<Txid, MutinyError>(txid)
+            }
+            .await;
 
-        if let Ok(wallet) = self.wallet.wallet.try_read() {
-            log_trace!(self.logger, "finished calling get_wallet_balance");
-            return Ok(wallet.balance().total().to_sat());
+            match res {
+                Ok(txid) => {
+                    deposit.status = SwapDepositStatus::Refunding;
+                    deposit.refund_txid = Some(txid);
+                    deposit.error = None;
+                }
+                Err(e) => {
+                    log_error!(
+                        self.logger,
+                        "Failed to refund swap deposit {}: {e}",
+                        deposit.id
+                    );
+                    deposit.error = Some(e.to_string());
+                }
+            }
+            persist_swap_deposit(storage, &deposit)?;
         }
 
-        log_error!(
-            self.logger,
-            "Could not get wallet lock to get wallet balance"
-        );
-        Err(MutinyError::WalletOperationFailed)
+        Ok(())
     }
 
-    /// Sends an on-chain transaction to the given address.
-    /// The amount is in satoshis and the fee rate is in sat/vbyte.
-    ///
-    /// If a fee rate is not provided, one will be used from the fee estimator.
-    pub async fn send_to_address(
+    /// Publishes a signed nostr event to the given relays. The event is kept in the
+    /// outbox and retried in the background until a relay accepts it, so it isn't
+    /// lost if we are offline. Returns whether a relay accepted it right away.
+    pub async fn publish_nostr_event(
         &self,
-        send_to: Address,
-        amount: u64,
-        labels: Vec<String>,
-        fee_rate: Option<u64>,
-    ) -> Result<Txid, MutinyError> {
-        log_trace!(self.logger, "calling send_to_address");
-        let res = self.wallet.send(send_to, amount, labels, fee_rate).await;
-        log_trace!(self.logger, "finished calling send_to_address");
+        event: Value,
+        relays: Vec<String>,
+    ) -> Result<bool, MutinyError> {
+        log_trace!(self.logger, "calling publish_nostr_event");
+
+        let event = OutboxEvent::new(event, relays)?;
+        persist_outbox_event(&self.storage, &event)?;
+        let id = event.id.clone();
+        let key = nostr_key(self.xprivkey)?;
+        let published = match publish_outbox_event(&self.storage, event, &key).await {
+            Ok(()) => true,
+            Err(e) => {
+                log_warn!(
+                    self.logger,
+                    "Failed to publish nostr event {id}, will retry: {e}"
+                );
+                false
+            }
+        };
+        log_trace!(self.logger, "finished calling publish_nostr_event");
 
-        res
+        Ok(published)
     }
 
-    /// Sweeps all the funds from the wallet to the given address.
-    /// The fee rate is in sat/vbyte.
-    ///
-    /// If a fee rate is not provided, one will be used from the fee estimator.
-    pub async fn sweep_wallet(
-        &self,
-        send_to: Address,
-        labels: Vec<String>,
-        fee_rate: Option<u64>,
-        allow_dust: Option<bool>,
-    ) -> Result<Txid, MutinyError> {
-        log_trace!(self.logger, "calling sweep_wallet");
-        let res = self
-            .wallet
-            .sweep(send_to, labels, fee_rate, allow_dust)
-            .await;
-        log_trace!(self.logger, "calling sweep_wallet");
-
-        res
+    /// Nostr events that no relay has accepted yet, oldest first.
+    pub fn pending_nostr_events(&self) -> Result<Vec<OutboxEvent>, MutinyError> {
+        list_outbox_events(&self.storage)
+    }
+
+    /// Records the milestones our payments have reached, their badges are
+    /// queued in the outbox when the user publishes them.
+    fn check_milestones(&self) -> Result<(), MutinyError> {
+        let labels_map = self.storage.get_invoice_labels()?;
+        let mut invoices = vec![];
+        for inbound in [true, false] {
+            for (hash, info) in list_payment_info(&self.storage, inbound)? {
+                if info.status != HTLCStatus::Succeeded {
+                    continue;
+                }
+                let labels = info
+                    .bolt11
+                    .as_ref()
+                    .and_then(|b| labels_map.get(b).cloned())
+                    .unwrap_or_default();
+                invoices.push(MutinyInvoice::from(info, hash, inbound, labels)?);
+            }
+        }
+
+        let relays = get_wallet_settings(&self.storage)?
+            .unwrap_or_default()
+            .sync_relays();
+        let key = nostr_key(self.xprivkey)?;
+        for earned in update_milestones(&self.storage, &key, &invoices, relays)? {
+            log_info!(self.logger, "Reached milestone {:?}", earned.milestone);
+        }
+
+        Ok(())
+    }
+
+    /// Retries publishing the outbox events that are due for another attempt.
+    async fn retry_nostr_outbox(&self) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let key = nostr_key(self.xprivkey)?;
+        for event in list_outbox_events(&self.storage)? {
+            if event.next_attempt_at() > now {
+                continue;
+            }
+            let id = event.id.clone();
+            match publish_outbox_event(&self.storage, event, &key).await {
+                Ok(()) => log_info!(self.logger, "Published queued nostr event {id}"),
+                Err(e) => log_debug!(self.logger, "Failed to publish nostr event {id}: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives the queued justice transactions to the watchtowers that don't have them yet.
+    async fn deliver_justice_blobs(&self) -> Result<(), MutinyError> {
+        let key = watchtower_key(self.xprivkey)?;
+        deliver_justice_blobs(&self.storage, &key).await
+    }
+
+    /// Estimates how much memory the largest in-memory structures are using.
+    pub fn get_memory_usage(&self) -> Result<MemoryUsage, MutinyError> {
+        let network_graph = self.gossip_sync.network_graph();
+        let (network_graph_nodes, network_graph_channels) = {
+            let graph = network_graph.read_only();
+            (graph.nodes().len() as u64, graph.channels().len() as u64)
+        };
+
+        let (activity_index_items, activity_index_bytes) = {
+            let index = self.storage.activity_index();
+            let index = index.try_read()?;
+            let bytes = index
+                .iter()
+                .map(|i| (std::mem::size_of::<IndexItem>() + i.key.len()) as u64)
+                .sum();
+            (index.len() as u64, bytes)
+        };
+
+        let log_bytes = self.logger.memory_logs_size() + logging_data_size(&self.storage)?;
+
+        Ok(MemoryUsage {
+            network_graph_bytes: network_graph.serialized_length() as u64,
+            network_graph_nodes,
+            network_graph_channels,
+            activity_index_items,
+            activity_index_bytes,
+            log_bytes,
+        })
+    }
+
+    /// Frees what memory we can without losing anything needed to run the node:
+    /// stale network graph entries and older logs.
+    pub fn trim_memory(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling trim_memory");
+
+        let now = utils::now().as_secs();
+        self.gossip_sync
+            .network_graph()
+            .remove_stale_channels_and_tracking_with_time(now);
+        self.logger.trim_memory_logs(TRIMMED_MEMORY_LOG_ITEMS);
+        trim_logging_data(&self.storage, TRIMMED_LOG_ITEMS)?;
+
+        log_trace!(self.logger, "finished calling trim_memory");
+        Ok(())
+    }
+
+    fn trim_memory_if_over_budget(&self) -> Result<(), MutinyError> {
+        if self.memory_budget_bytes.is_none() {
+            return Ok(());
+        }
+
+        let usage = self.get_memory_usage()?;
+        if usage.over_budget(self.memory_budget_bytes) {
+            log_info!(
+                self.logger,
+                "Memory usage of {} bytes is over budget, trimming",
+                usage.total_bytes()
+            );
+            self.trim_memory()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the writes VSS missed while it was unreachable and emits a
+    /// [`CommonLnEvent::RemoteStorageStatusChanged`] when it goes down or comes back.
+    async fn reconcile_remote_storage(&self, was_down: &mut bool) {
+        let Some(vss) = self.storage.vss_client() else {
+            return;
+        };
+
+        match self.storage.reconcile_vss().await {
+            Ok(0) => {}
+            Ok(sent) => log_info!(self.logger, "Sent {sent} queued writes to VSS"),
+            Err(e) => log_warn!(self.logger, "Could not send queued writes to VSS: {e}"),
+        }
+        // keep what VSS showed us when reading too, not only when writing
+        if let Err(e) = self.storage.save_vss_seen_versions() {
+            log_warn!(self.logger, "Could not save seen VSS versions: {e}");
+        }
+
+        let down = vss.health.unreachable_since().is_some();
+        if down == *was_down {
+            return;
+        }
+        *was_down = down;
+
+        let pending_remote_writes = list_pending_vss_items(&self.storage)
+            .map(|p| p.len())
+            .unwrap_or_default();
+        if let Some(cb) = self.ln_event_callback.as_ref() {
+            cb.trigger(CommonLnEvent::RemoteStorageStatusChanged {
+                reachable: !down,
+                pending_remote_writes,
+            });
+        }
+    }
+
+    /// What a static channel backup needs to know about each node's channels
+    pub(crate) async fn channel_backups(&self) -> Vec<NodeChannelBackup> {
+        let nodes = self.nodes.read().await;
+        nodes
+            .values()
+            .map(|node| NodeChannelBackup {
+                child_index: node.child_index,
+                channels: node
+                    .channel_manager
+                    .list_channels()
+                    .iter()
+                    .filter_map(|c| {
+                        let node_id = NodeId::from_pubkey(&c.counterparty.node_id);
+                        let connection_string = read_peer_info(&self.storage, &node_id)
+                            .ok()
+                            .flatten()
+                            .and_then(|p| p.connection_string);
+                        ChannelBackup::from_details(&self.storage, c, connection_string)
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Starts recovering the channels of a static channel backup that none of
+    /// the nodes have a monitor for. Returns how many channels are recovering.
+    pub(crate) async fn restore_scb(
+        &self,
+        backup: &StaticChannelBackup,
+    ) -> Result<usize, MutinyError> {
+        let nodes: HashMap<u32, PublicKey> = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .map(|n| (n.child_index, n.pubkey))
+            .collect();
+
+        // monitors are kept under `monitors/<txid>_<index>_<node id>`
+        let monitored = self
+            .storage
+            .scan_keys(MONITORS_PREFIX_KEY, None)?
+            .into_iter()
+            .filter_map(|key| {
+                let (funding_txo, _) = key.strip_prefix(MONITORS_PREFIX_KEY)?.rsplit_once('_')?;
+                Some(funding_txo.to_string())
+            })
+            .collect();
+
+        restore_scb(&self.storage, self.network, backup, &nodes, &monitored)
+    }
+
+    /// The channels being recovered from a static channel backup
+    pub fn list_scb_recoveries(&self) -> Result<Vec<ScbRecovery>, MutinyError> {
+        list_scb_recoveries(&self.storage)
+    }
+
+    /// Moves the channels being recovered from a static channel backup along
+    async fn recover_scb_channels(&self) -> Result<(), MutinyError> {
+        let recoveries: Vec<ScbRecovery> = list_scb_recoveries(&self.storage)?
+            .into_iter()
+            .filter(|r| r.status == ScbRecoveryStatus::Closing)
+            .collect();
+        if recoveries.is_empty() {
+            return Ok(());
+        }
+
+        let nodes = self.nodes.read().await.clone();
+        for recovery in recoveries {
+            let Some(node) = nodes.get(&recovery.node_id) else {
+                continue;
+            };
+            match node.recover_scb_channel(recovery.clone()).await {
+                Ok(updated) if updated != recovery => {
+                    persist_scb_recovery(&self.storage, &updated)?;
+                }
+                Ok(_) => {}
+                Err(e) => log_warn!(
+                    self.logger,
+                    "Failed to recover channel {}: {e}",
+                    recovery.channel.funding_txo
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits a [`CommonLnEvent::BackupReminder`] if the channel state is newer than
+    /// any verified backup and we haven't reminded the user recently.
+    fn check_backup_reminder(&self) -> Result<(), MutinyError> {
+        let Some(cb) = self.ln_event_callback.as_ref() else {
+            return Ok(());
+        };
+
+        let now = utils::now().as_secs();
+        let status = get_backup_status(&self.storage)?;
+        if !status.should_remind(now) {
+            return Ok(());
+        }
+
+        let status = update_backup_status(&self.storage, |s| s.last_reminder = Some(now))?;
+        cb.trigger(CommonLnEvent::BackupReminder {
+            state_changed_at: status.last_state_change.unwrap_or_default(),
+            last_verified_at: status.last_verified,
+        });
+
+        Ok(())
+    }
+
+    /// Gets when the channel state last changed and when it was last backed up.
+    pub fn get_backup_status(&self) -> Result<BackupStatus, MutinyError> {
+        get_backup_status(&self.storage)
+    }
+
+    /// Checks that an exported backup contains the latest channel state.
+    /// If it does, the backup is recorded as verified and reminders stop
+    /// until the channel state changes again.
+    pub fn verify_backup(&self, backup: &Value) -> Result<bool, MutinyError> {
+        log_trace!(self.logger, "calling verify_backup");
+
+        let verified = backup_has_latest_state(&self.storage, backup)?;
+        if verified {
+            let now = utils::now().as_secs();
+            update_backup_status(&self.storage, |s| s.last_verified = Some(now))?;
+            if let Some(cb) = self.ln_event_callback.as_ref() {
+                cb.trigger(CommonLnEvent::BackupVerified { verified_at: now });
+            }
+        }
+        log_trace!(self.logger, "finished calling verify_backup");
+
+        Ok(verified)
+    }
+
+    /// Returns the network of the wallet.
+    pub fn get_network(&self) -> Network {
+        self.network
+    }
+
+    /// Gets a new bitcoin address of the default address type from the wallet.
+    /// Will generate the last unused address in our bdk wallet.
+    pub fn get_new_address(&self, labels: Vec<String>) -> Result<Address, MutinyError> {
+        self.get_new_address_of_type(labels, None)
+    }
+
+    /// Gets a new bitcoin address from the wallet, of the given type
+    /// or the default address type if not set.
+    pub fn get_new_address_of_type(
+        &self,
+        labels: Vec<String>,
+        address_type: Option<AddressType>,
+    ) -> Result<Address, MutinyError> {
+        log_trace!(self.logger, "calling get_new_address");
+
+        let address_type = match address_type {
+            Some(address_type) => address_type,
+            None => self.get_default_address_type()?,
+        };
+        let address = self.wallet.get_new_address(address_type).map_err(|e| {
+            log_error!(
+                self.logger,
+                "Could not get new {address_type:?} address: {e}"
+            );
+            e
+        })?;
+        self.set_address_labels(address.clone(), labels)?;
+        log_trace!(self.logger, "finished calling get_new_address");
+
+        Ok(address)
+    }
+
+    /// The address type used for receive addresses when none is given
+    pub fn get_default_address_type(&self) -> Result<AddressType, MutinyError> {
+        Ok(self
+            .storage
+            .get_data(DEFAULT_ADDRESS_TYPE_KEY)?
+            .unwrap_or_default())
+    }
+
+    pub fn set_default_address_type(&self, address_type: AddressType) -> Result<(), MutinyError> {
+        self.storage
+            .write_data(DEFAULT_ADDRESS_TYPE_KEY.to_string(), address_type, None)
+    }
+
+    /// What new invoices put in their description by default
+    pub fn get_description_privacy(&self) -> Result<DescriptionPrivacy, MutinyError> {
+        get_description_privacy(&self.storage)
+    }
+
+    pub fn set_description_privacy(&self, privacy: DescriptionPrivacy) -> Result<(), MutinyError> {
+        set_description_privacy(&self.storage, privacy)
+    }
+
+    /// Merges small coins into one now, if fees are low enough, using the
+    /// configured consolidation settings or the defaults when not opted in.
+    /// Returns the txid, or None if there was nothing worth consolidating.
+    pub async fn consolidate_utxos(&self) -> Result<Option<Txid>, MutinyError> {
+        log_trace!(self.logger, "calling consolidate_utxos");
+        let config = self.consolidation.unwrap_or_default();
+        let res = self.wallet.consolidate_utxos(&config).await;
+        log_trace!(self.logger, "finished calling consolidate_utxos");
+
+        res
+    }
+
+    /// Whether one of our addresses has already received funds, so the user can
+    /// be warned before sharing it again
+    pub fn is_address_reused(&self, address: &Address) -> Result<bool, MutinyError> {
+        self.wallet.is_address_reused(address)
+    }
+
+    /// Signs a message with one of our on-chain addresses, as a BIP-322 simple
+    /// signature, to prove ownership of the address
+    pub fn sign_message_bip322(
+        &self,
+        address: &Address,
+        message: &str,
+    ) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling sign_message_bip322");
+
+        let res = self.wallet.sign_message_bip322(address, message);
+
+        log_trace!(self.logger, "finished calling sign_message_bip322");
+        res
+    }
+
+    /// Verifies a BIP-322 simple signature of the message for the address
+    pub fn verify_message_bip322(
+        &self,
+        address: &Address,
+        message: &str,
+        signature: &str,
+    ) -> Result<bool, MutinyError> {
+        crate::bip322::verify_message_bip322(address, message, signature)
+    }
+
+    /// Gets the public receive and change descriptors of the on-chain wallet, for
+    /// starting a watch-only wallet whose PSBTs this wallet can sign.
+    pub fn get_watch_only_descriptors(&self) -> Result<(String, String), MutinyError> {
+        get_watch_only_descriptors(self.xprivkey, self.network, 0)
+    }
+
+    /// Gets the current balance of the on-chain wallet.
+    pub fn get_wallet_balance(&self) -> Result<u64, MutinyError> {
+        log_trace!(self.logger, "calling get_wallet_balance");
+
+        if let Ok(balance) = self.wallet.balance() {
+            log_trace!(self.logger, "finished calling get_wallet_balance");
+            return Ok(balance.total().to_sat());
+        }
+
+        log_error!(
+            self.logger,
+            "Could not get wallet lock to get wallet balance"
+        );
+        Err(MutinyError::WalletOperationFailed)
+    }
+
+    /// Sends an on-chain transaction to the given address.
+    /// The amount is in satoshis and the fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// If any utxos are provided only those will be used to fund the transaction,
+    /// otherwise they are selected automatically.
+    pub async fn send_to_address(
+        &self,
+        send_to: Address,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+        utxos: Vec<OutPoint>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling send_to_address");
+        let labels = self
+            .run_send_hooks(send_to.to_string(), Some(amount), labels)
+            .await?;
+        let res = self
+            .wallet
+            .send(send_to, amount, labels, fee_rate, &utxos)
+            .await;
+        log_trace!(self.logger, "finished calling send_to_address");
+
+        res
+    }
+
+    /// Creates an unsigned PSBT sending to the given address so it can be signed
+    /// outside the wallet, e.g. by a hardware wallet. The PSBT is kept in storage
+    /// until it is broadcast.
+    pub fn create_psbt(
+        &self,
+        send_to: Address,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+        utxos: Vec<OutPoint>,
+    ) -> Result<PsbtSession, MutinyError> {
+        log_trace!(self.logger, "calling create_psbt");
+
+        let psbt = self.wallet.create_unsigned_psbt(
+            vec![(send_to.script_pubkey(), Amount::from_sat(amount))],
+            fee_rate,
+            &utxos,
+        )?;
+        let session = PsbtSession::new(&psbt, labels);
+        persist_psbt_session(&self.storage, &session)?;
+        log_trace!(self.logger, "finished calling create_psbt");
+
+        Ok(session)
+    }
+
+    /// Imports a PSBT signed elsewhere, merging its signatures into the stored
+    /// session for the same transaction or starting a new session for it.
+    pub fn import_psbt(&self, psbt: Psbt) -> Result<PsbtSession, MutinyError> {
+        log_trace!(self.logger, "calling import_psbt");
+
+        let id = psbt.unsigned_tx.compute_txid();
+        let session = match get_psbt_session(&self.storage, &id)? {
+            Some(mut session) => {
+                if session.status == PsbtStatus::Broadcast {
+                    return Err(MutinyError::InvalidArgumentsError);
+                }
+                session.combine(psbt)?;
+                session
+            }
+            None => PsbtSession::new(&psbt, vec![]),
+        };
+        persist_psbt_session(&self.storage, &session)?;
+        log_trace!(self.logger, "finished calling import_psbt");
+
+        Ok(session)
+    }
+
+    /// Imports the PSBT like [`NodeManager::import_psbt`] and adds our signatures to it.
+    pub fn sign_psbt(&self, psbt: Psbt) -> Result<PsbtSession, MutinyError> {
+        log_trace!(self.logger, "calling sign_psbt");
+
+        let mut session = self.import_psbt(psbt)?;
+        let mut psbt = session.psbt()?;
+        self.wallet.sign_psbt(&mut psbt)?;
+        session.set_psbt(&psbt);
+        persist_psbt_session(&self.storage, &session)?;
+        log_trace!(self.logger, "finished calling sign_psbt");
+
+        Ok(session)
+    }
+
+    /// Broadcasts the transaction of a fully signed PSBT session.
+    pub async fn broadcast_psbt(&self, id: Txid) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling broadcast_psbt");
+
+        let mut session = get_psbt_session(&self.storage, &id)?.ok_or(MutinyError::NotFound)?;
+        if session.status != PsbtStatus::Signed {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+        let psbt = session.psbt()?;
+        self.wallet.check_inputs_spendable(&psbt.unsigned_tx)?;
+        let mut labels = session.labels.clone();
+        for output in psbt.unsigned_tx.output.iter() {
+            if self.wallet.is_mine(&output.script_pubkey)? {
+                continue;
+            }
+            let destination = Address::from_script(&output.script_pubkey, self.network)
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            labels = self
+                .run_send_hooks(destination, Some(output.value.to_sat()), labels)
+                .await?;
+        }
+        let txid = self.wallet.broadcast_psbt(psbt, labels).await?;
+        session.status = PsbtStatus::Broadcast;
+        session.updated_at = utils::now().as_secs();
+        persist_psbt_session(&self.storage, &session)?;
+        log_trace!(self.logger, "finished calling broadcast_psbt");
+
+        Ok(txid)
+    }
+
+    /// Gets a PSBT session by the txid of its transaction.
+    pub fn get_psbt_session(&self, id: &Txid) -> Result<Option<PsbtSession>, MutinyError> {
+        get_psbt_session(&self.storage, id)
+    }
+
+    /// Lists all PSBT sessions, most recently updated first.
+    pub fn list_psbt_sessions(&self) -> Result<Vec<PsbtSession>, MutinyError> {
+        list_psbt_sessions(&self.storage)
+    }
+
+    /// Sends to several addresses in a single transaction, each recipient is
+    /// an address, an amount in sats and the labels for that payment.
+    /// The fee rate is in sat/vbyte.
+    pub async fn send_batch(
+        &self,
+        recipients: Vec<(Address, u64, Vec<String>)>,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling send_batch");
+        // a veto on any of the recipients stops the whole batch
+        let mut hooked = Vec::with_capacity(recipients.len());
+        for (address, amount, labels) in recipients {
+            let labels = self
+                .run_send_hooks(address.to_string(), Some(amount), labels)
+                .await?;
+            hooked.push((address, amount, labels));
+        }
+        let res = self.wallet.send_batch(hooked, fee_rate).await;
+        log_trace!(self.logger, "finished calling send_batch");
+
+        res
+    }
+
+    /// Sweeps all the funds from the wallet to the given address.
+    /// The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    pub async fn sweep_wallet(
+        &self,
+        send_to: Address,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+        allow_dust: Option<bool>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling sweep_wallet");
+        let labels = self
+            .run_send_hooks(send_to.to_string(), None, labels)
+            .await?;
+        let res = self
+            .wallet
+            .sweep(send_to, labels, fee_rate, allow_dust)
+            .await;
+        log_trace!(self.logger, "calling sweep_wallet");
+
+        res
+    }
+
+    /// Sweeps all the coins held by a WIF encoded private key, such as a
+    /// paper wallet, into this wallet. The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    pub async fn sweep_private_key(
+        &self,
+        wif: String,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling sweep_private_key");
+
+        let key =
+            PrivateKey::from_wif(wif.trim()).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        let res = self.wallet.sweep_private_key(key, fee_rate).await;
+        log_trace!(self.logger, "finished calling sweep_private_key");
+
+        res
     }
 
     pub fn construct_sweep_tx(
@@ -850,6 +2132,18 @@ impl<S: MutinyStorage> NodeManager<S> {
         res
     }
 
+    /// Speeds up an unconfirmed transaction paying us by spending our output
+    /// in a child transaction (CPFP), so the parent and child together pay
+    /// the given fee rate in sats/vbyte.
+    pub async fn accelerate_tx(&self, txid: Txid, fee_rate: u64) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling accelerate_tx");
+
+        let res = self.wallet.accelerate_tx(txid, fee_rate).await;
+        log_trace!(self.logger, "finished calling accelerate_tx");
+
+        res
+    }
+
     /// Checks if the given address has any transactions.
     /// If it does, it returns the details of the first transaction.
     ///
@@ -897,6 +2191,8 @@ impl<S: MutinyStorage> NodeManager<S> {
                 fee: None,
                 confirmation_time,
                 labels,
+                replaces: vec![],
+                address_reused: false,
             };
 
             let block_id = match tx.status.block_hash {
@@ -963,97 +2259,639 @@ impl<S: MutinyStorage> NodeManager<S> {
             })
             .unwrap_or_default();
 
-        tx
+        tx
+    }
+
+    /// Lists all the on-chain transactions in the wallet.
+    /// These are sorted by confirmation time.
+    pub fn list_onchain(&self) -> Result<Vec<TransactionDetails>, MutinyError> {
+        log_trace!(self.logger, "calling list_onchain");
+
+        let mut txs = self.wallet.list_transactions(true)?;
+        txs.sort();
+        let address_labels = self.get_address_labels()?;
+        let txs = txs
+            .into_iter()
+            .map(|tx| self.add_onchain_labels(&address_labels, tx))
+            .collect();
+
+        log_trace!(self.logger, "finished calling list_onchain");
+        Ok(txs)
+    }
+
+    /// Gets the details of a specific on-chain transaction.
+    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
+        log_trace!(self.logger, "calling get_transaction");
+
+        let res = match self.wallet.get_transaction(txid)? {
+            Some(tx) => {
+                let address_labels = self.get_address_labels()?;
+                let tx_details = self.add_onchain_labels(&address_labels, tx);
+                Ok(Some(tx_details))
+            }
+            None => Ok(None),
+        };
+        log_trace!(self.logger, "finished calling get_transaction");
+
+        res
+    }
+
+    /// Gets the current balance of the wallet.
+    /// This includes both on-chain and lightning funds.
+    ///
+    /// This will not include any funds in an unconfirmed lightning channel.
+    pub(crate) async fn get_balance(&self) -> Result<NodeBalance, MutinyError> {
+        log_trace!(self.logger, "calling get_balance");
+
+        let onchain = if let Ok(wallet) = self.wallet.wallet.try_read() {
+            log_debug!(
+                self.logger,
+                "Calling get_balance, the tx_graph: ({:?})",
+                wallet.tx_graph()
+            );
+            drop(wallet);
+            self.wallet.balance()?
+        } else {
+            log_error!(self.logger, "Could not get wallet lock to get balance");
+            return Err(MutinyError::WalletOperationFailed);
+        };
+
+        let nodes = self.nodes.read().await;
+        let lightning_sats: u64 = nodes
+            .iter()
+            .flat_map(|(_, n)| n.chain_monitor.get_claimable_balances(&[]))
+            .map(|b| b.claimable_amount_satoshis())
+            .sum::<u64>();
+
+        // get the amount closing
+        let closing: u64 = nodes
+            .iter()
+            .flat_map(|(_, n)| {
+                let channels = n.channel_manager.list_channels();
+                let ignored_channels: Vec<&ChannelDetails> = channels.iter().collect();
+                n.chain_monitor.get_claimable_balances(&ignored_channels)
+            })
+            .map(|b| b.claimable_amount_satoshis())
+            .sum();
+
+        log_trace!(self.logger, "finished calling get_balance");
+
+        Ok(NodeBalance {
+            confirmed: (onchain.confirmed + onchain.trusted_pending).to_sat(),
+            unconfirmed: (onchain.untrusted_pending + onchain.immature).to_sat(),
+            lightning: lightning_sats.saturating_sub(closing),
+            closing,
+        })
+    }
+
+    /// Gets the lightning balance of each node
+    pub(crate) async fn get_node_balances(&self) -> Vec<NodeLightningBalance> {
+        log_trace!(self.logger, "calling get_node_balances");
+
+        let nodes = self.nodes.read().await;
+        let res = nodes
+            .values()
+            .map(|n| {
+                let channels = n.channel_manager.list_channels();
+
+                let total: u64 = n
+                    .chain_monitor
+                    .get_claimable_balances(&[])
+                    .iter()
+                    .map(|b| b.claimable_amount_satoshis())
+                    .sum();
+                let ignored_channels: Vec<&ChannelDetails> = channels.iter().collect();
+                let closing: u64 = n
+                    .chain_monitor
+                    .get_claimable_balances(&ignored_channels)
+                    .iter()
+                    .map(|b| b.claimable_amount_satoshis())
+                    .sum();
+
+                let reserved = channels
+                    .iter()
+                    .filter_map(|c| c.unspendable_punishment_reserve)
+                    .sum();
+                // the next htlc limit also takes out the commitment fee and
+                // dust exposure, outbound capacity alone overstates it
+                let spendable = channels
+                    .iter()
+                    .filter(|c| c.is_usable)
+                    .map(|c| c.next_outbound_htlc_limit_msat / 1_000)
+                    .sum();
+
+                NodeLightningBalance {
+                    node_pubkey: n.pubkey,
+                    lightning: total.saturating_sub(closing),
+                    closing,
+                    reserved,
+                    spendable,
+                }
+            })
+            .collect();
+        log_trace!(self.logger, "finished calling get_node_balances");
+
+        res
+    }
+
+    /// Lists the change outputs that inherited labels from the coins they were made from
+    pub fn list_label_lineage(&self) -> Result<Vec<LabelLineage>, MutinyError> {
+        log_trace!(self.logger, "calling list_label_lineage");
+        let res = list_label_lineage(&self.storage);
+        log_trace!(self.logger, "finished calling list_label_lineage");
+
+        res
+    }
+
+    /// Replaces the labels a change address inherited, for when the
+    /// labels it got from the spent coins don't apply to it.
+    pub fn correct_label_lineage(
+        &self,
+        address: Address,
+        labels: Vec<String>,
+    ) -> Result<LabelLineage, MutinyError> {
+        log_trace!(self.logger, "calling correct_label_lineage");
+
+        let mut lineage =
+            get_label_lineage(&self.storage, &address.to_string())?.ok_or(MutinyError::NotFound)?;
+        self.storage.set_address_labels(address, labels)?;
+        lineage.corrected = true;
+        persist_label_lineage(&self.storage, &lineage)?;
+
+        log_trace!(self.logger, "finished calling correct_label_lineage");
+
+        Ok(lineage)
+    }
+
+    /// Exports the on-chain labels as BIP-329 JSON lines, for use in other
+    /// wallets like Sparrow. Addresses and transactions get their labels,
+    /// unspent outputs their address's labels and whether they're frozen.
+    pub fn export_bip329_labels(&self) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling export_bip329_labels");
+
+        let address_labels = self.get_address_labels()?;
+        let mut records = vec![];
+        for tx in self.list_onchain()? {
+            if let (Some(txid), false) = (tx.txid, tx.labels.is_empty()) {
+                records.push(Bip329Label::new("tx", txid.to_string(), &tx.labels));
+            }
+        }
+        let mut addresses = address_labels
+            .iter()
+            .filter(|(_, labels)| !labels.is_empty())
+            .collect::<Vec<_>>();
+        addresses.sort();
+        for (address, labels) in addresses {
+            records.push(Bip329Label::new("addr", address.clone(), labels));
+        }
+        let frozen = self.wallet.get_frozen_utxos()?;
+        for utxo in self.wallet.list_utxos()? {
+            let labels = Address::from_script(&utxo.txout.script_pubkey, self.network)
+                .ok()
+                .and_then(|a| address_labels.get(&a.to_string()).cloned())
+                .unwrap_or_default();
+            let mut record = Bip329Label::new("output", utxo.outpoint.to_string(), &labels);
+            record.spendable = Some(!frozen.contains(&utxo.outpoint));
+            records.push(record);
+        }
+        let res = to_bip329_jsonl(&records);
+
+        log_trace!(self.logger, "finished calling export_bip329_labels");
+        res
+    }
+
+    /// Imports BIP-329 JSON lines from another wallet. Labels are added to the
+    /// ones we already have. Transaction labels go on our addresses in that
+    /// transaction and output labels on the output's address, an output that
+    /// isn't spendable is frozen. Lightning has no BIP-329 type so invoice
+    /// labels are untouched, other record types are skipped.
+    pub fn import_bip329_labels(&self, jsonl: &str) -> Result<Bip329ImportSummary, MutinyError> {
+        log_trace!(self.logger, "calling import_bip329_labels");
+
+        let records = parse_bip329_jsonl(jsonl)?;
+        let utxos = self.wallet.list_utxos()?;
+        let mut summary = Bip329ImportSummary::default();
+        for record in records {
+            let labels = record.labels();
+            let mut scripts = vec![];
+            match record.kind.as_str() {
+                "addr" => {
+                    let address = record
+                        .reference
+                        .parse::<Address<NetworkUnchecked>>()
+                        .ok()
+                        .and_then(|a| a.require_network(self.network).ok());
+                    if let Some(address) = address {
+                        scripts.push(address.script_pubkey());
+                    }
+                }
+                "tx" => {
+                    let tx = record
+                        .reference
+                        .parse::<Txid>()
+                        .ok()
+                        .and_then(|txid| self.wallet.get_transaction(txid).transpose())
+                        .transpose()?;
+                    if let Some(transaction) = tx.and_then(|t| t.transaction) {
+                        for output in transaction.output {
+                            if self.wallet.is_mine(&output.script_pubkey)? {
+                                scripts.push(output.script_pubkey);
+                            }
+                        }
+                    }
+                }
+                "output" => {
+                    let utxo = record
+                        .reference
+                        .parse::<OutPoint>()
+                        .ok()
+                        .and_then(|o| utxos.iter().find(|u| u.outpoint == o));
+                    if let Some(utxo) = utxo {
+                        match record.spendable {
+                            Some(false) => self.wallet.freeze_utxo(utxo.outpoint)?,
+                            Some(true) => self.wallet.unfreeze_utxo(utxo.outpoint)?,
+                            None => {}
+                        }
+                        scripts.push(utxo.txout.script_pubkey.clone());
+                    }
+                }
+                _ => {}
+            }
+
+            if scripts.is_empty() {
+                summary.skipped += 1;
+                continue;
+            }
+            for script in scripts {
+                let Ok(address) = Address::from_script(&script, self.network) else {
+                    continue;
+                };
+                let mut merged = self
+                    .get_address_labels()?
+                    .get(&address.to_string())
+                    .cloned()
+                    .unwrap_or_default();
+                for label in labels.iter() {
+                    if !merged.contains(label) {
+                        merged.push(label.clone());
+                    }
+                }
+                self.set_address_labels(address, merged)?;
+            }
+            summary.imported += 1;
+        }
+
+        log_trace!(self.logger, "finished calling import_bip329_labels");
+        Ok(summary)
+    }
+
+    /// The unfrozen UTXOs whose address has any of the given labels,
+    /// for spending only the coins from a particular source.
+    pub fn utxos_with_labels(&self, labels: &[String]) -> Result<Vec<OutPoint>, MutinyError> {
+        log_trace!(self.logger, "calling utxos_with_labels");
+
+        let utxos = self
+            .list_utxos()?
+            .into_iter()
+            .filter(|u| !u.frozen && u.labels.iter().any(|l| labels.contains(l)))
+            .map(|u| u.outpoint)
+            .collect();
+        log_trace!(self.logger, "finished calling utxos_with_labels");
+
+        Ok(utxos)
+    }
+
+    /// Lists all the UTXOs in the wallet.
+    pub fn list_utxos(&self) -> Result<Vec<MutinyUtxo>, MutinyError> {
+        log_trace!(self.logger, "calling list_utxos");
+
+        let tip_height = self.wallet.tip_height()?;
+        let frozen = self.wallet.get_frozen_utxos()?;
+        let address_labels = self.get_address_labels()?;
+        let utxos = self
+            .wallet
+            .list_utxos()?
+            .into_iter()
+            .map(|u| {
+                let confirmations = match u.confirmation_time {
+                    ConfirmationTime::Confirmed { height, .. } => {
+                        tip_height.saturating_sub(height) + 1
+                    }
+                    ConfirmationTime::Unconfirmed { .. } => 0,
+                };
+                let address = Address::from_script(&u.txout.script_pubkey, self.network)
+                    .ok()
+                    .map(|a| a.to_string());
+                let labels = address
+                    .as_ref()
+                    .and_then(|a| address_labels.get(a).cloned())
+                    .unwrap_or_default();
+
+                MutinyUtxo {
+                    outpoint: u.outpoint,
+                    amount_sats: u.txout.value.to_sat(),
+                    confirmations,
+                    address,
+                    labels,
+                    is_change: u.keychain == KeychainKind::Internal,
+                    frozen: frozen.contains(&u.outpoint),
+                }
+            })
+            .collect();
+        log_trace!(self.logger, "finished calling list_utxos");
+
+        Ok(utxos)
+    }
+
+    /// Freezes a UTXO so it isn't used by automatic coin selection when sending
+    /// or opening channels. It can still be spent by selecting it manually.
+    pub fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling freeze_utxo");
+        let res = self.wallet.freeze_utxo(outpoint);
+        log_trace!(self.logger, "finished calling freeze_utxo");
+
+        res
+    }
+
+    pub fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling unfreeze_utxo");
+        let res = self.wallet.unfreeze_utxo(outpoint);
+        log_trace!(self.logger, "finished calling unfreeze_utxo");
+
+        res
+    }
+
+    /// The vault's delay and the coins in it
+    pub fn get_vault(&self) -> Result<Vault, MutinyError> {
+        get_vault(&self.storage)
+    }
+
+    /// How much is in the vault, in sats
+    pub fn get_vault_balance(&self) -> Result<u64, MutinyError> {
+        let vault = get_vault(&self.storage)?;
+        Ok(self
+            .wallet
+            .list_utxos()?
+            .iter()
+            .filter(|u| vault.utxos.contains(&u.outpoint))
+            .map(|u| u.txout.value.to_sat())
+            .sum())
+    }
+
+    /// Sets how long vault withdrawals wait before they're sent, 0 turns the
+    /// vault off. A longer delay applies right away, a shorter one only once
+    /// the current delay has passed.
+    pub fn set_vault_delay(&self, delay_secs: u64) -> Result<Vault, MutinyError> {
+        let mut vault = get_vault(&self.storage)?;
+        vault.set_delay(delay_secs, utils::now().as_secs());
+        persist_vault(&self.storage, &vault)?;
+        Ok(vault)
+    }
+
+    /// Moves the amount of on-chain funds into the vault, after which they can
+    /// only be spent by a vault withdrawal.
+    pub async fn deposit_to_vault(
+        &self,
+        amount: u64,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        log_trace!(self.logger, "calling deposit_to_vault");
+
+        let mut vault = get_vault(&self.storage)?;
+        if !vault.is_enabled() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let address = self
+            .get_new_address_of_type(vec![VAULT_LABEL.to_string()], Some(AddressType::Taproot))?;
+        let spk = address.script_pubkey();
+        let psbt = self
+            .wallet
+            .create_signed_psbt_to_spk(spk.clone(), amount, fee_rate)?;
+        self.wallet
+            .label_psbt(&psbt, vec![VAULT_LABEL.to_string()])?;
+        let tx = psbt.extract_tx()?;
+        let txid = tx.compute_txid();
+        let outpoints = vault_outpoints(&tx, |script| script == &spk);
+
+        // locked before it's broadcast so it's never spendable
+        vault.utxos.extend(outpoints.iter().copied());
+        persist_vault(&self.storage, &vault)?;
+        self.wallet.broadcast_transaction(tx).await?;
+        for outpoint in outpoints {
+            self.wallet.freeze_utxo(outpoint)?;
+        }
+        log_trace!(self.logger, "finished calling deposit_to_vault");
+
+        Ok(txid)
+    }
+
+    /// Asks to send vault funds to the address, everything in the vault if there
+    /// is no amount. It's sent once the vault's delay has passed, until then it
+    /// can be cancelled with `cancel_vault_withdrawal`.
+    pub fn request_vault_withdrawal(
+        &self,
+        send_to: Address,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+    ) -> Result<VaultWithdrawal, MutinyError> {
+        log_trace!(self.logger, "calling request_vault_withdrawal");
+
+        let balance = self.get_vault_balance()?;
+        if balance == 0 || amount.is_some_and(|a| a > balance) {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let vault = get_vault(&self.storage)?;
+        let withdrawal = VaultWithdrawal::new(
+            send_to.to_string(),
+            amount,
+            fee_rate,
+            labels,
+            vault.delay_secs,
+        );
+        persist_vault_withdrawal(&self.storage, &withdrawal)?;
+        log_info!(
+            self.logger,
+            "Vault withdrawal {} will be sent at {}",
+            withdrawal.id,
+            withdrawal.available_at
+        );
+        log_trace!(self.logger, "finished calling request_vault_withdrawal");
+
+        Ok(withdrawal)
     }
 
-    /// Lists all the on-chain transactions in the wallet.
-    /// These are sorted by confirmation time.
-    pub fn list_onchain(&self) -> Result<Vec<TransactionDetails>, MutinyError> {
-        log_trace!(self.logger, "calling list_onchain");
+    /// Cancels a vault withdrawal that is still waiting out the delay
+    pub fn cancel_vault_withdrawal(&self, id: String) -> Result<VaultWithdrawal, MutinyError> {
+        let mut withdrawal =
+            get_vault_withdrawal(&self.storage, &id)?.ok_or(MutinyError::NotFound)?;
+        if withdrawal.status != VaultWithdrawalStatus::Pending {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
 
-        let mut txs = self.wallet.list_transactions(true)?;
-        txs.sort();
-        let address_labels = self.get_address_labels()?;
-        let txs = txs
-            .into_iter()
-            .map(|tx| self.add_onchain_labels(&address_labels, tx))
-            .collect();
+        withdrawal.status = VaultWithdrawalStatus::Cancelled;
+        persist_vault_withdrawal(&self.storage, &withdrawal)?;
+        Ok(withdrawal)
+    }
 
-        log_trace!(self.logger, "finished calling list_onchain");
-        Ok(txs)
+    /// Lists the vault withdrawals, newest first
+    pub fn list_vault_withdrawals(&self) -> Result<Vec<VaultWithdrawal>, MutinyError> {
+        list_vault_withdrawals(&self.storage)
     }
 
-    /// Gets the details of a specific on-chain transaction.
-    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
-        log_trace!(self.logger, "calling get_transaction");
+    /// Sends the vault withdrawals whose delay has passed and applies a shorter
+    /// vault delay once it's due. Withdrawals that fail to broadcast are retried
+    /// the next time, ones the vault can't cover are failed.
+    pub(crate) async fn process_vault_withdrawals(&self) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let mut vault = get_vault(&self.storage)?;
+        if vault.apply_pending_delay(now) {
+            persist_vault(&self.storage, &vault)?;
+        }
 
-        let res = match self.wallet.get_transaction(txid)? {
-            Some(tx) => {
-                let address_labels = self.get_address_labels()?;
-                let tx_details = self.add_onchain_labels(&address_labels, tx);
-                Ok(Some(tx_details))
+        // oldest first
+        for mut withdrawal in list_vault_withdrawals(&self.storage)?.into_iter().rev() {
+            if withdrawal.status != VaultWithdrawalStatus::Pending || withdrawal.available_at > now
+            {
+                continue;
             }
-            None => Ok(None),
-        };
-        log_trace!(self.logger, "finished calling get_transaction");
 
-        res
+            match self.send_vault_withdrawal(&withdrawal, &mut vault).await {
+                Ok(txid) => {
+                    log_info!(self.logger, "Sent vault withdrawal {}", withdrawal.id);
+                    withdrawal.status = VaultWithdrawalStatus::Completed;
+                    withdrawal.txid = Some(txid);
+                    withdrawal.failure = None;
+                }
+                Err(MutinyError::InsufficientBalance) => {
+                    withdrawal.status = VaultWithdrawalStatus::Failed;
+                    withdrawal.failure = Some(MutinyError::InsufficientBalance.to_string());
+                }
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not send vault withdrawal {}: {e}",
+                        withdrawal.id
+                    );
+                    withdrawal.failure = Some(e.to_string());
+                }
+            }
+            persist_vault_withdrawal(&self.storage, &withdrawal)?;
+        }
+
+        Ok(())
     }
 
-    /// Gets the current balance of the wallet.
-    /// This includes both on-chain and lightning funds.
-    ///
-    /// This will not include any funds in an unconfirmed lightning channel.
-    pub(crate) async fn get_balance(&self) -> Result<NodeBalance, MutinyError> {
-        log_trace!(self.logger, "calling get_balance");
+    async fn send_vault_withdrawal(
+        &self,
+        withdrawal: &VaultWithdrawal,
+        vault: &mut Vault,
+    ) -> Result<Txid, MutinyError> {
+        let spk = withdrawal
+            .address
+            .parse::<Address<NetworkUnchecked>>()?
+            .require_network(self.network)?
+            .script_pubkey();
+        let utxos = self
+            .wallet
+            .list_utxos()?
+            .into_iter()
+            .filter(|u| vault.utxos.contains(&u.outpoint))
+            .collect::<Vec<_>>();
+        let total: u64 = utxos.iter().map(|u| u.txout.value.to_sat()).sum();
+        if total == 0 || withdrawal.amount_sats.is_some_and(|a| a > total) {
+            return Err(MutinyError::InsufficientBalance);
+        }
 
-        let onchain = if let Ok(wallet) = self.wallet.wallet.try_read() {
-            log_debug!(
-                self.logger,
-                "Calling get_balance, the tx_graph: ({:?})",
-                wallet.tx_graph()
-            );
-            wallet.balance()
-        } else {
-            log_error!(self.logger, "Could not get wallet lock to get balance");
-            return Err(MutinyError::WalletOperationFailed);
-        };
+        let outpoints = utxos.iter().map(|u| u.outpoint).collect::<Vec<_>>();
+        let psbt = self.wallet.create_vault_withdrawal_psbt(
+            &outpoints,
+            spk.clone(),
+            withdrawal.amount_sats,
+            withdrawal.fee_rate,
+        )?;
+        let tx = psbt.clone().extract_tx()?;
+        self.wallet.check_allowed_tx(&tx)?;
+        self.wallet.label_psbt(&psbt, withdrawal.labels.clone())?;
+        let txid = tx.compute_txid();
+        // the change stays in the vault
+        let change = vault_outpoints(&tx, |script| {
+            script != &spk && self.wallet.is_mine(script).unwrap_or(false)
+        });
 
-        let nodes = self.nodes.read().await;
-        let lightning_sats: u64 = nodes
-            .iter()
-            .flat_map(|(_, n)| n.chain_monitor.get_claimable_balances(&[]))
-            .map(|b| b.claimable_amount_satoshis())
-            .sum::<u64>();
+        self.wallet.broadcast_transaction(tx).await?;
+        vault.utxos.retain(|o| !outpoints.contains(o));
+        vault.utxos.extend(change.iter().copied());
+        persist_vault(&self.storage, vault)?;
+        for outpoint in change {
+            self.wallet.freeze_utxo(outpoint)?;
+        }
 
-        // get the amount closing
-        let closing: u64 = nodes
-            .iter()
-            .flat_map(|(_, n)| {
-                let channels = n.channel_manager.list_channels();
-                let ignored_channels: Vec<&ChannelDetails> = channels.iter().collect();
-                n.chain_monitor.get_claimable_balances(&ignored_channels)
-            })
-            .map(|b| b.claimable_amount_satoshis())
-            .sum();
+        Ok(txid)
+    }
 
-        log_trace!(self.logger, "finished calling get_balance");
+    /// The withdrawal allowlist, with the changes still waiting out the delay
+    pub fn get_withdrawal_allowlist(&self) -> Result<WithdrawalAllowlist, MutinyError> {
+        get_withdrawal_allowlist(&self.storage, utils::now().as_secs())
+    }
 
-        Ok(NodeBalance {
-            confirmed: (onchain.confirmed + onchain.trusted_pending).to_sat(),
-            unconfirmed: (onchain.untrusted_pending + onchain.immature).to_sat(),
-            lightning: lightning_sats.saturating_sub(closing),
-            closing,
-        })
+    /// Turns on the withdrawal allowlist, after which on-chain sends and channel
+    /// closes can only pay us or an allowed destination. If it's already on this
+    /// changes the delay, a shorter one only applies once the current delay has passed.
+    pub fn enable_withdrawal_allowlist(
+        &self,
+        delay_secs: u64,
+    ) -> Result<WithdrawalAllowlist, MutinyError> {
+        let mut allowlist = self.get_withdrawal_allowlist()?;
+        allowlist.enable(delay_secs, utils::now().as_secs());
+        persist_withdrawal_allowlist(&self.storage, &allowlist)?;
+        Ok(allowlist)
     }
 
-    /// Lists all the UTXOs in the wallet.
-    pub fn list_utxos(&self) -> Result<Vec<LocalOutput>, MutinyError> {
-        log_trace!(self.logger, "calling list_utxos");
-        let res = self.wallet.list_utxos();
-        log_trace!(self.logger, "calling list_utxos");
+    /// Asks to turn off the withdrawal allowlist, which waits out the delay
+    pub fn disable_withdrawal_allowlist(&self) -> Result<WithdrawalAllowlist, MutinyError> {
+        self.change_withdrawal_allowlist(AllowlistChange::Disable)
+    }
 
-        res
+    /// Adds an address or xpub to the withdrawal allowlist once the delay has passed
+    pub fn add_allowlist_entry(
+        &self,
+        entry: AllowlistEntry,
+    ) -> Result<WithdrawalAllowlist, MutinyError> {
+        entry.validate(self.network)?;
+        self.change_withdrawal_allowlist(AllowlistChange::Add(entry))
+    }
+
+    /// Removes an address or xpub from the withdrawal allowlist right away
+    pub fn remove_allowlist_entry(
+        &self,
+        entry: AllowlistEntry,
+    ) -> Result<WithdrawalAllowlist, MutinyError> {
+        let mut allowlist = self.get_withdrawal_allowlist()?;
+        allowlist.remove(&entry);
+        persist_withdrawal_allowlist(&self.storage, &allowlist)?;
+        Ok(allowlist)
+    }
+
+    /// Drops the withdrawal allowlist changes still waiting out the delay,
+    /// e.g. ones made by someone who got into the wallet
+    pub fn cancel_pending_allowlist_changes(&self) -> Result<WithdrawalAllowlist, MutinyError> {
+        let mut allowlist = self.get_withdrawal_allowlist()?;
+        allowlist.cancel_pending();
+        persist_withdrawal_allowlist(&self.storage, &allowlist)?;
+        Ok(allowlist)
+    }
+
+    fn change_withdrawal_allowlist(
+        &self,
+        change: AllowlistChange,
+    ) -> Result<WithdrawalAllowlist, MutinyError> {
+        let mut allowlist = self.get_withdrawal_allowlist()?;
+        allowlist.request(change, utils::now().as_secs());
+        persist_withdrawal_allowlist(&self.storage, &allowlist)?;
+        Ok(allowlist)
     }
 
     /// Syncs the lightning wallet with the blockchain.
@@ -1129,6 +2967,10 @@ impl<S: MutinyStorage> NodeManager<S> {
                     &self.logger,
                 )
                 .await?;
+
+                if let Err(e) = self.cache_peer_node_info().await {
+                    log_warn!(self.logger, "Failed to cache peer node info: {e}");
+                }
             }
         }
 
@@ -1136,6 +2978,73 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(())
     }
 
+    /// Keeps what the network graph knows about our peers and channel
+    /// counterparties, so they can be shown by alias before the next session's
+    /// graph is ready.
+    async fn cache_peer_node_info(&self) -> Result<(), MutinyError> {
+        let mut node_ids: Vec<NodeId> = gossip::get_all_peers(&self.storage)?.into_keys().collect();
+        let nodes = self.nodes.read().await;
+        for node in nodes.values() {
+            for channel in node.channel_manager.list_channels() {
+                node_ids.push(NodeId::from_pubkey(&channel.counterparty.node_id));
+            }
+        }
+        drop(nodes);
+        node_ids.sort();
+        node_ids.dedup();
+
+        let updated = cache_node_info(&self.storage, self.gossip_sync.network_graph(), &node_ids)?;
+        log_debug!(self.logger, "Updated cached info for {updated} nodes");
+
+        Ok(())
+    }
+
+    fn persist_scorer(&self) -> Result<(), MutinyError> {
+        let scorer = self
+            .scorer
+            .try_lock()
+            .map_err(|_| MutinyError::WalletSyncError)?;
+        gossip::persist_scorer(&self.storage, &scorer)
+    }
+
+    /// Exports the pathfinding scorer as hex, so it can be imported
+    /// into another wallet with [`NodeManager::import_scorer`].
+    pub fn export_scorer(&self) -> Result<String, MutinyError> {
+        log_trace!(self.logger, "calling export_scorer");
+
+        let scorer = self
+            .scorer
+            .try_lock()
+            .map_err(|_| MutinyError::WalletSyncError)?;
+        let res = gossip::encode_scorer(&scorer);
+
+        log_trace!(self.logger, "finished calling export_scorer");
+        Ok(res)
+    }
+
+    /// Replaces the pathfinding scorer with one exported from another wallet
+    /// and saves it, so a fresh wallet doesn't have to relearn the network.
+    pub fn import_scorer(&self, scorer_hex: &str) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling import_scorer");
+
+        let scorer = gossip::decode_scorer(
+            scorer_hex.trim(),
+            self.gossip_sync.network_graph().clone(),
+            self.logger.clone(),
+        )
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        gossip::persist_scorer(&self.storage, &scorer)?;
+
+        let mut lock = self
+            .scorer
+            .try_lock()
+            .map_err(|_| MutinyError::WalletSyncError)?;
+        *lock = scorer;
+
+        log_trace!(self.logger, "finished calling import_scorer");
+        Ok(())
+    }
+
     /// Downloads the latest score data from the server and replaces the current scorer.
     /// Will be skipped if in safe mode.
     async fn sync_scorer(&self) -> Result<(), MutinyError> {
@@ -1242,6 +3151,50 @@ impl<S: MutinyStorage> NodeManager<S> {
         res
     }
 
+    /// Fee rates seen over the past week, oldest first. Rates are in sat/vbyte.
+    pub fn get_fee_history(&self) -> Result<Vec<FeeRateSample>, MutinyError> {
+        log_trace!(self.logger, "calling get_fee_history");
+        let res = self.fee_estimator.get_fee_history();
+        log_trace!(self.logger, "finished calling get_fee_history");
+
+        res
+    }
+
+    /// Suggests whether to send the given amount on-chain now or wait for
+    /// lower fees, based on the fee rates seen over the past week.
+    pub fn suggest_send_timing(&self, amount: u64) -> Result<SendTimingSuggestion, MutinyError> {
+        log_trace!(self.logger, "calling suggest_send_timing");
+
+        // estimate how many of our coins the send would need, biggest first
+        let frozen = self.wallet.get_frozen_utxos()?;
+        let mut values = self
+            .wallet
+            .list_utxos()?
+            .into_iter()
+            .filter(|u| !frozen.contains(&u.outpoint))
+            .map(|u| u.txout.value.to_sat())
+            .collect::<Vec<_>>();
+        values.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut total = 0;
+        let mut num_utxos = 0;
+        for value in values {
+            if total >= amount {
+                break;
+            }
+            total += value;
+            num_utxos += 1;
+        }
+        if total < amount {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let res = self.fee_estimator.suggest_send_timing(num_utxos.max(1));
+        log_trace!(self.logger, "finished calling suggest_send_timing");
+
+        res
+    }
+
     /// Creates a new lightning node and adds it to the manager.
     pub async fn new_node(&self) -> Result<NodeIdentity, MutinyError> {
         log_trace!(self.logger, "calling new_node");
@@ -1291,73 +3244,377 @@ impl<S: MutinyStorage> NodeManager<S> {
                 // Check that we did override the previous node index
                 debug_assert!(prev.is_some());
 
-                Ok(())
+                Ok(())
+            }
+        }
+    }
+
+    /// Lists the pubkeys of the lightning node in the manager.
+    pub async fn list_nodes(&self) -> Result<Vec<PublicKey>, MutinyError> {
+        log_trace!(self.logger, "calling list_nodes");
+
+        let nodes = self.nodes.read().await;
+        let peers = nodes.iter().map(|(_, n)| n.pubkey).collect();
+
+        log_trace!(self.logger, "finished calling list_nodes");
+        Ok(peers)
+    }
+
+    pub async fn get_configured_lsp(&self) -> Result<Option<LspConfig>, MutinyError> {
+        let node = self.get_node_by_key_or_first(None).await?;
+        Ok(node.node_index().await.lsp)
+    }
+
+    /// Changes all the node's LSPs to the given config. If any of the nodes have an active channel with the
+    /// current LSP, it will fail to change the LSP.
+    ///
+    /// Requires a restart of the node manager to take effect.
+    pub async fn change_lsp(&self, mut lsp_config: Option<LspConfig>) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling change_lsp");
+
+        // if we are in safe mode we don't load the lightning state so we can't know if it is safe to change the LSP.
+        if self.safe_mode {
+            return Err(MutinyError::NotRunning);
+        }
+
+        // verify that the LSP config is valid
+        match lsp_config.as_mut() {
+            Some(LspConfig::VoltageFlow(config)) => {
+                let http_client = Client::new();
+
+                // try to connect to the LSP, update the config if successful
+                let (pk, str) = voltage::LspClient::fetch_connection_info(
+                    &http_client,
+                    &config.url,
+                    &self.logger,
+                )
+                .await?;
+                config.pubkey = Some(pk);
+                config.connection_string = Some(str);
+            }
+            Some(LspConfig::Lsps(config)) => {
+                // make sure a valid connection string was provided
+                PubkeyConnectionInfo::new(&config.connection_string)?;
+            }
+            None => {} // Nothing to verify
+        }
+
+        self.save_lsp_config(lsp_config).await?;
+        log_trace!(self.logger, "finished calling change_lsp");
+
+        Ok(())
+    }
+
+    /// Sets the LSP of every node, used once the wallet is restarted
+    async fn save_lsp_config(&self, lsp_config: Option<LspConfig>) -> Result<(), MutinyError> {
+        // edit node storage
+        let mut node_storage = self.node_storage.write().await;
+        node_storage.nodes.iter_mut().for_each(|(_, n)| {
+            n.lsp = lsp_config.clone();
+        });
+        node_storage.version += 1; // update version for VSS
+
+        // save updated lsp to storage
+        self.storage.insert_nodes(&node_storage)
+    }
+
+    /// The LSPs the wallet fails over between and the fee it accepts from them
+    pub fn get_lsp_list(&self) -> Result<LspList, MutinyError> {
+        get_lsp_list(&self.storage)
+    }
+
+    /// Sets the LSPs to use in order of preference. When the LSP in use can't be
+    /// reached or quotes a fee over the limit the wallet switches to the next one
+    /// and sends an `LspFailover` event.
+    ///
+    /// The first LSP is used when the wallet isn't configured with one. To switch
+    /// to it right away use `change_lsp`.
+    pub fn set_lsp_list(&self, list: LspList) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling set_lsp_list");
+
+        // the backups may well be down right now, only check the configs are usable
+        for lsp in list.lsps.iter() {
+            match lsp {
+                LspConfig::VoltageFlow(config) => {
+                    Url::parse(&config.url).map_err(|_| MutinyError::InvalidArgumentsError)?;
+                }
+                LspConfig::Lsps(config) => {
+                    PubkeyConnectionInfo::new(&config.connection_string)?;
+                }
+            }
+        }
+        persist_lsp_list(&self.storage, &list)?;
+
+        log_trace!(self.logger, "finished calling set_lsp_list");
+        Ok(())
+    }
+
+    /// Moves on from the LSP in use to the next one in the LSP list. The LSP
+    /// client can't be swapped while running, so like `change_lsp` the new LSP
+    /// is saved and used from the next start, the app is told so it can restart.
+    /// Returns false if there is no other LSP to move to.
+    async fn fail_over_lsp(
+        &self,
+        current: LspConfig,
+        reason: LspFailoverReason,
+    ) -> Result<bool, MutinyError> {
+        if self.safe_mode {
+            return Ok(false);
+        }
+
+        let list = get_lsp_list(&self.storage)?;
+        let Some(next) = list.next_after(&current).cloned() else {
+            return Ok(false);
+        };
+
+        // already switched since we started, don't tell the app again
+        let saved = self.node_storage.read().await;
+        if saved
+            .nodes
+            .values()
+            .all(|n| n.lsp.as_ref().is_some_and(|l| l.matches(&next)))
+        {
+            return Ok(false);
+        }
+        drop(saved);
+
+        self.save_lsp_config(Some(next.clone())).await?;
+        log_warn!(
+            self.logger,
+            "LSP {current:?} failed ({reason:?}), switching to {next:?}"
+        );
+
+        if let Some(cb) = self.ln_event_callback.as_ref() {
+            cb.trigger(CommonLnEvent::LspFailover {
+                previous: current,
+                next,
+                reason,
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Gets the channels an LSPS1 server sells
+    pub async fn get_lsps1_info(&self, lsp_url: &str) -> Result<Lsps1Info, MutinyError> {
+        Lsps1Client::new(lsp_url, self.logger.clone())
+            .get_info()
+            .await
+    }
+
+    /// Orders an inbound channel with `lsp_balance_sat` of inbound liquidity from an
+    /// LSPS1 server. The order still has to be paid with `pay_channel_purchase`.
+    pub async fn buy_inbound_channel(
+        &self,
+        lsp_url: &str,
+        lsp_balance_sat: u64,
+        channel_expiry_blocks: Option<u32>,
+        token: Option<String>,
+    ) -> Result<ChannelPurchase, MutinyError> {
+        log_trace!(self.logger, "calling buy_inbound_channel");
+
+        let client = Lsps1Client::new(lsp_url, self.logger.clone());
+        let info = client.get_info().await?;
+        info.options.check_lsp_balance(lsp_balance_sat)?;
+
+        // the LSP opens the channel to us, so we need to be connected
+        let node = self.get_node_by_key_or_first(None).await?;
+        if !info.uris.is_empty() {
+            let mut connected = false;
+            for uri in info.uris.iter() {
+                if self
+                    .connect_to_peer(Some(&node.pubkey), uri, None)
+                    .await
+                    .is_ok()
+                {
+                    connected = true;
+                    break;
+                }
+            }
+            if !connected {
+                return Err(MutinyError::LspConnectionError);
             }
         }
+
+        let request = CreateOrderRequest {
+            lsp_balance_sat,
+            client_balance_sat: 0,
+            required_channel_confirmations: info.options.min_required_channel_confirmations,
+            funding_confirms_within_blocks: info.options.min_funding_confirms_within_blocks,
+            channel_expiry_blocks: info.options.channel_expiry_blocks(channel_expiry_blocks),
+            token,
+            refund_onchain_address: self.get_new_address(vec![])?.to_string(),
+            announce_channel: false,
+            public_key: node.pubkey.to_string(),
+        };
+        let order = client.create_order(&request).await?;
+
+        let purchase = ChannelPurchase {
+            order,
+            lsp_url: lsp_url.to_string(),
+            paid_with: None,
+            confirmed_at: None,
+            created_at: utils::now().as_secs(),
+        };
+        persist_channel_purchase(&self.storage, &purchase)?;
+        log_info!(
+            self.logger,
+            "Created LSPS1 order {} for {lsp_balance_sat} sats",
+            purchase.order.order_id
+        );
+        log_trace!(self.logger, "finished calling buy_inbound_channel");
+
+        Ok(purchase)
     }
 
-    /// Lists the pubkeys of the lightning node in the manager.
-    pub async fn list_nodes(&self) -> Result<Vec<PublicKey>, MutinyError> {
-        log_trace!(self.logger, "calling list_nodes");
+    /// Pays for a channel order, over lightning or on-chain. The fee rate is
+    /// only used for on-chain payments.
+    pub async fn pay_channel_purchase(
+        &self,
+        order_id: &str,
+        onchain: bool,
+        fee_rate: Option<u64>,
+    ) -> Result<ChannelPurchase, MutinyError> {
+        log_trace!(self.logger, "calling pay_channel_purchase");
 
-        let nodes = self.nodes.read().await;
-        let peers = nodes.iter().map(|(_, n)| n.pubkey).collect();
+        let mut purchase =
+            get_channel_purchase(&self.storage, order_id)?.ok_or(MutinyError::NotFound)?;
+        if purchase.paid_with.is_some() || purchase.order.order_state != OrderState::Created {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
 
-        log_trace!(self.logger, "finished calling list_nodes");
-        Ok(peers)
+        let labels = vec![purchase.label()];
+        let paid_with = if onchain {
+            let payment = purchase
+                .order
+                .payment
+                .onchain
+                .as_ref()
+                .ok_or(MutinyError::InvalidArgumentsError)?;
+            let address = Address::from_str(&payment.address)?.require_network(self.network)?;
+            let txid = self
+                .send_to_address(address, payment.order_total_sat, labels, fee_rate, vec![])
+                .await?;
+            PurchasePayment::OnChain { txid }
+        } else {
+            let payment = purchase
+                .order
+                .payment
+                .bolt11
+                .as_ref()
+                .ok_or(MutinyError::InvalidArgumentsError)?;
+            let invoice = Bolt11Invoice::from_str(&payment.invoice)?;
+            // don't pay more than the order says it costs
+            if invoice.amount_milli_satoshis() != Some(payment.order_total_sat * 1_000) {
+                log_error!(
+                    self.logger,
+                    "LSPS1 invoice amount doesn't match the order total of {} sats",
+                    payment.order_total_sat
+                );
+                return Err(MutinyError::LspGenericError);
+            }
+            let paid = self
+                .pay_invoice(
+                    None,
+                    &invoice,
+                    None,
+                    MppConfig::default(),
+                    PaymentPolicy::default(),
+                    labels,
+                )
+                .await?;
+            PurchasePayment::Lightning {
+                payment_hash: paid.payment_hash.to_string(),
+            }
+        };
+
+        purchase.paid_with = Some(paid_with);
+        persist_channel_purchase(&self.storage, &purchase)?;
+        log_trace!(self.logger, "finished calling pay_channel_purchase");
+
+        Ok(purchase)
     }
 
-    pub async fn get_configured_lsp(&self) -> Result<Option<LspConfig>, MutinyError> {
-        let node = self.get_node_by_key_or_first(None).await?;
-        Ok(node.node_index().await.lsp)
+    /// Gets a channel order, with its latest state from the LSP while it is pending
+    pub async fn get_channel_purchase(
+        &self,
+        order_id: &str,
+    ) -> Result<Option<ChannelPurchase>, MutinyError> {
+        let Some(purchase) = get_channel_purchase(&self.storage, order_id)? else {
+            return Ok(None);
+        };
+        if !purchase.is_pending() {
+            return Ok(Some(purchase));
+        }
+
+        let channels = self.list_channels().await?;
+        self.refresh_channel_purchase(purchase, &channels)
+            .await
+            .map(Some)
     }
 
-    /// Changes all the node's LSPs to the given config. If any of the nodes have an active channel with the
-    /// current LSP, it will fail to change the LSP.
-    ///
-    /// Requires a restart of the node manager to take effect.
-    pub async fn change_lsp(&self, mut lsp_config: Option<LspConfig>) -> Result<(), MutinyError> {
-        log_trace!(self.logger, "calling change_lsp");
+    /// Lists the channel orders, newest first
+    pub fn list_channel_purchases(&self) -> Result<Vec<ChannelPurchase>, MutinyError> {
+        list_channel_purchases(&self.storage)
+    }
 
-        // if we are in safe mode we don't load the lightning state so we can't know if it is safe to change the LSP.
-        if self.safe_mode {
-            return Err(MutinyError::NotRunning);
+    /// Checks the pending channel orders with their LSPs
+    pub(crate) async fn refresh_channel_purchases(&self) -> Result<(), MutinyError> {
+        let pending: Vec<ChannelPurchase> = list_channel_purchases(&self.storage)?
+            .into_iter()
+            .filter(|p| p.is_pending() && p.paid_with.is_some())
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
         }
 
-        // verify that the LSP config is valid
-        match lsp_config.as_mut() {
-            Some(LspConfig::VoltageFlow(config)) => {
-                let http_client = Client::new();
-
-                // try to connect to the LSP, update the config if successful
-                let (pk, str) = voltage::LspClient::fetch_connection_info(
-                    &http_client,
-                    &config.url,
-                    &self.logger,
-                )
-                .await?;
-                config.pubkey = Some(pk);
-                config.connection_string = Some(str);
-            }
-            Some(LspConfig::Lsps(config)) => {
-                // make sure a valid connection string was provided
-                PubkeyConnectionInfo::new(&config.connection_string)?;
+        let channels = self.list_channels().await?;
+        for purchase in pending {
+            let order_id = purchase.order.order_id.clone();
+            if let Err(e) = self.refresh_channel_purchase(purchase, &channels).await {
+                log_warn!(self.logger, "Failed to refresh LSPS1 order {order_id}: {e}");
             }
-            None => {} // Nothing to verify
         }
 
-        // edit node storage
-        let mut node_storage = self.node_storage.write().await;
-        node_storage.nodes.iter_mut().for_each(|(_, n)| {
-            n.lsp = lsp_config.clone();
-        });
-        node_storage.version += 1; // update version for VSS
+        Ok(())
+    }
 
-        // save updated lsp to storage
-        self.storage.insert_nodes(&node_storage)?;
-        log_trace!(self.logger, "finished calling change_lsp");
+    /// Updates the order from the LSP and marks it confirmed once our channel
+    /// from it has a confirmation, which adds it to the activity
+    async fn refresh_channel_purchase(
+        &self,
+        mut purchase: ChannelPurchase,
+        channels: &[MutinyChannel],
+    ) -> Result<ChannelPurchase, MutinyError> {
+        let order = Lsps1Client::new(&purchase.lsp_url, self.logger.clone())
+            .get_order(&purchase.order.order_id)
+            .await?;
+        purchase.order = order;
 
-        Ok(())
+        let outpoint = purchase.order.channel.as_ref().and_then(|c| c.outpoint());
+        let confirmed = outpoint.is_some_and(|outpoint| {
+            channels
+                .iter()
+                .any(|c| c.outpoint == Some(outpoint) && c.confirmations > 0)
+        });
+        if confirmed {
+            let now = utils::now().as_secs();
+            purchase.confirmed_at = Some(now);
+            let index = self.storage.activity_index();
+            let mut index = index.try_write()?;
+            index.insert(IndexItem {
+                timestamp: Some(now),
+                key: format!("{CHANNEL_PURCHASE_PREFIX}{}", purchase.order.order_id),
+            });
+            log_info!(
+                self.logger,
+                "Channel from LSPS1 order {} confirmed",
+                purchase.order.order_id
+            );
+        }
+        persist_channel_purchase(&self.storage, &purchase)?;
+
+        Ok(purchase)
     }
 
     /// Attempts to connect to a peer using either a specified node or the first available node.
@@ -1435,99 +3692,479 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// Creates a lightning invoice. The amount should be in satoshis.
     /// If no description is provided, the invoice will be created with no description.
     ///
-    /// If the manager has more than one node it will create a phantom invoice.
-    /// If there is only one node it will create an invoice just for that node.
+    /// If the manager has more than one node it will create a phantom invoice
+    /// that any of them can claim, with route hints for the nodes that can
+    /// receive the whole amount since a payment can't be split across nodes.
+    /// If only one node can, the invoice is just for that node.
+    ///
+    /// Without a description privacy setting the wallet's default is used.
     pub async fn create_invoice(
         &self,
         amount: u64,
         labels: Vec<String>,
         expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
     ) -> Result<(MutinyInvoice, u64), MutinyError> {
         log_trace!(self.logger, "calling create_invoice");
 
         let nodes = self.nodes.read().await;
-        let use_phantom = nodes.len() > 1 && self.lsp_config.is_none();
-        if nodes.len() == 0 {
+        let Some(first_node) = nodes.values().next() else {
             return Err(MutinyError::InvoiceCreationFailed);
-        }
-        let route_hints: Option<Vec<PhantomRouteHints>> = if use_phantom {
-            Some(
-                nodes
-                    .iter()
-                    .map(|(_, n)| n.get_phantom_route_hint())
-                    .collect(),
-            )
+        };
+
+        let (node, route_hints) = if nodes.len() > 1 && self.lsp_config.is_none() {
+            let inbound: Vec<(PublicKey, u64)> = nodes
+                .iter()
+                .map(|(pk, n)| (*pk, n.get_inbound_capacity_msat()))
+                .collect();
+            let amount_msat = amount
+                .checked_mul(1_000)
+                .ok_or(MutinyError::BadAmountError)?;
+            let receivers = phantom_receivers(amount_msat, &inbound);
+            match receivers.as_slice() {
+                [only] => (&nodes[only], None),
+                _ => {
+                    let hints = receivers
+                        .iter()
+                        .map(|pk| nodes[pk].get_phantom_route_hint())
+                        .collect();
+                    (&nodes[&receivers[0]], Some(hints))
+                }
+            }
         } else {
-            None
+            (first_node, None)
+        };
+
+        let res = node
+            .create_invoice(amount, route_hints, labels, expiry_delta_secs, privacy)
+            .await;
+        if let (Err(e), Some(lsp)) = (&res, node.lsp_client.as_ref()) {
+            if let Some(reason) = LspFailoverReason::from_error(e) {
+                if let Err(e) = self.fail_over_lsp(lsp.get_config().await, reason).await {
+                    log_error!(self.logger, "Failed to fail over LSP: {e}");
+                }
+            }
+        }
+        let invoice = res?;
+        log_trace!(self.logger, "finished calling create_invoice");
+
+        Ok((invoice.0.into(), invoice.1))
+    }
+
+    /// Creates an invoice on the first node with the route hints chosen by the caller,
+    /// either through the channels with the given funding outpoints or given as is.
+    /// Left on auto it's the same as `create_invoice`.
+    pub async fn create_invoice_with_route_hints(
+        &self,
+        amount: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+        privacy: Option<DescriptionPrivacy>,
+        route_hints: RouteHintSelection,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling create_invoice_with_route_hints");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let route_hints = match route_hints {
+            RouteHintSelection::Auto => {
+                return self
+                    .create_invoice(amount, labels, expiry_delta_secs, privacy)
+                    .await
+                    .map(|(invoice, _)| invoice)
+            }
+            RouteHintSelection::Channels(outpoints) => node.route_hints_for_channels(&outpoints)?,
+            RouteHintSelection::Explicit(hints) => {
+                hints.iter().map(|hops| to_route_hint(hops)).collect()
+            }
         };
+        let invoice = node
+            .create_invoice_with_route_hints(
+                amount,
+                route_hints,
+                labels,
+                expiry_delta_secs,
+                privacy,
+            )
+            .await?;
+        log_trace!(
+            self.logger,
+            "finished calling create_invoice_with_route_hints"
+        );
+
+        Ok(invoice.into())
+    }
+
+    /// Creates a hold invoice on the first node for a payment hash whose preimage
+    /// we don't know yet. A payment to it is held until it's settled with the
+    /// preimage or cancelled.
+    pub async fn create_hodl_invoice(
+        &self,
+        payment_hash: PaymentHash,
+        amount_sats: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling create_hodl_invoice");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node
+            .create_hodl_invoice(payment_hash, amount_sats, labels, expiry_delta_secs)
+            .await;
+        log_trace!(self.logger, "finished calling create_hodl_invoice");
+
+        res
+    }
+
+    /// Claims the payment held for a hold invoice with its preimage
+    pub async fn settle_invoice(
+        &self,
+        preimage: PaymentPreimage,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling settle_invoice");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.settle_hodl_invoice(preimage);
+        log_trace!(self.logger, "finished calling settle_invoice");
+
+        res
+    }
+
+    /// Cancels a hold invoice, failing any payment held for it back to the payer
+    pub async fn cancel_invoice(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> Result<HodlInvoice, MutinyError> {
+        log_trace!(self.logger, "calling cancel_invoice");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.cancel_hodl_invoice(payment_hash);
+        log_trace!(self.logger, "finished calling cancel_invoice");
+
+        res
+    }
+
+    /// Lists the hold invoices we created, newest first
+    pub fn list_hodl_invoices(&self) -> Result<Vec<HodlInvoice>, MutinyError> {
+        list_hodl_invoices(&self.storage)
+    }
+
+    /// Gets the LSP fee for receiving an invoice down the first node that exists.
+    /// This could include the fee if a channel open is necessary. Otherwise the fee
+    /// will be low or non-existant.
+    pub async fn get_lsp_fee(&self, amount: u64) -> Result<u64, MutinyError> {
+        log_trace!(self.logger, "calling get_lsp_fee");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.get_lsp_fee(amount).await;
+
+        // the quote is still returned, the next LSP is used from the next start
+        if let Some(lsp) = node.lsp_client.as_ref() {
+            let list = get_lsp_list(&self.storage)?;
+            if let Some(reason) = LspFailoverReason::from_fee_result(&list, amount, &res) {
+                if let Err(e) = self.fail_over_lsp(lsp.get_config().await, reason).await {
+                    log_error!(self.logger, "Failed to fail over LSP: {e}");
+                }
+            }
+        }
+
+        log_trace!(self.logger, "finished calling get_lsp_fee");
+
+        res
+    }
+
+    /// Compares the total cost of receiving the given amount over each path we
+    /// have: existing inbound liquidity, a JIT channel from each node's LSP, and
+    /// on-chain. Returned cheapest first, paths that can't receive the amount are left out.
+    ///
+    /// This wallet doesn't join federations, so there are no ecash options.
+    pub async fn compare_receive_options(
+        &self,
+        amount_sats: u64,
+    ) -> Result<Vec<ReceiveOption>, MutinyError> {
+        log_trace!(self.logger, "calling compare_receive_options");
+
+        if amount_sats == 0 {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let mut options = vec![];
+        let nodes = self.nodes.read().await;
+        for node in nodes.values() {
+            if node.get_inbound_capacity_msat() >= amount_sats * 1_000 {
+                options.push(ReceiveOption {
+                    kind: ReceiveOptionKind::Lightning,
+                    node_pubkey: Some(node.pubkey),
+                    fee_sats: 0,
+                });
+            }
+
+            if let Some(lsp) = node.lsp_client.as_ref() {
+                match node.get_lsp_fee(amount_sats).await {
+                    Ok(fee_sats) => options.push(ReceiveOption {
+                        kind: ReceiveOptionKind::JitChannel {
+                            lsp_pubkey: lsp.get_lsp_pubkey().await,
+                        },
+                        node_pubkey: Some(node.pubkey),
+                        fee_sats,
+                    }),
+                    Err(e) => log_warn!(
+                        self.logger,
+                        "Could not get LSP fee for node {}: {e}",
+                        node.pubkey
+                    ),
+                }
+            }
+        }
+        drop(nodes);
+
+        // receiving on-chain is free but the coins cost an input to spend later
+        let onchain_fee = TAPROOT_INPUT_VBYTES * self.estimate_fee_normal() as u64;
+        if amount_sats > onchain_fee {
+            options.push(ReceiveOption {
+                kind: ReceiveOptionKind::OnChain,
+                node_pubkey: None,
+                fee_sats: onchain_fee,
+            });
+        }
+
+        options.sort_by_key(|o| o.fee_sats);
+
+        log_trace!(self.logger, "finished calling compare_receive_options");
+
+        Ok(options)
+    }
+
+    /// Pays a lightning invoice from either a specified node or the first available node.
+    /// An amount should only be provided if the invoice does not have an amount.
+    /// The amount should be in satoshis. Limits on splitting it into parts that
+    /// aren't set in the MPP config come from the wallet's default, the payment
+    /// policy sets how long it's retried for.
+    pub(crate) async fn pay_invoice(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        mpp: MppConfig,
+        policy: PaymentPolicy,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling pay_invoice");
+
+        let amount = amt_sats.or(invoice.amount_milli_satoshis().map(|a| a / 1_000));
+        let labels = self
+            .run_send_hooks(invoice.to_string(), amount, labels)
+            .await?;
+        // set labels now, need to set it before in case the payment times out
+        self.storage
+            .set_invoice_labels(invoice.clone(), labels.clone())?;
+
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        let res = node
+            .pay_invoice_with_timeout(invoice, amt_sats, mpp, policy, labels)
+            .await;
+        log_trace!(self.logger, "finished calling pay_invoice");
+
+        res
+    }
+
+    /// The wallet's default limits on splitting payments into parts
+    pub fn get_mpp_config(&self) -> Result<MppConfig, MutinyError> {
+        get_mpp_config(&self.storage)
+    }
+
+    /// Sets the wallet's default limits on splitting payments into parts,
+    /// a payment can still override them
+    pub fn set_mpp_config(&self, config: MppConfig) -> Result<(), MutinyError> {
+        set_mpp_config(&self.storage, config)
+    }
+
+    /// Runs the before-send hooks, returns the labels with the ones hooks added.
+    /// Every way of sending funds goes through here, so a veto can't be skipped.
+    pub(crate) async fn run_send_hooks(
+        &self,
+        destination: String,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<Vec<String>, MutinyError> {
+        let added = self
+            .hooks
+            .before_send(HookEvent::BeforeSend {
+                destination,
+                amount_sats,
+                labels: labels.clone(),
+            })
+            .await?;
+
+        Ok(merge_labels(labels, added))
+    }
+
+    /// Sends a spontaneous payment to a node from either a specified node or the first available node.
+    /// The amount should be in satoshis. Custom TLV records, like podcasting 2.0 boosts,
+    /// are sent along with the payment.
+    pub async fn keysend(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        to_node: PublicKey,
+        amt_sats: u64,
+        message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling keysend");
+
+        let labels = self
+            .run_send_hooks(to_node.to_string(), Some(amt_sats), labels)
+            .await?;
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        log_debug!(self.logger, "Keysending to {to_node}");
+        let res = node
+            .keysend_with_timeout(to_node, amt_sats, message, custom_tlvs, labels, None)
+            .await;
+        log_trace!(self.logger, "finished calling keysend");
+
+        res
+    }
+
+    /// Probes the routes to an invoice's payee or a node from the first node,
+    /// estimating the fee and how likely the payment is to succeed without
+    /// sending any funds. The amount is in satoshis, it's only needed for
+    /// nodes and invoices without one.
+    pub async fn probe_payment(
+        &self,
+        target: &ProbeTarget,
+        amt_sats: Option<u64>,
+    ) -> Result<ProbeResult, MutinyError> {
+        log_trace!(self.logger, "calling probe_payment");
 
-        // just create a normal invoice from the first node
-        let first_node = if let Some(node) = nodes.values().next() {
-            node
-        } else {
-            return Err(MutinyError::WalletOperationFailed);
-        };
-        let invoice = first_node
-            .create_invoice(amount, route_hints, labels, expiry_delta_secs)
-            .await?;
-        log_trace!(self.logger, "finished calling create_invoice");
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.probe_payment(target, amt_sats, None).await;
+        log_trace!(self.logger, "finished calling probe_payment");
 
-        Ok((invoice.0.into(), invoice.1))
+        res
     }
 
-    /// Gets the LSP fee for receiving an invoice down the first node that exists.
-    /// This could include the fee if a channel open is necessary. Otherwise the fee
-    /// will be low or non-existant.
-    pub async fn get_lsp_fee(&self, amount: u64) -> Result<u64, MutinyError> {
-        log_trace!(self.logger, "calling get_lsp_fee");
+    /// Explains the route the first node would pay the invoice over, without
+    /// paying it. The amount is in satoshis, for invoices without one.
+    pub async fn explain_route(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<RouteExplanation, MutinyError> {
+        log_trace!(self.logger, "calling explain_route");
 
         let node = self.get_node_by_key_or_first(None).await?;
-        let res = node.get_lsp_fee(amount).await;
+        let res = node.explain_route(invoice, amt_sats);
+        log_trace!(self.logger, "finished calling explain_route");
 
-        log_trace!(self.logger, "finished calling get_lsp_fee");
+        res
+    }
+
+    /// Creates a BOLT12 offer on the first node. The amount should be in satoshis,
+    /// without one the payer picks how much to send.
+    pub async fn create_offer(
+        &self,
+        amount_sats: Option<u64>,
+        description: Option<String>,
+    ) -> Result<MutinyOffer, MutinyError> {
+        log_trace!(self.logger, "calling create_offer");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.create_offer(amount_sats, description);
+        log_trace!(self.logger, "finished calling create_offer");
 
         res
     }
 
-    /// Pays a lightning invoice from either a specified node or the first available node.
-    /// An amount should only be provided if the invoice does not have an amount.
-    /// The amount should be in satoshis.
-    pub(crate) async fn pay_invoice(
+    /// Pays a BOLT12 offer from either a specified node or the first available node.
+    /// An amount should only be provided if the offer does not have an amount,
+    /// or to pay more than it asks. The amount should be in satoshis.
+    pub(crate) async fn pay_offer(
         &self,
         self_node_pubkey: Option<&PublicKey>,
-        invoice: &Bolt11Invoice,
-        amt_sats: Option<u64>,
+        offer: &Offer,
+        amount_sats: Option<u64>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
-        log_trace!(self.logger, "calling pay_invoice");
+        log_trace!(self.logger, "calling pay_offer");
 
+        let amount = amount_sats.or(offer_amount_sats(offer)?);
+        let labels = self
+            .run_send_hooks(offer.to_string(), amount, labels)
+            .await?;
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         let res = node
-            .pay_invoice_with_timeout(invoice, amt_sats, None, labels)
+            .pay_offer_with_timeout(offer, amount_sats, None, labels)
             .await;
-        log_trace!(self.logger, "finished calling pay_invoice");
+        log_trace!(self.logger, "finished calling pay_offer");
 
         res
     }
 
-    /// Sends a spontaneous payment to a node from either a specified node or the first available node.
-    /// The amount should be in satoshis.
-    pub async fn keysend(
+    /// Lists the BOLT12 offers we created, newest first
+    pub fn list_offers(&self) -> Result<Vec<MutinyOffer>, MutinyError> {
+        list_offers(&self.storage)
+    }
+
+    /// Creates a BOLT12 refund on the first node, paid to whoever claims it
+    /// before it expires. The amount should be in satoshis.
+    pub async fn create_refund(
         &self,
-        self_node_pubkey: Option<&PublicKey>,
-        to_node: PublicKey,
-        amt_sats: u64,
-        message: Option<String>,
-        labels: Vec<String>,
-    ) -> Result<MutinyInvoice, MutinyError> {
-        log_trace!(self.logger, "calling keysend");
+        amount_sats: u64,
+        description: Option<String>,
+        expiry_secs: Option<u64>,
+    ) -> Result<MutinyRefund, MutinyError> {
+        log_trace!(self.logger, "calling create_refund");
+
+        // who it's paid to isn't known until it's claimed
+        self.run_send_hooks(String::new(), Some(amount_sats), vec![])
+            .await?;
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.create_refund(amount_sats, description, expiry_secs);
+        log_trace!(self.logger, "finished calling create_refund");
 
-        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
-        log_debug!(self.logger, "Keysending to {to_node}");
+        res
+    }
+
+    /// Claims a BOLT12 refund to the first node
+    pub async fn claim_refund(&self, refund: &Refund) -> Result<MutinyInvoice, MutinyError> {
+        log_trace!(self.logger, "calling claim_refund");
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        let res = node.claim_refund(refund);
+        log_trace!(self.logger, "finished calling claim_refund");
+
+        res
+    }
+
+    /// Lists the BOLT12 refunds we created, newest first
+    pub fn list_refunds(&self) -> Result<Vec<MutinyRefund>, MutinyError> {
+        list_refunds(&self.storage)
+    }
+
+    /// Moves liquidity between two channels of the same node by paying
+    /// ourselves over a circular route. The channels are given by their
+    /// funding outpoints, the routing fee paid is in the result.
+    pub async fn rebalance(
+        &self,
+        from_channel: &OutPoint,
+        to_channel: &OutPoint,
+        amount_sats: u64,
+    ) -> Result<Rebalance, MutinyError> {
+        log_trace!(self.logger, "calling rebalance");
+
+        let nodes = self.nodes.read().await;
+        let node = nodes
+            .values()
+            .find(|n| {
+                n.channel_manager.list_channels().iter().any(|c| {
+                    c.funding_txo.map(|f| f.into_bitcoin_outpoint()) == Some(*from_channel)
+                })
+            })
+            .ok_or(MutinyError::NotFound)?;
         let res = node
-            .keysend_with_timeout(to_node, amt_sats, message, labels, None)
+            .rebalance(*from_channel, *to_channel, amount_sats, None)
             .await;
-        log_trace!(self.logger, "finished calling keysend");
+        log_trace!(self.logger, "finished calling rebalance");
 
         res
     }
@@ -1570,16 +4207,34 @@ impl<S: MutinyStorage> NodeManager<S> {
     ///
     /// The node must be online and have a connection to the peer.
     /// The wallet must have enough funds to open the channel.
+    ///
+    /// If any utxos are provided only those will fund the channel, with the
+    /// change going back to the wallet, otherwise they are selected automatically.
+    ///
+    /// `push_msat` is given to the peer as soon as the channel opens, so they
+    /// start out with that much to spend and we with that much inbound. It's
+    /// refused while the withdrawal allowlist is on, it would send funds to the peer.
+    ///
+    /// An announced channel is gossiped to the network so others can route
+    /// through it, see `set_routing_node_identity` to be found as a routing node.
+    #[allow(clippy::too_many_arguments)]
     pub async fn open_channel(
         &self,
         self_node_pubkey: Option<&PublicKey>,
         to_pubkey: Option<PublicKey>,
         amount: u64,
+        push_msat: Option<u64>,
         fee_rate: Option<u64>,
         user_channel_id: Option<u128>,
+        utxos: Vec<OutPoint>,
+        announce: bool,
     ) -> Result<MutinyChannel, MutinyError> {
         log_trace!(self.logger, "calling open_channel");
 
+        if push_msat.unwrap_or_default() > 0 && self.get_withdrawal_allowlist()?.enabled {
+            return Err(MutinyError::DestinationNotAllowed);
+        }
+
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         let to_pubkey = match to_pubkey {
             Some(pubkey) => pubkey,
@@ -1593,7 +4248,16 @@ impl<S: MutinyStorage> NodeManager<S> {
         };
 
         let outpoint = node
-            .open_channel_with_timeout(to_pubkey, amount, fee_rate, user_channel_id, 60)
+            .open_channel_with_timeout(
+                to_pubkey,
+                amount,
+                push_msat.unwrap_or_default(),
+                fee_rate,
+                user_channel_id,
+                &utxos,
+                announce,
+                60,
+            )
             .await?;
 
         let all_channels = node.channel_manager.list_channels();
@@ -1648,8 +4312,132 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
     }
 
+    /// Creates a request to open a channel funded from outside the wallet, such as
+    /// a federation withdrawing ecash to the returned deposit address. Once the
+    /// deposit confirms a channel is opened with it to the given peer, or the LSP.
+    pub fn request_channel_funding(
+        &self,
+        amount_sats: u64,
+        to_pubkey: Option<PublicKey>,
+    ) -> Result<ChannelFundingRequest, MutinyError> {
+        log_trace!(self.logger, "calling request_channel_funding");
+
+        if amount_sats < utils::min_lightning_amount(self.network, false) {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let label = format!("{CHANNEL_FUNDING_PREFIX}{id}");
+        let address = self.get_new_address_of_type(vec![label], Some(AddressType::Taproot))?;
+        let request = ChannelFundingRequest {
+            id,
+            address: address.to_string(),
+            amount_sats,
+            peer: to_pubkey,
+            status: ChannelFundingStatus::WaitingForDeposit,
+            channel_outpoint: None,
+            error: None,
+            created_at: utils::now().as_secs(),
+        };
+        persist_channel_funding_request(&self.storage, &request)?;
+        log_trace!(self.logger, "finished calling request_channel_funding");
+
+        Ok(request)
+    }
+
+    /// Lists the channel funding requests, newest first.
+    pub fn list_channel_funding_requests(&self) -> Result<Vec<ChannelFundingRequest>, MutinyError> {
+        list_channel_funding_requests(&self.storage)
+    }
+
+    /// Stops waiting for the deposit of a channel funding request. Anything
+    /// that was deposited stays in the on-chain wallet.
+    pub fn cancel_channel_funding(&self, id: String) -> Result<(), MutinyError> {
+        let mut request =
+            get_channel_funding_request(&self.storage, &id)?.ok_or(MutinyError::NotFound)?;
+        if request.status != ChannelFundingStatus::WaitingForDeposit {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        request.status = ChannelFundingStatus::Cancelled;
+        persist_channel_funding_request(&self.storage, &request)?;
+        for utxo in self.deposit_utxos(&request)? {
+            self.wallet.unfreeze_utxo(utxo.outpoint)?;
+        }
+        Ok(())
+    }
+
+    fn deposit_utxos(
+        &self,
+        request: &ChannelFundingRequest,
+    ) -> Result<Vec<LocalOutput>, MutinyError> {
+        let script = request
+            .address
+            .parse::<Address<NetworkUnchecked>>()?
+            .require_network(self.network)?
+            .script_pubkey();
+        Ok(self
+            .wallet
+            .list_utxos()?
+            .into_iter()
+            .filter(|u| u.txout.script_pubkey == script)
+            .collect())
+    }
+
+    /// Opens channels for the funding requests whose deposits have confirmed.
+    /// Deposits are frozen as soon as they are seen so they aren't spent by
+    /// anything else, failed opens are retried on the next sync with the error
+    /// recorded on the request.
+    pub(crate) async fn open_funded_channels(&self) -> Result<(), MutinyError> {
+        let storage = &self.storage;
+        for mut request in list_channel_funding_requests(storage)? {
+            if request.status != ChannelFundingStatus::WaitingForDeposit {
+                continue;
+            }
+
+            let utxos = self.deposit_utxos(&request)?;
+            for utxo in utxos.iter() {
+                self.wallet.freeze_utxo(utxo.outpoint)?;
+            }
+            if utxos.is_empty() || utxos.iter().any(|u| !u.confirmation_time.is_confirmed()) {
+                continue;
+            }
+
+            log_info!(
+                self.logger,
+                "Opening channel for funding request {}",
+                request.id
+            );
+            let outpoints = utxos.iter().map(|u| u.outpoint).collect::<Vec<_>>();
+            match self.sweep_utxos_to_channel(&outpoints, request.peer).await {
+                Ok(channel) => {
+                    request.status = ChannelFundingStatus::Opened;
+                    request.channel_outpoint = channel.outpoint;
+                    request.error = None;
+                    if let Some(cb) = self.ln_event_callback.as_ref() {
+                        cb.trigger(CommonLnEvent::ChannelFundingOpened {
+                            id: request.id.clone(),
+                            outpoint: channel.outpoint.map(|o| o.to_string()).unwrap_or_default(),
+                        });
+                    }
+                }
+                Err(e) => {
+                    log_error!(
+                        self.logger,
+                        "Failed to open channel for funding request {}: {e}",
+                        request.id
+                    );
+                    request.error = Some(e.to_string());
+                }
+            }
+            persist_channel_funding_request(storage, &request)?;
+        }
+
+        Ok(())
+    }
+
     /// Opens a channel from our selected node to the given pubkey.
-    /// It will spend the all the on-chain utxo in full to fund the channel.
+    /// It will spend all the on-chain utxos that aren't frozen in full to fund the channel.
     ///
     /// The node must be online and have a connection to the peer.
     pub async fn sweep_all_to_channel(
@@ -1661,6 +4449,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         let utxos = self
             .list_utxos()?
             .iter()
+            .filter(|u| !u.frozen)
             .map(|u| u.outpoint)
             .collect::<Vec<_>>();
 
@@ -1680,7 +4469,10 @@ impl<S: MutinyStorage> NodeManager<S> {
     ///
     /// If both force and abandon are true, an error will be returned.
     ///
-    /// ldk uses background fee rate for closing channels which can be very slow
+    /// ldk uses background fee rate for closing channels which can be very slow.
+    /// For a cooperative close the target feerate is the least we propose and the
+    /// max feerate caps what we agree to, so fee spikes don't eat into the balance.
+    /// The cap only matters for channels we opened, the opener pays the closing fee.
     pub async fn close_channel(
         &self,
         outpoint: &OutPoint,
@@ -1688,6 +4480,7 @@ impl<S: MutinyStorage> NodeManager<S> {
         force: bool,
         abandon: bool,
         target_feerate_sats_per_1000_weight: Option<u32>,
+        max_feerate_sats_per_1000_weight: Option<u32>,
     ) -> Result<(), MutinyError> {
         log_trace!(self.logger, "calling close_channel");
 
@@ -1742,7 +4535,9 @@ impl<S: MutinyStorage> NodeManager<S> {
                 } else {
                     // convert address to ShutdownScript
                     let shutdown_script = if let Some(addr) = address {
-                        Some(ShutdownScript::try_from(addr.script_pubkey())?)
+                        let spk = addr.script_pubkey();
+                        self.wallet.check_allowed_destinations(&[&spk])?;
+                        Some(ShutdownScript::try_from(spk)?)
                     } else {
                         None
                     };
@@ -1754,7 +4549,18 @@ impl<S: MutinyStorage> NodeManager<S> {
                     } else {
                         channel.inbound_capacity_msat / 1000
                     };
-                    if let Some(feerate_pkw) = target_feerate_sats_per_1000_weight {
+                    if let (Some(target), Some(max)) = (
+                        target_feerate_sats_per_1000_weight,
+                        max_feerate_sats_per_1000_weight,
+                    ) {
+                        if max < target {
+                            return Err(MutinyError::InvalidFeerate);
+                        }
+                    }
+                    // check against the most we could end up paying
+                    if let Some(feerate_pkw) =
+                        max_feerate_sats_per_1000_weight.or(target_feerate_sats_per_1000_weight)
+                    {
                         let estimate_fee: u64 = ((close_tx_weight * feerate_pkw) / 1000).into();
                         if estimate_fee > (initiator_balance * 8 / 10) {
                             log_warn!(
@@ -1765,6 +4571,34 @@ impl<S: MutinyStorage> NodeManager<S> {
                         }
                     }
 
+                    if let Some(max) = max_feerate_sats_per_1000_weight {
+                        // ldk agrees to closing fees up to its own feerate plus this
+                        // allowance, so leave exactly enough room to reach the max
+                        let base = target_feerate_sats_per_1000_weight
+                            .unwrap_or_else(|| self.fee_estimator.get_normal_fee_rate());
+                        let update =
+                            ChannelConfigUpdate {
+                                force_close_avoidance_max_fee_satoshis: Some(
+                                    closing_fee_allowance(base, max, close_tx_weight),
+                                ),
+                                ..Default::default()
+                            };
+                        node.channel_manager
+                            .update_partial_channel_config(
+                                &channel.counterparty.node_id,
+                                &[channel.channel_id],
+                                &update,
+                            )
+                            .map_err(|e| {
+                                log_error!(
+                                    self.logger,
+                                    "had an error setting the closing fee range of channel {}: {e:?}",
+                                    &channel.channel_id
+                                );
+                                MutinyError::ChannelClosingFailed
+                            })?;
+                    }
+
                     node.channel_manager
                         .close_channel_with_feerate_and_script(
                             &channel.channel_id,
@@ -1798,6 +4632,41 @@ impl<S: MutinyStorage> NodeManager<S> {
         res
     }
 
+    /// Bumps a force close that's stuck unconfirmed by spending its anchor output
+    /// in a child transaction at the feerate. LDK keeps bumping at least at this
+    /// feerate until the commitment confirms.
+    pub async fn bump_force_close(
+        &self,
+        channel_id: &ChannelId,
+        sats_per_vbyte: u64,
+    ) -> Result<AnchorBump, MutinyError> {
+        log_trace!(self.logger, "calling bump_force_close");
+
+        let feerate = FeeRate::from_sat_per_vb(sats_per_vbyte)
+            .filter(|f| *f > FeeRate::ZERO)
+            .ok_or(MutinyError::InvalidFeerate)?;
+        let feerate_pkw =
+            u32::try_from(feerate.to_sat_per_kwu()).map_err(|_| MutinyError::InvalidFeerate)?;
+
+        let nodes = self.nodes.read().await;
+        let mut res = Err(MutinyError::NotFound);
+        for node in nodes.values() {
+            if node.has_anchor_close(channel_id).await {
+                res = node.bump_force_close(channel_id, feerate_pkw).await;
+                break;
+            }
+        }
+        log_trace!(self.logger, "finished calling bump_force_close");
+
+        res
+    }
+
+    /// Lists the force closes that could be bumped and the child transactions
+    /// that bumped them, newest first
+    pub fn list_anchor_bumps(&self) -> Result<Vec<AnchorBump>, MutinyError> {
+        list_anchor_bumps(&self.storage)
+    }
+
     /// Lists all the channels for all the nodes in the node manager.
     pub async fn list_channels(&self) -> Result<Vec<MutinyChannel>, MutinyError> {
         log_trace!(self.logger, "calling list_channels");
@@ -1808,13 +4677,32 @@ impl<S: MutinyStorage> NodeManager<S> {
             .flat_map(|(_, n)| n.channel_manager.list_channels())
             .collect();
 
-        let mutiny_channels: Vec<MutinyChannel> =
-            channels.iter().map(MutinyChannel::from).collect();
+        let mutiny_channels: Vec<MutinyChannel> = channels
+            .iter()
+            .map(|c| MutinyChannel {
+                peer_alias: self.node_alias(&c.counterparty.node_id),
+                ..MutinyChannel::from(c)
+            })
+            .collect();
 
         log_trace!(self.logger, "finished calling list_channels");
         Ok(mutiny_channels)
     }
 
+    /// Looks up the alias a node announced, from our saved peers, the network
+    /// graph or the cached gossip, in that order.
+    pub fn lookup_node_alias(&self, pubkey: &PublicKey) -> Result<Option<String>, MutinyError> {
+        let node_id = NodeId::from_pubkey(pubkey);
+        if let Some(alias) = read_peer_info(&self.storage, &node_id)?.and_then(|p| p.alias) {
+            return Ok(Some(alias));
+        }
+        lookup_node_alias(&self.storage, self.gossip_sync.network_graph(), &node_id)
+    }
+
+    fn node_alias(&self, pubkey: &PublicKey) -> Option<String> {
+        self.lookup_node_alias(pubkey).ok().flatten()
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     pub async fn list_peers(&self) -> Result<Vec<MutinyPeer>, MutinyError> {
         log_trace!(self.logger, "calling list_peers");
@@ -1828,7 +4716,11 @@ impl<S: MutinyStorage> NodeManager<S> {
                 // node id should be safe here
                 pubkey: PublicKey::from_slice(node_id.as_slice()).expect("Invalid pubkey"),
                 connection_string: metadata.connection_string.clone(),
-                alias: metadata.alias.clone(),
+                alias: metadata.alias.clone().or_else(|| {
+                    lookup_node_alias(&self.storage, self.gossip_sync.network_graph(), node_id)
+                        .ok()
+                        .flatten()
+                }),
                 color: metadata.color.clone(),
                 label: metadata.label.clone(),
                 is_connected: false,
@@ -1858,7 +4750,7 @@ impl<S: MutinyStorage> NodeManager<S> {
                 let new = MutinyPeer {
                     pubkey: peer,
                     connection_string: None,
-                    alias: None,
+                    alias: self.node_alias(&peer),
                     color: None,
                     label: None,
                     is_connected: true,
@@ -1905,6 +4797,24 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(())
     }
 
+    /// Rescans the on-chain wallet to find funds a normal sync missed,
+    /// skipping transactions confirmed before the start height.
+    /// The gap limit defaults to the configured one.
+    pub async fn rescan_onchain(
+        &self,
+        start_height: u32,
+        gap_limit: Option<usize>,
+        progress: Option<Arc<dyn Fn(u8) + Send + Sync>>,
+    ) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling rescan_onchain");
+
+        let gap = gap_limit.unwrap_or(self.wallet.gap_limit());
+        let res = self.wallet.rescan(start_height, gap, progress).await;
+        log_trace!(self.logger, "finished calling rescan_onchain");
+
+        res
+    }
+
     /// Resets BDK's keychain tracker. This will require a re-sync of the blockchain.
     ///
     /// This can be useful if you get stuck in a bad state.
@@ -1918,7 +4828,8 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
 
         // delete the bdk keychain store
-        self.storage.delete(&[KEYCHAIN_STORE_KEY])?;
+        self.storage
+            .delete(&[KEYCHAIN_STORE_KEY, SEGWIT_KEYCHAIN_STORE_KEY])?;
         self.storage
             .write_data(NEED_FULL_SYNC_KEY.to_string(), true, None)?;
 
@@ -1950,10 +4861,13 @@ impl<S: MutinyStorage> NodeManager<S> {
             )
         }));
 
+        let res = record_backup_exported(&storage);
+
         // shut back down after reading if it was already closed
         if needs_db_connection {
             storage.clone().stop().await;
         }
+        res?;
 
         Ok(Value::Object(serde_map))
     }
@@ -1999,7 +4913,9 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         .with_wallet(node_manager.wallet.clone())
         .with_esplora(node_manager.esplora.clone())
         .with_network(node_manager.network)
-        .with_initial_sync(node_manager.has_done_initial_ldk_sync.clone());
+        .with_initial_sync(node_manager.has_done_initial_ldk_sync.clone())
+        .with_idle_flag(node_manager.idle.idle_flag())
+        .with_hooks(node_manager.hooks.clone());
     node_builder.with_logger(node_manager.logger.clone());
 
     #[cfg(target_arch = "wasm32")]
@@ -2018,6 +4934,7 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
     if node_manager.do_not_bump_channel_close_tx {
         node_builder.do_not_bump_channel_close_tx();
     }
+    node_builder.with_mpp_strategy(node_manager.mpp_strategy);
 
     let new_node = node_builder.build().await?;
     let node_pubkey = new_node.pubkey;
@@ -2071,11 +4988,39 @@ pub fn create_lsp_config(
     }
 }
 
+/// The nodes a phantom invoice for the amount should lead to, the ones with
+/// enough inbound liquidity to receive all of it. When none has, it leads to
+/// all of them, our liquidity may have changed by the time it's paid.
+pub(crate) fn phantom_receivers<K: Clone>(amount_msat: u64, inbound_msat: &[(K, u64)]) -> Vec<K> {
+    let able: Vec<K> = inbound_msat
+        .iter()
+        .filter(|(_, inbound)| *inbound >= amount_msat)
+        .map(|(k, _)| k.clone())
+        .collect();
+    if able.is_empty() {
+        inbound_msat.iter().map(|(k, _)| k.clone()).collect()
+    } else {
+        able
+    }
+}
+
+/// The closing fee ldk may agree to on top of what `base_feerate_pkw` pays,
+/// so the negotiated fee stays within `max_feerate_pkw`
+pub(crate) fn closing_fee_allowance(
+    base_feerate_pkw: u32,
+    max_feerate_pkw: u32,
+    close_tx_weight: u32,
+) -> u64 {
+    max_feerate_pkw.saturating_sub(base_feerate_pkw) as u64 * close_tx_weight as u64 / 1000
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         encrypt::encryption_key_from_pass,
-        nodemanager::{ChannelClosure, MutinyInvoice, NodeManager, TransactionDetails},
+        nodemanager::{
+            AddressType, ChannelClosure, MutinyInvoice, NodeManager, TransactionDetails,
+        },
         ActivityItem, MutinyWalletConfigBuilder, PrivacyLevel,
     };
     use crate::{keymanager::generate_seed, nodemanager::NodeManagerBuilder};
@@ -2083,7 +5028,7 @@ mod tests {
     use bitcoin::hashes::hex::FromHex;
     use bitcoin::hashes::{sha256, Hash};
     use bitcoin::secp256k1::PublicKey;
-    use bitcoin::{absolute, Network, Transaction, TxOut, Txid};
+    use bitcoin::{absolute, Network, ScriptBuf, Transaction, TxIn, TxOut, Txid, WPubkeyHash};
     use bitcoin::{bip32::Xpriv, transaction::Version, Amount};
     use hex_conservative::DisplayHex;
     use lightning::ln::PaymentHash;
@@ -2091,12 +5036,11 @@ mod tests {
     use std::collections::HashMap;
     use std::str::FromStr;
 
-    use crate::test_utils::*;
-
-    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::event::{CustomTlv, HTLCStatus, MillisatAmount, PaymentInfo};
     use crate::lsp::voltage::VoltageConfig;
     use crate::nodemanager::{LspConfig, NodeIndex, NodeStorage};
     use crate::storage::{MemoryStorage, MutinyStorage};
+    use crate::test_utils::*;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -2172,6 +5116,131 @@ mod tests {
         }
     }
 
+    #[test]
+    async fn created_address_types() {
+        let test_name = "created_address_types";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = Xpriv::new_master(network, &seed.to_seed("")).unwrap();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        assert_eq!(nm.get_default_address_type().unwrap(), AddressType::Taproot);
+        let address = nm.get_new_address(vec![]).unwrap();
+        assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2tr));
+
+        let address = nm
+            .get_new_address_of_type(vec![], Some(AddressType::SegwitV0))
+            .unwrap();
+        assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2wpkh));
+
+        nm.set_default_address_type(AddressType::SegwitV0).unwrap();
+        let address = nm.get_new_address(vec![]).unwrap();
+        assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2wpkh));
+        let address = nm
+            .get_new_address_of_type(vec![], Some(AddressType::Taproot))
+            .unwrap();
+        assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2tr));
+    }
+
+    #[test]
+    async fn broadcast_raw_tx_rejects_invalid_hex() {
+        let test_name = "broadcast_raw_tx_rejects_invalid_hex";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = Xpriv::new_master(network, &seed.to_seed("")).unwrap();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        assert!(matches!(
+            nm.broadcast_raw_tx("not a transaction").await,
+            Err(MutinyError::InvalidTransaction)
+        ));
+        assert!(matches!(
+            nm.broadcast_raw_tx("0200000000").await,
+            Err(MutinyError::InvalidTransaction)
+        ));
+    }
+
+    #[test]
+    async fn allowlist_blocks_raw_tx_and_push() {
+        let test_name = "allowlist_blocks_raw_tx_and_push";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let seed = generate_seed(12).expect("Failed to gen seed");
+        let network = Network::Regtest;
+        let xpriv = Xpriv::new_master(network, &seed.to_seed("")).unwrap();
+        let c = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let nm = NodeManagerBuilder::new(xpriv, storage.clone())
+            .with_config(c)
+            .build()
+            .await
+            .expect("node manager should initialize");
+
+        // with the allowlist on, raw transactions and pushes can't send funds elsewhere
+        nm.enable_withdrawal_allowlist(60).unwrap();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([9; 20])),
+            }],
+        };
+        assert!(matches!(
+            nm.broadcast_raw_tx(&bitcoin::consensus::encode::serialize_hex(&tx))
+                .await,
+            Err(MutinyError::DestinationNotAllowed)
+        ));
+
+        let peer = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+        assert!(matches!(
+            nm.open_channel(
+                None,
+                Some(peer),
+                100_000,
+                Some(1_000),
+                None,
+                None,
+                vec![],
+                false
+            )
+            .await,
+            Err(MutinyError::DestinationNotAllowed)
+        ));
+    }
+
     #[test]
     async fn created_label_transaction() {
         let test_name = "created_new_nodes";
@@ -2259,12 +5328,16 @@ mod tests {
             amt_msat: MillisatAmount(Some(100_000_000)),
             fee_paid_msat: None,
             bolt11: Some(invoice.clone()),
+            offer: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: 1681781585,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
             bolt11: Some(invoice),
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash,
             preimage: Some(preimage.to_lower_hex_string()),
@@ -2307,6 +5380,11 @@ mod tests {
         )
         .unwrap();
 
+        let boost = CustomTlv {
+            tlv_type: 7629169,
+            value: "7b22616374696f6e223a22626f6f7374227d".to_string(),
+        };
+
         let payment_info = PaymentInfo {
             preimage: Some(preimage),
             secret: None,
@@ -2315,12 +5393,16 @@ mod tests {
             amt_msat: MillisatAmount(Some(100_000)),
             fee_paid_msat: Some(1_020),
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![boost.clone()],
             payee_pubkey: Some(pubkey),
             last_update: 1681781585,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![boost],
             description: None,
             payment_hash,
             preimage: Some(preimage.to_lower_hex_string()),
@@ -2395,6 +5477,11 @@ mod tests {
             reason: "".to_string(),
             timestamp: 1686258926,
             channel_funding_txo: None,
+            closing_txid: None,
+            swept_amount_sats: 0,
+            pending_sweep_sats: 0,
+            fully_swept: false,
+            estimated_secs_remaining: None,
         };
 
         let tx1: TransactionDetails = TransactionDetails {
@@ -2406,6 +5493,8 @@ mod tests {
             fee: None,
             confirmation_time: ConfirmationTime::Unconfirmed { last_seen: 0_u64 },
             labels: vec![],
+            replaces: vec![],
+            address_reused: false,
         };
 
         let tx2: TransactionDetails = TransactionDetails {
@@ -2420,10 +5509,14 @@ mod tests {
                 time: 1234,
             },
             labels: vec![],
+            replaces: vec![],
+            address_reused: false,
         };
 
         let invoice1: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash,
             preimage: Some(preimage.to_lower_hex_string()),
@@ -2441,6 +5534,8 @@ mod tests {
 
         let invoice2: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash,
             preimage: Some(preimage.to_lower_hex_string()),
@@ -2458,6 +5553,8 @@ mod tests {
 
         let invoice3: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash,
             preimage: None,
@@ -2475,6 +5572,8 @@ mod tests {
 
         let invoice4: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: None,
             payment_hash,
             preimage: None,
@@ -2492,6 +5591,8 @@ mod tests {
 
         let invoice5: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            offer: None,
+            custom_tlvs: vec![],
             description: Some("difference".to_string()),
             payment_hash,
             preimage: Some(preimage.to_lower_hex_string()),
@@ -2533,4 +5634,48 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_phantom_receivers() {
+        let test_name = "test_phantom_receivers";
+        log!("{}", test_name);
+
+        let inbound = [("a", 5_000_000), ("b", 20_000_000), ("c", 30_000_000)];
+        assert_eq!(
+            super::phantom_receivers(1_000_000, &inbound),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(
+            super::phantom_receivers(10_000_000, &inbound),
+            vec!["b", "c"]
+        );
+        assert_eq!(super::phantom_receivers(25_000_000, &inbound), vec!["c"]);
+        // nobody can take it all, so any of them might
+        assert_eq!(
+            super::phantom_receivers(40_000_000, &inbound),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_closing_fee_allowance() {
+        let test_name = "test_closing_fee_allowance";
+        log!("{}", test_name);
+
+        assert_eq!(super::closing_fee_allowance(1_000, 5_000, 720), 2_880);
+        assert_eq!(super::closing_fee_allowance(1_000, 1_000, 720), 0);
+        // a max below the base leaves no room at all
+        assert_eq!(super::closing_fee_allowance(5_000, 1_000, 720), 0);
+    }
+
+    #[test]
+    fn test_commitment_fee_sats() {
+        let test_name = "test_commitment_fee_sats";
+        log!("{}", test_name);
+
+        assert_eq!(super::commitment_fee_sats(1_000, 0, false), 724);
+        assert_eq!(super::commitment_fee_sats(1_000, 2, false), 1_068);
+        // anchor channels pay for both anchor outputs too
+        assert_eq!(super::commitment_fee_sats(253, 0, true), 284 + 660);
+    }
 }
@@ -0,0 +1,376 @@
+//! Signing, verifying and fetching nostr events and NIP-04 direct messages,
+//! shared by everything in the wallet that talks to nostr relays.
+
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::networking::websocket::{SimpleWebSocket, WebSocketImpl};
+use crate::utils;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::Aes256;
+use bitcoin::bip32::Xpriv;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{
+    self, ecdh, schnorr, Keypair, Message, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey,
+};
+use cbc::{Decryptor, Encryptor};
+use futures::{future::Either, pin_mut};
+use hex_conservative::{DisplayHex, FromHex};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// NIP-04 encrypted direct message
+pub(crate) const ENCRYPTED_DM_KIND: u64 = 4;
+/// Relays used when the user hasn't picked any
+pub const DEFAULT_RELAYS: [&str; 2] = ["wss://relay.mutinywallet.com", "wss://relay.primal.net"];
+/// How long to wait for a relay to send back the events we asked for
+const RELAY_TIMEOUT_MS: i32 = 10_000;
+/// NIP-42 client authentication, signed in answer to a relay's AUTH challenge
+const CLIENT_AUTH_KIND: u64 = 22242;
+
+/// The wallet's nostr key, the one behind its npub
+pub(crate) fn nostr_key(xprivkey: Xpriv) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    Ok(create_root_child_key(&context, xprivkey, ChildKey::Nostr)?.private_key)
+}
+
+/// The NIP-01 event id, the hash of the serialized event
+pub(crate) fn event_id(
+    pubkey: &XOnlyPublicKey,
+    created_at: u64,
+    kind: u64,
+    tags: &Value,
+    content: &str,
+) -> sha256::Hash {
+    let serialized = json!([0, pubkey.to_string(), created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes())
+}
+
+/// Creates a NIP-01 event signed by the key
+pub(crate) fn sign_event(key: &SecretKey, kind: u64, tags: Value, content: String) -> Value {
+    let secp = Secp256k1::new();
+    let keypair = Keypair::from_secret_key(&secp, key);
+    let pubkey = keypair.x_only_public_key().0;
+    let created_at = utils::now().as_secs();
+
+    let id = event_id(&pubkey, created_at, kind, &tags, &content);
+    let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(id.to_byte_array()), &keypair);
+
+    json!({
+        "id": id.to_string(),
+        "pubkey": pubkey.to_string(),
+        "created_at": created_at,
+        "kind": kind,
+        "tags": tags,
+        "content": content,
+        "sig": sig.serialize().to_lower_hex_string(),
+    })
+}
+
+/// Checks the event's id and signature, returning the author's pubkey
+pub(crate) fn verify_event(event: &Value) -> Result<XOnlyPublicKey, MutinyError> {
+    let secp = Secp256k1::verification_only();
+    let field = |name: &str| event.get(name).ok_or(MutinyError::NostrError);
+    let str_field = |name: &str| field(name)?.as_str().ok_or(MutinyError::NostrError);
+
+    let pubkey =
+        XOnlyPublicKey::from_str(str_field("pubkey")?).map_err(|_| MutinyError::NostrError)?;
+    let created_at = field("created_at")?
+        .as_u64()
+        .ok_or(MutinyError::NostrError)?;
+    let kind = field("kind")?.as_u64().ok_or(MutinyError::NostrError)?;
+    let id = event_id(
+        &pubkey,
+        created_at,
+        kind,
+        field("tags")?,
+        str_field("content")?,
+    );
+    if str_field("id")? != id.to_string() {
+        return Err(MutinyError::NostrError);
+    }
+    let sig = <[u8; 64]>::from_hex(str_field("sig")?)
+        .ok()
+        .and_then(|sig| schnorr::Signature::from_slice(&sig).ok())
+        .ok_or(MutinyError::NostrError)?;
+    secp.verify_schnorr(&sig, &Message::from_digest(id.to_byte_array()), &pubkey)
+        .map_err(|_| MutinyError::NostrError)?;
+
+    Ok(pubkey)
+}
+
+/// Signs the NIP-42 event answering the relay's AUTH challenge
+pub(crate) fn create_auth_event(key: &SecretKey, relay: &str, challenge: &str) -> Value {
+    let tags = json!([["relay", relay], ["challenge", challenge]]);
+    sign_event(key, CLIENT_AUTH_KIND, tags, String::new())
+}
+
+/// Whether a relay's CLOSED or OK message turned us away until we authenticate
+pub(crate) fn is_auth_required(reason: &str) -> bool {
+    reason.starts_with("auth-required:")
+}
+
+/// What a relay's message meant for authenticating with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AuthUpdate {
+    /// Send this back to answer the relay's challenge
+    Respond(String),
+    Accepted,
+    Rejected,
+}
+
+/// Answers a relay's NIP-42 AUTH challenges with the key and keeps track of
+/// whether the relay accepted us. Private and paid relays reject reads and
+/// writes from connections that haven't authenticated.
+pub(crate) struct RelayAuth<'a> {
+    key: &'a SecretKey,
+    relay: &'a str,
+    /// The id of the last auth event we sent
+    event_id: Option<String>,
+    pub authenticated: bool,
+}
+
+impl<'a> RelayAuth<'a> {
+    pub(crate) fn new(key: &'a SecretKey, relay: &'a str) -> Self {
+        Self {
+            key,
+            relay,
+            event_id: None,
+            authenticated: false,
+        }
+    }
+
+    /// Handles the message if it's about authenticating, None if it isn't
+    pub(crate) fn handle(&mut self, msg: &[Value]) -> Option<AuthUpdate> {
+        match msg {
+            [Value::String(t), Value::String(challenge)] if t == "AUTH" => {
+                let event = create_auth_event(self.key, self.relay, challenge);
+                self.event_id = event
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .map(|id| id.to_string());
+                Some(AuthUpdate::Respond(json!(["AUTH", event]).to_string()))
+            }
+            [Value::String(t), Value::String(id), Value::Bool(accepted), ..]
+                if t == "OK" && self.event_id.as_ref() == Some(id) =>
+            {
+                self.authenticated = *accepted;
+                Some(if *accepted {
+                    AuthUpdate::Accepted
+                } else {
+                    AuthUpdate::Rejected
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Asks a relay for the events matching the filter, until it has sent all it has.
+/// Relays that require NIP-42 authentication are answered with the key, and
+/// the request is sent again once they've accepted it.
+pub(crate) async fn fetch_from_relay(
+    relay: &str,
+    filter: &Value,
+    key: &SecretKey,
+) -> Result<Vec<Value>, MutinyError> {
+    let fetch = async {
+        let mut ws = WebSocketImpl::new(relay.to_string())
+            .await
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let sub_id = "mutiny";
+        let req = json!(["REQ", sub_id, filter]).to_string();
+        ws.send(req.clone())
+            .await
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        let mut auth = RelayAuth::new(key, relay);
+        let mut retried = false;
+        let mut waiting_for_auth = false;
+        let mut events = vec![];
+        loop {
+            let msg = ws.recv().await.map_err(|_| MutinyError::ConnectionFailed)?;
+            let Ok(msg) = serde_json::from_str::<Vec<Value>>(&msg) else {
+                continue;
+            };
+            match auth.handle(&msg) {
+                Some(AuthUpdate::Respond(reply)) => {
+                    ws.send(reply)
+                        .await
+                        .map_err(|_| MutinyError::ConnectionFailed)?;
+                    continue;
+                }
+                Some(AuthUpdate::Accepted) => {
+                    if waiting_for_auth {
+                        waiting_for_auth = false;
+                        ws.send(req.clone())
+                            .await
+                            .map_err(|_| MutinyError::ConnectionFailed)?;
+                    }
+                    continue;
+                }
+                Some(AuthUpdate::Rejected) => return Err(MutinyError::NostrError),
+                None => {}
+            }
+            match msg.as_slice() {
+                [Value::String(t), Value::String(id), event] if t == "EVENT" && id == sub_id => {
+                    events.push(event.clone());
+                }
+                [Value::String(t), Value::String(id), ..] if t == "EOSE" && id == sub_id => {
+                    let _ = ws.send(json!(["CLOSE", sub_id]).to_string()).await;
+                    return Ok(events);
+                }
+                [Value::String(t), Value::String(id), Value::String(reason)]
+                    if t == "CLOSED" && id == sub_id =>
+                {
+                    if !is_auth_required(reason) || retried {
+                        return Err(MutinyError::NostrError);
+                    }
+                    // ask again once the relay knows who we are
+                    retried = true;
+                    if auth.authenticated {
+                        ws.send(req.clone())
+                            .await
+                            .map_err(|_| MutinyError::ConnectionFailed)?;
+                    } else {
+                        waiting_for_auth = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+    };
+    let timeout = async {
+        utils::sleep(RELAY_TIMEOUT_MS).await;
+        Err(MutinyError::ConnectionFailed)
+    };
+
+    pin_mut!(fetch);
+    pin_mut!(timeout);
+
+    match futures::future::select(fetch, timeout).await {
+        Either::Left((res, _)) => res,
+        Either::Right((err, _)) => err,
+    }
+}
+
+/// The NIP-04 shared secret, the x coordinate of the ECDH point
+fn nip04_key(key: &SecretKey, pubkey: &XOnlyPublicKey) -> [u8; 32] {
+    let pubkey = PublicKey::from_x_only_public_key(*pubkey, Parity::Even);
+    let point = ecdh::shared_secret_point(&pubkey, key);
+    point[..32].try_into().expect("32 bytes")
+}
+
+pub(crate) fn nip04_encrypt(key: &SecretKey, to: &XOnlyPublicKey, plaintext: &str) -> String {
+    let iv: [u8; 16] = secp256k1::rand::random();
+    let cipher = Encryptor::<Aes256>::new(&nip04_key(key, to).into(), &iv.into());
+    let encrypted = cipher.encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+    format!("{}?iv={}", base64::encode(encrypted), base64::encode(iv))
+}
+
+pub(crate) fn nip04_decrypt(
+    key: &SecretKey,
+    from: &XOnlyPublicKey,
+    content: &str,
+) -> Result<String, MutinyError> {
+    let (encrypted, iv) = content.split_once("?iv=").ok_or(MutinyError::NostrError)?;
+    let iv: [u8; 16] = base64::decode(iv)?
+        .try_into()
+        .map_err(|_| MutinyError::NostrError)?;
+    let cipher = Decryptor::<Aes256>::new(&nip04_key(key, from).into(), &iv.into());
+    let decrypted = cipher.decrypt_padded_vec_mut::<Pkcs7>(&base64::decode(encrypted)?)?;
+    Ok(String::from_utf8(decrypted)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_sign_and_verify_event() {
+        let test_name = "test_sign_and_verify_event";
+        log!("{}", test_name);
+
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let event = sign_event(&key, 1, json!([["t", "test"]]), "hello".to_string());
+        let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+        assert_eq!(verify_event(&event).unwrap(), pubkey);
+
+        let mut tampered = event.clone();
+        tampered["content"] = json!("goodbye");
+        assert!(verify_event(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_nip04_round_trip() {
+        let test_name = "test_nip04_round_trip";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let alice = SecretKey::from_slice(&[1; 32]).unwrap();
+        let bob = SecretKey::from_slice(&[2; 32]).unwrap();
+        let alice_pubkey = alice.x_only_public_key(&secp).0;
+        let bob_pubkey = bob.x_only_public_key(&secp).0;
+
+        let content = nip04_encrypt(&alice, &bob_pubkey, "hi bob");
+        assert!(!content.contains("hi bob"));
+        assert_eq!(
+            nip04_decrypt(&bob, &alice_pubkey, &content).unwrap(),
+            "hi bob"
+        );
+
+        let eve = SecretKey::from_slice(&[3; 32]).unwrap();
+        assert_ne!(
+            nip04_decrypt(&eve, &alice_pubkey, &content).ok().as_deref(),
+            Some("hi bob")
+        );
+    }
+
+    #[test]
+    fn test_relay_auth() {
+        let test_name = "test_relay_auth";
+        log!("{}", test_name);
+
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let relay = "wss://relay.example.com";
+        let mut auth = RelayAuth::new(&key, relay);
+        assert_eq!(auth.handle(&[json!("EOSE"), json!("mutiny")]), None);
+
+        let Some(AuthUpdate::Respond(reply)) =
+            auth.handle(&[json!("AUTH"), json!("challenge-string")])
+        else {
+            panic!("should answer the challenge");
+        };
+        let reply: Vec<Value> = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply[0], json!("AUTH"));
+        let event = &reply[1];
+        let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+        assert_eq!(verify_event(event).unwrap(), pubkey);
+        assert_eq!(event["kind"], json!(CLIENT_AUTH_KIND));
+        assert_eq!(
+            event["tags"],
+            json!([["relay", relay], ["challenge", "challenge-string"]])
+        );
+
+        // only the OK for our auth event counts
+        assert_eq!(
+            auth.handle(&[json!("OK"), json!("other"), json!(true), json!("")]),
+            None
+        );
+        assert!(!auth.authenticated);
+        assert_eq!(
+            auth.handle(&[json!("OK"), event["id"].clone(), json!(true), json!("")]),
+            Some(AuthUpdate::Accepted)
+        );
+        assert!(auth.authenticated);
+
+        assert!(is_auth_required(
+            "auth-required: we only serve paying users"
+        ));
+        assert!(!is_auth_required("blocked: spam"));
+    }
+}
@@ -0,0 +1,314 @@
+//! BOLT12 offers, static payment codes that can be paid any number of times,
+//! and refunds, which work the other way around: the one paying publishes it
+//! and whoever it's for claims it by sending back an invoice.
+//!
+//! LDK answers the invoice requests for our offers, fetches the invoice for
+//! offers we pay and pays the invoices sent for our refunds, over onion messages
+//! routed through our LSP. We only keep the offers and refunds we created, to
+//! show them and to tie the payments back to them.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hex::DisplayHex;
+use lightning::ln::channelmanager::PaymentId;
+use lightning::offers::offer::{Amount, Offer, OfferId};
+use lightning::offers::refund::Refund;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const OFFER_PREFIX: &str = "offer/";
+pub(crate) const REFUND_PREFIX: &str = "refund/";
+/// How long a refund can be claimed for when not given
+pub const DEFAULT_REFUND_EXPIRY_SECS: u64 = 86_400;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MutinyOffer {
+    /// Hex encoded offer id
+    pub id: String,
+    /// The encoded offer to share, starts with `lno`
+    pub offer: String,
+    /// Amount every payment has to pay, any amount when not set
+    pub amount_sats: Option<u64>,
+    pub description: Option<String>,
+    pub created_at: u64,
+}
+
+impl MutinyOffer {
+    pub(crate) fn new(offer: &Offer) -> Result<Self, MutinyError> {
+        Ok(Self {
+            id: offer_id_hex(&offer.id()),
+            offer: offer.to_string(),
+            amount_sats: offer_amount_sats(offer)?,
+            description: offer
+                .description()
+                .map(|d| d.to_string())
+                .filter(|d| !d.is_empty()),
+            created_at: utils::now().as_secs(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RefundStatus {
+    /// Waiting for the recipient to claim it
+    Open,
+    Paid,
+    /// The payment to the recipient failed, or it was never claimed
+    Failed,
+}
+
+/// A refund we're paying out
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MutinyRefund {
+    /// Hex encoded payment id LDK pays the refund with
+    pub id: String,
+    /// The encoded refund for the recipient to claim, starts with `lnr`
+    pub refund: String,
+    pub amount_sats: u64,
+    pub description: Option<String>,
+    pub status: RefundStatus,
+    pub created_at: u64,
+    pub expires_at: u64,
+}
+
+impl MutinyRefund {
+    pub(crate) fn new(refund: &Refund, payment_id: PaymentId, expires_at: u64) -> Self {
+        Self {
+            id: payment_id.0.to_lower_hex_string(),
+            refund: refund.to_string(),
+            amount_sats: refund.amount_msats() / 1_000,
+            description: refund
+                .description()
+                .map(|d| d.to_string())
+                .filter(|d| !d.is_empty()),
+            status: RefundStatus::Open,
+            created_at: utils::now().as_secs(),
+            expires_at,
+        }
+    }
+}
+
+pub(crate) fn offer_id_hex(id: &OfferId) -> String {
+    id.0.to_lower_hex_string()
+}
+
+/// The amount the offer asks for. Offers priced in a currency aren't
+/// supported, we'd have no way to agree on the price with the payee.
+pub(crate) fn offer_amount_sats(offer: &Offer) -> Result<Option<u64>, MutinyError> {
+    match offer.amount() {
+        None => Ok(None),
+        Some(Amount::Bitcoin { amount_msats }) => Ok(Some(amount_msats / 1_000)),
+        Some(Amount::Currency { .. }) => Err(MutinyError::BadAmountError),
+    }
+}
+
+/// The amount to pay an offer, in msats. Offers without an amount need one
+/// given, offers with an amount can be paid more than it but never less.
+pub(crate) fn offer_payment_msats(
+    offer: &Offer,
+    amount_sats: Option<u64>,
+) -> Result<Option<u64>, MutinyError> {
+    match (offer_amount_sats(offer)?, amount_sats) {
+        (None, None) | (_, Some(0)) => Err(MutinyError::BadAmountError),
+        (Some(min), Some(amount)) if amount < min => Err(MutinyError::BadAmountError),
+        (_, amount) => Ok(amount.map(|a| a * 1_000)),
+    }
+}
+
+fn offer_key(id: &str) -> String {
+    format!("{OFFER_PREFIX}{id}")
+}
+
+pub(crate) fn persist_offer<S: MutinyStorage>(
+    storage: &S,
+    offer: &MutinyOffer,
+) -> Result<(), MutinyError> {
+    storage.write_data(offer_key(&offer.id), offer, None)
+}
+
+pub(crate) fn get_offer<S: MutinyStorage>(
+    storage: &S,
+    id: &OfferId,
+) -> Result<Option<MutinyOffer>, MutinyError> {
+    storage.get_data(offer_key(&offer_id_hex(id)))
+}
+
+/// Lists the offers we created, newest first
+pub(crate) fn list_offers<S: MutinyStorage>(storage: &S) -> Result<Vec<MutinyOffer>, MutinyError> {
+    let mut offers: Vec<MutinyOffer> = storage
+        .scan::<MutinyOffer>(OFFER_PREFIX, None)?
+        .into_values()
+        .collect();
+    offers.sort_by_key(|o| std::cmp::Reverse(o.created_at));
+
+    Ok(offers)
+}
+
+fn refund_key(id: &str) -> String {
+    format!("{REFUND_PREFIX}{id}")
+}
+
+pub(crate) fn persist_refund<S: MutinyStorage>(
+    storage: &S,
+    refund: &MutinyRefund,
+) -> Result<(), MutinyError> {
+    storage.write_data(refund_key(&refund.id), refund, None)
+}
+
+pub(crate) fn get_refund<S: MutinyStorage>(
+    storage: &S,
+    payment_id: &PaymentId,
+) -> Result<Option<MutinyRefund>, MutinyError> {
+    storage.get_data(refund_key(&payment_id.0.to_lower_hex_string()))
+}
+
+/// Updates the status of our refund paid with the payment id, returns the
+/// refund or None if the payment wasn't for one
+pub(crate) fn update_refund_status<S: MutinyStorage>(
+    storage: &S,
+    payment_id: &PaymentId,
+    status: RefundStatus,
+) -> Result<Option<MutinyRefund>, MutinyError> {
+    let Some(mut refund) = get_refund(storage, payment_id)? else {
+        return Ok(None);
+    };
+    refund.status = status;
+    persist_refund(storage, &refund)?;
+    Ok(Some(refund))
+}
+
+/// Lists the refunds we created, newest first. Refunds nobody claimed
+/// before they expired show as failed.
+pub(crate) fn list_refunds<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<MutinyRefund>, MutinyError> {
+    let now = utils::now().as_secs();
+    let mut refunds: Vec<MutinyRefund> = storage
+        .scan::<MutinyRefund>(REFUND_PREFIX, None)?
+        .into_values()
+        .map(|mut r| {
+            if r.status == RefundStatus::Open && r.expires_at < now {
+                r.status = RefundStatus::Failed;
+            }
+            r
+        })
+        .collect();
+    refunds.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+    Ok(refunds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning::offers::offer::OfferBuilder;
+    use lightning::offers::refund::RefundBuilder;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn offer(amount_msats: Option<u64>) -> Offer {
+        let signing_pubkey = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        let builder = OfferBuilder::new(signing_pubkey).description("coffee".to_string());
+        match amount_msats {
+            Some(amount) => builder.amount_msats(amount).build().unwrap(),
+            None => builder.build().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_offer_amounts() {
+        let test_name = "test_offer_amounts";
+        log!("{}", test_name);
+
+        let any_amount = offer(None);
+        assert!(offer_payment_msats(&any_amount, None).is_err());
+        assert!(offer_payment_msats(&any_amount, Some(0)).is_err());
+        assert_eq!(
+            offer_payment_msats(&any_amount, Some(1_000)).unwrap(),
+            Some(1_000_000)
+        );
+
+        let fixed = offer(Some(5_000_000));
+        assert_eq!(offer_amount_sats(&fixed).unwrap(), Some(5_000));
+        assert_eq!(offer_payment_msats(&fixed, None).unwrap(), None);
+        assert!(offer_payment_msats(&fixed, Some(4_999)).is_err());
+        assert_eq!(
+            offer_payment_msats(&fixed, Some(6_000)).unwrap(),
+            Some(6_000_000)
+        );
+    }
+
+    #[test]
+    fn test_offer_records() {
+        let test_name = "test_offer_records";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let offer = offer(Some(5_000_000));
+        let record = MutinyOffer::new(&offer).unwrap();
+        assert_eq!(Offer::from_str(&record.offer).unwrap(), offer);
+        assert_eq!(record.description, Some("coffee".to_string()));
+
+        persist_offer(&storage, &record).unwrap();
+        assert_eq!(
+            get_offer(&storage, &offer.id()).unwrap(),
+            Some(record.clone())
+        );
+        assert_eq!(list_offers(&storage).unwrap(), vec![record]);
+    }
+
+    #[test]
+    fn test_refund_records() {
+        let test_name = "test_refund_records";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let payer = SecretKey::from_slice(&[2; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        let refund = RefundBuilder::new(vec![1; 32], payer, 5_000_000)
+            .unwrap()
+            .description("returned shoes".to_string())
+            .build()
+            .unwrap();
+        let payment_id = PaymentId([3; 32]);
+        let now = utils::now().as_secs();
+
+        let record = MutinyRefund::new(&refund, payment_id, now + DEFAULT_REFUND_EXPIRY_SECS);
+        assert_eq!(Refund::from_str(&record.refund).unwrap(), refund);
+        assert_eq!(record.amount_sats, 5_000);
+        persist_refund(&storage, &record).unwrap();
+        assert_eq!(list_refunds(&storage).unwrap(), vec![record.clone()]);
+
+        let paid = update_refund_status(&storage, &payment_id, RefundStatus::Paid)
+            .unwrap()
+            .unwrap();
+        assert_eq!(paid.status, RefundStatus::Paid);
+        assert_eq!(get_refund(&storage, &payment_id).unwrap(), Some(paid));
+        assert!(
+            update_refund_status(&storage, &PaymentId([4; 32]), RefundStatus::Paid)
+                .unwrap()
+                .is_none()
+        );
+
+        // nobody claimed it in time
+        let expired = MutinyRefund {
+            id: "expired".to_string(),
+            expires_at: now - 1,
+            created_at: now + 1,
+            ..record
+        };
+        persist_refund(&storage, &expired).unwrap();
+        assert_eq!(
+            list_refunds(&storage).unwrap()[0].status,
+            RefundStatus::Failed
+        );
+    }
+}
@@ -2,52 +2,83 @@ use anyhow::anyhow;
 use bdk_chain::spk_client::{
     FullScanRequestBuilder, FullScanResult, SyncRequestBuilder, SyncResult,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 use bdk_chain::{BlockId, ConfirmationBlockTime, ConfirmationTime, Indexer, TxUpdate};
 use bdk_esplora::EsploraAsyncExt;
 use bdk_wallet::bitcoin::FeeRate;
+use bdk_wallet::coin_selection::{CoinSelectionAlgorithm, OldestFirstCoinSelection};
+use bdk_wallet::descriptor::IntoWalletDescriptor;
 use bdk_wallet::psbt::PsbtUtils;
 use bdk_wallet::template::DescriptorTemplateOut;
 use bdk_wallet::{
-    CreateParams, KeychainKind, LoadParams, LocalOutput, SignOptions, Update, Wallet,
+    Balance, CreateParams, KeychainKind, LoadParams, LocalOutput, SignOptions, TxBuilder, Update,
+    Wallet,
 };
-use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv, Xpub};
 use bitcoin::consensus::serialize;
+use bitcoin::params::Params;
 use bitcoin::psbt::{Input, Psbt};
-use bitcoin::{Address, Amount, Network, OutPoint, ScriptBuf, Transaction, Txid};
+use bitcoin::secp256k1::{Secp256k1, XOnlyPublicKey};
+use bitcoin::{
+    Address, Amount, BlockHash, Network, OutPoint, PrivateKey, ScriptBuf, Transaction, Txid, Weight,
+};
 use esplora_client::AsyncClient;
 use hex_conservative::DisplayHex;
 use lightning::events::bump_transaction::{Utxo, WalletSource};
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
+use reqwest::Client;
 
+use crate::allowlist::get_withdrawal_allowlist;
+use crate::anchorbump::{record_anchor_bump_tx, ANCHOR_BUMP_LABEL};
+use crate::bip322;
+use crate::cbf::{filter_matches, CompactFilterClient, DifficultyContext, HEADERS_PER_REQUEST};
 use crate::error::MutinyError;
 use crate::fees::MutinyFeeEstimator;
 use crate::labels::*;
 use crate::logging::MutinyLogger;
+use crate::nodemanager::{AddressType, CoinSelectionStrategy, ConsolidationConfig};
+use crate::snapshot::{fetch_chain_snapshot, get_chain_snapshot_tip, persist_chain_snapshot_tip};
 use crate::storage::{
-    IndexItem, MutinyStorage, KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX,
+    get_replaced_txids, persist_tx_replacement, IndexItem, MutinyStorage, ACTIVE_ESPLORA_URL_KEY,
+    FROZEN_UTXOS_KEY, KEYCHAIN_STORE_KEY, NEED_FULL_SYNC_KEY, ONCHAIN_PREFIX,
+    SEGWIT_KEYCHAIN_STORE_KEY,
 };
-use crate::utils::{now, sleep};
+use crate::sweepkey::{build_sweep_tx, find_key_utxos, key_scripts};
+use crate::utils::{self, now, sleep};
+use crate::vault::get_vault;
 use crate::TransactionDetails;
 
 pub(crate) const FULL_SYNC_STOP_GAP: usize = 150;
 pub(crate) const RESTORE_SYNC_STOP_GAP: usize = 50;
 const PARALLEL_REQUESTS: usize = 10;
+/// How far past the last used address we look for payments when syncing with filters
+const FILTER_LOOKAHEAD: u32 = 25;
 
 #[derive(Clone)]
 pub struct OnChainWallet<S: MutinyStorage> {
     pub wallet: Arc<RwLock<Wallet>>,
+    /// Native segwit wallet for handing out p2wpkh addresses to payers that
+    /// don't support taproot, everything else goes through the taproot wallet.
+    /// Its funds are spent together with the taproot wallet's.
+    pub segwit_wallet: Option<Arc<RwLock<Wallet>>>,
     pub(crate) storage: S,
     pub network: Network,
     pub blockchain: Arc<AsyncClient>,
     pub fees: Arc<MutinyFeeEstimator<S>>,
     pub(crate) stop: Arc<AtomicBool>,
     logger: Arc<MutinyLogger>,
+    /// How coins are picked when nothing was selected manually
+    pub(crate) coin_selection: CoinSelectionStrategy,
+    /// How many unused addresses in a row to look at before a full scan stops
+    pub(crate) gap_limit: Option<usize>,
+    /// When set, syncs match compact block filters locally instead of
+    /// asking esplora about our addresses
+    pub(crate) compact_filters: Option<Arc<CompactFilterClient>>,
 }
 
 impl<S: MutinyStorage> OnChainWallet<S> {
@@ -64,60 +95,158 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         let (receive_descriptor_template, change_descriptor_template) =
             get_tr_descriptors_for_extended_key(xprivkey, network, account_number)?;
 
-        // if we have a keychain set, load the wallet, otherwise create one
-        // receive_descriptor_template.clone(),
-        // Some(change_descriptor_template.clone()),
-        // OnChainStorage(db.clone()),
-        let load_wallet_res = db.read_changes()?.map(|changeset| {
-            Wallet::load_with_params(
-                changeset,
-                LoadParams::new()
-                    .descriptor(
-                        KeychainKind::External,
-                        Some(receive_descriptor_template.clone()),
-                    )
-                    .descriptor(
-                        KeychainKind::Internal,
-                        Some(change_descriptor_template.clone()),
-                    )
-                    .extract_keys(),
-            )
-        });
-        let wallet = match load_wallet_res {
-            Some(Ok(Some(wallet))) => wallet,
-            None | Some(Ok(None)) => {
-                // we don't have a bdk wallet, create one
-                Wallet::create_with_params(
-                    CreateParams::new(receive_descriptor_template, change_descriptor_template)
-                        .network(network),
-                )?
-            }
-            Some(Err(bdk_wallet::LoadError::Mismatch(_))) => {
-                // failed to read storage, means we have old encoding and need to delete and re-init wallet
-                db.delete(&[KEYCHAIN_STORE_KEY])?;
-                db.write_data(NEED_FULL_SYNC_KEY.to_string(), true, None)?;
-                Wallet::create_with_params(
-                    CreateParams::new(receive_descriptor_template, change_descriptor_template)
-                        .network(network),
-                )?
-            }
-            Some(Err(e)) => {
-                log_error!(logger, "Failed to load wallet: {e}");
-                return Err(MutinyError::WalletOperationFailed);
-            }
-        };
+        let wallet = load_or_create_wallet(
+            receive_descriptor_template,
+            change_descriptor_template,
+            true,
+            KEYCHAIN_STORE_KEY,
+            &db,
+            network,
+            &logger,
+        )?;
+
+        // wallets from before segwit addresses were added just start a fresh
+        // segwit wallet, nothing could have been received to it yet
+        let (segwit_descriptor, segwit_change_descriptor) =
+            get_wpkh_descriptors_for_extended_key(xprivkey, network, account_number)?;
+        let segwit_wallet = load_or_create_wallet(
+            segwit_descriptor,
+            segwit_change_descriptor,
+            true,
+            SEGWIT_KEYCHAIN_STORE_KEY,
+            &db,
+            network,
+            &logger,
+        )?;
+
+        Ok(OnChainWallet {
+            wallet: Arc::new(RwLock::new(wallet)),
+            segwit_wallet: Some(Arc::new(RwLock::new(segwit_wallet))),
+            storage: db,
+            network,
+            blockchain: esplora,
+            fees,
+            stop,
+            logger,
+            coin_selection: CoinSelectionStrategy::default(),
+            gap_limit: None,
+            compact_filters: None,
+        })
+    }
+
+    /// Creates a wallet that only knows the public descriptors, it can track funds
+    /// and build transactions but any PSBTs it creates have to be signed elsewhere.
+    /// A newly created watch-only wallet does a full sync first to find its history.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_watch_only(
+        descriptor: String,
+        change_descriptor: String,
+        db: S,
+        network: Network,
+        esplora: Arc<AsyncClient>,
+        fees: Arc<MutinyFeeEstimator<S>>,
+        stop: Arc<AtomicBool>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<OnChainWallet<S>, MutinyError> {
+        let is_new = db.read_changes()?.is_none();
+        let wallet = load_or_create_wallet(
+            descriptor,
+            change_descriptor,
+            false,
+            KEYCHAIN_STORE_KEY,
+            &db,
+            network,
+            &logger,
+        )?;
+        if !wallet
+            .get_signers(KeychainKind::External)
+            .signers()
+            .is_empty()
+            || !wallet
+                .get_signers(KeychainKind::Internal)
+                .signers()
+                .is_empty()
+        {
+            log_error!(logger, "Watch-only wallet descriptors contain private keys");
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if is_new {
+            db.write_data(NEED_FULL_SYNC_KEY.to_string(), true, None)?;
+        }
 
         Ok(OnChainWallet {
             wallet: Arc::new(RwLock::new(wallet)),
+            segwit_wallet: None,
             storage: db,
             network,
             blockchain: esplora,
             fees,
             stop,
             logger,
+            coin_selection: CoinSelectionStrategy::default(),
+            gap_limit: None,
+            compact_filters: None,
         })
     }
 
+    pub fn with_coin_selection(mut self, coin_selection: CoinSelectionStrategy) -> Self {
+        self.coin_selection = coin_selection;
+        self
+    }
+
+    pub fn with_gap_limit(mut self, gap_limit: Option<usize>) -> Self {
+        self.gap_limit = gap_limit;
+        self
+    }
+
+    pub fn with_compact_filters(mut self, compact_filters: Option<CompactFilterClient>) -> Self {
+        self.compact_filters = compact_filters.map(Arc::new);
+        self
+    }
+
+    /// The gap limit used for full scans, wallets with deep address usage need a bigger one
+    pub fn gap_limit(&self) -> usize {
+        self.gap_limit.unwrap_or(FULL_SYNC_STOP_GAP)
+    }
+
+    /// All of our bdk wallets with the key their changes are stored under
+    fn wallets(&self) -> Vec<(&RwLock<Wallet>, &'static str)> {
+        let mut wallets = vec![(self.wallet.as_ref(), KEYCHAIN_STORE_KEY)];
+        if let Some(segwit) = self.segwit_wallet.as_ref() {
+            wallets.push((segwit.as_ref(), SEGWIT_KEYCHAIN_STORE_KEY));
+        }
+        wallets
+    }
+
+    /// Reveals the next receive address of the given type
+    pub(crate) fn get_new_address(
+        &self,
+        address_type: AddressType,
+    ) -> Result<Address, MutinyError> {
+        let (wallet, store_key) = match (address_type, self.segwit_wallet.as_ref()) {
+            (AddressType::Taproot, _) => (self.wallet.as_ref(), KEYCHAIN_STORE_KEY),
+            (AddressType::SegwitV0, Some(segwit)) => (segwit.as_ref(), SEGWIT_KEYCHAIN_STORE_KEY),
+            (AddressType::SegwitV0, None) => return Err(MutinyError::InvalidArgumentsError),
+        };
+
+        let mut wallet = wallet.try_write()?;
+        let address = wallet.reveal_next_address(KeychainKind::External).address;
+        if let Some(changeset) = wallet.take_staged() {
+            self.storage.write_changes_to(store_key, &changeset)?;
+        }
+
+        Ok(address)
+    }
+
+    /// The combined balance of all our wallets
+    pub fn balance(&self) -> Result<Balance, MutinyError> {
+        let mut balance = Balance::default();
+        for (wallet, _) in self.wallets() {
+            balance = balance + wallet.try_read()?.balance();
+        }
+        Ok(balance)
+    }
+
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
         let txid = tx.compute_txid();
         log_info!(self.logger, "Broadcasting transaction: {txid}");
@@ -145,14 +274,19 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     }
 
     /// Tries to commit a wallet update, returns true if successful.
-    fn try_commit_update(&self, update: Update) -> Result<bool, MutinyError> {
+    fn try_commit_update(
+        &self,
+        wallet: &RwLock<Wallet>,
+        store_key: &str,
+        update: Update,
+    ) -> Result<bool, MutinyError> {
         // get wallet lock for writing and apply the update
-        match self.wallet.try_write() {
+        match wallet.try_write() {
             Ok(mut wallet) => match wallet.apply_update_at(update, Some(now().as_secs())) {
                 Ok(_) => {
                     // commit the changes
                     if let Some(changeset) = wallet.take_staged() {
-                        self.storage.write_changes(&changeset)?;
+                        self.storage.write_changes_to(store_key, &changeset)?;
                     }
                     drop(wallet); // drop so we can read from wallet
 
@@ -204,12 +338,32 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     pub async fn sync(&self) -> Result<(), MutinyError> {
         // if we need a full sync from a restore
         if self.storage.get(NEED_FULL_SYNC_KEY)?.unwrap_or_default() {
-            self.full_sync(RESTORE_SYNC_STOP_GAP).await?;
+            self.full_sync(self.gap_limit.unwrap_or(RESTORE_SYNC_STOP_GAP))
+                .await?;
             self.storage.delete(&[NEED_FULL_SYNC_KEY])?;
         }
+
+        for (wallet, store_key) in self.wallets() {
+            match self.compact_filters.as_ref() {
+                Some(filters) => {
+                    self.sync_wallet_with_filters(filters, wallet, store_key)
+                        .await?
+                }
+                None => self.sync_wallet(wallet, store_key).await?,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn sync_wallet(
+        &self,
+        wallet: &RwLock<Wallet>,
+        store_key: &str,
+    ) -> Result<(), MutinyError> {
         // get first wallet lock that only needs to read
         let (spks, txids, chain_tip) = {
-            if let Ok(wallet) = self.wallet.try_read() {
+            if let Ok(wallet) = wallet.try_read() {
                 let spk_vec = wallet
                     .spk_index()
                     .unused_spks()
@@ -253,7 +407,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         };
 
         for _ in 0..10 {
-            let successful = self.try_commit_update(update.clone())?;
+            let successful = self.try_commit_update(wallet, store_key, update.clone())?;
 
             if successful {
                 return Ok(());
@@ -267,10 +421,321 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Err(MutinyError::WalletOperationFailed)
     }
 
+    /// Syncs the wallet by walking the blocks since our tip and checking each block's
+    /// compact filter against our scripts, only downloading the blocks that match.
+    ///
+    /// Filters only cover confirmed transactions, incoming payments show up once they
+    /// confirm. Restores and rescans still go through esplora since they have to look
+    /// at the whole chain.
+    async fn sync_wallet_with_filters(
+        &self,
+        filters: &CompactFilterClient,
+        wallet: &RwLock<Wallet>,
+        store_key: &str,
+    ) -> Result<(), MutinyError> {
+        let (mut scripts, mut outpoints, local_tip) = {
+            let wallet = wallet.try_read()?;
+            let index = wallet.spk_index().inner();
+            let scripts = index.all_spks().values().cloned().collect::<HashSet<_>>();
+            let outpoints = index
+                .outpoints()
+                .iter()
+                .map(|(_, op)| *op)
+                .collect::<HashSet<_>>();
+            (scripts, outpoints, wallet.latest_checkpoint())
+        };
+
+        let server_height = filters.get_tip_height().await?;
+        if local_tip.height() == 0 {
+            // a new wallet has nothing in old blocks, start it at the tip
+            let hash = filters.get_block_hash(server_height).await?;
+            let chain = local_tip
+                .push(BlockId {
+                    height: server_height,
+                    hash,
+                })
+                .map_err(|_| MutinyError::WalletOperationFailed)?;
+            let update = Update {
+                chain: Some(chain),
+                ..Default::default()
+            };
+            return self.commit_filter_update(wallet, store_key, update).await;
+        }
+
+        // find the last block we agree on with the server, there may have been a reorg
+        let mut base = None;
+        for cp in local_tip.iter() {
+            if cp.height() <= server_height
+                && filters.get_block_hash(cp.height()).await? == cp.hash()
+            {
+                base = Some(cp);
+                break;
+            }
+        }
+        let base = base.ok_or(MutinyError::ChainAccessFailed)?;
+
+        // walk the headers from the start of the base's retarget period so each
+        // one's difficulty can be checked, the ones up to the base must be ours
+        let params = Params::new(self.network);
+        let period_start = DifficultyContext::period_start(&params, base.height());
+        let mut tip = if period_start == base.height() {
+            base.block_id()
+        } else {
+            BlockId {
+                height: period_start,
+                hash: filters.get_block_hash(period_start).await?,
+            }
+        };
+        let mut difficulty: Option<DifficultyContext> = None;
+
+        let mut chain = base.clone();
+        let mut tx_update = TxUpdate::<ConfirmationBlockTime>::default();
+        let mut last_active_indices: BTreeMap<KeychainKind, u32> = BTreeMap::new();
+        while !self.stop.load(Ordering::Relaxed) {
+            let headers = filters.get_headers(tip.hash, HEADERS_PER_REQUEST).await?;
+            // the first header is the block we asked from
+            if headers.len() <= 1 {
+                break;
+            }
+            if difficulty.is_none() {
+                difficulty = Some(DifficultyContext::new(
+                    params.clone(),
+                    tip.height,
+                    headers[0],
+                )?);
+            }
+            let difficulty = difficulty.as_mut().expect("set above");
+
+            let mut block_ids = Vec::with_capacity(headers.len() - 1);
+            for header in headers.iter().skip(1) {
+                difficulty.connect(header)?;
+                tip = BlockId {
+                    height: tip.height + 1,
+                    hash: header.block_hash(),
+                };
+                if tip.height == base.height() && tip.hash != base.hash() {
+                    return Err(MutinyError::ChainAccessFailed);
+                }
+                if tip.height > base.height() {
+                    block_ids.push(tip);
+                }
+            }
+
+            let hashes: Vec<BlockHash> = block_ids.iter().map(|b| b.hash).collect();
+            let filters_batch = filters.get_filters(&hashes).await?;
+            for (block_id, filter) in block_ids.into_iter().zip(filters_batch) {
+                if !filter_matches(&filter, block_id.hash, &scripts)? {
+                    continue;
+                }
+
+                let block = filters.get_block(block_id.hash).await?;
+                let wallet = wallet.try_read()?;
+                let index = wallet.spk_index();
+                let mut found = false;
+                for tx in block.txdata {
+                    let txid = tx.compute_txid();
+                    let spends_ours = tx
+                        .input
+                        .iter()
+                        .any(|i| outpoints.contains(&i.previous_output));
+                    let mut pays_ours = false;
+                    for (vout, output) in tx.output.iter().enumerate() {
+                        if !scripts.contains(&output.script_pubkey) {
+                            continue;
+                        }
+                        pays_ours = true;
+                        outpoints.insert(OutPoint::new(txid, vout as u32));
+
+                        // watch the addresses after this one too
+                        let Some((keychain, i)) =
+                            index.index_of_spk(output.script_pubkey.clone()).copied()
+                        else {
+                            continue;
+                        };
+                        let last = last_active_indices
+                            .get(&keychain)
+                            .copied()
+                            .or(index.last_revealed_index(keychain));
+                        if last.map_or(true, |last| i > last) {
+                            last_active_indices.insert(keychain, i);
+                            for next in i + 1..=i + FILTER_LOOKAHEAD {
+                                scripts.insert(wallet.peek_address(keychain, next).script_pubkey());
+                            }
+                        }
+                    }
+
+                    if spends_ours || pays_ours {
+                        found = true;
+                        tx_update.anchors.insert((
+                            ConfirmationBlockTime {
+                                block_id,
+                                confirmation_time: block.header.time as u64,
+                            },
+                            txid,
+                        ));
+                        tx_update.txs.push(Arc::new(tx));
+                    }
+                }
+
+                // anchors need their block in the chain to count as confirmed
+                if found {
+                    chain = chain
+                        .push(block_id)
+                        .map_err(|_| MutinyError::WalletOperationFailed)?;
+                }
+            }
+        }
+
+        if tip.height > chain.height() {
+            chain = chain
+                .push(tip)
+                .map_err(|_| MutinyError::WalletOperationFailed)?;
+        }
+        let update = Update {
+            last_active_indices,
+            tx_update,
+            chain: Some(chain),
+        };
+
+        self.commit_filter_update(wallet, store_key, update).await
+    }
+
+    async fn commit_filter_update(
+        &self,
+        wallet: &RwLock<Wallet>,
+        store_key: &str,
+        update: Update,
+    ) -> Result<(), MutinyError> {
+        for _ in 0..10 {
+            let successful = self.try_commit_update(wallet, store_key, update.clone())?;
+
+            if successful {
+                return Ok(());
+            } else {
+                sleep(250).await;
+            }
+        }
+
+        log_error!(self.logger, "Could not get wallet lock after 10 retries");
+        Err(MutinyError::WalletOperationFailed)
+    }
+
+    /// Starts the chain of every wallet that hasn't synced yet at the tip of a verified
+    /// snapshot, including one added later like the segwit wallet. Returns false if
+    /// there was no such wallet.
+    ///
+    /// This only saves work with compact filters, where the first sync catches up
+    /// from the snapshot's tip instead of trusting the server's. Esplora looks up
+    /// every address on each sync, so there the snapshot only sets the chain tip.
+    pub(crate) async fn bootstrap_from_snapshot(
+        &self,
+        url: &str,
+        signing_key: &XOnlyPublicKey,
+    ) -> Result<bool, MutinyError> {
+        let mut fresh = vec![];
+        for (wallet, store_key) in self.wallets() {
+            if wallet.try_read()?.latest_checkpoint().height() == 0 {
+                fresh.push((wallet, store_key));
+            }
+        }
+        if fresh.is_empty() {
+            return Ok(false);
+        }
+
+        let fetched = match fetch_chain_snapshot(&Client::new(), url).await {
+            Ok(snapshot) => snapshot.verify(self.network, signing_key),
+            Err(e) => Err(e),
+        };
+        // fall back to the tip we verified before, still better than starting from genesis
+        let tip = match (fetched, get_chain_snapshot_tip(&self.storage)?) {
+            (Ok(tip), _) => tip,
+            (Err(e), Some(tip)) => {
+                log_warn!(self.logger, "Using stored chain snapshot tip: {e}");
+                tip
+            }
+            (Err(e), None) => return Err(e),
+        };
+
+        for (wallet, store_key) in fresh {
+            let mut wallet = wallet.try_write()?;
+            wallet.insert_checkpoint(tip.clone().into())?;
+            if let Some(changeset) = wallet.take_staged() {
+                self.storage.write_changes_to(store_key, &changeset)?;
+            }
+        }
+        persist_chain_snapshot_tip(&self.storage, &tip)?;
+        log_info!(
+            self.logger,
+            "Bootstrapped wallet from chain snapshot at height {}",
+            tip.height
+        );
+
+        Ok(true)
+    }
+
     pub async fn full_sync(&self, gap: usize) -> Result<(), MutinyError> {
+        for (wallet, store_key) in self.wallets() {
+            self.full_sync_wallet(wallet, store_key, gap, 0, None)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Scans every address of the wallet again until `gap` unused addresses in a row,
+    /// to find funds a normal sync missed. Transactions confirmed before the start
+    /// height are skipped, use 0 to rescan everything.
+    ///
+    /// This can take minutes over esplora, the progress callback is given an
+    /// estimate from 0 to 99 based on how many addresses have been looked at.
+    pub async fn rescan(
+        &self,
+        start_height: u32,
+        gap: usize,
+        progress: Option<Arc<dyn Fn(u8) + Send + Sync>>,
+    ) -> Result<(), MutinyError> {
+        // we can't know how far we need to go, assume up to the gap past the addresses we revealed
+        let mut expected = 0;
+        for (wallet, _) in self.wallets() {
+            let wallet = wallet.try_read()?;
+            for keychain in [KeychainKind::External, KeychainKind::Internal] {
+                let revealed = wallet.derivation_index(keychain).map_or(0, |i| i + 1);
+                expected += revealed as u64 + gap as u64;
+            }
+        }
+
+        let scanned = Arc::new(AtomicU64::new(0));
+        let inspect = progress.map(|progress| {
+            let scanned = scanned.clone();
+            Arc::new(move || {
+                let count = scanned.fetch_add(1, Ordering::Relaxed) + 1;
+                let percent = (count * 100 / expected.max(1)).min(99);
+                // only report when the percentage changes
+                if percent > (count - 1) * 100 / expected.max(1) {
+                    progress(percent as u8);
+                }
+            }) as Arc<dyn Fn() + Send + Sync>
+        });
+
+        for (wallet, store_key) in self.wallets() {
+            self.full_sync_wallet(wallet, store_key, gap, start_height, inspect.clone())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn full_sync_wallet(
+        &self,
+        wallet: &RwLock<Wallet>,
+        store_key: &str,
+        gap: usize,
+        start_height: u32,
+        inspect: Option<Arc<dyn Fn() + Send + Sync>>,
+    ) -> Result<(), MutinyError> {
         // get first wallet lock that only needs to read
         let spks = {
-            if let Ok(wallet) = self.wallet.try_read() {
+            if let Ok(wallet) = wallet.try_read() {
                 wallet.all_unbounded_spk_iters()
             } else {
                 log_error!(self.logger, "Could not get wallet lock to sync");
@@ -282,15 +747,33 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         for (kind, pks) in spks.into_iter() {
             request_builder = request_builder.spks_for_keychain(kind, pks)
         }
+        if let Some(inspect) = inspect {
+            request_builder = request_builder.inspect(move |_, _, _| inspect());
+        }
 
         let FullScanResult {
-            tx_update,
+            mut tx_update,
             last_active_indices,
             chain_update,
         } = self
             .blockchain
             .full_scan(request_builder, gap, PARALLEL_REQUESTS)
             .await?;
+
+        if start_height > 0 {
+            let skipped = tx_update
+                .anchors
+                .iter()
+                .filter(|(anchor, _)| anchor.block_id.height < start_height)
+                .map(|(_, txid)| *txid)
+                .collect::<HashSet<_>>();
+            tx_update
+                .txs
+                .retain(|tx| !skipped.contains(&tx.compute_txid()));
+            tx_update
+                .anchors
+                .retain(|(_, txid)| !skipped.contains(txid));
+        }
         let update = Update {
             last_active_indices,
             tx_update,
@@ -299,7 +782,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
 
         // get new wallet lock for writing and apply the update
         for _ in 0..10 {
-            let successful = self.try_commit_update(update.clone())?;
+            let successful = self.try_commit_update(wallet, store_key, update.clone())?;
 
             if successful {
                 return Ok(());
@@ -356,6 +839,18 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                 }
             }
             ConfirmationTime::Unconfirmed { last_seen } => {
+                // the segwit wallet only keeps it if it pays to or spends from it
+                if let Some(segwit) = self.segwit_wallet.as_ref() {
+                    let mut segwit = segwit.try_write()?;
+                    if segwit.get_tx(txid).is_none() {
+                        segwit.apply_unconfirmed_txs(vec![(tx.clone(), last_seen)]);
+                        if let Some(changeset) = segwit.take_staged() {
+                            self.storage
+                                .write_changes_to(SEGWIT_KEYCHAIN_STORE_KEY, &changeset)?;
+                        }
+                    }
+                }
+
                 // if the transaction is unconfirmed, we can just insert it
                 let mut wallet = self.wallet.try_write()?;
 
@@ -401,77 +896,246 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(())
     }
 
+    /// The height of the best block we know of
+    pub(crate) fn tip_height(&self) -> Result<u32, MutinyError> {
+        Ok(self.wallet.try_read()?.latest_checkpoint().height())
+    }
+
+    /// Utxos that are left out of automatic coin selection,
+    /// they are only spent when selected manually
+    pub fn get_frozen_utxos(&self) -> Result<HashSet<OutPoint>, MutinyError> {
+        Ok(self.storage.get_data(FROZEN_UTXOS_KEY)?.unwrap_or_default())
+    }
+
+    pub fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        let utxos = self.list_utxos()?;
+        if !utxos.iter().any(|u| u.outpoint == outpoint) {
+            return Err(MutinyError::NotFound);
+        }
+
+        // drop the ones that have been spent since they were frozen
+        let mut frozen = self.get_frozen_utxos()?;
+        frozen.retain(|o| utxos.iter().any(|u| u.outpoint == *o));
+        frozen.insert(outpoint);
+        self.storage
+            .write_data(FROZEN_UTXOS_KEY.to_string(), frozen, None)
+    }
+
+    pub fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<(), MutinyError> {
+        self.check_not_in_vault(&[outpoint])?;
+        let mut frozen = self.get_frozen_utxos()?;
+        if frozen.remove(&outpoint) {
+            self.storage
+                .write_data(FROZEN_UTXOS_KEY.to_string(), frozen, None)?;
+        }
+        Ok(())
+    }
+
+    /// Vault coins can only be spent by a vault withdrawal
+    pub(crate) fn check_not_in_vault(&self, utxos: &[OutPoint]) -> Result<(), MutinyError> {
+        let vault = get_vault(&self.storage)?;
+        if utxos.iter().any(|o| vault.utxos.contains(o)) {
+            return Err(MutinyError::VaultLocked);
+        }
+        Ok(())
+    }
+
     pub fn list_utxos(&self) -> Result<Vec<LocalOutput>, MutinyError> {
-        Ok(self.wallet.try_read()?.list_unspent().collect())
+        let mut utxos = vec![];
+        for (wallet, _) in self.wallets() {
+            utxos.extend(wallet.try_read()?.list_unspent());
+        }
+        Ok(utxos)
+    }
+
+    /// How many different transactions have paid to each of our scripts
+    fn receive_counts(wallet: &Wallet) -> HashMap<ScriptBuf, usize> {
+        let mut counts: HashMap<ScriptBuf, usize> = HashMap::new();
+        for tx in wallet.transactions() {
+            let scripts = tx
+                .tx_node
+                .tx
+                .output
+                .iter()
+                .map(|o| &o.script_pubkey)
+                .filter(|spk| wallet.is_mine((*spk).clone()))
+                .collect::<HashSet<_>>();
+            for spk in scripts {
+                *counts.entry(spk.clone()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Whether the transaction paid to one of our addresses that has received
+    /// in other transactions too
+    fn pays_reused_address(tx: &Transaction, counts: &HashMap<ScriptBuf, usize>) -> bool {
+        tx.output
+            .iter()
+            .any(|o| counts.get(&o.script_pubkey).is_some_and(|c| *c > 1))
+    }
+
+    /// Whether the address is ours and has already received funds, so giving
+    /// it out again would link those payments together
+    pub fn is_address_reused(&self, address: &Address) -> Result<bool, MutinyError> {
+        let script = address.script_pubkey();
+        for (wallet, _) in self.wallets() {
+            let wallet = wallet.try_read()?;
+            if !wallet.is_mine(script.clone()) {
+                continue;
+            }
+            let used = wallet.transactions().any(|tx| {
+                tx.tx_node
+                    .tx
+                    .output
+                    .iter()
+                    .any(|o| o.script_pubkey == script)
+            });
+            return Ok(used);
+        }
+        Ok(false)
+    }
+
+    /// Signs the message with the key of one of our addresses, as a BIP-322
+    /// simple signature
+    pub fn sign_message_bip322(
+        &self,
+        address: &Address,
+        message: &str,
+    ) -> Result<String, MutinyError> {
+        let script_pubkey = address.script_pubkey();
+        for (wallet, _) in self.wallets() {
+            let wallet = wallet.try_read()?;
+            let Some((keychain, derivation_index)) =
+                wallet.derivation_of_spk(script_pubkey.clone())
+            else {
+                continue;
+            };
+
+            let to_spend = bip322::to_spend_tx(&script_pubkey, message.as_bytes());
+            let mut psbt = Psbt::from_unsigned_tx(bip322::to_sign_tx(&to_spend))
+                .map_err(|_| MutinyError::WalletSigningFailed)?;
+            // the virtual output is never in the wallet, so describe it like one of ours
+            let utxo = LocalOutput {
+                outpoint: OutPoint::new(to_spend.compute_txid(), 0),
+                txout: to_spend.output[0].clone(),
+                keychain,
+                is_spent: false,
+                derivation_index,
+                confirmation_time: ConfirmationTime::Unconfirmed { last_seen: 0 },
+            };
+            psbt.inputs[0] = wallet.get_psbt_input(utxo, None, true)?;
+
+            let sign_options = SignOptions {
+                trust_witness_utxo: true,
+                ..Default::default()
+            };
+            if !wallet.sign(&mut psbt, sign_options)? {
+                return Err(MutinyError::WalletSigningFailed);
+            }
+            let witness = psbt.inputs[0]
+                .final_script_witness
+                .as_ref()
+                .ok_or(MutinyError::WalletSigningFailed)?;
+            return Ok(bip322::encode_signature(witness));
+        }
+
+        Err(MutinyError::NotFound)
     }
 
     pub fn list_transactions(
         &self,
         include_raw: bool,
     ) -> Result<Vec<TransactionDetails>, MutinyError> {
-        if let Ok(wallet) = self.wallet.try_read() {
-            let txs = wallet
-                .transactions()
-                .filter_map(|tx| {
-                    // skip txs that were not relevant to our bdk wallet
-                    if wallet.spk_index().is_tx_relevant(&tx.tx_node.tx) {
-                        let (sent, received) = wallet.sent_and_received(&tx.tx_node.tx);
-
-                        let transaction = if include_raw {
-                            Some(tx.tx_node.tx.clone())
-                        } else {
-                            None
-                        };
+        let mut txs: Vec<TransactionDetails> = vec![];
+        let mut positions: HashMap<Txid, usize> = HashMap::new();
+        for (wallet, _) in self.wallets() {
+            let Ok(wallet) = wallet.try_read() else {
+                log_error!(
+                    self.logger,
+                    "Could not get wallet lock to list transactions"
+                );
+                return Err(MutinyError::WalletOperationFailed);
+            };
+            let receive_counts = Self::receive_counts(&wallet);
 
-                        let fee = wallet.calculate_fee(&tx.tx_node.tx).ok();
-
-                        Some(TransactionDetails {
-                            transaction: transaction.map(|t| Transaction::clone(&t)),
-                            txid: Some(tx.tx_node.txid),
-                            internal_id: tx.tx_node.txid,
-                            received: received.to_sat(),
-                            sent: sent.to_sat(),
-                            fee: fee.map(|f| f.to_sat()),
-                            confirmation_time: tx.chain_position.cloned().into(),
-                            labels: vec![],
-                        })
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            return Ok(txs);
-        }
-        log_error!(
-            self.logger,
-            "Could not get wallet lock to list transactions"
-        );
-        Err(MutinyError::WalletOperationFailed)
-    }
+            for tx in wallet.transactions() {
+                // skip txs that were not relevant to our bdk wallet
+                if !wallet.spk_index().is_tx_relevant(&tx.tx_node.tx) {
+                    continue;
+                }
+                let (sent, received) = wallet.sent_and_received(&tx.tx_node.tx);
 
-    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
-        let wallet = self.wallet.try_read()?;
-        let bdk_tx = wallet.get_tx(txid);
+                let transaction = if include_raw {
+                    Some(tx.tx_node.tx.clone())
+                } else {
+                    None
+                };
 
-        match bdk_tx {
-            None => Ok(None),
-            Some(tx) => {
-                let (sent, received) = wallet.sent_and_received(&tx.tx_node.tx);
                 let fee = wallet.calculate_fee(&tx.tx_node.tx).ok();
+
                 let details = TransactionDetails {
-                    transaction: Some(Transaction::clone(&tx.tx_node.tx)),
-                    txid: Some(txid),
-                    internal_id: txid,
+                    transaction: transaction.map(|t| Transaction::clone(&t)),
+                    txid: Some(tx.tx_node.txid),
+                    internal_id: tx.tx_node.txid,
                     received: received.to_sat(),
                     sent: sent.to_sat(),
-                    fee: fee.map(|fee| fee.to_sat()),
+                    fee: fee.map(|f| f.to_sat()),
                     confirmation_time: tx.chain_position.cloned().into(),
                     labels: vec![],
+                    replaces: vec![],
+                    address_reused: Self::pays_reused_address(&tx.tx_node.tx, &receive_counts),
                 };
 
-                Ok(Some(details))
+                // a transaction can touch both of our wallets, combine their views of it
+                match positions.get(&tx.tx_node.txid) {
+                    Some(i) => merge_transaction_details(&mut txs[*i], details),
+                    None => {
+                        positions.insert(tx.tx_node.txid, txs.len());
+                        txs.push(details);
+                    }
+                }
+            }
+        }
+
+        Ok(txs)
+    }
+
+    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, MutinyError> {
+        let mut details: Option<TransactionDetails> = None;
+        for (wallet, _) in self.wallets() {
+            let wallet = wallet.try_read()?;
+            let Some(tx) = wallet.get_tx(txid) else {
+                continue;
+            };
+
+            let (sent, received) = wallet.sent_and_received(&tx.tx_node.tx);
+            let fee = wallet.calculate_fee(&tx.tx_node.tx).ok();
+            let receive_counts = Self::receive_counts(&wallet);
+            let wallet_details = TransactionDetails {
+                transaction: Some(Transaction::clone(&tx.tx_node.tx)),
+                txid: Some(txid),
+                internal_id: txid,
+                received: received.to_sat(),
+                sent: sent.to_sat(),
+                fee: fee.map(|fee| fee.to_sat()),
+                confirmation_time: tx.chain_position.cloned().into(),
+                labels: vec![],
+                replaces: vec![],
+                address_reused: Self::pays_reused_address(&tx.tx_node.tx, &receive_counts),
+            };
+
+            match details.as_mut() {
+                Some(existing) => merge_transaction_details(existing, wallet_details),
+                None => details = Some(wallet_details),
             }
         }
+
+        if let Some(details) = details.as_mut() {
+            details.replaces = get_replaced_txids(&self.storage, txid)?;
+        }
+
+        Ok(details)
     }
 
     #[allow(dead_code)]
@@ -506,43 +1170,114 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     }
 
     #[allow(dead_code)]
+    /// Labels the outputs of a transaction we're making. Our own outputs, the change,
+    /// also keep the labels of the coins they were made from so a label isn't lost
+    /// the first time a labeled coin is spent.
     pub(crate) fn label_psbt(&self, psbt: &Psbt, labels: Vec<String>) -> Result<(), MutinyError> {
-        let mut prev_labels = vec![];
+        let agg_labels = dedup_labels(labels);
 
-        // add on new labels
-        prev_labels.extend(labels);
+        // find the labels of the coins being spent
+        let address_labels = self.storage.get_address_labels()?;
+        let mut input_addresses = vec![];
+        let mut inherited = vec![];
+        for (input, txin) in psbt.inputs.iter().zip(psbt.unsigned_tx.input.iter()) {
+            let prev_out = input.witness_utxo.clone().or_else(|| {
+                input
+                    .non_witness_utxo
+                    .as_ref()
+                    .and_then(|tx| tx.output.get(txin.previous_output.vout as usize).cloned())
+            });
+            let Some(address) =
+                prev_out.and_then(|o| Address::from_script(&o.script_pubkey, self.network).ok())
+            else {
+                continue;
+            };
+            let address = address.to_string();
+            if let Some(labels) = address_labels.get(&address) {
+                inherited.extend(labels.iter().filter(|l| !agg_labels.contains(l)).cloned());
+            }
+            input_addresses.push(address);
+        }
+        let inherited = dedup_labels(inherited);
 
-        // deduplicate labels and create aggregate label
-        // we use a HashSet to deduplicate so we can retain the order of the labels
-        let mut seen = HashSet::new();
-        let agg_labels = prev_labels
-            .into_iter()
-            .filter(|s| seen.insert(s.clone()))
-            .collect::<Vec<_>>();
+        let txid = psbt.unsigned_tx.compute_txid();
+        for output in psbt.unsigned_tx.output.iter() {
+            let Ok(addr) = Address::from_script(&output.script_pubkey, self.network) else {
+                continue;
+            };
 
-        // add output addresses to previous addresses
-        let addresses = psbt
-            .unsigned_tx
-            .output
-            .iter()
-            .filter_map(|o| Address::from_script(&o.script_pubkey, self.network).ok())
-            .collect::<Vec<_>>();
+            if inherited.is_empty() || !self.is_mine(&output.script_pubkey)? {
+                self.storage.set_address_labels(addr, agg_labels.clone())?;
+                continue;
+            }
 
-        // set label for send to address
-        for addr in addresses {
-            self.storage.set_address_labels(addr, agg_labels.clone())?;
+            let mut change_labels = agg_labels.clone();
+            change_labels.extend(inherited.iter().cloned());
+            self.storage
+                .set_address_labels(addr.clone(), change_labels)?;
+            persist_label_lineage(
+                &self.storage,
+                &LabelLineage {
+                    address: addr.to_string(),
+                    txid,
+                    input_addresses: input_addresses.clone(),
+                    inherited_labels: inherited.clone(),
+                    corrected: false,
+                    created_at: now().as_secs(),
+                },
+            )?;
         }
 
         Ok(())
     }
 
-    pub fn create_signed_psbt(
+    /// Whether the script belongs to one of our wallets
+    pub(crate) fn is_mine(&self, script: &ScriptBuf) -> Result<bool, MutinyError> {
+        for (wallet, _) in self.wallets() {
+            if wallet.try_read()?.is_mine(script.clone()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// With the withdrawal allowlist on, every script must be ours or an
+    /// allowed destination
+    pub(crate) fn check_allowed_destinations(
         &self,
-        send_to: Address,
-        amount: u64,
+        scripts: &[&ScriptBuf],
+    ) -> Result<(), MutinyError> {
+        let allowlist = get_withdrawal_allowlist(&self.storage, now().as_secs())?;
+        if !allowlist.enabled {
+            return Ok(());
+        }
+        let secp = Secp256k1::verification_only();
+        for script in scripts {
+            if !self.is_mine(script)? && !allowlist.allows(&secp, script, self.network) {
+                return Err(MutinyError::DestinationNotAllowed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every output of the transaction against the withdrawal allowlist
+    pub(crate) fn check_allowed_tx(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let scripts = tx
+            .output
+            .iter()
+            .map(|o| &o.script_pubkey)
+            .collect::<Vec<_>>();
+        self.check_allowed_destinations(&scripts)
+    }
+
+    pub fn create_signed_psbt(
+        &self,
+        send_to: Address,
+        amount: u64,
         fee_rate: Option<u64>,
+        utxos: &[OutPoint],
     ) -> Result<Psbt, MutinyError> {
-        self.create_signed_psbt_to_spk(send_to.script_pubkey(), amount, fee_rate)
+        self.create_signed_psbt_with_utxos(send_to.script_pubkey(), amount, fee_rate, utxos)
     }
 
     pub fn create_signed_psbt_to_spk(
@@ -551,7 +1286,48 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount: u64,
         fee_rate: Option<u64>,
     ) -> Result<Psbt, MutinyError> {
-        let mut wallet = self.wallet.try_write()?;
+        self.create_signed_psbt_with_utxos(spk, amount, fee_rate, &[])
+    }
+
+    /// Creates a signed PSBT paying the given amount to the script.
+    /// If any utxos are given only those will be spent, even if they are frozen,
+    /// otherwise coin selection is left to BDK and frozen utxos are skipped.
+    pub fn create_signed_psbt_with_utxos(
+        &self,
+        spk: ScriptBuf,
+        amount: u64,
+        fee_rate: Option<u64>,
+        utxos: &[OutPoint],
+    ) -> Result<Psbt, MutinyError> {
+        self.create_signed_psbt_to_recipients(
+            vec![(spk, Amount::from_sat(amount))],
+            fee_rate,
+            utxos,
+        )
+    }
+
+    /// Creates a single signed transaction paying all of the given outputs
+    pub fn create_signed_psbt_to_recipients(
+        &self,
+        recipients: Vec<(ScriptBuf, Amount)>,
+        fee_rate: Option<u64>,
+        utxos: &[OutPoint],
+    ) -> Result<Psbt, MutinyError> {
+        let mut psbt = self.create_unsigned_psbt(recipients, fee_rate, utxos)?;
+        let finalized = self.sign_own_psbt(&mut psbt)?;
+        log_debug!(self.logger, "finalized: {finalized}");
+        Ok(psbt)
+    }
+
+    /// Creates a transaction paying all of the given outputs without signing it,
+    /// so it can be signed elsewhere, e.g. by a hardware wallet
+    pub fn create_unsigned_psbt(
+        &self,
+        recipients: Vec<(ScriptBuf, Amount)>,
+        fee_rate: Option<u64>,
+        utxos: &[OutPoint],
+    ) -> Result<Psbt, MutinyError> {
+        self.check_not_in_vault(utxos)?;
 
         let fee_rate = if let Some(rate) = fee_rate {
             FeeRate::from_sat_per_vb(rate).ok_or(MutinyError::InvalidFeerate)?
@@ -559,31 +1335,239 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             let sat_per_kwu = self.fees.get_normal_fee_rate();
             FeeRate::from_sat_per_kwu(sat_per_kwu.into())
         };
-        let mut psbt = {
-            let mut builder = wallet.build_tx();
-            builder
-                .add_recipient(spk, Amount::from_sat(amount))
-                .enable_rbf()
-                .fee_rate(fee_rate);
-            builder.finish()?
+
+        // segwit coins are spent as foreign inputs of the taproot wallet's transaction
+        let psbt = if utxos.is_empty() {
+            // prefer not mixing address types, only add the segwit coins if we need them
+            match self.build_psbt(&recipients, fee_rate, &[], &[], false) {
+                Ok(psbt) => psbt,
+                Err(e) => {
+                    let segwit_utxos = self.segwit_foreign_utxos(None)?;
+                    if segwit_utxos.is_empty() {
+                        return Err(e);
+                    }
+                    self.build_psbt(&recipients, fee_rate, &[], &segwit_utxos, false)?
+                }
+            }
+        } else {
+            let segwit_utxos = self.segwit_foreign_utxos(Some(utxos))?;
+            let taproot_utxos = utxos
+                .iter()
+                .filter(|o| !segwit_utxos.iter().any(|(s, _, _)| s == *o))
+                .copied()
+                .collect::<Vec<_>>();
+            self.build_psbt(&recipients, fee_rate, &taproot_utxos, &segwit_utxos, true)?
         };
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
-        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-        log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
     }
 
+    fn build_psbt(
+        &self,
+        recipients: &[(ScriptBuf, Amount)],
+        fee_rate: FeeRate,
+        utxos: &[OutPoint],
+        foreign_utxos: &[(OutPoint, Input, Weight)],
+        manually_selected_only: bool,
+    ) -> Result<Psbt, MutinyError> {
+        // frozen coins can only be spent by selecting them manually
+        let unspendable = if manually_selected_only {
+            None
+        } else {
+            Some(self.get_frozen_utxos()?.into_iter().collect::<Vec<_>>())
+        };
+
+        if let (Some(unspendable), CoinSelectionStrategy::Privacy) =
+            (unspendable.as_ref(), self.coin_selection)
+        {
+            if let Some(psbt) =
+                self.build_single_address_psbt(recipients, fee_rate, foreign_utxos, unspendable)?
+            {
+                return Ok(psbt);
+            }
+        }
+
+        let mut wallet = self.wallet.try_write()?;
+        match self.coin_selection {
+            CoinSelectionStrategy::OldestFirst => finish_psbt(
+                wallet.build_tx().coin_selection(OldestFirstCoinSelection),
+                recipients,
+                fee_rate,
+                utxos,
+                foreign_utxos,
+                unspendable,
+            ),
+            CoinSelectionStrategy::BranchAndBound | CoinSelectionStrategy::Privacy => finish_psbt(
+                wallet.build_tx(),
+                recipients,
+                fee_rate,
+                utxos,
+                foreign_utxos,
+                unspendable,
+            ),
+        }
+    }
+
+    /// Tries to fund the transaction from the coins of a single address so the
+    /// transaction doesn't link our addresses together. Coins sharing an address
+    /// are already linked, so all of them get spent together. Starts with the
+    /// smallest address that could cover the amount, returns `None` if none can.
+    fn build_single_address_psbt(
+        &self,
+        recipients: &[(ScriptBuf, Amount)],
+        fee_rate: FeeRate,
+        foreign_utxos: &[(OutPoint, Input, Weight)],
+        unspendable: &[OutPoint],
+    ) -> Result<Option<Psbt>, MutinyError> {
+        // foreign coins are spent in full, that already mixes addresses
+        if !foreign_utxos.is_empty() {
+            return Ok(None);
+        }
+
+        let target: Amount = recipients.iter().map(|(_, amount)| *amount).sum();
+        let mut groups: HashMap<ScriptBuf, (Amount, Vec<OutPoint>)> = HashMap::new();
+        for utxo in self.wallet.try_read()?.list_unspent() {
+            if unspendable.contains(&utxo.outpoint) {
+                continue;
+            }
+            let group = groups.entry(utxo.txout.script_pubkey).or_default();
+            group.0 += utxo.txout.value;
+            group.1.push(utxo.outpoint);
+        }
+
+        let mut groups = groups
+            .into_values()
+            .filter(|(total, _)| *total > target)
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|(total, _)| *total);
+
+        let mut wallet = self.wallet.try_write()?;
+        for (_, outpoints) in groups {
+            // not enough left over for the fee, try the next address
+            if let Ok(psbt) = finish_psbt(
+                wallet.build_tx(),
+                recipients,
+                fee_rate,
+                &outpoints,
+                &[],
+                None,
+            ) {
+                return Ok(Some(psbt));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The segwit wallet's utxos as inputs the taproot wallet can spend,
+    /// either only the given ones or all that aren't frozen
+    fn segwit_foreign_utxos(
+        &self,
+        outpoints: Option<&[OutPoint]>,
+    ) -> Result<Vec<(OutPoint, Input, Weight)>, MutinyError> {
+        let Some(segwit) = self.segwit_wallet.as_ref() else {
+            return Ok(vec![]);
+        };
+        let frozen = self.get_frozen_utxos()?;
+        let segwit = segwit.try_read()?;
+        let satisfaction_weight = segwit
+            .public_descriptor(KeychainKind::External)
+            .max_weight_to_satisfy()
+            .map_err(|_| MutinyError::WalletOperationFailed)?;
+
+        segwit
+            .list_unspent()
+            .filter(|u| match outpoints {
+                Some(o) => o.contains(&u.outpoint),
+                None => !frozen.contains(&u.outpoint),
+            })
+            .map(|u| {
+                let prev_tx = segwit
+                    .get_tx(u.outpoint.txid)
+                    .ok_or(MutinyError::WalletOperationFailed)?;
+                let input = Input {
+                    witness_utxo: Some(u.txout.clone()),
+                    non_witness_utxo: Some(Transaction::clone(&prev_tx.tx_node.tx)),
+                    ..Default::default()
+                };
+                Ok((u.outpoint, input, satisfaction_weight))
+            })
+            .collect()
+    }
+
+    /// Adds our signatures to a PSBT built elsewhere, returns whether it is now
+    /// fully signed. Vault and frozen coins are refused, they can only be spent
+    /// through the wallet's own transactions.
+    pub fn sign_psbt(&self, psbt: &mut Psbt) -> Result<bool, MutinyError> {
+        self.check_inputs_spendable(&psbt.unsigned_tx)?;
+        self.sign_own_psbt(psbt)
+    }
+
+    /// Refuses transactions spending vault or frozen coins
+    pub(crate) fn check_inputs_spendable(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let inputs = tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .collect::<Vec<_>>();
+        self.check_not_in_vault(&inputs)?;
+        let frozen = self.get_frozen_utxos()?;
+        if inputs.iter().any(|o| frozen.contains(o)) {
+            return Err(MutinyError::WalletOperationFailed);
+        }
+        Ok(())
+    }
+
+    fn sign_own_psbt(&self, psbt: &mut Psbt) -> Result<bool, MutinyError> {
+        // each wallet finalizes the inputs it signs and skips already finalized ones,
+        // so the last one tells us if the whole PSBT is done
+        if let Some(segwit) = self.segwit_wallet.as_ref() {
+            segwit.try_read()?.sign(psbt, SignOptions::default())?;
+        }
+        let wallet = self.wallet.try_read()?;
+        Ok(wallet.sign(psbt, SignOptions::default())?)
+    }
+
+    /// Finalizes a fully signed PSBT and broadcasts its transaction
+    pub async fn broadcast_psbt(
+        &self,
+        mut psbt: Psbt,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        {
+            if let Some(segwit) = self.segwit_wallet.as_ref() {
+                segwit
+                    .try_read()?
+                    .finalize_psbt(&mut psbt, SignOptions::default())?;
+            }
+            let wallet = self.wallet.try_read()?;
+            if !wallet.finalize_psbt(&mut psbt, SignOptions::default())? {
+                return Err(MutinyError::WalletSigningFailed);
+            }
+        }
+        let raw_transaction = psbt.clone().extract_tx()?;
+        self.check_allowed_tx(&raw_transaction)?;
+        self.label_psbt(&psbt, labels)?;
+        let txid = raw_transaction.compute_txid();
+
+        self.broadcast_transaction(raw_transaction).await?;
+        log_debug!(self.logger, "PSBT broadcast! TXID: {txid}");
+        Ok(txid)
+    }
+
     pub async fn send(
         &self,
         destination_address: Address,
         amount: u64,
         labels: Vec<String>,
         fee_rate: Option<u64>,
+        utxos: &[OutPoint],
     ) -> Result<Txid, MutinyError> {
-        let psbt = self.create_signed_psbt(destination_address, amount, fee_rate)?;
+        let psbt = self.create_signed_psbt(destination_address, amount, fee_rate, utxos)?;
+        let raw_transaction = psbt.clone().extract_tx()?;
+        self.check_allowed_tx(&raw_transaction)?;
         self.label_psbt(&psbt, labels)?;
 
-        let raw_transaction = psbt.extract_tx()?;
         let txid = raw_transaction.compute_txid();
 
         self.broadcast_transaction(raw_transaction).await?;
@@ -591,6 +1575,41 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(txid)
     }
 
+    /// Pays several addresses in one transaction, each with their own labels.
+    pub async fn send_batch(
+        &self,
+        recipients: Vec<(Address, u64, Vec<String>)>,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        if recipients.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let outputs = recipients
+            .iter()
+            .map(|(address, amount, _)| (address.script_pubkey(), Amount::from_sat(*amount)))
+            .collect();
+        let psbt = self.create_signed_psbt_to_recipients(outputs, fee_rate, &[])?;
+        let raw_transaction = psbt.clone().extract_tx()?;
+        self.check_allowed_tx(&raw_transaction)?;
+
+        // the change gets everyone's labels, then each recipient gets only their own
+        let all_labels = recipients
+            .iter()
+            .flat_map(|(_, _, labels)| labels.clone())
+            .collect();
+        self.label_psbt(&psbt, all_labels)?;
+        for (address, _, labels) in recipients {
+            self.storage.set_address_labels(address, labels)?;
+        }
+
+        let txid = raw_transaction.compute_txid();
+
+        self.broadcast_transaction(raw_transaction).await?;
+        log_debug!(self.logger, "Batch transaction broadcast! TXID: {txid}");
+        Ok(txid)
+    }
+
     pub async fn send_payjoin(
         &self,
         mut original_psbt: Psbt,
@@ -638,8 +1657,9 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         result?;
         drop(wallet);
 
+        let payjoin = proposal_psbt.clone().extract_tx()?;
+        self.check_allowed_tx(&payjoin)?;
         self.label_psbt(&proposal_psbt, labels)?;
-        let payjoin = proposal_psbt.extract_tx()?;
 
         Ok(payjoin)
     }
@@ -650,6 +1670,8 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         fee_rate: Option<u64>,
         allow_dust: Option<bool>,
     ) -> Result<Psbt, MutinyError> {
+        let segwit_utxos = self.segwit_foreign_utxos(None)?;
+        let frozen = self.get_frozen_utxos()?;
         let mut wallet = self.wallet.try_write()?;
 
         let fee_rate = if let Some(rate) = fee_rate {
@@ -660,8 +1682,13 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         };
         let mut psbt = {
             let mut builder = wallet.build_tx();
+            // the segwit wallet's coins get swept too
+            for (outpoint, input, weight) in segwit_utxos {
+                builder.add_foreign_utxo(outpoint, input, weight)?;
+            }
             builder
                 .drain_wallet() // Spend all outputs in this wallet.
+                .unspendable(frozen.into_iter().collect()) // except the frozen ones
                 .drain_to(spk)
                 .enable_rbf()
                 .allow_dust(allow_dust.unwrap_or_default())
@@ -669,15 +1696,16 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             builder.finish()?
         };
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
-        let finalized = wallet.sign(
-            &mut psbt,
-            SignOptions {
-                trust_witness_utxo: true,
-                try_finalize: true,
-                allow_all_sighashes: true,
-                ..Default::default()
-            },
-        )?;
+        let sign_options = SignOptions {
+            trust_witness_utxo: true,
+            try_finalize: true,
+            allow_all_sighashes: true,
+            ..Default::default()
+        };
+        if let Some(segwit) = self.segwit_wallet.as_ref() {
+            segwit.try_read()?.sign(&mut psbt, sign_options.clone())?;
+        }
+        let finalized = wallet.sign(&mut psbt, sign_options)?;
         log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
     }
@@ -691,9 +1719,10 @@ impl<S: MutinyStorage> OnChainWallet<S> {
     ) -> Result<Txid, MutinyError> {
         let psbt =
             self.create_sweep_psbt(destination_address.script_pubkey(), fee_rate, allow_dust)?;
+        let raw_transaction = psbt.clone().extract_tx()?;
+        self.check_allowed_tx(&raw_transaction)?;
         self.label_psbt(&psbt, labels)?;
 
-        let raw_transaction = psbt.extract_tx()?;
         let txid = raw_transaction.compute_txid();
 
         self.broadcast_transaction(raw_transaction).await?;
@@ -701,6 +1730,40 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(txid)
     }
 
+    /// Sweeps every coin held by a single private key, e.g. a paper wallet,
+    /// into a new address of this wallet. The fee rate is in sat/vbyte.
+    pub async fn sweep_private_key(
+        &self,
+        key: PrivateKey,
+        fee_rate: Option<u64>,
+    ) -> Result<Txid, MutinyError> {
+        if key.network != self.network.into() {
+            return Err(MutinyError::IncorrectNetwork);
+        }
+
+        let secp = Secp256k1::new();
+        let scripts = key_scripts(&secp, &key);
+        let utxos = find_key_utxos(&self.blockchain, &scripts).await?;
+        if utxos.is_empty() {
+            return Err(MutinyError::NotFound);
+        }
+
+        let fee_rate = match fee_rate {
+            Some(rate) => rate,
+            None => {
+                let sat_per_kwu = self.fees.get_normal_fee_rate() as u64;
+                (sat_per_kwu * 4).div_ceil(1_000)
+            }
+        };
+        let destination = self.get_new_address(AddressType::Taproot)?;
+        let tx = build_sweep_tx(&secp, &key, &utxos, destination.script_pubkey(), fee_rate)?;
+        let txid = tx.compute_txid();
+
+        self.broadcast_transaction(tx).await?;
+        log_debug!(self.logger, "Swept private key in {txid}");
+        Ok(txid)
+    }
+
     /// Creates a PSBT that spends all the selected utxos a given output.
     /// A fee rate is not specified because it should be precalculated
     /// in the output's amount.
@@ -711,18 +1774,69 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount_sats: u64,
         absolute_fee: u64,
     ) -> Result<Psbt, MutinyError> {
+        self.check_not_in_vault(utxos)?;
+        // the segwit wallet's coins are added to the taproot wallet's transaction
+        let segwit_utxos = self.segwit_foreign_utxos(Some(utxos))?;
+        let taproot_utxos = utxos
+            .iter()
+            .filter(|o| !segwit_utxos.iter().any(|(s, _, _)| s == *o))
+            .copied()
+            .collect::<Vec<_>>();
         let mut wallet = self.wallet.try_write()?;
         let mut psbt = {
             let mut builder = wallet.build_tx();
+            for (outpoint, input, weight) in segwit_utxos {
+                builder.add_foreign_utxo(outpoint, input, weight)?;
+            }
             builder
                 .manually_selected_only()
-                .add_utxos(utxos)?
+                .add_utxos(&taproot_utxos)?
                 .add_recipient(spk, Amount::from_sat(amount_sats))
                 .fee_absolute(Amount::from_sat(absolute_fee))
                 .enable_rbf();
             builder.finish()?
         };
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
+        if let Some(segwit) = self.segwit_wallet.as_ref() {
+            segwit.try_read()?.sign(&mut psbt, SignOptions::default())?;
+        }
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        log_debug!(self.logger, "finalized: {finalized}");
+        Ok(psbt)
+    }
+
+    /// Creates a signed PSBT spending the given vault coins to the script, all of
+    /// them when there's no amount. Unlike the other ways of spending, vault coins
+    /// are allowed here so this must only be used for vault withdrawals.
+    pub(crate) fn create_vault_withdrawal_psbt(
+        &self,
+        utxos: &[OutPoint],
+        spk: ScriptBuf,
+        amount_sats: Option<u64>,
+        fee_rate: Option<u64>,
+    ) -> Result<Psbt, MutinyError> {
+        let fee_rate = if let Some(rate) = fee_rate {
+            FeeRate::from_sat_per_vb(rate).ok_or(MutinyError::InvalidFeerate)?
+        } else {
+            let sat_per_kwu = self.fees.get_normal_fee_rate();
+            FeeRate::from_sat_per_kwu(sat_per_kwu.into())
+        };
+
+        let mut wallet = self.wallet.try_write()?;
+        let mut psbt = {
+            let mut builder = wallet.build_tx();
+            builder
+                .manually_selected_only()
+                .add_utxos(utxos)?
+                .enable_rbf()
+                .fee_rate(fee_rate);
+            match amount_sats {
+                Some(amount) => builder.add_recipient(spk, Amount::from_sat(amount)),
+                None => builder.drain_to(spk),
+            };
+            builder.finish()?
+        };
+        log_debug!(self.logger, "Unsigned PSBT: {psbt}");
         let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
         log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
@@ -780,12 +1894,299 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             psbt.extract_tx()?
         };
 
-        let txid = tx.compute_txid();
+        let new_txid = tx.compute_txid();
 
         self.broadcast_transaction(tx).await?;
-        log_debug!(self.logger, "Fee bump Transaction broadcast! TXID: {txid}");
-        Ok(txid)
+        log_debug!(
+            self.logger,
+            "Fee bump Transaction broadcast! TXID: {new_txid}"
+        );
+
+        // remember the replacement so the activity can show them as one payment
+        if let Err(e) = persist_tx_replacement(&self.storage, txid, new_txid) {
+            log_warn!(self.logger, "Failed to persist tx replacement: {e}");
+        }
+
+        Ok(new_txid)
+    }
+
+    /// Speeds up an unconfirmed transaction that pays us by spending our output
+    /// of it in a child transaction (CPFP). The child pays enough fees for the
+    /// parent and child together to reach the given fee rate in sats/vbyte.
+    pub async fn accelerate_tx(&self, txid: Txid, fee_rate: u64) -> Result<Txid, MutinyError> {
+        let target_fee_rate =
+            FeeRate::from_sat_per_vb(fee_rate).ok_or(MutinyError::InvalidFeerate)?;
+
+        let (parent, parent_fee, utxo) = {
+            let wallet = self.wallet.try_read()?;
+            let tx = wallet.get_tx(txid).ok_or(MutinyError::NotFound)?;
+            if tx.chain_position.is_confirmed() {
+                return Err(MutinyError::InvalidArgumentsError);
+            }
+            let parent = Transaction::clone(&tx.tx_node.tx);
+            let fee = wallet.calculate_fee(&parent).ok().map(|f| f.to_sat());
+
+            // spend our largest output of the parent, it has the most room for fees
+            let utxo = wallet
+                .list_unspent()
+                .filter(|u| u.outpoint.txid == txid)
+                .max_by_key(|u| u.txout.value)
+                .ok_or(MutinyError::InvalidArgumentsError)?
+                .outpoint;
+
+            (parent, fee, utxo)
+        };
+        self.check_not_in_vault(&[utxo])?;
+
+        // we don't know the inputs of transactions sent to us by others, look them up
+        let parent_fee = match parent_fee {
+            Some(fee) => fee,
+            None => self.fetch_tx_fee(&parent).await?,
+        };
+        let parent_vsize = parent.vsize() as u64;
+        if parent_fee >= parent_vsize * fee_rate {
+            // already pays enough, nothing to accelerate
+            return Err(MutinyError::InvalidFeerate);
+        }
+
+        let tx = {
+            let mut wallet = self.wallet.try_write()?;
+            let change = wallet
+                .next_unused_address(KeychainKind::Internal)
+                .script_pubkey();
+
+            // build the child once at the target rate to learn its size
+            let child_vsize = {
+                let mut builder = wallet.build_tx();
+                builder
+                    .add_utxo(utxo)?
+                    .manually_selected_only()
+                    .drain_to(change.clone())
+                    .enable_rbf()
+                    .fee_rate(target_fee_rate);
+                let mut psbt = builder.finish()?;
+                wallet.sign(&mut psbt, SignOptions::default())?;
+                psbt.extract_tx()?.vsize() as u64
+            };
+
+            let fee = cpfp_child_fee(parent_vsize, parent_fee, child_vsize, fee_rate);
+            let mut builder = wallet.build_tx();
+            builder
+                .add_utxo(utxo)?
+                .manually_selected_only()
+                .drain_to(change)
+                .enable_rbf()
+                .fee_absolute(Amount::from_sat(fee));
+            let mut psbt = builder.finish()?;
+            wallet.sign(&mut psbt, SignOptions::default())?;
+
+            psbt.extract_tx()?
+        };
+
+        let child_txid = tx.compute_txid();
+        self.broadcast_transaction(tx).await?;
+        log_debug!(
+            self.logger,
+            "CPFP Transaction broadcast! TXID: {child_txid}"
+        );
+
+        Ok(child_txid)
+    }
+
+    /// Merges the small confirmed coins of the taproot wallet into a single new
+    /// output of ours when the normal fee rate is at or below the configured
+    /// maximum. The coins' labels carry over to the new output. Returns None if
+    /// fees are too high or there aren't enough small coins.
+    pub async fn consolidate_utxos(
+        &self,
+        config: &ConsolidationConfig,
+    ) -> Result<Option<Txid>, MutinyError> {
+        let sats_per_vbyte = (self.fees.get_normal_fee_rate() as u64 * 4).div_ceil(1_000);
+        if sats_per_vbyte > config.max_fee_rate {
+            return Ok(None);
+        }
+
+        let frozen = self.get_frozen_utxos()?;
+        let psbt = {
+            let mut wallet = self.wallet.try_write()?;
+            let utxos = wallet
+                .list_unspent()
+                .filter(|u| {
+                    u.confirmation_time.is_confirmed()
+                        && u.txout.value.to_sat() <= config.max_utxo_sats
+                        && !frozen.contains(&u.outpoint)
+                })
+                .map(|u| u.outpoint)
+                .collect::<Vec<_>>();
+            if utxos.len() < config.min_utxos.max(2) {
+                return Ok(None);
+            }
+
+            let destination = wallet
+                .next_unused_address(KeychainKind::Internal)
+                .script_pubkey();
+            if let Some(changeset) = wallet.take_staged() {
+                self.storage
+                    .write_changes_to(KEYCHAIN_STORE_KEY, &changeset)?;
+            }
+
+            let fee_rate =
+                FeeRate::from_sat_per_vb(sats_per_vbyte).ok_or(MutinyError::InvalidFeerate)?;
+            let mut builder = wallet.build_tx();
+            builder
+                .add_utxos(&utxos)?
+                .manually_selected_only()
+                .drain_to(destination)
+                .enable_rbf()
+                .fee_rate(fee_rate);
+            let mut psbt = builder.finish()?;
+            wallet.sign(&mut psbt, SignOptions::default())?;
+            psbt
+        };
+        self.label_psbt(&psbt, vec![])?;
+
+        let tx = psbt.extract_tx()?;
+        let txid = tx.compute_txid();
+        self.broadcast_transaction(tx).await?;
+        log_info!(self.logger, "Consolidated small coins in {txid}");
+
+        Ok(Some(txid))
+    }
+
+    /// Calculates the fee of a transaction by looking up the outputs it spends
+    async fn fetch_tx_fee(&self, tx: &Transaction) -> Result<u64, MutinyError> {
+        let mut input_value = 0;
+        for input in tx.input.iter() {
+            let prev_tx = self
+                .blockchain
+                .get_tx(&input.previous_output.txid)
+                .await
+                .map_err(|_| MutinyError::ChainAccessFailed)?
+                .ok_or(MutinyError::ChainAccessFailed)?;
+            let prev_out = prev_tx
+                .output
+                .get(input.previous_output.vout as usize)
+                .ok_or(MutinyError::ChainAccessFailed)?;
+            input_value += prev_out.value.to_sat();
+        }
+        let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+
+        input_value
+            .checked_sub(output_value)
+            .ok_or(MutinyError::ChainAccessFailed)
+    }
+}
+
+/// The fee in sats a child transaction has to pay so that the parent and child
+/// together reach the target fee rate in sats/vbyte. The child always pays at
+/// least the target rate for itself.
+pub(crate) fn cpfp_child_fee(
+    parent_vsize: u64,
+    parent_fee: u64,
+    child_vsize: u64,
+    target_fee_rate: u64,
+) -> u64 {
+    let package_fee = (parent_vsize + child_vsize) * target_fee_rate;
+    package_fee
+        .saturating_sub(parent_fee)
+        .max(child_vsize * target_fee_rate)
+}
+
+/// Combines how two of our wallets see the same transaction
+fn merge_transaction_details(details: &mut TransactionDetails, other: TransactionDetails) {
+    details.sent += other.sent;
+    details.received += other.received;
+    details.fee = details.fee.or(other.fee);
+    details.address_reused |= other.address_reused;
+    if details.transaction.is_none() {
+        details.transaction = other.transaction;
+    }
+}
+
+/// Finishes building a transaction with whichever coin selection the builder uses.
+/// Without a list of unspendable coins only the given utxos are spent.
+fn finish_psbt<Cs: CoinSelectionAlgorithm>(
+    mut builder: TxBuilder<'_, Cs>,
+    recipients: &[(ScriptBuf, Amount)],
+    fee_rate: FeeRate,
+    utxos: &[OutPoint],
+    foreign_utxos: &[(OutPoint, Input, Weight)],
+    unspendable: Option<Vec<OutPoint>>,
+) -> Result<Psbt, MutinyError> {
+    match unspendable {
+        Some(unspendable) => {
+            builder.unspendable(unspendable);
+        }
+        None => {
+            builder.manually_selected_only();
+        }
+    }
+    if !utxos.is_empty() {
+        builder.add_utxos(utxos)?;
     }
+    for (outpoint, input, weight) in foreign_utxos {
+        builder.add_foreign_utxo(*outpoint, input.clone(), *weight)?;
+    }
+    builder
+        .set_recipients(recipients.to_vec())
+        .enable_rbf()
+        .fee_rate(fee_rate);
+    Ok(builder.finish()?)
+}
+
+/// Loads the bdk wallet from storage, creating a new one if we don't have one yet
+fn load_or_create_wallet<S: MutinyStorage, D>(
+    descriptor: D,
+    change_descriptor: D,
+    extract_keys: bool,
+    store_key: &str,
+    db: &S,
+    network: Network,
+    logger: &MutinyLogger,
+) -> Result<Wallet, MutinyError>
+where
+    D: IntoWalletDescriptor + Send + Clone + 'static,
+{
+    let load_wallet_res = db.read_changes_from(store_key)?.map(|changeset| {
+        let params = LoadParams::new()
+            .descriptor(KeychainKind::External, Some(descriptor.clone()))
+            .descriptor(KeychainKind::Internal, Some(change_descriptor.clone()));
+        let params = if extract_keys {
+            params.extract_keys()
+        } else {
+            params
+        };
+        Wallet::load_with_params(changeset, params)
+    });
+    let wallet = match load_wallet_res {
+        Some(Ok(Some(wallet))) => wallet,
+        None | Some(Ok(None)) => {
+            // we don't have a bdk wallet, create one
+            Wallet::create_with_params(
+                CreateParams::new(descriptor, change_descriptor).network(network),
+            )?
+        }
+        Some(Err(bdk_wallet::LoadError::Mismatch(_))) => {
+            // failed to read storage, means we have old encoding and need to delete and re-init wallet
+            db.delete(&[store_key])?;
+            db.write_data(NEED_FULL_SYNC_KEY.to_string(), true, None)?;
+            Wallet::create_with_params(
+                CreateParams::new(descriptor, change_descriptor).network(network),
+            )?
+        }
+        Some(Err(e)) => {
+            log_error!(logger, "Failed to load wallet: {e}");
+            return Err(MutinyError::WalletOperationFailed);
+        }
+    };
+
+    Ok(wallet)
+}
+
+/// Descriptors for a watch-only wallet of an account-level xpub,
+/// using the same taproot layout as our own wallet
+pub fn watch_only_descriptors_for_xpub(xpub: Xpub) -> (String, String) {
+    (format!("tr({xpub}/0/*)"), format!("tr({xpub}/1/*)"))
 }
 
 fn get_tr_descriptors_for_extended_key(
@@ -793,19 +2194,37 @@ fn get_tr_descriptors_for_extended_key(
     network: Network,
     account_number: u32,
 ) -> Result<(DescriptorTemplateOut, DescriptorTemplateOut), MutinyError> {
-    let coin_type = coin_type_from_network(network);
+    let derivation_path = get_account_derivation_path(network, account_number)?;
 
-    let base_path = DerivationPath::from_str("m/86'")?;
-    let derivation_path = base_path.extend([
+    let receive_descriptor_template = bdk_wallet::descriptor!(tr((
+        master_xprv,
+        derivation_path.extend([ChildNumber::Normal { index: 0 }])
+    )))?;
+    let change_descriptor_template = bdk_wallet::descriptor!(tr((
+        master_xprv,
+        derivation_path.extend([ChildNumber::Normal { index: 1 }])
+    )))?;
+
+    Ok((receive_descriptor_template, change_descriptor_template))
+}
+
+/// BIP84 descriptors for the native segwit wallet
+fn get_wpkh_descriptors_for_extended_key(
+    master_xprv: Xpriv,
+    network: Network,
+    account_number: u32,
+) -> Result<(DescriptorTemplateOut, DescriptorTemplateOut), MutinyError> {
+    let coin_type = coin_type_from_network(network);
+    let derivation_path = DerivationPath::from_str("m/84'")?.extend([
         ChildNumber::from_hardened_idx(coin_type)?,
         ChildNumber::from_hardened_idx(account_number)?,
     ]);
 
-    let receive_descriptor_template = bdk_wallet::descriptor!(tr((
+    let receive_descriptor_template = bdk_wallet::descriptor!(wpkh((
         master_xprv,
         derivation_path.extend([ChildNumber::Normal { index: 0 }])
     )))?;
-    let change_descriptor_template = bdk_wallet::descriptor!(tr((
+    let change_descriptor_template = bdk_wallet::descriptor!(wpkh((
         master_xprv,
         derivation_path.extend([ChildNumber::Normal { index: 1 }])
     )))?;
@@ -813,6 +2232,42 @@ fn get_tr_descriptors_for_extended_key(
     Ok((receive_descriptor_template, change_descriptor_template))
 }
 
+fn get_account_derivation_path(
+    network: Network,
+    account_number: u32,
+) -> Result<DerivationPath, MutinyError> {
+    let coin_type = coin_type_from_network(network);
+
+    let base_path = DerivationPath::from_str("m/86'")?;
+    Ok(base_path.extend([
+        ChildNumber::from_hardened_idx(coin_type)?,
+        ChildNumber::from_hardened_idx(account_number)?,
+    ]))
+}
+
+/// Public descriptors for our wallet including the key origin,
+/// so PSBTs created by a watch-only copy can be signed by this wallet
+pub(crate) fn get_watch_only_descriptors(
+    master_xprv: Xpriv,
+    network: Network,
+    account_number: u32,
+) -> Result<(String, String), MutinyError> {
+    let secp = Secp256k1::new();
+    let derivation_path = get_account_derivation_path(network, account_number)?;
+    let account_xprv = master_xprv.derive_priv(&secp, &derivation_path)?;
+    let xpub = Xpub::from_priv(&secp, &account_xprv);
+    let origin = format!(
+        "[{}/{}]",
+        master_xprv.fingerprint(&secp),
+        derivation_path.to_string().trim_start_matches("m/")
+    );
+
+    Ok((
+        format!("tr({origin}{xpub}/0/*)"),
+        format!("tr({origin}{xpub}/1/*)"),
+    ))
+}
+
 pub(crate) fn coin_type_from_network(network: Network) -> u32 {
     match network {
         Network::Bitcoin => 0,
@@ -838,17 +2293,100 @@ pub(crate) fn get_esplora_url(network: Network, user_provided_url: Option<String
     }
 }
 
+/// Removes duplicate labels while keeping their order
+fn dedup_labels(labels: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    labels
+        .into_iter()
+        .filter(|s| seen.insert(s.clone()))
+        .collect()
+}
+
+/// The esplora servers to use in order of preference, the user's or
+/// our default one first and then the fallbacks
+pub(crate) fn get_esplora_urls(
+    network: Network,
+    user_provided_url: Option<String>,
+    fallback_urls: &[String],
+) -> Vec<String> {
+    let mut urls = vec![get_esplora_url(network, user_provided_url)];
+    for url in fallback_urls {
+        let url = url.trim().trim_end_matches('/').to_string();
+        if !url.is_empty() && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// Picks the esplora server to start with. The one we last failed over to is
+/// tried first, then the rest in order of preference, using the first that responds.
+pub(crate) async fn select_esplora_url<S: MutinyStorage>(
+    storage: &S,
+    urls: &[String],
+    logger: &MutinyLogger,
+) -> String {
+    if urls.len() < 2 {
+        return urls.first().cloned().unwrap_or_default();
+    }
+
+    let active: Option<String> = storage.get_data(ACTIVE_ESPLORA_URL_KEY).ok().flatten();
+    let mut candidates = urls.to_vec();
+    if let Some(pos) = active.and_then(|a| candidates.iter().position(|u| *u == a)) {
+        let active = candidates.remove(pos);
+        candidates.insert(0, active);
+    }
+
+    let client = Client::new();
+    for url in candidates.iter() {
+        let Ok(request) = client.get(format!("{url}/blocks/tip/height")).build() else {
+            continue;
+        };
+        match utils::fetch_with_timeout(&client, request).await {
+            Ok(response) if response.status().is_success() => return url.clone(),
+            _ => log_warn!(logger, "Esplora server {url} is not responding"),
+        }
+    }
+
+    // nothing responded, stick with the first choice and let sync retry
+    candidates[0].clone()
+}
+
+pub(crate) fn persist_active_esplora_url<S: MutinyStorage>(
+    storage: &S,
+    url: &str,
+) -> Result<(), MutinyError> {
+    storage.write_data(ACTIVE_ESPLORA_URL_KEY.to_string(), url, None)
+}
+
 impl<S: MutinyStorage> WalletSource for OnChainWallet<S> {
     fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
-        let wallet = self.wallet.try_read().map_err(|_| ())?;
-        let utxos = wallet
-            .list_unspent()
-            .map(|u| Utxo {
-                outpoint: u.outpoint,
-                output: u.txout,
-                satisfaction_weight: 4 + 2 + 64,
-            })
-            .collect();
+        // vault and frozen coins must not be picked for bumping a force close
+        let frozen = self
+            .get_frozen_utxos()
+            .map_err(|e| log_error!(self.logger, "Could not get frozen utxos: {e:?}"))?;
+        let vault = get_vault(&self.storage)
+            .map_err(|e| log_error!(self.logger, "Could not get vault: {e:?}"))?;
+        let mut utxos = vec![];
+        for (wallet, store_key) in self.wallets() {
+            let satisfaction_weight = if store_key == SEGWIT_KEYCHAIN_STORE_KEY {
+                // empty script sig, then the witness item count, signature and public key
+                4 + 1 + 1 + 73 + 1 + 33
+            } else {
+                4 + 2 + 64
+            };
+            let wallet = wallet.try_read().map_err(|_| ())?;
+            utxos.extend(
+                wallet
+                    .list_unspent()
+                    .filter(|u| !frozen.contains(&u.outpoint) && !vault.utxos.contains(&u.outpoint))
+                    .map(|u| Utxo {
+                        outpoint: u.outpoint,
+                        output: u.txout,
+                        satisfaction_weight,
+                    }),
+            );
+        }
 
         Ok(utxos)
     }
@@ -860,24 +2398,47 @@ impl<S: MutinyStorage> WalletSource for OnChainWallet<S> {
     }
 
     fn sign_psbt(&self, mut psbt: Psbt) -> Result<Transaction, ()> {
-        let wallet = self.wallet.try_read().map_err(|e| {
-            log_error!(
-                self.logger,
-                "Could not get wallet lock to sign transaction: {e:?}"
-            )
-        })?;
-
         // need to trust witness_utxo for signing since that's LDK sets in the psbt
         let sign_options = SignOptions {
             trust_witness_utxo: true,
             ..Default::default()
         };
-        wallet
-            .sign(&mut psbt, sign_options)
-            .map_err(|e| log_error!(self.logger, "Could not sign transaction: {e:?}"))?;
+        // the bump can spend coins from either wallet, each signs its own inputs
+        for (wallet, _) in self.wallets() {
+            let wallet = wallet.try_read().map_err(|e| {
+                log_error!(
+                    self.logger,
+                    "Could not get wallet lock to sign transaction: {e:?}"
+                )
+            })?;
+            wallet
+                .sign(&mut psbt, sign_options.clone())
+                .map_err(|e| log_error!(self.logger, "Could not sign transaction: {e:?}"))?;
+        }
+
+        let tx = psbt
+            .extract_tx()
+            .map_err(|e| log_error!(self.logger, "Extract signed transaction: {e:?}"))?;
+
+        // label the child transactions bumping a force close, so they show in activity
+        match record_anchor_bump_tx(&self.storage, &tx) {
+            Ok(Some(_)) => {
+                for output in tx.output.iter() {
+                    if let Ok(addr) = Address::from_script(&output.script_pubkey, self.network) {
+                        if let Err(e) = self
+                            .storage
+                            .set_address_labels(addr, vec![ANCHOR_BUMP_LABEL.to_string()])
+                        {
+                            log_warn!(self.logger, "Could not label anchor bump: {e}");
+                        }
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log_warn!(self.logger, "Could not record anchor bump: {e}"),
+        }
 
-        psbt.extract_tx()
-            .map_err(|e| log_error!(self.logger, "Extract signed transaction: {e:?}"))
+        Ok(tx)
     }
 }
 
@@ -922,6 +2483,147 @@ mod tests {
         let _wallet = create_wallet().await;
     }
 
+    #[test]
+    async fn test_get_esplora_urls() {
+        let test_name = "get_esplora_urls";
+        log!("{}", test_name);
+
+        let urls = get_esplora_urls(
+            Network::Signet,
+            None,
+            &[
+                "https://mutinynet.com/api/".to_string(),
+                "https://backup.example.com/api".to_string(),
+                " ".to_string(),
+            ],
+        );
+        assert_eq!(
+            urls,
+            vec![
+                "https://mutinynet.com/api".to_string(),
+                "https://backup.example.com/api".to_string(),
+            ]
+        );
+
+        // a single server is used without checking it
+        let storage = MemoryStorage::default();
+        let logger = MutinyLogger::default();
+        let url = select_esplora_url(&storage, &urls[..1], &logger).await;
+        assert_eq!(url, urls[0]);
+    }
+
+    #[test]
+    async fn test_watch_only_wallet() {
+        let test_name = "watch_only_wallet";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
+        let xpriv = Xpriv::new_master(Network::Testnet, &mnemonic.to_seed("")).unwrap();
+        let (descriptor, change_descriptor) =
+            get_watch_only_descriptors(xpriv, Network::Testnet, 0).unwrap();
+        assert!(!descriptor.contains("tprv"));
+
+        let db = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let watch_only = OnChainWallet::new_watch_only(
+            descriptor,
+            change_descriptor,
+            db.clone(),
+            Network::Testnet,
+            wallet.blockchain.clone(),
+            wallet.fees.clone(),
+            Arc::new(AtomicBool::new(false)),
+            logger.clone(),
+        )
+        .unwrap();
+        assert_eq!(db.get::<bool>(NEED_FULL_SYNC_KEY).unwrap(), Some(true));
+
+        // same addresses as the wallet with the keys
+        let address = wallet
+            .wallet
+            .try_write()
+            .unwrap()
+            .reveal_next_address(KeychainKind::External)
+            .address;
+        let watch_only_address = watch_only
+            .wallet
+            .try_write()
+            .unwrap()
+            .reveal_next_address(KeychainKind::External)
+            .address;
+        assert_eq!(address, watch_only_address);
+
+        // private keys are rejected
+        let (descriptor, change_descriptor) =
+            get_tr_descriptors_for_extended_key(xpriv, Network::Testnet, 0).unwrap();
+        let descriptor = descriptor.0.to_string_with_secret(&descriptor.1);
+        let change_descriptor = change_descriptor
+            .0
+            .to_string_with_secret(&change_descriptor.1);
+        assert!(OnChainWallet::new_watch_only(
+            descriptor,
+            change_descriptor,
+            MemoryStorage::default(),
+            Network::Testnet,
+            wallet.blockchain.clone(),
+            wallet.fees.clone(),
+            Arc::new(AtomicBool::new(false)),
+            logger,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_cpfp_child_fee() {
+        let test_name = "cpfp_child_fee";
+        log!("{}", test_name);
+
+        // parent paid 1 sat/vbyte, child has to make up the difference to 10 sat/vbyte
+        assert_eq!(cpfp_child_fee(200, 200, 110, 10), 2_900);
+        // parent already pays more than the target, child still pays its own way
+        assert_eq!(cpfp_child_fee(200, 5_000, 110, 10), 1_100);
+    }
+
+    #[test]
+    async fn test_freeze_utxo() {
+        let test_name = "freeze_utxo";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        // can't freeze a coin the wallet doesn't own
+        let outpoint = OutPoint::from_str(
+            "e45b7b7a879f13a82138153fcd3dc3f5b35af1e217f4128e9bc4f75aa049a90b:0",
+        )
+        .unwrap();
+        assert!(wallet.freeze_utxo(outpoint).is_err());
+        assert!(wallet.get_frozen_utxos().unwrap().is_empty());
+
+        // unfreezing something that isn't frozen is a no-op
+        wallet.unfreeze_utxo(outpoint).unwrap();
+        assert!(wallet.get_frozen_utxos().unwrap().is_empty());
+    }
+
+    #[test]
+    async fn test_sign_psbt_refuses_frozen_inputs() {
+        let test_name = "sign_psbt_refuses_frozen_inputs";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let mut psbt = Psbt::from_str("cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEHakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpIAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA").unwrap();
+        assert!(wallet.check_inputs_spendable(&psbt.unsigned_tx).is_ok());
+
+        let frozen: HashSet<OutPoint> = [psbt.unsigned_tx.input[1].previous_output].into();
+        wallet
+            .storage
+            .write_data(FROZEN_UTXOS_KEY.to_string(), frozen, None)
+            .unwrap();
+        assert_eq!(
+            wallet.sign_psbt(&mut psbt),
+            Err(MutinyError::WalletOperationFailed)
+        );
+    }
+
     #[test]
     async fn test_label_psbt() {
         let test_name = "label_psbt";
@@ -974,4 +2676,145 @@ mod tests {
             .contains(&send_to_addr.to_string()));
         assert!(label.unwrap().addresses.contains(&change_addr.to_string()));
     }
+
+    #[test]
+    async fn test_label_psbt_change_inherits_labels() {
+        let test_name = "label_psbt_change_inherits_labels";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let input_addr = Address::from_str("2Mx6uYKYGW5J6sV59e5NsdtCTsJYRxednbx")
+            .unwrap()
+            .assume_checked();
+        wallet
+            .storage
+            .set_address_labels(input_addr.clone(), vec!["savings".to_string()])
+            .unwrap();
+
+        let send_to_addr = Address::from_str("mrKjeffvbnmKJURrLNdqLkfrptLrFtnkFx")
+            .unwrap()
+            .assume_checked();
+        let change_addr = wallet.get_new_address(AddressType::Taproot).unwrap();
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![
+                bitcoin::TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: send_to_addr.script_pubkey(),
+                },
+                bitcoin::TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: change_addr.script_pubkey(),
+                },
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(bitcoin::TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: input_addr.script_pubkey(),
+        });
+
+        wallet.label_psbt(&psbt, vec!["rent".to_string()]).unwrap();
+
+        let addr_labels = wallet.storage.get_address_labels().unwrap();
+        assert_eq!(
+            addr_labels.get(&send_to_addr.to_string()),
+            Some(&vec!["rent".to_string()])
+        );
+        assert_eq!(
+            addr_labels.get(&change_addr.to_string()),
+            Some(&vec!["rent".to_string(), "savings".to_string()])
+        );
+
+        let lineage = list_label_lineage(&wallet.storage).unwrap();
+        assert_eq!(lineage.len(), 1);
+        assert_eq!(lineage[0].address, change_addr.to_string());
+        assert_eq!(lineage[0].txid, psbt.unsigned_tx.compute_txid());
+        assert_eq!(lineage[0].input_addresses, vec![input_addr.to_string()]);
+        assert_eq!(lineage[0].inherited_labels, vec!["savings".to_string()]);
+    }
+
+    #[test]
+    async fn test_is_address_reused() {
+        let test_name = "is_address_reused";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let address = wallet.get_new_address(AddressType::Taproot).unwrap();
+        let other = wallet.get_new_address(AddressType::Taproot).unwrap();
+        assert!(!wallet.is_address_reused(&address).unwrap());
+
+        let pay_to = |script_pubkey: ScriptBuf, sats: u64| Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey,
+            }],
+        };
+        let unconfirmed = ConfirmationTime::Unconfirmed {
+            last_seen: now().as_secs(),
+        };
+
+        let first = pay_to(address.script_pubkey(), 10_000);
+        wallet
+            .insert_tx(first.clone(), unconfirmed.clone(), None)
+            .await
+            .unwrap();
+        assert!(wallet.is_address_reused(&address).unwrap());
+        assert!(!wallet.is_address_reused(&other).unwrap());
+        let details = wallet
+            .get_transaction(first.compute_txid())
+            .unwrap()
+            .unwrap();
+        assert!(!details.address_reused);
+
+        let second = pay_to(address.script_pubkey(), 20_000);
+        wallet
+            .insert_tx(second.clone(), unconfirmed, None)
+            .await
+            .unwrap();
+        let txs = wallet.list_transactions(false).unwrap();
+        assert_eq!(txs.len(), 2);
+        assert!(txs.iter().all(|t| t.address_reused));
+    }
+
+    #[test]
+    async fn test_consolidate_utxos_skips() {
+        let test_name = "consolidate_utxos_skips";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        // the fallback fee rate is too high to consolidate at
+        let config = ConsolidationConfig::default();
+        assert_eq!(wallet.consolidate_utxos(&config).await.unwrap(), None);
+
+        // cheap enough, but there is nothing to consolidate
+        let config = ConsolidationConfig {
+            max_fee_rate: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(wallet.consolidate_utxos(&config).await.unwrap(), None);
+    }
+
+    #[test]
+    async fn test_sign_message_bip322() {
+        let test_name = "sign_message_bip322";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let address = wallet.get_new_address(AddressType::Taproot).unwrap();
+        let signature = wallet.sign_message_bip322(&address, "Hello World").unwrap();
+        assert!(bip322::verify_message_bip322(&address, "Hello World", &signature).unwrap());
+        assert!(!bip322::verify_message_bip322(&address, "Goodbye", &signature).unwrap());
+
+        // can't sign for an address that isn't ours
+        let other = Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l")
+            .unwrap()
+            .assume_checked();
+        assert!(wallet.sign_message_bip322(&other, "Hello World").is_err());
+    }
 }
@@ -0,0 +1,94 @@
+//! Activity that belongs to one user action but shows up as several items,
+//! e.g. a swap deposit is an on-chain send, a lightning receive and maybe a
+//! refund. Everything an action creates gets the same label, that label is
+//! the operation id that ties the items together.
+
+use crate::channelfunding::CHANNEL_FUNDING_PREFIX;
+use crate::split::SPLIT_SEND_PREFIX;
+use crate::swap::SWAP_DEPOSIT_PREFIX;
+use crate::ActivityItem;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OperationKind {
+    /// On-chain deposit to a swap provider, paid back over lightning
+    SwapDeposit,
+    /// One amount sent to several lightning addresses
+    SplitSend,
+    /// Channel opened with funds sent to the wallet from outside
+    ChannelFunding,
+}
+
+impl OperationKind {
+    const ALL: [OperationKind; 3] = [
+        OperationKind::SwapDeposit,
+        OperationKind::SplitSend,
+        OperationKind::ChannelFunding,
+    ];
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            OperationKind::SwapDeposit => SWAP_DEPOSIT_PREFIX,
+            OperationKind::SplitSend => SPLIT_SEND_PREFIX,
+            OperationKind::ChannelFunding => CHANNEL_FUNDING_PREFIX,
+        }
+    }
+
+    /// The kind of operation the id belongs to, `None` if it isn't an operation id
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| {
+            id.strip_prefix(k.prefix())
+                .is_some_and(|rest| !rest.is_empty())
+        })
+    }
+}
+
+/// The operation id among the labels of an activity item, if it has one
+pub fn operation_id(labels: &[String]) -> Option<String> {
+    labels
+        .iter()
+        .find(|l| OperationKind::from_id(l).is_some())
+        .cloned()
+}
+
+/// All the activity of one user action
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Operation {
+    pub id: String,
+    pub kind: OperationKind,
+    /// Newest first, like the rest of the activity
+    pub items: Vec<ActivityItem>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_operation_id() {
+        let test_name = "test_operation_id";
+        log!("{}", test_name);
+
+        assert_eq!(
+            OperationKind::from_id("swap_deposit/abc"),
+            Some(OperationKind::SwapDeposit)
+        );
+        assert_eq!(
+            OperationKind::from_id("split_send/abc"),
+            Some(OperationKind::SplitSend)
+        );
+        assert_eq!(OperationKind::from_id("swap_deposit/"), None);
+        assert_eq!(OperationKind::from_id("Coffee"), None);
+
+        let labels = vec!["Coffee".to_string(), "channel_funding/abc".to_string()];
+        assert_eq!(
+            operation_id(&labels),
+            Some("channel_funding/abc".to_string())
+        );
+        assert_eq!(operation_id(&["Coffee".to_string()]), None);
+    }
+}
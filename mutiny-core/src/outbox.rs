@@ -0,0 +1,311 @@
+use crate::error::MutinyError;
+use crate::networking::websocket::{SimpleWebSocket, WebSocketImpl};
+use crate::nostr::{is_auth_required, AuthUpdate, RelayAuth};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1::SecretKey;
+use futures::{future::Either, pin_mut};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub(crate) const NOSTR_OUTBOX_PREFIX: &str = "nostr_outbox/";
+/// How long to wait for a relay to acknowledge an event
+const RELAY_TIMEOUT_MS: i32 = 10_000;
+const MIN_RETRY_DELAY_SECS: u64 = 30;
+const MAX_RETRY_DELAY_SECS: u64 = 60 * 60;
+
+/// A signed nostr event waiting to be accepted by at least one relay.
+/// Events stay in the outbox across reloads until a relay acknowledges them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutboxEvent {
+    /// The nostr event id
+    pub id: String,
+    pub kind: u64,
+    /// The full signed event
+    pub event: Value,
+    pub relays: Vec<String>,
+    pub attempts: u32,
+    pub last_attempt: Option<u64>,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+}
+
+impl OutboxEvent {
+    pub(crate) fn new(event: Value, relays: Vec<String>) -> Result<Self, MutinyError> {
+        let id = event
+            .get("id")
+            .and_then(|id| id.as_str())
+            .filter(|id| id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or(MutinyError::InvalidArgumentsError)?
+            .to_string();
+        let kind = event
+            .get("kind")
+            .and_then(|k| k.as_u64())
+            .ok_or(MutinyError::InvalidArgumentsError)?;
+        // only signed events can be published
+        if event.get("sig").and_then(|s| s.as_str()).is_none() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        if relays.is_empty()
+            || relays
+                .iter()
+                .any(|r| !r.starts_with("wss://") && !r.starts_with("ws://"))
+        {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        Ok(Self {
+            id,
+            kind,
+            event,
+            relays,
+            attempts: 0,
+            last_attempt: None,
+            last_error: None,
+            created_at: utils::now().as_secs(),
+        })
+    }
+
+    /// When to try publishing again, backing off exponentially after each failure
+    pub fn next_attempt_at(&self) -> u64 {
+        match self.last_attempt {
+            None => 0,
+            Some(last) => {
+                let delay = MIN_RETRY_DELAY_SECS
+                    .saturating_mul(1 << self.attempts.saturating_sub(1).min(16))
+                    .min(MAX_RETRY_DELAY_SECS);
+                last + delay
+            }
+        }
+    }
+}
+
+fn outbox_key(id: &str) -> String {
+    format!("{NOSTR_OUTBOX_PREFIX}{id}")
+}
+
+pub(crate) fn persist_outbox_event<S: MutinyStorage>(
+    storage: &S,
+    event: &OutboxEvent,
+) -> Result<(), MutinyError> {
+    storage.write_data(outbox_key(&event.id), event, None)
+}
+
+pub(crate) fn remove_outbox_event<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<(), MutinyError> {
+    storage.delete(&[outbox_key(id)])
+}
+
+/// Lists the events no relay has accepted yet, oldest first
+pub(crate) fn list_outbox_events<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<OutboxEvent>, MutinyError> {
+    let mut events: Vec<OutboxEvent> = storage
+        .scan::<OutboxEvent>(NOSTR_OUTBOX_PREFIX, None)?
+        .into_values()
+        .collect();
+    events.sort_by_key(|e| e.created_at);
+
+    Ok(events)
+}
+
+/// Parses a relay's `["OK", <id>, <accepted>, <message>]` response to the given event
+fn parse_ok_message(msg: &str, id: &str) -> Option<(bool, String)> {
+    let msg: Vec<Value> = serde_json::from_str(msg).ok()?;
+    match msg.as_slice() {
+        [Value::String(t), Value::String(event_id), Value::Bool(accepted), rest @ ..]
+            if t == "OK" && event_id == id =>
+        {
+            let reason = rest
+                .first()
+                .and_then(|r| r.as_str())
+                .unwrap_or_default()
+                .to_string();
+            Some((*accepted, reason))
+        }
+        _ => None,
+    }
+}
+
+/// Sends the event to the relay, authenticating with the key first if the
+/// relay requires NIP-42 authentication to accept it
+async fn publish_to_relay(
+    relay: &str,
+    event: &OutboxEvent,
+    key: &SecretKey,
+) -> Result<(), MutinyError> {
+    let publish = async {
+        let mut ws = WebSocketImpl::new(relay.to_string())
+            .await
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+        let event_msg = json!(["EVENT", event.event]).to_string();
+        ws.send(event_msg.clone())
+            .await
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        let mut auth = RelayAuth::new(key, relay);
+        let mut retried = false;
+        let mut waiting_for_auth = false;
+        loop {
+            let msg = ws.recv().await.map_err(|_| MutinyError::ConnectionFailed)?;
+            let update = serde_json::from_str::<Vec<Value>>(&msg)
+                .ok()
+                .and_then(|m| auth.handle(&m));
+            match update {
+                Some(AuthUpdate::Respond(reply)) => {
+                    ws.send(reply)
+                        .await
+                        .map_err(|_| MutinyError::ConnectionFailed)?;
+                    continue;
+                }
+                Some(AuthUpdate::Accepted) => {
+                    if waiting_for_auth {
+                        waiting_for_auth = false;
+                        ws.send(event_msg.clone())
+                            .await
+                            .map_err(|_| MutinyError::ConnectionFailed)?;
+                    }
+                    continue;
+                }
+                Some(AuthUpdate::Rejected) => return Err(MutinyError::NostrError),
+                None => {}
+            }
+
+            match parse_ok_message(&msg, &event.id) {
+                Some((true, _)) => return Ok(()),
+                Some((false, reason)) if is_auth_required(&reason) && !retried => {
+                    // send it again once the relay knows who we are
+                    retried = true;
+                    if auth.authenticated {
+                        ws.send(event_msg.clone())
+                            .await
+                            .map_err(|_| MutinyError::ConnectionFailed)?;
+                    } else {
+                        waiting_for_auth = true;
+                    }
+                }
+                Some((false, _)) => return Err(MutinyError::NostrError),
+                None => {}
+            }
+        }
+    };
+    let timeout = async {
+        utils::sleep(RELAY_TIMEOUT_MS).await;
+        Err(MutinyError::ConnectionFailed)
+    };
+
+    pin_mut!(publish);
+    pin_mut!(timeout);
+
+    match futures::future::select(publish, timeout).await {
+        Either::Left((res, _)) => res,
+        Either::Right((err, _)) => err,
+    }
+}
+
+/// Tries each of the event's relays until one accepts it. Accepted events are
+/// removed from the outbox, otherwise the failure is recorded for the next retry.
+/// Relays that require it are authenticated with the key.
+pub(crate) async fn publish_outbox_event<S: MutinyStorage>(
+    storage: &S,
+    mut event: OutboxEvent,
+    auth_key: &SecretKey,
+) -> Result<(), MutinyError> {
+    let mut last_error = MutinyError::NostrError;
+    for relay in event.relays.iter() {
+        match publish_to_relay(relay, &event, auth_key).await {
+            Ok(()) => return remove_outbox_event(storage, &event.id),
+            Err(e) => last_error = e,
+        }
+    }
+
+    event.attempts += 1;
+    event.last_attempt = Some(utils::now().as_secs());
+    event.last_error = Some(last_error.to_string());
+    persist_outbox_event(storage, &event)?;
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const EVENT_ID: &str = "b9fead6eef87d8400cbc1a5621600b360438affb9760a6a043cc0bddea21dab6";
+
+    fn event() -> Value {
+        json!({
+            "id": EVENT_ID,
+            "pubkey": "82341f882b6eabcd2ba7f1ef90aad961cf074af15b9ef44a09f9d2a8fbfbe6a2",
+            "created_at": 1700000000,
+            "kind": 1,
+            "tags": [],
+            "content": "hello",
+            "sig": "00"
+        })
+    }
+
+    #[test]
+    fn test_nostr_outbox() {
+        let test_name = "test_nostr_outbox";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let relays = vec!["wss://relay.damus.io".to_string()];
+
+        assert!(OutboxEvent::new(event(), vec![]).is_err());
+        assert!(OutboxEvent::new(event(), vec!["https://relay.damus.io".to_string()]).is_err());
+        let mut unsigned = event();
+        unsigned.as_object_mut().unwrap().remove("sig");
+        assert!(OutboxEvent::new(unsigned, relays.clone()).is_err());
+
+        let mut outbox_event = OutboxEvent::new(event(), relays).unwrap();
+        assert_eq!(outbox_event.kind, 1);
+        assert_eq!(outbox_event.next_attempt_at(), 0);
+
+        outbox_event.attempts = 1;
+        outbox_event.last_attempt = Some(1_000);
+        assert_eq!(outbox_event.next_attempt_at(), 1_000 + MIN_RETRY_DELAY_SECS);
+        outbox_event.attempts = 3;
+        assert_eq!(
+            outbox_event.next_attempt_at(),
+            1_000 + 4 * MIN_RETRY_DELAY_SECS
+        );
+        outbox_event.attempts = 30;
+        assert_eq!(outbox_event.next_attempt_at(), 1_000 + MAX_RETRY_DELAY_SECS);
+
+        persist_outbox_event(&storage, &outbox_event).unwrap();
+        assert_eq!(
+            list_outbox_events(&storage).unwrap(),
+            vec![outbox_event.clone()]
+        );
+        remove_outbox_event(&storage, &outbox_event.id).unwrap();
+        assert!(list_outbox_events(&storage).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_ok_message() {
+        let test_name = "test_parse_ok_message";
+        log!("{}", test_name);
+
+        let accepted = format!(r#"["OK","{EVENT_ID}",true,""]"#);
+        assert_eq!(
+            parse_ok_message(&accepted, EVENT_ID),
+            Some((true, String::new()))
+        );
+        let rejected = format!(r#"["OK","{EVENT_ID}",false,"blocked: spam"]"#);
+        assert_eq!(
+            parse_ok_message(&rejected, EVENT_ID),
+            Some((false, "blocked: spam".to_string()))
+        );
+        assert_eq!(parse_ok_message(&accepted, "other"), None);
+        assert_eq!(parse_ok_message(r#"["NOTICE","hi"]"#, EVENT_ID), None);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::error::MutinyError;
+use crate::lnaddress::LightningAddress;
+use crate::storage::MutinyStorage;
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{Bech32m, Checksum, Fe32};
+use bitcoin::constants::ChainHash;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Network;
+use lightning::offers::offer::Offer;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const PAYMENT_CARD_KEY: &str = "payment_card";
+
+/// All the static identifiers someone can use to pay this wallet,
+/// kept in one place so receive screens don't need to assemble them.
+///
+/// The version is bumped every time one of the identifiers the user sets
+/// changes. The node pubkey and npub come from the wallet's own keys and are
+/// filled in when the card is read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentCard {
+    pub version: u32,
+    /// Node pubkey that can be used for keysend payments
+    #[serde(default)]
+    pub node_pubkey: Option<PublicKey>,
+    /// The wallet's nostr identity
+    #[serde(default)]
+    pub npub: Option<String>,
+    #[serde(default)]
+    pub lightning_address: Option<String>,
+    /// BOLT12 offer
+    #[serde(default)]
+    pub offer: Option<String>,
+    #[serde(default)]
+    pub silent_payment_address: Option<String>,
+    /// Epoch time in seconds of the last change
+    pub updated_at: u64,
+}
+
+/// The identifiers the user can set on their payment card, `None`
+/// leaves the existing value untouched and an empty string clears it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentCardUpdate {
+    pub lightning_address: Option<String>,
+    pub offer: Option<String>,
+    pub silent_payment_address: Option<String>,
+}
+
+fn apply(field: &mut Option<String>, update: Option<String>) -> bool {
+    let Some(update) = update else {
+        return false;
+    };
+    let update = Some(update.trim().to_string()).filter(|s| !s.is_empty());
+    let changed = *field != update;
+    *field = update;
+    changed
+}
+
+fn validate_offer(offer: &str, network: Network) -> Result<(), MutinyError> {
+    let offer = Offer::from_str(offer).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    if !offer.supports_chain(ChainHash::using_genesis_block(network)) {
+        return Err(MutinyError::IncorrectNetwork);
+    }
+    Ok(())
+}
+
+/// Bech32m without the 90 character limit, silent payment addresses are longer
+enum SilentPaymentChecksum {}
+
+impl Checksum for SilentPaymentChecksum {
+    type MidstateRepr = <Bech32m as Checksum>::MidstateRepr;
+    const CODE_LENGTH: usize = 1023;
+    const CHECKSUM_LENGTH: usize = Bech32m::CHECKSUM_LENGTH;
+    const GENERATOR_SH: [Self::MidstateRepr; 5] = Bech32m::GENERATOR_SH;
+    const TARGET_RESIDUE: Self::MidstateRepr = Bech32m::TARGET_RESIDUE;
+}
+
+/// A BIP 352 silent payment address, version 0 holds the scan and spend public keys
+fn validate_silent_payment_address(address: &str, network: Network) -> Result<(), MutinyError> {
+    let mut address = CheckedHrpstring::new::<SilentPaymentChecksum>(&address.to_lowercase())
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let expected_hrp = match network {
+        Network::Bitcoin => "sp",
+        _ => "tsp",
+    };
+    if address.hrp().to_string() != expected_hrp {
+        return Err(MutinyError::IncorrectNetwork);
+    }
+    if address.remove_witness_version() != Some(Fe32::Q) {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    let keys = address.byte_iter().collect::<Vec<u8>>();
+    if keys.len() != 66 || keys.chunks(33).any(|k| PublicKey::from_slice(k).is_err()) {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    Ok(())
+}
+
+impl PaymentCardUpdate {
+    /// Checks every identifier being set is valid on the network
+    pub(crate) fn validate(&self, network: Network) -> Result<(), MutinyError> {
+        // an empty value clears the field, same as in apply
+        fn set(field: &Option<String>) -> Option<&str> {
+            field.as_deref().map(str::trim).filter(|s| !s.is_empty())
+        }
+        if let Some(address) = set(&self.lightning_address) {
+            LightningAddress::from_str(address)?;
+        }
+        if let Some(offer) = set(&self.offer) {
+            validate_offer(offer, network)?;
+        }
+        if let Some(address) = set(&self.silent_payment_address) {
+            validate_silent_payment_address(address, network)?;
+        }
+        Ok(())
+    }
+}
+
+impl PaymentCard {
+    /// Applies the update, returns true if anything changed
+    pub(crate) fn update(&mut self, update: PaymentCardUpdate) -> bool {
+        // don't short circuit, every field needs to be applied
+        let changed = [
+            apply(&mut self.lightning_address, update.lightning_address),
+            apply(&mut self.offer, update.offer),
+            apply(
+                &mut self.silent_payment_address,
+                update.silent_payment_address,
+            ),
+        ];
+
+        changed.contains(&true)
+    }
+
+    /// Encodes the card as a BIP 21 style URI that can be put in a QR code.
+    /// Wallets that don't understand a parameter will ignore it.
+    pub fn to_uri(&self) -> String {
+        let mut params = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(offer) = self.offer.as_ref() {
+            params.append_pair("lno", offer);
+        }
+        if let Some(sp) = self.silent_payment_address.as_ref() {
+            params.append_pair("sp", sp);
+        }
+        if let Some(address) = self.lightning_address.as_ref() {
+            params.append_pair("lnaddr", address);
+        }
+        if let Some(npub) = self.npub.as_ref() {
+            params.append_pair("npub", npub);
+        }
+        if let Some(node_pubkey) = self.node_pubkey.as_ref() {
+            params.append_pair("node", &node_pubkey.to_string());
+        }
+
+        let params = params.finish();
+        if params.is_empty() {
+            "bitcoin:".to_string()
+        } else {
+            format!("bitcoin:?{params}")
+        }
+    }
+}
+
+pub(crate) fn get_payment_card<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<PaymentCard>, MutinyError> {
+    storage.get_data(PAYMENT_CARD_KEY)
+}
+
+pub(crate) fn persist_payment_card<S: MutinyStorage>(
+    storage: &S,
+    card: &PaymentCard,
+) -> Result<(), MutinyError> {
+    storage.write_data(PAYMENT_CARD_KEY.to_string(), card, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_payment_card_update() {
+        let test_name = "test_payment_card_update";
+        log!("{}", test_name);
+
+        let mut card = PaymentCard::default();
+        assert_eq!(card.to_uri(), "bitcoin:");
+
+        let changed = card.update(PaymentCardUpdate {
+            lightning_address: Some("satoshi@example.com".to_string()),
+            offer: Some("lno1qqqq".to_string()),
+            ..Default::default()
+        });
+        assert!(changed);
+        assert_eq!(
+            card.to_uri(),
+            "bitcoin:?lno=lno1qqqq&lnaddr=satoshi%40example.com"
+        );
+
+        // same values are not a change
+        let changed = card.update(PaymentCardUpdate {
+            lightning_address: Some("satoshi@example.com".to_string()),
+            ..Default::default()
+        });
+        assert!(!changed);
+
+        // empty string clears
+        let changed = card.update(PaymentCardUpdate {
+            offer: Some(String::new()),
+            ..Default::default()
+        });
+        assert!(changed);
+        assert_eq!(card.offer, None);
+    }
+
+    #[test]
+    fn test_payment_card_validation() {
+        let test_name = "test_payment_card_validation";
+        log!("{}", test_name);
+
+        let update = |lightning_address: &str, offer: &str, silent_payment_address: &str| {
+            PaymentCardUpdate {
+                lightning_address: Some(lightning_address.to_string()),
+                offer: Some(offer.to_string()),
+                silent_payment_address: Some(silent_payment_address.to_string()),
+            }
+        };
+
+        // clearing is always fine
+        assert!(update("", "", "").validate(Network::Bitcoin).is_ok());
+        assert!(update("satoshi@example.com", "", "")
+            .validate(Network::Bitcoin)
+            .is_ok());
+        assert!(update("satoshi", "", "")
+            .validate(Network::Bitcoin)
+            .is_err());
+        assert!(update("", "lno1qqqq", "")
+            .validate(Network::Bitcoin)
+            .is_err());
+
+        // the BIP 352 test vector address
+        let sp = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+        assert!(update("", "", sp).validate(Network::Bitcoin).is_ok());
+        assert_eq!(
+            update("", "", sp).validate(Network::Signet),
+            Err(MutinyError::IncorrectNetwork)
+        );
+        assert!(
+            update("", "", "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c")
+                .validate(Network::Bitcoin)
+                .is_err()
+        );
+    }
+}
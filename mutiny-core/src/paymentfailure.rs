@@ -0,0 +1,211 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1::PublicKey;
+use hex_conservative::DisplayHex;
+use lightning::events::PathFailure;
+use lightning::routing::router::Path;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const PAYMENT_FAILURE_PREFIX: &str = "payment_failure/";
+/// Only the most recent attempts are kept, a payment can be retried many times
+const MAX_FAILED_ATTEMPTS: usize = 25;
+
+/// Everything we learned about why a payment failed, one entry per failed path
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentFailureDetails {
+    pub payment_hash: String,
+    pub attempts: Vec<FailedPaymentAttempt>,
+    /// Why the payment as a whole failed, set once LDK gives up on it
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FailedPaymentAttempt {
+    /// Epoch time in seconds when the attempt failed
+    pub timestamp: u64,
+    pub hops: Vec<AttemptHop>,
+    /// The channel that failed, if the failure could be attributed to one
+    #[serde(default)]
+    pub failed_short_channel_id: Option<u64>,
+    /// Index into `hops` of the hop whose channel failed
+    #[serde(default)]
+    pub failed_hop: Option<usize>,
+    /// Description of the failure, e.g. the error we got back from the failing node
+    pub failure: String,
+    /// Whether the failure means the payment cannot succeed over any path
+    pub permanent: bool,
+    pub amount_msat: u64,
+    /// Total routing fees this attempt would have paid
+    pub fee_msat: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AttemptHop {
+    pub pubkey: PublicKey,
+    pub short_channel_id: u64,
+    pub fee_msat: u64,
+    pub cltv_expiry_delta: u32,
+}
+
+impl FailedPaymentAttempt {
+    pub(crate) fn new(
+        path: &Path,
+        failure: &PathFailure,
+        short_channel_id: Option<u64>,
+        permanent: bool,
+    ) -> Self {
+        let hops: Vec<AttemptHop> = path
+            .hops
+            .iter()
+            .map(|hop| AttemptHop {
+                pubkey: hop.pubkey,
+                short_channel_id: hop.short_channel_id,
+                fee_msat: hop.fee_msat,
+                cltv_expiry_delta: hop.cltv_expiry_delta,
+            })
+            .collect();
+        let failed_hop =
+            short_channel_id.and_then(|scid| hops.iter().position(|h| h.short_channel_id == scid));
+
+        let failure = match failure {
+            PathFailure::InitialSend { err } => format!("Failed to send: {err:?}"),
+            PathFailure::OnPath {
+                network_update: Some(update),
+            } => format!("Failed on path: {update:?}"),
+            PathFailure::OnPath {
+                network_update: None,
+            } => "Failed on path".to_string(),
+        };
+
+        Self {
+            timestamp: utils::now().as_secs(),
+            hops,
+            failed_short_channel_id: short_channel_id,
+            failed_hop,
+            failure,
+            permanent,
+            amount_msat: path.final_value_msat(),
+            fee_msat: path.fee_msat(),
+        }
+    }
+}
+
+fn payment_failure_key(payment_hash: &[u8; 32]) -> String {
+    format!(
+        "{PAYMENT_FAILURE_PREFIX}{}",
+        payment_hash.to_lower_hex_string()
+    )
+}
+
+pub(crate) fn get_payment_failure_details<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Result<Option<PaymentFailureDetails>, MutinyError> {
+    storage.get_data(payment_failure_key(payment_hash))
+}
+
+fn update_payment_failure_details<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    f: impl FnOnce(&mut PaymentFailureDetails),
+) -> Result<(), MutinyError> {
+    let mut details = get_payment_failure_details(storage, payment_hash)?.unwrap_or_else(|| {
+        PaymentFailureDetails {
+            payment_hash: payment_hash.to_lower_hex_string(),
+            ..Default::default()
+        }
+    });
+    f(&mut details);
+    storage.write_data(payment_failure_key(payment_hash), details, None)
+}
+
+/// Records a failed attempt at paying the given payment hash
+pub(crate) fn record_failed_attempt<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    attempt: FailedPaymentAttempt,
+) -> Result<(), MutinyError> {
+    update_payment_failure_details(storage, payment_hash, |details| {
+        details.attempts.push(attempt);
+        if details.attempts.len() > MAX_FAILED_ATTEMPTS {
+            let excess = details.attempts.len() - MAX_FAILED_ATTEMPTS;
+            details.attempts.drain(..excess);
+        }
+    })
+}
+
+/// Records why the payment as a whole failed
+pub(crate) fn record_payment_failure_reason<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    reason: String,
+) -> Result<(), MutinyError> {
+    update_payment_failure_details(storage, payment_hash, |details| {
+        details.reason = Some(reason);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_attempt(failed_short_channel_id: Option<u64>) -> FailedPaymentAttempt {
+        let secp = Secp256k1::new();
+        let pubkey = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        FailedPaymentAttempt {
+            timestamp: 1,
+            hops: vec![AttemptHop {
+                pubkey,
+                short_channel_id: 42,
+                fee_msat: 1_000,
+                cltv_expiry_delta: 40,
+            }],
+            failed_short_channel_id,
+            failed_hop: failed_short_channel_id.map(|_| 0),
+            failure: "Failed on path".to_string(),
+            permanent: false,
+            amount_msat: 1_000,
+            fee_msat: 0,
+        }
+    }
+
+    #[test]
+    fn test_record_payment_failure() {
+        let test_name = "test_record_payment_failure";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let payment_hash = [7; 32];
+        assert_eq!(
+            get_payment_failure_details(&storage, &payment_hash).unwrap(),
+            None
+        );
+
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            record_failed_attempt(&storage, &payment_hash, create_attempt(None)).unwrap();
+        }
+        record_failed_attempt(&storage, &payment_hash, create_attempt(Some(42))).unwrap();
+        record_payment_failure_reason(&storage, &payment_hash, "RetriesExhausted".to_string())
+            .unwrap();
+
+        let details = get_payment_failure_details(&storage, &payment_hash)
+            .unwrap()
+            .unwrap();
+        assert_eq!(details.payment_hash, "07".repeat(32));
+        assert_eq!(details.attempts.len(), MAX_FAILED_ATTEMPTS);
+        // oldest attempt was dropped, newest is last
+        assert_eq!(
+            details.attempts.last().unwrap().failed_short_channel_id,
+            Some(42)
+        );
+        assert_eq!(details.reason, Some("RetriesExhausted".to_string()));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use url::Url;
+
+pub(crate) const PAYMENT_LINK_PREFIX: &str = "payment_link/";
+
+/// Page that opens payment links for payers without a wallet that handles them
+pub const DEFAULT_PAYMENT_LINK_URL: &str = "https://pay.mutinywallet.com/";
+
+/// Something that happened to a payment link, kept for the link's owner
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PaymentLinkEvent {
+    /// The link was opened through the wallet
+    Clicked { at: u64 },
+    /// The link's invoice was paid
+    Paid { at: u64 },
+}
+
+/// A shareable link for getting paid. It carries the invoice, and a
+/// lightning address to fall back to once the invoice has expired.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentLink {
+    pub id: String,
+    pub invoice: Bolt11Invoice,
+    pub amount_sats: u64,
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Lightning address to pay once the invoice has expired
+    #[serde(default)]
+    pub fallback_address: Option<String>,
+    /// The shareable url
+    pub url: String,
+    /// Epoch time in seconds when the link was created
+    pub created_at: u64,
+    #[serde(default)]
+    pub events: Vec<PaymentLinkEvent>,
+}
+
+impl PaymentLink {
+    pub(crate) fn new(
+        invoice: Bolt11Invoice,
+        amount_sats: u64,
+        memo: Option<String>,
+        fallback_address: Option<String>,
+    ) -> Result<Self, MutinyError> {
+        let id = invoice.payment_hash().to_string();
+        let url = payment_link_url(
+            DEFAULT_PAYMENT_LINK_URL,
+            &id,
+            &invoice,
+            fallback_address.as_deref(),
+        )?;
+        Ok(Self {
+            id,
+            invoice,
+            amount_sats,
+            memo,
+            fallback_address,
+            url,
+            created_at: utils::now().as_secs(),
+            events: vec![],
+        })
+    }
+
+    pub fn clicks(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|e| matches!(e, PaymentLinkEvent::Clicked { .. }))
+            .count()
+    }
+
+    pub fn is_paid(&self) -> bool {
+        self.events
+            .iter()
+            .any(|e| matches!(e, PaymentLinkEvent::Paid { .. }))
+    }
+}
+
+/// What a payment link points to, as found by parsing its url
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentLinkTarget {
+    pub id: String,
+    pub invoice: Option<Bolt11Invoice>,
+    pub fallback_address: Option<String>,
+}
+
+impl PaymentLinkTarget {
+    /// The invoice if it can still be paid, otherwise the fallback address
+    pub fn payable(&self) -> Option<String> {
+        self.invoice
+            .as_ref()
+            .filter(|i| !i.would_expire(utils::now()))
+            .map(|i| i.to_string())
+            .or(self.fallback_address.clone())
+    }
+}
+
+fn payment_link_url(
+    base: &str,
+    id: &str,
+    invoice: &Bolt11Invoice,
+    fallback_address: Option<&str>,
+) -> Result<String, MutinyError> {
+    let mut url = Url::parse(base).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("id", id);
+        query.append_pair("lightning", &invoice.to_string());
+        if let Some(address) = fallback_address {
+            query.append_pair("lnaddress", address);
+        }
+    }
+    Ok(url.to_string())
+}
+
+/// Reads a payment link's url, from any host that serves them
+pub fn parse_payment_link(url: &str) -> Result<PaymentLinkTarget, MutinyError> {
+    let url = Url::parse(url.trim()).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let mut id = None;
+    let mut invoice = None;
+    let mut fallback_address = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "id" => id = Some(value.to_string()),
+            "lightning" => invoice = Some(Bolt11Invoice::from_str(&value)?),
+            "lnaddress" => fallback_address = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let id = id.ok_or(MutinyError::InvalidArgumentsError)?;
+    if invoice.is_none() && fallback_address.is_none() {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    Ok(PaymentLinkTarget {
+        id,
+        invoice,
+        fallback_address,
+    })
+}
+
+fn payment_link_key(id: &str) -> String {
+    format!("{PAYMENT_LINK_PREFIX}{id}")
+}
+
+pub(crate) fn persist_payment_link<S: MutinyStorage>(
+    storage: &S,
+    link: &PaymentLink,
+) -> Result<(), MutinyError> {
+    storage.write_data(payment_link_key(&link.id), link, None)
+}
+
+pub(crate) fn get_payment_link<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<PaymentLink>, MutinyError> {
+    storage.get_data(payment_link_key(id))
+}
+
+/// Lists all our payment links, newest first
+pub(crate) fn list_payment_links<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PaymentLink>, MutinyError> {
+    let mut links: Vec<PaymentLink> = storage
+        .scan::<PaymentLink>(PAYMENT_LINK_PREFIX, None)?
+        .into_values()
+        .collect();
+    links.sort_by_key(|l| std::cmp::Reverse(l.created_at));
+
+    Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
+
+    #[test]
+    fn test_payment_link_url() {
+        let test_name = "test_payment_link_url";
+        log!("{}", test_name);
+
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let link = PaymentLink::new(
+            invoice.clone(),
+            92_372,
+            Some("coffee".to_string()),
+            Some("satoshi@example.com".to_string()),
+        )
+        .unwrap();
+        assert!(link.url.starts_with(DEFAULT_PAYMENT_LINK_URL));
+
+        let target = parse_payment_link(&link.url).unwrap();
+        assert_eq!(target.id, link.id);
+        assert_eq!(target.invoice, Some(invoice));
+        assert_eq!(
+            target.fallback_address,
+            Some("satoshi@example.com".to_string())
+        );
+        // the invoice is long expired, so pay the lightning address instead
+        assert_eq!(target.payable(), Some("satoshi@example.com".to_string()));
+
+        assert!(parse_payment_link("https://pay.mutinywallet.com/?id=abc").is_err());
+        assert!(parse_payment_link("not a url").is_err());
+    }
+
+    #[test]
+    fn test_payment_link_storage() {
+        let test_name = "test_payment_link_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let mut link = PaymentLink::new(invoice, 92_372, None, None).unwrap();
+        link.events.push(PaymentLinkEvent::Clicked { at: 1 });
+        link.events.push(PaymentLinkEvent::Clicked { at: 2 });
+        persist_payment_link(&storage, &link).unwrap();
+
+        let stored = get_payment_link(&storage, &link.id).unwrap().unwrap();
+        assert_eq!(stored.clicks(), 2);
+        assert!(!stored.is_paid());
+        assert_eq!(list_payment_links(&storage).unwrap(), vec![link]);
+    }
+}
@@ -0,0 +1,80 @@
+//! How long the payer keeps a lightning payment going.
+//!
+//! By default a payment is retried by LDK until it succeeds, runs out of
+//! attempts or hits the wallet's timeout, which keeps it in-flight longer
+//! than some UIs want. A [`PaymentPolicy`] lets a single payment retry less,
+//! give up sooner, stop as soon as a part fails for good or cap its fees.
+
+use crate::error::MutinyError;
+use serde::{Deserialize, Serialize};
+
+/// More than this and the payment outlives any reasonable timeout anyways
+const MAX_RETRIES: u32 = 50;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentPolicy {
+    /// How many times failed parts are routed again, otherwise picked from
+    /// the payment's MPP limits
+    pub max_retries: Option<u32>,
+    /// How long to wait for the payment before abandoning it, in seconds
+    pub timeout_secs: Option<u64>,
+    /// Abandon the payment as soon as a part fails in a way retrying can't fix,
+    /// instead of waiting for the other parts to come back
+    pub abandon_on_permanent_failure: bool,
+    /// The most the payment may pay in routing fees, in sats, unlimited if not set
+    #[serde(default)]
+    pub max_fee_sats: Option<u64>,
+}
+
+impl PaymentPolicy {
+    pub(crate) fn validate(&self) -> Result<(), MutinyError> {
+        if self.max_retries.is_some_and(|r| r > MAX_RETRIES) || self.timeout_secs == Some(0) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        Ok(())
+    }
+
+    /// The retry attempts given to LDK, `default` when it isn't set here
+    pub(crate) fn retry_attempts(&self, default: u32) -> u32 {
+        self.max_retries.unwrap_or(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_payment_policy() {
+        let test_name = "test_payment_policy";
+        log!("{}", test_name);
+
+        let policy = PaymentPolicy::default();
+        policy.validate().unwrap();
+        assert_eq!(policy.retry_attempts(15), 15);
+
+        let policy = PaymentPolicy {
+            max_retries: Some(0),
+            timeout_secs: Some(10),
+            abandon_on_permanent_failure: true,
+            max_fee_sats: Some(10),
+        };
+        policy.validate().unwrap();
+        assert_eq!(policy.retry_attempts(15), 0);
+
+        let too_many = PaymentPolicy {
+            max_retries: Some(MAX_RETRIES + 1),
+            ..Default::default()
+        };
+        assert!(too_many.validate().is_err());
+        let no_time = PaymentPolicy {
+            timeout_secs: Some(0),
+            ..Default::default()
+        };
+        assert!(no_time.validate().is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! Requests for payment sent to the wallet, queued until the user approves or
+//! declines them. Other wallets send them as NIP-04 direct messages to our
+//! npub, the embedder can also queue requests it received some other way.
+
+use crate::error::MutinyError;
+use crate::nostr::{fetch_from_relay, nip04_decrypt, verify_event, ENCRYPTED_DM_KIND};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+pub(crate) const PAYMENT_REQUEST_PREFIX: &str = "payment_request/";
+/// Time of the newest payment request message we've handled, so they aren't fetched again
+pub(crate) const PAYMENT_REQUEST_MESSAGES_SINCE_KEY: &str = "payment_request_messages_since";
+/// How often we look for payment requests sent to us
+pub(crate) const PAYMENT_REQUEST_POLL_INTERVAL_SECS: u64 = 30;
+
+/// The direct message asking us to pay
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaymentRequestMessage {
+    PaymentRequest {
+        invoice: String,
+        #[serde(default)]
+        amount_sats: Option<u64>,
+        #[serde(default)]
+        memo: Option<String>,
+        #[serde(default)]
+        reference: Option<String>,
+    },
+}
+
+/// A request for payment that was sent to the wallet, these are queued
+/// until the user approves or declines them.
+///
+/// Whoever receives these is responsible for setting a unique `id`, requests
+/// that came as direct messages use the id of the event.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// Unique identifier of the request, e.g. the id of the event it came from
+    pub id: String,
+    /// Who sent the request, e.g. the hex pubkey of the sender
+    pub sender: String,
+    /// The invoice to pay if the request is approved
+    pub invoice: Bolt11Invoice,
+    /// Amount to pay in sats, only used if the invoice does not have an amount
+    #[serde(default)]
+    pub amount_sats: Option<u64>,
+    /// Memo from the sender
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Reference the sender can use to reconcile the payment, e.g. an order id
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// Epoch time in seconds when we received the request
+    pub created_at: u64,
+}
+
+impl PaymentRequest {
+    /// The amount that will be paid in sats, if it is known
+    pub fn amount_sats(&self) -> Option<u64> {
+        self.invoice
+            .amount_milli_satoshis()
+            .map(|msats| msats / 1_000)
+            .or(self.amount_sats)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.invoice.would_expire(crate::utils::now())
+    }
+}
+
+/// Verifies and decrypts a payment request direct message sent to us, returning
+/// the request and when it was sent. The sender is the hex pubkey of the event.
+pub(crate) fn read_payment_request_dm(
+    key: &SecretKey,
+    event: &Value,
+) -> Result<(PaymentRequest, u64), MutinyError> {
+    let sender = verify_event(event)?;
+    let id = event.get("id").and_then(|i| i.as_str());
+    let kind = event.get("kind").and_then(|k| k.as_u64());
+    let created_at = event.get("created_at").and_then(|c| c.as_u64());
+    let content = event.get("content").and_then(|c| c.as_str());
+    let (Some(id), Some(ENCRYPTED_DM_KIND), Some(created_at), Some(content)) =
+        (id, kind, created_at, content)
+    else {
+        return Err(MutinyError::NostrError);
+    };
+
+    let plaintext = nip04_decrypt(key, &sender, content)?;
+    let PaymentRequestMessage::PaymentRequest {
+        invoice,
+        amount_sats,
+        memo,
+        reference,
+    } = serde_json::from_str(&plaintext)?;
+    let request = PaymentRequest {
+        id: id.to_string(),
+        sender: sender.to_string(),
+        invoice: Bolt11Invoice::from_str(&invoice)?,
+        amount_sats,
+        memo,
+        reference,
+        created_at: utils::now().as_secs(),
+    };
+
+    Ok((request, created_at))
+}
+
+/// Fetches the payment requests sent to the key since the given time. Other
+/// direct messages are ignored.
+pub(crate) async fn fetch_payment_request_dms(
+    key: &SecretKey,
+    relays: &[String],
+    since: u64,
+) -> Result<Vec<(PaymentRequest, u64)>, MutinyError> {
+    let secp = Secp256k1::new();
+    let pubkey = key.x_only_public_key(&secp).0;
+    let filter = json!({
+        "kinds": [ENCRYPTED_DM_KIND],
+        "#p": [pubkey.to_string()],
+        "since": since,
+    });
+
+    let mut requests: Vec<(PaymentRequest, u64)> = vec![];
+    let mut reached_relay = false;
+    for relay in relays {
+        let Ok(events) = fetch_from_relay(relay, &filter, key).await else {
+            continue;
+        };
+        reached_relay = true;
+        for event in events {
+            if let Ok(request) = read_payment_request_dm(key, &event) {
+                if !requests.iter().any(|(r, _)| r.id == request.0.id) {
+                    requests.push(request);
+                }
+            }
+        }
+    }
+
+    if !reached_relay {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    Ok(requests)
+}
+
+fn payment_request_key(id: &str) -> String {
+    format!("{PAYMENT_REQUEST_PREFIX}{id}")
+}
+
+pub(crate) fn persist_payment_request<S: MutinyStorage>(
+    storage: &S,
+    request: &PaymentRequest,
+) -> Result<(), MutinyError> {
+    storage.write_data(payment_request_key(&request.id), request, None)
+}
+
+pub(crate) fn get_payment_request<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<PaymentRequest>, MutinyError> {
+    storage.get_data(payment_request_key(id))
+}
+
+/// Lists all the queued payment requests, oldest first
+pub(crate) fn list_payment_requests<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PaymentRequest>, MutinyError> {
+    let mut requests: Vec<PaymentRequest> = storage
+        .scan::<PaymentRequest>(PAYMENT_REQUEST_PREFIX, None)?
+        .into_values()
+        .collect();
+    requests.sort_by_key(|r| r.created_at);
+
+    Ok(requests)
+}
+
+pub(crate) fn delete_payment_request<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<(), MutinyError> {
+    storage.delete(&[payment_request_key(id)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr::{nip04_encrypt, sign_event};
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
+
+    fn create_request(id: &str, created_at: u64) -> PaymentRequest {
+        PaymentRequest {
+            id: id.to_string(),
+            sender: "sender".to_string(),
+            invoice: Bolt11Invoice::from_str(INVOICE).unwrap(),
+            amount_sats: None,
+            memo: Some("coffee".to_string()),
+            reference: Some("order-1".to_string()),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn test_read_payment_request_dm() {
+        let test_name = "test_read_payment_request_dm";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let sender_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let our_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let sender = sender_key.x_only_public_key(&secp).0;
+        let us = our_key.x_only_public_key(&secp).0;
+
+        let message = PaymentRequestMessage::PaymentRequest {
+            invoice: INVOICE.to_string(),
+            amount_sats: None,
+            memo: Some("coffee".to_string()),
+            reference: Some("order-1".to_string()),
+        };
+        let content = nip04_encrypt(&sender_key, &us, &serde_json::to_string(&message).unwrap());
+        let event = sign_event(
+            &sender_key,
+            ENCRYPTED_DM_KIND,
+            json!([["p", us.to_string()]]),
+            content,
+        );
+
+        let (request, _) = read_payment_request_dm(&our_key, &event).unwrap();
+        assert_eq!(request.id, event["id"].as_str().unwrap());
+        assert_eq!(request.sender, sender.to_string());
+        assert_eq!(request.invoice.to_string(), INVOICE);
+        assert_eq!(request.memo.as_deref(), Some("coffee"));
+        assert_eq!(request.reference.as_deref(), Some("order-1"));
+
+        // only we can read it
+        assert!(
+            read_payment_request_dm(&SecretKey::from_slice(&[3; 32]).unwrap(), &event).is_err()
+        );
+    }
+
+    #[test]
+    fn test_payment_request_storage() {
+        let test_name = "test_payment_request_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+
+        let second = create_request("b", 2);
+        let first = create_request("a", 1);
+        persist_payment_request(&storage, &second).unwrap();
+        persist_payment_request(&storage, &first).unwrap();
+
+        assert_eq!(
+            get_payment_request(&storage, "a").unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(first.amount_sats(), Some(92_372));
+
+        let requests = list_payment_requests(&storage).unwrap();
+        assert_eq!(requests, vec![first, second.clone()]);
+
+        delete_payment_request(&storage, "a").unwrap();
+        let requests = list_payment_requests(&storage).unwrap();
+        assert_eq!(requests, vec![second]);
+    }
+}
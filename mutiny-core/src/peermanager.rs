@@ -10,7 +10,7 @@ use crate::{gossip, ldkstorage::PhantomChannelManager, logging::MutinyLogger};
 use crate::{gossip::read_peer_info, node::PubkeyConnectionInfo};
 use bitcoin::key::{Secp256k1, Verification};
 use bitcoin::secp256k1::{PublicKey, Signing};
-use lightning::blinded_path::message::{BlindedMessagePath, MessageContext};
+use lightning::blinded_path::message::{BlindedMessagePath, MessageContext, MessageForwardNode};
 use lightning::blinded_path::IntroductionNode;
 use lightning::events::{MessageSendEvent, MessageSendEventsProvider};
 use lightning::ln::features::{InitFeatures, NodeFeatures};
@@ -20,6 +20,7 @@ use lightning::ln::peer_handler::PeerManager as LdkPeerManager;
 use lightning::ln::peer_handler::{APeerManager, PeerHandleError};
 use lightning::onion_message::messenger::{Destination, MessageRouter, OnionMessagePath};
 use lightning::routing::gossip::NodeId;
+use lightning::sign::RandomBytes;
 use lightning::util::logger::Logger;
 use lightning::{ln::msgs::SocketAddress, log_warn};
 use std::sync::atomic::AtomicBool;
@@ -34,6 +35,9 @@ use lightning::ln::peer_handler::SocketDescriptor as LdkSocketDescriptor;
 #[cfg(target_arch = "wasm32")]
 use crate::networking::proxy::WsProxy;
 
+#[cfg(target_arch = "wasm32")]
+use crate::peeroverrides::get_peer_overrides;
+
 #[allow(dead_code)]
 pub trait PeerManager: Send + Sync + 'static {
     fn get_peer_node_ids(&self) -> Vec<PublicKey>;
@@ -302,16 +306,20 @@ impl<S: MutinyStorage> RoutingMessageHandler for GossipMessageHandler<S> {
 /// We just assume they are connected to us or the LSP.
 pub struct LspMessageRouter {
     intermediate_nodes: Vec<PublicKey>,
+    entropy_source: RandomBytes,
 }
 
 impl LspMessageRouter {
-    pub fn new(lsp_pubkey: Option<PublicKey>) -> Self {
+    pub fn new(lsp_pubkey: Option<PublicKey>, seed: [u8; 32]) -> Self {
         let intermediate_nodes = match lsp_pubkey {
             Some(pubkey) => vec![pubkey],
             None => vec![],
         };
 
-        Self { intermediate_nodes }
+        Self {
+            intermediate_nodes,
+            entropy_source: RandomBytes::new(seed),
+        }
     }
 }
 
@@ -347,13 +355,29 @@ impl MessageRouter for LspMessageRouter {
 
     fn create_blinded_paths<T: Signing + Verification>(
         &self,
-        _recipient: PublicKey,
-        _context: MessageContext,
+        recipient: PublicKey,
+        context: MessageContext,
         _peers: Vec<PublicKey>,
-        _secp_ctx: &Secp256k1<T>,
+        secp_ctx: &Secp256k1<T>,
     ) -> Result<Vec<BlindedMessagePath>, ()> {
-        // Bolt12 not yet supported
-        Err(())
+        // we're a private node, so replies have to come in through the LSP
+        let intermediate_nodes = self
+            .intermediate_nodes
+            .iter()
+            .map(|node_id| MessageForwardNode {
+                node_id: *node_id,
+                short_channel_id: None,
+            })
+            .collect::<Vec<_>>();
+
+        BlindedMessagePath::new(
+            &intermediate_nodes,
+            recipient,
+            context,
+            &self.entropy_source,
+            secp_ctx,
+        )
+        .map(|path| vec![path])
     }
 }
 
@@ -425,7 +449,24 @@ pub(crate) async fn connect_peer_if_necessary<
     // otherwise we could cause force closes.
     // If we didn't have the lock last, we need to panic because
     // the state could have changed.
-    if let Some(lock) = storage.fetch_device_lock().await? {
+    let lock = match storage.fetch_device_lock().await {
+        Ok(lock) => lock,
+        // we only start without VSS if this device was the last to run,
+        // so until VSS is back the local lock is the best we have
+        Err(e)
+            if storage
+                .vss_client()
+                .is_some_and(|v| v.health.unreachable_since().is_some()) =>
+        {
+            log_warn!(
+                logger,
+                "VSS is unreachable, checking the local device lock: {e}"
+            );
+            storage.get_device_lock()?
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(lock) = lock {
         let id = storage.get_device_id()?;
         if !lock.is_last_locker(&id) {
             log_warn!(
@@ -443,10 +484,15 @@ pub(crate) async fn connect_peer_if_necessary<
     // could occur due to UpdateFee message conflicts.
     fee_estimator.update_fee_estimates_if_necessary().await?;
 
+    // some peers can only be reached through a particular proxy
+    #[cfg(target_arch = "wasm32")]
+    let proxy_override = get_peer_overrides(storage, &peer_connection_info.pubkey)
+        .ok()
+        .and_then(|o| o.proxy_addr);
     #[cfg(target_arch = "wasm32")]
     let ret = connect_peer(
         #[cfg(target_arch = "wasm32")]
-        websocket_proxy_addr,
+        proxy_override.as_deref().unwrap_or(websocket_proxy_addr),
         peer_connection_info,
         logger,
         peer_manager,
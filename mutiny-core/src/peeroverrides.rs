@@ -0,0 +1,130 @@
+//! Per peer overrides for peers and LSPs that need special treatment.
+//!
+//! Some peers only work through a particular websocket proxy, drop connections
+//! we keep reopening, or mishandle anchor channels. The overrides are kept per
+//! peer and applied whenever we connect to them or open a channel with them.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub(crate) const PEER_OVERRIDES_PREFIX: &str = "peer_overrides/";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerOverrides {
+    /// Websocket proxy to reach the peer through instead of the wallet's, only
+    /// used in the browser
+    pub proxy_addr: Option<String>,
+    /// Only connect when asked to, not on start or after a disconnect
+    pub never_auto_reconnect: bool,
+    /// Open channels without anchor outputs. Channels the peer opens to us use
+    /// whatever type they ask for.
+    pub force_legacy_channel_type: bool,
+}
+
+fn peer_overrides_key(peer: &PublicKey) -> String {
+    format!("{PEER_OVERRIDES_PREFIX}{peer}")
+}
+
+/// The overrides for the peer, the defaults if none are set
+pub(crate) fn get_peer_overrides<S: MutinyStorage>(
+    storage: &S,
+    peer: &PublicKey,
+) -> Result<PeerOverrides, MutinyError> {
+    Ok(storage
+        .get_data(peer_overrides_key(peer))?
+        .unwrap_or_default())
+}
+
+/// Whether we should leave connecting to the peer to the user
+pub(crate) fn is_auto_reconnect_disabled<S: MutinyStorage>(storage: &S, peer: &PublicKey) -> bool {
+    get_peer_overrides(storage, peer).is_ok_and(|o| o.never_auto_reconnect)
+}
+
+/// Sets the overrides for the peer, setting the defaults removes them
+pub(crate) fn set_peer_overrides<S: MutinyStorage>(
+    storage: &S,
+    peer: &PublicKey,
+    overrides: &PeerOverrides,
+) -> Result<(), MutinyError> {
+    let key = peer_overrides_key(peer);
+    if *overrides == PeerOverrides::default() {
+        return storage.delete(&[key]);
+    }
+    if overrides
+        .proxy_addr
+        .as_ref()
+        .is_some_and(|p| p.trim().is_empty())
+    {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    storage.write_data(key, overrides, None)
+}
+
+/// All the peers with overrides set
+pub(crate) fn list_peer_overrides<S: MutinyStorage>(
+    storage: &S,
+) -> Result<HashMap<PublicKey, PeerOverrides>, MutinyError> {
+    let all: HashMap<String, PeerOverrides> = storage.scan(PEER_OVERRIDES_PREFIX, None)?;
+    Ok(all
+        .into_iter()
+        .filter_map(|(key, overrides)| {
+            let peer = key.strip_prefix(PEER_OVERRIDES_PREFIX)?;
+            Some((PublicKey::from_str(peer).ok()?, overrides))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_peer_overrides() {
+        let test_name = "test_peer_overrides";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let peer = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        assert_eq!(
+            get_peer_overrides(&storage, &peer).unwrap(),
+            PeerOverrides::default()
+        );
+        assert!(!is_auto_reconnect_disabled(&storage, &peer));
+
+        let overrides = PeerOverrides {
+            proxy_addr: Some("wss://p.example.com".to_string()),
+            never_auto_reconnect: true,
+            force_legacy_channel_type: true,
+        };
+        set_peer_overrides(&storage, &peer, &overrides).unwrap();
+        assert_eq!(get_peer_overrides(&storage, &peer).unwrap(), overrides);
+        assert!(is_auto_reconnect_disabled(&storage, &peer));
+        assert_eq!(
+            list_peer_overrides(&storage).unwrap(),
+            HashMap::from([(peer, overrides)])
+        );
+
+        let blank_proxy = PeerOverrides {
+            proxy_addr: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert!(set_peer_overrides(&storage, &peer, &blank_proxy).is_err());
+
+        // going back to the defaults removes them
+        set_peer_overrides(&storage, &peer, &PeerOverrides::default()).unwrap();
+        assert!(list_peer_overrides(&storage).unwrap().is_empty());
+        assert!(!is_auto_reconnect_disabled(&storage, &peer));
+    }
+}
@@ -0,0 +1,363 @@
+use crate::error::MutinyError;
+use crate::mpp::MppConfig;
+use crate::nodemanager::MutinyBip21RawMaterials;
+use crate::paymentpolicy::PaymentPolicy;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use crate::{ActivityItem, MutinyBalance, MutinyInvoice, MutinyWallet};
+use bitcoin::hashes::{sha256, Hash};
+use hex_conservative::DisplayHex;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+pub(crate) const APP_GRANT_PREFIX: &str = "app_grant/";
+/// Routing fees an app's payment may pay on top of its amount, in parts per
+/// million, the same 1% LDK allows by default
+const APP_MAX_FEE_PPM: u64 = 10_000;
+/// Routing fees an app's payment may always pay, however small it is
+const APP_MIN_FEE_SATS: u64 = 50;
+
+/// The most an app's payment of the amount may pay in routing fees, in sats
+pub(crate) fn app_max_fee_sats(amount_sats: u64) -> u64 {
+    (amount_sats.saturating_mul(APP_MAX_FEE_PPM) / 1_000_000).saturating_add(APP_MIN_FEE_SATS)
+}
+
+/// What an embedding app is allowed to do with the wallet
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum AppPermission {
+    /// See balances and activity
+    Read,
+    /// Create invoices and addresses to receive funds
+    Receive,
+    /// Pay invoices, up to the grant's spending limit
+    Spend,
+}
+
+impl core::str::FromStr for AppPermission {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Read" => Ok(Self::Read),
+            "Receive" => Ok(Self::Receive),
+            "Spend" => Ok(Self::Spend),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// Access granted to an embedding app, identified by its api key
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AppGrant {
+    pub id: String,
+    pub name: String,
+    /// Hash of the api key, the key itself is only returned when the grant is created
+    pub key_hash: sha256::Hash,
+    pub permissions: Vec<AppPermission>,
+    /// Total amount in sats the app can spend, unlimited if not set
+    pub spend_limit_sats: Option<u64>,
+    /// Total amount in sats the app has spent, including fees
+    pub spent_sats: u64,
+    /// Amount in sats held for the app's payments that are in flight
+    #[serde(default)]
+    pub reserved_sats: u64,
+    pub revoked: bool,
+    pub created_at: u64,
+}
+
+impl AppGrant {
+    /// Creates a new grant and the api key the app uses to access it
+    pub(crate) fn new(
+        name: String,
+        permissions: Vec<AppPermission>,
+        spend_limit_sats: Option<u64>,
+    ) -> Result<(Self, String), MutinyError> {
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).map_err(|_| MutinyError::WalletOperationFailed)?;
+
+        let id = Uuid::new_v4().to_string();
+        let api_key = format!("{id}.{}", secret.to_lower_hex_string());
+        let grant = Self {
+            id,
+            name,
+            key_hash: sha256::Hash::hash(api_key.as_bytes()),
+            permissions,
+            spend_limit_sats,
+            spent_sats: 0,
+            reserved_sats: 0,
+            revoked: false,
+            created_at: utils::now().as_secs(),
+        };
+
+        Ok((grant, api_key))
+    }
+
+    /// Amount in sats the app can still spend, `None` if unlimited
+    pub fn remaining_sats(&self) -> Option<u64> {
+        self.spend_limit_sats.map(|limit| {
+            limit
+                .saturating_sub(self.spent_sats)
+                .saturating_sub(self.reserved_sats)
+        })
+    }
+
+    pub(crate) fn check(&self, permission: AppPermission) -> Result<(), MutinyError> {
+        if self.revoked || !self.permissions.contains(&permission) {
+            return Err(MutinyError::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_spend(&self, amount_sats: u64) -> Result<(), MutinyError> {
+        self.check(AppPermission::Spend)?;
+        if self
+            .remaining_sats()
+            .is_some_and(|remaining| amount_sats > remaining)
+        {
+            return Err(MutinyError::PermissionDenied);
+        }
+        Ok(())
+    }
+}
+
+fn app_grant_key(id: &str) -> String {
+    format!("{APP_GRANT_PREFIX}{id}")
+}
+
+pub(crate) fn persist_app_grant<S: MutinyStorage>(
+    storage: &S,
+    grant: &AppGrant,
+) -> Result<(), MutinyError> {
+    storage.write_data(app_grant_key(&grant.id), grant, None)
+}
+
+pub(crate) fn get_app_grant<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<AppGrant>, MutinyError> {
+    storage.get_data(app_grant_key(id))
+}
+
+/// Lists all app grants, newest first
+pub(crate) fn list_app_grants<S: MutinyStorage>(storage: &S) -> Result<Vec<AppGrant>, MutinyError> {
+    let mut grants: Vec<AppGrant> = storage
+        .scan::<AppGrant>(APP_GRANT_PREFIX, None)?
+        .into_values()
+        .collect();
+    grants.sort_by_key(|g| std::cmp::Reverse(g.created_at));
+
+    Ok(grants)
+}
+
+/// Finds the grant for the given api key
+pub(crate) fn get_app_grant_by_key<S: MutinyStorage>(
+    storage: &S,
+    api_key: &str,
+) -> Result<AppGrant, MutinyError> {
+    let (id, _) = api_key
+        .split_once('.')
+        .ok_or(MutinyError::PermissionDenied)?;
+    let grant = get_app_grant(storage, id)?.ok_or(MutinyError::PermissionDenied)?;
+    if grant.key_hash != sha256::Hash::hash(api_key.as_bytes()) {
+        return Err(MutinyError::PermissionDenied);
+    }
+
+    Ok(grant)
+}
+
+/// Holds the amount against the app's spending limit until the payment is
+/// settled, failing if it doesn't fit in what's left. The lock is held while
+/// the grant is read and updated, so two payments can't both fit in what's left.
+pub(crate) fn reserve_spend<S: MutinyStorage>(
+    storage: &S,
+    lock: &Mutex<()>,
+    api_key: &str,
+    amount_sats: u64,
+) -> Result<AppGrant, MutinyError> {
+    let _lock = lock.lock().unwrap();
+    let mut grant = get_app_grant_by_key(storage, api_key)?;
+    grant.check_spend(amount_sats)?;
+    grant.reserved_sats = grant.reserved_sats.saturating_add(amount_sats);
+    persist_app_grant(storage, &grant)?;
+
+    Ok(grant)
+}
+
+/// Releases an amount held by [`reserve_spend`] and counts what was actually
+/// spent, 0 if the payment failed
+pub(crate) fn settle_spend<S: MutinyStorage>(
+    storage: &S,
+    lock: &Mutex<()>,
+    id: &str,
+    reserved_sats: u64,
+    spent_sats: u64,
+) -> Result<(), MutinyError> {
+    let _lock = lock.lock().unwrap();
+    let mut grant = get_app_grant(storage, id)?.ok_or(MutinyError::NotFound)?;
+    grant.reserved_sats = grant.reserved_sats.saturating_sub(reserved_sats);
+    grant.spent_sats = grant.spent_sats.saturating_add(spent_sats);
+    persist_app_grant(storage, &grant)
+}
+
+/// A view of the wallet limited to what an app has been granted.
+/// Every call re-reads the grant so revocations apply immediately.
+#[derive(Clone)]
+pub struct AppSession<S: MutinyStorage> {
+    wallet: MutinyWallet<S>,
+    api_key: String,
+}
+
+impl<S: MutinyStorage> AppSession<S> {
+    pub(crate) fn new(wallet: MutinyWallet<S>, api_key: String) -> Result<Self, MutinyError> {
+        // fail early if the key is wrong
+        get_app_grant_by_key(&wallet.storage, &api_key)?;
+        Ok(Self { wallet, api_key })
+    }
+
+    fn grant(&self, permission: AppPermission) -> Result<AppGrant, MutinyError> {
+        let grant = get_app_grant_by_key(&self.wallet.storage, &self.api_key)?;
+        grant.check(permission)?;
+        Ok(grant)
+    }
+
+    /// The grant this session was opened with
+    pub fn get_grant(&self) -> Result<AppGrant, MutinyError> {
+        get_app_grant_by_key(&self.wallet.storage, &self.api_key)
+    }
+
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyError> {
+        self.grant(AppPermission::Read)?;
+        self.wallet.get_balance().await
+    }
+
+    pub fn get_activity(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<Vec<ActivityItem>, MutinyError> {
+        self.grant(AppPermission::Read)?;
+        self.wallet.get_activity(limit, offset)
+    }
+
+    pub async fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyError> {
+        self.grant(AppPermission::Receive)?;
+        self.wallet.create_bip21(amount, labels, None).await
+    }
+
+    /// Pays the invoice if it and the most it may pay in fees fit in the app's
+    /// remaining spending limit. That total is held against the limit while the
+    /// payment is in flight, then the amount paid including fees is counted instead.
+    pub async fn pay_invoice(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.grant(AppPermission::Spend)?;
+        let amount = amt_sats
+            .or(inv.amount_milli_satoshis().map(|msats| msats / 1_000))
+            .ok_or(MutinyError::BadAmountError)?;
+        let max_fee = app_max_fee_sats(amount);
+        let reserved = amount.saturating_add(max_fee);
+        let lock = &self.wallet.app_spend_lock;
+        let grant = reserve_spend(&self.wallet.storage, lock, &self.api_key, reserved)?;
+
+        let policy = PaymentPolicy {
+            max_fee_sats: Some(max_fee),
+            ..Default::default()
+        };
+        let res = self
+            .wallet
+            .pay_invoice_with_options(inv, amt_sats, MppConfig::default(), policy, labels)
+            .await;
+        let spent = match &res {
+            Ok(invoice) => invoice.amount_sats.unwrap_or(amount) + invoice.fees_paid.unwrap_or(0),
+            // a timed out payment can still go through, keep counting the most it could cost
+            Err(MutinyError::PaymentTimeout) => reserved,
+            Err(_) => 0,
+        };
+        settle_spend(&self.wallet.storage, lock, &grant.id, reserved, spent)?;
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_app_grant_permissions() {
+        let test_name = "test_app_grant_permissions";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let (mut grant, api_key) = AppGrant::new(
+            "app".to_string(),
+            vec![AppPermission::Read, AppPermission::Spend],
+            Some(1_000),
+        )
+        .unwrap();
+        persist_app_grant(&storage, &grant).unwrap();
+
+        assert_eq!(get_app_grant_by_key(&storage, &api_key).unwrap(), grant);
+        assert!(get_app_grant_by_key(&storage, &format!("{}.00", grant.id)).is_err());
+        assert!(get_app_grant_by_key(&storage, "garbage").is_err());
+
+        assert!(grant.check(AppPermission::Read).is_ok());
+        assert!(grant.check(AppPermission::Receive).is_err());
+
+        assert!(grant.check_spend(1_000).is_ok());
+        grant.spent_sats = 600;
+        assert_eq!(grant.remaining_sats(), Some(400));
+        assert!(grant.check_spend(401).is_err());
+
+        grant.revoked = true;
+        assert!(grant.check(AppPermission::Read).is_err());
+    }
+
+    #[test]
+    fn test_spend_reservation() {
+        let test_name = "test_spend_reservation";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let lock = Mutex::new(());
+        let (grant, api_key) =
+            AppGrant::new("app".to_string(), vec![AppPermission::Spend], Some(1_000)).unwrap();
+        persist_app_grant(&storage, &grant).unwrap();
+
+        // a payment holds its amount and the most it may pay in fees
+        let reserved = 500 + app_max_fee_sats(500);
+        assert_eq!(reserved, 555);
+
+        // a second payment can't use what the first one holds
+        reserve_spend(&storage, &lock, &api_key, reserved).unwrap();
+        assert!(reserve_spend(&storage, &lock, &api_key, reserved).is_err());
+        let held = get_app_grant(&storage, &grant.id).unwrap().unwrap();
+        assert_eq!(held.remaining_sats(), Some(445));
+
+        // the first one succeeds with less in fees than it held
+        settle_spend(&storage, &lock, &grant.id, reserved, 510).unwrap();
+        let settled = get_app_grant(&storage, &grant.id).unwrap().unwrap();
+        assert_eq!(settled.reserved_sats, 0);
+        assert_eq!(settled.remaining_sats(), Some(490));
+
+        // a failed payment gives its amount back
+        reserve_spend(&storage, &lock, &api_key, 300).unwrap();
+        settle_spend(&storage, &lock, &grant.id, 300, 0).unwrap();
+        let released = get_app_grant(&storage, &grant.id).unwrap().unwrap();
+        assert_eq!(released.remaining_sats(), Some(490));
+    }
+}
@@ -0,0 +1,154 @@
+//! Probing a payment before sending it.
+//!
+//! A probe is an HTLC sent along the route a payment would take, with a
+//! payment hash nobody knows the preimage of. It can never be claimed, so no
+//! funds move, but where it fails tells us whether the payment would get
+//! through and what the route would cost. LDK sends one probe per path the
+//! payment would be split into and reports back through events.
+
+use bitcoin::secp256k1::PublicKey;
+use futures_util::lock::Mutex;
+use lightning::ln::channelmanager::PaymentId;
+use lightning::routing::router::Path;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How long to wait for probes to come back by default
+pub const DEFAULT_PROBE_TIMEOUT_SECS: u64 = 30;
+
+/// What to probe a route to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeTarget {
+    Invoice(Bolt11Invoice),
+    /// A node we'd keysend to
+    Node(PublicKey),
+}
+
+/// How each probe we sent turned out, filled in by the event handler
+pub(crate) type ProbeResults = Arc<Mutex<HashMap<PaymentId, ProbeOutcome>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProbeOutcome {
+    pub succeeded: bool,
+    /// What the path would deliver to the recipient
+    pub amount_msat: u64,
+    pub fee_msat: u64,
+}
+
+impl ProbeOutcome {
+    pub(crate) fn new(path: &Path, succeeded: bool) -> Self {
+        Self {
+            succeeded,
+            amount_msat: path.final_value_msat(),
+            fee_msat: path.fee_msat(),
+        }
+    }
+}
+
+/// What probing a payment found out
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProbeResult {
+    /// Whether we found a route to the recipient at all
+    pub reachable: bool,
+    /// What the routes found would pay in routing fees, in sats
+    pub fee_sats: Option<u64>,
+    /// Share of the amount whose probes made it to the recipient, from 0 to 1.
+    /// Probes that didn't come back in time count as failed.
+    pub success_likelihood: f64,
+    /// How many parts the payment would be split into
+    pub paths: usize,
+}
+
+impl ProbeResult {
+    pub(crate) fn unreachable() -> Self {
+        Self {
+            reachable: false,
+            fee_sats: None,
+            success_likelihood: 0.0,
+            paths: 0,
+        }
+    }
+
+    /// Sums up the probes for the paths we sent. Paths LDK didn't need to probe,
+    /// like a channel straight to the recipient, leave nothing to send.
+    pub(crate) fn from_outcomes(sent: usize, outcomes: &[ProbeOutcome]) -> Self {
+        if sent == 0 {
+            return Self {
+                reachable: true,
+                fee_sats: Some(0),
+                success_likelihood: 1.0,
+                paths: 1,
+            };
+        }
+
+        let total_msat: u64 = outcomes.iter().map(|o| o.amount_msat).sum();
+        let succeeded_msat: u64 = outcomes
+            .iter()
+            .filter(|o| o.succeeded)
+            .map(|o| o.amount_msat)
+            .sum();
+        // a probe that never came back counts as a path that failed
+        let answered = outcomes.len() as f64 / sent as f64;
+        let success_likelihood = succeeded_msat as f64 / total_msat.max(1) as f64 * answered;
+        let fee_msat: u64 = outcomes.iter().map(|o| o.fee_msat).sum();
+
+        Self {
+            reachable: true,
+            fee_sats: (outcomes.len() == sent).then(|| fee_msat.div_ceil(1_000)),
+            success_likelihood,
+            paths: sent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn outcome(succeeded: bool, amount_msat: u64, fee_msat: u64) -> ProbeOutcome {
+        ProbeOutcome {
+            succeeded,
+            amount_msat,
+            fee_msat,
+        }
+    }
+
+    #[test]
+    fn test_probe_result() {
+        let test_name = "test_probe_result";
+        log!("{}", test_name);
+
+        // nothing to probe, straight to the recipient
+        let direct = ProbeResult::from_outcomes(0, &[]);
+        assert!(direct.reachable);
+        assert_eq!(direct.fee_sats, Some(0));
+        assert_eq!(direct.success_likelihood, 1.0);
+
+        let all = ProbeResult::from_outcomes(
+            2,
+            &[outcome(true, 60_000, 1_500), outcome(true, 40_000, 1_000)],
+        );
+        assert_eq!(all.fee_sats, Some(3));
+        assert_eq!(all.success_likelihood, 1.0);
+        assert_eq!(all.paths, 2);
+
+        let some = ProbeResult::from_outcomes(
+            2,
+            &[outcome(true, 60_000, 1_500), outcome(false, 40_000, 1_000)],
+        );
+        assert_eq!(some.success_likelihood, 0.6);
+
+        // one never came back
+        let late = ProbeResult::from_outcomes(2, &[outcome(true, 60_000, 1_500)]);
+        assert_eq!(late.fee_sats, None);
+        assert_eq!(late.success_likelihood, 0.5);
+
+        assert!(!ProbeResult::unreachable().reachable);
+    }
+}
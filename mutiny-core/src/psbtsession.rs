@@ -0,0 +1,163 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::psbt::Psbt;
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const PSBT_SESSION_PREFIX: &str = "psbt_session/";
+
+/// A transaction being signed outside of the wallet, e.g. by a hardware wallet
+/// or another coordinator. Persisted so signing can continue after a reload.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PsbtSession {
+    /// The txid of the unsigned transaction, it does not change as signatures are added
+    pub id: Txid,
+    /// The PSBT with every signature collected so far, base64 encoded
+    pub psbt: String,
+    pub status: PsbtStatus,
+    /// Labels to apply to the transaction once it is broadcast
+    pub labels: Vec<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PsbtStatus {
+    /// No input has been signed yet
+    Unsigned,
+    /// Some inputs still need signatures
+    PartiallySigned,
+    /// Every input is signed, ready to broadcast
+    Signed,
+    Broadcast,
+}
+
+impl PsbtSession {
+    pub(crate) fn new(psbt: &Psbt, labels: Vec<String>) -> Self {
+        let now = utils::now().as_secs();
+        Self {
+            id: psbt.unsigned_tx.compute_txid(),
+            psbt: psbt.to_string(),
+            status: psbt_status(psbt),
+            labels,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn psbt(&self) -> Result<Psbt, MutinyError> {
+        Psbt::from_str(&self.psbt).map_err(|_| MutinyError::InvalidArgumentsError)
+    }
+
+    /// Merges in signatures from another copy of the same PSBT
+    pub(crate) fn combine(&mut self, other: Psbt) -> Result<(), MutinyError> {
+        if other.unsigned_tx.compute_txid() != self.id {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let mut psbt = self.psbt()?;
+        psbt.combine(other)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        self.set_psbt(&psbt);
+
+        Ok(())
+    }
+
+    pub(crate) fn set_psbt(&mut self, psbt: &Psbt) {
+        self.psbt = psbt.to_string();
+        self.status = psbt_status(psbt);
+        self.updated_at = utils::now().as_secs();
+    }
+}
+
+fn input_is_signed(input: &bitcoin::psbt::Input) -> bool {
+    input.final_script_witness.is_some()
+        || input.final_script_sig.is_some()
+        || input.tap_key_sig.is_some()
+        || !input.tap_script_sigs.is_empty()
+        || !input.partial_sigs.is_empty()
+}
+
+/// How far along signing the PSBT is
+pub(crate) fn psbt_status(psbt: &Psbt) -> PsbtStatus {
+    let signed = psbt.inputs.iter().filter(|i| input_is_signed(i)).count();
+    if signed == 0 {
+        PsbtStatus::Unsigned
+    } else if signed < psbt.inputs.len() {
+        PsbtStatus::PartiallySigned
+    } else {
+        PsbtStatus::Signed
+    }
+}
+
+fn psbt_session_key(id: &Txid) -> String {
+    format!("{PSBT_SESSION_PREFIX}{id}")
+}
+
+pub(crate) fn persist_psbt_session<S: MutinyStorage>(
+    storage: &S,
+    session: &PsbtSession,
+) -> Result<(), MutinyError> {
+    storage.write_data(psbt_session_key(&session.id), session, None)
+}
+
+pub(crate) fn get_psbt_session<S: MutinyStorage>(
+    storage: &S,
+    id: &Txid,
+) -> Result<Option<PsbtSession>, MutinyError> {
+    storage.get_data(psbt_session_key(id))
+}
+
+/// Lists all PSBT sessions, most recently updated first
+pub(crate) fn list_psbt_sessions<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PsbtSession>, MutinyError> {
+    let mut sessions: Vec<PsbtSession> = storage
+        .scan::<PsbtSession>(PSBT_SESSION_PREFIX, None)?
+        .into_values()
+        .collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+
+    Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // two inputs, the first one has a final script sig, the second has none
+    const PSBT: &str = "cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEHakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpIAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA";
+
+    #[test]
+    fn test_psbt_session() {
+        let test_name = "test_psbt_session";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let psbt = Psbt::from_str(PSBT).unwrap();
+        assert_eq!(psbt_status(&psbt), PsbtStatus::PartiallySigned);
+
+        let mut unsigned = psbt.clone();
+        unsigned.inputs[0].final_script_sig = None;
+        assert_eq!(psbt_status(&unsigned), PsbtStatus::Unsigned);
+
+        // signatures from another signer get merged into the stored session
+        let mut session = PsbtSession::new(&unsigned, vec!["test".to_string()]);
+        persist_psbt_session(&storage, &session).unwrap();
+        session.combine(psbt.clone()).unwrap();
+        assert_eq!(session.status, PsbtStatus::PartiallySigned);
+        persist_psbt_session(&storage, &session).unwrap();
+
+        let stored = get_psbt_session(&storage, &psbt.unsigned_tx.compute_txid())
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored, session);
+        assert_eq!(list_psbt_sessions(&storage).unwrap(), vec![session]);
+    }
+}
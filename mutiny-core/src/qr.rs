@@ -0,0 +1,116 @@
+//! QR codes for addresses, invoices, offers and connection URIs, rendered in
+//! core so every frontend encodes them the same way.
+
+use crate::error::MutinyError;
+use anyhow::anyhow;
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{Bech32, Bech32m};
+use image::codecs::png::PngEncoder;
+use image::Luma;
+use lightning_invoice::Bolt11Invoice;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use std::str::FromStr;
+
+/// Bech32 strings like addresses and LNURLs, and BOLT11 invoices, are case
+/// insensitive, in upper case they fit the denser alphanumeric mode and make
+/// smaller codes. Anything else, like URIs with query strings, is encoded as is.
+pub(crate) fn qr_data(data: &str) -> String {
+    let data = data.trim();
+    let case_insensitive = CheckedHrpstring::new::<Bech32>(data).is_ok()
+        || CheckedHrpstring::new::<Bech32m>(data).is_ok()
+        || Bolt11Invoice::from_str(data).is_ok();
+    if case_insensitive {
+        data.to_ascii_uppercase()
+    } else {
+        data.to_string()
+    }
+}
+
+fn qr_code(data: &str) -> Result<QrCode, MutinyError> {
+    QrCode::new(qr_data(data)).map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
+/// Renders the data as an SVG document at least `size` pixels wide
+pub fn qr_svg(data: &str, size: u32) -> Result<String, MutinyError> {
+    let code = qr_code(data)?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(size, size)
+        .dark_color(svg::Color("#000000"))
+        .light_color(svg::Color("#ffffff"))
+        .build())
+}
+
+/// Renders the data as a black and white PNG, `module_size` pixels per module
+pub fn qr_png(data: &str, module_size: u32) -> Result<Vec<u8>, MutinyError> {
+    if module_size == 0 {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    let image = qr_code(data)?
+        .render::<Luma<u8>>()
+        .module_dimensions(module_size, module_size)
+        .build();
+
+    let mut png = vec![];
+    image
+        .write_with_encoder(PngEncoder::new(&mut png))
+        .map_err(|e| MutinyError::Other(anyhow!("Failed to encode QR code: {e}")))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const INVOICE: &str = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
+
+    #[test]
+    fn test_qr_data() {
+        let test_name = "test_qr_data";
+        log!("{}", test_name);
+
+        assert_eq!(qr_data(ADDRESS), ADDRESS.to_uppercase());
+        assert_eq!(qr_data(INVOICE), INVOICE.to_uppercase());
+        // looks like bech32 but the checksum doesn't match
+        let typo = ADDRESS.replace('q', "p");
+        assert_eq!(qr_data(&typo), typo);
+        assert_eq!(qr_data("hello123"), "hello123");
+        let uri = "nostr+walletconnect://abc?relay=wss://relay.example.com&secret=def";
+        assert_eq!(qr_data(uri), uri);
+        let bip21 = format!("bitcoin:{ADDRESS}?amount=0.001");
+        assert_eq!(qr_data(&bip21), bip21);
+    }
+
+    #[test]
+    fn test_qr_png() {
+        let test_name = "test_qr_png";
+        log!("{}", test_name);
+
+        let png = qr_png(ADDRESS, 4).unwrap();
+        assert_eq!(
+            png[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        assert_eq!(&png[12..16], b"IHDR");
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        assert_eq!(width % 4, 0);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(image.width(), width);
+        // the quiet zone is light, the finder pattern's corner dark
+        assert_eq!(image.get_pixel(0, 0).0, [255]);
+        assert_eq!(image.get_pixel(4 * 4, 4 * 4).0, [0]);
+
+        assert!(qr_png(ADDRESS, 0).is_err());
+
+        let svg = qr_svg(ADDRESS, 200).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Circular rebalancing, moving liquidity from one of our channels to
+//! another by paying ourselves. The payment leaves over the first channel and
+//! comes back over the second, the only cost is the routing fee.
+
+use crate::error::MutinyError;
+use crate::event::HTLCStatus;
+use crate::storage::MutinyStorage;
+use crate::MutinyInvoice;
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning::ln::channel_state::CounterpartyForwardingInfo;
+use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+use lightning::routing::router::{Path, RouteHop};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub(crate) const REBALANCE_PREFIX: &str = "rebalance/";
+/// CLTV delta of the hop back to us, same as we use for keysend
+pub(crate) const REBALANCE_FINAL_CLTV_EXPIRY_DELTA: u32 = 40;
+
+/// A payment to ourselves that moved funds between two of our channels
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Rebalance {
+    pub payment_hash: sha256::Hash,
+    /// Funding outpoint of the channel the funds left from
+    pub from_channel: OutPoint,
+    /// Funding outpoint of the channel the funds came back over
+    pub to_channel: OutPoint,
+    pub amount_sats: u64,
+    pub status: HTLCStatus,
+    /// The routing fee, the only thing a rebalance costs
+    pub fee_paid_msat: Option<u64>,
+    pub created_at: u64,
+    pub last_updated: u64,
+}
+
+impl Rebalance {
+    /// Takes the status and fee from the outgoing side of the payment
+    pub(crate) fn with_payment(mut self, payment: &MutinyInvoice) -> Self {
+        self.status = payment.status.clone();
+        self.fee_paid_msat = payment.fee_paid_msat;
+        self.last_updated = payment.last_updated;
+        self
+    }
+}
+
+/// What the peer charges to forward `amount_msat` to us
+pub(crate) fn forwarding_fee_msat(
+    forwarding: &CounterpartyForwardingInfo,
+    amount_msat: u64,
+) -> u64 {
+    forwarding.fee_base_msat as u64
+        + amount_msat * forwarding.fee_proportional_millionths as u64 / 1_000_000
+}
+
+/// Extends a path ending at the peer of the channel we're rebalancing into
+/// with the hop back to us over that channel. The path has to deliver the
+/// amount plus the peer's forwarding fee to the peer.
+pub(crate) fn close_circular_path(
+    mut path: Path,
+    our_node_id: PublicKey,
+    our_features: NodeFeatures,
+    to_scid: u64,
+    forwarding: &CounterpartyForwardingInfo,
+    amount_msat: u64,
+) -> Result<Path, MutinyError> {
+    let peer = path.hops.last_mut().ok_or(MutinyError::RoutingFailed)?;
+    // the peer's hop now carries what it charges for the last hop
+    peer.fee_msat = forwarding_fee_msat(forwarding, amount_msat);
+    peer.cltv_expiry_delta = forwarding.cltv_expiry_delta as u32;
+
+    path.hops.push(RouteHop {
+        pubkey: our_node_id,
+        node_features: our_features,
+        short_channel_id: to_scid,
+        channel_features: ChannelFeatures::empty(),
+        fee_msat: amount_msat,
+        cltv_expiry_delta: REBALANCE_FINAL_CLTV_EXPIRY_DELTA,
+        maybe_announced_channel: false,
+    });
+
+    Ok(path)
+}
+
+fn rebalance_key(payment_hash: &sha256::Hash) -> String {
+    format!("{REBALANCE_PREFIX}{payment_hash}")
+}
+
+pub(crate) fn persist_rebalance<S: MutinyStorage>(
+    storage: &S,
+    rebalance: &Rebalance,
+) -> Result<(), MutinyError> {
+    storage.write_data(rebalance_key(&rebalance.payment_hash), rebalance, None)
+}
+
+/// All rebalances by their payment hash, used to tell them apart from other payments
+pub(crate) fn get_rebalances<S: MutinyStorage>(
+    storage: &S,
+) -> Result<HashMap<sha256::Hash, Rebalance>, MutinyError> {
+    Ok(storage
+        .scan::<Rebalance>(REBALANCE_PREFIX, None)?
+        .into_values()
+        .map(|r| (r.payment_hash, r))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Txid;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn pubkey(byte: u8) -> PublicKey {
+        SecretKey::from_slice(&[byte; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new())
+    }
+
+    fn hop(byte: u8, fee_msat: u64, cltv_expiry_delta: u32) -> RouteHop {
+        RouteHop {
+            pubkey: pubkey(byte),
+            node_features: NodeFeatures::empty(),
+            short_channel_id: byte as u64,
+            channel_features: ChannelFeatures::empty(),
+            fee_msat,
+            cltv_expiry_delta,
+            maybe_announced_channel: true,
+        }
+    }
+
+    #[test]
+    fn test_close_circular_path() {
+        let test_name = "test_close_circular_path";
+        log!("{}", test_name);
+
+        let forwarding = CounterpartyForwardingInfo {
+            fee_base_msat: 1_000,
+            fee_proportional_millionths: 100,
+            cltv_expiry_delta: 144,
+        };
+        let amount_msat = 10_000_000;
+        assert_eq!(forwarding_fee_msat(&forwarding, amount_msat), 2_000);
+
+        // out over our first channel to a hub, then to the peer of the second
+        let path = Path {
+            hops: vec![hop(2, 500, 80), hop(3, 10_002_000, 52)],
+            blinded_tail: None,
+        };
+        let path = close_circular_path(
+            path,
+            pubkey(1),
+            NodeFeatures::empty(),
+            42,
+            &forwarding,
+            amount_msat,
+        )
+        .unwrap();
+
+        assert_eq!(path.hops.len(), 3);
+        assert_eq!(path.hops[1].fee_msat, 2_000);
+        assert_eq!(path.hops[1].cltv_expiry_delta, 144);
+        let last = path.hops.last().unwrap();
+        assert_eq!(last.pubkey, pubkey(1));
+        assert_eq!(last.short_channel_id, 42);
+        assert_eq!(last.fee_msat, amount_msat);
+        assert_eq!(path.fee_msat(), 2_500);
+        assert_eq!(path.final_value_msat(), amount_msat);
+
+        let empty = Path {
+            hops: vec![],
+            blinded_tail: None,
+        };
+        assert!(close_circular_path(
+            empty,
+            pubkey(1),
+            NodeFeatures::empty(),
+            42,
+            &forwarding,
+            amount_msat
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rebalance_storage() {
+        let test_name = "test_rebalance_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let rebalance = Rebalance {
+            payment_hash: sha256::Hash::from_byte_array([1; 32]),
+            from_channel: OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+            to_channel: OutPoint::new(Txid::from_byte_array([3; 32]), 1),
+            amount_sats: 10_000,
+            status: HTLCStatus::InFlight,
+            fee_paid_msat: None,
+            created_at: 1,
+            last_updated: 1,
+        };
+        persist_rebalance(&storage, &rebalance).unwrap();
+
+        let rebalances = get_rebalances(&storage).unwrap();
+        assert_eq!(rebalances.get(&rebalance.payment_hash), Some(&rebalance));
+
+        let payment = MutinyInvoice {
+            status: HTLCStatus::Succeeded,
+            fee_paid_msat: Some(2_500),
+            last_updated: 5,
+            ..Default::default()
+        };
+        let updated = rebalance.with_payment(&payment);
+        assert_eq!(updated.status, HTLCStatus::Succeeded);
+        assert_eq!(updated.fee_paid_msat, Some(2_500));
+        assert_eq!(updated.last_updated, 5);
+    }
+}
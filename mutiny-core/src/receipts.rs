@@ -0,0 +1,267 @@
+//! Receipts for lightning payments between contacts, sent as NIP-04 direct
+//! messages to the payee's npub.
+//!
+//! After paying someone with a nostr identity the payer can send them a receipt
+//! with the amount, a memo and the preimage. When the payee's wallet finds a
+//! receipt for a payment it received, checked against the preimage, it labels
+//! the payment with the payer's npub so both sides have matching records.
+
+use crate::error::MutinyError;
+use crate::nostr::{
+    fetch_from_relay, nip04_decrypt, nip04_encrypt, sign_event, verify_event, ENCRYPTED_DM_KIND,
+};
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{Secp256k1, SecretKey, XOnlyPublicKey};
+use hex_conservative::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub(crate) const PAYMENT_RECEIPT_PREFIX: &str = "payment_receipt/";
+/// Time of the newest receipt message we've handled, so they aren't fetched again
+pub(crate) const RECEIPT_MESSAGES_SINCE_KEY: &str = "receipt_messages_since";
+
+/// The direct message sent to the payee
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReceiptMessage {
+    PaymentReceipt {
+        payment_hash: String,
+        amount_sats: u64,
+        memo: Option<String>,
+        preimage: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaymentReceipt {
+    pub payment_hash: String,
+    /// Hex encoded nostr pubkey of the other side
+    pub counterparty: String,
+    /// Whether we sent it, i.e. we were the payer
+    pub outgoing: bool,
+    pub amount_sats: u64,
+    pub memo: Option<String>,
+    pub preimage: String,
+    pub created_at: u64,
+}
+
+impl PaymentReceipt {
+    pub(crate) fn message(&self) -> ReceiptMessage {
+        ReceiptMessage::PaymentReceipt {
+            payment_hash: self.payment_hash.clone(),
+            amount_sats: self.amount_sats,
+            memo: self.memo.clone(),
+            preimage: self.preimage.clone(),
+        }
+    }
+
+    /// A receipt sent to us, only when its preimage is the payment's
+    pub(crate) fn from_message(
+        sender: &XOnlyPublicKey,
+        message: ReceiptMessage,
+        created_at: u64,
+    ) -> Result<Self, MutinyError> {
+        let ReceiptMessage::PaymentReceipt {
+            payment_hash,
+            amount_sats,
+            memo,
+            preimage,
+        } = message;
+        let preimage_bytes =
+            <[u8; 32]>::from_hex(&preimage).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        if sha256::Hash::hash(&preimage_bytes).to_string() != payment_hash {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        Ok(Self {
+            payment_hash,
+            counterparty: sender.to_string(),
+            outgoing: false,
+            amount_sats,
+            memo,
+            preimage,
+            created_at,
+        })
+    }
+}
+
+/// Creates a signed direct message event carrying the receipt
+pub(crate) fn create_receipt_dm(
+    key: &SecretKey,
+    to: &XOnlyPublicKey,
+    message: &ReceiptMessage,
+) -> Result<Value, MutinyError> {
+    let content = nip04_encrypt(key, to, &serde_json::to_string(message)?);
+    Ok(sign_event(
+        key,
+        ENCRYPTED_DM_KIND,
+        json!([["p", to.to_string()]]),
+        content,
+    ))
+}
+
+/// Verifies and decrypts a receipt direct message sent to us, returning the
+/// sender, the message and when it was sent
+pub(crate) fn read_receipt_dm(
+    key: &SecretKey,
+    event: &Value,
+) -> Result<(XOnlyPublicKey, ReceiptMessage, u64), MutinyError> {
+    let sender = verify_event(event)?;
+    let kind = event.get("kind").and_then(|k| k.as_u64());
+    let created_at = event.get("created_at").and_then(|c| c.as_u64());
+    let content = event.get("content").and_then(|c| c.as_str());
+    let (Some(ENCRYPTED_DM_KIND), Some(created_at), Some(content)) = (kind, created_at, content)
+    else {
+        return Err(MutinyError::NostrError);
+    };
+
+    let plaintext = nip04_decrypt(key, &sender, content)?;
+    Ok((sender, serde_json::from_str(&plaintext)?, created_at))
+}
+
+/// Fetches the receipts sent to the key since the given time. Other direct
+/// messages are ignored.
+pub(crate) async fn fetch_receipt_dms(
+    key: &SecretKey,
+    relays: &[String],
+    since: u64,
+) -> Result<Vec<(XOnlyPublicKey, ReceiptMessage, u64)>, MutinyError> {
+    let secp = Secp256k1::new();
+    let pubkey = key.x_only_public_key(&secp).0;
+    let filter = json!({
+        "kinds": [ENCRYPTED_DM_KIND],
+        "#p": [pubkey.to_string()],
+        "since": since,
+    });
+
+    let mut messages = vec![];
+    let mut reached_relay = false;
+    for relay in relays {
+        let Ok(events) = fetch_from_relay(relay, &filter, key).await else {
+            continue;
+        };
+        reached_relay = true;
+        for event in events {
+            if let Ok(message) = read_receipt_dm(key, &event) {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+        }
+    }
+
+    if !reached_relay {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    Ok(messages)
+}
+
+fn receipt_key(payment_hash: &str, outgoing: bool) -> String {
+    let direction = if outgoing { "sent" } else { "received" };
+    format!("{PAYMENT_RECEIPT_PREFIX}{direction}/{payment_hash}")
+}
+
+pub(crate) fn persist_receipt<S: MutinyStorage>(
+    storage: &S,
+    receipt: &PaymentReceipt,
+) -> Result<(), MutinyError> {
+    storage.write_data(
+        receipt_key(&receipt.payment_hash, receipt.outgoing),
+        receipt,
+        None,
+    )
+}
+
+/// The receipt for the payment, the one we received before the one we sent
+pub(crate) fn get_receipt<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &str,
+) -> Result<Option<PaymentReceipt>, MutinyError> {
+    match storage.get_data(receipt_key(payment_hash, false))? {
+        Some(receipt) => Ok(Some(receipt)),
+        None => storage.get_data(receipt_key(payment_hash, true)),
+    }
+}
+
+/// Lists the receipts we sent and received, newest first
+pub(crate) fn list_receipts<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PaymentReceipt>, MutinyError> {
+    let mut receipts: Vec<PaymentReceipt> = storage
+        .scan::<PaymentReceipt>(PAYMENT_RECEIPT_PREFIX, None)?
+        .into_values()
+        .collect();
+    receipts.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+    Ok(receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use hex_conservative::DisplayHex;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_payment_receipts() {
+        let test_name = "test_payment_receipts";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let payer_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let payee_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let payer = payer_key.x_only_public_key(&secp).0;
+        let payee = payee_key.x_only_public_key(&secp).0;
+
+        let preimage = [7u8; 32];
+        let sent = PaymentReceipt {
+            payment_hash: sha256::Hash::hash(&preimage).to_string(),
+            counterparty: payee.to_string(),
+            outgoing: true,
+            amount_sats: 21_000,
+            memo: Some("pizza".to_string()),
+            preimage: preimage.to_lower_hex_string(),
+            created_at: 1,
+        };
+
+        let event = create_receipt_dm(&payer_key, &payee, &sent.message()).unwrap();
+        let (from, message, created_at) = read_receipt_dm(&payee_key, &event).unwrap();
+        assert_eq!(from, payer);
+        assert_eq!(message, sent.message());
+        // only the payee can read it
+        assert!(read_receipt_dm(&SecretKey::from_slice(&[3; 32]).unwrap(), &event).is_err());
+
+        let received = PaymentReceipt::from_message(&from, message, created_at).unwrap();
+        assert_eq!(received.counterparty, payer.to_string());
+        assert!(!received.outgoing);
+        assert_eq!(received.memo, sent.memo);
+
+        // a preimage that isn't the payment's is refused
+        let forged = ReceiptMessage::PaymentReceipt {
+            payment_hash: sent.payment_hash.clone(),
+            amount_sats: 21_000,
+            memo: None,
+            preimage: [8u8; 32].to_lower_hex_string(),
+        };
+        assert!(PaymentReceipt::from_message(&payer, forged, 2).is_err());
+
+        // the same payment can be both sent and received, e.g. between our own wallets
+        let storage = MemoryStorage::default();
+        persist_receipt(&storage, &sent).unwrap();
+        assert_eq!(
+            get_receipt(&storage, &sent.payment_hash).unwrap(),
+            Some(sent.clone())
+        );
+        persist_receipt(&storage, &received).unwrap();
+        assert_eq!(
+            get_receipt(&storage, &sent.payment_hash).unwrap(),
+            Some(received.clone())
+        );
+        assert_eq!(list_receipts(&storage).unwrap(), vec![received, sent]);
+    }
+}
@@ -0,0 +1,318 @@
+//! Configuration published by whoever runs the wallet, so they can steer their
+//! users' wallets without shipping a new release: which LSPs and fee APIs to use,
+//! warnings to show about federations and the oldest version still supported.
+//!
+//! The config is only used when it's signed by the key the wallet was built with.
+//! The last good one is kept for when fetching fails, and one older than it is
+//! rejected so a stale config can't be replayed. Local overrides always win.
+
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{schnorr, All, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::Network;
+use lightning::log_warn;
+use lightning::util::logger::Logger;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const REMOTE_CONFIG_KEY: &str = "remote_config";
+pub(crate) const REMOTE_CONFIG_OVERRIDES_KEY: &str = "remote_config_overrides";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteConfig {
+    pub network: Network,
+    /// Goes up with every config published
+    pub serial: u64,
+    /// LSP urls, the first is used when the wallet isn't given one
+    pub recommended_lsps: Vec<String>,
+    /// mempool.space compatible fee APIs, the first is used when the wallet
+    /// is on the default fee source
+    pub fee_endpoints: Vec<String>,
+    /// Warnings to show users of federations
+    pub federation_warnings: Vec<String>,
+    /// Older versions should ask the user to update
+    pub min_supported_version: Option<String>,
+}
+
+/// A [RemoteConfig] with a schnorr signature from whoever published it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignedRemoteConfig {
+    pub config: RemoteConfig,
+    pub signature: schnorr::Signature,
+}
+
+/// Settings the user picked locally, used instead of the remote ones
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteConfigOverrides {
+    pub recommended_lsps: Option<Vec<String>>,
+    pub fee_endpoints: Option<Vec<String>>,
+    pub federation_warnings: Option<Vec<String>>,
+    pub min_supported_version: Option<String>,
+}
+
+fn input_str(engine: &mut sha256::HashEngine, s: &str) {
+    engine.input(&(s.len() as u64).to_le_bytes());
+    engine.input(s.as_bytes());
+}
+
+fn input_list(engine: &mut sha256::HashEngine, list: &[String]) {
+    engine.input(&(list.len() as u64).to_le_bytes());
+    for item in list {
+        input_str(engine, item);
+    }
+}
+
+impl RemoteConfig {
+    fn signing_hash(&self) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.network.magic().to_bytes());
+        engine.input(&self.serial.to_le_bytes());
+        input_list(&mut engine, &self.recommended_lsps);
+        input_list(&mut engine, &self.fee_endpoints);
+        input_list(&mut engine, &self.federation_warnings);
+        match self.min_supported_version.as_ref() {
+            Some(version) => {
+                engine.input(&[1]);
+                input_str(&mut engine, version);
+            }
+            None => engine.input(&[0]),
+        }
+        sha256::Hash::from_engine(engine)
+    }
+
+    /// Signs the config, for whoever publishes it
+    pub fn sign(self, secp: &Secp256k1<All>, keypair: &Keypair) -> SignedRemoteConfig {
+        let msg = Message::from_digest(self.signing_hash().to_byte_array());
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, keypair);
+        SignedRemoteConfig {
+            config: self,
+            signature,
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: &RemoteConfigOverrides) -> Self {
+        if let Some(lsps) = overrides.recommended_lsps.clone() {
+            self.recommended_lsps = lsps;
+        }
+        if let Some(endpoints) = overrides.fee_endpoints.clone() {
+            self.fee_endpoints = endpoints;
+        }
+        if let Some(warnings) = overrides.federation_warnings.clone() {
+            self.federation_warnings = warnings;
+        }
+        if let Some(version) = overrides.min_supported_version.clone() {
+            self.min_supported_version = Some(version);
+        }
+        self
+    }
+
+    /// Whether the app version is older than the minimum supported one
+    pub fn update_required(&self, version: &str) -> bool {
+        self.min_supported_version
+            .as_deref()
+            .is_some_and(|min| version_older(version, min))
+    }
+}
+
+impl SignedRemoteConfig {
+    /// Verifies the config is for our network and signed by the given key
+    pub fn verify(
+        &self,
+        network: Network,
+        signing_key: &XOnlyPublicKey,
+    ) -> Result<(), MutinyError> {
+        if self.config.network != network {
+            return Err(MutinyError::InvalidRemoteConfig);
+        }
+
+        let msg = Message::from_digest(self.config.signing_hash().to_byte_array());
+        Secp256k1::verification_only()
+            .verify_schnorr(&self.signature, &msg, signing_key)
+            .map_err(|_| MutinyError::InvalidRemoteConfig)
+    }
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Compares dotted versions like `1.10.2`, anything after the numbers is ignored
+pub(crate) fn version_older(version: &str, min: &str) -> bool {
+    let mut version = parse_version(version);
+    let mut min = parse_version(min);
+    let len = version.len().max(min.len());
+    version.resize(len, 0);
+    min.resize(len, 0);
+    version < min
+}
+
+pub(crate) async fn fetch_remote_config(
+    client: &Client,
+    url: &str,
+) -> Result<SignedRemoteConfig, MutinyError> {
+    let request = client
+        .get(url)
+        .build()
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+
+    utils::fetch_with_timeout(client, request)
+        .await?
+        .error_for_status()
+        .map_err(|_| MutinyError::ConnectionFailed)?
+        .json()
+        .await
+        .map_err(|_| MutinyError::InvalidRemoteConfig)
+}
+
+/// Fetches the remote config and keeps it when it's valid and not older than
+/// the one we have. Falls back to the last good config if anything fails.
+pub(crate) async fn load_remote_config<S: MutinyStorage>(
+    storage: &S,
+    client: &Client,
+    url: &str,
+    signing_key: &XOnlyPublicKey,
+    network: Network,
+    logger: &MutinyLogger,
+) -> Option<RemoteConfig> {
+    let cached = get_remote_config(storage).ok().flatten();
+    let fetched = match fetch_remote_config(client, url).await {
+        Ok(signed) => match signed.verify(network, signing_key) {
+            Ok(()) => Some(signed.config),
+            Err(e) => {
+                log_warn!(logger, "Remote config failed verification: {e}");
+                None
+            }
+        },
+        Err(e) => {
+            log_warn!(logger, "Failed to fetch remote config: {e}");
+            None
+        }
+    };
+
+    match (cached, fetched) {
+        (Some(cached), Some(fetched)) if fetched.serial < cached.serial => {
+            log_warn!(
+                logger,
+                "Ignoring remote config {} older than our {}",
+                fetched.serial,
+                cached.serial
+            );
+            Some(cached)
+        }
+        (_, Some(fetched)) => {
+            if let Err(e) = persist_remote_config(storage, &fetched) {
+                log_warn!(logger, "Failed to save remote config: {e}");
+            }
+            Some(fetched)
+        }
+        (cached, None) => cached,
+    }
+}
+
+pub(crate) fn get_remote_config<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<RemoteConfig>, MutinyError> {
+    storage.get_data(REMOTE_CONFIG_KEY)
+}
+
+fn persist_remote_config<S: MutinyStorage>(
+    storage: &S,
+    config: &RemoteConfig,
+) -> Result<(), MutinyError> {
+    storage.write_data(REMOTE_CONFIG_KEY.to_string(), config, None)
+}
+
+pub(crate) fn get_remote_config_overrides<S: MutinyStorage>(
+    storage: &S,
+) -> Result<RemoteConfigOverrides, MutinyError> {
+    Ok(storage
+        .get_data(REMOTE_CONFIG_OVERRIDES_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn set_remote_config_overrides<S: MutinyStorage>(
+    storage: &S,
+    overrides: RemoteConfigOverrides,
+) -> Result<(), MutinyError> {
+    storage.write_data(REMOTE_CONFIG_OVERRIDES_KEY.to_string(), overrides, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::SecretKey;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn config() -> RemoteConfig {
+        RemoteConfig {
+            network: Network::Regtest,
+            serial: 2,
+            recommended_lsps: vec!["https://lsp.example.com".to_string()],
+            fee_endpoints: vec![],
+            federation_warnings: vec![],
+            min_supported_version: Some("0.0.1".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_verify_remote_config() {
+        let test_name = "test_verify_remote_config";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (signing_key, _) = keypair.x_only_public_key();
+        let signed = config().sign(&secp, &keypair);
+        assert!(signed.verify(Network::Regtest, &signing_key).is_ok());
+        assert!(!signed.config.update_required("1.10.0"));
+
+        // wrong network
+        assert!(signed.verify(Network::Signet, &signing_key).is_err());
+
+        // tampered with
+        let mut tampered = signed.clone();
+        tampered.config.recommended_lsps = vec!["https://evil.example.com".to_string()];
+        assert!(tampered.verify(Network::Regtest, &signing_key).is_err());
+
+        // signed by someone else
+        let other = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let (other_key, _) = other.x_only_public_key();
+        assert!(signed.verify(Network::Regtest, &other_key).is_err());
+    }
+
+    #[test]
+    fn test_remote_config_overrides() {
+        let test_name = "test_remote_config_overrides";
+        log!("{}", test_name);
+
+        let overrides = RemoteConfigOverrides {
+            recommended_lsps: Some(vec![]),
+            min_supported_version: Some("999.0.0".to_string()),
+            ..Default::default()
+        };
+        let config = config().with_overrides(&overrides);
+        assert!(config.recommended_lsps.is_empty());
+        assert!(config.update_required("1.10.0"));
+
+        assert!(version_older("1.9.0", "1.10.0"));
+        assert!(version_older("1.2", "1.2.1"));
+        assert!(!version_older("1.2.0", "1.2"));
+        assert!(!version_older("v2.0.0-rc1", "1.10.3"));
+    }
+}
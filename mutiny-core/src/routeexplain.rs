@@ -0,0 +1,244 @@
+//! A readable explanation of the route a payment would take.
+//!
+//! Runs the same router a payment would and describes each hop: whose
+//! channel it is, how big it is according to gossip and what the node
+//! charges to forward. Meant for users and support to see why a payment is
+//! failing or expensive without digging through trace logs.
+
+use bitcoin::secp256k1::PublicKey;
+use lightning::routing::router::{Path, Route};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HopExplanation {
+    /// The node this hop's channel leads to
+    pub node_id: PublicKey,
+    pub alias: Option<String>,
+    pub short_channel_id: u64,
+    /// Size of the channel, from gossip or our own channel list
+    pub capacity_sats: Option<u64>,
+    /// What the node charges to forward to the next hop, nothing for the payee
+    pub fee_msat: u64,
+    pub cltv_expiry_delta: u32,
+    /// Whether this is one of our channels
+    pub our_channel: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PathExplanation {
+    /// What this path delivers to the payee
+    pub amount_msat: u64,
+    pub fee_msat: u64,
+    pub hops: Vec<HopExplanation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RouteExplanation {
+    pub amount_msat: u64,
+    pub fee_msat: u64,
+    /// One for each part the payment would be split into, empty if no route was found
+    pub paths: Vec<PathExplanation>,
+    /// Why the router couldn't find a route
+    pub failure: Option<String>,
+    /// Everything above as text, one line per hop
+    pub description: String,
+}
+
+/// Formats a short channel id the usual way, block x transaction x output
+pub(crate) fn format_scid(scid: u64) -> String {
+    format!(
+        "{}x{}x{}",
+        scid >> 40,
+        (scid >> 16) & 0xFF_FFFF,
+        scid & 0xFFFF
+    )
+}
+
+impl PathExplanation {
+    pub(crate) fn from_path(
+        path: &Path,
+        our_scids: &[u64],
+        capacity_sats: &impl Fn(u64) -> Option<u64>,
+        alias: &impl Fn(&PublicKey) -> Option<String>,
+    ) -> Self {
+        let last = path.hops.len().saturating_sub(1);
+        let hops = path
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(i, hop)| HopExplanation {
+                node_id: hop.pubkey,
+                alias: alias(&hop.pubkey),
+                short_channel_id: hop.short_channel_id,
+                capacity_sats: capacity_sats(hop.short_channel_id),
+                // the last hop's fee is the amount it receives
+                fee_msat: if i == last { 0 } else { hop.fee_msat },
+                cltv_expiry_delta: hop.cltv_expiry_delta,
+                our_channel: i == 0 && our_scids.contains(&hop.short_channel_id),
+            })
+            .collect();
+
+        Self {
+            amount_msat: path.final_value_msat(),
+            fee_msat: path.fee_msat(),
+            hops,
+        }
+    }
+}
+
+impl RouteExplanation {
+    pub(crate) fn from_route(
+        route: &Route,
+        our_scids: &[u64],
+        capacity_sats: impl Fn(u64) -> Option<u64>,
+        alias: impl Fn(&PublicKey) -> Option<String>,
+    ) -> Self {
+        let paths: Vec<PathExplanation> = route
+            .paths
+            .iter()
+            .map(|p| PathExplanation::from_path(p, our_scids, &capacity_sats, &alias))
+            .collect();
+
+        let mut explanation = Self {
+            amount_msat: paths.iter().map(|p| p.amount_msat).sum(),
+            fee_msat: paths.iter().map(|p| p.fee_msat).sum(),
+            paths,
+            failure: None,
+            description: String::new(),
+        };
+        explanation.description = explanation.describe();
+        explanation
+    }
+
+    /// No route was found, with what we could have sent instead
+    pub(crate) fn no_route(amount_msat: u64, failure: String, outbound_msat: u64) -> Self {
+        let mut description = format!(
+            "No route found for {} sats: {failure}\n",
+            amount_msat / 1_000
+        );
+        let _ = writeln!(
+            description,
+            "Our channels can send at most {} sats right now",
+            outbound_msat / 1_000
+        );
+
+        Self {
+            amount_msat,
+            fee_msat: 0,
+            paths: vec![],
+            failure: Some(failure),
+            description,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut out = format!(
+            "Paying {} sats over {} path(s) for {} msat in fees\n",
+            self.amount_msat / 1_000,
+            self.paths.len(),
+            self.fee_msat
+        );
+        for (i, path) in self.paths.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "Path {}: {} sats, {} msat in fees",
+                i + 1,
+                path.amount_msat / 1_000,
+                path.fee_msat
+            );
+            for (j, hop) in path.hops.iter().enumerate() {
+                let node = match hop.alias.as_deref() {
+                    Some(alias) => format!("{alias} ({})", hop.node_id),
+                    None => hop.node_id.to_string(),
+                };
+                let capacity = match hop.capacity_sats {
+                    Some(sats) => format!("{sats} sats"),
+                    None => "unknown capacity".to_string(),
+                };
+                let owner = if hop.our_channel { "our channel, " } else { "" };
+                let _ = write!(
+                    out,
+                    "  {}. {node} over {} ({owner}{capacity})",
+                    j + 1,
+                    format_scid(hop.short_channel_id)
+                );
+                if j + 1 < path.hops.len() {
+                    let _ = write!(out, ", charges {} msat to forward", hop.fee_msat);
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning::ln::features::{ChannelFeatures, NodeFeatures};
+    use lightning::routing::router::RouteHop;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn hop(seed: u8, scid: u64, fee_msat: u64) -> RouteHop {
+        let secp = Secp256k1::new();
+        RouteHop {
+            pubkey: SecretKey::from_slice(&[seed; 32])
+                .unwrap()
+                .public_key(&secp),
+            node_features: NodeFeatures::empty(),
+            short_channel_id: scid,
+            channel_features: ChannelFeatures::empty(),
+            fee_msat,
+            cltv_expiry_delta: 40,
+            maybe_announced_channel: true,
+        }
+    }
+
+    #[test]
+    fn test_explain_route() {
+        let test_name = "test_explain_route";
+        log!("{}", test_name);
+
+        let our_scid: u64 = (800_000 << 40) | (12 << 16) | 1;
+        let route = Route {
+            paths: vec![Path {
+                hops: vec![hop(1, our_scid, 1_000), hop(2, 42, 100_000)],
+                blinded_tail: None,
+            }],
+            route_params: None,
+        };
+        let hub = route.paths[0].hops[0].pubkey;
+
+        let explanation = RouteExplanation::from_route(
+            &route,
+            &[our_scid],
+            |scid| (scid == 42).then_some(5_000_000),
+            |pk| (*pk == hub).then(|| "hub".to_string()),
+        );
+        assert_eq!(explanation.amount_msat, 100_000);
+        assert_eq!(explanation.fee_msat, 1_000);
+        assert_eq!(explanation.failure, None);
+
+        let hops = &explanation.paths[0].hops;
+        assert!(hops[0].our_channel);
+        assert_eq!(hops[0].alias.as_deref(), Some("hub"));
+        assert_eq!(hops[0].capacity_sats, None);
+        assert_eq!(hops[0].fee_msat, 1_000);
+        assert!(!hops[1].our_channel);
+        assert_eq!(hops[1].capacity_sats, Some(5_000_000));
+        assert_eq!(hops[1].fee_msat, 0);
+
+        assert!(explanation.description.contains("800000x12x1 (our channel"));
+        assert!(explanation.description.contains("charges 1000 msat"));
+
+        let failed = RouteExplanation::no_route(100_000, "no path".to_string(), 50_000);
+        assert!(failed.paths.is_empty());
+        assert_eq!(failed.failure.as_deref(), Some("no path"));
+        assert!(failed.description.contains("at most 50 sats"));
+    }
+}
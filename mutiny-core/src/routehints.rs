@@ -0,0 +1,116 @@
+//! Choosing the route hints that go into our invoices.
+//!
+//! A payer can only reach us over a private channel if the invoice tells it
+//! about the channel. LDK picks those channels on its own, which includes
+//! channels with peers that are often offline or slow to forward. The caller
+//! can instead name the channels to hint, or give the hints themselves.
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning::ln::channel_state::ChannelDetails;
+use lightning::routing::gossip::RoutingFees;
+use lightning::routing::router::{RouteHint, RouteHintHop};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RouteHintSelection {
+    /// Leave it to LDK
+    #[default]
+    Auto,
+    /// Only hint the channels with these funding outpoints, none at all if empty
+    Channels(Vec<OutPoint>),
+    /// Use these hints as they are, each a route ending at our node
+    Explicit(Vec<Vec<InvoiceRouteHintHop>>),
+}
+
+/// A hop of a route hint, the channel from `src_node_id` towards us
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InvoiceRouteHintHop {
+    pub src_node_id: PublicKey,
+    pub short_channel_id: u64,
+    pub fee_base_msat: u32,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u16,
+    pub htlc_minimum_msat: Option<u64>,
+    pub htlc_maximum_msat: Option<u64>,
+}
+
+impl From<InvoiceRouteHintHop> for RouteHintHop {
+    fn from(hop: InvoiceRouteHintHop) -> Self {
+        RouteHintHop {
+            src_node_id: hop.src_node_id,
+            short_channel_id: hop.short_channel_id,
+            fees: RoutingFees {
+                base_msat: hop.fee_base_msat,
+                proportional_millionths: hop.fee_proportional_millionths,
+            },
+            cltv_expiry_delta: hop.cltv_expiry_delta,
+            htlc_minimum_msat: hop.htlc_minimum_msat,
+            htlc_maximum_msat: hop.htlc_maximum_msat,
+        }
+    }
+}
+
+pub(crate) fn to_route_hint(hops: &[InvoiceRouteHintHop]) -> RouteHint {
+    RouteHint(hops.iter().copied().map(RouteHintHop::from).collect())
+}
+
+/// The hint for a channel, the same LDK would make for it. None until the
+/// peer told us its forwarding fees, a payer couldn't use the channel before.
+pub(crate) fn channel_route_hint(channel: &ChannelDetails) -> Option<RouteHint> {
+    let forwarding_info = channel.counterparty.forwarding_info.as_ref()?;
+    Some(RouteHint(vec![RouteHintHop {
+        src_node_id: channel.counterparty.node_id,
+        short_channel_id: channel.get_inbound_payment_scid()?,
+        fees: RoutingFees {
+            base_msat: forwarding_info.fee_base_msat,
+            proportional_millionths: forwarding_info.fee_proportional_millionths,
+        },
+        cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+        htlc_minimum_msat: channel.inbound_htlc_minimum_msat,
+        htlc_maximum_msat: channel.inbound_htlc_maximum_msat,
+    }]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_explicit_route_hint() {
+        let test_name = "test_explicit_route_hint";
+        log!("{}", test_name);
+
+        let hop = InvoiceRouteHintHop {
+            src_node_id: PublicKey::from_str(
+                "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+            )
+            .unwrap(),
+            short_channel_id: 872_000_000_000_001,
+            fee_base_msat: 1_000,
+            fee_proportional_millionths: 100,
+            cltv_expiry_delta: 144,
+            htlc_minimum_msat: None,
+            htlc_maximum_msat: Some(50_000_000),
+        };
+        let hint = to_route_hint(&[hop]);
+        assert_eq!(hint.0.len(), 1);
+        assert_eq!(hint.0[0].src_node_id, hop.src_node_id);
+        assert_eq!(hint.0[0].fees.base_msat, 1_000);
+        assert_eq!(hint.0[0].fees.proportional_millionths, 100);
+        assert_eq!(hint.0[0].htlc_maximum_msat, Some(50_000_000));
+
+        let selection = RouteHintSelection::Explicit(vec![vec![hop]]);
+        let json = serde_json::to_string(&selection).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RouteHintSelection>(&json).unwrap(),
+            selection
+        );
+        assert_eq!(RouteHintSelection::default(), RouteHintSelection::Auto);
+    }
+}
@@ -0,0 +1,123 @@
+//! The identity we gossip when running as a public routing node.
+//!
+//! Our channels are private by default, nobody but our peers knows about them.
+//! A wallet running natively can instead open announced channels and tell the
+//! network who it is, an alias and color shown by explorers and the addresses
+//! other nodes can connect to, so it can be found and used as a routing peer.
+//! The node announcement is only taken by the network once one of our announced
+//! channels is, so it is sent again regularly.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use hex_conservative::FromHex;
+use lightning::ln::msgs::SocketAddress;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const ROUTING_NODE_KEY: &str = "routing_node";
+/// How often the node announcement is sent again
+pub(crate) const NODE_ANNOUNCEMENT_INTERVAL_SECS: u64 = 60 * 60;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoutingNodeIdentity {
+    /// At most 32 bytes of UTF-8
+    pub alias: String,
+    /// A hex color like "#f7931a"
+    pub color: String,
+    /// Where other nodes can connect to us, like "203.0.113.5:9735"
+    pub addresses: Vec<String>,
+}
+
+impl RoutingNodeIdentity {
+    pub(crate) fn alias_bytes(&self) -> Result<[u8; 32], MutinyError> {
+        let alias = self.alias.as_bytes();
+        if alias.len() > 32 {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let mut bytes = [0u8; 32];
+        bytes[..alias.len()].copy_from_slice(alias);
+        Ok(bytes)
+    }
+
+    pub(crate) fn rgb(&self) -> Result<[u8; 3], MutinyError> {
+        let color = self.color.strip_prefix('#').unwrap_or(&self.color);
+        <[u8; 3]>::from_hex(color).map_err(|_| MutinyError::InvalidArgumentsError)
+    }
+
+    pub(crate) fn socket_addresses(&self) -> Result<Vec<SocketAddress>, MutinyError> {
+        self.addresses
+            .iter()
+            .map(|a| SocketAddress::from_str(a).map_err(|_| MutinyError::InvalidArgumentsError))
+            .collect()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), MutinyError> {
+        self.alias_bytes()?;
+        self.rgb()?;
+        self.socket_addresses()?;
+        Ok(())
+    }
+}
+
+pub(crate) fn get_routing_node_identity<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<RoutingNodeIdentity>, MutinyError> {
+    storage.get_data(ROUTING_NODE_KEY)
+}
+
+/// Saves the identity to gossip, None stops announcing the node
+pub(crate) fn set_routing_node_identity<S: MutinyStorage>(
+    storage: &S,
+    identity: Option<RoutingNodeIdentity>,
+) -> Result<(), MutinyError> {
+    match identity {
+        Some(identity) => {
+            identity.validate()?;
+            storage.write_data(ROUTING_NODE_KEY.to_string(), identity, None)
+        }
+        None => storage.delete(&[ROUTING_NODE_KEY]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_routing_node_identity() {
+        let test_name = "test_routing_node_identity";
+        log!("{}", test_name);
+
+        let identity = RoutingNodeIdentity {
+            alias: "mutiny routing".to_string(),
+            color: "#f7931a".to_string(),
+            addresses: vec!["203.0.113.5:9735".to_string()],
+        };
+        assert_eq!(identity.rgb().unwrap(), [0xf7, 0x93, 0x1a]);
+        assert_eq!(&identity.alias_bytes().unwrap()[..14], b"mutiny routing");
+        assert_eq!(identity.socket_addresses().unwrap().len(), 1);
+
+        let too_long = RoutingNodeIdentity {
+            alias: "a".repeat(33),
+            ..identity.clone()
+        };
+        assert!(too_long.validate().is_err());
+        let bad_color = RoutingNodeIdentity {
+            color: "orange".to_string(),
+            ..identity.clone()
+        };
+        assert!(bad_color.validate().is_err());
+
+        let storage = MemoryStorage::default();
+        assert!(set_routing_node_identity(&storage, Some(bad_color)).is_err());
+        set_routing_node_identity(&storage, Some(identity.clone())).unwrap();
+        assert_eq!(get_routing_node_identity(&storage).unwrap(), Some(identity));
+        set_routing_node_identity(&storage, None).unwrap();
+        assert_eq!(get_routing_node_identity(&storage).unwrap(), None);
+    }
+}
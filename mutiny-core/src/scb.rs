@@ -0,0 +1,435 @@
+//! Static channel backups (SCB), a last resort for when the wallet's state is
+//! gone and VSS can't give it back.
+//!
+//! The backup only lists which channels we had: the peer, the funding outpoint
+//! and what we need to find our output once the channel is closed. It holds no
+//! channel state, so it can't go stale and recovering from it never broadcasts
+//! a commitment of ours that could be revoked.
+//!
+//! To recover we connect to each peer without having the channel. The peer
+//! sends a `channel_reestablish` for it and LDK answers with a data loss
+//! protect `channel_reestablish`, which asks the peer to force close with its
+//! latest commitment. Once that confirms our output of it is swept to the wallet.
+
+use crate::encrypt::{decrypt_with_key_and_aad, encrypt_with_key_and_aad};
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::bip32::Xpriv;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{Network, ScriptBuf, Transaction, TxOut, WPubkeyHash};
+use hex_conservative::{DisplayHex, FromHex};
+use lightning::ln::chan_utils::get_to_countersignatory_with_anchors_redeemscript;
+use lightning::ln::channel_state::ChannelDetails;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Binds the ciphertext to what it is, so no other encrypted blob can pass as a backup
+const SCB_AAD: &[u8] = b"mutiny-static-channel-backup";
+const SCB_VERSION: u32 = 1;
+
+pub(crate) const CHANNEL_KEYS_ID_PREFIX: &str = "channel_keys_id/";
+pub(crate) const SCB_RECOVERY_PREFIX: &str = "scb_recovery/";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelBackup {
+    pub channel_id: String,
+    pub counterparty_node_id: PublicKey,
+    /// Where to reach the peer, if we knew
+    pub peer_connection_string: Option<String>,
+    /// The channel's funding outpoint as `txid_index`
+    pub funding_txo: String,
+    pub channel_value_sats: u64,
+    /// What our keys for the channel are derived from, hex encoded. Channels
+    /// opened before these were recorded don't have it, the peer still closes
+    /// them but our output has to be swept by hand.
+    pub channel_keys_id: Option<String>,
+    /// Whether the channel has anchor outputs, which changes our output's script
+    pub anchors: bool,
+}
+
+impl ChannelBackup {
+    /// None for channels that don't have a funding outpoint yet, there is
+    /// nothing to recover from those
+    pub(crate) fn from_details<S: MutinyStorage>(
+        storage: &S,
+        channel: &ChannelDetails,
+        peer_connection_string: Option<String>,
+    ) -> Option<Self> {
+        let funding_txo = channel.funding_txo?;
+        Some(Self {
+            channel_id: channel.channel_id.0.to_lower_hex_string(),
+            counterparty_node_id: channel.counterparty.node_id,
+            peer_connection_string,
+            funding_txo: format!("{}_{}", funding_txo.txid, funding_txo.index),
+            channel_value_sats: channel.channel_value_satoshis,
+            channel_keys_id: get_channel_keys_id(storage, channel.user_channel_id)
+                .map(|id| id.to_lower_hex_string()),
+            anchors: channel
+                .channel_type
+                .as_ref()
+                .is_some_and(|t| t.supports_anchors_zero_fee_htlc_tx()),
+        })
+    }
+
+    pub(crate) fn keys_id(&self) -> Option<[u8; 32]> {
+        self.channel_keys_id
+            .as_ref()
+            .and_then(|id| <[u8; 32]>::from_hex(id).ok())
+    }
+
+    /// The script of our output of the peer's commitment, paid to our payment point
+    pub(crate) fn to_remote_script(&self, payment_point: &PublicKey) -> ScriptBuf {
+        if self.anchors {
+            get_to_countersignatory_with_anchors_redeemscript(payment_point).to_p2wsh()
+        } else {
+            ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()))
+        }
+    }
+}
+
+/// The channels of one node. Nodes are matched by their child index, node
+/// ids are random and differ between installs of the same seed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeChannelBackup {
+    pub child_index: u32,
+    pub channels: Vec<ChannelBackup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StaticChannelBackup {
+    pub version: u32,
+    pub network: Network,
+    /// Epoch time in seconds
+    pub created_at: u64,
+    pub nodes: Vec<NodeChannelBackup>,
+}
+
+impl StaticChannelBackup {
+    pub fn channel_count(&self) -> usize {
+        self.nodes.iter().map(|n| n.channels.len()).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ScbRecoveryStatus {
+    /// Waiting for the peer to force close and the close to confirm
+    Closing,
+    /// Our output of the peer's commitment was swept to the wallet
+    Swept { txid: String },
+    /// The close had no output for us, e.g. our balance was below dust
+    NothingToSweep { closing_txid: String },
+    /// We don't have the keys id, the output has to be swept by hand
+    CannotSweep { closing_txid: String },
+}
+
+/// A channel from a backup that is being recovered
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScbRecovery {
+    /// The local node the channel was restored to
+    pub node_id: PublicKey,
+    pub channel: ChannelBackup,
+    #[serde(flatten)]
+    pub status: ScbRecoveryStatus,
+    pub updated_at: u64,
+}
+
+/// The key backups are encrypted to, only used for them
+pub(crate) fn channel_backup_key(xprivkey: Xpriv) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    Ok(create_root_child_key(&context, xprivkey, ChildKey::ChannelBackup)?.private_key)
+}
+
+/// Remembers what a channel's keys are derived from, so a backup can sweep
+/// our output of the peer's commitment without any channel state
+pub(crate) fn record_channel_keys_id<S: MutinyStorage>(
+    storage: &S,
+    user_channel_id: u128,
+    keys_id: &[u8; 32],
+) -> Result<(), MutinyError> {
+    storage.write_data(
+        format!("{CHANNEL_KEYS_ID_PREFIX}{user_channel_id}"),
+        keys_id.to_lower_hex_string(),
+        None,
+    )
+}
+
+pub(crate) fn get_channel_keys_id<S: MutinyStorage>(
+    storage: &S,
+    user_channel_id: u128,
+) -> Option<[u8; 32]> {
+    storage
+        .get_data::<String>(format!("{CHANNEL_KEYS_ID_PREFIX}{user_channel_id}"))
+        .ok()
+        .flatten()
+        .and_then(|id| <[u8; 32]>::from_hex(&id).ok())
+}
+
+pub(crate) fn create_scb(
+    network: Network,
+    mut nodes: Vec<NodeChannelBackup>,
+) -> StaticChannelBackup {
+    nodes.retain(|n| !n.channels.is_empty());
+    nodes.sort_by_key(|n| n.child_index);
+    for node in nodes.iter_mut() {
+        node.channels
+            .sort_by(|a, b| a.funding_txo.cmp(&b.funding_txo));
+    }
+
+    StaticChannelBackup {
+        version: SCB_VERSION,
+        network,
+        created_at: utils::now().as_secs(),
+        nodes,
+    }
+}
+
+pub(crate) fn encrypt_scb(
+    key: &SecretKey,
+    backup: &StaticChannelBackup,
+) -> Result<String, MutinyError> {
+    let bytes = serde_json::to_vec(backup)?;
+    Ok(base64::encode(encrypt_with_key_and_aad(
+        key, &bytes, SCB_AAD,
+    )?))
+}
+
+pub(crate) fn decrypt_scb(key: &SecretKey, scb: &str) -> Result<StaticChannelBackup, MutinyError> {
+    let bytes = base64::decode(scb.trim()).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let bytes = decrypt_with_key_and_aad(key, &bytes, SCB_AAD)?;
+    let version = serde_json::from_slice::<serde_json::Value>(&bytes)?
+        .get("version")
+        .and_then(|v| v.as_u64());
+    if version != Some(SCB_VERSION as u64) {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn scb_recovery_key(funding_txo: &str) -> String {
+    format!("{SCB_RECOVERY_PREFIX}{funding_txo}")
+}
+
+pub(crate) fn persist_scb_recovery<S: MutinyStorage>(
+    storage: &S,
+    recovery: &ScbRecovery,
+) -> Result<(), MutinyError> {
+    storage.write_data(
+        scb_recovery_key(&recovery.channel.funding_txo),
+        recovery,
+        None,
+    )
+}
+
+pub(crate) fn list_scb_recoveries<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<ScbRecovery>, MutinyError> {
+    let mut recoveries: Vec<ScbRecovery> = storage
+        .scan::<ScbRecovery>(SCB_RECOVERY_PREFIX, None)?
+        .into_values()
+        .collect();
+    recoveries.sort_by(|a, b| a.channel.funding_txo.cmp(&b.channel.funding_txo));
+    Ok(recoveries)
+}
+
+/// Starts recovering the channels of the backup on the local nodes, a map of
+/// child index to node id. Channels the nodes still have and ones already
+/// being recovered are skipped. Returns how many channels are now recovering.
+pub(crate) fn restore_scb<S: MutinyStorage>(
+    storage: &S,
+    network: Network,
+    backup: &StaticChannelBackup,
+    nodes: &HashMap<u32, PublicKey>,
+    open_funding_txos: &HashSet<String>,
+) -> Result<usize, MutinyError> {
+    if backup.network != network {
+        return Err(MutinyError::IncorrectNetwork);
+    }
+
+    let mut restored = 0;
+    for node in backup.nodes.iter() {
+        let node_id = nodes.get(&node.child_index).ok_or(MutinyError::NotFound)?;
+        for channel in node.channels.iter() {
+            if open_funding_txos.contains(&channel.funding_txo)
+                || storage
+                    .get_data::<ScbRecovery>(scb_recovery_key(&channel.funding_txo))?
+                    .is_some()
+            {
+                continue;
+            }
+            persist_scb_recovery(
+                storage,
+                &ScbRecovery {
+                    node_id: *node_id,
+                    channel: channel.clone(),
+                    status: ScbRecoveryStatus::Closing,
+                    updated_at: utils::now().as_secs(),
+                },
+            )?;
+            restored += 1;
+        }
+    }
+
+    Ok(restored)
+}
+
+/// Our output of the transaction that closed the channel, if it has one
+pub(crate) fn find_to_remote_output(
+    closing_tx: &Transaction,
+    channel: &ChannelBackup,
+    payment_point: &PublicKey,
+) -> Option<(u32, TxOut)> {
+    let script = channel.to_remote_script(payment_point);
+    closing_tx
+        .output
+        .iter()
+        .enumerate()
+        .find(|(_, o)| o.script_pubkey == script)
+        .map(|(i, o)| (i as u32, o.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::Amount;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const TXID: &str = "e5acb1f07ba3c5fa5a2fb8e4ef8fb3d1ea32a3b7a1f6e7d4c2b3a1f0e9d8c7b6";
+
+    fn pubkey(seed: u8) -> PublicKey {
+        SecretKey::from_slice(&[seed; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new())
+    }
+
+    fn channel(index: u32, anchors: bool) -> ChannelBackup {
+        ChannelBackup {
+            channel_id: [index as u8; 32].to_lower_hex_string(),
+            counterparty_node_id: pubkey(9),
+            peer_connection_string: Some(format!("{}@127.0.0.1:9735", pubkey(9))),
+            funding_txo: format!("{TXID}_{index}"),
+            channel_value_sats: 100_000,
+            channel_keys_id: Some([7u8; 32].to_lower_hex_string()),
+            anchors,
+        }
+    }
+
+    #[test]
+    fn test_scb_round_trip() {
+        let test_name = "test_scb_round_trip";
+        log!("{}", test_name);
+
+        let backup = create_scb(
+            Network::Regtest,
+            vec![
+                NodeChannelBackup {
+                    child_index: 0,
+                    channels: vec![channel(1, true), channel(0, false)],
+                },
+                NodeChannelBackup {
+                    child_index: 1,
+                    channels: vec![],
+                },
+            ],
+        );
+        assert_eq!(backup.channel_count(), 2);
+        assert_eq!(backup.nodes.len(), 1);
+        assert_eq!(backup.nodes[0].channels[0].funding_txo, format!("{TXID}_0"));
+        assert_eq!(backup.nodes[0].channels[0].keys_id(), Some([7u8; 32]));
+
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let scb = encrypt_scb(&key, &backup).unwrap();
+        let wrong_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        assert!(decrypt_scb(&wrong_key, &scb).is_err());
+        let decrypted = decrypt_scb(&key, &scb).unwrap();
+        assert_eq!(decrypted, backup);
+
+        // a fresh install of the same seed has a new node id
+        let storage = MemoryStorage::default();
+        let node = pubkey(3);
+        let nodes = HashMap::from([(0, node)]);
+        assert_eq!(
+            restore_scb(
+                &storage,
+                Network::Bitcoin,
+                &decrypted,
+                &nodes,
+                &HashSet::new()
+            ),
+            Err(MutinyError::IncorrectNetwork)
+        );
+
+        // a channel we still have is left alone
+        let open = HashSet::from([format!("{TXID}_1")]);
+        assert_eq!(
+            restore_scb(&storage, Network::Regtest, &decrypted, &nodes, &open).unwrap(),
+            1
+        );
+        // and restoring again doesn't start over
+        assert_eq!(
+            restore_scb(&storage, Network::Regtest, &decrypted, &nodes, &open).unwrap(),
+            0
+        );
+        let recoveries = list_scb_recoveries(&storage).unwrap();
+        assert_eq!(recoveries.len(), 1);
+        assert_eq!(recoveries[0].node_id, node);
+        assert_eq!(recoveries[0].channel, channel(0, false));
+        assert_eq!(recoveries[0].status, ScbRecoveryStatus::Closing);
+    }
+
+    #[test]
+    fn test_scb_rejects_unknown_versions() {
+        let test_name = "test_scb_rejects_unknown_versions";
+        log!("{}", test_name);
+
+        let key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let newer = serde_json::json!({
+            "version": SCB_VERSION + 1,
+            "network": "regtest",
+            "created_at": 0,
+            "nodes": [],
+        });
+        let bytes = serde_json::to_vec(&newer).unwrap();
+        let scb = base64::encode(encrypt_with_key_and_aad(&key, &bytes, SCB_AAD).unwrap());
+        assert!(decrypt_scb(&key, &scb).is_err());
+    }
+
+    #[test]
+    fn test_find_to_remote_output() {
+        let test_name = "test_find_to_remote_output";
+        log!("{}", test_name);
+
+        let payment_point = pubkey(5);
+        for anchors in [false, true] {
+            let channel = channel(0, anchors);
+            let ours = TxOut {
+                value: Amount::from_sat(40_000),
+                script_pubkey: channel.to_remote_script(&payment_point),
+            };
+            let theirs = TxOut {
+                value: Amount::from_sat(59_000),
+                script_pubkey: channel.to_remote_script(&pubkey(6)),
+            };
+            let tx = Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![],
+                output: vec![theirs, ours.clone()],
+            };
+            assert_eq!(
+                find_to_remote_output(&tx, &channel, &payment_point),
+                Some((1, ours))
+            );
+            assert_eq!(find_to_remote_output(&tx, &channel, &pubkey(7)), None);
+        }
+    }
+}
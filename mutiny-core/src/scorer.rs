@@ -293,6 +293,15 @@ impl HubPreferentialScorer {
         }
     }
 
+    /// The scorer's estimate of a channel's liquidity towards `target`, in msat
+    pub(crate) fn estimated_channel_liquidity_range(
+        &self,
+        scid: u64,
+        target: &NodeId,
+    ) -> Option<(u64, u64)> {
+        self.inner.estimated_channel_liquidity_range(scid, target)
+    }
+
     fn is_source_preferred_hub(&self, candidate: &CandidateRouteHop) -> bool {
         match candidate {
             CandidateRouteHop::FirstHop(_) => false, // source of first hop is us
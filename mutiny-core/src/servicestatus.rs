@@ -0,0 +1,229 @@
+//! Whether the remote services the wallet relies on are reachable.
+//!
+//! When VSS or the auth server is down the wallet keeps running on its local
+//! state instead of failing. Writes VSS couldn't take are kept locally and
+//! sent again once it answers, see [`MutinyStorage::reconcile_vss`].
+
+use crate::authclient::MutinyAuthClient;
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use crate::vss::VssKeyValueItem;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+pub(crate) const PENDING_VSS_PREFIX: &str = "pending_vss/";
+
+pub(crate) fn pending_vss_key(key: &str) -> String {
+    format!("{PENDING_VSS_PREFIX}{key}")
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    /// The wallet isn't configured to use the service
+    Disabled,
+    Online,
+    /// The last request to the service failed
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub vss: ServiceState,
+    pub auth: ServiceState,
+    /// Whether the wallet is running without one of its remote services
+    pub degraded: bool,
+    /// When the first of the services became unreachable, in epoch seconds
+    pub degraded_since: Option<u64>,
+    /// Writes waiting for VSS to be reachable again
+    pub pending_remote_writes: usize,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    unreachable_since: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// Tracks whether the last request to a remote service succeeded
+#[derive(Debug, Default)]
+pub struct ServiceHealth {
+    state: Mutex<HealthState>,
+}
+
+impl ServiceHealth {
+    /// Records a successful request, returns true if the service was unreachable before
+    pub fn record_success(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.last_error = None;
+        state.unreachable_since.take().is_some()
+    }
+
+    /// Records a failed request, returns true if the service was reachable before
+    pub fn record_failure(&self, error: &MutinyError) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.last_error = Some(error.to_string());
+        if state.unreachable_since.is_some() {
+            return false;
+        }
+        state.unreachable_since = Some(utils::now().as_secs());
+        true
+    }
+
+    /// When the service became unreachable, none if it is reachable
+    pub fn unreachable_since(&self) -> Option<u64> {
+        self.state.lock().unwrap().unreachable_since
+    }
+
+    pub fn state(&self) -> ServiceState {
+        match self.unreachable_since() {
+            Some(_) => ServiceState::Unreachable,
+            None => ServiceState::Online,
+        }
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+}
+
+/// The status of VSS and the auth server, as of their last requests
+pub(crate) fn service_status<S: MutinyStorage>(
+    storage: &S,
+    auth: Option<&MutinyAuthClient>,
+) -> Result<ServiceStatus, MutinyError> {
+    let vss = storage.vss_client();
+    let healths: Vec<&ServiceHealth> = vss
+        .as_deref()
+        .map(|v| &v.health)
+        .into_iter()
+        .chain(auth.map(|a| &a.health))
+        .collect();
+
+    let degraded_since = healths.iter().filter_map(|h| h.unreachable_since()).min();
+    let last_error = healths.iter().find_map(|h| h.last_error());
+
+    Ok(ServiceStatus {
+        vss: vss.map_or(ServiceState::Disabled, |v| v.health.state()),
+        auth: auth.map_or(ServiceState::Disabled, |a| a.health.state()),
+        degraded: degraded_since.is_some(),
+        degraded_since,
+        pending_remote_writes: storage.scan_keys(PENDING_VSS_PREFIX, None)?.len(),
+        last_error,
+    })
+}
+
+/// Keeps the VSS writes that failed so they can be sent again later. Only the
+/// newest version of each key is kept.
+pub(crate) fn queue_vss_items<S: MutinyStorage>(
+    storage: &S,
+    items: Vec<VssKeyValueItem>,
+) -> Result<(), MutinyError> {
+    for item in items {
+        let key = pending_vss_key(&item.key);
+        let queued: Option<VssKeyValueItem> = storage.get_data(&key)?;
+        if queued.is_some_and(|q| q.version > item.version) {
+            continue;
+        }
+        storage.write_data(key, item, None)?;
+    }
+    Ok(())
+}
+
+/// The VSS writes waiting to be sent again
+pub(crate) fn list_pending_vss_items<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<VssKeyValueItem>, MutinyError> {
+    Ok(storage
+        .scan::<VssKeyValueItem>(PENDING_VSS_PREFIX, None)?
+        .into_values()
+        .collect())
+}
+
+/// Removes the items from the queue, unless a newer version was queued meanwhile
+pub(crate) fn remove_pending_vss_items<S: MutinyStorage>(
+    storage: &S,
+    items: &[VssKeyValueItem],
+) -> Result<(), MutinyError> {
+    let mut done = vec![];
+    for item in items {
+        let key = pending_vss_key(&item.key);
+        let queued: Option<VssKeyValueItem> = storage.get_data(&key)?;
+        if queued.is_some_and(|q| q.version <= item.version) {
+            done.push(key);
+        }
+    }
+    if done.is_empty() {
+        return Ok(());
+    }
+    storage.delete(&done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn item(key: &str, version: u32) -> VssKeyValueItem {
+        VssKeyValueItem {
+            key: key.to_string(),
+            value: json!({ "version": version }),
+            version,
+        }
+    }
+
+    #[test]
+    fn test_service_health() {
+        let test_name = "test_service_health";
+        log!("{}", test_name);
+
+        let health = ServiceHealth::default();
+        assert_eq!(health.state(), ServiceState::Online);
+        assert!(!health.record_success());
+
+        assert!(health.record_failure(&MutinyError::ConnectionFailed));
+        let since = health.unreachable_since();
+        assert!(since.is_some());
+        assert_eq!(health.state(), ServiceState::Unreachable);
+        assert!(health.last_error().is_some());
+
+        // staying down keeps when it went down
+        assert!(!health.record_failure(&MutinyError::ConnectionFailed));
+        assert_eq!(health.unreachable_since(), since);
+
+        assert!(health.record_success());
+        assert_eq!(health.state(), ServiceState::Online);
+        assert_eq!(health.last_error(), None);
+    }
+
+    #[test]
+    fn test_pending_vss_queue() {
+        let test_name = "test_pending_vss_queue";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert!(list_pending_vss_items(&storage).unwrap().is_empty());
+
+        queue_vss_items(&storage, vec![item("a", 2), item("b", 1)]).unwrap();
+        // an older write that failed later doesn't replace the newer one
+        queue_vss_items(&storage, vec![item("a", 1)]).unwrap();
+        let mut pending = list_pending_vss_items(&storage).unwrap();
+        pending.sort_by(|x, y| x.key.cmp(&y.key));
+        assert_eq!(pending, vec![item("a", 2), item("b", 1)]);
+
+        // a newer write queued while sending stays queued
+        queue_vss_items(&storage, vec![item("b", 3)]).unwrap();
+        remove_pending_vss_items(&storage, &pending).unwrap();
+        assert_eq!(
+            list_pending_vss_items(&storage).unwrap(),
+            vec![item("b", 3)]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::denominations::DenominationPreferences;
+use crate::encrypt::{decrypt_with_key_and_aad, encrypt_with_key_and_aad};
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::nostr::{fetch_from_relay, sign_event, verify_event, DEFAULT_RELAYS};
+use crate::outbox::{list_outbox_events, persist_outbox_event, remove_outbox_event, OutboxEvent};
+use crate::storage::MutinyStorage;
+use bitcoin::bip32::Xpriv;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub(crate) const WALLET_SETTINGS_KEY: &str = "wallet_settings";
+/// NIP-78 arbitrary custom app data, a parameterized replaceable event
+pub(crate) const APP_DATA_KIND: u64 = 30078;
+/// The `d` tag our settings event is replaced by
+const SETTINGS_D_TAG: &str = "mutiny-wallet-settings";
+/// Relays used when the user hasn't picked any
+pub const DEFAULT_SETTINGS_RELAYS: [&str; 2] = DEFAULT_RELAYS;
+
+/// Which notifications the user wants to see
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotificationSettings {
+    pub payment_received: bool,
+    pub payment_sent: bool,
+    pub channel_events: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            payment_received: true,
+            payment_sent: true,
+            channel_events: true,
+        }
+    }
+}
+
+/// Preferences that follow the user across devices. Nothing in here may be
+/// sensitive, it is published to nostr relays encrypted to the wallet key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WalletSettings {
+    #[serde(default)]
+    pub denomination: DenominationPreferences,
+    /// Nostr relays the settings are synced through
+    #[serde(default)]
+    pub relays: Vec<String>,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    /// Label to a display color, e.g. "#ff0000"
+    #[serde(default)]
+    pub label_colors: HashMap<String, String>,
+    /// When the settings were last changed on any device
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl WalletSettings {
+    /// The relays to sync through, the defaults if the user hasn't set any
+    pub fn sync_relays(&self) -> Vec<String> {
+        if self.relays.is_empty() {
+            DEFAULT_SETTINGS_RELAYS.map(String::from).to_vec()
+        } else {
+            self.relays.clone()
+        }
+    }
+}
+
+pub(crate) fn get_wallet_settings<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<WalletSettings>, MutinyError> {
+    storage.get_data(WALLET_SETTINGS_KEY)
+}
+
+pub(crate) fn persist_wallet_settings<S: MutinyStorage>(
+    storage: &S,
+    settings: &WalletSettings,
+) -> Result<(), MutinyError> {
+    storage.write_data(WALLET_SETTINGS_KEY.to_string(), settings, None)
+}
+
+/// The key settings events are signed by and encrypted to, it is only
+/// used for this so the wallet's other keys are never linked to it
+pub(crate) fn settings_key(xprivkey: Xpriv) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    Ok(create_root_child_key(&context, xprivkey, ChildKey::SettingsSync)?.private_key)
+}
+
+/// Creates a signed NIP-78 event holding the settings encrypted to our own key
+pub(crate) fn create_settings_event(
+    key: &SecretKey,
+    settings: &WalletSettings,
+) -> Result<Value, MutinyError> {
+    let plaintext = serde_json::to_vec(settings)?;
+    let content = base64::encode(encrypt_with_key_and_aad(
+        key,
+        &plaintext,
+        SETTINGS_D_TAG.as_bytes(),
+    )?);
+    let tags = json!([["d", SETTINGS_D_TAG]]);
+
+    Ok(sign_event(key, APP_DATA_KIND, tags, content))
+}
+
+/// Checks the event is our settings event with a valid id and signature,
+/// then decrypts the settings out of it
+pub(crate) fn read_settings_event(
+    key: &SecretKey,
+    event: &Value,
+) -> Result<WalletSettings, MutinyError> {
+    let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+    if verify_event(event)? != pubkey {
+        return Err(MutinyError::NostrError);
+    }
+
+    let kind = event["kind"].as_u64();
+    let has_d_tag = event["tags"]
+        .as_array()
+        .is_some_and(|tags| tags.iter().any(|t| t == &json!(["d", SETTINGS_D_TAG])));
+    if kind != Some(APP_DATA_KIND) || !has_d_tag {
+        return Err(MutinyError::NostrError);
+    }
+
+    let content = event["content"].as_str().ok_or(MutinyError::NostrError)?;
+    let encrypted = base64::decode(content).map_err(|_| MutinyError::NostrError)?;
+    let plaintext = decrypt_with_key_and_aad(key, &encrypted, SETTINGS_D_TAG.as_bytes())?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Puts the settings event in the nostr outbox, replacing any older settings
+/// event that hasn't been published yet
+pub(crate) fn queue_settings_event<S: MutinyStorage>(
+    storage: &S,
+    key: &SecretKey,
+    settings: &WalletSettings,
+) -> Result<(), MutinyError> {
+    for queued in list_outbox_events(storage)? {
+        if queued.kind == APP_DATA_KIND && read_settings_event(key, &queued.event).is_ok() {
+            remove_outbox_event(storage, &queued.id)?;
+        }
+    }
+
+    let event = create_settings_event(key, settings)?;
+    let event = OutboxEvent::new(event, settings.sync_relays())?;
+    persist_outbox_event(storage, &event)
+}
+
+/// The newest settings any of the relays has for us. Events that aren't ours
+/// or fail to decrypt are ignored.
+pub(crate) async fn fetch_remote_settings(
+    key: &SecretKey,
+    relays: &[String],
+) -> Result<Option<WalletSettings>, MutinyError> {
+    let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+    let filter = json!({
+        "kinds": [APP_DATA_KIND],
+        "authors": [pubkey.to_string()],
+        "#d": [SETTINGS_D_TAG],
+        "limit": 1,
+    });
+
+    let mut newest: Option<WalletSettings> = None;
+    let mut reached_relay = false;
+    for relay in relays {
+        let Ok(events) = fetch_from_relay(relay, &filter, key).await else {
+            continue;
+        };
+        reached_relay = true;
+        for event in events {
+            if let Ok(settings) = read_settings_event(key, &event) {
+                if newest
+                    .as_ref()
+                    .map_or(true, |n| settings.updated_at > n.updated_at)
+                {
+                    newest = Some(settings);
+                }
+            }
+        }
+    }
+
+    if !reached_relay {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    Ok(newest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::Network;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn test_key(seed: u8) -> SecretKey {
+        let xpriv = Xpriv::new_master(Network::Regtest, &[seed; 32]).unwrap();
+        settings_key(xpriv).unwrap()
+    }
+
+    #[test]
+    fn test_settings_event_round_trip() {
+        let test_name = "test_settings_event_round_trip";
+        log!("{}", test_name);
+
+        let key = test_key(1);
+        let mut settings = WalletSettings {
+            relays: vec!["wss://relay.example.com".to_string()],
+            updated_at: 1_700_000_000,
+            ..Default::default()
+        };
+        settings.notifications.payment_sent = false;
+        settings
+            .label_colors
+            .insert("Rent".to_string(), "#ff0000".to_string());
+
+        let event = create_settings_event(&key, &settings).unwrap();
+        assert_eq!(event["kind"], json!(APP_DATA_KIND));
+        // the settings aren't readable by relays
+        assert!(!event["content"].as_str().unwrap().contains("Rent"));
+        assert_eq!(read_settings_event(&key, &event).unwrap(), settings);
+
+        // someone else can't read or forge them
+        assert!(read_settings_event(&test_key(2), &event).is_err());
+        let mut tampered = event.clone();
+        tampered["created_at"] = json!(1);
+        assert!(read_settings_event(&key, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_queue_settings_event() {
+        let test_name = "test_queue_settings_event";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let key = test_key(1);
+        let settings = WalletSettings::default();
+
+        queue_settings_event(&storage, &key, &settings).unwrap();
+        queue_settings_event(&storage, &key, &settings).unwrap();
+
+        // only the latest settings are waiting to be published
+        let queued = list_outbox_events(&storage).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].kind, APP_DATA_KIND);
+        assert_eq!(queued[0].relays, settings.sync_relays());
+    }
+}
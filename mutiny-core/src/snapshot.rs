@@ -0,0 +1,288 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bdk_chain::BlockId;
+use bitcoin::bip158::FilterHeader;
+use bitcoin::block::Header;
+use bitcoin::consensus::serialize;
+use bitcoin::constants::genesis_block;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::params::Params;
+use bitcoin::secp256k1::{schnorr, All, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::{BlockHash, Network};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const CHAIN_SNAPSHOT_TIP_KEY: &str = "chain_snapshot_tip";
+
+/// Blocks a snapshot is allowed to start from. Snapshots have to start exactly at
+/// one of these so that every header in them is anchored to a block we know.
+fn checkpoints(network: Network) -> Vec<(u32, BlockHash)> {
+    let mut checkpoints = vec![(0, genesis_block(network).block_hash())];
+    if network == Network::Bitcoin {
+        let mainnet = [
+            (
+                800_000,
+                "00000000000000000002a7c4c1e48d76c5a37902165a270156b7a8d72728a054",
+            ),
+            (
+                840_000,
+                "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5",
+            ),
+        ];
+        checkpoints.extend(
+            mainnet.into_iter().map(|(height, hash)| {
+                (height, BlockHash::from_str(hash).expect("valid block hash"))
+            }),
+        );
+    }
+    checkpoints
+}
+
+/// Recent block headers, and optionally their compact filter headers,
+/// so a new wallet doesn't have to learn the chain from scratch.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChainSnapshot {
+    pub network: Network,
+    /// Height of the first header, has to be a checkpoint
+    pub start_height: u32,
+    pub headers: Vec<Header>,
+    /// Either empty or one filter header for each block header
+    pub filter_headers: Vec<FilterHeader>,
+}
+
+/// A [ChainSnapshot] with a schnorr signature from whoever built it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignedChainSnapshot {
+    pub snapshot: ChainSnapshot,
+    pub signature: schnorr::Signature,
+}
+
+/// The part of a verified snapshot we keep around after bootstrapping
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChainSnapshotTip {
+    pub height: u32,
+    pub hash: BlockHash,
+    pub filter_header: Option<FilterHeader>,
+}
+
+impl ChainSnapshot {
+    fn signing_hash(&self) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.network.magic().to_bytes());
+        engine.input(&self.start_height.to_le_bytes());
+        for header in self.headers.iter() {
+            engine.input(&serialize(header));
+        }
+        for filter_header in self.filter_headers.iter() {
+            engine.input(filter_header.as_byte_array());
+        }
+        sha256::Hash::from_engine(engine)
+    }
+
+    /// Signs the snapshot, for whoever publishes them
+    pub fn sign(self, secp: &Secp256k1<All>, keypair: &Keypair) -> SignedChainSnapshot {
+        let msg = Message::from_digest(self.signing_hash().to_byte_array());
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, keypair);
+        SignedChainSnapshot {
+            snapshot: self,
+            signature,
+        }
+    }
+
+    /// Checks the headers connect to a checkpoint and each other with valid proof of work,
+    /// returning the tip of the snapshot.
+    fn verify_headers(&self) -> Result<ChainSnapshotTip, MutinyError> {
+        let first = self
+            .headers
+            .first()
+            .ok_or(MutinyError::InvalidChainSnapshot)?;
+        if !self.filter_headers.is_empty() && self.filter_headers.len() != self.headers.len() {
+            return Err(MutinyError::InvalidChainSnapshot);
+        }
+
+        let checkpoint = checkpoints(self.network)
+            .into_iter()
+            .find(|(height, _)| *height == self.start_height)
+            .ok_or(MutinyError::InvalidChainSnapshot)?;
+        if first.block_hash() != checkpoint.1 {
+            return Err(MutinyError::InvalidChainSnapshot);
+        }
+
+        let params = Params::new(self.network);
+        let check_retarget = !params.allow_min_difficulty_blocks && !params.no_pow_retargeting;
+        let interval = params.difficulty_adjustment_interval() as u32;
+
+        let mut prev: Option<&Header> = None;
+        for (i, header) in self.headers.iter().enumerate() {
+            let height = self.start_height + i as u32;
+            let target = header.target();
+            if target > params.max_attainable_target || header.validate_pow(target).is_err() {
+                return Err(MutinyError::InvalidChainSnapshot);
+            }
+
+            if let Some(prev) = prev {
+                if header.prev_blockhash != prev.block_hash() {
+                    return Err(MutinyError::InvalidChainSnapshot);
+                }
+                // difficulty can only change at the start of a retarget period
+                if check_retarget && height % interval != 0 && header.bits != prev.bits {
+                    return Err(MutinyError::InvalidChainSnapshot);
+                }
+            }
+            prev = Some(header);
+        }
+
+        let tip = prev.expect("headers is not empty");
+        Ok(ChainSnapshotTip {
+            height: self.start_height + self.headers.len() as u32 - 1,
+            hash: tip.block_hash(),
+            filter_header: self.filter_headers.last().copied(),
+        })
+    }
+}
+
+impl SignedChainSnapshot {
+    /// Verifies the snapshot is for our network, signed by the given key
+    /// and anchored to a hard-coded checkpoint.
+    pub fn verify(
+        &self,
+        network: Network,
+        signing_key: &XOnlyPublicKey,
+    ) -> Result<ChainSnapshotTip, MutinyError> {
+        if self.snapshot.network != network {
+            return Err(MutinyError::InvalidChainSnapshot);
+        }
+
+        let msg = Message::from_digest(self.snapshot.signing_hash().to_byte_array());
+        Secp256k1::verification_only()
+            .verify_schnorr(&self.signature, &msg, signing_key)
+            .map_err(|_| MutinyError::InvalidChainSnapshot)?;
+
+        self.snapshot.verify_headers()
+    }
+}
+
+impl From<ChainSnapshotTip> for BlockId {
+    fn from(tip: ChainSnapshotTip) -> Self {
+        BlockId {
+            height: tip.height,
+            hash: tip.hash,
+        }
+    }
+}
+
+pub(crate) async fn fetch_chain_snapshot(
+    client: &Client,
+    url: &str,
+) -> Result<SignedChainSnapshot, MutinyError> {
+    let request = client
+        .get(url)
+        .build()
+        .map_err(|_| MutinyError::ChainAccessFailed)?;
+
+    utils::fetch_with_timeout(client, request)
+        .await?
+        .error_for_status()
+        .map_err(|_| MutinyError::ChainAccessFailed)?
+        .json()
+        .await
+        .map_err(|_| MutinyError::InvalidChainSnapshot)
+}
+
+pub(crate) fn persist_chain_snapshot_tip<S: MutinyStorage>(
+    storage: &S,
+    tip: &ChainSnapshotTip,
+) -> Result<(), MutinyError> {
+    storage.write_data(CHAIN_SNAPSHOT_TIP_KEY.to_string(), tip, None)
+}
+
+pub(crate) fn get_chain_snapshot_tip<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<ChainSnapshotTip>, MutinyError> {
+    storage.get_data(CHAIN_SNAPSHOT_TIP_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::block::Version;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::TxMerkleNode;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn mine_header(prev: &Header) -> Header {
+        let mut header = Header {
+            version: Version::ONE,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: prev.time + 600,
+            bits: prev.bits,
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn regtest_snapshot() -> ChainSnapshot {
+        let genesis = genesis_block(Network::Regtest).header;
+        let one = mine_header(&genesis);
+        let two = mine_header(&one);
+        ChainSnapshot {
+            network: Network::Regtest,
+            start_height: 0,
+            headers: vec![genesis, one, two],
+            filter_headers: vec![],
+        }
+    }
+
+    #[test]
+    fn test_verify_chain_snapshot() {
+        let test_name = "test_verify_chain_snapshot";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (key, _) = keypair.x_only_public_key();
+
+        let snapshot = regtest_snapshot();
+        let tip_hash = snapshot.headers[2].block_hash();
+        let signed = snapshot.clone().sign(&secp, &keypair);
+        let tip = signed.verify(Network::Regtest, &key).unwrap();
+        assert_eq!(tip.height, 2);
+        assert_eq!(tip.hash, tip_hash);
+
+        // wrong network
+        assert!(signed.verify(Network::Signet, &key).is_err());
+
+        // wrong signer
+        let other = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        assert!(signed
+            .verify(Network::Regtest, &other.x_only_public_key().0)
+            .is_err());
+
+        // tampered after signing
+        let mut tampered = signed.clone();
+        tampered.snapshot.headers.pop();
+        assert!(tampered.verify(Network::Regtest, &key).is_err());
+
+        // not starting at a checkpoint
+        let mut unanchored = snapshot.clone();
+        unanchored.start_height = 1;
+        unanchored.headers.remove(0);
+        let unanchored = unanchored.sign(&secp, &keypair);
+        assert!(unanchored.verify(Network::Regtest, &key).is_err());
+
+        // headers that don't connect
+        let mut disconnected = snapshot;
+        disconnected.headers.remove(1);
+        let disconnected = disconnected.sign(&secp, &keypair);
+        assert!(disconnected.verify(Network::Regtest, &key).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+//! Social recovery: the seed is encrypted with a random backup key, the backup
+//! key is split into Shamir shares and each trusted contact (a "guardian") is
+//! sent one share and the encrypted seed over NIP-04 direct messages. Any
+//! `threshold` of them can give the seed back, fewer learn nothing about it.
+//!
+//! Guardians are pinged now and then so the user knows if recovery would still
+//! work. To recover, a new device makes a throwaway recovery key, the user asks
+//! their guardians to release their shards to it and the device reassembles them.
+
+use crate::encrypt::{decrypt_with_key_and_aad, encrypt_with_key_and_aad};
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::nostr::{
+    fetch_from_relay, nip04_decrypt, nip04_encrypt, sign_event, verify_event, ENCRYPTED_DM_KIND,
+};
+use crate::outbox::{persist_outbox_event, OutboxEvent};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bip39::Mnemonic;
+use bitcoin::bip32::Xpriv;
+use bitcoin::secp256k1::{self, Secp256k1, SecretKey, XOnlyPublicKey};
+use hex_conservative::{DisplayHex, FromHex};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+pub(crate) const SOCIAL_RECOVERY_KEY: &str = "social_recovery";
+pub(crate) const HELD_SHARD_PREFIX: &str = "held_shard/";
+/// Time of the newest direct message we've handled, so they aren't fetched again
+pub(crate) const RECOVERY_MESSAGES_SINCE_KEY: &str = "social_recovery_messages_since";
+/// How often guardians are pinged to check they're still around
+pub const LIVENESS_CHECK_INTERVAL_SECS: u64 = 7 * 24 * 60 * 60;
+/// A guardian that hasn't answered a ping in this long is counted as gone
+pub const GUARDIAN_STALE_SECS: u64 = 30 * 24 * 60 * 60;
+const SHARD_VERSION: u8 = 1;
+
+/// One guardian's piece of the backup
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupShard {
+    pub version: u8,
+    /// Hex pubkey of the wallet the backup is for
+    pub owner: String,
+    /// The Shamir x coordinate, starting at 1
+    pub index: u8,
+    /// How many shards are needed to recover
+    pub threshold: u8,
+    /// Hex encoded Shamir share of the backup key
+    pub share: String,
+    /// Base64 encoded seed, encrypted with the backup key
+    pub ciphertext: String,
+}
+
+/// A trusted contact holding one of our shards
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Guardian {
+    pub contact_id: String,
+    /// Hex pubkey the guardian's wallet receives shards on
+    pub pubkey: String,
+    pub index: u8,
+    /// Epoch time in seconds the guardian last answered a ping
+    #[serde(default)]
+    pub last_seen: Option<u64>,
+}
+
+impl Guardian {
+    pub fn is_alive(&self, now: u64) -> bool {
+        self.last_seen
+            .is_some_and(|seen| now.saturating_sub(seen) < GUARDIAN_STALE_SECS)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SocialRecoveryConfig {
+    pub threshold: u8,
+    pub guardians: Vec<Guardian>,
+    pub created_at: u64,
+    /// Epoch time in seconds the guardians were last pinged
+    pub last_check_at: u64,
+}
+
+impl SocialRecoveryConfig {
+    /// Whether enough guardians have answered recently for recovery to work
+    pub fn is_recoverable(&self, now: u64) -> bool {
+        let alive = self.guardians.iter().filter(|g| g.is_alive(now)).count();
+        alive >= self.threshold as usize
+    }
+}
+
+/// A shard we're holding for one of our contacts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeldShard {
+    pub shard: BackupShard,
+    pub received_at: u64,
+}
+
+/// What goes in the direct messages between the wallet and its guardians
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum RecoveryMessage {
+    /// From the owner to a guardian, the shard to hold
+    Shard { shard: BackupShard },
+    /// From the owner, to check the guardian still has their shard
+    Ping { owner: String },
+    /// The guardian's answer to a ping
+    Pong { owner: String },
+    /// From a guardian to a recovering device, the shard being given back
+    Release { shard: BackupShard },
+}
+
+/// The key our recovery messages are signed by and encrypted to. Its pubkey is
+/// what contacts use to make us their guardian.
+pub(crate) fn social_recovery_key(xprivkey: Xpriv) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    Ok(create_root_child_key(&context, xprivkey, ChildKey::SocialRecovery)?.private_key)
+}
+
+/// A throwaway key for a new device to receive released shards on
+pub fn generate_recovery_key() -> SecretKey {
+    SecretKey::new(&mut secp256k1::rand::thread_rng())
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            // x^8 + x^4 + x^3 + x + 1
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 is the inverse in GF(256)
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+/// Splits the secret into `shares` Shamir shares, any `threshold` of which recover it
+pub(crate) fn split_secret(
+    secret: &[u8],
+    threshold: u8,
+    shares: u8,
+) -> Result<Vec<(u8, Vec<u8>)>, MutinyError> {
+    if threshold == 0 || threshold > shares {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let mut result: Vec<(u8, Vec<u8>)> = (1..=shares).map(|x| (x, vec![])).collect();
+    for byte in secret {
+        let mut coefficients = vec![*byte];
+        coefficients.extend((1..threshold).map(|_| secp256k1::rand::random::<u8>()));
+        for (x, share) in result.iter_mut() {
+            // horner's method, highest coefficient first
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, c| gf_mul(acc, *x) ^ c);
+            share.push(y);
+        }
+    }
+    Ok(result)
+}
+
+/// Recovers the secret from shares, they must be at least the threshold it was split with
+pub(crate) fn combine_shares(shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, MutinyError> {
+    let len = shares.first().map(|(_, s)| s.len()).unwrap_or_default();
+    let mut indexes: Vec<u8> = shares.iter().map(|(x, _)| *x).collect();
+    indexes.sort();
+    indexes.dedup();
+    if shares.is_empty()
+        || indexes.len() != shares.len()
+        || indexes.contains(&0)
+        || shares.iter().any(|(_, s)| s.len() != len)
+    {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    // lagrange interpolation at x = 0, in GF(256) subtraction is xor
+    let mut secret = vec![0u8; len];
+    for (i, (xi, share)) in shares.iter().enumerate() {
+        let mut basis = 1;
+        for (j, (xj, _)) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*xj, gf_inv(xi ^ xj)));
+            }
+        }
+        for (s, y) in secret.iter_mut().zip(share) {
+            *s ^= gf_mul(basis, *y);
+        }
+    }
+    Ok(secret)
+}
+
+/// Encrypts the seed and makes a shard for each guardian
+pub(crate) fn create_shards(
+    mnemonic: &Mnemonic,
+    owner: &XOnlyPublicKey,
+    threshold: u8,
+    guardians: u8,
+) -> Result<Vec<BackupShard>, MutinyError> {
+    let backup_key = generate_recovery_key();
+    let owner = owner.to_string();
+    let ciphertext = base64::encode(encrypt_with_key_and_aad(
+        &backup_key,
+        mnemonic.to_string().as_bytes(),
+        owner.as_bytes(),
+    )?);
+
+    Ok(
+        split_secret(&backup_key.secret_bytes(), threshold, guardians)?
+            .into_iter()
+            .map(|(index, share)| BackupShard {
+                version: SHARD_VERSION,
+                owner: owner.clone(),
+                index,
+                threshold,
+                share: share.to_lower_hex_string(),
+                ciphertext: ciphertext.clone(),
+            })
+            .collect(),
+    )
+}
+
+/// Reassembles the seed from the shards of one owner's backup
+pub fn recover_mnemonic(shards: &[BackupShard]) -> Result<Mnemonic, MutinyError> {
+    let first = shards.first().ok_or(MutinyError::InvalidArgumentsError)?;
+    let shards: Vec<&BackupShard> = shards
+        .iter()
+        .filter(|s| s.owner == first.owner && s.ciphertext == first.ciphertext)
+        .collect();
+    if first.version != SHARD_VERSION || shards.len() < first.threshold as usize {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let shares = shards
+        .iter()
+        .take(first.threshold as usize)
+        .map(|s| Ok((s.index, Vec::<u8>::from_hex(&s.share)?)))
+        .collect::<Result<Vec<_>, MutinyError>>()?;
+    let backup_key = SecretKey::from_slice(&combine_shares(&shares)?)
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let plaintext = decrypt_with_key_and_aad(
+        &backup_key,
+        &base64::decode(&first.ciphertext)?,
+        first.owner.as_bytes(),
+    )?;
+    Ok(Mnemonic::from_str(&String::from_utf8(plaintext)?)?)
+}
+
+/// Creates a signed direct message event carrying the recovery message
+pub(crate) fn create_recovery_dm(
+    key: &SecretKey,
+    to: &XOnlyPublicKey,
+    message: &RecoveryMessage,
+) -> Result<Value, MutinyError> {
+    let content = nip04_encrypt(key, to, &serde_json::to_string(message)?);
+    Ok(sign_event(
+        key,
+        ENCRYPTED_DM_KIND,
+        json!([["p", to.to_string()]]),
+        content,
+    ))
+}
+
+/// Verifies and decrypts a recovery direct message sent to us, returning the
+/// sender, the message and when it was sent
+pub(crate) fn read_recovery_dm(
+    key: &SecretKey,
+    event: &Value,
+) -> Result<(XOnlyPublicKey, RecoveryMessage, u64), MutinyError> {
+    let sender = verify_event(event)?;
+    let kind = event.get("kind").and_then(|k| k.as_u64());
+    let created_at = event.get("created_at").and_then(|c| c.as_u64());
+    let content = event.get("content").and_then(|c| c.as_str());
+    let (Some(ENCRYPTED_DM_KIND), Some(created_at), Some(content)) = (kind, created_at, content)
+    else {
+        return Err(MutinyError::NostrError);
+    };
+
+    let plaintext = nip04_decrypt(key, &sender, content)?;
+    Ok((sender, serde_json::from_str(&plaintext)?, created_at))
+}
+
+/// Puts a recovery message in the nostr outbox
+pub(crate) fn queue_recovery_dm<S: MutinyStorage>(
+    storage: &S,
+    key: &SecretKey,
+    to: &XOnlyPublicKey,
+    message: &RecoveryMessage,
+    relays: Vec<String>,
+) -> Result<(), MutinyError> {
+    let event = create_recovery_dm(key, to, message)?;
+    persist_outbox_event(storage, &OutboxEvent::new(event, relays)?)
+}
+
+/// Fetches the recovery messages sent to the key since the given time. Events
+/// that aren't valid recovery messages are ignored.
+pub async fn fetch_recovery_dms(
+    key: &SecretKey,
+    relays: &[String],
+    since: u64,
+) -> Result<Vec<(XOnlyPublicKey, RecoveryMessage, u64)>, MutinyError> {
+    let secp = Secp256k1::new();
+    let pubkey = key.x_only_public_key(&secp).0;
+    let filter = json!({
+        "kinds": [ENCRYPTED_DM_KIND],
+        "#p": [pubkey.to_string()],
+        "since": since,
+    });
+
+    let mut messages = vec![];
+    let mut reached_relay = false;
+    for relay in relays {
+        let Ok(events) = fetch_from_relay(relay, &filter, key).await else {
+            continue;
+        };
+        reached_relay = true;
+        for event in events {
+            if let Ok(message) = read_recovery_dm(key, &event) {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+        }
+    }
+
+    if !reached_relay {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    Ok(messages)
+}
+
+/// Collects the shards guardians have released to the recovery key and
+/// recovers the seed once there are enough of them
+pub async fn recover_from_guardians(
+    recovery_key: &SecretKey,
+    relays: &[String],
+) -> Result<Option<Mnemonic>, MutinyError> {
+    let shards: Vec<BackupShard> = fetch_recovery_dms(recovery_key, relays, 0)
+        .await?
+        .into_iter()
+        .filter_map(|(_, message, _)| match message {
+            RecoveryMessage::Release { shard } => Some(shard),
+            _ => None,
+        })
+        .collect();
+
+    // guardians may hold backups of different owners, try each of them
+    let mut owners: Vec<&String> = shards.iter().map(|s| &s.owner).collect();
+    owners.sort();
+    owners.dedup();
+    for owner in owners {
+        let mut owned: Vec<BackupShard> = shards
+            .iter()
+            .filter(|s| &s.owner == owner)
+            .cloned()
+            .collect();
+        owned.sort_by_key(|s| s.index);
+        owned.dedup_by_key(|s| s.index);
+        if let Ok(mnemonic) = recover_mnemonic(&owned) {
+            return Ok(Some(mnemonic));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn get_social_recovery_config<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Option<SocialRecoveryConfig>, MutinyError> {
+    storage.get_data(SOCIAL_RECOVERY_KEY)
+}
+
+pub(crate) fn persist_social_recovery_config<S: MutinyStorage>(
+    storage: &S,
+    config: &SocialRecoveryConfig,
+) -> Result<(), MutinyError> {
+    storage.write_data(SOCIAL_RECOVERY_KEY.to_string(), config, None)
+}
+
+fn held_shard_key(owner: &str) -> String {
+    format!("{HELD_SHARD_PREFIX}{owner}")
+}
+
+pub(crate) fn get_held_shard<S: MutinyStorage>(
+    storage: &S,
+    owner: &str,
+) -> Result<Option<HeldShard>, MutinyError> {
+    storage.get_data(held_shard_key(owner))
+}
+
+pub(crate) fn persist_held_shard<S: MutinyStorage>(
+    storage: &S,
+    held: &HeldShard,
+) -> Result<(), MutinyError> {
+    storage.write_data(held_shard_key(&held.shard.owner), held, None)
+}
+
+/// Lists the shards we hold for our contacts, newest first
+pub(crate) fn list_held_shards<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<HeldShard>, MutinyError> {
+    let mut shards: Vec<HeldShard> = storage
+        .scan::<HeldShard>(HELD_SHARD_PREFIX, None)?
+        .into_values()
+        .collect();
+    shards.sort_by_key(|s| std::cmp::Reverse(s.received_at));
+
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_shamir_recover_mnemonic() {
+        let test_name = "test_shamir_recover_mnemonic";
+        log!("{}", test_name);
+
+        let secret = [7u8; 32];
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert_eq!(combine_shares(&shares[1..4]).unwrap(), secret);
+        assert_eq!(
+            combine_shares(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap(),
+            secret
+        );
+        assert_ne!(combine_shares(&shares[..2]).unwrap(), secret);
+        assert!(split_secret(&secret, 3, 2).is_err());
+
+        let mnemonic = crate::generate_seed(12).unwrap();
+        let owner = generate_recovery_key()
+            .x_only_public_key(&Secp256k1::new())
+            .0;
+        let shards = create_shards(&mnemonic, &owner, 2, 3).unwrap();
+        assert_eq!(recover_mnemonic(&shards[1..]).unwrap(), mnemonic);
+        assert!(recover_mnemonic(&shards[..1]).is_err());
+    }
+
+    #[test]
+    fn test_recovery_dm_round_trip() {
+        let test_name = "test_recovery_dm_round_trip";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let owner = generate_recovery_key();
+        let guardian = generate_recovery_key();
+        let guardian_pubkey = guardian.x_only_public_key(&secp).0;
+
+        let message = RecoveryMessage::Ping {
+            owner: owner.x_only_public_key(&secp).0.to_string(),
+        };
+        let event = create_recovery_dm(&owner, &guardian_pubkey, &message).unwrap();
+        let (sender, read, _) = read_recovery_dm(&guardian, &event).unwrap();
+        assert_eq!(sender, owner.x_only_public_key(&secp).0);
+        assert_eq!(read, message);
+
+        // nobody else can read it
+        assert!(read_recovery_dm(&generate_recovery_key(), &event).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::Hash;
+use hex_conservative::DisplayHex;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const SPLIT_REQUEST_PREFIX: &str = "split_request/";
+pub(crate) const SPLIT_SEND_PREFIX: &str = "split_send/";
+/// Maps the payment hash of each unpaid share to its split request
+pub(crate) const SPLIT_SHARE_PREFIX: &str = "split_share/";
+
+/// A bill that was split between contacts, each contact gets their own
+/// invoice for their share.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SplitRequest {
+    pub id: String,
+    #[serde(default)]
+    pub memo: Option<String>,
+    pub total_sats: u64,
+    pub shares: Vec<SplitShare>,
+    /// Epoch time in seconds when the split was created
+    pub created_at: u64,
+    /// Whether every share has been paid
+    pub completed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SplitShare {
+    /// The contact this share is for
+    pub contact_id: String,
+    pub amount_sats: u64,
+    pub invoice: Bolt11Invoice,
+    pub paid: bool,
+}
+
+impl SplitRequest {
+    /// Total amount paid so far in sats
+    pub fn paid_sats(&self) -> u64 {
+        self.shares
+            .iter()
+            .filter(|s| s.paid)
+            .map(|s| s.amount_sats)
+            .sum()
+    }
+}
+
+/// Splits the total as evenly as possible, any remainder is
+/// spread over the first participants.
+pub(crate) fn split_amount(total_sats: u64, participants: usize) -> Vec<u64> {
+    if participants == 0 {
+        return vec![];
+    }
+    let participants = participants as u64;
+    let base = total_sats / participants;
+    let remainder = total_sats % participants;
+
+    (0..participants)
+        .map(|i| if i < remainder { base + 1 } else { base })
+        .collect()
+}
+
+/// Splits the total proportionally to the weights, any remainder from
+/// rounding goes to the recipients that were rounded down the most.
+pub(crate) fn split_weighted(total_sats: u64, weights: &[u32]) -> Result<Vec<u64>, MutinyError> {
+    let total_weight: u128 = weights.iter().map(|w| *w as u128).sum();
+    if total_weight == 0 {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let shares: Vec<(u64, u128)> = weights
+        .iter()
+        .map(|w| {
+            let exact = total_sats as u128 * *w as u128;
+            ((exact / total_weight) as u64, exact % total_weight)
+        })
+        .collect();
+
+    let mut amounts: Vec<u64> = shares.iter().map(|(amount, _)| *amount).collect();
+    let remainder = total_sats - amounts.iter().sum::<u64>();
+
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(shares[*i].1));
+    for i in order.into_iter().take(remainder as usize) {
+        amounts[i] += 1;
+    }
+
+    Ok(amounts)
+}
+
+/// A payment that was split between several lightning addresses,
+/// kept so the payments can be shown as one entry in the activity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SplitSend {
+    pub id: String,
+    pub total_sats: u64,
+    pub recipients: Vec<SplitSendRecipient>,
+    /// Epoch time in seconds when the payments were sent
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SplitSendRecipient {
+    /// The lightning address that was paid
+    pub address: String,
+    pub weight: u32,
+    pub amount_sats: u64,
+    /// Payment hash of the invoice we got from the address
+    #[serde(default)]
+    pub payment_hash: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl SplitSend {
+    /// The label that is put on every payment of this split
+    pub fn label(&self) -> String {
+        format!("{SPLIT_SEND_PREFIX}{}", self.id)
+    }
+
+    /// Total amount that was paid successfully in sats
+    pub fn paid_sats(&self) -> u64 {
+        self.recipients
+            .iter()
+            .filter(|r| r.error.is_none())
+            .map(|r| r.amount_sats)
+            .sum()
+    }
+}
+
+fn split_send_key(id: &str) -> String {
+    format!("{SPLIT_SEND_PREFIX}{id}")
+}
+
+pub(crate) fn persist_split_send<S: MutinyStorage>(
+    storage: &S,
+    split: &SplitSend,
+) -> Result<(), MutinyError> {
+    storage.write_data(split_send_key(&split.id), split, None)
+}
+
+pub(crate) fn get_split_send<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<SplitSend>, MutinyError> {
+    storage.get_data(split_send_key(id))
+}
+
+fn split_request_key(id: &str) -> String {
+    format!("{SPLIT_REQUEST_PREFIX}{id}")
+}
+
+pub(crate) fn persist_split_request<S: MutinyStorage>(
+    storage: &S,
+    split: &SplitRequest,
+) -> Result<(), MutinyError> {
+    storage.write_data(split_request_key(&split.id), split, None)
+}
+
+fn split_share_key(payment_hash: &[u8; 32]) -> String {
+    format!("{SPLIT_SHARE_PREFIX}{}", payment_hash.to_lower_hex_string())
+}
+
+/// Saves a new split request and indexes its shares by payment hash, so a
+/// payment can find its share without going through every split
+pub(crate) fn persist_new_split_request<S: MutinyStorage>(
+    storage: &S,
+    split: &SplitRequest,
+) -> Result<(), MutinyError> {
+    persist_split_request(storage, split)?;
+    for share in split.shares.iter() {
+        let key = split_share_key(&share.invoice.payment_hash().to_byte_array());
+        storage.write_data(key, &split.id, None)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn get_split_request<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<SplitRequest>, MutinyError> {
+    storage.get_data(split_request_key(id))
+}
+
+/// Lists all split requests, newest first
+pub(crate) fn list_split_requests<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<SplitRequest>, MutinyError> {
+    let mut splits: Vec<SplitRequest> = storage
+        .scan::<SplitRequest>(SPLIT_REQUEST_PREFIX, None)?
+        .into_values()
+        .collect();
+    splits.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+
+    Ok(splits)
+}
+
+/// Marks the share with the given payment hash as paid.
+/// Returns the split request if this payment completed it.
+pub(crate) fn mark_split_share_paid<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Result<Option<SplitRequest>, MutinyError> {
+    let key = split_share_key(payment_hash);
+    let Some(id) = storage.get_data::<String>(&key)? else {
+        return Ok(None);
+    };
+    let Some(mut split) = get_split_request(storage, &id)? else {
+        return Ok(None);
+    };
+
+    if let Some(share) = split
+        .shares
+        .iter_mut()
+        .find(|s| &s.invoice.payment_hash().to_byte_array() == payment_hash)
+    {
+        share.paid = true;
+        split.completed = split.shares.iter().all(|s| s.paid);
+        persist_split_request(storage, &split)?;
+    }
+    storage.delete(&[key])?;
+
+    Ok(split.completed.then_some(split))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nr6zpp5xmvlq2u5253htn52mflh2e6gn7pk5ht0d4qyhc62fadytccxw7hqhp5l4s6qwh57a7cwr7zrcz706qx0qy4eykcpr8m8dwz08hqf362egfscqzzsxqzfvsp5pr7yjvcn4ggrf6fq090zey0yvf8nqvdh2kq7fue0s0gnm69evy6s9qyyssqjyq0fwjr22eeg08xvmz88307yqu8tqqdjpycmermks822fpqyxgshj8hvnl9mkh6srclnxx0uf4ugfq43d66ak3rrz4dqcqd23vxwpsqf7dmhm";
+
+    #[test]
+    fn test_split_amount() {
+        let test_name = "test_split_amount";
+        log!("{}", test_name);
+
+        assert_eq!(split_amount(10, 3), vec![4, 3, 3]);
+        assert_eq!(split_amount(9, 3), vec![3, 3, 3]);
+        assert_eq!(split_amount(1, 2), vec![1, 0]);
+        assert!(split_amount(10, 0).is_empty());
+        assert_eq!(split_amount(100_001, 4).iter().sum::<u64>(), 100_001);
+    }
+
+    #[test]
+    fn test_split_weighted() {
+        let test_name = "test_split_weighted";
+        log!("{}", test_name);
+
+        assert_eq!(split_weighted(100, &[1, 1]).unwrap(), vec![50, 50]);
+        assert_eq!(split_weighted(100, &[1, 3]).unwrap(), vec![25, 75]);
+        assert_eq!(split_weighted(10, &[1, 1, 1]).unwrap(), vec![4, 3, 3]);
+        assert_eq!(split_weighted(10, &[1, 2, 0]).unwrap(), vec![3, 7, 0]);
+        assert_eq!(
+            split_weighted(100_001, &[3, 5, 7])
+                .unwrap()
+                .iter()
+                .sum::<u64>(),
+            100_001
+        );
+        assert!(split_weighted(100, &[0, 0]).is_err());
+        assert!(split_weighted(100, &[]).is_err());
+    }
+
+    #[test]
+    fn test_mark_split_share_paid() {
+        let test_name = "test_mark_split_share_paid";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let invoice = Bolt11Invoice::from_str(INVOICE).unwrap();
+        let split = SplitRequest {
+            id: "split".to_string(),
+            memo: Some("dinner".to_string()),
+            total_sats: 92_372,
+            shares: vec![SplitShare {
+                contact_id: "contact".to_string(),
+                amount_sats: 92_372,
+                invoice: invoice.clone(),
+                paid: false,
+            }],
+            created_at: 1,
+            completed: false,
+        };
+        persist_new_split_request(&storage, &split).unwrap();
+
+        // unknown payment hash does nothing
+        assert!(mark_split_share_paid(&storage, &[0; 32]).unwrap().is_none());
+
+        let completed = mark_split_share_paid(&storage, &invoice.payment_hash().to_byte_array())
+            .unwrap()
+            .unwrap();
+        assert!(completed.completed);
+        assert_eq!(completed.paid_sats(), 92_372);
+        assert_eq!(
+            get_split_request(&storage, "split").unwrap(),
+            Some(completed)
+        );
+
+        // the share is only marked once
+        assert!(
+            mark_split_share_paid(&storage, &invoice.payment_hash().to_byte_array())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,577 @@
+//! Monthly account statements, for businesses using the wallet to run on.
+//!
+//! Balances are worked out backwards from the current ones using the wallet's
+//! activity. Channel opens and cooperative closes move funds between on-chain
+//! and lightning, those show up as transfers. Sweeps of force closed channels
+//! can't be told apart from other receives, so they show up as on-chain receives.
+//! Fiat values use the recorded price of bitcoin at the time of each entry,
+//! entries from before the price history starts have none.
+
+use crate::denominations::sats_to_fiat;
+use crate::error::MutinyError;
+use crate::event::HTLCStatus;
+use crate::ActivityItem;
+use bdk_chain::ConfirmationTime;
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const PDF_LINES_PER_PAGE: usize = 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum StatementSource {
+    OnChain,
+    Lightning,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum StatementEntryKind {
+    Received,
+    Sent,
+    /// Moved from one source to the other, e.g. a channel open
+    Transfer {
+        from: StatementSource,
+        to: StatementSource,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatementEntry {
+    /// Epoch time in seconds
+    pub timestamp: u64,
+    pub kind: StatementEntryKind,
+    pub source: StatementSource,
+    /// The txid or payment hash
+    pub reference: String,
+    pub amount_sats: u64,
+    pub fee_sats: u64,
+    pub labels: Vec<String>,
+    pub fiat_value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SourceSummary {
+    pub opening_balance: u64,
+    pub closing_balance: u64,
+    pub received: u64,
+    pub sent: u64,
+    pub fees: u64,
+    pub transfers_in: u64,
+    pub transfers_out: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatementFiat {
+    pub currency: String,
+    /// Bitcoin price at the start of the period
+    pub opening_price: Option<f32>,
+    /// Bitcoin price at the end of the period
+    pub closing_price: Option<f32>,
+    pub opening_value: Option<f64>,
+    pub closing_value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Statement {
+    pub year: i32,
+    pub month: u32,
+    /// Start of the month, epoch seconds UTC
+    pub period_start: u64,
+    /// Start of the next month, epoch seconds UTC
+    pub period_end: u64,
+    pub generated_at: u64,
+    pub on_chain: SourceSummary,
+    pub lightning: SourceSummary,
+    pub total_fees: u64,
+    /// Oldest first
+    pub entries: Vec<StatementEntry>,
+    pub fiat: Option<StatementFiat>,
+}
+
+/// Epoch seconds at the start of the month, UTC
+pub(crate) fn month_start(year: i32, month: u32) -> Result<u64, MutinyError> {
+    if !(1..=12).contains(&month) || year < 1970 {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+    // days from civil, from Howard Hinnant's date algorithms
+    let y = if month <= 2 { year - 1 } else { year } as i64;
+    let m = month as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Ok(days as u64 * 24 * 60 * 60)
+}
+
+/// One entry and what it did to each source's balance
+struct Flow {
+    entry: StatementEntry,
+    on_chain: i64,
+    lightning: i64,
+}
+
+fn flows(
+    activity: &[ActivityItem],
+    funding_txids: &HashSet<Txid>,
+    closing_txids: &HashSet<Txid>,
+) -> Vec<Flow> {
+    let mut flows = vec![];
+    for item in activity {
+        match item {
+            ActivityItem::OnChain(tx) => {
+                let timestamp = match tx.confirmation_time {
+                    ConfirmationTime::Confirmed { time, .. } => time,
+                    ConfirmationTime::Unconfirmed { last_seen } => last_seen,
+                };
+                let fee_sats = tx.fee.unwrap_or_default();
+                let net = tx.received as i64 - tx.sent as i64;
+                let txid = tx.txid.unwrap_or(tx.internal_id);
+                let (kind, amount_sats, lightning) = if funding_txids.contains(&txid) {
+                    let amount = (-net - fee_sats as i64).max(0);
+                    let kind = StatementEntryKind::Transfer {
+                        from: StatementSource::OnChain,
+                        to: StatementSource::Lightning,
+                    };
+                    (kind, amount as u64, amount)
+                } else if closing_txids.contains(&txid) {
+                    let kind = StatementEntryKind::Transfer {
+                        from: StatementSource::Lightning,
+                        to: StatementSource::OnChain,
+                    };
+                    (kind, tx.received, -(tx.received as i64))
+                } else if net >= 0 {
+                    (StatementEntryKind::Received, net as u64, 0)
+                } else {
+                    let amount = (-net - fee_sats as i64).max(0) as u64;
+                    (StatementEntryKind::Sent, amount, 0)
+                };
+                flows.push(Flow {
+                    entry: StatementEntry {
+                        timestamp,
+                        kind,
+                        source: StatementSource::OnChain,
+                        reference: txid.to_string(),
+                        amount_sats,
+                        fee_sats: if net < 0 { fee_sats } else { 0 },
+                        labels: tx.labels.clone(),
+                        fiat_value: None,
+                    },
+                    on_chain: net,
+                    lightning,
+                });
+            }
+            ActivityItem::Lightning(invoice) => {
+                if invoice.status != HTLCStatus::Succeeded {
+                    continue;
+                }
+                let amount_sats = invoice.amount_sats.unwrap_or_default();
+                let fee_sats = invoice.fees_paid.unwrap_or_default();
+                let (kind, lightning) = if invoice.inbound {
+                    (StatementEntryKind::Received, amount_sats as i64)
+                } else {
+                    (StatementEntryKind::Sent, -((amount_sats + fee_sats) as i64))
+                };
+                flows.push(Flow {
+                    entry: StatementEntry {
+                        timestamp: invoice.last_updated,
+                        kind,
+                        source: StatementSource::Lightning,
+                        reference: invoice.payment_hash.to_string(),
+                        amount_sats,
+                        fee_sats: if invoice.inbound { 0 } else { fee_sats },
+                        labels: invoice.labels.clone(),
+                        fiat_value: None,
+                    },
+                    on_chain: 0,
+                    lightning,
+                });
+            }
+            // the funds move with the closing transaction
+            ActivityItem::ChannelClosed(_) => {}
+            // paid for with its own lightning or on-chain payment
+            ActivityItem::ChannelPurchase(_) => {}
+            // only the routing fee leaves the wallet
+            ActivityItem::Rebalance(rebalance) => {
+                if rebalance.status != HTLCStatus::Succeeded {
+                    continue;
+                }
+                let fee_sats = rebalance.fee_paid_msat.unwrap_or_default() / 1_000;
+                flows.push(Flow {
+                    entry: StatementEntry {
+                        timestamp: rebalance.last_updated,
+                        kind: StatementEntryKind::Transfer {
+                            from: StatementSource::Lightning,
+                            to: StatementSource::Lightning,
+                        },
+                        source: StatementSource::Lightning,
+                        reference: rebalance.payment_hash.to_string(),
+                        amount_sats: rebalance.amount_sats,
+                        fee_sats,
+                        labels: vec![],
+                        fiat_value: None,
+                    },
+                    on_chain: 0,
+                    lightning: -(fee_sats as i64),
+                });
+            }
+        }
+    }
+    flows
+}
+
+/// The balance at `at`, rolled back from the current one
+fn balance_at(current: u64, flows: &[Flow], at: u64, delta: fn(&Flow) -> i64) -> u64 {
+    let after: i64 = flows
+        .iter()
+        .filter(|f| f.entry.timestamp >= at)
+        .map(delta)
+        .sum();
+    (current as i64 - after).max(0) as u64
+}
+
+impl Statement {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        year: i32,
+        month: u32,
+        now: u64,
+        on_chain_balance: u64,
+        lightning_balance: u64,
+        activity: &[ActivityItem],
+        funding_txids: &HashSet<Txid>,
+        closing_txids: &HashSet<Txid>,
+        fiat: Option<(String, impl Fn(u64) -> Option<f32>)>,
+    ) -> Result<Self, MutinyError> {
+        let period_start = month_start(year, month)?;
+        let period_end = if month == 12 {
+            month_start(year + 1, 1)?
+        } else {
+            month_start(year, month + 1)?
+        };
+
+        let mut flows = flows(activity, funding_txids, closing_txids);
+        flows.sort_by_key(|f| f.entry.timestamp);
+
+        let summary = |current: u64, source: StatementSource, delta: fn(&Flow) -> i64| {
+            let mut summary = SourceSummary {
+                opening_balance: balance_at(current, &flows, period_start, delta),
+                closing_balance: balance_at(current, &flows, period_end, delta),
+                ..Default::default()
+            };
+            let in_period = flows
+                .iter()
+                .filter(|f| f.entry.timestamp >= period_start && f.entry.timestamp < period_end);
+            for flow in in_period {
+                let entry = &flow.entry;
+                match entry.kind {
+                    StatementEntryKind::Received if entry.source == source => {
+                        summary.received += entry.amount_sats
+                    }
+                    StatementEntryKind::Sent if entry.source == source => {
+                        summary.sent += entry.amount_sats;
+                        summary.fees += entry.fee_sats;
+                    }
+                    StatementEntryKind::Transfer { from, to } => {
+                        if from == source {
+                            summary.transfers_out += entry.amount_sats;
+                        }
+                        if to == source {
+                            summary.transfers_in += entry.amount_sats;
+                        }
+                        if entry.source == source {
+                            summary.fees += entry.fee_sats;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            summary
+        };
+        let on_chain = summary(on_chain_balance, StatementSource::OnChain, |f| f.on_chain);
+        let lightning = summary(lightning_balance, StatementSource::Lightning, |f| {
+            f.lightning
+        });
+
+        let mut entries: Vec<StatementEntry> = flows
+            .into_iter()
+            .map(|f| f.entry)
+            .filter(|e| e.timestamp >= period_start && e.timestamp < period_end)
+            .collect();
+        if let Some((_, price_at)) = fiat.as_ref() {
+            for entry in entries.iter_mut() {
+                entry.fiat_value =
+                    price_at(entry.timestamp).map(|price| sats_to_fiat(entry.amount_sats, price));
+            }
+        }
+
+        let fiat = fiat.map(|(currency, price_at)| {
+            let opening_price = price_at(period_start);
+            let closing_price = price_at(period_end);
+            let opening_balance = on_chain.opening_balance + lightning.opening_balance;
+            let closing_balance = on_chain.closing_balance + lightning.closing_balance;
+            StatementFiat {
+                currency,
+                opening_price,
+                closing_price,
+                opening_value: opening_price.map(|price| sats_to_fiat(opening_balance, price)),
+                closing_value: closing_price.map(|price| sats_to_fiat(closing_balance, price)),
+            }
+        });
+
+        Ok(Self {
+            year,
+            month,
+            period_start,
+            period_end,
+            generated_at: now,
+            total_fees: on_chain.fees + lightning.fees,
+            on_chain,
+            lightning,
+            entries,
+            fiat,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, MutinyError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn text_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Statement for {}-{:02}", self.year, self.month),
+            String::new(),
+        ];
+        for (name, summary) in [("On-chain", &self.on_chain), ("Lightning", &self.lightning)] {
+            lines.push(format!(
+                "{name}: opening {} sats, closing {} sats",
+                summary.opening_balance, summary.closing_balance
+            ));
+            lines.push(format!(
+                "  received {}, sent {}, fees {}, transfers in {}, transfers out {}",
+                summary.received,
+                summary.sent,
+                summary.fees,
+                summary.transfers_in,
+                summary.transfers_out
+            ));
+        }
+        lines.push(format!("Total fees: {} sats", self.total_fees));
+        if let Some(fiat) = self.fiat.as_ref() {
+            let currency = fiat.currency.to_uppercase();
+            let value = |value: Option<f64>, price: Option<f32>| match (value, price) {
+                (Some(value), Some(price)) => {
+                    format!("{value:.2} {currency} at {price:.2} per bitcoin")
+                }
+                _ => "no price".to_string(),
+            };
+            lines.push(format!(
+                "Valued opening {}, closing {}",
+                value(fiat.opening_value, fiat.opening_price),
+                value(fiat.closing_value, fiat.closing_price)
+            ));
+        }
+        lines.push(String::new());
+        lines.push("Activity".to_string());
+        for entry in self.entries.iter() {
+            let kind = match entry.kind {
+                StatementEntryKind::Received => "received".to_string(),
+                StatementEntryKind::Sent => "sent".to_string(),
+                StatementEntryKind::Transfer { from, to } => format!("{from:?} to {to:?}"),
+            };
+            lines.push(format!(
+                "{} {:?} {kind} {} sats, fee {} {}",
+                entry.timestamp,
+                entry.source,
+                entry.amount_sats,
+                entry.fee_sats,
+                entry.labels.join(", ")
+            ));
+        }
+        lines
+    }
+
+    /// A plain text rendering of the statement as a PDF document
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let lines = self.text_lines();
+        let pages: Vec<&[String]> = lines.chunks(PDF_LINES_PER_PAGE).collect();
+
+        // catalog, page tree and font come first, then a page and its content for each page
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                (0..pages.len())
+                    .map(|i| format!("{} 0 R", 4 + i * 2))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                pages.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+        for (i, page) in pages.iter().enumerate() {
+            let mut content = "BT /F1 9 Tf 12 TL 40 800 Td\n".to_string();
+            for line in page.iter() {
+                content.push_str(&format!("({}) '\n", pdf_escape(line)));
+            }
+            content.push_str("ET");
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                5 + i * 2
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{content}\nendstream",
+                content.len()
+            ));
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+        }
+        let xref = pdf.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            trailer.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        trailer.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        ));
+        pdf.extend_from_slice(trailer.as_bytes());
+        pdf
+    }
+}
+
+/// The built in fonts only cover ASCII, anything else becomes a question mark
+fn pdf_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_ascii() && !c.is_ascii_control() => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{MutinyInvoice, TransactionDetails};
+    use bitcoin::hashes::Hash;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // 2024-03-01 and 2024-04-01 UTC
+    const MARCH: u64 = 1_709_251_200;
+    const APRIL: u64 = 1_711_929_600;
+
+    fn onchain(txid: u8, time: u64, received: u64, sent: u64, fee: u64) -> ActivityItem {
+        let txid = Txid::from_byte_array([txid; 32]);
+        ActivityItem::OnChain(TransactionDetails {
+            transaction: None,
+            txid: Some(txid),
+            internal_id: txid,
+            received,
+            sent,
+            fee: Some(fee),
+            confirmation_time: ConfirmationTime::Confirmed { height: 1, time },
+            labels: vec![],
+            replaces: vec![],
+            address_reused: false,
+        })
+    }
+
+    fn lightning(time: u64, amount: u64, inbound: bool, fee: u64) -> ActivityItem {
+        ActivityItem::Lightning(Box::new(MutinyInvoice {
+            amount_sats: Some(amount),
+            inbound,
+            fees_paid: Some(fee),
+            status: HTLCStatus::Succeeded,
+            last_updated: time,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_month_start() {
+        let test_name = "test_month_start";
+        log!("{}", test_name);
+
+        assert_eq!(month_start(1970, 1).unwrap(), 0);
+        assert_eq!(month_start(2024, 3).unwrap(), MARCH);
+        assert_eq!(month_start(2024, 4).unwrap(), APRIL);
+        assert!(month_start(2024, 13).is_err());
+    }
+
+    #[test]
+    fn test_statement() {
+        let test_name = "test_statement";
+        log!("{}", test_name);
+
+        let activity = vec![
+            // february deposit
+            onchain(1, MARCH - 10, 1_000_000, 0, 0),
+            // march channel open of 500k
+            onchain(2, MARCH + 10, 0, 501_000, 1_000),
+            lightning(MARCH + 20, 20_000, true, 0),
+            lightning(MARCH + 30, 10_000, false, 10),
+            // april send
+            onchain(3, APRIL + 10, 100_000, 300_000, 500),
+        ];
+        let funding = HashSet::from([Txid::from_byte_array([2; 32])]);
+        let statement = Statement::new(
+            2024,
+            3,
+            APRIL + 100,
+            299_000,
+            509_990,
+            &activity,
+            &funding,
+            &HashSet::new(),
+            // the price doubled in march
+            Some(("usd".to_string(), |time| {
+                if time < MARCH {
+                    None
+                } else if time < APRIL {
+                    Some(100_000.0)
+                } else {
+                    Some(200_000.0)
+                }
+            })),
+        )
+        .unwrap();
+
+        assert_eq!(statement.on_chain.opening_balance, 1_000_000);
+        assert_eq!(statement.on_chain.closing_balance, 499_000);
+        assert_eq!(statement.on_chain.transfers_out, 500_000);
+        assert_eq!(statement.on_chain.fees, 1_000);
+        assert_eq!(statement.lightning.opening_balance, 0);
+        assert_eq!(statement.lightning.closing_balance, 509_990);
+        assert_eq!(statement.lightning.received, 20_000);
+        assert_eq!(statement.lightning.sent, 10_000);
+        assert_eq!(statement.lightning.fees, 10);
+        assert_eq!(statement.total_fees, 1_010);
+        assert_eq!(statement.entries.len(), 3);
+        assert!((statement.entries[1].fiat_value.unwrap() - 20.0).abs() < 0.01);
+
+        let fiat = statement.fiat.as_ref().unwrap();
+        assert_eq!(fiat.opening_price, Some(100_000.0));
+        assert_eq!(fiat.closing_price, Some(200_000.0));
+        assert!((fiat.opening_value.unwrap() - 1_000.0).abs() < 0.01);
+        assert!((fiat.closing_value.unwrap() - 2_017.98).abs() < 0.01);
+
+        let pdf = statement.to_pdf();
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+    }
+}
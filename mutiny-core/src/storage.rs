@@ -1,6 +1,9 @@
-use crate::ldkstorage::CHANNEL_MANAGER_KEY;
+use crate::gossip::{LN_PEER_METADATA_KEY_PREFIX, NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
 use crate::logging::MutinyLogger;
+use crate::logging::LOGGING_KEY;
 use crate::nodemanager::{ChannelClosure, NodeStorage};
+use crate::servicestatus::{list_pending_vss_items, queue_vss_items, remove_pending_vss_items};
 use crate::utils::{now, spawn, DBTasks, Task};
 use crate::vss::{MutinyVssClient, VssKeyValueItem};
 use crate::{
@@ -25,14 +28,19 @@ use lightning::{ln::PaymentHash, util::logger::Logger};
 use lightning::{log_debug, log_trace};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
 pub const SUBSCRIPTION_TIMESTAMP: &str = "subscription_timestamp";
 pub const KEYCHAIN_STORE_KEY: &str = "bdk_keychain";
+/// Keychain store for the native segwit (p2wpkh) receive wallet
+pub const SEGWIT_KEYCHAIN_STORE_KEY: &str = "bdk_keychain_segwit";
 pub const MNEMONIC_KEY: &str = "mnemonic";
 pub(crate) const NEED_FULL_SYNC_KEY: &str = "needs_full_sync";
+pub(crate) const DEFAULT_ADDRESS_TYPE_KEY: &str = "default_address_type";
+pub(crate) const FROZEN_UTXOS_KEY: &str = "frozen_utxos";
+pub(crate) const ACTIVE_ESPLORA_URL_KEY: &str = "active_esplora_url";
 pub const NODES_KEY: &str = "nodes";
 pub const SERVICE_TOKENS: &str = "service_tokens";
 const FEE_ESTIMATES_KEY: &str = "fee_estimates";
@@ -41,11 +49,19 @@ const FIRST_SYNC_KEY: &str = "first_sync";
 pub const LAST_NWC_SYNC_TIME_KEY: &str = "last_nwc_sync_time";
 pub(crate) const DEVICE_ID_KEY: &str = "device_id";
 pub const DEVICE_LOCK_KEY: &str = "device_lock";
+pub const INSTANCE_LOCK_KEY: &str = "instance_lock";
+/// The highest version of each VSS key this device has seen
+pub const VSS_SEEN_VERSIONS_KEY: &str = "vss_seen_versions";
+/// Set once the VSS values written without authenticated data were rewritten
+const VSS_LEGACY_MIGRATED_KEY: &str = "vss_legacy_migrated";
 pub(crate) const EXPECTED_NETWORK_KEY: &str = "network";
 pub const PAYMENT_INBOUND_PREFIX_KEY: &str = "payment_inbound/";
 pub const PAYMENT_OUTBOUND_PREFIX_KEY: &str = "payment_outbound/";
 pub const TRANSACTION_DETAILS_PREFIX_KEY: &str = "transaction_details/";
 pub(crate) const ONCHAIN_PREFIX: &str = "onchain_tx/";
+pub(crate) const TX_REPLACEMENT_PREFIX: &str = "tx_replacement/";
+/// Reverse index of [`TX_REPLACEMENT_PREFIX`], from the replacement to the original
+pub(crate) const TX_REPLACED_PREFIX: &str = "tx_replaced/";
 pub const LAST_DM_SYNC_TIME_KEY: &str = "last_dm_sync_time";
 pub const LAST_HERMES_SYNC_TIME_KEY: &str = "last_hermes_sync_time";
 pub const NOSTR_PROFILE_METADATA: &str = "nostr_profile_metadata";
@@ -69,10 +85,70 @@ impl From<DelayedKeyValueItem> for VssKeyValueItem {
     }
 }
 
+/// A set of writes and deletes that are committed together with
+/// [MutinyStorage::commit_transaction], so related records can't end up half written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageTransaction {
+    writes: Vec<(String, Value, Option<u32>)>,
+    deletes: Vec<String>,
+}
+
+impl StorageTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages a write, like [MutinyStorage::write_data] a later write to the same key wins
+    pub fn write<T>(
+        &mut self,
+        key: impl Into<String>,
+        value: T,
+        version: Option<u32>,
+    ) -> Result<&mut Self, MutinyError>
+    where
+        T: Serialize,
+    {
+        let key = key.into();
+        let value = serde_json::to_value(value).map_err(|e| MutinyError::PersistenceFailed {
+            source: MutinyStorageError::SerdeError { source: e },
+        })?;
+        self.deletes.retain(|k| *k != key);
+        self.writes.retain(|(k, _, _)| *k != key);
+        self.writes.push((key, value, version));
+
+        Ok(self)
+    }
+
+    /// Stages a delete, dropping any staged write to the same key
+    pub fn delete(&mut self, key: impl Into<String>) -> &mut Self {
+        let key = key.into();
+        self.writes.retain(|(k, _, _)| *k != key);
+        if !self.deletes.contains(&key) {
+            self.deletes.push(key);
+        }
+        self
+    }
+
+    /// The value staged for the key, so later steps of a transaction can see earlier writes
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>, MutinyError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        match self.writes.iter().find(|(k, _, _)| k == key) {
+            Some((_, value, _)) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty() && self.deletes.is_empty()
+    }
+}
+
 fn needs_encryption(key: &str) -> bool {
     match key {
         MNEMONIC_KEY => true,
-        KEYCHAIN_STORE_KEY => true,
+        KEYCHAIN_STORE_KEY | SEGWIT_KEYCHAIN_STORE_KEY => true,
         str if str.starts_with(CHANNEL_MANAGER_KEY) => true,
         _ => false,
     }
@@ -114,6 +190,60 @@ pub fn decrypt_value(
     Ok(json)
 }
 
+/// Approximate number of bytes used in storage, grouped by what the data is for.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StorageBreakdown {
+    pub channel_monitors: u64,
+    pub channel_manager: u64,
+    /// Network graph, scorer and peer metadata, these can be re-downloaded
+    pub network_graph: u64,
+    /// Lightning payments and on-chain transaction history
+    pub payment_history: u64,
+    /// On-chain wallet state
+    pub onchain_wallet: u64,
+    pub nostr_cache: u64,
+    pub logs: u64,
+    pub other: u64,
+    pub total: u64,
+}
+
+impl StorageBreakdown {
+    /// Builds the breakdown from the size in bytes of each key
+    pub fn from_sizes(sizes: &HashMap<String, u64>) -> Self {
+        let mut breakdown = Self::default();
+        for (key, size) in sizes {
+            let bucket = if key.starts_with(MONITORS_PREFIX_KEY) {
+                &mut breakdown.channel_monitors
+            } else if key.starts_with(&format!("{CHANNEL_MANAGER_KEY}_")) {
+                &mut breakdown.channel_manager
+            } else if key == NETWORK_GRAPH_KEY
+                || key == PROB_SCORER_KEY
+                || key.starts_with(LN_PEER_METADATA_KEY_PREFIX)
+            {
+                &mut breakdown.network_graph
+            } else if key.starts_with(PAYMENT_INBOUND_PREFIX_KEY)
+                || key.starts_with(PAYMENT_OUTBOUND_PREFIX_KEY)
+                || key.starts_with(TRANSACTION_DETAILS_PREFIX_KEY)
+                || key.starts_with(ONCHAIN_PREFIX)
+            {
+                &mut breakdown.payment_history
+            } else if key == KEYCHAIN_STORE_KEY || key == SEGWIT_KEYCHAIN_STORE_KEY {
+                &mut breakdown.onchain_wallet
+            } else if key.starts_with("nostr") || key == LAST_DM_SYNC_TIME_KEY {
+                &mut breakdown.nostr_cache
+            } else if key == LOGGING_KEY {
+                &mut breakdown.logs
+            } else {
+                &mut breakdown.other
+            };
+            *bucket += size;
+            breakdown.total += size;
+        }
+
+        breakdown
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IndexItem {
     pub timestamp: Option<u64>,
@@ -170,6 +300,26 @@ impl DeviceLock {
     }
 }
 
+/// Heartbeat of the wallet instance that is running on this machine.
+///
+/// Unlike the [DeviceLock] this is only kept in local storage, it is used to detect
+/// the same wallet being opened twice on the same device, e.g. in two browser tabs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceLock {
+    pub time: u32,
+    pub instance: String,
+}
+
+impl InstanceLock {
+    /// Check if another instance is holding the lock
+    /// This is determined by whether it has sent a heartbeat recently
+    pub fn is_locked(&self, id: &str) -> bool {
+        let now = now().as_secs();
+        let diff = now.saturating_sub(self.time as u64);
+        diff < DEVICE_LOCK_INTERVAL_SECS * 2 && self.instance != id
+    }
+}
+
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
@@ -207,10 +357,86 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
                 version,
             };
 
-            vss.put_objects(vec![item]).await
-        } else {
-            Ok(())
+            if let Err(e) = vss.put_objects(vec![item.clone()]).await {
+                // keep it so it is sent once VSS is reachable again
+                queue_vss_items(self, vec![item])?;
+                return Err(e);
+            }
+            self.save_vss_seen_versions()?;
         }
+        Ok(())
+    }
+
+    /// Saves the versions VSS has shown us, if they changed, so a rollback
+    /// is still caught after a restart
+    fn save_vss_seen_versions(&self) -> Result<(), MutinyError> {
+        if let Some(versions) = self.vss_client().and_then(|v| v.take_changed_versions()) {
+            self.write_data(VSS_SEEN_VERSIONS_KEY.to_string(), versions, None)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the VSS values written without authenticated data, once per device
+    async fn migrate_legacy_vss(&self) -> Result<usize, MutinyError> {
+        let Some(vss) = self.vss_client() else {
+            return Ok(0);
+        };
+        if self
+            .get_data::<bool>(VSS_LEGACY_MIGRATED_KEY)?
+            .unwrap_or(false)
+        {
+            return Ok(0);
+        }
+
+        let upgraded = vss.migrate_legacy_items().await?;
+        self.save_vss_seen_versions()?;
+        self.write_data(VSS_LEGACY_MIGRATED_KEY.to_string(), true, None)?;
+
+        Ok(upgraded)
+    }
+
+    /// Sends the items to VSS in the background, keeping them to send again
+    /// later if VSS can't be reached
+    fn spawn_vss_write(&self, vss: Arc<MutinyVssClient>, items: Vec<VssKeyValueItem>) {
+        let db = self.clone();
+        self.spawn(async move {
+            if let Err(e) = vss.put_objects(items.clone()).await {
+                queue_vss_items(&db, items)?;
+                return Err(e);
+            }
+            db.save_vss_seen_versions()
+        });
+    }
+
+    /// Sends the writes VSS couldn't take earlier, skipping the ones VSS already
+    /// has a newer version of. Returns how many were sent.
+    async fn reconcile_vss(&self) -> Result<usize, MutinyError> {
+        let Some(vss) = self.vss_client() else {
+            return Ok(0);
+        };
+        let pending = list_pending_vss_items(self)?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let remote: HashMap<String, u32> = vss
+            .list_key_versions(None)
+            .await?
+            .into_iter()
+            .map(|kv| (kv.key, kv.version))
+            .collect();
+        let items: Vec<VssKeyValueItem> = pending
+            .iter()
+            .filter(|item| remote.get(&item.key).map_or(true, |v| *v < item.version))
+            .cloned()
+            .collect();
+        if !items.is_empty() {
+            vss.put_objects(items.clone()).await?;
+            self.save_vss_seen_versions()?;
+        }
+        remove_pending_vss_items(self, &pending)?;
+
+        Ok(items.len())
     }
 
     /// Set a value in the storage, the function will encrypt the value if needed
@@ -238,6 +464,55 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
         Ok(())
     }
 
+    /// Writes the items and deletes the keys as a single unit, either all of them
+    /// are persisted or none are. Storage backed by a transactional database should
+    /// override this, the default does the writes and then the deletes.
+    fn write_raw_and_delete<T>(
+        &self,
+        items: Vec<(String, T)>,
+        deletes: Vec<String>,
+    ) -> Result<(), MutinyError>
+    where
+        T: Serialize + Send,
+    {
+        self.write_raw(items)?;
+        if !deletes.is_empty() {
+            self.delete(&deletes)?;
+        }
+        Ok(())
+    }
+
+    /// Commits every write and delete of the transaction together, encrypting values
+    /// if needed. The versioned writes are sent to VSS in a single request.
+    fn commit_transaction(&self, tx: StorageTransaction) -> Result<(), MutinyError> {
+        if tx.is_empty() {
+            return Ok(());
+        }
+
+        let mut items = Vec::with_capacity(tx.writes.len());
+        let mut vss_items = vec![];
+        for (key, value, version) in tx.writes {
+            let json = encrypt_value(&key, value.clone(), self.cipher())?;
+            items.push((key.clone(), json));
+            if let Some(version) = version {
+                vss_items.push(VssKeyValueItem {
+                    key,
+                    value,
+                    version,
+                });
+            }
+        }
+        self.write_raw_and_delete(items, tx.deletes)?;
+
+        if let Some(vss) = self.vss_client() {
+            if !vss_items.is_empty() {
+                self.spawn_vss_write(vss, vss_items);
+            }
+        }
+
+        Ok(())
+    }
+
     fn get_delayed_objects(&self) -> Arc<Mutex<HashMap<String, DelayedKeyValueItem>>>;
 
     /// Get a value from the storage, use get_data if you want the value to be decrypted
@@ -269,6 +544,9 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
     /// Stop the storage, this will be called when the application is shutting down
     async fn stop(&self);
 
+    /// Wait for any pending background writes to finish without closing the storage
+    async fn flush(&self) {}
+
     /// Check if the storage is connected
     fn connected(&self) -> Result<bool, MutinyError>;
 
@@ -517,14 +795,80 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
 
     async fn fetch_device_lock(&self) -> Result<Option<DeviceLock>, MutinyError>;
 
+    fn get_instance_lock(&self) -> Result<Option<InstanceLock>, MutinyError> {
+        self.get_data(INSTANCE_LOCK_KEY)
+    }
+
+    /// Takes the instance lock, or sends a heartbeat if we already hold it.
+    /// Returns false if another instance holds it. Storage shared between
+    /// instances must check and write the lock atomically.
+    async fn try_claim_instance_lock(&self, instance: &str) -> Result<bool, MutinyError> {
+        if self
+            .get_instance_lock()?
+            .is_some_and(|lock| lock.is_locked(instance))
+        {
+            return Ok(false);
+        }
+        self.set_instance_lock(instance)?;
+        Ok(true)
+    }
+
+    /// Sends a heartbeat for the given instance, this is never written to VSS
+    fn set_instance_lock(&self, instance: &str) -> Result<(), MutinyError> {
+        let lock = InstanceLock {
+            time: now().as_secs() as u32,
+            instance: instance.to_string(),
+        };
+        self.write_raw(vec![(INSTANCE_LOCK_KEY.to_string(), lock)])
+    }
+
+    /// Releases the instance lock if it is held by the given instance
+    async fn release_instance_lock(&self, instance: &str) -> Result<(), MutinyError> {
+        match self.fetch_instance_lock().await? {
+            Some(lock) if lock.instance == instance => {
+                let lock = InstanceLock { time: 0, ..lock };
+                self.write_raw(vec![(INSTANCE_LOCK_KEY.to_string(), lock)])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the instance lock from the underlying storage so heartbeats
+    /// from other instances that share it are visible.
+    async fn fetch_instance_lock(&self) -> Result<Option<InstanceLock>, MutinyError> {
+        self.get_instance_lock()
+    }
+
+    /// Gets the size in bytes of every value as it is kept in storage (i.e. encrypted)
+    async fn value_sizes(&self) -> Result<HashMap<String, u64>, MutinyError> {
+        let mut sizes = HashMap::new();
+        for key in self.scan_keys("", None)? {
+            if let Some(value) = self.get::<Value>(&key)? {
+                sizes.insert(key, serde_json::to_vec(&value)?.len() as u64);
+            }
+        }
+
+        Ok(sizes)
+    }
+
     /// Write Wallet changeset
     fn write_changes(&self, changeset: &ChangeSet) -> Result<(), MutinyError> {
+        self.write_changes_to(KEYCHAIN_STORE_KEY, changeset)
+    }
+
+    /// Read Wallet changeset
+    fn read_changes(&self) -> Result<Option<ChangeSet>, MutinyError> {
+        self.read_changes_from(KEYCHAIN_STORE_KEY)
+    }
+
+    /// Write the changeset of the wallet stored under the given key
+    fn write_changes_to(&self, key: &str, changeset: &ChangeSet) -> Result<(), MutinyError> {
         if changeset.is_empty() {
             return Ok(());
         }
 
         let version = now().as_secs() as u32;
-        let value = match self.read_changes()? {
+        let value = match self.read_changes_from(key)? {
             Some(mut keychain_store) => {
                 keychain_store.merge(changeset.clone());
                 let value = serde_json::to_value(keychain_store)?;
@@ -535,12 +879,12 @@ pub trait MutinyStorage: Clone + Sized + Send + Sync + 'static {
                 VersionedValue { value, version }
             }
         };
-        self.write_data(KEYCHAIN_STORE_KEY.to_string(), value, Some(version))
+        self.write_data(key.to_string(), value, Some(version))
     }
 
-    /// Read Wallet changeset
-    fn read_changes(&self) -> Result<Option<ChangeSet>, MutinyError> {
-        match self.get_data::<VersionedValue>(KEYCHAIN_STORE_KEY)? {
+    /// Read the changeset of the wallet stored under the given key
+    fn read_changes_from(&self, key: &str) -> Result<Option<ChangeSet>, MutinyError> {
+        match self.get_data::<VersionedValue>(key)? {
             Some(versioned) => {
                 let changeset = serde_json::from_value(versioned.value)?;
                 Ok(Some(changeset))
@@ -585,6 +929,9 @@ impl MemoryStorage {
 
     pub async fn load_from_vss(&self) -> Result<(), MutinyError> {
         if let Some(vss) = self.vss_client() {
+            if let Some(seen) = self.get_data(VSS_SEEN_VERSIONS_KEY)? {
+                vss.restore_seen_versions(seen);
+            }
             let keys = vss.list_key_versions(None).await?;
             let mut items = HashMap::new();
             for key in keys {
@@ -648,6 +995,38 @@ impl MutinyStorage for MemoryStorage {
         Ok(())
     }
 
+    fn write_raw_and_delete<T>(
+        &self,
+        items: Vec<(String, T)>,
+        deletes: Vec<String>,
+    ) -> Result<(), MutinyError>
+    where
+        T: Serialize + Send,
+    {
+        // serialize everything first so a failure doesn't leave a partial write
+        let items = items
+            .into_iter()
+            .map(|(key, value)| {
+                serde_json::to_value(value)
+                    .map_err(|e| MutinyError::PersistenceFailed {
+                        source: MutinyStorageError::SerdeError { source: e },
+                    })
+                    .map(|data| (key, data))
+            })
+            .collect::<Result<Vec<_>, MutinyError>>()?;
+
+        let mut map = self
+            .memory
+            .try_write()
+            .map_err(|e| MutinyError::write_err(e.into()))?;
+        map.extend(items);
+        for key in deletes {
+            map.remove(&key);
+        }
+
+        Ok(())
+    }
+
     fn get<T>(&self, key: impl AsRef<str>) -> Result<Option<T>, MutinyError>
     where
         T: for<'de> Deserialize<'de>,
@@ -904,6 +1283,59 @@ pub(crate) fn get_transaction_details<S: MutinyStorage>(
     }
 }
 
+/// Records that the original transaction was replaced with RBF
+pub(crate) fn persist_tx_replacement<S: MutinyStorage>(
+    storage: &S,
+    original: Txid,
+    replacement: Txid,
+) -> Result<(), MutinyError> {
+    storage.write_data(
+        format!("{TX_REPLACEMENT_PREFIX}{original}"),
+        replacement,
+        None,
+    )?;
+    storage.write_data(format!("{TX_REPLACED_PREFIX}{replacement}"), original, None)
+}
+
+/// Gets the latest transaction that replaced the given one, if it was replaced
+pub(crate) fn get_tx_replacement<S: MutinyStorage>(
+    storage: &S,
+    txid: Txid,
+) -> Result<Option<Txid>, MutinyError> {
+    let mut latest = None;
+    let mut current = txid;
+    // a transaction can't be replaced by one of its own ancestors, but don't trust storage
+    let mut seen = HashSet::from([txid]);
+    while let Some(next) = storage.get_data::<Txid>(format!("{TX_REPLACEMENT_PREFIX}{current}"))? {
+        if !seen.insert(next) {
+            break;
+        }
+        latest = Some(next);
+        current = next;
+    }
+
+    Ok(latest)
+}
+
+/// Gets every transaction that was replaced by the given one, oldest first
+pub(crate) fn get_replaced_txids<S: MutinyStorage>(
+    storage: &S,
+    txid: Txid,
+) -> Result<Vec<Txid>, MutinyError> {
+    let mut replaced = vec![];
+    let mut current = txid;
+    while let Some(original) = storage.get_data::<Txid>(format!("{TX_REPLACED_PREFIX}{current}"))? {
+        if original == txid || replaced.contains(&original) {
+            break;
+        }
+        replaced.push(original);
+        current = original;
+    }
+    replaced.reverse();
+
+    Ok(replaced)
+}
+
 pub(crate) fn payment_key(inbound: bool, payment_hash: &[u8; 32]) -> String {
     if inbound {
         format!("{}{}", PAYMENT_INBOUND_PREFIX_KEY, payment_hash.as_hex())
@@ -917,13 +1349,32 @@ pub(crate) fn persist_payment_info<S: MutinyStorage>(
     payment_hash: &[u8; 32],
     payment_info: &PaymentInfo,
     inbound: bool,
+) -> Result<(), MutinyError> {
+    persist_payment_info_with(
+        storage,
+        StorageTransaction::new(),
+        payment_hash,
+        payment_info,
+        inbound,
+    )
+}
+
+/// Persists the payment in the same transaction as the other changes staged in `tx`,
+/// the activity index is only updated once all of them are committed.
+pub(crate) fn persist_payment_info_with<S: MutinyStorage>(
+    storage: &S,
+    mut tx: StorageTransaction,
+    payment_hash: &[u8; 32],
+    payment_info: &PaymentInfo,
+    inbound: bool,
 ) -> Result<(), MutinyError> {
     let key = payment_key(inbound, payment_hash);
-    storage.write_data(
+    tx.write(
         key.clone(),
-        payment_info.clone(),
+        payment_info,
         Some(payment_info.last_update as u32),
     )?;
+    storage.commit_transaction(tx)?;
 
     // insert into activity index
     match payment_info.status {
@@ -1057,13 +1508,134 @@ pub(crate) fn get_payment_hash_from_key<'a>(key: &'a str, prefix: &str) -> &'a s
 mod tests {
     use crate::test_utils::*;
 
+    use crate::storage::{get_replaced_txids, get_tx_replacement, persist_tx_replacement};
     use crate::{encrypt::encryption_key_from_pass, storage::MemoryStorage};
-    use crate::{keymanager, storage::MutinyStorage};
+    use crate::{keymanager, storage::MutinyStorage, storage::StorageBreakdown};
+    use crate::{storage::StorageTransaction, storage::KEYCHAIN_STORE_KEY};
+    use std::str::FromStr;
 
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
 
+    #[test]
+    async fn test_tx_replacement() {
+        let test_name = "test_tx_replacement";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let original = bitcoin::Txid::from_str(&"11".repeat(32)).unwrap();
+        let first_bump = bitcoin::Txid::from_str(&"22".repeat(32)).unwrap();
+        let second_bump = bitcoin::Txid::from_str(&"33".repeat(32)).unwrap();
+
+        assert_eq!(get_tx_replacement(&storage, original).unwrap(), None);
+        assert!(get_replaced_txids(&storage, original).unwrap().is_empty());
+
+        persist_tx_replacement(&storage, original, first_bump).unwrap();
+        persist_tx_replacement(&storage, first_bump, second_bump).unwrap();
+
+        assert_eq!(
+            get_tx_replacement(&storage, original).unwrap(),
+            Some(second_bump)
+        );
+        assert_eq!(get_tx_replacement(&storage, second_bump).unwrap(), None);
+        assert_eq!(
+            get_replaced_txids(&storage, second_bump).unwrap(),
+            vec![original, first_bump]
+        );
+    }
+
+    #[test]
+    async fn test_claim_instance_lock() {
+        let test_name = "test_claim_instance_lock";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert!(storage.try_claim_instance_lock("first").await.unwrap());
+        // the holder's heartbeat keeps it, nobody else gets it
+        assert!(storage.try_claim_instance_lock("first").await.unwrap());
+        assert!(!storage.try_claim_instance_lock("second").await.unwrap());
+
+        // once it's released the waiting instance takes it over
+        storage.release_instance_lock("first").await.unwrap();
+        assert!(storage.try_claim_instance_lock("second").await.unwrap());
+        assert!(!storage.try_claim_instance_lock("first").await.unwrap());
+    }
+
+    #[test]
+    async fn test_storage_transaction() {
+        let test_name = "test_storage_transaction";
+        log!("{}", test_name);
+
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        storage
+            .write_data("old".to_string(), "stale", None)
+            .unwrap();
+
+        let mut tx = StorageTransaction::new();
+        tx.write("a", 1, None).unwrap();
+        tx.write("b", 2, Some(1)).unwrap();
+        tx.write("a", 3, None).unwrap();
+        tx.write("deleted", 4, None).unwrap();
+        tx.delete("deleted").delete("old");
+        tx.write(KEYCHAIN_STORE_KEY, "secret", None).unwrap();
+        assert_eq!(tx.get::<u32>("a").unwrap(), Some(3));
+        assert_eq!(tx.get::<u32>("deleted").unwrap(), None);
+
+        // nothing is written until the transaction is committed
+        assert_eq!(storage.get_data::<u32>("a").unwrap(), None);
+        storage.commit_transaction(tx).unwrap();
+
+        assert_eq!(storage.get_data::<u32>("a").unwrap(), Some(3));
+        assert_eq!(storage.get_data::<u32>("b").unwrap(), Some(2));
+        assert_eq!(storage.get_data::<u32>("deleted").unwrap(), None);
+        assert_eq!(storage.get_data::<String>("old").unwrap(), None);
+        // values are still encrypted when needed
+        assert_ne!(
+            storage
+                .get::<serde_json::Value>(KEYCHAIN_STORE_KEY)
+                .unwrap(),
+            Some(serde_json::json!("secret"))
+        );
+        assert_eq!(
+            storage.get_data::<String>(KEYCHAIN_STORE_KEY).unwrap(),
+            Some("secret".to_string())
+        );
+    }
+
+    #[test]
+    async fn test_storage_breakdown() {
+        let test_name = "test_storage_breakdown";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        storage
+            .write_data("monitors/txid_0_node".to_string(), "monitor", None)
+            .unwrap();
+        storage
+            .write_data("manager_node".to_string(), "manager", None)
+            .unwrap();
+        storage
+            .write_data("payment_inbound/hash".to_string(), "payment", None)
+            .unwrap();
+        storage
+            .write_data("something_else".to_string(), "other", None)
+            .unwrap();
+
+        let sizes = storage.value_sizes().await.unwrap();
+        assert_eq!(sizes.len(), 4);
+
+        let breakdown = StorageBreakdown::from_sizes(&sizes);
+        assert_eq!(breakdown.channel_monitors, "\"monitor\"".len() as u64);
+        assert_eq!(breakdown.channel_manager, "\"manager\"".len() as u64);
+        assert_eq!(breakdown.payment_history, "\"payment\"".len() as u64);
+        assert_eq!(breakdown.other, "\"other\"".len() as u64);
+        assert_eq!(breakdown.network_graph, 0);
+        assert_eq!(breakdown.total, sizes.values().sum::<u64>());
+    }
+
     #[test]
     async fn insert_and_get_mnemonic_no_password() {
         let test_name = "insert_and_get_mnemonic_no_password";
@@ -0,0 +1,429 @@
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::storage::MutinyStorage;
+use crate::DUST_LIMIT;
+use bitcoin::absolute::LockTime;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::opcodes::all::{
+    OP_CHECKSIG, OP_CLTV, OP_DROP, OP_ELSE, OP_ENDIF, OP_EQUAL, OP_IF, OP_SHA256,
+};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{All, Message, PublicKey, Secp256k1, SecretKey, Signing};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+pub(crate) const SWAP_DEPOSIT_PREFIX: &str = "swap_deposit/";
+/// The next refund key index to hand out
+pub(crate) const SWAP_REFUND_KEY_INDEX_KEY: &str = "swap_refund_key_index";
+
+/// Held while a refund key index is handed out, so it can't be handed out twice
+static REFUND_KEY_INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// An on-chain deposit made to a swap provider to receive the funds over lightning.
+///
+/// The deposit is locked to a script the provider can only claim with the preimage
+/// of our invoice, so they have to pay us first. If they never do, we can take the
+/// funds back with our refund key once the timeout height has been reached.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SwapDeposit {
+    pub id: String,
+    /// The invoice the swap provider has to pay to complete the swap
+    pub invoice: String,
+    pub payment_hash: sha256::Hash,
+    pub provider_pubkey: PublicKey,
+    pub refund_pubkey: PublicKey,
+    /// Index of the refund key, used to derive it again when refunding
+    pub refund_key_index: u32,
+    /// Block height after which the deposit can be refunded
+    pub timeout_height: u32,
+    pub address: String,
+    pub amount_sats: u64,
+    #[serde(default)]
+    pub funding_txid: Option<Txid>,
+    #[serde(default)]
+    pub funding_vout: Option<u32>,
+    #[serde(default)]
+    pub refund_txid: Option<Txid>,
+    pub status: SwapDepositStatus,
+    /// The last error we got trying to refund the deposit
+    #[serde(default)]
+    pub error: Option<String>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SwapDepositStatus {
+    /// Waiting for the swap provider to pay our invoice
+    Pending,
+    /// The swap provider paid our invoice
+    Completed,
+    /// The swap timed out and the refund transaction was broadcast,
+    /// waiting for it to confirm
+    Refunding,
+    /// The refund transaction confirmed
+    Refunded,
+}
+
+impl SwapDeposit {
+    /// Label used for the deposit and refund transactions so they
+    /// can be tied back to the swap in the activity
+    pub fn label(&self) -> String {
+        format!("{SWAP_DEPOSIT_PREFIX}{}", self.id)
+    }
+
+    pub fn funding_outpoint(&self) -> Option<OutPoint> {
+        Some(OutPoint::new(self.funding_txid?, self.funding_vout?))
+    }
+
+    pub fn script(&self) -> ScriptBuf {
+        deposit_script(
+            &self.payment_hash,
+            &self.provider_pubkey,
+            &self.refund_pubkey,
+            self.timeout_height,
+        )
+    }
+}
+
+/// The script the deposit is locked to:
+/// the provider can claim with the preimage, or we can refund after the timeout.
+pub fn deposit_script(
+    payment_hash: &sha256::Hash,
+    provider_pubkey: &PublicKey,
+    refund_pubkey: &PublicKey,
+    timeout_height: u32,
+) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(OP_SHA256)
+        .push_slice(payment_hash.as_byte_array())
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_IF)
+        .push_key(&bitcoin::PublicKey::new(*provider_pubkey))
+        .push_opcode(OP_ELSE)
+        .push_int(timeout_height as i64)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_key(&bitcoin::PublicKey::new(*refund_pubkey))
+        .push_opcode(OP_ENDIF)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+pub fn deposit_address(script: &ScriptBuf, network: Network) -> Address {
+    Address::p2wsh(script, network)
+}
+
+/// Derives the key used to refund the deposit with the given index
+pub(crate) fn derive_refund_key(
+    secp: &Secp256k1<All>,
+    xprivkey: Xpriv,
+    index: u32,
+) -> Result<SecretKey, MutinyError> {
+    let swap_key = create_root_child_key(secp, xprivkey, ChildKey::SwapRefund)?;
+    let child = swap_key.derive_priv(
+        secp,
+        &DerivationPath::from(vec![ChildNumber::from_hardened_idx(index)?]),
+    )?;
+
+    Ok(child.private_key)
+}
+
+/// Builds and signs the transaction taking the deposit back to the destination
+pub(crate) fn build_refund_tx<C: Signing>(
+    secp: &Secp256k1<C>,
+    deposit: &SwapDeposit,
+    refund_key: &SecretKey,
+    destination: ScriptBuf,
+    fee_rate: u64,
+) -> Result<Transaction, MutinyError> {
+    let outpoint = deposit
+        .funding_outpoint()
+        .ok_or(MutinyError::WalletOperationFailed)?;
+    let script = deposit.script();
+    let lock_time = LockTime::from_height(deposit.timeout_height)
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            // must not be final for the timelock to be enforced
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: destination,
+        }],
+    };
+
+    // size the fee with a worst case signature
+    tx.input[0].witness = Witness::from_slice(&[vec![0; 73], vec![], script.to_bytes()]);
+    let fee = tx.vsize() as u64 * fee_rate;
+    let value = deposit.amount_sats.saturating_sub(fee);
+    if value < DUST_LIMIT {
+        return Err(MutinyError::InsufficientBalance);
+    }
+    tx.output[0].value = Amount::from_sat(value);
+
+    let sighash = SighashCache::new(&tx)
+        .p2wsh_signature_hash(
+            0,
+            &script,
+            Amount::from_sat(deposit.amount_sats),
+            EcdsaSighashType::All,
+        )
+        .map_err(|_| MutinyError::WalletSigningFailed)?;
+    let signature = bitcoin::ecdsa::Signature::sighash_all(
+        secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), refund_key),
+    );
+
+    // the empty element takes the refund branch of the script
+    tx.input[0].witness = Witness::from_slice(&[signature.to_vec(), vec![], script.to_bytes()]);
+
+    Ok(tx)
+}
+
+fn swap_deposit_key(id: &str) -> String {
+    format!("{SWAP_DEPOSIT_PREFIX}{id}")
+}
+
+pub(crate) fn persist_swap_deposit<S: MutinyStorage>(
+    storage: &S,
+    deposit: &SwapDeposit,
+) -> Result<(), MutinyError> {
+    storage.write_data(swap_deposit_key(&deposit.id), deposit, None)
+}
+
+pub(crate) fn get_swap_deposit<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<SwapDeposit>, MutinyError> {
+    storage.get_data(swap_deposit_key(id))
+}
+
+/// Hands out the index of the next refund key. The counter only goes up, so
+/// a key is never reused even if a deposit is deleted.
+pub(crate) fn next_refund_key_index<S: MutinyStorage>(storage: &S) -> Result<u32, MutinyError> {
+    let _lock = REFUND_KEY_INDEX_LOCK.lock().unwrap();
+    let index = match storage.get_data::<u32>(SWAP_REFUND_KEY_INDEX_KEY)? {
+        Some(index) => index,
+        // deposits made before the counter existed used their position as the index
+        None => list_swap_deposits(storage)?
+            .iter()
+            .map(|d| d.refund_key_index + 1)
+            .max()
+            .unwrap_or(0),
+    };
+    let next = index
+        .checked_add(1)
+        .ok_or(MutinyError::WalletOperationFailed)?;
+    storage.write_data(SWAP_REFUND_KEY_INDEX_KEY.to_string(), next, None)?;
+
+    Ok(index)
+}
+
+/// Lists all swap deposits, newest first
+pub(crate) fn list_swap_deposits<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<SwapDeposit>, MutinyError> {
+    let mut deposits: Vec<SwapDeposit> = storage
+        .scan::<SwapDeposit>(SWAP_DEPOSIT_PREFIX, None)?
+        .into_values()
+        .collect();
+    deposits.sort_by_key(|d| std::cmp::Reverse(d.created_at));
+
+    Ok(deposits)
+}
+
+/// Marks the pending swap deposit for the given payment hash as completed,
+/// returns the deposit if there was one.
+pub(crate) fn mark_swap_deposit_completed<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Result<Option<SwapDeposit>, MutinyError> {
+    let Some(mut deposit) = list_swap_deposits(storage)?.into_iter().find(|d| {
+        d.status == SwapDepositStatus::Pending && d.payment_hash.as_byte_array() == payment_hash
+    }) else {
+        return Ok(None);
+    };
+
+    deposit.status = SwapDepositStatus::Completed;
+    deposit.error = None;
+    persist_swap_deposit(storage, &deposit)?;
+
+    Ok(Some(deposit))
+}
+
+/// Pending deposits whose timeout has been reached at the given height
+pub(crate) fn refundable_swap_deposits<S: MutinyStorage>(
+    storage: &S,
+    height: u32,
+) -> Result<Vec<SwapDeposit>, MutinyError> {
+    Ok(list_swap_deposits(storage)?
+        .into_iter()
+        .filter(|d| {
+            d.status == SwapDepositStatus::Pending
+                && d.funding_outpoint().is_some()
+                && height >= d.timeout_height
+        })
+        .collect())
+}
+
+/// Deposits whose refund was broadcast but hasn't confirmed yet
+pub(crate) fn unconfirmed_swap_refunds<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<SwapDeposit>, MutinyError> {
+    Ok(list_swap_deposits(storage)?
+        .into_iter()
+        .filter(|d| d.status == SwapDepositStatus::Refunding && d.refund_txid.is_some())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::ecdsa::Signature;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_deposit(secp: &Secp256k1<All>, refund_key: &SecretKey) -> SwapDeposit {
+        let provider_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        SwapDeposit {
+            id: "test".to_string(),
+            invoice: "lnbc".to_string(),
+            payment_hash: sha256::Hash::hash(&[3; 32]),
+            provider_pubkey: PublicKey::from_secret_key(secp, &provider_key),
+            refund_pubkey: PublicKey::from_secret_key(secp, refund_key),
+            refund_key_index: 0,
+            timeout_height: 800_000,
+            address: "".to_string(),
+            amount_sats: 100_000,
+            funding_txid: Some(
+                Txid::from_str("0000000000000000000000000000000000000000000000000000000000000001")
+                    .unwrap(),
+            ),
+            funding_vout: Some(1),
+            refund_txid: None,
+            status: SwapDepositStatus::Pending,
+            error: None,
+            created_at: 1,
+        }
+    }
+
+    #[test]
+    fn test_build_refund_tx() {
+        let test_name = "test_build_refund_tx";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let refund_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let deposit = create_deposit(&secp, &refund_key);
+        let destination = ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros());
+
+        let tx = build_refund_tx(&secp, &deposit, &refund_key, destination.clone(), 2).unwrap();
+        assert_eq!(tx.lock_time, LockTime::from_height(800_000).unwrap());
+        assert_eq!(
+            tx.input[0].previous_output,
+            deposit.funding_outpoint().unwrap()
+        );
+        assert_eq!(tx.output[0].script_pubkey, destination);
+        let fee = deposit.amount_sats - tx.output[0].value.to_sat();
+        assert!(fee >= tx.vsize() as u64 * 2);
+
+        // the witness signs with the refund key and takes the timeout branch
+        let witness: Vec<&[u8]> = tx.input[0].witness.iter().collect();
+        assert_eq!(witness.len(), 3);
+        assert!(witness[1].is_empty());
+        assert_eq!(witness[2], deposit.script().as_bytes());
+
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(
+                0,
+                &deposit.script(),
+                Amount::from_sat(deposit.amount_sats),
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = Signature::from_der(&witness[0][..witness[0].len() - 1]).unwrap();
+        secp.verify_ecdsa(
+            &Message::from_digest(sighash.to_byte_array()),
+            &sig,
+            &deposit.refund_pubkey,
+        )
+        .unwrap();
+
+        // can't refund if the fees eat the whole deposit
+        assert!(build_refund_tx(&secp, &deposit, &refund_key, destination, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_swap_deposit_storage() {
+        let test_name = "test_swap_deposit_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let secp = Secp256k1::new();
+        let refund_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let deposit = create_deposit(&secp, &refund_key);
+        persist_swap_deposit(&storage, &deposit).unwrap();
+
+        assert!(refundable_swap_deposits(&storage, 799_999)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            refundable_swap_deposits(&storage, 800_000).unwrap(),
+            vec![deposit.clone()]
+        );
+
+        assert_eq!(
+            mark_swap_deposit_completed(&storage, &[4; 32]).unwrap(),
+            None
+        );
+        let completed = mark_swap_deposit_completed(&storage, deposit.payment_hash.as_byte_array())
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.status, SwapDepositStatus::Completed);
+        assert_eq!(get_swap_deposit(&storage, "test").unwrap(), Some(completed));
+
+        // completed swaps are never refunded
+        assert!(refundable_swap_deposits(&storage, 900_000)
+            .unwrap()
+            .is_empty());
+        assert!(unconfirmed_swap_refunds(&storage).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_refund_key_index() {
+        let test_name = "test_next_refund_key_index";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let secp = Secp256k1::new();
+        let refund_key = SecretKey::from_slice(&[1; 32]).unwrap();
+
+        // picks up after the deposits made before the counter existed
+        let mut deposit = create_deposit(&secp, &refund_key);
+        deposit.refund_key_index = 2;
+        persist_swap_deposit(&storage, &deposit).unwrap();
+        assert_eq!(next_refund_key_index(&storage).unwrap(), 3);
+
+        // deleting a deposit doesn't hand out its index again
+        storage.delete(&[swap_deposit_key(&deposit.id)]).unwrap();
+        assert_eq!(next_refund_key_index(&storage).unwrap(), 4);
+        assert_eq!(next_refund_key_index(&storage).unwrap(), 5);
+    }
+}
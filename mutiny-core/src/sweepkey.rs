@@ -0,0 +1,320 @@
+use crate::error::MutinyError;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Keypair, TapTweak};
+use bitcoin::script::PushBytesBuf;
+use bitcoin::secp256k1::{Message, Secp256k1, Signing, Verification};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    ecdsa, taproot, Amount, CompressedPublicKey, OutPoint, PrivateKey, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Witness,
+};
+use esplora_client::AsyncClient;
+use std::collections::HashSet;
+
+/// Esplora returns confirmed transactions in pages of this size
+const ESPLORA_PAGE_SIZE: usize = 25;
+
+/// The kinds of scripts a single private key can receive to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyScript {
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+    P2tr,
+}
+
+/// A coin locked to the private key being swept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyUtxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub kind: KeyScript,
+}
+
+/// All the scripts the key could have been paid to. Uncompressed keys
+/// can only be used with p2pkh.
+pub(crate) fn key_scripts<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    key: &PrivateKey,
+) -> Vec<(KeyScript, ScriptBuf)> {
+    let pubkey = key.public_key(secp);
+    let mut scripts = vec![(
+        KeyScript::P2pkh,
+        ScriptBuf::new_p2pkh(&pubkey.pubkey_hash()),
+    )];
+
+    if let Ok(compressed) = CompressedPublicKey::try_from(pubkey) {
+        let wpkh = ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash());
+        scripts.push((
+            KeyScript::P2shP2wpkh,
+            ScriptBuf::new_p2sh(&wpkh.script_hash()),
+        ));
+        scripts.push((KeyScript::P2wpkh, wpkh));
+        let (xonly, _) = key.inner.x_only_public_key(secp);
+        scripts.push((KeyScript::P2tr, ScriptBuf::new_p2tr(secp, xonly, None)));
+    }
+
+    scripts
+}
+
+/// Looks up the unspent outputs of each script through esplora.
+/// Any transaction spending one of the script's outputs is in the
+/// script's own history, so we don't need an outspend lookup per output.
+pub(crate) async fn find_key_utxos(
+    esplora: &AsyncClient,
+    scripts: &[(KeyScript, ScriptBuf)],
+) -> Result<Vec<KeyUtxo>, MutinyError> {
+    let mut utxos = vec![];
+    for (kind, script) in scripts {
+        let mut txs = vec![];
+        let mut last_seen: Option<Txid> = None;
+        loop {
+            let page = esplora.scripthash_txs(script, last_seen).await?;
+            let confirmed = page
+                .iter()
+                .filter(|tx| tx.status.confirmed)
+                .map(|tx| tx.txid)
+                .collect::<Vec<_>>();
+            txs.extend(page);
+            if confirmed.len() < ESPLORA_PAGE_SIZE {
+                break;
+            }
+            last_seen = confirmed.last().copied();
+        }
+
+        let spent: HashSet<OutPoint> = txs
+            .iter()
+            .flat_map(|tx| tx.vin.iter().map(|i| OutPoint::new(i.txid, i.vout)))
+            .collect();
+
+        for tx in txs.iter() {
+            for (vout, output) in tx.vout.iter().enumerate() {
+                let outpoint = OutPoint::new(tx.txid, vout as u32);
+                if output.scriptpubkey == *script
+                    && !spent.contains(&outpoint)
+                    && !utxos.iter().any(|u: &KeyUtxo| u.outpoint == outpoint)
+                {
+                    utxos.push(KeyUtxo {
+                        outpoint,
+                        txout: TxOut {
+                            value: Amount::from_sat(output.value),
+                            script_pubkey: output.scriptpubkey.clone(),
+                        },
+                        kind: *kind,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(utxos)
+}
+
+/// Builds and signs a transaction sending all the given coins to the destination.
+///
+/// The transaction is signed once to measure its size and then again with
+/// the final fee, each input is padded by a vbyte since ECDSA signatures
+/// can be a byte longer than the ones we measured with.
+pub(crate) fn build_sweep_tx<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    key: &PrivateKey,
+    utxos: &[KeyUtxo],
+    destination: ScriptBuf,
+    sats_per_vbyte: u64,
+) -> Result<Transaction, MutinyError> {
+    if utxos.is_empty() {
+        return Err(MutinyError::InsufficientBalance);
+    }
+    let total: u64 = utxos.iter().map(|u| u.txout.value.to_sat()).sum();
+
+    let measure = sign_sweep_tx(secp, key, utxos, destination.clone(), total)?;
+    let vsize = measure.vsize() as u64 + utxos.len() as u64;
+    let fee = vsize * sats_per_vbyte;
+
+    let amount = total.saturating_sub(fee);
+    if amount < destination.minimal_non_dust().to_sat() {
+        return Err(MutinyError::InsufficientBalance);
+    }
+
+    sign_sweep_tx(secp, key, utxos, destination, amount)
+}
+
+fn sign_sweep_tx<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    key: &PrivateKey,
+    utxos: &[KeyUtxo],
+    destination: ScriptBuf,
+    amount: u64,
+) -> Result<Transaction, MutinyError> {
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: utxos
+            .iter()
+            .map(|u| TxIn {
+                previous_output: u.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: destination,
+        }],
+    };
+
+    let pubkey = key.public_key(secp);
+    let prevouts = utxos.iter().map(|u| u.txout.clone()).collect::<Vec<_>>();
+    let mut signatures = Vec::with_capacity(utxos.len());
+    {
+        let mut cache = SighashCache::new(&tx);
+        for (index, utxo) in utxos.iter().enumerate() {
+            let sig = match utxo.kind {
+                KeyScript::P2pkh => {
+                    let hash = cache
+                        .legacy_signature_hash(
+                            index,
+                            &utxo.txout.script_pubkey,
+                            EcdsaSighashType::All.to_u32(),
+                        )
+                        .map_err(|_| MutinyError::WalletSigningFailed)?;
+                    let signature = ecdsa::Signature {
+                        signature: secp
+                            .sign_ecdsa(&Message::from_digest(hash.to_byte_array()), &key.inner),
+                        sighash_type: EcdsaSighashType::All,
+                    };
+                    let script_sig = ScriptBuf::builder()
+                        .push_slice(signature.serialize())
+                        .push_key(&pubkey)
+                        .into_script();
+                    (script_sig, Witness::new())
+                }
+                KeyScript::P2wpkh | KeyScript::P2shP2wpkh => {
+                    let compressed = CompressedPublicKey::try_from(pubkey)
+                        .map_err(|_| MutinyError::WalletSigningFailed)?;
+                    let wpkh = ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash());
+                    let hash = cache
+                        .p2wpkh_signature_hash(
+                            index,
+                            &wpkh,
+                            utxo.txout.value,
+                            EcdsaSighashType::All,
+                        )
+                        .map_err(|_| MutinyError::WalletSigningFailed)?;
+                    let signature = ecdsa::Signature {
+                        signature: secp
+                            .sign_ecdsa(&Message::from_digest(hash.to_byte_array()), &key.inner),
+                        sighash_type: EcdsaSighashType::All,
+                    };
+                    let script_sig = if utxo.kind == KeyScript::P2shP2wpkh {
+                        let redeem_script = PushBytesBuf::try_from(wpkh.to_bytes())
+                            .map_err(|_| MutinyError::WalletSigningFailed)?;
+                        ScriptBuf::builder().push_slice(redeem_script).into_script()
+                    } else {
+                        ScriptBuf::new()
+                    };
+                    (script_sig, Witness::p2wpkh(&signature, &compressed.0))
+                }
+                KeyScript::P2tr => {
+                    let hash = cache
+                        .taproot_key_spend_signature_hash(
+                            index,
+                            &Prevouts::All(&prevouts),
+                            TapSighashType::Default,
+                        )
+                        .map_err(|_| MutinyError::WalletSigningFailed)?;
+                    let keypair = Keypair::from_secret_key(secp, &key.inner)
+                        .tap_tweak(secp, None)
+                        .to_inner();
+                    let signature = taproot::Signature {
+                        signature: secp.sign_schnorr_no_aux_rand(
+                            &Message::from_digest(hash.to_byte_array()),
+                            &keypair,
+                        ),
+                        sighash_type: TapSighashType::Default,
+                    };
+                    (ScriptBuf::new(), Witness::p2tr_key_spend(&signature))
+                }
+            };
+            signatures.push(sig);
+        }
+    }
+
+    for (input, (script_sig, witness)) in tx.input.iter_mut().zip(signatures) {
+        input.script_sig = script_sig;
+        input.witness = witness;
+    }
+
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::Network;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_build_sweep_tx() {
+        let test_name = "test_build_sweep_tx";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let key =
+            PrivateKey::from_wif("cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy").unwrap();
+        assert_eq!(key.network, Network::Testnet.into());
+
+        let scripts = key_scripts(&secp, &key);
+        assert_eq!(scripts.len(), 4);
+
+        let utxos = scripts
+            .iter()
+            .enumerate()
+            .map(|(i, (kind, script))| KeyUtxo {
+                outpoint: OutPoint::new(
+                    Txid::from_str(
+                        "e45b7b7a879f13a82138153fcd3dc3f5b35af1e217f4128e9bc4f75aa049a90b",
+                    )
+                    .unwrap(),
+                    i as u32,
+                ),
+                txout: TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: script.clone(),
+                },
+                kind: *kind,
+            })
+            .collect::<Vec<_>>();
+
+        let destination = scripts[2].1.clone();
+        let tx = build_sweep_tx(&secp, &key, &utxos, destination.clone(), 2).unwrap();
+        assert_eq!(tx.input.len(), 4);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, destination);
+
+        // every input is signed
+        assert!(!tx.input[0].script_sig.is_empty());
+        assert!(!tx.input[1].script_sig.is_empty());
+        assert!(!tx.input[1].witness.is_empty());
+        assert!(tx.input[2].script_sig.is_empty());
+        assert_eq!(tx.input[2].witness.len(), 2);
+        assert_eq!(tx.input[3].witness.len(), 1);
+
+        // pays at least the fee rate without overpaying much
+        let fee = 40_000 - tx.output[0].value.to_sat();
+        let vsize = tx.vsize() as u64;
+        assert!(fee >= vsize * 2);
+        assert!(fee <= (vsize + 4) * 2);
+
+        // not enough to cover the fee
+        assert!(build_sweep_tx(&secp, &key, &utxos[..1], destination, 100).is_err());
+        assert!(build_sweep_tx(&secp, &key, &[], scripts[0].1.clone(), 1).is_err());
+    }
+}
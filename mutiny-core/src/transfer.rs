@@ -0,0 +1,331 @@
+//! Moving a whole wallet to another Mutiny user, found by their npub.
+//!
+//! The two wallets agree on how over NIP-04 direct messages between their nostr
+//! identities: the sender asks for a transfer saying how much it has on
+//! lightning and whether it has on-chain funds, the recipient answers with an
+//! invoice for the lightning part and an address for the on-chain part, or
+//! declines. Wallets sharing a federation could move ecash between them instead,
+//! but this wallet doesn't join federations so lightning and on-chain are used.
+//!
+//! Every step is saved on the transfer so the UI can show how far it got and
+//! why it stopped.
+
+use crate::error::MutinyError;
+use crate::nostr::{
+    fetch_from_relay, nip04_decrypt, nip04_encrypt, sign_event, verify_event, ENCRYPTED_DM_KIND,
+};
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::secp256k1::{Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::Txid;
+use hex_conservative::DisplayHex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub(crate) const TRANSFER_PREFIX: &str = "transfer/";
+/// Time of the newest transfer message we've handled, so they aren't fetched again
+pub(crate) const TRANSFER_MESSAGES_SINCE_KEY: &str = "transfer_messages_since";
+/// How long the recipient has to answer a transfer request
+pub const TRANSFER_ANSWER_TIMEOUT_SECS: u64 = 120;
+/// How often we look for answers and for requests sent to us
+pub(crate) const TRANSFER_POLL_INTERVAL_SECS: u64 = 10;
+/// Share of the lightning balance left behind for routing fees, in percent
+const LIGHTNING_FEE_BUFFER_PERCENT: u64 = 1;
+/// Least left behind for routing fees
+const MIN_LIGHTNING_FEE_BUFFER_SATS: u64 = 10;
+
+/// The direct messages the two wallets send each other
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferMessage {
+    TransferRequest {
+        id: String,
+        /// What will be sent over lightning, 0 if nothing
+        lightning_sats: u64,
+        /// Whether there are on-chain funds to sweep
+        onchain: bool,
+    },
+    TransferAccept {
+        id: String,
+        /// Invoice for the lightning part, if the recipient could make one
+        invoice: Option<String>,
+        /// Address for the on-chain part
+        address: Option<String>,
+    },
+    TransferDecline {
+        id: String,
+        reason: String,
+    },
+}
+
+impl TransferMessage {
+    fn id(&self) -> &str {
+        match self {
+            Self::TransferRequest { id, .. } => id,
+            Self::TransferAccept { id, .. } => id,
+            Self::TransferDecline { id, .. } => id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TransferStatus {
+    /// Waiting for the recipient to answer
+    Requested,
+    /// We answered a request sent to us and are waiting to be paid
+    Accepted,
+    /// Sending the funds
+    Paying,
+    Completed,
+    /// See `failure` for why, parts that went through are still recorded
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Transfer {
+    pub id: String,
+    /// Hex encoded nostr pubkey of the other wallet
+    pub counterparty: String,
+    /// Whether we're the one sending the funds
+    pub outgoing: bool,
+    pub lightning_sats: u64,
+    pub onchain: bool,
+    pub status: TransferStatus,
+    /// Invoice the lightning part is paid to
+    pub invoice: Option<String>,
+    /// Address the on-chain part is swept to
+    pub address: Option<String>,
+    /// Set once the lightning payment succeeded
+    pub lightning_paid: bool,
+    pub txid: Option<Txid>,
+    pub failure: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Transfer {
+    pub(crate) fn new_outgoing(
+        counterparty: &XOnlyPublicKey,
+        lightning_sats: u64,
+        onchain: bool,
+    ) -> Self {
+        let id: [u8; 16] = bitcoin::secp256k1::rand::random();
+        let now = utils::now().as_secs();
+        Self {
+            id: id.to_lower_hex_string(),
+            counterparty: counterparty.to_string(),
+            outgoing: true,
+            lightning_sats,
+            onchain,
+            status: TransferStatus::Requested,
+            invoice: None,
+            address: None,
+            lightning_paid: false,
+            txid: None,
+            failure: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub(crate) fn request(&self) -> TransferMessage {
+        TransferMessage::TransferRequest {
+            id: self.id.clone(),
+            lightning_sats: self.lightning_sats,
+            onchain: self.onchain,
+        }
+    }
+
+    /// Records a failure, the parts that already went through are kept
+    pub(crate) fn fail(&mut self, reason: impl ToString) {
+        self.status = TransferStatus::Failed;
+        self.failure = Some(reason.to_string());
+    }
+}
+
+/// How much of the spendable lightning balance to send, leaving enough behind
+/// to pay the routing fees
+pub(crate) fn lightning_transfer_amount(spendable_sats: u64) -> u64 {
+    let buffer =
+        (spendable_sats * LIGHTNING_FEE_BUFFER_PERCENT / 100).max(MIN_LIGHTNING_FEE_BUFFER_SATS);
+    spendable_sats.saturating_sub(buffer)
+}
+
+/// Creates a signed direct message event carrying the transfer message
+pub(crate) fn create_transfer_dm(
+    key: &SecretKey,
+    to: &XOnlyPublicKey,
+    message: &TransferMessage,
+) -> Result<Value, MutinyError> {
+    let content = nip04_encrypt(key, to, &serde_json::to_string(message)?);
+    Ok(sign_event(
+        key,
+        ENCRYPTED_DM_KIND,
+        json!([["p", to.to_string()]]),
+        content,
+    ))
+}
+
+/// Verifies and decrypts a transfer direct message sent to us, returning the
+/// sender, the message and when it was sent
+pub(crate) fn read_transfer_dm(
+    key: &SecretKey,
+    event: &Value,
+) -> Result<(XOnlyPublicKey, TransferMessage, u64), MutinyError> {
+    let sender = verify_event(event)?;
+    let kind = event.get("kind").and_then(|k| k.as_u64());
+    let created_at = event.get("created_at").and_then(|c| c.as_u64());
+    let content = event.get("content").and_then(|c| c.as_str());
+    let (Some(ENCRYPTED_DM_KIND), Some(created_at), Some(content)) = (kind, created_at, content)
+    else {
+        return Err(MutinyError::NostrError);
+    };
+
+    let plaintext = nip04_decrypt(key, &sender, content)?;
+    Ok((sender, serde_json::from_str(&plaintext)?, created_at))
+}
+
+/// Fetches the transfer messages sent to the key since the given time. Other
+/// direct messages are ignored.
+pub(crate) async fn fetch_transfer_dms(
+    key: &SecretKey,
+    relays: &[String],
+    since: u64,
+) -> Result<Vec<(XOnlyPublicKey, TransferMessage, u64)>, MutinyError> {
+    let secp = Secp256k1::new();
+    let pubkey = key.x_only_public_key(&secp).0;
+    let filter = json!({
+        "kinds": [ENCRYPTED_DM_KIND],
+        "#p": [pubkey.to_string()],
+        "since": since,
+    });
+
+    let mut messages = vec![];
+    let mut reached_relay = false;
+    for relay in relays {
+        let Ok(events) = fetch_from_relay(relay, &filter, key).await else {
+            continue;
+        };
+        reached_relay = true;
+        for event in events {
+            if let Ok(message) = read_transfer_dm(key, &event) {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+        }
+    }
+
+    if !reached_relay {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    Ok(messages)
+}
+
+/// The recipient's answer to our transfer request, if there is one yet
+pub(crate) fn find_answer(
+    transfer: &Transfer,
+    messages: &[(XOnlyPublicKey, TransferMessage, u64)],
+) -> Option<TransferMessage> {
+    messages
+        .iter()
+        .filter(|(sender, message, _)| {
+            sender.to_string() == transfer.counterparty
+                && message.id() == transfer.id
+                && !matches!(message, TransferMessage::TransferRequest { .. })
+        })
+        .map(|(_, message, _)| message.clone())
+        .next()
+}
+
+fn transfer_key(id: &str) -> String {
+    format!("{TRANSFER_PREFIX}{id}")
+}
+
+pub(crate) fn persist_transfer<S: MutinyStorage>(
+    storage: &S,
+    transfer: &mut Transfer,
+) -> Result<(), MutinyError> {
+    transfer.updated_at = utils::now().as_secs();
+    storage.write_data(transfer_key(&transfer.id), &*transfer, None)
+}
+
+pub(crate) fn get_transfer<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<Transfer>, MutinyError> {
+    storage.get_data(transfer_key(id))
+}
+
+/// Lists the transfers we sent and received, newest first
+pub(crate) fn list_transfers<S: MutinyStorage>(storage: &S) -> Result<Vec<Transfer>, MutinyError> {
+    let mut transfers: Vec<Transfer> = storage
+        .scan::<Transfer>(TRANSFER_PREFIX, None)?
+        .into_values()
+        .collect();
+    transfers.sort_by_key(|t| std::cmp::Reverse(t.created_at));
+
+    Ok(transfers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_lightning_transfer_amount() {
+        let test_name = "test_lightning_transfer_amount";
+        log!("{}", test_name);
+
+        assert_eq!(lightning_transfer_amount(0), 0);
+        assert_eq!(lightning_transfer_amount(5), 0);
+        assert_eq!(lightning_transfer_amount(500), 490);
+        assert_eq!(lightning_transfer_amount(100_000), 99_000);
+    }
+
+    #[test]
+    fn test_transfer_messages() {
+        let test_name = "test_transfer_messages";
+        log!("{}", test_name);
+
+        let secp = Secp256k1::new();
+        let sender_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let recipient_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let sender = sender_key.x_only_public_key(&secp).0;
+        let recipient = recipient_key.x_only_public_key(&secp).0;
+
+        let storage = MemoryStorage::default();
+        let mut transfer = Transfer::new_outgoing(&recipient, 10_000, true);
+        persist_transfer(&storage, &mut transfer).unwrap();
+        assert_eq!(list_transfers(&storage).unwrap(), vec![transfer.clone()]);
+
+        let event = create_transfer_dm(&sender_key, &recipient, &transfer.request()).unwrap();
+        let (from, message, _) = read_transfer_dm(&recipient_key, &event).unwrap();
+        assert_eq!(from, sender);
+        assert_eq!(message, transfer.request());
+        // only the recipient can read it
+        assert!(read_transfer_dm(&SecretKey::from_slice(&[3; 32]).unwrap(), &event).is_err());
+
+        let accept = TransferMessage::TransferAccept {
+            id: transfer.id.clone(),
+            invoice: None,
+            address: Some("bcrt1qexample".to_string()),
+        };
+        let other = TransferMessage::TransferDecline {
+            id: "someone else's".to_string(),
+            reason: "no".to_string(),
+        };
+        let mut messages = vec![(recipient, other, 1)];
+        assert_eq!(find_answer(&transfer, &messages), None);
+        // the answer has to come from the recipient
+        messages.push((sender, accept.clone(), 2));
+        assert_eq!(find_answer(&transfer, &messages), None);
+        messages.push((recipient, accept.clone(), 3));
+        assert_eq!(find_answer(&transfer, &messages), Some(accept));
+    }
+}
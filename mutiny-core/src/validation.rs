@@ -0,0 +1,215 @@
+//! Checking addresses and invoices pasted or scanned by the user before using
+//! them, with the reason they're rejected.
+//!
+//! The most common mistake is something for another network, e.g. a testnet
+//! address in a mainnet wallet. Parsing alone only says the input is invalid,
+//! here we find out which networks it would have been valid on so the UI can
+//! say so.
+
+use crate::utils;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Network};
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const NETWORKS: [Network; 4] = [
+    Network::Bitcoin,
+    Network::Testnet,
+    Network::Signet,
+    Network::Regtest,
+];
+
+/// Why an input was rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Rejection {
+    Empty,
+    /// A lightning invoice where an address was expected
+    LightningInvoice,
+    /// A bitcoin address where an invoice was expected
+    BitcoinAddress,
+    /// Not something we can parse, with what the parser said
+    Malformed {
+        detail: String,
+    },
+    /// Valid, but for other networks. Testnet and signet addresses look the
+    /// same, so it can be more than one.
+    WrongNetwork {
+        expected: Network,
+        found: Vec<Network>,
+    },
+    Expired,
+}
+
+/// The outcome of a validation, for callers that want a value instead of an error
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Validation {
+    pub valid: bool,
+    pub rejection: Option<Rejection>,
+}
+
+impl<T> From<Result<T, Rejection>> for Validation {
+    fn from(res: Result<T, Rejection>) -> Self {
+        let rejection = res.err();
+        Self {
+            valid: rejection.is_none(),
+            rejection,
+        }
+    }
+}
+
+fn strip_scheme<'a>(input: &'a str, scheme: &str) -> &'a str {
+    match input.get(..scheme.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(scheme) => &input[scheme.len()..],
+        _ => input,
+    }
+}
+
+fn looks_like_invoice(input: &str) -> bool {
+    // covers every network, regtest is lnbcrt and signet lntbs
+    let lower = input.to_lowercase();
+    lower.starts_with("lnbc") || lower.starts_with("lntb")
+}
+
+/// Checks that the input is an address for the network
+pub fn validate_address(input: &str, network: Network) -> Result<Address, Rejection> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(Rejection::Empty);
+    }
+    let input = strip_scheme(input, "bitcoin:");
+    if looks_like_invoice(strip_scheme(input, "lightning:")) {
+        return Err(Rejection::LightningInvoice);
+    }
+
+    let address =
+        Address::<NetworkUnchecked>::from_str(input).map_err(|e| Rejection::Malformed {
+            detail: e.to_string(),
+        })?;
+    if address.is_valid_for_network(network) {
+        return Ok(address.assume_checked());
+    }
+    Err(Rejection::WrongNetwork {
+        expected: network,
+        found: NETWORKS
+            .into_iter()
+            .filter(|n| address.is_valid_for_network(*n))
+            .collect(),
+    })
+}
+
+/// Checks that the input is an unexpired invoice for the network
+pub fn validate_invoice_for_network(
+    input: &str,
+    network: Network,
+) -> Result<Bolt11Invoice, Rejection> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(Rejection::Empty);
+    }
+    let input = strip_scheme(input, "lightning:");
+
+    let invoice = match Bolt11Invoice::from_str(input) {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            if Address::<NetworkUnchecked>::from_str(strip_scheme(input, "bitcoin:")).is_ok() {
+                return Err(Rejection::BitcoinAddress);
+            }
+            return Err(Rejection::Malformed {
+                detail: e.to_string(),
+            });
+        }
+    };
+    if invoice.network() != network {
+        return Err(Rejection::WrongNetwork {
+            expected: network,
+            found: vec![invoice.network()],
+        });
+    }
+    if invoice.would_expire(utils::now()) {
+        return Err(Rejection::Expired);
+    }
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const MAINNET: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+    const TESTNET: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const INVOICE: &str = "lnbc923720n1pj9nrefpp5pczykgk37af5388n8dzynljpkzs7sje4melqgazlwv9y3apay8jqhp5rd8saxz3juve3eejq7z5fjttxmpaq88d7l92xv34n4h3mq6kwq2qcqzzsxqzfvsp5z0jwpehkuz9f2kv96h62p8x30nku76aj8yddpcust7g8ad0tr52q9qyyssqfy622q25helv8cj8hyxqltws4rdwz0xx2hw0uh575mn7a76cp3q4jcptmtjkjs4a34dqqxn8uy70d0qlxqleezv4zp84uk30pp5q3nqq4c9gkz";
+
+    #[test]
+    fn test_validate_address() {
+        let test_name = "test_validate_address";
+        log!("{}", test_name);
+
+        assert!(validate_address(MAINNET, Network::Bitcoin).is_ok());
+        assert!(validate_address(&format!("bitcoin:{MAINNET}"), Network::Bitcoin).is_ok());
+        assert_eq!(
+            validate_address("  ", Network::Bitcoin),
+            Err(Rejection::Empty)
+        );
+        assert_eq!(
+            validate_address(INVOICE, Network::Bitcoin),
+            Err(Rejection::LightningInvoice)
+        );
+        assert!(matches!(
+            validate_address("bc1notanaddress", Network::Bitcoin),
+            Err(Rejection::Malformed { .. })
+        ));
+
+        // testnet and signet addresses can't be told apart
+        assert_eq!(
+            validate_address(TESTNET, Network::Bitcoin),
+            Err(Rejection::WrongNetwork {
+                expected: Network::Bitcoin,
+                found: vec![Network::Testnet, Network::Signet],
+            })
+        );
+        assert_eq!(
+            validate_address(MAINNET, Network::Signet),
+            Err(Rejection::WrongNetwork {
+                expected: Network::Signet,
+                found: vec![Network::Bitcoin],
+            })
+        );
+
+        let validation = Validation::from(validate_address(MAINNET, Network::Bitcoin));
+        assert!(validation.valid);
+        assert_eq!(validation.rejection, None);
+    }
+
+    #[test]
+    fn test_validate_invoice_for_network() {
+        let test_name = "test_validate_invoice_for_network";
+        log!("{}", test_name);
+
+        // long expired, but the network is checked first
+        assert_eq!(
+            validate_invoice_for_network(INVOICE, Network::Signet),
+            Err(Rejection::WrongNetwork {
+                expected: Network::Signet,
+                found: vec![Network::Bitcoin],
+            })
+        );
+        assert_eq!(
+            validate_invoice_for_network(&format!("lightning:{INVOICE}"), Network::Bitcoin),
+            Err(Rejection::Expired)
+        );
+        assert_eq!(
+            validate_invoice_for_network(MAINNET, Network::Bitcoin),
+            Err(Rejection::BitcoinAddress)
+        );
+        assert!(matches!(
+            validate_invoice_for_network("lnbc1garbage", Network::Bitcoin),
+            Err(Rejection::Malformed { .. })
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+//! An on-chain vault whose funds can only leave after a wait.
+//!
+//! Coins moved into the vault are frozen and refused by every way of spending
+//! them except a withdrawal request. A withdrawal is only sent once the
+//! configured delay has passed since it was requested, until then it can be
+//! cancelled. Due withdrawals are sent by the wallet's background sync.
+//! Someone who phished the wallet has to wait out the delay too, which gives
+//! the owner time to notice and cancel.
+//!
+//! Shortening the delay, or turning the vault off, waits out the current
+//! delay for the same reason. Making it longer applies right away.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::{OutPoint, ScriptBuf, Transaction, Txid};
+use hex_conservative::DisplayHex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub(crate) const VAULT_KEY: &str = "vault";
+pub(crate) const VAULT_WITHDRAWAL_PREFIX: &str = "vault_withdrawal/";
+/// Label put on the vault's coins
+pub(crate) const VAULT_LABEL: &str = "Vault";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Vault {
+    /// How long a withdrawal waits before it's sent, 0 when the vault is off
+    pub delay_secs: u64,
+    /// A shorter delay waiting to take over
+    pub pending_delay: Option<PendingVaultDelay>,
+    /// The coins in the vault
+    pub utxos: HashSet<OutPoint>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingVaultDelay {
+    pub delay_secs: u64,
+    /// When it replaces the current delay
+    pub effective_at: u64,
+}
+
+impl Vault {
+    pub fn is_enabled(&self) -> bool {
+        self.delay_secs > 0
+    }
+
+    /// Longer delays apply right away, shorter ones once the current delay passed
+    pub(crate) fn set_delay(&mut self, delay_secs: u64, now: u64) {
+        if delay_secs >= self.delay_secs {
+            self.delay_secs = delay_secs;
+            self.pending_delay = None;
+        } else {
+            self.pending_delay = Some(PendingVaultDelay {
+                delay_secs,
+                effective_at: now + self.delay_secs,
+            });
+        }
+    }
+
+    /// Switches to the pending delay if its wait is over, returns whether it did
+    pub(crate) fn apply_pending_delay(&mut self, now: u64) -> bool {
+        match self.pending_delay {
+            Some(pending) if pending.effective_at <= now => {
+                self.delay_secs = pending.delay_secs;
+                self.pending_delay = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum VaultWithdrawalStatus {
+    /// Waiting out the delay, can still be cancelled
+    Pending,
+    Cancelled,
+    Completed,
+    /// See `failure` for why
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VaultWithdrawal {
+    pub id: String,
+    pub address: String,
+    /// None to withdraw everything in the vault
+    pub amount_sats: Option<u64>,
+    pub fee_rate: Option<u64>,
+    pub labels: Vec<String>,
+    pub status: VaultWithdrawalStatus,
+    pub txid: Option<Txid>,
+    pub failure: Option<String>,
+    pub requested_at: u64,
+    /// When it's sent, if not cancelled before then
+    pub available_at: u64,
+}
+
+impl VaultWithdrawal {
+    pub(crate) fn new(
+        address: String,
+        amount_sats: Option<u64>,
+        fee_rate: Option<u64>,
+        labels: Vec<String>,
+        delay_secs: u64,
+    ) -> Self {
+        let id: [u8; 16] = bitcoin::secp256k1::rand::random();
+        let now = utils::now().as_secs();
+        Self {
+            id: id.to_lower_hex_string(),
+            address,
+            amount_sats,
+            fee_rate,
+            labels,
+            status: VaultWithdrawalStatus::Pending,
+            txid: None,
+            failure: None,
+            requested_at: now,
+            available_at: now + delay_secs,
+        }
+    }
+}
+
+/// The outputs of the transaction that go into the vault
+pub(crate) fn vault_outpoints(
+    tx: &Transaction,
+    is_vault: impl Fn(&ScriptBuf) -> bool,
+) -> Vec<OutPoint> {
+    let txid = tx.compute_txid();
+    tx.output
+        .iter()
+        .enumerate()
+        .filter(|(_, out)| is_vault(&out.script_pubkey))
+        .map(|(vout, _)| OutPoint::new(txid, vout as u32))
+        .collect()
+}
+
+pub(crate) fn get_vault<S: MutinyStorage>(storage: &S) -> Result<Vault, MutinyError> {
+    Ok(storage.get_data(VAULT_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_vault<S: MutinyStorage>(
+    storage: &S,
+    vault: &Vault,
+) -> Result<(), MutinyError> {
+    storage.write_data(VAULT_KEY.to_string(), vault, None)
+}
+
+fn vault_withdrawal_key(id: &str) -> String {
+    format!("{VAULT_WITHDRAWAL_PREFIX}{id}")
+}
+
+pub(crate) fn persist_vault_withdrawal<S: MutinyStorage>(
+    storage: &S,
+    withdrawal: &VaultWithdrawal,
+) -> Result<(), MutinyError> {
+    storage.write_data(vault_withdrawal_key(&withdrawal.id), withdrawal, None)
+}
+
+pub(crate) fn get_vault_withdrawal<S: MutinyStorage>(
+    storage: &S,
+    id: &str,
+) -> Result<Option<VaultWithdrawal>, MutinyError> {
+    storage.get_data(vault_withdrawal_key(id))
+}
+
+/// Lists the vault withdrawals, newest first
+pub(crate) fn list_vault_withdrawals<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<VaultWithdrawal>, MutinyError> {
+    let mut withdrawals: Vec<VaultWithdrawal> = storage
+        .scan::<VaultWithdrawal>(VAULT_WITHDRAWAL_PREFIX, None)?
+        .into_values()
+        .collect();
+    withdrawals.sort_by_key(|w| std::cmp::Reverse(w.requested_at));
+
+    Ok(withdrawals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_vault_delay() {
+        let test_name = "test_vault_delay";
+        log!("{}", test_name);
+
+        let mut vault = Vault::default();
+        assert!(!vault.is_enabled());
+
+        // turning it on or lengthening the delay applies right away
+        vault.set_delay(86_400, 1_000);
+        assert!(vault.is_enabled());
+        assert_eq!(vault.delay_secs, 86_400);
+        assert_eq!(vault.pending_delay, None);
+
+        // turning it off waits out the current delay
+        vault.set_delay(0, 2_000);
+        assert_eq!(vault.delay_secs, 86_400);
+        assert_eq!(
+            vault.pending_delay,
+            Some(PendingVaultDelay {
+                delay_secs: 0,
+                effective_at: 88_400,
+            })
+        );
+        assert!(!vault.apply_pending_delay(88_399));
+        assert!(vault.is_enabled());
+        assert!(vault.apply_pending_delay(88_400));
+        assert!(!vault.is_enabled());
+        assert_eq!(vault.pending_delay, None);
+
+        // lengthening it drops a pending shorter delay
+        vault.set_delay(3_600, 90_000);
+        vault.set_delay(60, 90_000);
+        vault.set_delay(7_200, 90_001);
+        assert_eq!(vault.delay_secs, 7_200);
+        assert_eq!(vault.pending_delay, None);
+    }
+
+    #[test]
+    fn test_vault_withdrawal_records() {
+        let test_name = "test_vault_withdrawal_records";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert_eq!(get_vault(&storage).unwrap(), Vault::default());
+
+        let withdrawal = VaultWithdrawal::new(
+            "bcrt1qexample".to_string(),
+            Some(10_000),
+            None,
+            vec![],
+            86_400,
+        );
+        assert_eq!(withdrawal.status, VaultWithdrawalStatus::Pending);
+        assert_eq!(withdrawal.available_at, withdrawal.requested_at + 86_400);
+        persist_vault_withdrawal(&storage, &withdrawal).unwrap();
+        assert_eq!(
+            get_vault_withdrawal(&storage, &withdrawal.id).unwrap(),
+            Some(withdrawal.clone())
+        );
+        assert_eq!(list_vault_withdrawals(&storage).unwrap(), vec![withdrawal]);
+    }
+}
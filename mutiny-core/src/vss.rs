@@ -1,15 +1,130 @@
 use crate::authclient::MutinyAuthClient;
-use crate::encrypt::{decrypt_with_key, encrypt_with_key};
+use crate::badges::BADGE_SETTINGS_KEY;
+use crate::encrypt::{decrypt_with_key, decrypt_with_key_and_aad, encrypt_with_key_and_aad};
+use crate::idle::IDLE_SETTINGS_KEY;
+use crate::labels::{
+    ADDRESS_LABELS_MAP_KEY, CONTACT_PREFIX, INVOICE_LABELS_MAP_KEY, LABELS_VERSION_KEY,
+    LABEL_HISTORY_PREFIX, LABEL_LINEAGE_PREFIX, LABEL_PREFIX,
+};
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::moderation::MODERATION_SETTINGS_KEY;
+use crate::servicestatus::ServiceHealth;
+use crate::settingssync::WALLET_SETTINGS_KEY;
+use crate::storage::{KEYCHAIN_STORE_KEY, NODES_KEY, SEGWIT_KEYCHAIN_STORE_KEY};
+use crate::utils;
 use crate::{error::MutinyError, logging::MutinyLogger};
 use anyhow::anyhow;
+use bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
 use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use hex_conservative::DisplayHex;
 use lightning::util::logger::*;
-use lightning::{log_error, log_info};
+use lightning::{log_error, log_info, log_warn};
 use reqwest::{Method, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Prefix marking a VSS value that was encrypted with its key and version
+/// bound in as associated data. Values without it are legacy unauthenticated
+/// values, which get upgraded on their next write and are only readable until
+/// [`LEGACY_VALUE_CUTOFF`].
+const AUTHENTICATED_VALUE_PREFIX: &[u8] = b"mvss1";
+
+/// After this time, in epoch seconds (2027-01-01), legacy unauthenticated
+/// values are refused. Until then they're read so wallets have time to
+/// rewrite them.
+pub(crate) const LEGACY_VALUE_CUTOFF: u64 = 1_798_761_600;
+
+/// Prefix marking an authenticated VSS value encrypted with the key of its
+/// [`VssCategory`] instead of the master key. Older values are still readable
+/// with the master key and move to their category's key on their next write.
+const CATEGORY_VALUE_PREFIX: &[u8] = b"mvss2";
+
+/// The kinds of data we back up to VSS, each encrypted with its own key
+/// derived from the master key. Handing out one category's key, e.g. to
+/// restore contacts on a new device first, doesn't expose the others.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum VssCategory {
+    /// Channel monitors, channel managers and the node index
+    Lightning,
+    /// The on-chain wallet's keychain
+    Onchain,
+    /// Contacts and labels
+    Contacts,
+    Settings,
+    Other,
+}
+
+impl VssCategory {
+    /// The category a VSS key belongs to
+    pub fn from_key(key: &str) -> Self {
+        if key == NODES_KEY
+            || key.starts_with(MONITORS_PREFIX_KEY)
+            || key.starts_with(&format!("{CHANNEL_MANAGER_KEY}_"))
+        {
+            Self::Lightning
+        } else if key == KEYCHAIN_STORE_KEY || key == SEGWIT_KEYCHAIN_STORE_KEY {
+            Self::Onchain
+        } else if key == ADDRESS_LABELS_MAP_KEY
+            || key == INVOICE_LABELS_MAP_KEY
+            || key == LABELS_VERSION_KEY
+            || key.starts_with(LABEL_PREFIX)
+            || key.starts_with(LABEL_HISTORY_PREFIX)
+            || key.starts_with(CONTACT_PREFIX)
+            || key.starts_with(LABEL_LINEAGE_PREFIX)
+        {
+            Self::Contacts
+        } else if [
+            WALLET_SETTINGS_KEY,
+            IDLE_SETTINGS_KEY,
+            MODERATION_SETTINGS_KEY,
+            BADGE_SETTINGS_KEY,
+        ]
+        .contains(&key)
+        {
+            Self::Settings
+        } else {
+            Self::Other
+        }
+    }
+
+    fn context(&self) -> &'static str {
+        match self {
+            Self::Lightning => "mutiny/vss/lightning",
+            Self::Onchain => "mutiny/vss/onchain",
+            Self::Contacts => "mutiny/vss/contacts",
+            Self::Settings => "mutiny/vss/settings",
+            Self::Other => "mutiny/vss/other",
+        }
+    }
+}
+
+/// Derives the encryption key for a category from the master VSS key
+pub fn derive_category_key(
+    master_key: &SecretKey,
+    category: VssCategory,
+) -> Result<SecretKey, MutinyError> {
+    let mut engine = HmacEngine::<sha256::Hash>::new(&master_key.secret_bytes());
+    engine.input(category.context().as_bytes());
+    let hmac = Hmac::<sha256::Hash>::from_engine(engine);
+
+    SecretKey::from_slice(&hmac.to_byte_array())
+        .map_err(|e| MutinyError::Other(anyhow!("Invalid category key: {e}")))
+}
+
+/// The associated data for a VSS value, this binds the value to its key and
+/// version so the server cannot swap values between keys or serve an old
+/// value under a newer version.
+fn vss_aad(key: &str, version: u32) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + key.len() + 4);
+    aad.extend((key.len() as u32).to_be_bytes());
+    aad.extend(key.as_bytes());
+    aad.extend(version.to_be_bytes());
+    aad
+}
 
 pub struct MutinyVssClient {
     auth_client: Option<Arc<MutinyAuthClient>>,
@@ -17,6 +132,13 @@ pub struct MutinyVssClient {
     url: String,
     store_id: Option<String>,
     encryption_key: SecretKey,
+    /// The highest version we've written or read of each key, so the server
+    /// can't hand back an older value than one we've already seen
+    seen_versions: Mutex<HashMap<String, u32>>,
+    /// Whether `seen_versions` changed since it was last saved locally
+    versions_changed: AtomicBool,
+    /// Whether VSS answered the last request
+    pub health: ServiceHealth,
     pub logger: Arc<MutinyLogger>,
 }
 
@@ -34,19 +156,24 @@ pub struct VssKeyValueItem {
 }
 
 impl VssKeyValueItem {
-    /// Encrypts the value of the item using the encryption key
-    /// and returns an encrypted version of the item
-    pub(crate) fn encrypt(self, encryption_key: &SecretKey) -> EncryptedVssKeyValueItem {
-        // should we handle this unwrap better?
+    /// Encrypts the value of the item using the key for its category,
+    /// derived from the master key, and returns an encrypted version of the item
+    pub(crate) fn encrypt(
+        self,
+        master_key: &SecretKey,
+    ) -> Result<EncryptedVssKeyValueItem, MutinyError> {
+        let category_key = derive_category_key(master_key, VssCategory::from_key(&self.key))?;
         let bytes = self.value.to_string().into_bytes();
+        let aad = vss_aad(&self.key, self.version);
 
-        let value = encrypt_with_key(encryption_key, &bytes);
+        let mut value = CATEGORY_VALUE_PREFIX.to_vec();
+        value.extend(encrypt_with_key_and_aad(&category_key, &bytes, &aad)?);
 
-        EncryptedVssKeyValueItem {
+        Ok(EncryptedVssKeyValueItem {
             key: self.key,
             value,
             version: self.version,
-        }
+        })
     }
 }
 
@@ -58,11 +185,47 @@ pub struct EncryptedVssKeyValueItem {
 }
 
 impl EncryptedVssKeyValueItem {
-    pub(crate) fn decrypt(
+    /// Returns true if the value was written without authenticated data,
+    /// these will be upgraded the next time the key is written.
+    pub(crate) fn is_legacy(&self) -> bool {
+        !self.value.starts_with(AUTHENTICATED_VALUE_PREFIX)
+            && !self.value.starts_with(CATEGORY_VALUE_PREFIX)
+    }
+
+    /// Decrypts the item with the master key, whichever way it was encrypted
+    pub(crate) fn decrypt(self, master_key: &SecretKey) -> Result<VssKeyValueItem, MutinyError> {
+        if self.value.starts_with(CATEGORY_VALUE_PREFIX) {
+            let category_key = derive_category_key(master_key, VssCategory::from_key(&self.key))?;
+            return self.decrypt_with_category_key(&category_key);
+        }
+
+        let decrypted = match self.value.strip_prefix(AUTHENTICATED_VALUE_PREFIX) {
+            Some(bytes) => {
+                let aad = vss_aad(&self.key, self.version);
+                decrypt_with_key_and_aad(master_key, bytes, &aad)
+                    .map_err(|_| MutinyError::VssIntegrityError)?
+            }
+            None => decrypt_with_key(master_key, self.value)?,
+        };
+        self.into_item(decrypted)
+    }
+
+    /// Decrypts the item with only the key of its category. Items written
+    /// before categories existed need the master key.
+    pub fn decrypt_with_category_key(
         self,
-        encryption_key: &SecretKey,
+        category_key: &SecretKey,
     ) -> Result<VssKeyValueItem, MutinyError> {
-        let decrypted = decrypt_with_key(encryption_key, self.value)?;
+        let Some(bytes) = self.value.strip_prefix(CATEGORY_VALUE_PREFIX) else {
+            return Err(MutinyError::InvalidArgumentsError);
+        };
+        let aad = vss_aad(&self.key, self.version);
+        let decrypted = decrypt_with_key_and_aad(category_key, bytes, &aad)
+            .map_err(|_| MutinyError::VssIntegrityError)?;
+        self.into_item(decrypted)
+    }
+
+    fn into_item(self, decrypted: Vec<u8>) -> Result<VssKeyValueItem, MutinyError> {
         let decrypted_value = String::from_utf8(decrypted)?;
         let value = serde_json::from_str(&decrypted_value)?;
 
@@ -88,6 +251,9 @@ impl MutinyVssClient {
             url,
             store_id: None, // we get this from the auth client
             encryption_key,
+            seen_versions: Mutex::new(HashMap::new()),
+            versions_changed: AtomicBool::new(false),
+            health: ServiceHealth::default(),
             logger,
         }
     }
@@ -108,17 +274,71 @@ impl MutinyVssClient {
             url,
             store_id: Some(pk),
             encryption_key,
+            seen_versions: Mutex::new(HashMap::new()),
+            versions_changed: AtomicBool::new(false),
+            health: ServiceHealth::default(),
             logger,
         }
     }
 
+    /// The encryption key for one category of data, which can decrypt that
+    /// category's items without the master key
+    pub fn category_key(&self, category: VssCategory) -> Result<SecretKey, MutinyError> {
+        derive_category_key(&self.encryption_key, category)
+    }
+
+    /// Records that we've seen the version of the key, keeping the highest
+    fn record_version(&self, key: &str, version: u32) {
+        let mut seen = self.seen_versions.lock().unwrap();
+        match seen.get(key) {
+            Some(highest) if *highest >= version => {}
+            _ => {
+                seen.insert(key.to_string(), version);
+                self.versions_changed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Adds the versions seen in a previous session, loaded from local storage,
+    /// so a rollback is caught across restarts
+    pub fn restore_seen_versions(&self, versions: HashMap<String, u32>) {
+        let mut seen = self.seen_versions.lock().unwrap();
+        for (key, version) in versions {
+            let highest = seen.entry(key).or_insert(version);
+            *highest = (*highest).max(version);
+        }
+    }
+
+    /// The versions we've seen, if they changed since the last call
+    pub fn take_changed_versions(&self) -> Option<HashMap<String, u32>> {
+        if !self.versions_changed.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.seen_versions.lock().unwrap().clone())
+    }
+
+    /// Fails if we've already seen a newer version of the key than the one given
+    fn check_not_rolled_back(&self, key: &str, version: u32) -> Result<(), MutinyError> {
+        let seen = self.seen_versions.lock().unwrap();
+        match seen.get(key) {
+            Some(highest) if *highest > version => {
+                log_error!(
+                    self.logger,
+                    "VSS returned version {version} of {key}, we've seen version {highest}"
+                );
+                Err(MutinyError::VssIntegrityError)
+            }
+            _ => Ok(()),
+        }
+    }
+
     async fn make_request(
         &self,
         method: Method,
         url: Url,
         body: Option<Value>,
     ) -> Result<reqwest::Response, MutinyError> {
-        match (self.auth_client.as_ref(), self.client.as_ref()) {
+        let result = match (self.auth_client.as_ref(), self.client.as_ref()) {
             (Some(auth_client), _) => auth_client.request(method, url, body).await,
             (None, Some(client)) => {
                 let mut request = client.request(method, url);
@@ -131,7 +351,24 @@ impl MutinyVssClient {
                 })
             }
             (None, None) => unreachable!("No auth client or http client"),
+        };
+
+        match result.as_ref() {
+            Ok(_) => {
+                if self.health.record_success() {
+                    log_info!(self.logger, "VSS is reachable again");
+                }
+            }
+            Err(e) => {
+                if self.health.record_failure(e) {
+                    log_warn!(
+                        self.logger,
+                        "VSS is unreachable, running on local state: {e}"
+                    );
+                }
+            }
         }
+        result
     }
 
     pub async fn put_objects(&self, items: Vec<VssKeyValueItem>) -> Result<(), MutinyError> {
@@ -140,20 +377,55 @@ impl MutinyVssClient {
             MutinyError::InvalidArgumentsError
         })?;
 
+        let versions: Vec<(String, u32)> = items
+            .iter()
+            .map(|item| (item.key.clone(), item.version))
+            .collect();
         let items = items
             .into_iter()
             .map(|item| item.encrypt(&self.encryption_key))
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, _>>()?;
 
         // todo do we need global version here?
         let body = json!({ "store_id": self.store_id, "transaction_items": items });
 
         self.make_request(Method::PUT, url, Some(body)).await?;
+        for (key, version) in versions {
+            self.record_version(&key, version);
+        }
 
         Ok(())
     }
 
     pub async fn get_object(&self, key: &str) -> Result<VssKeyValueItem, MutinyError> {
+        let result = self.get_encrypted_object(key).await?;
+
+        if result.is_legacy() {
+            if utils::now().as_secs() >= LEGACY_VALUE_CUTOFF {
+                log_error!(
+                    self.logger,
+                    "VSS key {key} is not authenticated, refusing it"
+                );
+                return Err(MutinyError::VssIntegrityError);
+            }
+            log_warn!(
+                self.logger,
+                "VSS key {key} is not authenticated, it will be upgraded on next write"
+            );
+        }
+
+        let item = result.decrypt(&self.encryption_key)?;
+        self.record_version(key, item.version);
+
+        Ok(item)
+    }
+
+    /// Fetches the item still encrypted, making sure it is the key we asked
+    /// for and not older than a version we've already seen
+    async fn get_encrypted_object(
+        &self,
+        key: &str,
+    ) -> Result<EncryptedVssKeyValueItem, MutinyError> {
         let url = Url::parse(&format!("{}/getObject", self.url)).map_err(|e| {
             log_error!(self.logger, "Error parsing get objects url: {e}");
             MutinyError::InvalidArgumentsError
@@ -171,7 +443,48 @@ impl MutinyVssClient {
                 MutinyError::FailedParsingVssValue
             })?;
 
-        result.decrypt(&self.encryption_key)
+        if result.key != key {
+            log_error!(
+                self.logger,
+                "VSS returned key {} when requesting {key}",
+                result.key
+            );
+            return Err(MutinyError::VssIntegrityError);
+        }
+
+        self.check_not_rolled_back(key, result.version)?;
+
+        Ok(result)
+    }
+
+    /// Rewrites every value stored without authenticated data so it is bound to
+    /// its key and version, instead of waiting for each key's next write. Values
+    /// keep their version so the code that versions each key isn't disturbed.
+    /// Returns how many were upgraded.
+    pub async fn migrate_legacy_items(&self) -> Result<usize, MutinyError> {
+        if utils::now().as_secs() >= LEGACY_VALUE_CUTOFF {
+            return Ok(0);
+        }
+
+        let mut upgraded = vec![];
+        for kv in self.list_key_versions(None).await? {
+            let encrypted = self.get_encrypted_object(&kv.key).await?;
+            if !encrypted.is_legacy() {
+                continue;
+            }
+            let item = encrypted.decrypt(&self.encryption_key)?;
+            self.record_version(&item.key, item.version);
+            upgraded.push(item);
+        }
+
+        if upgraded.is_empty() {
+            return Ok(0);
+        }
+        let count = upgraded.len();
+        self.put_objects(upgraded).await?;
+        log_info!(self.logger, "Upgraded {count} unauthenticated VSS values");
+
+        Ok(count)
     }
 
     pub async fn list_key_versions(
@@ -198,3 +511,191 @@ impl MutinyVssClient {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt::{encrypt_with_key, encrypt_with_key_and_aad};
+
+    fn item() -> VssKeyValueItem {
+        VssKeyValueItem {
+            key: "test_key".to_string(),
+            value: json!({ "hello": "world" }),
+            version: 2,
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_item() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+
+        let encrypted = item().encrypt(&key).unwrap();
+        assert!(!encrypted.is_legacy());
+
+        let decrypted = encrypted.decrypt(&key).unwrap();
+        assert_eq!(decrypted, item());
+    }
+
+    #[test]
+    fn test_swapped_key_or_version_fails() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let encrypted = item().encrypt(&key).unwrap();
+
+        let mut swapped = encrypted.clone();
+        swapped.key = "other_key".to_string();
+        assert_eq!(
+            swapped.decrypt(&key).unwrap_err(),
+            MutinyError::VssIntegrityError
+        );
+
+        let mut rolled = encrypted;
+        rolled.version = 3;
+        assert_eq!(
+            rolled.decrypt(&key).unwrap_err(),
+            MutinyError::VssIntegrityError
+        );
+    }
+
+    #[test]
+    fn test_rollback_refused() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let client = MutinyVssClient::new_unauthenticated(
+            "http://localhost".to_string(),
+            key,
+            Arc::new(MutinyLogger::default()),
+        );
+
+        // nothing seen yet
+        assert!(client.check_not_rolled_back("test_key", 1).is_ok());
+
+        client.record_version("test_key", 3);
+        client.record_version("test_key", 2);
+        assert!(client.check_not_rolled_back("test_key", 3).is_ok());
+        assert!(client.check_not_rolled_back("test_key", 4).is_ok());
+        assert_eq!(
+            client.check_not_rolled_back("test_key", 2).unwrap_err(),
+            MutinyError::VssIntegrityError
+        );
+        // other keys aren't affected
+        assert!(client.check_not_rolled_back("other_key", 1).is_ok());
+
+        // versions from a previous session are kept, and only saved when they change
+        let seen = client.take_changed_versions().unwrap();
+        assert_eq!(seen.get("test_key"), Some(&3));
+        assert!(client.take_changed_versions().is_none());
+        client.record_version("test_key", 3);
+        assert!(client.take_changed_versions().is_none());
+
+        let restarted = MutinyVssClient::new_unauthenticated(
+            "http://localhost".to_string(),
+            key,
+            Arc::new(MutinyLogger::default()),
+        );
+        restarted.restore_seen_versions(seen);
+        assert_eq!(
+            restarted.check_not_rolled_back("test_key", 2).unwrap_err(),
+            MutinyError::VssIntegrityError
+        );
+    }
+
+    #[test]
+    fn test_decrypt_legacy_item() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let item = item();
+
+        let legacy = EncryptedVssKeyValueItem {
+            key: item.key.clone(),
+            value: encrypt_with_key(&key, item.value.to_string().as_bytes()),
+            version: item.version,
+        };
+        assert!(legacy.is_legacy());
+
+        let decrypted = legacy.decrypt(&key).unwrap();
+        assert_eq!(decrypted, item);
+    }
+
+    #[test]
+    fn test_decrypt_master_key_item() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let item = item();
+
+        let mut value = AUTHENTICATED_VALUE_PREFIX.to_vec();
+        let bytes = item.value.to_string().into_bytes();
+        let aad = vss_aad(&item.key, item.version);
+        value.extend(encrypt_with_key_and_aad(&key, &bytes, &aad).unwrap());
+        let encrypted = EncryptedVssKeyValueItem {
+            key: item.key.clone(),
+            value,
+            version: item.version,
+        };
+        assert!(!encrypted.is_legacy());
+
+        // written before categories, so only the master key can read it
+        let contacts_key = derive_category_key(&key, VssCategory::Contacts).unwrap();
+        assert!(encrypted
+            .clone()
+            .decrypt_with_category_key(&contacts_key)
+            .is_err());
+        assert_eq!(encrypted.decrypt(&key).unwrap(), item);
+    }
+
+    #[test]
+    fn test_category_keys() {
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+
+        assert_eq!(
+            VssCategory::from_key("monitors/abc_0"),
+            VssCategory::Lightning
+        );
+        assert_eq!(
+            VssCategory::from_key(&format!("{CHANNEL_MANAGER_KEY}_node")),
+            VssCategory::Lightning
+        );
+        assert_eq!(
+            VssCategory::from_key(KEYCHAIN_STORE_KEY),
+            VssCategory::Onchain
+        );
+        assert_eq!(VssCategory::from_key("contact/abc"), VssCategory::Contacts);
+        assert_eq!(
+            VssCategory::from_key(WALLET_SETTINGS_KEY),
+            VssCategory::Settings
+        );
+        assert_eq!(VssCategory::from_key("device_lock"), VssCategory::Other);
+
+        let lightning_key = derive_category_key(&key, VssCategory::Lightning).unwrap();
+        let contacts_key = derive_category_key(&key, VssCategory::Contacts).unwrap();
+        assert_ne!(lightning_key, contacts_key);
+        assert_ne!(lightning_key, key);
+
+        let contact = VssKeyValueItem {
+            key: "contact/abc".to_string(),
+            value: json!({ "name": "alice" }),
+            version: 1,
+        };
+        let encrypted = contact.clone().encrypt(&key).unwrap();
+        assert_eq!(
+            encrypted
+                .clone()
+                .decrypt_with_category_key(&contacts_key)
+                .unwrap(),
+            contact
+        );
+        assert_eq!(encrypted.decrypt(&key).unwrap(), contact);
+
+        // the contacts key can't read channel state
+        let manager = VssKeyValueItem {
+            key: format!("{CHANNEL_MANAGER_KEY}_node"),
+            value: json!("00"),
+            version: 1,
+        };
+        let encrypted = manager.encrypt(&key).unwrap();
+        assert_eq!(
+            encrypted
+                .clone()
+                .decrypt_with_category_key(&contacts_key)
+                .unwrap_err(),
+            MutinyError::VssIntegrityError
+        );
+        assert!(encrypted.decrypt_with_category_key(&lightning_key).is_ok());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::error::MutinyError;
+use crate::fees::MutinyFeeEstimator;
+use crate::labels::LabelStorage;
+use crate::logging::MutinyLogger;
+use crate::onchain::{get_esplora_url, watch_only_descriptors_for_xpub, OnChainWallet};
+use crate::storage::MutinyStorage;
+use crate::TransactionDetails;
+use bdk_wallet::KeychainKind;
+use bitcoin::bip32::Xpub;
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Amount, Network, Txid};
+use esplora_client::Builder;
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_trace};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WatchOnlyBalance {
+    pub confirmed: u64,
+    pub unconfirmed: u64,
+}
+
+/// An on-chain wallet without private keys, built from an external descriptor or xpub.
+/// It can hand out addresses, track its balance and create PSBTs for another
+/// wallet to sign, but it can never sign or run lightning nodes.
+pub struct WatchOnlyWallet<S: MutinyStorage> {
+    wallet: Arc<OnChainWallet<S>>,
+    fee_estimator: Arc<MutinyFeeEstimator<S>>,
+    storage: S,
+    pub logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> WatchOnlyWallet<S> {
+    /// Starts a watch-only wallet from a receive and change descriptor.
+    /// The storage should not be shared with a regular wallet.
+    pub async fn new(
+        mut storage: S,
+        descriptor: String,
+        change_descriptor: String,
+        network: Network,
+        user_esplora_url: Option<String>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<Self, MutinyError> {
+        storage.start().await?;
+
+        let esplora_server_url = get_esplora_url(network, user_esplora_url);
+        let esplora = Arc::new(Builder::new(&esplora_server_url).build_async()?);
+        let fee_estimator = Arc::new(MutinyFeeEstimator::new(
+            storage.clone(),
+            esplora.clone(),
+            logger.clone(),
+        ));
+        let wallet = Arc::new(OnChainWallet::new_watch_only(
+            descriptor,
+            change_descriptor,
+            storage.clone(),
+            network,
+            esplora,
+            fee_estimator.clone(),
+            Arc::new(AtomicBool::new(false)),
+            logger.clone(),
+        )?);
+
+        Ok(Self {
+            wallet,
+            fee_estimator,
+            storage,
+            logger,
+        })
+    }
+
+    /// Starts a watch-only wallet for an account-level xpub with taproot addresses.
+    /// The xpub has no key origin, so a Mutiny wallet can't sign its PSBTs,
+    /// use the descriptors from `get_watch_only_descriptors` for that.
+    pub async fn from_xpub(
+        storage: S,
+        xpub: Xpub,
+        network: Network,
+        user_esplora_url: Option<String>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<Self, MutinyError> {
+        let (descriptor, change_descriptor) = watch_only_descriptors_for_xpub(xpub);
+        Self::new(
+            storage,
+            descriptor,
+            change_descriptor,
+            network,
+            user_esplora_url,
+            logger,
+        )
+        .await
+    }
+
+    pub fn network(&self) -> Network {
+        self.wallet.network
+    }
+
+    /// Syncs the wallet with the chain, the first sync after creation
+    /// scans for the descriptor's history.
+    pub async fn sync(&self) -> Result<(), MutinyError> {
+        log_trace!(self.logger, "calling sync");
+
+        if let Err(e) = self.fee_estimator.update_fee_estimates_if_necessary().await {
+            log_error!(self.logger, "Failed to update fee estimates: {e}");
+        }
+        let res = self.wallet.sync().await;
+        log_trace!(self.logger, "finished calling sync");
+
+        res
+    }
+
+    pub fn get_new_address(&self, labels: Vec<String>) -> Result<Address, MutinyError> {
+        let address = {
+            let mut wallet = self.wallet.wallet.try_write()?;
+            let address = wallet.reveal_next_address(KeychainKind::External).address;
+            if let Some(changeset) = wallet.take_staged() {
+                self.storage.write_changes(&changeset)?;
+            }
+            address
+        };
+        self.storage.set_address_labels(address.clone(), labels)?;
+
+        Ok(address)
+    }
+
+    pub fn get_balance(&self) -> Result<WatchOnlyBalance, MutinyError> {
+        let balance = self.wallet.balance()?;
+
+        Ok(WatchOnlyBalance {
+            confirmed: (balance.confirmed + balance.trusted_pending).to_sat(),
+            unconfirmed: (balance.untrusted_pending + balance.immature).to_sat(),
+        })
+    }
+
+    pub fn list_transactions(&self) -> Result<Vec<TransactionDetails>, MutinyError> {
+        self.wallet.list_transactions(false)
+    }
+
+    /// Creates an unsigned PSBT paying each address the amount in sats.
+    /// The fee rate is in sat/vbyte, the current normal rate is used if not set.
+    pub fn create_psbt(
+        &self,
+        recipients: Vec<(Address, u64)>,
+        fee_rate: Option<u64>,
+    ) -> Result<Psbt, MutinyError> {
+        let recipients = recipients
+            .into_iter()
+            .map(|(address, amount)| (address.script_pubkey(), Amount::from_sat(amount)))
+            .collect();
+        self.wallet.create_unsigned_psbt(recipients, fee_rate, &[])
+    }
+
+    /// Broadcasts a PSBT after it was fully signed elsewhere
+    pub async fn broadcast_psbt(
+        &self,
+        psbt: Psbt,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        self.wallet.broadcast_psbt(psbt, labels).await
+    }
+}
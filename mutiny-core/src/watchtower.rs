@@ -0,0 +1,535 @@
+//! Watchtower client, for wallets that are offline long enough for a peer to
+//! get away with broadcasting an old channel state.
+//!
+//! After every commitment update we build the justice transaction that
+//! would sweep the peer's funds if they broadcast that commitment, and sign it
+//! once they've revoked it. The signed transaction is given to the towers as a
+//! [TEOS] appointment, encrypted with the txid of the commitment it punishes
+//! and located by the first half of that txid. A tower only learns the txid,
+//! and so can only decrypt and broadcast the justice transaction, when the
+//! revoked commitment shows up on-chain.
+//!
+//! Towers only take appointments from users registered with them, we register
+//! with a key derived just for this, so towers can't link it to the node.
+//! Appointments are signed with that key the way lightning signs messages.
+//!
+//! [TEOS]: https://github.com/talaia-labs/rust-teos
+
+use crate::error::MutinyError;
+use crate::key::{create_root_child_key, ChildKey};
+use crate::node::default_user_config;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use bitcoin::bip32::Xpriv;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, ScriptBuf, Transaction, Txid};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hex_conservative::DisplayHex;
+use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
+use lightning::sign::InMemorySigner;
+use lightning::util::message_signing;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+pub(crate) const WATCHTOWER_CONFIG_KEY: &str = "watchtower_config";
+pub(crate) const PENDING_JUSTICE_TXS_PREFIX: &str = "justice_txs/";
+pub(crate) const JUSTICE_BLOB_PREFIX: &str = "justice_blob/";
+pub(crate) const TOWER_REGISTRATION_PREFIX: &str = "watchtower_registration/";
+/// The label of the address justice transactions pay to
+pub const WATCHTOWER_LABEL: &str = "Watchtower";
+/// Bytes of the commitment txid towers locate appointments by
+const LOCATOR_LEN: usize = 16;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WatchtowerConfig {
+    /// Base urls of the towers to register with
+    pub towers: Vec<String>,
+    /// Where justice transactions pay to, one of our addresses
+    #[serde(default)]
+    pub sweep_script: Option<ScriptBuf>,
+}
+
+impl WatchtowerConfig {
+    pub fn enabled(&self) -> bool {
+        !self.towers.is_empty() && self.sweep_script.is_some()
+    }
+}
+
+/// A justice transaction for a commitment the peer hasn't revoked yet,
+/// it can only be signed once they do
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct PendingJusticeTx {
+    pub channel: OutPoint,
+    pub commitment_txid: Txid,
+    pub commitment_number: u64,
+    /// Value of the commitment output the justice transaction spends
+    pub value_sats: u64,
+    pub tx: Transaction,
+}
+
+/// A signed and encrypted justice transaction, kept until every tower has it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct JusticeBlob {
+    /// Hex of the first half of the revoked commitment's txid
+    pub locator: String,
+    /// Hex of the encrypted justice transaction
+    pub encrypted_blob: String,
+    /// The delay on the output the justice transaction spends
+    pub to_self_delay: u32,
+    pub channel: OutPoint,
+    /// Towers that haven't accepted the blob yet
+    pub pending_towers: Vec<String>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+}
+
+/// Our subscription with a tower, as of its last answer
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct TowerRegistration {
+    pub available_slots: u32,
+    /// Block height the subscription ends at
+    pub subscription_expiry: u32,
+}
+
+/// The key towers know us by, appointments are signed with it
+pub(crate) fn watchtower_key(xprivkey: Xpriv) -> Result<SecretKey, MutinyError> {
+    let context = Secp256k1::new();
+    Ok(create_root_child_key(&context, xprivkey, ChildKey::Watchtower)?.private_key)
+}
+
+/// ChaCha20-Poly1305 keyed with the sha256 of the commitment txid. Every key
+/// only ever encrypts one transaction, so the nonce is always zero.
+fn justice_cipher(commitment_txid: &Txid) -> ChaCha20Poly1305 {
+    let key = sha256::Hash::hash(commitment_txid.as_byte_array());
+    ChaCha20Poly1305::new(Key::from_slice(key.as_byte_array()))
+}
+
+/// Encrypts the justice transaction with the txid of the commitment it
+/// punishes, returns the locator and the encrypted blob
+pub(crate) fn encrypt_justice_tx(
+    commitment_txid: &Txid,
+    tx: &Transaction,
+) -> Result<(String, String), MutinyError> {
+    let locator = &commitment_txid.as_byte_array()[..LOCATOR_LEN];
+    let encrypted = justice_cipher(commitment_txid)
+        .encrypt(Nonce::from_slice(&[0; 12]), serialize(tx).as_slice())
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+    Ok((
+        locator.to_lower_hex_string(),
+        encrypted.to_lower_hex_string(),
+    ))
+}
+
+/// What a tower does once it sees the revoked commitment on-chain
+pub fn decrypt_justice_blob(
+    commitment_txid: &Txid,
+    encrypted_blob: &str,
+) -> Result<Transaction, MutinyError> {
+    let encrypted =
+        Vec::<u8>::from_hex(encrypted_blob).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    let bytes = justice_cipher(commitment_txid)
+        .decrypt(Nonce::from_slice(&[0; 12]), encrypted.as_slice())
+        .map_err(|_| MutinyError::InvalidArgumentsError)?;
+    deserialize(&bytes).map_err(|_| MutinyError::InvalidArgumentsError)
+}
+
+/// The bytes an appointment signature covers: the locator, the encrypted
+/// blob and the big endian delay
+fn appointment_message(blob: &JusticeBlob) -> Result<Vec<u8>, MutinyError> {
+    let mut message =
+        Vec::<u8>::from_hex(&blob.locator).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    message.extend(
+        Vec::<u8>::from_hex(&blob.encrypted_blob)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?,
+    );
+    message.extend(blob.to_self_delay.to_be_bytes());
+    Ok(message)
+}
+
+/// Builds the justice transactions for the peer's commitments in the update
+/// and signs and queues the ones they've since revoked. This has to happen as
+/// the update is persisted, the commitments can't be rebuilt later. Without an
+/// update it's a new channel and its initial commitment is watched.
+/// Returns how many justice transactions were queued for the towers.
+pub(crate) fn watch_commitment_update<S: MutinyStorage>(
+    storage: &S,
+    feerate_per_kw: u32,
+    monitor: &ChannelMonitor<InMemorySigner>,
+    update: Option<&ChannelMonitorUpdate>,
+) -> Result<usize, MutinyError> {
+    let config = get_watchtower_config(storage)?;
+    let Some(sweep_script) = config.sweep_script.clone().filter(|_| config.enabled()) else {
+        return Ok(0);
+    };
+    let channel = monitor.get_funding_txo().0.into_bitcoin_outpoint();
+    let commitments = match update {
+        Some(update) => monitor.counterparty_commitment_txs_from_update(update),
+        None => monitor
+            .initial_counterparty_commitment_tx()
+            .into_iter()
+            .collect(),
+    };
+
+    let mut pending = get_pending_justice_txs(storage, &channel)?;
+    let mut changed = false;
+    for commitment in commitments {
+        let trusted = commitment.trust();
+        if pending
+            .iter()
+            .any(|p| p.commitment_number == trusted.commitment_number())
+        {
+            continue;
+        }
+        // nothing to punish if the peer has no balance in this commitment
+        let Some(output_index) = trusted.revokeable_output_index() else {
+            continue;
+        };
+        let built = trusted.built_transaction();
+        let value_sats = built.transaction.output[output_index].value.to_sat();
+        let Ok(tx) = trusted.build_to_local_justice_tx(feerate_per_kw as u64, sweep_script.clone())
+        else {
+            continue;
+        };
+        pending.push(PendingJusticeTx {
+            channel,
+            commitment_txid: built.txid,
+            commitment_number: trusted.commitment_number(),
+            value_sats,
+            tx,
+        });
+        changed = true;
+    }
+
+    // the peer's to_local output is delayed by what we asked of them
+    let to_self_delay = default_user_config(false)
+        .channel_handshake_config
+        .our_to_self_delay as u32;
+    let mut unrevoked = vec![];
+    let mut queued = 0;
+    for tx in pending {
+        // fails until the peer has revoked the commitment
+        let Ok(signed) =
+            monitor.sign_to_local_justice_tx(tx.tx.clone(), 0, tx.value_sats, tx.commitment_number)
+        else {
+            unrevoked.push(tx);
+            continue;
+        };
+        let (locator, encrypted_blob) = encrypt_justice_tx(&tx.commitment_txid, &signed)?;
+        let blob = JusticeBlob {
+            locator,
+            encrypted_blob,
+            to_self_delay,
+            channel,
+            pending_towers: config.towers.clone(),
+            attempts: 0,
+            last_error: None,
+            created_at: utils::now().as_secs(),
+        };
+        persist_justice_blob(storage, &blob)?;
+        queued += 1;
+    }
+
+    if changed || queued > 0 {
+        persist_pending_justice_txs(storage, &channel, &unrevoked)?;
+    }
+
+    Ok(queued)
+}
+
+async fn register_with_tower(
+    client: &reqwest::Client,
+    tower: &str,
+    user_id: &str,
+) -> Result<TowerRegistration, MutinyError> {
+    let res = client
+        .post(format!("{tower}/register"))
+        .json(&json!({ "user_id": user_id }))
+        .send()
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+    if !res.status().is_success() {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    res.json().await.map_err(|_| MutinyError::ConnectionFailed)
+}
+
+async fn add_appointment(
+    client: &reqwest::Client,
+    tower: &str,
+    blob: &JusticeBlob,
+    signature: &str,
+) -> Result<TowerRegistration, MutinyError> {
+    let body = json!({
+        "appointment": {
+            "locator": blob.locator,
+            "encrypted_blob": blob.encrypted_blob,
+            "to_self_delay": blob.to_self_delay,
+        },
+        "signature": signature,
+    });
+    let res = client
+        .post(format!("{tower}/add_appointment"))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|_| MutinyError::ConnectionFailed)?;
+    if !res.status().is_success() {
+        return Err(MutinyError::ConnectionFailed);
+    }
+    res.json().await.map_err(|_| MutinyError::ConnectionFailed)
+}
+
+/// Gives the appointment to the tower, registering with it first if we aren't
+async fn send_appointment<S: MutinyStorage>(
+    storage: &S,
+    client: &reqwest::Client,
+    tower: &str,
+    user_id: &str,
+    blob: &JusticeBlob,
+    signature: &str,
+) -> Result<(), MutinyError> {
+    let registered = get_tower_registration(storage, tower)?.is_some_and(|r| r.available_slots > 0);
+    if !registered {
+        let registration = register_with_tower(client, tower, user_id).await?;
+        persist_tower_registration(storage, tower, &registration)?;
+    }
+
+    match add_appointment(client, tower, blob, signature).await {
+        Ok(registration) => persist_tower_registration(storage, tower, &registration),
+        Err(e) => {
+            // e.g. the subscription expired, registering again renews it
+            storage.delete(&[tower_registration_key(tower)])?;
+            Err(e)
+        }
+    }
+}
+
+/// Gives the queued justice blobs to every tower that doesn't have them yet
+pub(crate) async fn deliver_justice_blobs<S: MutinyStorage>(
+    storage: &S,
+    user_key: &SecretKey,
+) -> Result<(), MutinyError> {
+    let blobs = list_justice_blobs(storage)?;
+    if blobs.is_empty() {
+        return Ok(());
+    }
+
+    let user_id = PublicKey::from_secret_key(&Secp256k1::new(), user_key).to_string();
+    let client = reqwest::Client::new();
+    for mut blob in blobs {
+        let signature = message_signing::sign(&appointment_message(&blob)?, user_key);
+        let mut pending = vec![];
+        for tower in blob.pending_towers.iter() {
+            if let Err(e) =
+                send_appointment(storage, &client, tower, &user_id, &blob, &signature).await
+            {
+                blob.last_error = Some(e.to_string());
+                pending.push(tower.clone());
+            }
+        }
+
+        if pending.is_empty() {
+            storage.delete(&[justice_blob_key(&blob.locator)])?;
+        } else {
+            blob.pending_towers = pending;
+            blob.attempts += 1;
+            persist_justice_blob(storage, &blob)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn get_watchtower_config<S: MutinyStorage>(
+    storage: &S,
+) -> Result<WatchtowerConfig, MutinyError> {
+    Ok(storage.get_data(WATCHTOWER_CONFIG_KEY)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_watchtower_config<S: MutinyStorage>(
+    storage: &S,
+    config: &WatchtowerConfig,
+) -> Result<(), MutinyError> {
+    storage.write_data(WATCHTOWER_CONFIG_KEY.to_string(), config, None)
+}
+
+/// A channel's unrevoked justice transactions are kept together under one
+/// key, so a monitor update only reads its own channel's
+fn pending_justice_txs_key(channel: &OutPoint) -> String {
+    format!(
+        "{PENDING_JUSTICE_TXS_PREFIX}{}_{}",
+        channel.txid, channel.vout
+    )
+}
+
+fn get_pending_justice_txs<S: MutinyStorage>(
+    storage: &S,
+    channel: &OutPoint,
+) -> Result<Vec<PendingJusticeTx>, MutinyError> {
+    Ok(storage
+        .get_data(pending_justice_txs_key(channel))?
+        .unwrap_or_default())
+}
+
+fn persist_pending_justice_txs<S: MutinyStorage>(
+    storage: &S,
+    channel: &OutPoint,
+    pending: &[PendingJusticeTx],
+) -> Result<(), MutinyError> {
+    let key = pending_justice_txs_key(channel);
+    if pending.is_empty() {
+        return storage.delete(&[key]);
+    }
+    storage.write_data(key, pending, None)
+}
+
+fn tower_registration_key(tower: &str) -> String {
+    format!("{TOWER_REGISTRATION_PREFIX}{tower}")
+}
+
+fn get_tower_registration<S: MutinyStorage>(
+    storage: &S,
+    tower: &str,
+) -> Result<Option<TowerRegistration>, MutinyError> {
+    storage.get_data(tower_registration_key(tower))
+}
+
+fn persist_tower_registration<S: MutinyStorage>(
+    storage: &S,
+    tower: &str,
+    registration: &TowerRegistration,
+) -> Result<(), MutinyError> {
+    storage.write_data(tower_registration_key(tower), registration, None)
+}
+
+fn justice_blob_key(locator: &str) -> String {
+    format!("{JUSTICE_BLOB_PREFIX}{locator}")
+}
+
+fn persist_justice_blob<S: MutinyStorage>(
+    storage: &S,
+    blob: &JusticeBlob,
+) -> Result<(), MutinyError> {
+    storage.write_data(justice_blob_key(&blob.locator), blob, None)
+}
+
+/// Lists the justice blobs some tower doesn't have yet, oldest first
+pub(crate) fn list_justice_blobs<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<JusticeBlob>, MutinyError> {
+    let mut blobs: Vec<JusticeBlob> = storage
+        .scan::<JusticeBlob>(JUSTICE_BLOB_PREFIX, None)?
+        .into_values()
+        .collect();
+    blobs.sort_by_key(|b| b.created_at);
+
+    Ok(blobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, TxOut};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn justice_tx() -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_justice_blob_round_trip() {
+        let test_name = "test_justice_blob_round_trip";
+        log!("{}", test_name);
+
+        let commitment_txid = Txid::from_byte_array([7; 32]);
+        let (locator, blob) = encrypt_justice_tx(&commitment_txid, &justice_tx()).unwrap();
+        assert_eq!(locator, [7u8; LOCATOR_LEN].to_lower_hex_string());
+        assert_eq!(
+            decrypt_justice_blob(&commitment_txid, &blob).unwrap(),
+            justice_tx()
+        );
+
+        // a tower that only knows the locator can't read it
+        let other = Txid::from_byte_array([8; 32]);
+        assert!(decrypt_justice_blob(&other, &blob).is_err());
+    }
+
+    #[test]
+    fn test_watchtower_storage() {
+        let test_name = "test_watchtower_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let config = get_watchtower_config(&storage).unwrap();
+        assert!(!config.enabled());
+        let config = WatchtowerConfig {
+            towers: vec!["https://tower.example.com".to_string()],
+            sweep_script: Some(ScriptBuf::new()),
+        };
+        persist_watchtower_config(&storage, &config).unwrap();
+        assert!(get_watchtower_config(&storage).unwrap().enabled());
+
+        let channel = OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let pending = PendingJusticeTx {
+            channel,
+            commitment_txid: Txid::from_byte_array([2; 32]),
+            commitment_number: 5,
+            value_sats: 10_000,
+            tx: justice_tx(),
+        };
+        persist_pending_justice_txs(&storage, &channel, &[pending.clone()]).unwrap();
+        assert_eq!(
+            get_pending_justice_txs(&storage, &channel).unwrap(),
+            vec![pending]
+        );
+        let other = OutPoint::new(Txid::from_byte_array([1; 32]), 1);
+        assert!(get_pending_justice_txs(&storage, &other)
+            .unwrap()
+            .is_empty());
+
+        persist_pending_justice_txs(&storage, &channel, &[]).unwrap();
+        assert!(get_pending_justice_txs(&storage, &channel)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_appointment_message() {
+        let test_name = "test_appointment_message";
+        log!("{}", test_name);
+
+        let blob = JusticeBlob {
+            locator: "0102".to_string(),
+            encrypted_blob: "aabb".to_string(),
+            to_self_delay: 288,
+            channel: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            pending_towers: vec![],
+            attempts: 0,
+            last_error: None,
+            created_at: 0,
+        };
+        assert_eq!(
+            appointment_message(&blob).unwrap(),
+            vec![0x01, 0x02, 0xaa, 0xbb, 0, 0, 0x01, 0x20]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Zero channel reserve with peers we trust.
+//!
+//! The reserve is the part of a channel balance that can't be spent so the
+//! owner has something to lose if they broadcast an old state. On small
+//! channels it strands a good chunk of the balance for little protection, so
+//! with trusted peers, like our LSP, we ask for no reserve at all. Everyone
+//! else has to keep the usual 1%.
+
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::secp256k1::PublicKey;
+
+pub(crate) const ZERO_RESERVE_PEERS_KEY: &str = "zero_reserve_peers";
+/// Reserve we ask of peers we don't trust, LDK's default
+pub(crate) const DEFAULT_THEIR_RESERVE_MILLIONTHS: u32 = 10_000;
+
+/// The reserve we ask the peer to keep, as millionths of the channel value.
+/// LDK still requires a small floor above the dust limit.
+pub(crate) fn their_reserve_millionths(zero_reserve: bool) -> u32 {
+    if zero_reserve {
+        0
+    } else {
+        DEFAULT_THEIR_RESERVE_MILLIONTHS
+    }
+}
+
+/// Peers we negotiate zero reserve with, besides our LSP which always is
+pub(crate) fn get_zero_reserve_peers<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<PublicKey>, MutinyError> {
+    Ok(storage
+        .get_data(ZERO_RESERVE_PEERS_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn is_zero_reserve_peer<S: MutinyStorage>(storage: &S, peer: &PublicKey) -> bool {
+    get_zero_reserve_peers(storage).is_ok_and(|peers| peers.contains(peer))
+}
+
+pub(crate) fn set_zero_reserve_peer<S: MutinyStorage>(
+    storage: &S,
+    peer: PublicKey,
+    enabled: bool,
+) -> Result<(), MutinyError> {
+    let mut peers = get_zero_reserve_peers(storage)?;
+    peers.retain(|p| *p != peer);
+    if enabled {
+        peers.push(peer);
+    }
+    storage.write_data(ZERO_RESERVE_PEERS_KEY.to_string(), peers, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_zero_reserve_peers() {
+        let test_name = "test_zero_reserve_peers";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let peer = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        assert!(!is_zero_reserve_peer(&storage, &peer));
+
+        set_zero_reserve_peer(&storage, peer, true).unwrap();
+        // setting it twice doesn't duplicate it
+        set_zero_reserve_peer(&storage, peer, true).unwrap();
+        assert_eq!(get_zero_reserve_peers(&storage).unwrap(), vec![peer]);
+        assert!(is_zero_reserve_peer(&storage, &peer));
+
+        set_zero_reserve_peer(&storage, peer, false).unwrap();
+        assert!(!is_zero_reserve_peer(&storage, &peer));
+
+        assert_eq!(their_reserve_millionths(true), 0);
+        assert_eq!(
+            their_reserve_millionths(false),
+            DEFAULT_THEIR_RESERVE_MILLIONTHS
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Lets the app decide on the channels peers ask to open with us from JS.
+
+use futures::Future;
+use gloo_utils::format::JsValueSerdeExt;
+use mutiny_core::hooks::{ChannelAcceptance, ChannelAcceptor, ChannelOpenRequest};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::js_sys::{Function, Promise};
+
+struct JsFunction(Function);
+
+// These are okay because we never actually send across threads in the browser
+unsafe impl Send for JsFunction {}
+unsafe impl Sync for JsFunction {}
+
+struct JsSendFuture<F>(Pin<Box<F>>);
+
+unsafe impl<F> Send for JsSendFuture<F> {}
+
+impl<F: Future> Future for JsSendFuture<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+fn reject(reason: impl Into<String>) -> ChannelAcceptance {
+    ChannelAcceptance::Reject {
+        reason: reason.into(),
+    }
+}
+
+/// True accepts, false or a string rejects, or the acceptance as an object
+fn to_acceptance(value: &JsValue) -> ChannelAcceptance {
+    if let Some(accept) = value.as_bool() {
+        return if accept {
+            ChannelAcceptance::Accept
+        } else {
+            reject("rejected by channel acceptor")
+        };
+    }
+    if let Some(reason) = value.as_string() {
+        return reject(reason);
+    }
+    value
+        .into_serde()
+        .unwrap_or_else(|_| reject("invalid channel acceptor response"))
+}
+
+/// Wraps the JS function as a channel acceptor. It's called with the request
+/// and can answer right away or with a promise. Throwing rejects the channel.
+pub(crate) fn js_channel_acceptor(function: Function) -> ChannelAcceptor {
+    let function = Arc::new(JsFunction(function));
+    Arc::new(move |request: ChannelOpenRequest| {
+        let function = function.clone();
+        Box::pin(JsSendFuture(Box::pin(async move {
+            let arg = match JsValue::from_serde(&request) {
+                Ok(arg) => arg,
+                Err(e) => return reject(format!("could not pass request: {e}")),
+            };
+            let value = match function.0.call1(&JsValue::NULL, &arg) {
+                Ok(value) => match value.dyn_into::<Promise>() {
+                    Ok(promise) => JsFuture::from(promise).await,
+                    Err(value) => Ok(value),
+                },
+                Err(e) => Err(e),
+            };
+            match value {
+                Ok(value) => to_acceptance(&value),
+                Err(e) => reject(format!("channel acceptor failed: {e:?}")),
+            }
+        })))
+    })
+}
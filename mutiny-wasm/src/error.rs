@@ -1,6 +1,7 @@
 use lightning_invoice::ParseOrSemanticError;
 use log::error;
 use mutiny_core::error::{MutinyError, MutinyStorageError};
+use mutiny_core::messages::MessageCode;
 use thiserror::Error;
 use wasm_bindgen::JsValue;
 
@@ -188,11 +189,113 @@ pub enum MutinyJsError {
     JwtAuthFailure,
     #[error("Failed to parse VSS value from getObject response.")]
     FailedParsingVssValue,
+    #[error("VSS value failed integrity check.")]
+    VssIntegrityError,
+    #[error("Failed to get an invoice from the lightning address.")]
+    LnUrlFailure,
+    #[error("The app does not have permission to do this.")]
+    PermissionDenied,
+    #[error("The chain snapshot failed verification.")]
+    InvalidChainSnapshot,
+    /// A hook registered by the embedder vetoed the operation.
+    #[error("Vetoed by a hook: {0}")]
+    HookVetoed(String),
+    #[error("The remote config failed verification.")]
+    InvalidRemoteConfig,
+    #[error("Funds in the vault can only be spent by requesting a withdrawal.")]
+    VaultLocked,
+    #[error("Funds can only be sent to an address on the withdrawal allowlist.")]
+    DestinationNotAllowed,
     /// Unknown error.
     #[error("Unknown Error")]
     UnknownError,
 }
 
+impl MessageCode for MutinyJsError {
+    fn code(&self) -> &'static str {
+        match self {
+            MutinyJsError::AlreadyRunning => "already_running",
+            MutinyJsError::NotRunning => "not_running",
+            MutinyJsError::NetworkMismatch => "network_mismatch",
+            MutinyJsError::PacketSizeExceeded => "packet_size_exceeded",
+            MutinyJsError::NotFound => "not_found",
+            MutinyJsError::FundingTxCreationFailed => "funding_tx_creation_failed",
+            MutinyJsError::ConnectionFailed => "connection_failed",
+            MutinyJsError::IncorrectNetwork => "incorrect_network",
+            MutinyJsError::NonUniquePaymentHash => "non_unique_payment_hash",
+            MutinyJsError::PaymentTimeout => "payment_timeout",
+            MutinyJsError::InvoiceInvalid => "invoice_invalid",
+            MutinyJsError::InvoiceExpired => "invoice_expired",
+            MutinyJsError::InvoiceCreationFailed => "invoice_creation_failed",
+            MutinyJsError::ReserveAmountError => "reserve_amount_error",
+            MutinyJsError::InsufficientBalance => "insufficient_balance",
+            MutinyJsError::LspGenericError => "lsp_generic_error",
+            MutinyJsError::LspFundingError => "lsp_funding_error",
+            MutinyJsError::LspAmountTooHighError => "lsp_amount_too_high_error",
+            MutinyJsError::LspConnectionError => "lsp_connection_error",
+            MutinyJsError::LspInvoiceRequired => "lsp_invoice_required",
+            MutinyJsError::SubscriptionClientNotConfigured => "subscription_client_not_configured",
+            MutinyJsError::InvalidParameter => "invalid_parameter",
+            MutinyJsError::IncorrectLnUrlFunction => "incorrect_ln_url_function",
+            MutinyJsError::RoutingFailed => "routing_failed",
+            MutinyJsError::PeerInfoParseFailed => "peer_info_parse_failed",
+            MutinyJsError::ChannelCreationFailed => "channel_creation_failed",
+            MutinyJsError::ChannelCreationFailedWithReason(_) => {
+                "channel_creation_failed_with_reason"
+            }
+            MutinyJsError::ChannelClosingFailed => "channel_closing_failed",
+            MutinyJsError::PersistenceFailed => "persistence_failed",
+            MutinyJsError::ReadError => "read_error",
+            MutinyJsError::LnDecodeError => "ln_decode_error",
+            MutinyJsError::SeedGenerationFailed => "seed_generation_failed",
+            MutinyJsError::InvalidMnemonic => "invalid_mnemonic",
+            MutinyJsError::InvalidTransaction => "invalid_transaction",
+            MutinyJsError::WalletOperationFailed => "wallet_operation_failed",
+            MutinyJsError::WalletSigningFailed => "wallet_signing_failed",
+            MutinyJsError::ChainAccessFailed => "chain_access_failed",
+            MutinyJsError::WalletSyncError => "wallet_sync_error",
+            MutinyJsError::RapidGossipSyncError => "rapid_gossip_sync_error",
+            MutinyJsError::JsonReadWriteError => "json_read_write_error",
+            MutinyJsError::PubkeyInvalid => "pubkey_invalid",
+            MutinyJsError::NostrError => "nostr_error",
+            MutinyJsError::Nip07Extension => "nip07_extension",
+            MutinyJsError::BitcoinPriceError => "bitcoin_price_error",
+            MutinyJsError::BadAmountError => "bad_amount_error",
+            MutinyJsError::DLCManagerError => "dlc_manager_error",
+            MutinyJsError::WasmBindgenError => "wasm_bindgen_error",
+            MutinyJsError::InvalidArgumentsError => "invalid_arguments_error",
+            MutinyJsError::InvalidAddressNetworkError => "invalid_address_network_error",
+            MutinyJsError::IncorrectPassword => "incorrect_password",
+            MutinyJsError::SamePassword => "same_password",
+            MutinyJsError::PayjoinCreateRequest => "payjoin_create_request",
+            MutinyJsError::PayjoinResponse(_) => "payjoin_response",
+            MutinyJsError::PayjoinConfigError => "payjoin_config_error",
+            MutinyJsError::CashuMintError => "cashu_mint_error",
+            MutinyJsError::EmptyMintURLError => "empty_mint_url_error",
+            MutinyJsError::EncryptOrDecryptError => "encrypt_or_decrypt_error",
+            MutinyJsError::TokenAlreadySpent => "token_already_spent",
+            MutinyJsError::InvalidFeerate => "invalid_feerate",
+            MutinyJsError::InvalidPsbt => "invalid_psbt",
+            MutinyJsError::InvalidHex => "invalid_hex",
+            MutinyJsError::JwtAuthFailure => "jwt_auth_failure",
+            MutinyJsError::FailedParsingVssValue => "failed_parsing_vss_value",
+            MutinyJsError::VssIntegrityError => "vss_integrity_error",
+            MutinyJsError::LnUrlFailure => "ln_url_failure",
+            MutinyJsError::PermissionDenied => "permission_denied",
+            MutinyJsError::InvalidChainSnapshot => "invalid_chain_snapshot",
+            MutinyJsError::HookVetoed(_) => "hook_vetoed",
+            MutinyJsError::InvalidRemoteConfig => "invalid_remote_config",
+            MutinyJsError::VaultLocked => "vault_locked",
+            MutinyJsError::DestinationNotAllowed => "destination_not_allowed",
+            MutinyJsError::UnknownError => "unknown_error",
+        }
+    }
+
+    fn default_message(&self) -> String {
+        self.to_string()
+    }
+}
+
 impl From<MutinyError> for MutinyJsError {
     fn from(e: MutinyError) -> Self {
         match e {
@@ -255,6 +358,14 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::InvalidHex => MutinyJsError::InvalidHex,
             MutinyError::JwtAuthFailure => MutinyJsError::JwtAuthFailure,
             MutinyError::FailedParsingVssValue => MutinyJsError::FailedParsingVssValue,
+            MutinyError::VssIntegrityError => MutinyJsError::VssIntegrityError,
+            MutinyError::LnUrlFailure => MutinyJsError::LnUrlFailure,
+            MutinyError::PermissionDenied => MutinyJsError::PermissionDenied,
+            MutinyError::InvalidChainSnapshot => MutinyJsError::InvalidChainSnapshot,
+            MutinyError::HookVetoed(x) => MutinyJsError::HookVetoed(x),
+            MutinyError::InvalidRemoteConfig => MutinyJsError::InvalidRemoteConfig,
+            MutinyError::VaultLocked => MutinyJsError::VaultLocked,
+            MutinyError::DestinationNotAllowed => MutinyJsError::DestinationNotAllowed,
         }
     }
 }
@@ -313,8 +424,13 @@ impl From<serde_json::error::Error> for MutinyJsError {
     }
 }
 
+/// Errors are thrown as a JS `Error` with the English text as its message, and
+/// a stable `code` and the `localized` message alongside it
 impl From<MutinyJsError> for JsValue {
     fn from(e: MutinyJsError) -> Self {
-        JsValue::from(e.to_string())
+        let error = js_sys::Error::new(&e.to_string());
+        let _ = js_sys::Reflect::set(&error, &"code".into(), &e.code().into());
+        let _ = js_sys::Reflect::set(&error, &"localized".into(), &e.localized_message().into());
+        error.into()
     }
 }
@@ -5,7 +5,7 @@ use futures::lock::Mutex;
 use futures::FutureExt;
 use gloo_utils::format::JsValueSerdeExt;
 use lightning::util::logger::Logger;
-use lightning::{log_debug, log_error, log_info};
+use lightning::{log_debug, log_error, log_info, log_warn};
 use log::error;
 use messagehandler::BumpChannelClosureTransaction;
 use mutiny_core::event::PaymentInfo;
@@ -311,6 +311,56 @@ impl IndexedDbStorage {
         Ok(())
     }
 
+    /// Saves the items and deletes the keys in one indexed db transaction,
+    /// so either all of the changes are applied or none are
+    async fn save_and_delete_in_indexed_db(
+        indexed_db: &Arc<RwLock<RexieContainer>>,
+        items: &[(String, Value)],
+        deletes: &[String],
+    ) -> Result<(), MutinyError> {
+        let tx = indexed_db
+            .try_write()
+            .map_err(|e| MutinyError::read_err(e.into()))
+            .and_then(|indexed_db_lock| {
+                if let Some(indexed_db) = &indexed_db_lock.0 {
+                    indexed_db
+                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadWrite)
+                        .map_err(|e| {
+                            MutinyError::read_err(
+                                anyhow!("Failed to create indexed db transaction: {e}").into(),
+                            )
+                        })
+                } else {
+                    Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
+                }
+            })?;
+
+        let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to create indexed db store: {e}").into())
+        })?;
+
+        for (key, data) in items {
+            store
+                .put(&JsValue::from_serde(&data)?, Some(&JsValue::from(key)))
+                .await
+                .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+        }
+        for key in deletes {
+            store
+                .delete(&JsValue::from(key))
+                .await
+                .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+        }
+
+        // nothing is committed if any of the requests failed, the transaction is aborted
+        // when it's dropped without finishing
+        tx.done()
+            .await
+            .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+
+        Ok(())
+    }
+
     pub(crate) async fn read_all(
         indexed_db: &Arc<RwLock<RexieContainer>>,
         password: Option<String>,
@@ -385,23 +435,41 @@ impl IndexedDbStorage {
             Some(vss) => {
                 log_info!(logger, "Reading from vss");
                 let start = instant::Instant::now();
-                let keys = vss.list_key_versions(None).await?;
-                log_info!(logger, "Read {} keys from vss", keys.len());
-                let mut futs = Vec::with_capacity(keys.len());
-                for kv in keys {
-                    let key = kv.key.clone();
-                    futs.push(
-                        Self::handle_vss_key(kv, vss, &map, logger).then(|r| async move {
-                            r.with_context(|| format!("handle vss key {}", key))
-                        }),
-                    );
+                if let Some(seen) = map.get_data(VSS_SEEN_VERSIONS_KEY)? {
+                    vss.restore_seen_versions(seen);
+                }
+                let fetched: Result<_, MutinyError> = async {
+                    let keys = vss.list_key_versions(None).await?;
+                    log_info!(logger, "Read {} keys from vss", keys.len());
+                    let mut futs = Vec::with_capacity(keys.len());
+                    for kv in keys {
+                        let key = kv.key.clone();
+                        futs.push(Self::handle_vss_key(kv, vss, &map, logger).then(
+                            |r| async move { r.with_context(|| format!("handle vss key {}", key)) },
+                        ));
+                    }
+                    Ok(futures::future::try_join_all(futs).await?)
                 }
-                let results = futures::future::try_join_all(futs).await?;
+                .await;
+                let results = match fetched {
+                    Ok(results) => results,
+                    // VSS being down shouldn't keep the wallet from starting,
+                    // anything else could mean the data was tampered with
+                    Err(e) if vss.health.unreachable_since().is_some() => {
+                        log_warn!(logger, "VSS is unreachable, starting with local state: {e}");
+                        let final_map = map.memory.read().unwrap();
+                        return Ok(final_map.clone());
+                    }
+                    Err(e) => return Err(e),
+                };
 
                 for (key, value) in results.into_iter().flatten() {
                     // save to memory and batch the write to local storage
                     map.write_data(key.clone(), value.clone(), None)?;
                 }
+                if let Some(seen) = vss.take_changed_versions() {
+                    map.write_data(VSS_SEEN_VERSIONS_KEY.to_string(), seen, None)?;
+                }
                 let inner_map = map.memory.read().unwrap().clone();
 
                 if !inner_map.is_empty() {
@@ -473,7 +541,7 @@ impl IndexedDbStorage {
                 let obj = vss.get_object(&kv.key).await?;
                 return Ok(Some((kv.key, obj.value)));
             }
-            KEYCHAIN_STORE_KEY => match current
+            KEYCHAIN_STORE_KEY | SEGWIT_KEYCHAIN_STORE_KEY => match current
                 .get_data::<VersionedValue>(&kv.key)
                 .with_context(|| "read keychain data from storage")?
             {
@@ -639,6 +707,27 @@ impl IndexedDbStorage {
                             }
                         }
                     }
+                } else if labels::is_synced_label_key(key) {
+                    // labels are synced as a whole, compare against the last bulk label change
+                    let local: u32 = current
+                        .get_data(labels::LABELS_VERSION_KEY)?
+                        .unwrap_or_default();
+                    if local < kv.version {
+                        let obj = vss.get_object(&kv.key).await?;
+                        // null marks a label that was deleted
+                        if obj.value.is_null() {
+                            return Ok(None);
+                        }
+                        return Ok(Some((kv.key, obj.value)));
+                    } else {
+                        log_debug!(
+                            logger,
+                            "Skipping vss key {} with version {}, current version is {local}",
+                            kv.key,
+                            kv.version
+                        );
+                        return Ok(None);
+                    }
                 }
             }
         }
@@ -771,6 +860,57 @@ impl MutinyStorage for IndexedDbStorage {
         Ok(())
     }
 
+    fn write_raw_and_delete<T>(
+        &self,
+        items: Vec<(String, T)>,
+        deletes: Vec<String>,
+    ) -> Result<(), MutinyError>
+    where
+        T: Serialize + Send,
+    {
+        let items = items
+            .into_iter()
+            .map(|(k, v)| {
+                serde_json::to_value(v)
+                    .map_err(|e| MutinyError::PersistenceFailed {
+                        source: MutinyStorageError::SerdeError { source: e },
+                    })
+                    .map(|d| (k, d))
+            })
+            .collect::<Result<Vec<(String, Value)>, MutinyError>>()?;
+
+        let indexed_db = self.indexed_db.clone();
+        let items_clone = items.clone();
+        let deletes_clone = deletes.clone();
+
+        // write to index DB in background job
+        self.spawn(async move {
+            Self::save_and_delete_in_indexed_db(&indexed_db, &items_clone, &deletes_clone)
+                .await
+                .map_err(|e| MutinyError::PersistenceFailed {
+                    source: MutinyStorageError::Other(anyhow!(
+                        "Failed to commit ({items_clone:?}, deleting {deletes_clone:?}) to indexed db: {e}"
+                    )),
+                })
+        });
+
+        // update memory under a single lock so readers never see half of the changes
+        let mut map = self
+            .memory
+            .try_write()
+            .map_err(|e| MutinyError::write_err(e.into()))?;
+        for (key, data) in items {
+            if !used_once(key.as_ref()) {
+                map.insert(key, data);
+            }
+        }
+        for key in deletes {
+            map.remove(&key);
+        }
+
+        Ok(())
+    }
+
     fn get<T>(&self, key: impl AsRef<str>) -> Result<Option<T>, MutinyError>
     where
         T: for<'de> Deserialize<'de>,
@@ -884,6 +1024,10 @@ impl MutinyStorage for IndexedDbStorage {
         log_debug!(self.logger, "stopped storage");
     }
 
+    async fn flush(&self) {
+        self.tasks.wait().await;
+    }
+
     fn connected(&self) -> Result<bool, MutinyError> {
         Ok(self.indexed_db.try_read()?.0.is_some())
     }
@@ -981,6 +1125,141 @@ impl MutinyStorage for IndexedDbStorage {
         }
     }
 
+    async fn fetch_instance_lock(&self) -> Result<Option<InstanceLock>, MutinyError> {
+        // other tabs only share indexed db with us, not our in memory map
+        let tx = self
+            .indexed_db
+            .try_read()
+            .map_err(|e| MutinyError::read_err(e.into()))
+            .and_then(|indexed_db_lock| {
+                if let Some(indexed_db) = &indexed_db_lock.0 {
+                    indexed_db
+                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadOnly)
+                        .map_err(|e| {
+                            MutinyError::read_err(
+                                anyhow!("Failed to create indexed db transaction: {e}").into(),
+                            )
+                        })
+                } else {
+                    Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
+                }
+            })?;
+
+        let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to create indexed db store: {e}").into())
+        })?;
+
+        let key = JsValue::from(INSTANCE_LOCK_KEY);
+        let read = store
+            .get(&key)
+            .await
+            .map_err(|_| MutinyError::read_err(MutinyStorageError::IndexedDBError))?;
+
+        let result: Option<InstanceLock> = read.into_serde()?;
+
+        Ok(result)
+    }
+
+    async fn try_claim_instance_lock(&self, instance: &str) -> Result<bool, MutinyError> {
+        // read and written in one transaction, indexed db runs read-write transactions
+        // on a store one at a time so two tabs can't both find the lock free
+        let tx = self
+            .indexed_db
+            .try_write()
+            .map_err(|e| MutinyError::read_err(e.into()))
+            .and_then(|indexed_db_lock| {
+                if let Some(indexed_db) = &indexed_db_lock.0 {
+                    indexed_db
+                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadWrite)
+                        .map_err(|e| {
+                            MutinyError::read_err(
+                                anyhow!("Failed to create indexed db transaction: {e}").into(),
+                            )
+                        })
+                } else {
+                    Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
+                }
+            })?;
+
+        let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to create indexed db store: {e}").into())
+        })?;
+
+        let key = JsValue::from(INSTANCE_LOCK_KEY);
+        let read = store
+            .get(&key)
+            .await
+            .map_err(|_| MutinyError::read_err(MutinyStorageError::IndexedDBError))?;
+        let current: Option<InstanceLock> = read.into_serde()?;
+        if current.is_some_and(|lock| lock.is_locked(instance)) {
+            tx.done()
+                .await
+                .map_err(|_| MutinyError::read_err(MutinyStorageError::IndexedDBError))?;
+            return Ok(false);
+        }
+
+        let lock = InstanceLock {
+            time: utils::now().as_secs() as u32,
+            instance: instance.to_string(),
+        };
+        let value = serde_json::to_value(&lock)?;
+        store
+            .put(&JsValue::from_serde(&value)?, Some(&key))
+            .await
+            .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+        tx.done()
+            .await
+            .map_err(|_| MutinyError::write_err(MutinyStorageError::IndexedDBError))?;
+
+        let mut map = self
+            .memory
+            .try_write()
+            .map_err(|e| MutinyError::write_err(e.into()))?;
+        map.insert(INSTANCE_LOCK_KEY.to_string(), value);
+
+        Ok(true)
+    }
+
+    async fn value_sizes(&self) -> Result<HashMap<String, u64>, MutinyError> {
+        // read from indexed db, some values like the network graph aren't kept in memory
+        let tx = self
+            .indexed_db
+            .try_read()
+            .map_err(|e| MutinyError::read_err(e.into()))
+            .and_then(|indexed_db_lock| {
+                if let Some(indexed_db) = &indexed_db_lock.0 {
+                    indexed_db
+                        .transaction(&[WALLET_OBJECT_STORE_NAME], TransactionMode::ReadOnly)
+                        .map_err(|e| {
+                            MutinyError::read_err(
+                                anyhow!("Failed to create indexed db transaction: {e}").into(),
+                            )
+                        })
+                } else {
+                    Err(MutinyError::read_err(MutinyStorageError::IndexedDBError))
+                }
+            })?;
+
+        let store = tx.store(WALLET_OBJECT_STORE_NAME).map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to create indexed db store: {e}").into())
+        })?;
+
+        let all_json = store.get_all(None, None, None, None).await.map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to get all from store: {e}").into())
+        })?;
+
+        let mut sizes = HashMap::with_capacity(all_json.len());
+        for (key, value) in all_json {
+            let Some(key) = key.as_string() else {
+                continue;
+            };
+            let json: Value = value.into_serde()?;
+            sizes.insert(key, serde_json::to_vec(&json)?.len() as u64);
+        }
+
+        Ok(sizes)
+    }
+
     fn get_delayed_objects(&self) -> Arc<Mutex<HashMap<String, DelayedKeyValueItem>>> {
         self.delayed_keys.clone()
     }
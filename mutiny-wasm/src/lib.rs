@@ -7,46 +7,83 @@
 
 extern crate mutiny_core;
 
+mod channel_acceptor;
 pub mod error;
 mod indexed_db;
 mod models;
 mod utils;
 
+use crate::channel_acceptor::js_channel_acceptor;
 use crate::error::MutinyJsError;
 use crate::indexed_db::IndexedDbStorage;
 use crate::models::*;
 use bip39::Mnemonic;
-use bitcoin::bip32::Xpriv;
+use bitcoin::bip32::{Xpriv, Xpub};
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::sha256;
-use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
 use bitcoin::{Address, Network, OutPoint, Txid};
 use futures::lock::Mutex;
 use gloo_utils::format::JsValueSerdeExt;
 
+use lightning::ln::types::ChannelId;
+use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::{log_info, log_warn, routing::gossip::NodeId, util::logger::Logger};
 use lightning_invoice::Bolt11Invoice;
 
+use mutiny_core::allowlist::AllowlistEntry;
 use mutiny_core::authclient::MutinyAuthClient;
 use mutiny_core::authmanager::AuthManager;
+use mutiny_core::badges::BadgeSettings;
+use mutiny_core::denominations::{
+    fiat_to_sats, sats_to_fiat, Denomination, DenominationPreferences,
+};
+use mutiny_core::descriptionprivacy::DescriptionPrivacy;
 use mutiny_core::encrypt::decrypt_with_password;
 use mutiny_core::error::MutinyError;
+use mutiny_core::idle::IdleSettings;
+use mutiny_core::inboundchannels::InboundChannelPolicy;
+use mutiny_core::jobs::JobKind;
+use mutiny_core::lnaddress::AliasRotationPolicy;
 use mutiny_core::messagehandler::CommonLnEventCallback;
+use mutiny_core::messages::{self, MessageCatalog};
+use mutiny_core::moderation::{DirectMessage, ModerationSettings};
+use mutiny_core::mpp::{MppConfig, MppStrategy};
+use mutiny_core::paymentcard::PaymentCardUpdate;
+use mutiny_core::paymentpolicy::PaymentPolicy;
+use mutiny_core::paymentrequest::PaymentRequest;
+use mutiny_core::peeroverrides::PeerOverrides;
+use mutiny_core::permissions::AppPermission;
+use mutiny_core::qr;
+use mutiny_core::remoteconfig::RemoteConfigOverrides;
+use mutiny_core::routehints::RouteHintSelection;
+use mutiny_core::settingssync::{WalletSettings, DEFAULT_SETTINGS_RELAYS};
+use mutiny_core::socialrecovery;
 use mutiny_core::storage::{DeviceLock, MutinyStorage, DEVICE_LOCK_KEY};
-use mutiny_core::utils::sleep;
+use mutiny_core::utils::{now, sleep};
+use mutiny_core::validation::{self, Validation};
 use mutiny_core::vss::MutinyVssClient;
 use mutiny_core::MutinyWalletBuilder;
 use mutiny_core::{
     encrypt::{encrypt, encryption_key_from_pass},
-    InvoiceHandler, MutinyWalletConfigBuilder,
+    FeeSource, InvoiceHandler, MutinyWalletConfigBuilder,
 };
 use mutiny_core::{
     labels::LabelStorage,
-    nodemanager::{create_lsp_config, NodeManager},
+    nodemanager::{
+        create_lsp_config, AddressType, CoinSelectionStrategy, ConsolidationConfig, NodeManager,
+    },
+};
+use mutiny_core::{
+    logging::MutinyLogger,
+    lsp::{LspConfig, LspList},
 };
-use mutiny_core::{logging::MutinyLogger, lsp::LspConfig};
 use web_sys::BroadcastChannel;
 
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
@@ -105,6 +142,20 @@ impl MutinyWallet {
         blind_auth_url: Option<String>,
         hermes_url: Option<String>,
         ln_event_topic: Option<String>,
+        memory_budget_bytes: Option<u64>,
+        chain_snapshot_url: Option<String>,
+        chain_snapshot_key: Option<String>,
+        coin_selection: Option<String>,
+        gap_limit: Option<u32>,
+        compact_filter_url: Option<String>,
+        fallback_esplora_urls: Option<Vec<String>>,
+        mempool_fee_url: Option<String>,
+        static_fee_rate: Option<f64>,
+        consolidation_fee_rate: Option<u64>,
+        consolidation_max_utxo_sats: Option<u64>,
+        mpp_strategy: Option<String>,
+        remote_config_url: Option<String>,
+        remote_config_key: Option<String>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         let start = instant::Instant::now();
 
@@ -157,6 +208,20 @@ impl MutinyWallet {
             blind_auth_url,
             hermes_url,
             ln_event_callback,
+            memory_budget_bytes,
+            chain_snapshot_url,
+            chain_snapshot_key,
+            coin_selection,
+            gap_limit,
+            compact_filter_url,
+            fallback_esplora_urls,
+            mempool_fee_url,
+            static_fee_rate,
+            consolidation_fee_rate,
+            consolidation_max_utxo_sats,
+            mpp_strategy,
+            remote_config_url,
+            remote_config_key,
         )
         .await
         {
@@ -201,6 +266,20 @@ impl MutinyWallet {
         blind_auth_url: Option<String>,
         hermes_url: Option<String>,
         ln_event_callback: Option<CommonLnEventCallback>,
+        memory_budget_bytes: Option<u64>,
+        chain_snapshot_url: Option<String>,
+        chain_snapshot_key: Option<String>,
+        coin_selection: Option<String>,
+        gap_limit: Option<u32>,
+        compact_filter_url: Option<String>,
+        fallback_esplora_urls: Option<Vec<String>>,
+        mempool_fee_url: Option<String>,
+        static_fee_rate: Option<f64>,
+        consolidation_fee_rate: Option<u64>,
+        consolidation_max_utxo_sats: Option<u64>,
+        mpp_strategy: Option<String>,
+        remote_config_url: Option<String>,
+        remote_config_key: Option<String>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         let safe_mode = safe_mode.unwrap_or(false);
         let logger = Arc::new(MutinyLogger::memory_only());
@@ -279,8 +358,16 @@ impl MutinyWallet {
                 &xprivkey.private_key,
                 logger.clone(),
             )
-            .await?
-            {
+            .await
+            .unwrap_or_else(|e| {
+                // keep using the old storage if we can't tell, its writes are
+                // queued until it is back and we check again on the next start
+                log_warn!(
+                    logger,
+                    "Could not check storage url, assuming it is still used: {e}"
+                );
+                true
+            }) {
                 let vss = storage_url.map(|url| {
                     Arc::new(MutinyVssClient::new_unauthenticated(
                         url,
@@ -314,6 +401,9 @@ impl MutinyWallet {
         if let Some(url) = user_esplora_url {
             config_builder.with_user_esplora_url(url);
         }
+        if let Some(urls) = fallback_esplora_urls {
+            config_builder.with_fallback_esplora_urls(urls);
+        }
         if let Some(url) = user_rgs_url {
             config_builder.with_user_rgs_url(url);
         }
@@ -350,6 +440,45 @@ impl MutinyWallet {
         if let Some(true) = do_not_bump_channel_close_tx {
             config_builder.do_not_bump_channel_close_tx();
         }
+        if let Some(budget) = memory_budget_bytes {
+            config_builder.with_memory_budget_bytes(budget);
+        }
+        if let (Some(url), Some(key)) = (chain_snapshot_url, chain_snapshot_key) {
+            let key = XOnlyPublicKey::from_str(&key)?;
+            config_builder.with_chain_snapshot(url, key);
+        }
+        if let Some(coin_selection) = coin_selection {
+            config_builder.with_coin_selection(CoinSelectionStrategy::from_str(&coin_selection)?);
+        }
+        if let Some(gap_limit) = gap_limit {
+            config_builder.with_gap_limit(gap_limit as usize);
+        }
+        if let Some(url) = compact_filter_url {
+            config_builder.with_compact_filter_url(url);
+        }
+        if let Some(sats_per_vbyte) = static_fee_rate {
+            config_builder.with_fee_source(FeeSource::Static(sats_per_vbyte));
+        } else if let Some(url) = mempool_fee_url {
+            config_builder.with_fee_source(FeeSource::MempoolSpace(url));
+        }
+        // consolidation is opt in, turned on by setting the fee rate to do it at
+        if let Some(max_fee_rate) = consolidation_fee_rate {
+            let mut consolidation = ConsolidationConfig {
+                max_fee_rate,
+                ..Default::default()
+            };
+            if let Some(max_utxo_sats) = consolidation_max_utxo_sats {
+                consolidation.max_utxo_sats = max_utxo_sats;
+            }
+            config_builder.with_consolidation(consolidation);
+        }
+        if let Some(mpp_strategy) = mpp_strategy {
+            config_builder.with_mpp_strategy(MppStrategy::from_str(&mpp_strategy)?);
+        }
+        if let (Some(url), Some(key)) = (remote_config_url, remote_config_key) {
+            let key = XOnlyPublicKey::from_str(&key)?;
+            config_builder.with_remote_config(url, key);
+        }
         if safe_mode {
             config_builder.with_safe_mode();
         }
@@ -372,6 +501,8 @@ impl MutinyWallet {
             .node_manager
             .as_ref()
             .ok_or(MutinyError::NotRunning)?;
+        // any call that reaches the nodes counts as the user being back
+        node_manager.record_interaction();
         Ok(node_manager)
     }
 
@@ -379,6 +510,12 @@ impl MutinyWallet {
         self.inner.is_safe_mode()
     }
 
+    /// Returns true if the wallet is already open elsewhere on this device (e.g. another tab)
+    /// and this instance was started read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
     #[wasm_bindgen]
     pub async fn get_version() -> String {
         let version = env!("CARGO_PKG_VERSION");
@@ -508,6 +645,19 @@ impl MutinyWallet {
         Ok(self.inner.stop().await?)
     }
 
+    /// Pauses the wallet when the page is backgrounded or frozen.
+    /// Peers are disconnected cleanly and all state is persisted.
+    #[wasm_bindgen]
+    pub async fn prepare_for_sleep(&mut self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.prepare_for_sleep().await?)
+    }
+
+    /// Resumes the wallet after `prepare_for_sleep`.
+    #[wasm_bindgen]
+    pub async fn resume(&mut self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.resume().await?)
+    }
+
     /// Returns the mnemonic seed phrase for the wallet.
     #[wasm_bindgen]
     pub fn show_seed(&self) -> String {
@@ -524,12 +674,21 @@ impl MutinyWallet {
     /// Will generate a new address on every call.
     ///
     /// It is recommended to create a new address for every transaction.
+    ///
+    /// The address type can be "p2tr" or "p2wpkh", the wallet's default is used if not set.
     #[wasm_bindgen]
     pub async fn get_new_address(
         &self,
         labels: Vec<String>,
+        address_type: Option<String>,
     ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
-        let address = self.inner.create_address(labels.clone()).await?;
+        let address_type = address_type
+            .map(|t| AddressType::from_str(&t))
+            .transpose()?;
+        let address = self
+            .inner
+            .create_address(labels.clone(), address_type)
+            .await?;
         Ok(MutinyBip21RawMaterials {
             address: address.to_string(),
             invoice: None,
@@ -565,19 +724,83 @@ impl MutinyWallet {
     ///
     /// If the server returns a status of 500 with a different error message,
     /// a [`MutinyJsError::LspGenericError`] is returned.
+    ///
+    /// The address type can be "p2tr" or "p2wpkh", the wallet's default is used if not set.
     #[wasm_bindgen]
     pub async fn create_bip21(
         &self,
         amount: Option<u64>,
         labels: Vec<String>,
+        address_type: Option<String>,
     ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
-        Ok(self.inner.create_bip21(amount, labels).await?.into())
+        let address_type = address_type
+            .map(|t| AddressType::from_str(&t))
+            .transpose()?;
+        Ok(self
+            .inner
+            .create_bip21(amount, labels, address_type)
+            .await?
+            .into())
+    }
+
+    /// Gets the address type new receive addresses use by default, "Taproot" or "SegwitV0"
+    #[wasm_bindgen]
+    pub fn get_default_address_type(&self) -> Result<String, MutinyJsError> {
+        let address_type = self.get_node_manager()?.get_default_address_type()?;
+        Ok(format!("{address_type:?}"))
+    }
+
+    /// Sets the address type new receive addresses use by default, "p2tr" or "p2wpkh"
+    #[wasm_bindgen]
+    pub fn set_default_address_type(&self, address_type: String) -> Result<(), MutinyJsError> {
+        let address_type = AddressType::from_str(&address_type)?;
+        Ok(self
+            .get_node_manager()?
+            .set_default_address_type(address_type)?)
+    }
+
+    /// Whether one of our addresses has already received funds. Giving out a used
+    /// address links the payments together, so warn before putting it in a BIP21.
+    #[wasm_bindgen]
+    pub fn is_address_reused(&self, address: String) -> Result<bool, MutinyJsError> {
+        let address = Address::from_str(&address)?.assume_checked();
+        Ok(self.get_node_manager()?.is_address_reused(&address)?)
+    }
+
+    /// Signs a message with one of our addresses to prove we own it.
+    /// Returns a base64 BIP-322 simple signature.
+    #[wasm_bindgen]
+    pub fn sign_message_bip322(
+        &self,
+        address: String,
+        message: String,
+    ) -> Result<String, MutinyJsError> {
+        let address = Address::from_str(&address)?.assume_checked();
+        Ok(self
+            .get_node_manager()?
+            .sign_message_bip322(&address, &message)?)
+    }
+
+    /// Verifies a base64 BIP-322 simple signature of the message for the address.
+    #[wasm_bindgen]
+    pub fn verify_message_bip322(
+        &self,
+        address: String,
+        message: String,
+        signature: String,
+    ) -> Result<bool, MutinyJsError> {
+        let address = Address::from_str(&address)?.assume_checked();
+        Ok(self
+            .get_node_manager()?
+            .verify_message_bip322(&address, &message, &signature)?)
     }
 
     /// Sends an on-chain transaction to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     ///
     /// If a fee rate is not provided, one will be used from the fee estimator.
+    ///
+    /// If any utxos are provided (as `txid:vout`) only those will be spent.
     #[wasm_bindgen]
     pub async fn send_to_address(
         &self,
@@ -585,12 +808,129 @@ impl MutinyWallet {
         amount: u64,
         labels: Vec<String>,
         fee_rate: Option<u64>,
+        utxos: Option<Vec<String>>,
     ) -> Result<String, MutinyJsError> {
         let send_to =
             Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
+        let utxos = utxos
+            .unwrap_or_default()
+            .iter()
+            .map(|o| OutPoint::from_str(o).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self
+            .inner
+            .send_to_address(send_to, amount, labels, fee_rate, utxos)
+            .await?
+            .to_string())
+    }
+
+    /// Creates an unsigned PSBT sending to the given address, to be signed outside
+    /// the wallet, e.g. by a hardware wallet. The session is kept until broadcast
+    /// so signing can continue after a reload.
+    #[wasm_bindgen]
+    pub fn create_psbt(
+        &self,
+        destination_address: String,
+        amount: u64,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+        utxos: Option<Vec<String>>,
+    ) -> Result<JsValue /* PsbtSession */, MutinyJsError> {
+        let send_to =
+            Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
+        let utxos = utxos
+            .unwrap_or_default()
+            .iter()
+            .map(|o| OutPoint::from_str(o).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .create_psbt(send_to, amount, labels, fee_rate, utxos)?,
+        )?)
+    }
+
+    /// Imports a base64 PSBT signed elsewhere, merging its signatures
+    /// into the session for the same transaction.
+    #[wasm_bindgen]
+    pub fn import_psbt(&self, psbt: String) -> Result<JsValue /* PsbtSession */, MutinyJsError> {
+        let psbt = Psbt::from_str(&psbt).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.import_psbt(psbt)?,
+        )?)
+    }
+
+    /// Gets the public receive and change descriptors of the on-chain wallet,
+    /// used to start a [WatchOnlyWallet] whose PSBTs this wallet can sign.
+    #[wasm_bindgen]
+    pub fn get_watch_only_descriptors(&self) -> Result<Vec<String>, MutinyJsError> {
+        let (descriptor, change_descriptor) =
+            self.get_node_manager()?.get_watch_only_descriptors()?;
+        Ok(vec![descriptor, change_descriptor])
+    }
+
+    /// Imports a base64 PSBT and adds the wallet's signatures to it.
+    #[wasm_bindgen]
+    pub fn sign_psbt(&self, psbt: String) -> Result<JsValue /* PsbtSession */, MutinyJsError> {
+        let psbt = Psbt::from_str(&psbt).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.sign_psbt(psbt)?,
+        )?)
+    }
+
+    /// Broadcasts the transaction of a fully signed PSBT session, returns its txid.
+    #[wasm_bindgen]
+    pub async fn broadcast_psbt(&self, id: String) -> Result<String, MutinyJsError> {
+        let id = Txid::from_str(&id)?;
+        Ok(self
+            .get_node_manager()?
+            .broadcast_psbt(id)
+            .await?
+            .to_string())
+    }
+
+    /// Lists all PSBT sessions, most recently updated first.
+    #[wasm_bindgen]
+    pub fn list_psbt_sessions(&self) -> Result<JsValue /* Vec<PsbtSession> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_psbt_sessions()?,
+        )?)
+    }
+
+    /// Sends to several addresses in a single transaction.
+    /// `amounts` are in sats and line up with `addresses`, as do `labels` if given,
+    /// one label per recipient with an empty string for no label.
+    /// The fee rate is in sat/vbyte.
+    #[wasm_bindgen]
+    pub async fn send_batch(
+        &self,
+        addresses: Vec<String>,
+        amounts: Vec<u64>,
+        labels: Option<Vec<String>>,
+        fee_rate: Option<u64>,
+    ) -> Result<String, MutinyJsError> {
+        let labels = labels.unwrap_or_else(|| vec![String::new(); addresses.len()]);
+        if amounts.len() != addresses.len() || labels.len() != addresses.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let recipients = addresses
+            .iter()
+            .zip(amounts)
+            .zip(labels)
+            .map(|((address, amount), label)| {
+                let address =
+                    Address::from_str(address)?.require_network(self.inner.get_network())?;
+                let labels = if label.is_empty() {
+                    vec![]
+                } else {
+                    vec![label]
+                };
+                Ok((address, amount, labels))
+            })
+            .collect::<Result<Vec<_>, MutinyJsError>>()?;
         Ok(self
             .inner
-            .send_to_address(send_to, amount, labels, fee_rate)
+            .send_batch(recipients, fee_rate)
             .await?
             .to_string())
     }
@@ -616,6 +956,120 @@ impl MutinyWallet {
             .to_string())
     }
 
+    /// Sweeps all the coins held by a WIF encoded private key, such as a paper wallet,
+    /// into this wallet. The fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    #[wasm_bindgen]
+    pub async fn sweep_private_key(
+        &self,
+        wif: String,
+        fee_rate: Option<u64>,
+    ) -> Result<String, MutinyJsError> {
+        Ok(self
+            .get_node_manager()?
+            .sweep_private_key(wif, fee_rate)
+            .await?
+            .to_string())
+    }
+
+    /// Sweeps all the funds from the wallet to the given address as a background job.
+    /// The job is persisted so it will still run if the page is reloaded before it starts.
+    #[wasm_bindgen]
+    pub fn submit_sweep_job(
+        &self,
+        destination_address: String,
+        fee_rate: Option<u64>,
+    ) -> Result<JsValue /* Job */, MutinyJsError> {
+        // validate the address before queuing the job
+        Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
+        let kind = JobKind::Sweep {
+            destination: destination_address,
+            fee_rate,
+        };
+        Ok(JsValue::from_serde(&self.inner.submit_job(kind)?)?)
+    }
+
+    /// Rescans the on-chain wallet in the background to find funds a normal sync
+    /// missed, skipping transactions confirmed before the start height.
+    /// The gap limit defaults to the one the wallet was started with.
+    ///
+    /// This can take minutes, follow the returned job's progress with `get_job`.
+    #[wasm_bindgen]
+    pub fn rescan_onchain(
+        &self,
+        start_height: u32,
+        gap_limit: Option<u32>,
+    ) -> Result<JsValue /* Job */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.rescan_onchain(
+            start_height,
+            gap_limit.map(|g| g as usize),
+        )?)?)
+    }
+
+    /// Grants an embedding app access to the wallet.
+    /// Permissions are any of "Read", "Receive" and "Spend", spending is capped
+    /// at `spend_limit_sats` in total if given.
+    /// Returns the grant and the api key, the key cannot be retrieved again.
+    #[wasm_bindgen]
+    pub fn create_app_grant(
+        &self,
+        name: String,
+        permissions: Vec<String>,
+        spend_limit_sats: Option<u64>,
+    ) -> Result<JsValue /* { grant: AppGrant, api_key: String } */, MutinyJsError> {
+        let permissions = permissions
+            .iter()
+            .map(|p| AppPermission::from_str(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (grant, api_key) = self
+            .inner
+            .create_app_grant(name, permissions, spend_limit_sats)?;
+        Ok(JsValue::from_serde(&serde_json::json!({
+            "grant": grant,
+            "api_key": api_key,
+        }))?)
+    }
+
+    /// Lists all app grants, newest first.
+    #[wasm_bindgen]
+    pub fn list_app_grants(&self) -> Result<JsValue /* Vec<AppGrant> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_app_grants()?)?)
+    }
+
+    /// Revokes an app's access.
+    #[wasm_bindgen]
+    pub fn revoke_app_grant(&self, id: String) -> Result<JsValue /* AppGrant */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.revoke_app_grant(&id)?)?)
+    }
+
+    /// Opens a session limited to what the api key has been granted,
+    /// to be handed to an embedded app instead of the wallet.
+    #[wasm_bindgen]
+    pub fn open_app_session(&self, api_key: String) -> Result<AppSession, MutinyJsError> {
+        Ok(AppSession {
+            inner: self.inner.open_app_session(api_key)?,
+        })
+    }
+
+    /// Lists all background jobs, newest first.
+    #[wasm_bindgen]
+    pub fn list_jobs(&self) -> Result<JsValue /* Vec<Job> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_jobs()?)?)
+    }
+
+    /// Gets a background job by its id.
+    #[wasm_bindgen]
+    pub fn get_job(&self, id: String) -> Result<JsValue /* Option<Job> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_job(&id)?)?)
+    }
+
+    /// Cancels a background job if it has not finished yet.
+    #[wasm_bindgen]
+    pub fn cancel_job(&self, id: String) -> Result<JsValue /* Job */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.cancel_job(&id)?)?)
+    }
+
     /// Constructs a sweep transaction to move all funds from the wallet to the given address.
     /// The fee rate is in sat/vbyte.
     ///
@@ -710,6 +1164,31 @@ impl MutinyWallet {
         Ok(result.to_string())
     }
 
+    /// Speeds up an unconfirmed transaction paying us with a child transaction
+    /// spending our output (CPFP), so both together pay the given fee rate in sats/vbyte.
+    /// Returns the txid of the child transaction.
+    pub async fn accelerate_tx(
+        &self,
+        txid: String,
+        fee_rate: u64,
+    ) -> Result<String, MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        let result = self
+            .get_node_manager()?
+            .accelerate_tx(txid, fee_rate)
+            .await?;
+
+        Ok(result.to_string())
+    }
+
+    /// Merges small coins into one output now if fees are low enough.
+    /// Returns the txid, or nothing if there was nothing worth consolidating.
+    #[wasm_bindgen]
+    pub async fn consolidate_utxos(&self) -> Result<Option<String>, MutinyJsError> {
+        let txid = self.get_node_manager()?.consolidate_utxos().await?;
+        Ok(txid.map(|txid| txid.to_string()))
+    }
+
     /// Checks if the given address has any transactions.
     /// If it does, it returns the details of the first transaction.
     ///
@@ -735,6 +1214,24 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&self.inner.get_transaction(txid)?)?)
     }
 
+    /// Broadcasts a hex encoded transaction built outside the wallet, returns its txid.
+    #[wasm_bindgen]
+    pub async fn broadcast_raw_tx(&self, hex: String) -> Result<String, MutinyJsError> {
+        Ok(self
+            .get_node_manager()?
+            .broadcast_raw_tx(&hex)
+            .await?
+            .to_string())
+    }
+
+    /// Gets the hex encoded transaction with the given txid, if it can be found.
+    #[wasm_bindgen]
+    pub async fn get_raw_tx(&self, txid: String) -> Result<Option<String>, MutinyJsError> {
+        let txid = Txid::from_str(&txid)?;
+        let tx = self.get_node_manager()?.get_raw_tx(txid).await?;
+        Ok(tx.map(|tx| bitcoin::consensus::encode::serialize_hex(&tx)))
+    }
+
     /// Gets the current balance of the wallet.
     /// This includes both on-chain and lightning funds.
     ///
@@ -744,344 +1241,1619 @@ impl MutinyWallet {
         Ok(self.inner.get_balance().await?.into())
     }
 
-    /// Lists all the UTXOs in the wallet.
+    /// Gets the balance broken down by node, including the amount
+    /// reserved in channels that cannot be spent.
     #[wasm_bindgen]
-    pub fn list_utxos(&self) -> Result<JsValue, MutinyJsError> {
+    pub async fn get_detailed_balance(
+        &self,
+    ) -> Result<JsValue /* MutinyDetailedBalance */, MutinyJsError> {
         Ok(JsValue::from_serde(
-            &self.get_node_manager()?.list_utxos()?,
+            &self.inner.get_detailed_balance().await?,
         )?)
     }
 
-    /// Gets a fee estimate for an low priority transaction.
-    /// Value is in sat/vbyte.
+    /// Gets how many bytes are used in storage by channel monitors,
+    /// the network graph, payment history and other data.
     #[wasm_bindgen]
-    pub fn estimate_fee_low(&self) -> Result<u32, MutinyJsError> {
-        Ok(self.get_node_manager()?.estimate_fee_low())
+    pub async fn get_storage_breakdown(
+        &self,
+    ) -> Result<JsValue /* StorageBreakdown */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_storage_breakdown().await?,
+        )?)
     }
 
-    /// Gets a fee estimate for an average priority transaction.
-    /// Value is in sat/vbyte.
+    /// Gets when the channel state last changed and when it was last exported and verified.
     #[wasm_bindgen]
-    pub fn estimate_fee_normal(&self) -> Result<u32, MutinyJsError> {
-        Ok(self.get_node_manager()?.estimate_fee_normal())
+    pub fn get_backup_status(&self) -> Result<JsValue /* BackupStatus */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_backup_status()?,
+        )?)
     }
 
-    /// Gets a fee estimate for an high priority transaction.
-    /// Value is in sat/vbyte.
+    /// Checks that a backup from `export_json` contains the latest channel state.
+    /// Backup reminders stop until the channel state changes again if it does.
     #[wasm_bindgen]
-    pub fn estimate_fee_high(&self) -> Result<u32, MutinyJsError> {
-        Ok(self.get_node_manager()?.estimate_fee_high())
+    pub fn verify_backup(&self, json: String) -> Result<bool, MutinyJsError> {
+        let backup: serde_json::Value = serde_json::from_str(&json)?;
+        Ok(self.get_node_manager()?.verify_backup(&backup)?)
     }
 
-    /// Creates a new lightning node and adds it to the manager.
+    /// Publishes a signed nostr event (as JSON) to the given relays. If no relay
+    /// accepts it now it is kept and retried in the background.
+    /// Returns whether a relay accepted it right away.
     #[wasm_bindgen]
-    pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
-        Ok(self.get_node_manager()?.new_node().await?.into())
+    pub async fn publish_nostr_event(
+        &self,
+        event: String,
+        relays: Vec<String>,
+    ) -> Result<bool, MutinyJsError> {
+        let event: serde_json::Value = serde_json::from_str(&event)?;
+        Ok(self
+            .get_node_manager()?
+            .publish_nostr_event(event, relays)
+            .await?)
     }
 
-    /// Lists the pubkeys of the lightning node in the manager.
+    /// Nostr events that no relay has accepted yet, oldest first.
     #[wasm_bindgen]
-    pub async fn list_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+    pub fn pending_nostr_events(&self) -> Result<JsValue /* Vec<OutboxEvent> */, MutinyJsError> {
         Ok(JsValue::from_serde(
-            &self.get_node_manager()?.list_nodes().await?,
+            &self.get_node_manager()?.pending_nostr_events()?,
         )?)
     }
 
-    /// Changes all the node's LSPs to the given config. If any of the nodes have an active channel with the
-    /// current LSP, it will fail to change the LSP.
-    ///
-    /// Requires a restart of the node manager to take effect.
-    pub async fn change_lsp(
-        &self,
-        lsp_url: Option<String>,
-        lsp_connection_string: Option<String>,
-        lsp_token: Option<String>,
-    ) -> Result<(), MutinyJsError> {
-        let lsp_config = create_lsp_config(lsp_url, lsp_connection_string, lsp_token)?;
-
-        self.get_node_manager()?.change_lsp(lsp_config).await?;
-        Ok(())
-    }
-
-    /// Returns the current LSP config
-    pub async fn get_configured_lsp(&self) -> Result<JsValue, MutinyJsError> {
-        match self.get_node_manager()?.get_configured_lsp().await? {
-            Some(LspConfig::VoltageFlow(config)) => Ok(JsValue::from_serde(&config)?),
-            Some(LspConfig::Lsps(config)) => Ok(JsValue::from_serde(&config)?),
-            None => Ok(JsValue::NULL),
-        }
+    /// Estimates how much memory the network graph, activity index and logs are using.
+    #[wasm_bindgen]
+    pub fn get_memory_usage(&self) -> Result<JsValue /* MemoryUsage */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_memory_usage()?,
+        )?)
     }
 
-    /// Attempts to connect to a peer from the selected node.
+    /// Frees memory held by stale network graph entries and older logs.
+    /// Useful when the browser signals memory pressure.
     #[wasm_bindgen]
-    pub async fn connect_to_peer(
-        &self,
-        connection_string: String,
-        label: Option<String>,
-    ) -> Result<(), MutinyJsError> {
-        Ok(self
-            .get_node_manager()?
-            .connect_to_peer(None, &connection_string, label)
-            .await?)
+    pub fn trim_memory(&self) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.trim_memory()?)
     }
 
-    /// Disconnects from a peer from the selected node.
+    /// Lists all the UTXOs in the wallet with their confirmations, address and labels.
     #[wasm_bindgen]
-    pub async fn disconnect_peer(&self, peer: String) -> Result<(), MutinyJsError> {
-        let peer = PublicKey::from_str(&peer)?;
-        Ok(self.get_node_manager()?.disconnect_peer(None, peer).await?)
+    pub fn list_utxos(&self) -> Result<JsValue /* Vec<MutinyUtxo> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_utxos()?,
+        )?)
     }
 
-    /// Deletes a peer from the selected node.
-    /// This will make it so that the node will not attempt to
-    /// reconnect to the peer.
+    /// Freezes a UTXO (as `txid:vout`) so it isn't used by automatic coin selection
+    /// when sending or opening channels. It can still be spent by selecting it manually.
     #[wasm_bindgen]
-    pub async fn delete_peer(&self, peer: String) -> Result<(), MutinyJsError> {
-        let peer = NodeId::from_str(&peer).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
-        Ok(self.get_node_manager()?.delete_peer(None, &peer).await?)
+    pub fn freeze_utxo(&self, outpoint: String) -> Result<(), MutinyJsError> {
+        let outpoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.get_node_manager()?.freeze_utxo(outpoint)?)
     }
 
-    /// Sets the label of a peer from the selected node.
+    /// Lets a frozen UTXO (as `txid:vout`) be used by automatic coin selection again.
     #[wasm_bindgen]
-    pub fn label_peer(&self, node_id: String, label: Option<String>) -> Result<(), MutinyJsError> {
-        let node_id =
-            NodeId::from_str(&node_id).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
-        self.get_node_manager()?.label_peer(&node_id, label)?;
-        Ok(())
+    pub fn unfreeze_utxo(&self, outpoint: String) -> Result<(), MutinyJsError> {
+        let outpoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.get_node_manager()?.unfreeze_utxo(outpoint)?)
     }
 
-    /// Creates a lightning invoice. The amount should be in satoshis.
-    /// If no amount is provided, the invoice will be created with no amount.
-    /// If no description is provided, the invoice will be created with no description.
-    ///
-    /// If the manager has more than one node it will create a phantom invoice.
-    /// If there is only one node it will create an invoice just for that node.
+    /// Gets the vault's delay, any pending shorter delay, and the coins in it.
     #[wasm_bindgen]
-    pub async fn create_invoice(
-        &self,
-        amount: u64,
-        label: String,
-        expiry_delta_secs: Option<u32>,
-    ) -> Result<MutinyInvoice, MutinyJsError> {
-        Ok(self
-            .inner
-            .create_invoice(amount, vec![label], expiry_delta_secs)
-            .await?
-            .into())
+    pub fn get_vault(&self) -> Result<JsValue /* Vault */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.get_node_manager()?.get_vault()?)?)
     }
 
-    /// Pays a lightning invoice from the selected node.
-    /// An amount should only be provided if the invoice does not have an amount.
-    /// The amount should be in satoshis.
+    /// How much is in the vault, in sats.
     #[wasm_bindgen]
-    pub async fn pay_invoice(
-        &self,
-        invoice_str: String,
-        amt_sats: Option<u64>,
-        labels: Vec<String>,
-    ) -> Result<MutinyInvoice, MutinyJsError> {
-        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
-        Ok(self
-            .inner
-            .pay_invoice(&invoice, amt_sats, labels)
-            .await?
-            .into())
+    pub fn get_vault_balance(&self) -> Result<u64, MutinyJsError> {
+        Ok(self.get_node_manager()?.get_vault_balance()?)
     }
 
-    /// Sends a spontaneous payment to a node from the selected node.
-    /// The amount should be in satoshis.
+    /// Sets how many seconds vault withdrawals wait before they're sent, 0 turns
+    /// the vault off. A shorter delay only applies once the current one has passed.
     #[wasm_bindgen]
-    pub async fn keysend(
+    pub fn set_vault_delay(&self, delay_secs: u64) -> Result<JsValue /* Vault */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.set_vault_delay(delay_secs)?,
+        )?)
+    }
+
+    /// Moves the amount of on-chain funds into the vault. Returns the txid.
+    #[wasm_bindgen]
+    pub async fn deposit_to_vault(
         &self,
-        to_node: String,
-        amt_sats: u64,
-        message: Option<String>,
-        labels: Vec<String>,
-    ) -> Result<MutinyInvoice, MutinyJsError> {
-        let to_node = PublicKey::from_str(&to_node)?;
+        amount: u64,
+        fee_rate: Option<u64>,
+    ) -> Result<String, MutinyJsError> {
         Ok(self
             .get_node_manager()?
-            .keysend(None, to_node, amt_sats, message, labels)
+            .deposit_to_vault(amount, fee_rate)
             .await?
-            .into())
+            .to_string())
     }
 
-    /// Decodes a lightning invoice into useful information.
-    /// Will return an error if the invoice is for a different network.
+    /// Asks to send vault funds to the address, all of them if there is no amount.
+    /// It's sent once the vault's delay has passed and can be cancelled until then.
     #[wasm_bindgen]
-    pub async fn decode_invoice(
+    pub fn request_vault_withdrawal(
         &self,
-        invoice: String,
-        network: Option<String>,
-    ) -> Result<MutinyInvoice, MutinyJsError> {
-        let invoice = Bolt11Invoice::from_str(&invoice)?;
-        let network = network
-            .map(|n| Network::from_str(&n).map_err(|_| MutinyJsError::InvalidArgumentsError))
-            .transpose()?;
-        Ok(self.inner.decode_invoice(invoice, network)?.into())
+        destination_address: String,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        fee_rate: Option<u64>,
+    ) -> Result<JsValue /* VaultWithdrawal */, MutinyJsError> {
+        let send_to =
+            Address::from_str(&destination_address)?.require_network(self.inner.get_network())?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .request_vault_withdrawal(send_to, amount, labels, fee_rate)?,
+        )?)
     }
 
-    /// Gets an invoice from the node manager.
-    /// This includes sent and received invoices.
+    /// Cancels a vault withdrawal that hasn't been sent yet.
     #[wasm_bindgen]
-    pub async fn get_invoice(&self, invoice: String) -> Result<MutinyInvoice, MutinyJsError> {
-        let invoice = Bolt11Invoice::from_str(&invoice)?;
-        Ok(self.inner.get_invoice(&invoice).await?.into())
+    pub fn cancel_vault_withdrawal(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* VaultWithdrawal */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.cancel_vault_withdrawal(id)?,
+        )?)
     }
 
-    /// Gets an invoice from the node manager.
-    /// This includes sent and received invoices.
+    /// Lists the vault withdrawals, newest first.
     #[wasm_bindgen]
-    pub async fn get_invoice_by_hash(&self, hash: String) -> Result<MutinyInvoice, MutinyJsError> {
-        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
-        Ok(self.inner.get_invoice_by_hash(&hash).await?.into())
+    pub fn list_vault_withdrawals(
+        &self,
+    ) -> Result<JsValue /* Vec<VaultWithdrawal> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_vault_withdrawals()?,
+        )?)
     }
 
-    /// Gets an invoice from the node manager.
-    /// This includes sent and received invoices.
+    /// Gets the withdrawal allowlist and the changes waiting out its delay.
     #[wasm_bindgen]
-    pub async fn list_invoices(&self) -> Result<JsValue /* Vec<MutinyInvoice> */, MutinyJsError> {
-        Ok(JsValue::from_serde(&self.inner.list_invoices()?)?)
+    pub fn get_withdrawal_allowlist(
+        &self,
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_withdrawal_allowlist()?,
+        )?)
     }
 
-    /// Gets an channel closure from the node manager.
+    /// Turns on the withdrawal allowlist, or changes its delay in seconds if it's on.
+    /// A shorter delay only applies once the current one has passed.
     #[wasm_bindgen]
-    pub async fn get_channel_closure(
+    pub fn enable_withdrawal_allowlist(
         &self,
-        user_channel_id: String,
-    ) -> Result<ChannelClosure, MutinyJsError> {
-        let user_channel_id: [u8; 16] = FromHex::from_hex(&user_channel_id)?;
-        Ok(self
-            .get_node_manager()?
-            .get_channel_closure(u128::from_be_bytes(user_channel_id))
-            .await?
-            .into())
+        delay_secs: u64,
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .enable_withdrawal_allowlist(delay_secs)?,
+        )?)
     }
 
-    /// Gets all channel closures from the node manager.
-    ///
-    /// The channel closures are sorted by the time they were closed.
+    /// Asks to turn off the withdrawal allowlist once its delay has passed.
     #[wasm_bindgen]
-    pub async fn list_channel_closures(
+    pub fn disable_withdrawal_allowlist(
         &self,
-    ) -> Result<JsValue /* Vec<ChannelClosure> */, MutinyJsError> {
-        let mut channel_closures: Vec<ChannelClosure> = self
-            .get_node_manager()?
-            .list_channel_closures()
-            .await?
-            .into_iter()
-            .map(Into::into)
-            .collect();
-        channel_closures.sort();
-        Ok(JsValue::from_serde(&channel_closures)?)
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.disable_withdrawal_allowlist()?,
+        )?)
     }
 
-    /// Opens a channel from our selected node to the given pubkey.
-    /// The amount is in satoshis.
-    ///
-    /// The node must be online and have a connection to the peer.
-    /// The wallet much have enough funds to open the channel.
+    /// Adds an address or xpub to the withdrawal allowlist once its delay has passed.
     #[wasm_bindgen]
-    pub async fn open_channel(
+    pub fn add_allowlist_entry(
         &self,
-        to_pubkey: Option<String>,
-        amount: u64,
-        fee_rate: Option<u64>,
-    ) -> Result<MutinyChannel, MutinyJsError> {
-        let to_pubkey = match to_pubkey {
-            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
-                Some(PublicKey::from_str(&pubkey_str)?)
-            }
-            _ => None,
-        };
-
-        Ok(self
-            .get_node_manager()?
-            .open_channel(None, to_pubkey, amount, fee_rate, None)
-            .await?
-            .into())
+        entry: JsValue, /* AllowlistEntry */
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        let entry: AllowlistEntry = entry.into_serde()?;
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.add_allowlist_entry(entry)?,
+        )?)
     }
 
-    /// Opens a channel from our selected node to the given pubkey.
-    /// It will spend the all the on-chain utxo in full to fund the channel.
-    ///
-    /// The node must be online and have a connection to the peer.
-    pub async fn sweep_all_to_channel(
+    /// Removes an address or xpub from the withdrawal allowlist right away.
+    #[wasm_bindgen]
+    pub fn remove_allowlist_entry(
         &self,
-        to_pubkey: Option<String>,
-    ) -> Result<MutinyChannel, MutinyJsError> {
-        let to_pubkey = match to_pubkey {
-            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
-                Some(PublicKey::from_str(&pubkey_str)?)
-            }
-            _ => None,
-        };
-
-        Ok(self
-            .get_node_manager()?
-            .sweep_all_to_channel(to_pubkey)
-            .await?
-            .into())
+        entry: JsValue, /* AllowlistEntry */
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        let entry: AllowlistEntry = entry.into_serde()?;
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.remove_allowlist_entry(entry)?,
+        )?)
     }
 
-    /// Closes a channel with the given outpoint.
-    ///
-    /// If force is true, the channel will be force closed.
-    ///
-    /// If abandon is true, the channel will be abandoned.
-    /// This will force close without broadcasting the latest transaction.
-    /// This should only be used if the channel will never actually be opened.
-    ///
-    /// If both force and abandon are true, an error will be returned.
-    /// The address must match the network and the network: "bitcoin", "testnet", "signet", "regtest"
-    ///
-    /// if leave target_feerate_sats_per_1000_weight to none, the node will determine a target
-    /// feerate base on current network status
+    /// Drops the withdrawal allowlist changes still waiting out the delay.
     #[wasm_bindgen]
-    pub async fn close_channel(
+    pub fn cancel_pending_allowlist_changes(
         &self,
-        outpoint: String,
-        force: bool,
-        abandon: bool,
-        address: Option<String>,
-        network: Option<String>,
-        target_feerate_sats_per_1000_weight: Option<u32>,
-    ) -> Result<(), MutinyJsError> {
-        let outpoint: OutPoint =
-            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+    ) -> Result<JsValue /* WithdrawalAllowlist */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .cancel_pending_allowlist_changes()?,
+        )?)
+    }
 
-        let network = if let Some(net) = network {
-            Network::from_str(&net).map_err(|_| MutinyJsError::InvalidAddressNetworkError)?
-        } else {
-            Network::Bitcoin
-        };
-        let address = if let Some(addr) = address {
-            let addr_ = Address::from_str(&addr)
-                .map_err(|_| MutinyJsError::InvalidAddressNetworkError)?
-                .require_network(network)
-                .map_err(|_| MutinyJsError::InvalidAddressNetworkError)?;
-            Some(addr_)
-        } else {
-            None
-        };
+    /// Gets a fee estimate for an low priority transaction.
+    /// Value is in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn estimate_fee_low(&self) -> Result<u32, MutinyJsError> {
+        Ok(self.get_node_manager()?.estimate_fee_low())
+    }
 
-        Ok(self
-            .get_node_manager()?
-            .close_channel(
-                &outpoint,
-                address,
-                force,
-                abandon,
-                target_feerate_sats_per_1000_weight,
-            )
-            .await?)
+    /// Gets a fee estimate for an average priority transaction.
+    /// Value is in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn estimate_fee_normal(&self) -> Result<u32, MutinyJsError> {
+        Ok(self.get_node_manager()?.estimate_fee_normal())
     }
 
-    /// Lists all the channels for all the nodes in the node manager.
+    /// Gets a fee estimate for an high priority transaction.
+    /// Value is in sat/vbyte.
     #[wasm_bindgen]
-    pub async fn list_channels(&self) -> Result<JsValue /* Vec<MutinyChannel> */, MutinyJsError> {
+    pub fn estimate_fee_high(&self) -> Result<u32, MutinyJsError> {
+        Ok(self.get_node_manager()?.estimate_fee_high())
+    }
+
+    /// Fee rates seen over the past week, oldest first, for charting.
+    /// Rates are in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn get_fee_history(&self) -> Result<JsValue /* Vec<FeeRateSample> */, MutinyJsError> {
         Ok(JsValue::from_serde(
-            &self.get_node_manager()?.list_channels().await?,
+            &self.get_node_manager()?.get_fee_history()?,
+        )?)
+    }
+
+    /// Suggests whether to send the given amount on-chain now or wait for lower fees
+    #[wasm_bindgen]
+    pub fn suggest_send_timing(
+        &self,
+        amount: u64,
+    ) -> Result<JsValue /* SendTimingSuggestion */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.suggest_send_timing(amount)?,
+        )?)
+    }
+
+    /// Compares the total cost of receiving the given amount over lightning,
+    /// a JIT channel from the LSP, and on-chain. Returned cheapest first.
+    #[wasm_bindgen]
+    pub async fn compare_receive_options(
+        &self,
+        amount_sats: u64,
+    ) -> Result<JsValue /* Vec<ReceiveOption> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .compare_receive_options(amount_sats)
+                .await?,
+        )?)
+    }
+
+    /// Creates a new lightning node and adds it to the manager.
+    #[wasm_bindgen]
+    pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
+        Ok(self.get_node_manager()?.new_node().await?.into())
+    }
+
+    /// Lists the pubkeys of the lightning node in the manager.
+    #[wasm_bindgen]
+    pub async fn list_nodes(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_nodes().await?,
+        )?)
+    }
+
+    /// Changes all the node's LSPs to the given config. If any of the nodes have an active channel with the
+    /// current LSP, it will fail to change the LSP.
+    ///
+    /// Requires a restart of the node manager to take effect.
+    pub async fn change_lsp(
+        &self,
+        lsp_url: Option<String>,
+        lsp_connection_string: Option<String>,
+        lsp_token: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let lsp_config = create_lsp_config(lsp_url, lsp_connection_string, lsp_token)?;
+
+        self.get_node_manager()?.change_lsp(lsp_config).await?;
+        Ok(())
+    }
+
+    /// Returns the current LSP config
+    pub async fn get_configured_lsp(&self) -> Result<JsValue, MutinyJsError> {
+        match self.get_node_manager()?.get_configured_lsp().await? {
+            Some(LspConfig::VoltageFlow(config)) => Ok(JsValue::from_serde(&config)?),
+            Some(LspConfig::Lsps(config)) => Ok(JsValue::from_serde(&config)?),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    /// The LSPs the wallet fails over between, in order of preference, and the
+    /// fee limit that makes it move on
+    #[wasm_bindgen]
+    pub fn get_lsp_list(&self) -> Result<JsValue /* LspList */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_lsp_list()?,
+        )?)
+    }
+
+    /// Sets the LSPs to use in order of preference. When the one in use can't be
+    /// reached or quotes a fee over the limit the wallet switches to the next and
+    /// sends an `LspFailover` event, the switch takes effect after a restart.
+    #[wasm_bindgen]
+    pub fn set_lsp_list(&self, list: JsValue /* LspList */) -> Result<(), MutinyJsError> {
+        let list: LspList = list.into_serde()?;
+        Ok(self.get_node_manager()?.set_lsp_list(list)?)
+    }
+
+    /// Gets the channels an LSPS1 server sells
+    #[wasm_bindgen]
+    pub async fn get_lsps1_info(
+        &self,
+        lsp_url: String,
+    ) -> Result<JsValue /* Lsps1Info */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_lsps1_info(&lsp_url).await?,
+        )?)
+    }
+
+    /// Orders an inbound channel from an LSPS1 server,
+    /// pay for it with `pay_channel_purchase`
+    #[wasm_bindgen]
+    pub async fn buy_inbound_channel(
+        &self,
+        lsp_url: String,
+        lsp_balance_sat: u64,
+        channel_expiry_blocks: Option<u32>,
+        token: Option<String>,
+    ) -> Result<JsValue /* ChannelPurchase */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .buy_inbound_channel(&lsp_url, lsp_balance_sat, channel_expiry_blocks, token)
+                .await?,
+        )?)
+    }
+
+    /// Pays for a channel order over lightning, or on-chain with the optional fee rate
+    #[wasm_bindgen]
+    pub async fn pay_channel_purchase(
+        &self,
+        order_id: String,
+        onchain: bool,
+        fee_rate: Option<u64>,
+    ) -> Result<JsValue /* ChannelPurchase */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .pay_channel_purchase(&order_id, onchain, fee_rate)
+                .await?,
+        )?)
+    }
+
+    /// Gets a channel order with its latest state from the LSP
+    #[wasm_bindgen]
+    pub async fn get_channel_purchase(
+        &self,
+        order_id: String,
+    ) -> Result<JsValue /* Option<ChannelPurchase> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .get_channel_purchase(&order_id)
+                .await?,
+        )?)
+    }
+
+    /// Lists the channel orders, newest first
+    #[wasm_bindgen]
+    pub fn list_channel_purchases(
+        &self,
+    ) -> Result<JsValue /* Vec<ChannelPurchase> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_channel_purchases()?,
+        )?)
+    }
+
+    /// Attempts to connect to a peer from the selected node.
+    #[wasm_bindgen]
+    pub async fn connect_to_peer(
+        &self,
+        connection_string: String,
+        label: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self
+            .get_node_manager()?
+            .connect_to_peer(None, &connection_string, label)
+            .await?)
+    }
+
+    /// Disconnects from a peer from the selected node.
+    #[wasm_bindgen]
+    pub async fn disconnect_peer(&self, peer: String) -> Result<(), MutinyJsError> {
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(self.get_node_manager()?.disconnect_peer(None, peer).await?)
+    }
+
+    /// Deletes a peer from the selected node.
+    /// This will make it so that the node will not attempt to
+    /// reconnect to the peer.
+    #[wasm_bindgen]
+    pub async fn delete_peer(&self, peer: String) -> Result<(), MutinyJsError> {
+        let peer = NodeId::from_str(&peer).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.get_node_manager()?.delete_peer(None, &peer).await?)
+    }
+
+    /// Sets the label of a peer from the selected node.
+    #[wasm_bindgen]
+    pub fn label_peer(&self, node_id: String, label: Option<String>) -> Result<(), MutinyJsError> {
+        let node_id =
+            NodeId::from_str(&node_id).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        self.get_node_manager()?.label_peer(&node_id, label)?;
+        Ok(())
+    }
+
+    /// Creates a lightning invoice. The amount should be in satoshis.
+    /// If no amount is provided, the invoice will be created with no amount.
+    /// If no description is provided, the invoice will be created with no description.
+    ///
+    /// If the manager has more than one node it will create a phantom invoice.
+    /// If there is only one node it will create an invoice just for that node.
+    ///
+    /// The description privacy can be "full", "generic" or "hashed",
+    /// the wallet's default is used if not set.
+    #[wasm_bindgen]
+    pub async fn create_invoice(
+        &self,
+        amount: u64,
+        label: String,
+        expiry_delta_secs: Option<u32>,
+        description_privacy: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let privacy = description_privacy
+            .map(|p| DescriptionPrivacy::from_str(&p))
+            .transpose()?;
+        Ok(self
+            .inner
+            .create_invoice_with_privacy(amount, vec![label], expiry_delta_secs, privacy)
+            .await?
+            .into())
+    }
+
+    /// Creates a lightning invoice with route hints chosen by the caller instead of the node.
+    /// The route hints are either `{ "Channels": [outpoint, ...] }` to only hint those
+    /// channels, none if empty, or `{ "Explicit": [[hop, ...], ...] }` to give the hints as is.
+    /// The description privacy is as in `create_invoice`.
+    #[wasm_bindgen]
+    pub async fn create_invoice_with_route_hints(
+        &self,
+        amount: u64,
+        label: String,
+        expiry_delta_secs: Option<u32>,
+        description_privacy: Option<String>,
+        route_hints: JsValue, /* RouteHintSelection */
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let privacy = description_privacy
+            .map(|p| DescriptionPrivacy::from_str(&p))
+            .transpose()?;
+        let route_hints: RouteHintSelection = route_hints.into_serde()?;
+        Ok(self
+            .inner
+            .create_invoice_with_route_hints(
+                amount,
+                vec![label],
+                expiry_delta_secs,
+                privacy,
+                route_hints,
+            )
+            .await?
+            .into())
+    }
+
+    /// Gets what new invoices put in their description by default, "Full", "Generic" or "Hashed"
+    #[wasm_bindgen]
+    pub fn get_description_privacy(&self) -> Result<String, MutinyJsError> {
+        let privacy = self.get_node_manager()?.get_description_privacy()?;
+        Ok(format!("{privacy:?}"))
+    }
+
+    /// Sets what new invoices put in their description by default: "full" for
+    /// the label as is, "generic" for a placeholder or "hashed" for only its hash.
+    #[wasm_bindgen]
+    pub fn set_description_privacy(&self, privacy: String) -> Result<(), MutinyJsError> {
+        let privacy = DescriptionPrivacy::from_str(&privacy)?;
+        Ok(self.get_node_manager()?.set_description_privacy(privacy)?)
+    }
+
+    /// Pays a lightning invoice from the selected node.
+    /// An amount should only be provided if the invoice does not have an amount.
+    /// The amount should be in satoshis.
+    ///
+    /// Whether it may be split into parts, the most parts and the largest part
+    /// in sats can be set for this payment, otherwise the wallet's default is used.
+    ///
+    /// The number of retries, the timeout in seconds and whether to give up as
+    /// soon as a part fails for good can be set too, to keep it in-flight less long,
+    /// and the most it may pay in routing fees, in sats.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn pay_invoice(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        allow_mpp: Option<bool>,
+        max_parts: Option<u8>,
+        max_shard_sats: Option<u64>,
+        max_retries: Option<u32>,
+        timeout_secs: Option<u64>,
+        abandon_on_permanent_failure: Option<bool>,
+        max_fee_sats: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let mpp = MppConfig {
+            allow_mpp,
+            max_parts,
+            max_shard_sats,
+        };
+        let policy = PaymentPolicy {
+            max_retries,
+            timeout_secs,
+            abandon_on_permanent_failure: abandon_on_permanent_failure.unwrap_or_default(),
+            max_fee_sats,
+        };
+        Ok(self
+            .inner
+            .pay_invoice_with_options(&invoice, amt_sats, mpp, policy, labels)
+            .await?
+            .into())
+    }
+
+    /// Probes the route to a lightning invoice or node pubkey without sending any funds.
+    /// Returns whether it's reachable, the expected fee and how likely a payment is to succeed.
+    /// The amount is in sats and only needed for nodes and invoices without an amount.
+    #[wasm_bindgen]
+    pub async fn probe_payment(
+        &self,
+        invoice_or_node: String,
+        amount: Option<u64>,
+    ) -> Result<JsValue /* ProbeResult */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.probe_payment(&invoice_or_node, amount).await?,
+        )?)
+    }
+
+    /// Finds the route a payment of the invoice would take, without paying it,
+    /// and explains it hop by hop: channel sizes, fees and which of our channels is used.
+    /// The amount is in sats and only needed for invoices without an amount.
+    #[wasm_bindgen]
+    pub async fn explain_route(
+        &self,
+        invoice: String,
+        amount: Option<u64>,
+    ) -> Result<JsValue /* RouteExplanation */, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .explain_route(&invoice, amount)
+                .await?,
+        )?)
+    }
+
+    /// Gets the wallet's default limits on splitting payments into parts.
+    #[wasm_bindgen]
+    pub fn get_mpp_config(&self) -> Result<JsValue /* MppConfig */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_mpp_config()?,
+        )?)
+    }
+
+    /// Sets the wallet's default limits on splitting payments into parts:
+    /// whether payments may be split, the most parts and the largest part in sats.
+    /// Unset limits are left to the MPP strategy.
+    #[wasm_bindgen]
+    pub fn set_mpp_config(
+        &self,
+        allow_mpp: Option<bool>,
+        max_parts: Option<u8>,
+        max_shard_sats: Option<u64>,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.set_mpp_config(MppConfig {
+            allow_mpp,
+            max_parts,
+            max_shard_sats,
+        })?)
+    }
+
+    /// Sends a spontaneous payment to a node from the selected node.
+    /// The amount should be in satoshis. Custom TLV records are given as
+    /// `{ tlv_type, value }` with a hex value, e.g. for podcasting 2.0 boosts.
+    #[wasm_bindgen]
+    pub async fn keysend(
+        &self,
+        to_node: String,
+        amt_sats: u64,
+        message: Option<String>,
+        labels: Vec<String>,
+        custom_tlvs: JsValue, /* Option<Vec<CustomTlv>> */
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let to_node = PublicKey::from_str(&to_node)?;
+        let custom_tlvs = if custom_tlvs.is_null() || custom_tlvs.is_undefined() {
+            vec![]
+        } else {
+            custom_tlvs.into_serde()?
+        };
+        Ok(self
+            .get_node_manager()?
+            .keysend(None, to_node, amt_sats, message, custom_tlvs, labels)
+            .await?
+            .into())
+    }
+
+    /// Creates a BOLT12 offer, a static payment code that can be paid any number of times.
+    /// Without an amount the payer picks how much to send. The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn create_offer(
+        &self,
+        amount_sats: Option<u64>,
+        description: Option<String>,
+    ) -> Result<JsValue /* MutinyOffer */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.create_offer(amount_sats, description).await?,
+        )?)
+    }
+
+    /// Lists the BOLT12 offers we created, newest first
+    #[wasm_bindgen]
+    pub fn list_offers(&self) -> Result<JsValue /* Vec<MutinyOffer> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_offers()?)?)
+    }
+
+    /// Creates a hold invoice for the hex encoded payment hash, for escrow.
+    /// A payment to it is held until `settle_invoice` is called with the preimage,
+    /// or it's failed back with `cancel_invoice`.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn create_hodl_invoice(
+        &self,
+        payment_hash: String,
+        amount: u64,
+        labels: Vec<String>,
+        expiry_delta_secs: Option<u32>,
+    ) -> Result<JsValue /* HodlInvoice */, MutinyJsError> {
+        let payment_hash: [u8; 32] = FromHex::from_hex(&payment_hash)?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .create_hodl_invoice(PaymentHash(payment_hash), amount, labels, expiry_delta_secs)
+                .await?,
+        )?)
+    }
+
+    /// Claims the payment held for a hold invoice with its hex encoded preimage
+    #[wasm_bindgen]
+    pub async fn settle_invoice(
+        &self,
+        preimage: String,
+    ) -> Result<JsValue /* HodlInvoice */, MutinyJsError> {
+        let preimage: [u8; 32] = FromHex::from_hex(&preimage)?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .settle_invoice(PaymentPreimage(preimage))
+                .await?,
+        )?)
+    }
+
+    /// Cancels the hold invoice for the hex encoded payment hash, failing any
+    /// payment held for it back to the payer
+    #[wasm_bindgen]
+    pub async fn cancel_invoice(
+        &self,
+        payment_hash: String,
+    ) -> Result<JsValue /* HodlInvoice */, MutinyJsError> {
+        let payment_hash: [u8; 32] = FromHex::from_hex(&payment_hash)?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .cancel_invoice(PaymentHash(payment_hash))
+                .await?,
+        )?)
+    }
+
+    /// Lists the hold invoices we created, newest first
+    #[wasm_bindgen]
+    pub fn list_hodl_invoices(&self) -> Result<JsValue /* Vec<HodlInvoice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_hodl_invoices()?,
+        )?)
+    }
+
+    /// Creates a BOLT12 refund, for merchants to pay back a customer. The customer
+    /// claims it with their wallet and it's paid right away.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn create_refund(
+        &self,
+        amount_sats: u64,
+        description: Option<String>,
+        expiry_secs: Option<u64>,
+    ) -> Result<JsValue /* MutinyRefund */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .create_refund(amount_sats, description, expiry_secs)
+                .await?,
+        )?)
+    }
+
+    /// Claims a BOLT12 refund someone created for us
+    #[wasm_bindgen]
+    pub async fn claim_refund(&self, refund: String) -> Result<MutinyInvoice, MutinyJsError> {
+        let refund = Refund::from_str(&refund).map_err(|_| MutinyJsError::InvoiceInvalid)?;
+        Ok(self.inner.claim_refund(&refund).await?.into())
+    }
+
+    /// Lists the BOLT12 refunds we created, newest first
+    #[wasm_bindgen]
+    pub fn list_refunds(&self) -> Result<JsValue /* Vec<MutinyRefund> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_refunds()?)?)
+    }
+
+    /// Pays a BOLT12 offer. An amount should only be provided if the offer does
+    /// not have an amount, or to pay more than it asks.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn pay_offer(
+        &self,
+        offer: String,
+        amount_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let offer = Offer::from_str(&offer).map_err(|_| MutinyJsError::InvoiceInvalid)?;
+        Ok(self
+            .inner
+            .pay_offer(&offer, amount_sats, labels)
+            .await?
+            .into())
+    }
+
+    /// Moves liquidity from one of our channels to another by paying ourselves
+    /// over a circular route. The channels are given by their funding outpoints,
+    /// the routing fee paid shows up in the activity as a rebalance.
+    #[wasm_bindgen]
+    pub async fn rebalance(
+        &self,
+        from_channel: String,
+        to_channel: String,
+        amount_sats: u64,
+    ) -> Result<JsValue /* Rebalance */, MutinyJsError> {
+        let from_channel =
+            OutPoint::from_str(&from_channel).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let to_channel =
+            OutPoint::from_str(&to_channel).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let rebalance = self
+            .get_node_manager()?
+            .rebalance(&from_channel, &to_channel, amount_sats)
+            .await?;
+        Ok(JsValue::from_serde(&rebalance)?)
+    }
+
+    /// Decodes a lightning invoice into useful information.
+    /// Will return an error if the invoice is for a different network.
+    #[wasm_bindgen]
+    pub async fn decode_invoice(
+        &self,
+        invoice: String,
+        network: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        let network = network
+            .map(|n| Network::from_str(&n).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .transpose()?;
+        Ok(self.inner.decode_invoice(invoice, network)?.into())
+    }
+
+    /// Gets an invoice from the node manager.
+    /// This includes sent and received invoices.
+    #[wasm_bindgen]
+    pub async fn get_invoice(&self, invoice: String) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        Ok(self.inner.get_invoice(&invoice).await?.into())
+    }
+
+    /// Gets an invoice from the node manager.
+    /// This includes sent and received invoices.
+    #[wasm_bindgen]
+    pub async fn get_invoice_by_hash(&self, hash: String) -> Result<MutinyInvoice, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
+        Ok(self.inner.get_invoice_by_hash(&hash).await?.into())
+    }
+
+    /// Gets the details of each failed attempt at paying the given payment hash,
+    /// such as the channel that failed and the fees that were attempted.
+    #[wasm_bindgen]
+    pub fn get_payment_failure_details(
+        &self,
+        hash: String,
+    ) -> Result<JsValue /* Option<PaymentFailureDetails> */, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&hash)?;
+        Ok(JsValue::from_serde(
+            &self.inner.get_payment_failure_details(&hash)?,
+        )?)
+    }
+
+    /// The anonymized diagnostics bundle as structured data, for previewing
+    /// what a bug report would include.
+    #[wasm_bindgen]
+    pub async fn preview_diagnostics(
+        &self,
+    ) -> Result<JsValue /* DiagnosticsBundle */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.export_diagnostics().await?,
+        )?)
+    }
+
+    /// The anonymized diagnostics bundle as the JSON to attach to a bug report.
+    #[wasm_bindgen]
+    pub async fn export_diagnostics(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.export_diagnostics().await?.to_json()?)
+    }
+
+    /// The account statement for a calendar month (UTC), month is 1 to 12.
+    /// With a fiat currency, amounts are valued at the current price.
+    #[wasm_bindgen]
+    pub async fn generate_statement(
+        &self,
+        year: i32,
+        month: u32,
+        fiat: Option<String>,
+    ) -> Result<JsValue /* Statement */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.generate_statement(year, month, fiat).await?,
+        )?)
+    }
+
+    /// The account statement for a calendar month rendered as a PDF document.
+    #[wasm_bindgen]
+    pub async fn generate_statement_pdf(
+        &self,
+        year: i32,
+        month: u32,
+        fiat: Option<String>,
+    ) -> Result<Vec<u8>, MutinyJsError> {
+        Ok(self
+            .inner
+            .generate_statement(year, month, fiat)
+            .await?
+            .to_pdf())
+    }
+
+    /// Gets an invoice from the node manager.
+    /// This includes sent and received invoices.
+    #[wasm_bindgen]
+    pub async fn list_invoices(&self) -> Result<JsValue /* Vec<MutinyInvoice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_invoices()?)?)
+    }
+
+    /// Queues a payment request so it can be approved or declined later.
+    /// The amount is only used if the invoice does not have an amount.
+    #[wasm_bindgen]
+    pub fn queue_payment_request(
+        &self,
+        id: String,
+        sender: String,
+        invoice_str: String,
+        amount_sats: Option<u64>,
+        memo: Option<String>,
+        reference: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let request = PaymentRequest {
+            id,
+            sender,
+            invoice,
+            amount_sats,
+            memo,
+            reference,
+            created_at: now().as_secs(),
+        };
+        Ok(self.inner.queue_payment_request(request)?)
+    }
+
+    /// Lists the payment requests waiting for approval, oldest first.
+    #[wasm_bindgen]
+    pub fn list_payment_requests(
+        &self,
+    ) -> Result<JsValue /* Vec<PaymentRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_payment_requests()?)?)
+    }
+
+    /// Approves a queued payment request and pays it.
+    #[wasm_bindgen]
+    pub async fn approve_payment_request(
+        &self,
+        id: String,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        Ok(self
+            .inner
+            .approve_payment_request(&id, labels)
+            .await?
+            .into())
+    }
+
+    /// Declines a queued payment request.
+    #[wasm_bindgen]
+    pub fn decline_payment_request(&self, id: String) -> Result<(), MutinyJsError> {
+        Ok(self.inner.decline_payment_request(&id)?)
+    }
+
+    /// Creates a shareable payment link for an invoice of the given amount,
+    /// falling back to our lightning address once the invoice expires.
+    #[wasm_bindgen]
+    pub async fn create_payment_link(
+        &self,
+        amount_sats: u64,
+        memo: Option<String>,
+        expiry_secs: Option<u32>,
+    ) -> Result<JsValue /* PaymentLink */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .create_payment_link(amount_sats, memo, expiry_secs)
+                .await?,
+        )?)
+    }
+
+    /// Opens a payment link and returns what to pay, recording the click if
+    /// it's one of our links.
+    #[wasm_bindgen]
+    pub fn resolve_payment_link(
+        &self,
+        url: String,
+    ) -> Result<JsValue /* PaymentLinkTarget */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.resolve_payment_link(&url)?,
+        )?)
+    }
+
+    /// Lists our payment links, newest first, with their click and paid events.
+    #[wasm_bindgen]
+    pub fn list_payment_links(&self) -> Result<JsValue /* Vec<PaymentLink> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_payment_links()?)?)
+    }
+
+    /// Splits a bill between the given contacts, creating an invoice for each share.
+    #[wasm_bindgen]
+    pub async fn request_split(
+        &self,
+        total_sats: u64,
+        participants: Vec<String>,
+        memo: Option<String>,
+    ) -> Result<JsValue /* SplitRequest */, MutinyJsError> {
+        let split = self
+            .inner
+            .request_split(total_sats, participants, memo)
+            .await?;
+        Ok(JsValue::from_serde(&split)?)
+    }
+
+    /// Gets a split request, including which shares have been paid.
+    #[wasm_bindgen]
+    pub fn get_split_request(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<SplitRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_split_request(&id)?)?)
+    }
+
+    /// Lists all split requests, newest first.
+    #[wasm_bindgen]
+    pub fn list_split_requests(&self) -> Result<JsValue /* Vec<SplitRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_split_requests()?)?)
+    }
+
+    /// Pays the total amount split between the given lightning addresses.
+    /// If weights are given the amount is split proportionally to them,
+    /// otherwise it is split equally.
+    #[wasm_bindgen]
+    pub async fn send_split(
+        &self,
+        addresses: Vec<String>,
+        weights: Option<Vec<u32>>,
+        total_sats: u64,
+    ) -> Result<JsValue /* SplitSend */, MutinyJsError> {
+        let weights = weights.unwrap_or_else(|| vec![1; addresses.len()]);
+        if weights.len() != addresses.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let recipients = addresses.into_iter().zip(weights).collect();
+        Ok(JsValue::from_serde(
+            &self.inner.send_split(recipients, total_sats).await?,
+        )?)
+    }
+
+    /// Gets a split payment, including the result of each recipient's payment.
+    #[wasm_bindgen]
+    pub fn get_split_send(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<SplitSend> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_split_send(&id)?)?)
+    }
+
+    /// Deposits on-chain funds with a swap provider to receive them over lightning.
+    /// The provider has to pay the returned invoice before the timeout height,
+    /// otherwise the deposit is refunded to the wallet automatically.
+    #[wasm_bindgen]
+    pub async fn create_swap_deposit(
+        &self,
+        provider_pubkey: String,
+        amount_sats: u64,
+        timeout_height: u32,
+        fee_rate: Option<u64>,
+    ) -> Result<JsValue /* SwapDeposit */, MutinyJsError> {
+        let provider_pubkey = PublicKey::from_str(&provider_pubkey)?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .create_swap_deposit(provider_pubkey, amount_sats, timeout_height, fee_rate)
+                .await?,
+        )?)
+    }
+
+    /// Gets a swap deposit, including its refund status.
+    #[wasm_bindgen]
+    pub fn get_swap_deposit(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<SwapDeposit> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_swap_deposit(&id)?)?)
+    }
+
+    /// Lists all swap deposits and their refund status, newest first.
+    #[wasm_bindgen]
+    pub fn list_swap_deposits(&self) -> Result<JsValue /* Vec<SwapDeposit> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_swap_deposits()?)?)
+    }
+
+    /// Gets the payment card, all the static identifiers that can be used to pay this wallet.
+    #[wasm_bindgen]
+    pub async fn get_payment_card(&self) -> Result<PaymentCard, MutinyJsError> {
+        Ok(self.inner.get_payment_card().await?.into())
+    }
+
+    /// Sets identifiers on the payment card.
+    /// Passing `undefined` leaves a field unchanged and an empty string clears it.
+    #[wasm_bindgen]
+    pub async fn update_payment_card(
+        &self,
+        lightning_address: Option<String>,
+        offer: Option<String>,
+        silent_payment_address: Option<String>,
+    ) -> Result<PaymentCard, MutinyJsError> {
+        let update = PaymentCardUpdate {
+            lightning_address,
+            offer,
+            silent_payment_address,
+        };
+        Ok(self.inner.update_payment_card(update).await?.into())
+    }
+
+    /// Registers another lightning address that pays this wallet.
+    #[wasm_bindgen]
+    pub async fn add_lightning_address_alias(
+        &self,
+        address: String,
+    ) -> Result<JsValue /* LnAddressAliases */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.add_lightning_address_alias(address).await?,
+        )?)
+    }
+
+    /// Removes a lightning address alias so it is no longer handed out.
+    #[wasm_bindgen]
+    pub async fn remove_lightning_address_alias(
+        &self,
+        address: String,
+    ) -> Result<JsValue /* LnAddressAliases */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.remove_lightning_address_alias(address).await?,
+        )?)
+    }
+
+    /// Lists the lightning address aliases along with the rotation policy.
+    #[wasm_bindgen]
+    pub fn list_lightning_address_aliases(
+        &self,
+    ) -> Result<JsValue /* LnAddressAliases */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_lightning_address_aliases()?,
+        )?)
+    }
+
+    /// Sets how lightning address aliases are handed out,
+    /// "static", "per_contact" or "time_based" with an interval in seconds.
+    #[wasm_bindgen]
+    pub fn set_lightning_address_rotation(
+        &self,
+        policy: String,
+        interval_secs: Option<u64>,
+    ) -> Result<(), MutinyJsError> {
+        let policy = match (policy.as_str(), interval_secs) {
+            ("static", _) => AliasRotationPolicy::Static,
+            ("per_contact", _) => AliasRotationPolicy::PerContact,
+            ("time_based", Some(interval_secs)) => AliasRotationPolicy::TimeBased { interval_secs },
+            _ => return Err(MutinyJsError::InvalidArgumentsError),
+        };
+        Ok(self.inner.set_lightning_address_rotation(policy)?)
+    }
+
+    /// Gets the filters applied to zap comments and direct messages.
+    #[wasm_bindgen]
+    pub fn get_moderation_settings(
+        &self,
+    ) -> Result<JsValue /* ModerationSettings */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_moderation_settings()?)?)
+    }
+
+    /// Sets the filters applied to zap comments and direct messages.
+    /// Followed pubkeys can be npubs or hex.
+    #[wasm_bindgen]
+    pub fn set_moderation_settings(
+        &self,
+        blocked_words: Vec<String>,
+        min_zap_comment_sats: Option<u64>,
+        followed_only: bool,
+        followed_pubkeys: Vec<String>,
+    ) -> Result<JsValue /* ModerationSettings */, MutinyJsError> {
+        let settings = ModerationSettings {
+            blocked_words,
+            min_zap_comment_sats,
+            followed_only,
+            followed_pubkeys,
+        };
+        Ok(JsValue::from_serde(
+            &self.inner.set_moderation_settings(settings)?,
+        )?)
+    }
+
+    /// The wallet's nostr identity as an npub, milestone badges are published by it.
+    #[wasm_bindgen]
+    pub fn get_npub(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.get_npub()?)
+    }
+
+    /// Whether milestone badges are published to nostr.
+    #[wasm_bindgen]
+    pub fn get_publish_badges(&self) -> Result<bool, MutinyJsError> {
+        Ok(self.inner.get_badge_settings()?.publish)
+    }
+
+    /// Opts in or out of publishing milestone badges to nostr.
+    /// Milestones reached before opting in are published too.
+    #[wasm_bindgen]
+    pub fn set_publish_badges(&self, publish: bool) -> Result<(), MutinyJsError> {
+        Ok(self.inner.set_badge_settings(BadgeSettings { publish })?)
+    }
+
+    /// Lists the milestones reached so far, newest first, whether or not
+    /// their badges were published.
+    #[wasm_bindgen]
+    pub fn list_milestones(&self) -> Result<JsValue /* Vec<EarnedMilestone> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_milestones()?)?)
+    }
+
+    /// Gets the policy for channels opened to us by peers other than our LSP.
+    #[wasm_bindgen]
+    pub fn get_inbound_channel_policy(
+        &self,
+    ) -> Result<JsValue /* InboundChannelPolicy */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_inbound_channel_policy()?,
+        )?)
+    }
+
+    /// Sets which inbound channel requests are accepted.
+    /// An empty list of allowed peers accepts channels from anyone.
+    #[wasm_bindgen]
+    pub fn set_inbound_channel_policy(
+        &self,
+        enabled: bool,
+        allowed_peers: Vec<String>,
+        min_funding_sats: u64,
+    ) -> Result<(), MutinyJsError> {
+        let allowed_peers = allowed_peers
+            .iter()
+            .map(|p| PublicKey::from_str(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let policy = InboundChannelPolicy {
+            enabled,
+            allowed_peers,
+            min_funding_sats,
+        };
+        Ok(self.inner.set_inbound_channel_policy(policy)?)
+    }
+
+    /// The channels peers other than our LSP asked to open, and what we did with them.
+    #[wasm_bindgen]
+    pub fn list_inbound_channel_requests(
+        &self,
+    ) -> Result<JsValue /* Vec<InboundChannelRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_inbound_channel_requests()?,
+        )?)
+    }
+
+    /// Sets a function that decides on the channels peers ask to open with us,
+    /// instead of the built in policy. It's called with the request and returns
+    /// true to accept, false or a reason to reject, or a promise of those.
+    /// Requests it doesn't answer within 30 seconds are rejected.
+    /// Without a function the built in policy is used again.
+    #[wasm_bindgen]
+    pub fn set_channel_acceptor(
+        &self,
+        acceptor: Option<web_sys::js_sys::Function>,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self
+            .inner
+            .set_channel_acceptor(acceptor.map(js_channel_acceptor))?)
+    }
+
+    /// Lists the peers trusted with zero reserve channels, besides our LSP
+    #[wasm_bindgen]
+    pub fn list_zero_reserve_peers(&self) -> Result<Vec<String>, MutinyJsError> {
+        Ok(self
+            .inner
+            .list_zero_reserve_peers()?
+            .iter()
+            .map(|p| p.to_string())
+            .collect())
+    }
+
+    /// Sets whether a peer is trusted with zero reserve channels
+    #[wasm_bindgen]
+    pub fn set_zero_reserve_peer(&self, peer: String, enabled: bool) -> Result<(), MutinyJsError> {
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(self.inner.set_zero_reserve_peer(peer, enabled)?)
+    }
+
+    /// The overrides set for a peer, the defaults if there are none
+    #[wasm_bindgen]
+    pub fn get_peer_overrides(
+        &self,
+        peer: String,
+    ) -> Result<JsValue /* PeerOverrides */, MutinyJsError> {
+        let peer = PublicKey::from_str(&peer)?;
+        Ok(JsValue::from_serde(&self.inner.get_peer_overrides(&peer)?)?)
+    }
+
+    /// Every peer with overrides set, keyed by their pubkey
+    #[wasm_bindgen]
+    pub fn list_peer_overrides(
+        &self,
+    ) -> Result<JsValue /* Map<String, PeerOverrides> */, MutinyJsError> {
+        let overrides: HashMap<String, PeerOverrides> = self
+            .inner
+            .list_peer_overrides()?
+            .into_iter()
+            .map(|(peer, o)| (peer.to_string(), o))
+            .collect();
+        Ok(JsValue::from_serde(&overrides)?)
+    }
+
+    /// Sets a proxy to reach the peer through, whether to reconnect to them on our
+    /// own and whether channels we open to them use the legacy channel type.
+    /// Setting everything back to the defaults clears them.
+    #[wasm_bindgen]
+    pub fn set_peer_overrides(
+        &self,
+        peer: String,
+        proxy_addr: Option<String>,
+        never_auto_reconnect: bool,
+        force_legacy_channel_type: bool,
+    ) -> Result<(), MutinyJsError> {
+        let peer = PublicKey::from_str(&peer)?;
+        let overrides = PeerOverrides {
+            proxy_addr,
+            never_auto_reconnect,
+            force_legacy_channel_type,
+        };
+        Ok(self.inner.set_peer_overrides(peer, overrides)?)
+    }
+
+    /// Whether VSS and the auth server are reachable. When they aren't the wallet
+    /// runs on its local state and sends its backups once they are back.
+    #[wasm_bindgen]
+    pub fn get_service_status(&self) -> Result<JsValue /* ServiceStatus */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_service_status()?)?)
+    }
+
+    /// Drops the decrypted direct messages that don't pass the moderation settings.
+    #[wasm_bindgen]
+    pub fn filter_direct_messages(
+        &self,
+        messages: JsValue, /* Vec<DirectMessage> */
+    ) -> Result<JsValue /* Vec<DirectMessage> */, MutinyJsError> {
+        let messages: Vec<DirectMessage> = messages.into_serde()?;
+        Ok(JsValue::from_serde(
+            &self.inner.filter_direct_messages(messages)?,
+        )?)
+    }
+
+    /// Gets the lightning address to give out, optionally to a specific contact.
+    #[wasm_bindgen]
+    pub async fn get_lightning_address(
+        &self,
+        contact_id: Option<String>,
+    ) -> Result<Option<String>, MutinyJsError> {
+        Ok(self.inner.get_lightning_address(contact_id).await?)
+    }
+
+    /// Gets an channel closure from the node manager.
+    #[wasm_bindgen]
+    pub async fn get_channel_closure(
+        &self,
+        user_channel_id: String,
+    ) -> Result<ChannelClosure, MutinyJsError> {
+        let user_channel_id: [u8; 16] = FromHex::from_hex(&user_channel_id)?;
+        Ok(self
+            .get_node_manager()?
+            .get_channel_closure(u128::from_be_bytes(user_channel_id))
+            .await?
+            .into())
+    }
+
+    /// Gets all channel closures from the node manager.
+    ///
+    /// The channel closures are sorted by the time they were closed.
+    #[wasm_bindgen]
+    pub async fn list_channel_closures(
+        &self,
+    ) -> Result<JsValue /* Vec<ChannelClosure> */, MutinyJsError> {
+        let mut channel_closures: Vec<ChannelClosure> = self
+            .get_node_manager()?
+            .list_channel_closures()
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        channel_closures.sort();
+        Ok(JsValue::from_serde(&channel_closures)?)
+    }
+
+    /// Opens a channel from our selected node to the given pubkey.
+    /// The amount is in satoshis.
+    ///
+    /// The node must be online and have a connection to the peer.
+    /// The wallet much have enough funds to open the channel.
+    ///
+    /// To fund it from specific coins give either the utxos (as `txid:vout`)
+    /// or labels, in which case the coins of addresses with those labels are spent.
+    ///
+    /// `push_msat` is given to the peer when the channel opens, e.g. to give
+    /// a friend some balance along with their inbound liquidity.
+    ///
+    /// An announced channel is gossiped to the network, channels are private by default.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn open_channel(
+        &self,
+        to_pubkey: Option<String>,
+        amount: u64,
+        fee_rate: Option<u64>,
+        utxos: Option<Vec<String>>,
+        only_labels: Option<Vec<String>>,
+        push_msat: Option<u64>,
+        announce: Option<bool>,
+    ) -> Result<MutinyChannel, MutinyJsError> {
+        let to_pubkey = match to_pubkey {
+            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
+                Some(PublicKey::from_str(&pubkey_str)?)
+            }
+            _ => None,
+        };
+        let node_manager = self.get_node_manager()?;
+        let utxos = match (utxos, only_labels) {
+            (Some(_), Some(_)) => return Err(MutinyJsError::InvalidArgumentsError),
+            (Some(utxos), None) => utxos
+                .iter()
+                .map(|o| OutPoint::from_str(o).map_err(|_| MutinyJsError::InvalidArgumentsError))
+                .collect::<Result<Vec<_>, _>>()?,
+            (None, Some(labels)) => {
+                let utxos = node_manager.utxos_with_labels(&labels)?;
+                if utxos.is_empty() {
+                    return Err(MutinyJsError::InsufficientBalance);
+                }
+                utxos
+            }
+            (None, None) => vec![],
+        };
+
+        Ok(node_manager
+            .open_channel(
+                None,
+                to_pubkey,
+                amount,
+                push_msat,
+                fee_rate,
+                None,
+                utxos,
+                announce.unwrap_or(false),
+            )
+            .await?
+            .into())
+    }
+
+    /// Opens a channel from our selected node to the given pubkey.
+    /// It will spend the all the on-chain utxo in full to fund the channel.
+    ///
+    /// The node must be online and have a connection to the peer.
+    pub async fn sweep_all_to_channel(
+        &self,
+        to_pubkey: Option<String>,
+    ) -> Result<MutinyChannel, MutinyJsError> {
+        let to_pubkey = match to_pubkey {
+            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
+                Some(PublicKey::from_str(&pubkey_str)?)
+            }
+            _ => None,
+        };
+
+        Ok(self
+            .get_node_manager()?
+            .sweep_all_to_channel(to_pubkey)
+            .await?
+            .into())
+    }
+
+    /// Creates a request to open a channel funded from outside the wallet, such as
+    /// a federation withdrawing ecash to the returned deposit address. Once the
+    /// deposit confirms a channel is opened with it to the given peer, or the LSP.
+    #[wasm_bindgen]
+    pub fn request_channel_funding(
+        &self,
+        amount_sats: u64,
+        to_pubkey: Option<String>,
+    ) -> Result<JsValue /* ChannelFundingRequest */, MutinyJsError> {
+        let to_pubkey = match to_pubkey {
+            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
+                Some(PublicKey::from_str(&pubkey_str)?)
+            }
+            _ => None,
+        };
+
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .request_channel_funding(amount_sats, to_pubkey)?,
+        )?)
+    }
+
+    /// Lists the channel funding requests, newest first.
+    #[wasm_bindgen]
+    pub fn list_channel_funding_requests(
+        &self,
+    ) -> Result<JsValue /* Vec<ChannelFundingRequest> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_channel_funding_requests()?,
+        )?)
+    }
+
+    /// Stops waiting for the deposit of a channel funding request,
+    /// anything deposited stays in the on-chain wallet.
+    #[wasm_bindgen]
+    pub fn cancel_channel_funding(&self, id: String) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.cancel_channel_funding(id)?)
+    }
+
+    /// Closes a channel with the given outpoint.
+    ///
+    /// If force is true, the channel will be force closed.
+    ///
+    /// If abandon is true, the channel will be abandoned.
+    /// This will force close without broadcasting the latest transaction.
+    /// This should only be used if the channel will never actually be opened.
+    ///
+    /// If both force and abandon are true, an error will be returned.
+    /// The address must match the network and the network: "bitcoin", "testnet", "signet", "regtest"
+    ///
+    /// if leave target_feerate_sats_per_1000_weight to none, the node will determine a target
+    /// feerate base on current network status
+    ///
+    /// max_feerate_sats_per_1000_weight caps the feerate agreed to with the peer, with the
+    /// target as the low end of the range
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn close_channel(
+        &self,
+        outpoint: String,
+        force: bool,
+        abandon: bool,
+        address: Option<String>,
+        network: Option<String>,
+        target_feerate_sats_per_1000_weight: Option<u32>,
+        max_feerate_sats_per_1000_weight: Option<u32>,
+    ) -> Result<(), MutinyJsError> {
+        let outpoint: OutPoint =
+            OutPoint::from_str(&outpoint).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+
+        let network = if let Some(net) = network {
+            Network::from_str(&net).map_err(|_| MutinyJsError::InvalidAddressNetworkError)?
+        } else {
+            Network::Bitcoin
+        };
+        let address = if let Some(addr) = address {
+            let addr_ = Address::from_str(&addr)
+                .map_err(|_| MutinyJsError::InvalidAddressNetworkError)?
+                .require_network(network)
+                .map_err(|_| MutinyJsError::InvalidAddressNetworkError)?;
+            Some(addr_)
+        } else {
+            None
+        };
+
+        Ok(self
+            .get_node_manager()?
+            .close_channel(
+                &outpoint,
+                address,
+                force,
+                abandon,
+                target_feerate_sats_per_1000_weight,
+                max_feerate_sats_per_1000_weight,
+            )
+            .await?)
+    }
+
+    /// Bumps a force close that's stuck unconfirmed, by spending its anchor output
+    /// in a child transaction (CPFP) at the given feerate.
+    /// The channel id is the hex channel id from the force close.
+    #[wasm_bindgen]
+    pub async fn bump_force_close(
+        &self,
+        channel_id: String,
+        sats_per_vbyte: u64,
+    ) -> Result<JsValue /* AnchorBump */, MutinyJsError> {
+        let channel_id: [u8; 32] = FromHex::from_hex(&channel_id)?;
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .bump_force_close(&ChannelId(channel_id), sats_per_vbyte)
+                .await?,
+        )?)
+    }
+
+    /// Lists the force closes that could be bumped and the transactions that bumped them
+    #[wasm_bindgen]
+    pub fn list_anchor_bumps(&self) -> Result<JsValue /* Vec<AnchorBump> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_anchor_bumps()?,
+        )?)
+    }
+
+    /// Lists all the channels for all the nodes in the node manager.
+    #[wasm_bindgen]
+    pub async fn list_channels(&self) -> Result<JsValue /* Vec<MutinyChannel> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_channels().await?,
         )?)
     }
 
@@ -1093,6 +2865,14 @@ impl MutinyWallet {
         )?)
     }
 
+    /// Looks up the alias a lightning node announced. Works before the network
+    /// graph has synced for nodes we've seen before.
+    #[wasm_bindgen]
+    pub fn lookup_node_alias(&self, pubkey: String) -> Result<Option<String>, MutinyJsError> {
+        let pubkey = PublicKey::from_str(&pubkey)?;
+        Ok(self.get_node_manager()?.lookup_node_alias(&pubkey)?)
+    }
+
     /// Returns all the on-chain and lightning activity from the wallet.
     #[wasm_bindgen]
     pub async fn get_activity(
@@ -1106,6 +2886,24 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&activity)?)
     }
 
+    /// Gets all the activity of one user action, e.g. the deposit, payment and
+    /// refund of a swap. The id is the `operation_id` of one of its activity items.
+    #[wasm_bindgen]
+    pub fn get_operation(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<Operation> */, MutinyJsError> {
+        let Some(operation) = self.inner.get_operation(&id)? else {
+            return Ok(JsValue::NULL);
+        };
+        let items: Vec<ActivityItem> = operation.items.into_iter().map(|a| a.into()).collect();
+        Ok(JsValue::from_serde(&serde_json::json!({
+            "id": operation.id,
+            "kind": operation.kind,
+            "items": items,
+        }))?)
+    }
+
     pub fn get_address_labels(
         &self,
     ) -> Result<JsValue /* Map<Address, Vec<String>> */, MutinyJsError> {
@@ -1128,32 +2926,452 @@ impl MutinyWallet {
             .set_address_labels(address, labels)?)
     }
 
-    pub fn get_invoice_labels(
+    /// Lists the change outputs that inherited labels from the coins they were made from
+    pub fn list_label_lineage(&self) -> Result<JsValue /* Vec<LabelLineage> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.list_label_lineage()?,
+        )?)
+    }
+
+    /// Replaces the labels a change address inherited from the coins it was made from
+    pub fn correct_label_lineage(
+        &self,
+        address: String,
+        labels: Vec<String>,
+    ) -> Result<JsValue /* LabelLineage */, MutinyJsError> {
+        let address = Address::from_str(&address)?.assume_checked();
+        Ok(JsValue::from_serde(
+            &self
+                .get_node_manager()?
+                .correct_label_lineage(address, labels)?,
+        )?)
+    }
+
+    /// Exports the on-chain labels as BIP-329 JSON lines
+    pub fn export_bip329_labels(&self) -> Result<String, MutinyJsError> {
+        Ok(self.get_node_manager()?.export_bip329_labels()?)
+    }
+
+    /// Imports BIP-329 JSON lines, adding to the existing labels
+    pub fn import_bip329_labels(
+        &self,
+        jsonl: String,
+    ) -> Result<JsValue /* Bip329ImportSummary */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.import_bip329_labels(&jsonl)?,
+        )?)
+    }
+
+    pub fn get_invoice_labels(
+        &self,
+    ) -> Result<JsValue /* Map<Invoice, Vec<String>> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_invoice_labels()?,
+        )?)
+    }
+
+    /// Set the labels for an invoice, replacing any existing labels
+    /// If you want to do not want to replace any existing labels, use `get_invoice_labels` to get the existing labels,
+    /// add the new labels, and then use `set_invoice_labels` to set the new labels
+    pub fn set_invoice_labels(
+        &self,
+        invoice: String,
+        labels: Vec<String>,
+    ) -> Result<(), MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        Ok(self
+            .get_node_manager()?
+            .set_invoice_labels(invoice, labels)?)
+    }
+
+    /// Moves everything labeled `from` over to `into` and removes `from`
+    pub fn merge_labels(&self, from: String, into: String) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.merge_labels(&from, &into)?)
+    }
+
+    /// Renames a label, keeping the old name in its history
+    pub fn rename_label(&self, label: String, new_label: String) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.rename_label(&label, &new_label)?)
+    }
+
+    pub fn get_label_history(
+        &self,
+        label: String,
+    ) -> Result<JsValue /* Vec<LabelRename> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_label_history(&label)?,
+        )?)
+    }
+
+    /// Adds the label to each of the addresses and invoices, keeping their other labels
+    pub fn apply_label(
+        &self,
+        label: String,
+        addresses: Vec<String>,
+        invoices: Vec<String>,
+    ) -> Result<(), MutinyJsError> {
+        let invoices = invoices
+            .iter()
+            .map(|i| Bolt11Invoice::from_str(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self
+            .get_node_manager()?
+            .apply_label(&label, addresses, invoices)?)
+    }
+
+    /// Removes the label from each of the addresses and invoices
+    pub fn remove_label(
+        &self,
+        label: String,
+        addresses: Vec<String>,
+        invoices: Vec<String>,
+    ) -> Result<(), MutinyJsError> {
+        let invoices = invoices
+            .iter()
+            .map(|i| Bolt11Invoice::from_str(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self
+            .get_node_manager()?
+            .remove_label(&label, addresses, invoices)?)
+    }
+
+    /// Deletes a label from everything it is on, or moves it all to `reassign_to`
+    pub fn delete_label(
+        &self,
+        label: String,
+        reassign_to: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.delete_label(&label, reassign_to)?)
+    }
+
+    /// Gets a contact or profile image as a `data:` url, cached locally so it
+    /// shows right away and while offline.
+    #[wasm_bindgen]
+    pub async fn get_avatar(&self, url: String) -> Result<Option<String>, MutinyJsError> {
+        Ok(self.inner.get_avatar(url).await?)
+    }
+
+    /// Caches the images that aren't cached yet or have gone stale, all of the
+    /// contacts' images when no urls are given. Returns how many were fetched.
+    #[wasm_bindgen]
+    pub async fn prewarm_avatars(&self, urls: Option<Vec<String>>) -> Result<usize, MutinyJsError> {
+        Ok(self.inner.prewarm_avatars(urls).await?)
+    }
+
+    /// Removes every cached contact and profile image.
+    #[wasm_bindgen]
+    pub fn clear_avatar_cache(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.clear_avatar_cache()?)
+    }
+
+    /// Gets the current bitcoin price in chosen Fiat.
+    #[wasm_bindgen]
+    pub async fn get_bitcoin_price(&self, fiat: Option<String>) -> Result<f32, MutinyJsError> {
+        Ok(self.inner.get_bitcoin_price(fiat).await?)
+    }
+
+    /// Creates an invoice priced in fiat. The sat amount is locked at the current
+    /// price and requoted if the price moves, until paid or `validity_secs` runs out.
+    #[wasm_bindgen]
+    pub async fn create_invoice_fiat(
+        &self,
+        fiat_amount: f64,
+        currency: String,
+        validity_secs: u64,
+    ) -> Result<JsValue /* FiatInvoice */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .create_invoice_fiat(fiat_amount, currency, validity_secs)
+                .await?,
+        )?)
+    }
+
+    /// Gets a fiat priced invoice by id, with its current quote
+    #[wasm_bindgen]
+    pub fn get_fiat_invoice(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<FiatInvoice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_fiat_invoice(&id)?)?)
+    }
+
+    /// Lists the fiat priced invoices, newest first
+    #[wasm_bindgen]
+    pub fn list_fiat_invoices(&self) -> Result<JsValue /* Vec<FiatInvoice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_fiat_invoices()?)?)
+    }
+
+    /// Gets the user's denomination preferences.
+    #[wasm_bindgen]
+    pub fn get_denomination_preferences(
+        &self,
+    ) -> Result<JsValue /* DenominationPreferences */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_denomination_preferences()?,
+        )?)
+    }
+
+    /// Sets the user's denomination preferences.
+    /// The denomination should be one of "sats", "btc" or "fiat".
+    #[wasm_bindgen]
+    pub fn set_denomination_preferences(
+        &self,
+        denomination: String,
+        fiat_currency: String,
+        locale: String,
+    ) -> Result<(), MutinyJsError> {
+        let prefs = DenominationPreferences {
+            denomination: Denomination::from_str(&denomination)?,
+            fiat_currency,
+            locale,
+        };
+        Ok(self.inner.set_denomination_preferences(prefs)?)
+    }
+
+    /// Gets the preferences that are synced across the user's devices.
+    #[wasm_bindgen]
+    pub fn get_wallet_settings(&self) -> Result<JsValue /* WalletSettings */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_wallet_settings()?)?)
+    }
+
+    /// The operator's config fetched at startup with the user's overrides applied,
+    /// if the wallet was set up to fetch one
+    #[wasm_bindgen]
+    pub fn get_remote_config(&self) -> Result<JsValue /* Option<RemoteConfig> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_remote_config()?)?)
+    }
+
+    /// Whether the remote config says this version is too old and should be updated
+    #[wasm_bindgen]
+    pub fn is_update_required(&self) -> Result<bool, MutinyJsError> {
+        let version = env!("CARGO_PKG_VERSION");
+        Ok(self
+            .inner
+            .get_remote_config()?
+            .is_some_and(|c| c.update_required(version)))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_remote_config_overrides(
+        &self,
+    ) -> Result<JsValue /* RemoteConfigOverrides */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.get_remote_config_overrides()?,
+        )?)
+    }
+
+    /// Sets what the user picked over the remote config, LSP and fee api
+    /// changes are used from the next startup
+    #[wasm_bindgen]
+    pub fn set_remote_config_overrides(
+        &self,
+        overrides: JsValue, /* RemoteConfigOverrides */
+    ) -> Result<(), MutinyJsError> {
+        let overrides: RemoteConfigOverrides = overrides.into_serde()?;
+        Ok(self.inner.set_remote_config_overrides(overrides)?)
+    }
+
+    /// Saves the wallet settings and publishes them, encrypted, to the user's
+    /// relays so their other devices pick them up.
+    #[wasm_bindgen]
+    pub fn set_wallet_settings(
+        &self,
+        settings: JsValue, /* WalletSettings */
+    ) -> Result<(), MutinyJsError> {
+        let settings: WalletSettings = settings.into_serde()?;
+        Ok(self.inner.set_wallet_settings(settings)?)
+    }
+
+    /// Pulls the latest wallet settings from the user's relays.
+    #[wasm_bindgen]
+    pub async fn sync_wallet_settings(
+        &self,
+    ) -> Result<JsValue /* WalletSettings */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.sync_wallet_settings().await?,
+        )?)
+    }
+
+    /// The hex pubkey contacts use to make this wallet one of their guardians.
+    #[wasm_bindgen]
+    pub fn get_social_recovery_pubkey(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.get_social_recovery_pubkey()?)
+    }
+
+    /// Turns on social recovery, sending a backup shard to each guardian.
+    /// `contact_ids` and `pubkeys` pair up, the pubkeys can be npubs or hex.
+    #[wasm_bindgen]
+    pub fn enable_social_recovery(
+        &self,
+        threshold: u8,
+        contact_ids: Vec<String>,
+        pubkeys: Vec<String>,
+    ) -> Result<JsValue /* SocialRecoveryConfig */, MutinyJsError> {
+        if contact_ids.len() != pubkeys.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let guardians = contact_ids.into_iter().zip(pubkeys).collect();
+        Ok(JsValue::from_serde(
+            &self.inner.enable_social_recovery(threshold, guardians)?,
+        )?)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_social_recovery(
+        &self,
+    ) -> Result<JsValue /* Option<SocialRecoveryConfig> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_social_recovery()?)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn disable_social_recovery(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.disable_social_recovery()?)
+    }
+
+    /// Handles incoming guardian messages and pings guardians when due.
+    #[wasm_bindgen]
+    pub async fn check_social_recovery(
+        &self,
+    ) -> Result<JsValue /* Option<SocialRecoveryConfig> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.check_social_recovery().await?,
+        )?)
+    }
+
+    /// Lists the backup shards this wallet holds for its contacts.
+    #[wasm_bindgen]
+    pub fn list_held_backup_shards(&self) -> Result<JsValue /* Vec<HeldShard> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_held_backup_shards()?)?)
+    }
+
+    /// Sends a contact's backup shard to the recovery pubkey of their new device.
+    #[wasm_bindgen]
+    pub fn release_backup_shard(
+        &self,
+        owner: String,
+        recovery_pubkey: String,
+    ) -> Result<(), MutinyJsError> {
+        Ok(self.inner.release_backup_shard(owner, recovery_pubkey)?)
+    }
+
+    /// Moves the whole balance to another Mutiny user by their npub or hex pubkey.
+    /// Waits for their wallet to answer, then sends the lightning and on-chain funds.
+    /// The returned transfer says how far it got and why it stopped if it failed.
+    #[wasm_bindgen]
+    pub async fn transfer_balance(
+        &self,
+        recipient: String,
+        labels: Vec<String>,
+    ) -> Result<JsValue /* Transfer */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.transfer_balance(recipient, labels).await?,
+        )?)
+    }
+
+    /// Gets a balance transfer by id, to follow its progress.
+    #[wasm_bindgen]
+    pub fn get_transfer(
+        &self,
+        id: String,
+    ) -> Result<JsValue /* Option<Transfer> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_transfer(id)?)?)
+    }
+
+    /// Lists the balance transfers sent and received, newest first.
+    #[wasm_bindgen]
+    pub fn list_transfers(&self) -> Result<JsValue /* Vec<Transfer> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_transfers()?)?)
+    }
+
+    /// Sends the payee of a lightning payment we made a receipt with the amount,
+    /// memo and preimage, as a direct message to their npub or hex nostr pubkey.
+    #[wasm_bindgen]
+    pub async fn send_payment_receipt(
+        &self,
+        payment_hash: String,
+        recipient: String,
+        memo: Option<String>,
+    ) -> Result<JsValue /* PaymentReceipt */, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&payment_hash)?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .send_payment_receipt(&hash, recipient, memo)
+                .await?,
+        )?)
+    }
+
+    /// Gets the receipt for a payment, the one sent to us if there are both.
+    #[wasm_bindgen]
+    pub fn get_payment_receipt(
+        &self,
+        payment_hash: String,
+    ) -> Result<JsValue /* Option<PaymentReceipt> */, MutinyJsError> {
+        let hash: sha256::Hash = sha256::Hash::from_str(&payment_hash)?;
+        Ok(JsValue::from_serde(
+            &self.inner.get_payment_receipt(&hash)?,
+        )?)
+    }
+
+    /// Lists the payment receipts sent and received, newest first.
+    #[wasm_bindgen]
+    pub fn list_payment_receipts(
+        &self,
+    ) -> Result<JsValue /* Vec<PaymentReceipt> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_payment_receipts()?)?)
+    }
+
+    /// Makes a throwaway key for a new device to receive released shards on.
+    /// Returns the hex secret to keep and the pubkey to give to guardians.
+    #[wasm_bindgen]
+    pub fn generate_recovery_key() -> Result<JsValue /* { secret, pubkey } */, MutinyJsError> {
+        let key = socialrecovery::generate_recovery_key();
+        let pubkey = key.x_only_public_key(&Secp256k1::new()).0;
+        Ok(JsValue::from_serde(&serde_json::json!({
+            "secret": key.display_secret().to_string(),
+            "pubkey": pubkey.to_string(),
+        }))?)
+    }
+
+    /// Collects the shards released to the recovery key and returns the seed
+    /// once enough guardians have answered. Restore it with `restore_mnemonic`.
+    #[wasm_bindgen]
+    pub async fn recover_from_guardians(
+        recovery_secret: String,
+        relays: Vec<String>,
+    ) -> Result<Option<String>, MutinyJsError> {
+        let key = SecretKey::from_str(&recovery_secret)?;
+        let relays = if relays.is_empty() {
+            DEFAULT_SETTINGS_RELAYS.map(String::from).to_vec()
+        } else {
+            relays
+        };
+        let mnemonic = socialrecovery::recover_from_guardians(&key, &relays).await?;
+        Ok(mnemonic.map(|m| m.to_string()))
+    }
+
+    /// Formats an amount in sats using the user's denomination preferences.
+    /// If a timestamp is given, fiat amounts use the price from that time.
+    #[wasm_bindgen]
+    pub async fn format_amount(
         &self,
-    ) -> Result<JsValue /* Map<Invoice, Vec<String>> */, MutinyJsError> {
-        Ok(JsValue::from_serde(
-            &self.get_node_manager()?.get_invoice_labels()?,
-        )?)
+        sats: u64,
+        timestamp: Option<u64>,
+    ) -> Result<String, MutinyJsError> {
+        Ok(self.inner.format_amount(sats, timestamp).await?)
     }
 
-    /// Set the labels for an invoice, replacing any existing labels
-    /// If you want to do not want to replace any existing labels, use `get_invoice_labels` to get the existing labels,
-    /// add the new labels, and then use `set_invoice_labels` to set the new labels
-    pub fn set_invoice_labels(
-        &self,
-        invoice: String,
-        labels: Vec<String>,
-    ) -> Result<(), MutinyJsError> {
-        let invoice = Bolt11Invoice::from_str(&invoice)?;
-        Ok(self
-            .get_node_manager()?
-            .set_invoice_labels(invoice, labels)?)
+    /// Converts sats to fiat at the given price.
+    #[wasm_bindgen]
+    pub fn convert_sats_to_fiat(sats: u64, price: f32) -> f64 {
+        sats_to_fiat(sats, price)
     }
 
-    /// Gets the current bitcoin price in chosen Fiat.
+    /// Converts fiat to sats at the given price.
     #[wasm_bindgen]
-    pub async fn get_bitcoin_price(&self, fiat: Option<String>) -> Result<f32, MutinyJsError> {
-        Ok(self.inner.get_bitcoin_price(fiat).await?)
+    pub fn convert_fiat_to_sats(fiat: f64, price: f32) -> Result<u64, MutinyJsError> {
+        Ok(fiat_to_sats(fiat, price)?)
     }
 
     /// Exports the current state of the node manager to a json object.
@@ -1174,6 +3392,18 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Exports the pathfinding scorer as hex, to seed another wallet with.
+    #[wasm_bindgen]
+    pub fn export_scorer(&self) -> Result<String, MutinyJsError> {
+        Ok(self.get_node_manager()?.export_scorer()?)
+    }
+
+    /// Replaces the pathfinding scorer with one exported from another wallet.
+    #[wasm_bindgen]
+    pub fn import_scorer(&self, scorer: String) -> Result<(), MutinyJsError> {
+        Ok(self.get_node_manager()?.import_scorer(&scorer)?)
+    }
+
     /// Resets BDK's keychain tracker. This will require a re-sync of the blockchain.
     ///
     /// This can be useful if you get stuck in a bad state.
@@ -1204,101 +3434,496 @@ impl MutinyWallet {
         Ok(serde_json::to_string(&json)?)
     }
 
-    /// Restore a node manager from a json object.
+    /// Restore a node manager from a json object.
+    #[wasm_bindgen]
+    pub async fn import_json(database: String, json: String) -> Result<(), MutinyJsError> {
+        let json: serde_json::Value = serde_json::from_str(&json)?;
+        IndexedDbStorage::import(database, json).await?;
+        Ok(())
+    }
+
+    /// Clears storage and deletes all data.
+    ///
+    /// All data in VSS persists but the device lock is cleared.
+    #[wasm_bindgen]
+    pub async fn delete_all(&self) -> Result<(), MutinyJsError> {
+        self.inner.delete_all().await?;
+        Ok(())
+    }
+
+    /// Restore's the mnemonic after deleting the previous state.
+    ///
+    /// Backup the state beforehand. Does not restore lightning data.
+    /// Should refresh or restart afterwards. Wallet should be stopped.
+    #[wasm_bindgen]
+    pub async fn restore_mnemonic(
+        database: String,
+        m: String,
+        password: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let logger = Arc::new(MutinyLogger::default());
+        let cipher = password
+            .as_ref()
+            .filter(|p| !p.is_empty())
+            .map(|p| encryption_key_from_pass(p))
+            .transpose()?;
+        let storage =
+            IndexedDbStorage::new(database, password, cipher, None, logger.clone()).await?;
+        mutiny_core::MutinyWallet::<IndexedDbStorage>::restore_mnemonic(
+            storage,
+            Mnemonic::from_str(&m).map_err(|_| MutinyJsError::InvalidMnemonic)?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Exports an encrypted static channel backup of every channel, for when
+    /// the wallet state is lost and VSS can't restore it. It holds no channel
+    /// state, export it again after opening channels.
+    #[wasm_bindgen]
+    pub async fn export_scb(&self) -> Result<String, MutinyJsError> {
+        Ok(self.inner.export_scb().await?)
+    }
+
+    /// Asks the peers of the channels in a static channel backup to force close
+    /// them and sweeps our funds on-chain once they do. Returns how many
+    /// channels are being recovered.
+    #[wasm_bindgen]
+    pub async fn recover_from_scb(&self, scb: String) -> Result<usize, MutinyJsError> {
+        Ok(self.inner.recover_from_scb(&scb).await?)
+    }
+
+    /// The channels being recovered from a static channel backup
+    #[wasm_bindgen]
+    pub fn list_scb_recoveries(&self) -> Result<JsValue /* Vec<ScbRecovery> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_scb_recoveries()?)?)
+    }
+
+    /// Gets the watchtowers justice transactions are registered with
+    #[wasm_bindgen]
+    pub fn get_watchtowers(&self) -> Result<Vec<String>, MutinyJsError> {
+        Ok(self.inner.get_watchtower_config()?.towers)
+    }
+
+    /// Sets the watchtowers to register justice transactions with,
+    /// an empty list turns the watchtower client off
+    #[wasm_bindgen]
+    pub fn set_watchtowers(&self, towers: Vec<String>) -> Result<(), MutinyJsError> {
+        Ok(self.inner.set_watchtowers(towers)?)
+    }
+
+    /// Lists the justice transactions some watchtower hasn't accepted yet
+    #[wasm_bindgen]
+    pub fn list_pending_justice_blobs(
+        &self,
+    ) -> Result<JsValue /* Vec<JusticeBlob> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_pending_justice_blobs()?,
+        )?)
+    }
+
+    /// Tells the wallet the user is interacting with it, waking it up if idle.
+    /// Calls that use the nodes do this on their own.
+    #[wasm_bindgen]
+    pub fn record_interaction(&self) -> Result<(), MutinyJsError> {
+        self.get_node_manager()?;
+        Ok(())
+    }
+
+    /// Whether the wallet is idle, since when, and the idle thresholds
+    #[wasm_bindgen]
+    pub fn get_idle_status(&self) -> Result<JsValue /* IdleStatus */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.get_node_manager()?.get_idle_status(),
+        )?)
+    }
+
+    /// Sets after how many minutes without interaction the wallet goes idle
+    /// and how often it polls the chain while idle
+    #[wasm_bindgen]
+    pub fn set_idle_settings(
+        &self,
+        enabled: bool,
+        idle_after_mins: u32,
+        idle_sync_interval_secs: u64,
+    ) -> Result<(), MutinyJsError> {
+        let settings = IdleSettings {
+            enabled,
+            idle_after_mins,
+            idle_sync_interval_secs,
+        };
+        Ok(self.get_node_manager()?.set_idle_settings(settings)?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn change_password(
+        &mut self,
+        old_password: Option<String>,
+        new_password: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let old_p = old_password.filter(|p| !p.is_empty());
+        let new_p = new_password.filter(|p| !p.is_empty());
+        self.inner.change_password(old_p, new_p).await?;
+        Ok(())
+    }
+
+    /// Installs translations for error and status messages, keyed by their
+    /// stable codes. Thrown errors carry the translation in `localized`.
+    /// Passing no messages goes back to English.
+    #[wasm_bindgen]
+    pub fn set_message_catalog(
+        locale: String,
+        translations: JsValue, /* Map<String, String> */
+    ) -> Result<(), MutinyJsError> {
+        let catalog = if translations.is_null() || translations.is_undefined() {
+            None
+        } else {
+            Some(MessageCatalog::new(locale, translations.into_serde()?))
+        };
+        messages::set_message_catalog(catalog);
+        Ok(())
+    }
+
+    /// The locale of the installed message catalog, if any
+    #[wasm_bindgen]
+    pub fn get_message_catalog_locale() -> Option<String> {
+        messages::message_catalog_locale()
+    }
+
+    /// Renders an address, invoice, offer or URI as an SVG QR code
+    /// at least `size` pixels wide.
+    #[wasm_bindgen]
+    pub fn qr_svg(data: String, size: Option<u32>) -> Result<String, MutinyJsError> {
+        Ok(qr::qr_svg(&data, size.unwrap_or(256))?)
+    }
+
+    /// Renders an address, invoice, offer or URI as PNG bytes,
+    /// `module_size` pixels for each module of the code.
+    #[wasm_bindgen]
+    pub fn qr_png(data: String, module_size: Option<u32>) -> Result<Vec<u8>, MutinyJsError> {
+        Ok(qr::qr_png(&data, module_size.unwrap_or(8))?)
+    }
+
+    /// Checks that the input is a bitcoin address for the network, e.g. "bitcoin"
+    /// or "signet". Returns whether it's valid and otherwise why it's rejected,
+    /// like being for another network.
+    #[wasm_bindgen]
+    pub fn validate_address(
+        address: String,
+        network: String,
+    ) -> Result<JsValue /* Validation */, MutinyJsError> {
+        let network =
+            Network::from_str(&network).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let validation = Validation::from(validation::validate_address(&address, network));
+        Ok(JsValue::from_serde(&validation)?)
+    }
+
+    /// Checks that the input is an unexpired lightning invoice for the network.
+    /// Returns whether it's valid and otherwise why it's rejected.
+    #[wasm_bindgen]
+    pub fn validate_invoice_for_network(
+        invoice: String,
+        network: String,
+    ) -> Result<JsValue /* Validation */, MutinyJsError> {
+        let network =
+            Network::from_str(&network).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let validation =
+            Validation::from(validation::validate_invoice_for_network(&invoice, network));
+        Ok(JsValue::from_serde(&validation)?)
+    }
+
+    /// Converts a bitcoin amount in BTC to satoshis.
+    #[wasm_bindgen]
+    pub fn convert_btc_to_sats(btc: f64) -> Result<u64, MutinyJsError> {
+        // rust bitcoin doesn't like extra precision in the float
+        // so we round to the nearest satoshi
+        // explained here: https://stackoverflow.com/questions/28655362/how-does-one-round-a-floating-point-number-to-a-specified-number-of-digits
+        let truncated = 10i32.pow(8) as f64;
+        let btc = (btc * truncated).round() / truncated;
+        if let Ok(amount) = bitcoin::Amount::from_btc(btc) {
+            Ok(amount.to_sat())
+        } else {
+            Err(MutinyJsError::BadAmountError)
+        }
+    }
+
+    /// Converts a satoshi amount to BTC.
+    #[wasm_bindgen]
+    pub fn convert_sats_to_btc(sats: u64) -> f64 {
+        bitcoin::Amount::from_sat(sats).to_btc()
+    }
+
+    /// If the invoice is from a node that gives hodl invoices
+    #[wasm_bindgen]
+    pub async fn is_potential_hodl_invoice(invoice: String) -> Result<bool, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice)?;
+        Ok(mutiny_core::utils::is_hodl_invoice(&invoice))
+    }
+
+    /// Encrypt mnemonic with password
+    #[wasm_bindgen]
+    pub fn encrypt_mnemonic(mnemonic: String, password: String) -> Result<String, MutinyJsError> {
+        let cipher = encryption_key_from_pass(&password).unwrap();
+        encrypt(&mnemonic, cipher).map_err(|_| MutinyJsError::EncryptOrDecryptError)
+    }
+
+    /// Decrypt mnemonic with password
+    #[wasm_bindgen]
+    pub fn decrypt_mnemonic(encrypted: String, password: String) -> Result<String, MutinyJsError> {
+        decrypt_with_password(&encrypted, &password)
+            .map_err(|_| MutinyJsError::EncryptOrDecryptError)
+    }
+}
+
+/// A view of the wallet limited to what an app has been granted,
+/// permissions are enforced by the wallet on every call.
+#[wasm_bindgen]
+pub struct AppSession {
+    inner: mutiny_core::permissions::AppSession<IndexedDbStorage>,
+}
+
+#[wasm_bindgen]
+impl AppSession {
+    /// The grant this session was opened with, including how much has been spent.
+    #[wasm_bindgen]
+    pub fn get_grant(&self) -> Result<JsValue /* AppGrant */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_grant()?)?)
+    }
+
+    /// Gets the current balance of the wallet, requires the "Read" permission.
+    #[wasm_bindgen]
+    pub async fn get_balance(&self) -> Result<MutinyBalance, MutinyJsError> {
+        Ok(self.inner.get_balance().await?.into())
+    }
+
+    /// Returns the wallet's activity, requires the "Read" permission.
+    #[wasm_bindgen]
+    pub fn get_activity(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<JsValue /* Vec<ActivityItem> */, MutinyJsError> {
+        let activity = self.inner.get_activity(limit, offset)?;
+        let activity: Vec<ActivityItem> = activity.into_iter().map(|a| a.into()).collect();
+        Ok(JsValue::from_serde(&activity)?)
+    }
+
+    /// Creates a BIP 21 URI to receive to, requires the "Receive" permission.
+    #[wasm_bindgen]
+    pub async fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        Ok(self.inner.create_bip21(amount, labels).await?.into())
+    }
+
+    /// Pays a lightning invoice, requires the "Spend" permission and
+    /// enough of the grant's spending limit left.
+    #[wasm_bindgen]
+    pub async fn pay_invoice(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        Ok(self
+            .inner
+            .pay_invoice(&invoice, amt_sats, labels)
+            .await?
+            .into())
+    }
+}
+
+/// A wallet that makes up its activity, balances and invoices from a seed
+/// string without touching the network, for developing and taking
+/// screenshots of a frontend without running any infrastructure.
+#[cfg(feature = "demo")]
+#[wasm_bindgen]
+pub struct DemoWallet {
+    inner: mutiny_core::demo::DemoWallet,
+}
+
+#[cfg(feature = "demo")]
+#[wasm_bindgen]
+impl DemoWallet {
+    /// The same seed always gives the same wallet
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: String, network: Option<String>) -> Result<DemoWallet, MutinyJsError> {
+        let network = network
+            .map(|n| Network::from_str(&n).map_err(|_| MutinyJsError::InvalidArgumentsError))
+            .transpose()?
+            .unwrap_or(Network::Signet);
+        Ok(DemoWallet {
+            inner: mutiny_core::demo::DemoWallet::new(seed, network),
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn get_balance(&self) -> MutinyBalance {
+        self.inner.get_balance().into()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_activity(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<JsValue /* Vec<ActivityItem> */, MutinyJsError> {
+        let activity: Vec<ActivityItem> = self
+            .inner
+            .get_activity()
+            .into_iter()
+            .skip(offset.unwrap_or_default())
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|a| a.into())
+            .collect();
+        Ok(JsValue::from_serde(&activity)?)
+    }
+
     #[wasm_bindgen]
-    pub async fn import_json(database: String, json: String) -> Result<(), MutinyJsError> {
-        let json: serde_json::Value = serde_json::from_str(&json)?;
-        IndexedDbStorage::import(database, json).await?;
-        Ok(())
+    pub fn get_new_address(&self) -> String {
+        self.inner.get_new_address().to_string()
     }
 
-    /// Clears storage and deletes all data.
-    ///
-    /// All data in VSS persists but the device lock is cleared.
     #[wasm_bindgen]
-    pub async fn delete_all(&self) -> Result<(), MutinyJsError> {
-        self.inner.delete_all().await?;
-        Ok(())
+    pub fn create_invoice(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        Ok(self.inner.create_invoice(amount, labels)?.into())
     }
 
-    /// Restore's the mnemonic after deleting the previous state.
-    ///
-    /// Backup the state beforehand. Does not restore lightning data.
-    /// Should refresh or restart afterwards. Wallet should be stopped.
     #[wasm_bindgen]
-    pub async fn restore_mnemonic(
+    pub fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyJsError> {
+        Ok(self.inner.create_bip21(amount, labels)?.into())
+    }
+}
+
+/// An on-chain wallet without private keys, for monitoring a wallet from
+/// another device. PSBTs it creates have to be signed by the wallet with the keys.
+#[wasm_bindgen]
+pub struct WatchOnlyWallet {
+    inner: mutiny_core::watchonly::WatchOnlyWallet<IndexedDbStorage>,
+}
+
+#[wasm_bindgen]
+impl WatchOnlyWallet {
+    /// Starts a watch-only wallet from either a receive and change descriptor,
+    /// or an account xpub passed as `descriptor` with no change descriptor.
+    /// The database should not be the one used by a regular wallet.
+    #[wasm_bindgen(constructor)]
+    pub async fn new(
         database: String,
-        m: String,
         password: Option<String>,
-    ) -> Result<(), MutinyJsError> {
-        let logger = Arc::new(MutinyLogger::default());
+        descriptor: String,
+        change_descriptor: Option<String>,
+        network_str: Option<String>,
+        user_esplora_url: Option<String>,
+    ) -> Result<WatchOnlyWallet, MutinyJsError> {
+        utils::set_panic_hook();
+        let logger = Arc::new(MutinyLogger::memory_only());
         let cipher = password
             .as_ref()
             .filter(|p| !p.is_empty())
             .map(|p| encryption_key_from_pass(p))
             .transpose()?;
+        let network: Network = network_str
+            .map(|s| s.parse().expect("Invalid network"))
+            .unwrap_or(Network::Bitcoin);
+
         let storage =
             IndexedDbStorage::new(database, password, cipher, None, logger.clone()).await?;
-        mutiny_core::MutinyWallet::<IndexedDbStorage>::restore_mnemonic(
-            storage,
-            Mnemonic::from_str(&m).map_err(|_| MutinyJsError::InvalidMnemonic)?,
-        )
-        .await?;
-        Ok(())
+        let inner = match change_descriptor {
+            Some(change_descriptor) => {
+                mutiny_core::watchonly::WatchOnlyWallet::new(
+                    storage,
+                    descriptor,
+                    change_descriptor,
+                    network,
+                    user_esplora_url,
+                    logger,
+                )
+                .await?
+            }
+            None => {
+                let xpub = Xpub::from_str(&descriptor)
+                    .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+                mutiny_core::watchonly::WatchOnlyWallet::from_xpub(
+                    storage,
+                    xpub,
+                    network,
+                    user_esplora_url,
+                    logger,
+                )
+                .await?
+            }
+        };
+
+        Ok(WatchOnlyWallet { inner })
     }
 
+    /// Syncs the wallet with the chain.
     #[wasm_bindgen]
-    pub async fn change_password(
-        &mut self,
-        old_password: Option<String>,
-        new_password: Option<String>,
-    ) -> Result<(), MutinyJsError> {
-        let old_p = old_password.filter(|p| !p.is_empty());
-        let new_p = new_password.filter(|p| !p.is_empty());
-        self.inner.change_password(old_p, new_p).await?;
-        Ok(())
+    pub async fn sync(&self) -> Result<(), MutinyJsError> {
+        Ok(self.inner.sync().await?)
     }
 
-    /// Converts a bitcoin amount in BTC to satoshis.
     #[wasm_bindgen]
-    pub fn convert_btc_to_sats(btc: f64) -> Result<u64, MutinyJsError> {
-        // rust bitcoin doesn't like extra precision in the float
-        // so we round to the nearest satoshi
-        // explained here: https://stackoverflow.com/questions/28655362/how-does-one-round-a-floating-point-number-to-a-specified-number-of-digits
-        let truncated = 10i32.pow(8) as f64;
-        let btc = (btc * truncated).round() / truncated;
-        if let Ok(amount) = bitcoin::Amount::from_btc(btc) {
-            Ok(amount.to_sat())
-        } else {
-            Err(MutinyJsError::BadAmountError)
-        }
+    pub fn get_new_address(&self, labels: Vec<String>) -> Result<String, MutinyJsError> {
+        Ok(self.inner.get_new_address(labels)?.to_string())
     }
 
-    /// Converts a satoshi amount to BTC.
     #[wasm_bindgen]
-    pub fn convert_sats_to_btc(sats: u64) -> f64 {
-        bitcoin::Amount::from_sat(sats).to_btc()
+    pub fn get_balance(&self) -> Result<JsValue /* WatchOnlyBalance */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_balance()?)?)
     }
 
-    /// If the invoice is from a node that gives hodl invoices
     #[wasm_bindgen]
-    pub async fn is_potential_hodl_invoice(invoice: String) -> Result<bool, MutinyJsError> {
-        let invoice = Bolt11Invoice::from_str(&invoice)?;
-        Ok(mutiny_core::utils::is_hodl_invoice(&invoice))
+    pub fn list_transactions(
+        &self,
+    ) -> Result<JsValue /* Vec<TransactionDetails> */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.list_transactions()?)?)
     }
 
-    /// Encrypt mnemonic with password
+    /// Creates an unsigned base64 PSBT paying each address the matching amount in sats.
     #[wasm_bindgen]
-    pub fn encrypt_mnemonic(mnemonic: String, password: String) -> Result<String, MutinyJsError> {
-        let cipher = encryption_key_from_pass(&password).unwrap();
-        encrypt(&mnemonic, cipher).map_err(|_| MutinyJsError::EncryptOrDecryptError)
+    pub fn create_psbt(
+        &self,
+        addresses: Vec<String>,
+        amounts: Vec<u64>,
+        fee_rate: Option<u64>,
+    ) -> Result<String, MutinyJsError> {
+        if amounts.len() != addresses.len() {
+            return Err(MutinyJsError::InvalidArgumentsError);
+        }
+        let network = self.inner.network();
+        let recipients = addresses
+            .iter()
+            .zip(amounts)
+            .map(|(address, amount)| {
+                let address = Address::from_str(address)?.require_network(network)?;
+                Ok((address, amount))
+            })
+            .collect::<Result<Vec<_>, MutinyJsError>>()?;
+        Ok(self.inner.create_psbt(recipients, fee_rate)?.to_string())
     }
 
-    /// Decrypt mnemonic with password
+    /// Broadcasts a base64 PSBT that has been fully signed elsewhere.
     #[wasm_bindgen]
-    pub fn decrypt_mnemonic(encrypted: String, password: String) -> Result<String, MutinyJsError> {
-        decrypt_with_password(&encrypted, &password)
-            .map_err(|_| MutinyJsError::EncryptOrDecryptError)
+    pub async fn broadcast_psbt(
+        &self,
+        psbt: String,
+        labels: Vec<String>,
+    ) -> Result<String, MutinyJsError> {
+        let psbt = Psbt::from_str(&psbt).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self.inner.broadcast_psbt(psbt, labels).await?.to_string())
     }
 }
 
@@ -1369,6 +3994,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1412,6 +4051,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1449,6 +4102,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -1499,6 +4166,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1535,6 +4216,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -1588,6 +4283,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -1643,6 +4352,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -1685,6 +4408,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -1727,6 +4464,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1799,6 +4550,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1862,6 +4627,20 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -5,7 +5,8 @@ use gloo_utils::format::JsValueSerdeExt;
 use hex_conservative::DisplayHex;
 use lightning_invoice::Bolt11Invoice;
 
-use mutiny_core::event::HTLCStatus;
+use mutiny_core::event::{CustomTlv, HTLCStatus};
+use mutiny_core::messages::MessageCode;
 
 use mutiny_core::*;
 use serde::{Deserialize, Serialize};
@@ -20,6 +21,7 @@ pub enum ActivityType {
     Lightning,
     ChannelOpen,
     ChannelClose,
+    Rebalance,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -33,6 +35,12 @@ pub struct ActivityItem {
     pub last_updated: Option<u64>,
     pub fee_paid_msat: Option<u64>,
     privacy_level: String,
+    /// Txids of the transactions this one replaced with RBF
+    replaces: Vec<String>,
+    /// Paid to one of our addresses that has received in another transaction
+    pub address_reused: bool,
+    /// Shared by the items of one user action, see `get_operation`
+    operation_id: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -51,6 +59,16 @@ impl ActivityItem {
     pub fn labels(&self) -> Vec<String> {
         self.labels.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn replaces(&self) -> Vec<String> {
+        self.replaces.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn operation_id(&self) -> Option<String> {
+        self.operation_id.clone()
+    }
 }
 
 impl From<mutiny_core::ActivityItem> for ActivityItem {
@@ -65,6 +83,8 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             mutiny_core::ActivityItem::Lightning(_) => ActivityType::Lightning,
             mutiny_core::ActivityItem::ChannelClosed(_) => ActivityType::ChannelClose,
+            mutiny_core::ActivityItem::Rebalance(_) => ActivityType::Rebalance,
+            mutiny_core::ActivityItem::ChannelPurchase(_) => ActivityType::ChannelOpen,
         };
 
         let id = match a {
@@ -76,6 +96,8 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
                 .user_channel_id
                 .map(|c| c.to_lower_hex_string())
                 .unwrap_or_default(),
+            mutiny_core::ActivityItem::Rebalance(ref r) => r.payment_hash.to_string(),
+            mutiny_core::ActivityItem::ChannelPurchase(ref p) => p.order.order_id.clone(),
         };
 
         let (inbound, amount_sats) = match a {
@@ -90,13 +112,31 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             mutiny_core::ActivityItem::Lightning(ref ln) => (ln.inbound, ln.amount_sats),
             mutiny_core::ActivityItem::ChannelClosed(_) => (false, None),
+            mutiny_core::ActivityItem::Rebalance(ref r) => (false, Some(r.amount_sats)),
+            // the inbound liquidity that was bought
+            mutiny_core::ActivityItem::ChannelPurchase(ref p) => {
+                (true, Some(p.order.lsp_balance_sat))
+            }
         };
 
         let fee_paid_msat = match a {
             mutiny_core::ActivityItem::Lightning(ref ln) => ln.fee_paid_msat,
+            mutiny_core::ActivityItem::Rebalance(ref r) => r.fee_paid_msat,
             _ => None,
         };
 
+        let replaces = match a {
+            mutiny_core::ActivityItem::OnChain(ref t) => {
+                t.replaces.iter().map(|txid| txid.to_string()).collect()
+            }
+            _ => vec![],
+        };
+
+        let address_reused = match a {
+            mutiny_core::ActivityItem::OnChain(ref t) => t.address_reused,
+            _ => false,
+        };
+
         let privacy_level = match kind {
             ActivityType::OnChain => PrivacyLevel::NotAvailable,
             ActivityType::Lightning => {
@@ -108,6 +148,7 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             ActivityType::ChannelOpen => PrivacyLevel::NotAvailable,
             ActivityType::ChannelClose => PrivacyLevel::NotAvailable,
+            ActivityType::Rebalance => PrivacyLevel::NotAvailable,
         };
 
         ActivityItem {
@@ -119,6 +160,9 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             labels: a.labels(),
             last_updated: a.last_updated(),
             privacy_level: privacy_level.to_string(),
+            replaces,
+            address_reused,
+            operation_id: a.operation_id(),
         }
     }
 }
@@ -127,6 +171,8 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
 #[wasm_bindgen]
 pub struct MutinyInvoice {
     bolt11: Option<Bolt11Invoice>,
+    offer: Option<String>,
+    custom_tlvs: Vec<CustomTlv>,
     description: Option<String>,
     payment_hash: String,
     preimage: Option<String>,
@@ -135,6 +181,8 @@ pub struct MutinyInvoice {
     pub expire: u64,
     pub expired: bool,
     status: String,
+    status_code: String,
+    status_message: String,
     privacy_level: String,
     pub fees_paid: Option<u64>,
     pub inbound: bool,
@@ -155,6 +203,18 @@ impl MutinyInvoice {
         self.bolt11.clone().map(|b| b.to_string())
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn offer(&self) -> Option<String> {
+        self.offer.clone()
+    }
+
+    /// Custom TLV records that came with a keysend payment, as `{ tlv_type, value }`
+    /// with a hex value
+    #[wasm_bindgen(getter)]
+    pub fn custom_tlvs(&self) -> JsValue /* Vec<CustomTlv> */ {
+        JsValue::from_serde(&self.custom_tlvs).unwrap()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn description(&self) -> Option<String> {
         self.description.clone()
@@ -180,6 +240,18 @@ impl MutinyInvoice {
         self.status.clone()
     }
 
+    /// Stable code for the status, for looking up translations
+    #[wasm_bindgen(getter)]
+    pub fn status_code(&self) -> String {
+        self.status_code.clone()
+    }
+
+    /// The status in the installed message catalog's language
+    #[wasm_bindgen(getter)]
+    pub fn status_message(&self) -> String {
+        self.status_message.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn privacy_level(&self) -> String {
         self.privacy_level.clone()
@@ -205,6 +277,8 @@ impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
         let now = utils::now().as_secs();
         MutinyInvoice {
             bolt11: m.bolt11,
+            offer: m.offer,
+            custom_tlvs: m.custom_tlvs,
             description: m.description,
             payment_hash: m.payment_hash.to_byte_array().to_lower_hex_string(),
             preimage: m.preimage,
@@ -213,6 +287,8 @@ impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
             expire: m.expire,
             expired: m.expire < now,
             status: m.status.to_string(),
+            status_code: m.status.code().to_string(),
+            status_message: m.status.localized_message(),
             privacy_level: m.privacy_level.to_string(),
             fees_paid: m.fees_paid,
             inbound: m.inbound,
@@ -295,6 +371,12 @@ pub struct MutinyChannel {
     pub is_outbound: bool,
     pub is_usable: bool,
     pub is_anchor: bool,
+    pub zero_reserve: bool,
+    pub punishment_reserve: u64,
+    pub pending_outbound_htlcs: u64,
+    pub dust_exposure: u64,
+    pub commitment_fee: u64,
+    peer_alias: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -319,6 +401,11 @@ impl MutinyChannel {
         self.peer.clone()
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn peer_alias(&self) -> Option<String> {
+        self.peer_alias.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn confirmed(&self) -> bool {
         match self.confirmations_required {
@@ -343,6 +430,12 @@ impl From<nodemanager::MutinyChannel> for MutinyChannel {
             is_outbound: m.is_outbound,
             is_usable: m.is_usable,
             is_anchor: m.is_anchor,
+            zero_reserve: m.zero_reserve,
+            punishment_reserve: m.punishment_reserve,
+            pending_outbound_htlcs: m.pending_outbound_htlcs,
+            dust_exposure: m.dust_exposure,
+            commitment_fee: m.commitment_fee,
+            peer_alias: m.peer_alias,
         }
     }
 }
@@ -364,6 +457,12 @@ impl From<MutinyChannel> for nodemanager::MutinyChannel {
             is_outbound: m.is_outbound,
             is_usable: m.is_usable,
             is_anchor: m.is_anchor,
+            zero_reserve: m.zero_reserve,
+            punishment_reserve: m.punishment_reserve,
+            pending_outbound_htlcs: m.pending_outbound_htlcs,
+            dust_exposure: m.dust_exposure,
+            commitment_fee: m.commitment_fee,
+            peer_alias: m.peer_alias,
         }
     }
 }
@@ -377,6 +476,11 @@ pub struct ChannelClosure {
     reason: String,
     pub timestamp: u64,
     channel_funding_txo: Option<String>,
+    closing_txid: Option<String>,
+    pub swept_amount_sats: u64,
+    pub pending_sweep_sats: u64,
+    pub fully_swept: bool,
+    pub estimated_secs_remaining: Option<u64>,
 }
 
 #[wasm_bindgen]
@@ -405,6 +509,11 @@ impl ChannelClosure {
     pub fn channel_funding_txo(&self) -> Option<String> {
         self.channel_funding_txo.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn closing_txid(&self) -> Option<String> {
+        self.closing_txid.clone()
+    }
 }
 
 impl PartialOrd for ChannelClosure {
@@ -440,6 +549,11 @@ impl From<nodemanager::ChannelClosure> for ChannelClosure {
             reason: c.reason,
             timestamp: c.timestamp,
             channel_funding_txo: c.channel_funding_txo.map(|txo| format!("{}", txo)),
+            closing_txid: c.closing_txid.map(|txid| txid.to_string()),
+            swept_amount_sats: c.swept_amount_sats,
+            pending_sweep_sats: c.pending_sweep_sats,
+            fully_swept: c.fully_swept,
+            estimated_secs_remaining: c.estimated_secs_remaining,
         }
     }
 }
@@ -539,6 +653,74 @@ impl From<nodemanager::NodeIdentity> for NodeIdentity {
     }
 }
 
+/// All the static identifiers that can be used to pay this wallet.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct PaymentCard {
+    pub version: u32,
+    node_pubkey: Option<String>,
+    npub: Option<String>,
+    lightning_address: Option<String>,
+    offer: Option<String>,
+    silent_payment_address: Option<String>,
+    uri: String,
+    pub updated_at: u64,
+}
+
+#[wasm_bindgen]
+impl PaymentCard {
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> JsValue {
+        JsValue::from_serde(&serde_json::to_value(self).unwrap()).unwrap()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn node_pubkey(&self) -> Option<String> {
+        self.node_pubkey.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn npub(&self) -> Option<String> {
+        self.npub.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lightning_address(&self) -> Option<String> {
+        self.lightning_address.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn offer(&self) -> Option<String> {
+        self.offer.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn silent_payment_address(&self) -> Option<String> {
+        self.silent_payment_address.clone()
+    }
+
+    /// URI encoding of the card, suitable for a QR code
+    #[wasm_bindgen(getter)]
+    pub fn uri(&self) -> String {
+        self.uri.clone()
+    }
+}
+
+impl From<paymentcard::PaymentCard> for PaymentCard {
+    fn from(c: paymentcard::PaymentCard) -> Self {
+        PaymentCard {
+            version: c.version,
+            node_pubkey: c.node_pubkey.map(|p| p.to_string()),
+            uri: c.to_uri(),
+            npub: c.npub,
+            lightning_address: c.lightning_address,
+            offer: c.offer,
+            silent_payment_address: c.silent_payment_address,
+            updated_at: c.updated_at,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 #[wasm_bindgen]
 pub struct MutinyBip21RawMaterials {